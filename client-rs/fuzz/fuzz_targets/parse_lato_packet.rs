@@ -0,0 +1,18 @@
+#![no_main]
+
+use lattice_core::{parse_lato_packet, LATO_PACKET_LEN, LATO_V2_PACKET_LEN, LATO_V2_VERSION};
+use libfuzzer_sys::fuzz_target;
+
+// Arbitrary datagrams: parsing must never panic, and anything it accepts is a
+// full-length packet of its version.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(packet) = parse_lato_packet(data) {
+        let len = if packet.version == LATO_V2_VERSION {
+            LATO_V2_PACKET_LEN
+        } else {
+            LATO_PACKET_LEN
+        };
+        assert_eq!(data.len(), len);
+        assert!(packet.echoes(&packet));
+    }
+});
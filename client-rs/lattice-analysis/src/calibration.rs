@@ -0,0 +1,257 @@
+//! Calibration: per-endpoint biases measured at a known location, taken off
+//! RTTs before they become distances.
+
+use crate::distance::{anchor_alt_km, DistanceModel};
+use crate::propagation::{Leg, PropagationSpec};
+use crate::stats::EndpointStats;
+use lattice_core::{ms_to_us, now_unix_ms, Config, Transport};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// Per-endpoint RTT bias measured at a known location, the `--calibration`
+/// table. Biases are what the path adds over the propagation model.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Calibration {
+    /// Unix time in ms, as a string.
+    pub generated_at: String,
+    pub calibration_lat: f64,
+    pub calibration_lon: f64,
+    pub speed_km_s: f64,
+    pub path_stretch: f64,
+    #[serde(default)]
+    pub distance_model: DistanceModel,
+    /// Model the biases were measured against; null in tables from before
+    /// models were selectable (linear at `speedKmS / pathStretch`).
+    #[serde(default)]
+    pub propagation_model: Option<PropagationSpec>,
+    pub endpoints: BTreeMap<String, EndpointCalibration>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointCalibration {
+    /// Subtracted from the floored RTT before `scale` divides it.
+    pub bias_ms: f64,
+    /// `bias_ms` in whole microseconds; informational, `bias_ms` is authoritative.
+    #[serde(default)]
+    pub bias_us: i64,
+    pub scale: f64,
+    /// Bias and scale per transport the endpoint answered over at the
+    /// calibration site. The endpoint's own values are UDP's, or the first
+    /// transport's when it never answered over UDP.
+    #[serde(default)]
+    pub transports: BTreeMap<Transport, TransportCalibration>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransportCalibration {
+    pub bias_ms: f64,
+    #[serde(default)]
+    pub bias_us: i64,
+    pub scale: f64,
+}
+
+pub fn load_calibration(path: &Path) -> io::Result<Calibration> {
+    let file = File::open(path)?;
+    let calib: Calibration =
+        serde_json::from_reader(file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(calib)
+}
+
+pub fn save_calibration(path: &Path, calib: &Calibration) -> io::Result<()> {
+    let file = File::create(path)?;
+    serde_json::to_writer_pretty(file, calib).map_err(io::Error::other)
+}
+
+/// Builds the bias table for a capture at `lat`/`lon` from stats kept per
+/// transport. Endpoints without coordinates or a usable RTT are left out.
+#[allow(clippy::too_many_arguments)]
+pub fn build_calibration(
+    cfg: &Config,
+    stats: &BTreeMap<Transport, HashMap<String, EndpointStats>>,
+    lat: f64,
+    lon: f64,
+    speed_km_s: f64,
+    path_stretch: f64,
+    distance: DistanceModel,
+    propagation: &PropagationSpec,
+) -> Calibration {
+    let bias = |id: &str, st: &EndpointStats| -> Option<f64> {
+        let base_id = id.split('@').next().unwrap_or(id);
+        let ep = cfg.endpoints.iter().find(|e| e.id == base_id)?;
+        let (Some(ep_lat), Some(ep_lon)) = (ep.lat, ep.lon) else {
+            return None;
+        };
+        let rtt = match st.p05.or(st.min) {
+            Some(v) if v.is_finite() && v > 0.0 => (v - st.floor_ms()).max(0.0),
+            _ => return None,
+        };
+        let dist_km = distance.distance_3d_km(lat, lon, ep_lat, ep_lon, anchor_alt_km(ep));
+        let leg = Leg {
+            region: ep.region_hint.as_deref(),
+            anchor: Some((ep_lat, ep_lon)),
+            host: Some((lat, lon)),
+        };
+        let expected = propagation.model().rtt_ms(dist_km, leg);
+        Some((rtt - expected).max(0.0))
+    };
+    let mut endpoints: BTreeMap<String, EndpointCalibration> = BTreeMap::new();
+    // UDP sorts first, so it sets the endpoint's own bias whenever it answered.
+    for (transport, stats) in stats {
+        for (id, st) in stats {
+            let Some(bias_ms) = bias(id, st) else {
+                continue;
+            };
+            let entry = endpoints
+                .entry(id.clone())
+                .or_insert_with(|| EndpointCalibration {
+                    bias_ms,
+                    bias_us: ms_to_us(bias_ms),
+                    scale: 1.0,
+                    transports: BTreeMap::new(),
+                });
+            entry.transports.insert(
+                *transport,
+                TransportCalibration {
+                    bias_ms,
+                    bias_us: ms_to_us(bias_ms),
+                    scale: 1.0,
+                },
+            );
+        }
+    }
+    Calibration {
+        generated_at: format!("{}", now_unix_ms()),
+        calibration_lat: lat,
+        calibration_lon: lon,
+        speed_km_s,
+        path_stretch,
+        distance_model: distance,
+        propagation_model: Some(propagation.clone()),
+        endpoints,
+    }
+}
+
+/// The table's entry for a stats id, falling back from `endpoint@path` to
+/// the endpoint.
+pub fn calibration_entry<'a>(
+    calibration: Option<&'a Calibration>,
+    endpoint_id: &str,
+) -> Option<&'a EndpointCalibration> {
+    let cal = calibration?;
+    if let Some(entry) = cal.endpoints.get(endpoint_id) {
+        return Some(entry);
+    }
+    if let Some(base) = endpoint_id.split('@').next() {
+        return cal.endpoints.get(base);
+    }
+    None
+}
+
+/// Removes the access and host floors, then applies the endpoint's calibration.
+pub fn adjust_rtt_ms(
+    rtt_ms: f64,
+    floor_ms: f64,
+    endpoint_id: &str,
+    calibration: Option<&Calibration>,
+) -> f64 {
+    let rtt_ms = (rtt_ms - floor_ms).max(0.0);
+    if let Some(entry) = calibration_entry(calibration, endpoint_id) {
+        let mut scale = entry.scale;
+        if scale <= 0.0 {
+            scale = 1.0;
+        }
+        let adj = (rtt_ms - entry.bias_ms) / scale;
+        return adj.max(0.0);
+    }
+    rtt_ms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::DEFAULT_PATH_STRETCH;
+    use crate::testutil::*;
+
+    const TEST_SCALE: f64 = 2.0;
+    const TEST_EXPECTED_ADJ_MS: f64 = 2.0;
+
+    #[test]
+    fn calibration_entry_resolves_base_id() {
+        let mut endpoints = BTreeMap::new();
+        endpoints.insert(
+            "nyc".to_string(),
+            EndpointCalibration {
+                bias_ms: 5.0,
+                bias_us: 0,
+                scale: 1.0,
+                transports: BTreeMap::new(),
+            },
+        );
+        let cal = Calibration {
+            generated_at: "0".to_string(),
+            calibration_lat: 0.0,
+            calibration_lon: 0.0,
+            speed_km_s: DEFAULT_SPEED_KM_S,
+            path_stretch: DEFAULT_PATH_STRETCH,
+            distance_model: DistanceModel::Sphere,
+            propagation_model: None,
+            endpoints,
+        };
+        let entry = calibration_entry(Some(&cal), "nyc@vpn");
+        assert!(entry.is_some());
+        assert_eq!(entry.unwrap().bias_ms, 5.0);
+    }
+
+    #[test]
+    fn adjust_rtt_applies_bias_and_scale() {
+        let mut endpoints = BTreeMap::new();
+        endpoints.insert(
+            "a".to_string(),
+            EndpointCalibration {
+                bias_ms: 5.0,
+                bias_us: 0,
+                scale: TEST_SCALE,
+                transports: BTreeMap::new(),
+            },
+        );
+        let cal = Calibration {
+            generated_at: "0".to_string(),
+            calibration_lat: 0.0,
+            calibration_lon: 0.0,
+            speed_km_s: DEFAULT_SPEED_KM_S,
+            path_stretch: DEFAULT_PATH_STRETCH,
+            distance_model: DistanceModel::Sphere,
+            propagation_model: None,
+            endpoints,
+        };
+        let adj = adjust_rtt_ms(9.0, 0.0, "a", Some(&cal));
+        assert!((adj - TEST_EXPECTED_ADJ_MS).abs() < TEST_EPSILON);
+        let adj2 = adjust_rtt_ms(3.0, 0.0, "a", Some(&cal));
+        assert_eq!(adj2, 0.0);
+    }
+
+    #[test]
+    fn build_calibration_uses_known_location() {
+        let cfg = sample_config(vec![endpoint("a", 0.0, 0.0)]);
+        let stats = BTreeMap::from([(Transport::Udp, stats_with_p05("a", 12.5))]);
+        let cal = build_calibration(
+            &cfg,
+            &stats,
+            0.0,
+            0.0,
+            DEFAULT_SPEED_KM_S,
+            TEST_PATH_STRETCH,
+            DistanceModel::Sphere,
+            &PropagationSpec::linear(DEFAULT_SPEED_KM_S / TEST_PATH_STRETCH),
+        );
+        let entry = cal.endpoints.get("a").unwrap();
+        assert!((entry.bias_ms - 12.5).abs() < TEST_EPSILON);
+        assert_eq!(entry.scale, 1.0);
+    }
+}
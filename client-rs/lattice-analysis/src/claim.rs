@@ -0,0 +1,186 @@
+//! Physics bounds on a claimed location: light in fiber cannot cover more
+//! distance than an anchor's RTT allows.
+
+use crate::calibration::{adjust_rtt_ms, Calibration};
+use crate::distance::{anchor_alt_km, DistanceModel};
+use crate::estimate::max_distance_km;
+use crate::propagation::{Leg, PropagationModel};
+use crate::stats::{stats_endpoint, EndpointStats};
+use lattice_core::Endpoint;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// One anchor's verdict on a claimed location: the claim is falsified when
+/// it lies farther from the anchor than the RTT allows. The tight bound comes
+/// from the p05 RTT, the loose one from the median.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClaimCheck {
+    pub id: String,
+    /// Claim to anchor.
+    pub dist_km: f64,
+    pub max_tight_km: Option<f64>,
+    pub max_loose_km: Option<f64>,
+    pub falsify_tight: Option<bool>,
+    pub falsify_loose: Option<bool>,
+}
+
+/// Checks a claimed location against every endpoint not excluded, in id
+/// order. RTTs are floored and calibrated first.
+pub fn claim_checks(
+    stats: &HashMap<String, EndpointStats>,
+    endpoints: &HashMap<String, Endpoint>,
+    claim_lat: f64,
+    claim_lon: f64,
+    model: &dyn PropagationModel,
+    calibration: Option<&Calibration>,
+    distance: DistanceModel,
+) -> Vec<ClaimCheck> {
+    let mut ids: Vec<&String> = stats.keys().collect();
+    ids.sort();
+    let mut out = Vec::new();
+    for id in ids {
+        let st = &stats[id];
+        if st.excluded.is_some() {
+            continue;
+        }
+        let Some(ep) = stats_endpoint(endpoints, id) else {
+            continue;
+        };
+        let adjust = |v: f64| adjust_rtt_ms(v, st.floor_ms(), id, calibration);
+        out.extend(claim_check(
+            id,
+            ep,
+            (claim_lat, claim_lon),
+            st.p05.map(adjust),
+            st.p50.map(adjust),
+            model,
+            distance,
+        ));
+    }
+    out
+}
+
+/// One anchor's bounds on the claim, from RTTs already floored and
+/// calibrated; `None` for an anchor without coordinates.
+pub fn claim_check(
+    id: &str,
+    ep: &Endpoint,
+    claim: (f64, f64),
+    tight_rtt_ms: Option<f64>,
+    loose_rtt_ms: Option<f64>,
+    model: &dyn PropagationModel,
+    distance: DistanceModel,
+) -> Option<ClaimCheck> {
+    let (claim_lat, claim_lon) = claim;
+    let (Some(ep_lat), Some(ep_lon)) = (ep.lat, ep.lon) else {
+        return None;
+    };
+    let dist_km = distance.distance_3d_km(claim_lat, claim_lon, ep_lat, ep_lon, anchor_alt_km(ep));
+    let leg = Leg {
+        region: ep.region_hint.as_deref(),
+        anchor: Some((ep_lat, ep_lon)),
+        host: Some((claim_lat, claim_lon)),
+    };
+    let tight = tight_rtt_ms.and_then(|v| max_distance_km(v, model, leg));
+    let loose = loose_rtt_ms.and_then(|v| max_distance_km(v, model, leg));
+    Some(ClaimCheck {
+        id: id.to_string(),
+        dist_km,
+        max_tight_km: tight,
+        max_loose_km: loose,
+        falsify_tight: tight.map(|t| dist_km > t),
+        falsify_loose: loose.map(|l| dist_km > l),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calibration::EndpointCalibration;
+    use crate::constants::{DEFAULT_PATH_STRETCH, MS_PER_SEC, RTT_FACTOR};
+    use crate::testutil::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn anchor_altitude_adds_to_distance() {
+        let mut haps = endpoint("haps", 10.0, 20.0);
+        haps.alt_m = Some(20_000.0);
+        let mut endpoints = HashMap::new();
+        endpoints.insert("haps".to_string(), haps.clone());
+
+        // 0.1 ms reaches ~9 km at the default speed: not enough to climb 20 km.
+        let stats = stats_with_p05("haps", 0.1);
+        let checks = claim_checks(
+            &stats,
+            &endpoints,
+            10.0,
+            20.0,
+            &TEST_MODEL,
+            None,
+            DistanceModel::Sphere,
+        );
+        assert!((checks[0].dist_km - 20.0).abs() < TEST_EPSILON);
+        assert_eq!(checks[0].falsify_tight, Some(true));
+
+        let surface = DistanceModel::Sphere.distance_km(10.0, 21.0, 10.0, 20.0);
+        let slant =
+            DistanceModel::Sphere.distance_3d_km(10.0, 21.0, 10.0, 20.0, anchor_alt_km(&haps));
+        assert!((slant - surface.hypot(20.0)).abs() < TEST_EPSILON);
+        assert_eq!(anchor_alt_km(&endpoint("ground", 0.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn claim_checks_resolve_base_endpoint() {
+        let mut stats = HashMap::new();
+        stats.insert(
+            "a@vpn".to_string(),
+            EndpointStats {
+                count: 10,
+                min: Some(10.0),
+                p05: Some(10.0),
+                p50: Some(10.0),
+                p95: Some(10.0),
+                jitter_ms: Some(0.0),
+                bursts: 10,
+                ..Default::default()
+            },
+        );
+        let mut endpoints = HashMap::new();
+        endpoints.insert("a".to_string(), endpoint("a", 0.0, 0.0));
+
+        let mut cal_eps = BTreeMap::new();
+        cal_eps.insert(
+            "a".to_string(),
+            EndpointCalibration {
+                bias_ms: 5.0,
+                bias_us: 0,
+                scale: 1.0,
+                transports: BTreeMap::new(),
+            },
+        );
+        let cal = Calibration {
+            generated_at: "0".to_string(),
+            calibration_lat: 0.0,
+            calibration_lon: 0.0,
+            speed_km_s: DEFAULT_SPEED_KM_S,
+            path_stretch: DEFAULT_PATH_STRETCH,
+            distance_model: DistanceModel::Sphere,
+            propagation_model: None,
+            endpoints: cal_eps,
+        };
+        let checks = claim_checks(
+            &stats,
+            &endpoints,
+            0.0,
+            0.0,
+            &TEST_MODEL,
+            Some(&cal),
+            DistanceModel::Sphere,
+        );
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].id, "a@vpn");
+        let expected = (DEFAULT_SPEED_KM_S / MS_PER_SEC) * (5.0 / RTT_FACTOR);
+        assert!((checks[0].max_tight_km.unwrap() - expected).abs() < TEST_EPSILON);
+    }
+}
@@ -0,0 +1,22 @@
+//! Estimator defaults and the physical constants behind them.
+
+pub const DEFAULT_GRID_DEG: f64 = 1.0;
+pub const DEFAULT_REFINE_DEG: f64 = 0.25;
+pub const DEFAULT_SPEED_KM_S: f64 = 200000.0;
+pub const DEFAULT_PATH_STRETCH: f64 = 1.1;
+pub const DEFAULT_BAND_FACTOR: f64 = 0.25;
+pub const DEFAULT_BAND_WINDOW_DEG: f64 = 3.0;
+pub const DEFAULT_RECENCY_HALF_LIFE_S: f64 = 3600.0;
+pub const MIN_JITTER_MS: f64 = 1.0;
+
+pub const MS_PER_SEC: f64 = 1000.0;
+pub const M_PER_KM: f64 = 1000.0;
+pub const RTT_FACTOR: f64 = 2.0;
+pub const WGS84_A_KM: f64 = 6378.137;
+pub const WGS84_F: f64 = 1.0 / 298.257223563;
+pub const VINCENTY_MAX_ITERATIONS: usize = 200;
+pub const VINCENTY_TOLERANCE: f64 = 1e-12;
+pub const WORLD_LAT_MAX: f64 = 90.0;
+pub const WORLD_LON_MAX: f64 = 180.0;
+pub const REFINE_WINDOW_MULT: f64 = 2.0;
+pub const SSE_EPSILON: f64 = 1e-6;
@@ -0,0 +1,130 @@
+//! Distances between anchors and candidate locations.
+
+use crate::constants::*;
+use lattice_core::{haversine_km, Endpoint};
+use serde::{Deserialize, Serialize};
+
+/// How distances between anchors and candidate locations are measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceModel {
+    /// Great circle on a sphere of mean Earth radius (haversine).
+    #[default]
+    Sphere,
+    /// Geodesic on the WGS-84 ellipsoid (Vincenty).
+    Wgs84,
+}
+
+impl DistanceModel {
+    /// Surface distance between two points.
+    pub fn distance_km(self, lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+        match self {
+            DistanceModel::Sphere => haversine_km(lat1, lon1, lat2, lon2),
+            // Vincenty does not converge for nearly antipodal points; the sphere is
+            // close enough there, where no RTT bound is tight anyway.
+            DistanceModel::Wgs84 => vincenty_km(lat1, lon1, lat2, lon2)
+                .unwrap_or_else(|| haversine_km(lat1, lon1, lat2, lon2)),
+        }
+    }
+
+    /// Distance from a surface point to an anchor `alt_km` above (or below) the
+    /// surface at its own position: the surface distance and the height combined
+    /// as legs of a right triangle.
+    pub fn distance_3d_km(self, lat1: f64, lon1: f64, lat2: f64, lon2: f64, alt_km: f64) -> f64 {
+        self.distance_km(lat1, lon1, lat2, lon2).hypot(alt_km)
+    }
+}
+
+/// Anchor altitude in km, 0 when unset.
+pub fn anchor_alt_km(ep: &Endpoint) -> f64 {
+    ep.alt_m.unwrap_or(0.0) / M_PER_KM
+}
+
+/// Inverse Vincenty on the WGS-84 ellipsoid. `None` when the iteration does
+/// not converge (nearly antipodal points).
+pub fn vincenty_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> Option<f64> {
+    let (a, f) = (WGS84_A_KM, WGS84_F);
+    let b = (1.0 - f) * a;
+    let l = (lon2 - lon1).to_radians();
+    let u1 = ((1.0 - f) * lat1.to_radians().tan()).atan();
+    let u2 = ((1.0 - f) * lat2.to_radians().tan()).atan();
+    let (sin_u1, cos_u1) = u1.sin_cos();
+    let (sin_u2, cos_u2) = u2.sin_cos();
+
+    let mut lambda = l;
+    for _ in 0..VINCENTY_MAX_ITERATIONS {
+        let (sin_lambda, cos_lambda) = lambda.sin_cos();
+        let sin_sigma = ((cos_u2 * sin_lambda).powi(2)
+            + (cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda).powi(2))
+        .sqrt();
+        if sin_sigma == 0.0 {
+            return Some(0.0);
+        }
+        let cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+        let sigma = sin_sigma.atan2(cos_sigma);
+        let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+        let cos2_alpha = 1.0 - sin_alpha * sin_alpha;
+        // On the equator cos2_alpha is 0 and the term drops out.
+        let cos_2sigma_m = if cos2_alpha != 0.0 {
+            cos_sigma - 2.0 * sin_u1 * sin_u2 / cos2_alpha
+        } else {
+            0.0
+        };
+        let c = f / 16.0 * cos2_alpha * (4.0 + f * (4.0 - 3.0 * cos2_alpha));
+        let prev = lambda;
+        lambda = l
+            + (1.0 - c)
+                * f
+                * sin_alpha
+                * (sigma
+                    + c * sin_sigma
+                        * (cos_2sigma_m + c * cos_sigma * (-1.0 + 2.0 * cos_2sigma_m.powi(2))));
+        if (lambda - prev).abs() < VINCENTY_TOLERANCE {
+            let u_sq = cos2_alpha * (a * a - b * b) / (b * b);
+            let big_a =
+                1.0 + u_sq / 16384.0 * (4096.0 + u_sq * (-768.0 + u_sq * (320.0 - 175.0 * u_sq)));
+            let big_b = u_sq / 1024.0 * (256.0 + u_sq * (-128.0 + u_sq * (74.0 - 47.0 * u_sq)));
+            let delta_sigma = big_b
+                * sin_sigma
+                * (cos_2sigma_m
+                    + big_b / 4.0
+                        * (cos_sigma * (-1.0 + 2.0 * cos_2sigma_m.powi(2))
+                            - big_b / 6.0
+                                * cos_2sigma_m
+                                * (-3.0 + 4.0 * sin_sigma.powi(2))
+                                * (-3.0 + 4.0 * cos_2sigma_m.powi(2))));
+            return Some(b * big_a * (sigma - delta_sigma));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wgs84_distance_matches_vincenty_reference() {
+        // Flinders Peak to Buninyong, the worked example in Vincenty (1975): 54972.271 m.
+        let dms = |d: f64, m: f64, s: f64| d + m / 60.0 + s / 3600.0;
+        let km = DistanceModel::Wgs84.distance_km(
+            -dms(37.0, 57.0, 3.72030),
+            dms(144.0, 25.0, 29.52440),
+            -dms(37.0, 39.0, 10.15610),
+            dms(143.0, 55.0, 35.38390),
+        );
+        assert!((km - 54.972271).abs() < 1e-6, "{km}");
+
+        // One degree along the equator is the semi-major axis times pi / 180.
+        let equator = DistanceModel::Wgs84.distance_km(0.0, 0.0, 0.0, 1.0);
+        assert!((equator - WGS84_A_KM * std::f64::consts::PI / 180.0).abs() < 1e-9);
+        assert_eq!(
+            DistanceModel::Wgs84.distance_km(10.0, 20.0, 10.0, 20.0),
+            0.0
+        );
+        // Nearly antipodal points fall back to the sphere instead of failing.
+        let antipodal = DistanceModel::Wgs84.distance_km(0.0, 0.0, 0.5, 179.7);
+        assert!(antipodal.is_finite() && antipodal > 19_000.0);
+    }
+}
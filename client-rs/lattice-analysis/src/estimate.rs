@@ -0,0 +1,576 @@
+//! The location fit: a weighted least-squares grid search for the point
+//! whose modelled RTTs, plus one common delay, best match the anchors'.
+
+use crate::calibration::{adjust_rtt_ms, Calibration};
+use crate::constants::*;
+use crate::distance::{anchor_alt_km, DistanceModel};
+use crate::geometry::{dop_at, Dop};
+use crate::propagation::{Leg, PropagationModel};
+use crate::stats::{stats_endpoint, EndpointStats};
+use lattice_core::Endpoint;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// One anchor as the fit sees it: where it is, the RTT to it after floors
+/// and calibration, and how much it counts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Observation {
+    pub lat: f64,
+    pub lon: f64,
+    pub alt_km: f64,
+    pub rtt_ms: f64,
+    pub weight: f64,
+    /// The endpoint's `regionHint`, for per-region propagation models.
+    pub region: Option<String>,
+}
+
+/// How much each anchor counts in the least-squares location fit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum WeightScheme {
+    /// `1 / jitter`: steadier anchors count more.
+    #[default]
+    Jitter,
+    /// `sqrt(samples) / jitter`: a p05 from many samples is a tighter estimate than one from a few.
+    Samples,
+    /// `jitter` weight halved for every half-life the anchor's newest burst trails the session's.
+    Recency,
+    /// `samples` and `recency` together.
+    Combined,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Weighting {
+    pub scheme: WeightScheme,
+    pub recency_half_life_s: f64,
+}
+
+impl Default for Weighting {
+    fn default() -> Self {
+        Weighting {
+            scheme: WeightScheme::Jitter,
+            recency_half_life_s: DEFAULT_RECENCY_HALF_LIFE_S,
+        }
+    }
+}
+
+impl Weighting {
+    /// Weights only matter relative to each other, so none of the factors is
+    /// normalized. An asymmetric path counts as that much more jitter.
+    pub fn weight(&self, st: &EndpointStats, newest_ts_ms: i64) -> f64 {
+        let jitter =
+            st.jitter_ms.unwrap_or(MIN_JITTER_MS).max(MIN_JITTER_MS) + st.asymmetry_ms.abs();
+        let samples = (st.count.max(1) as f64).sqrt();
+        let decay = if st.last_ts_ms <= 0 || self.recency_half_life_s <= 0.0 {
+            1.0
+        } else {
+            let age_s = (newest_ts_ms - st.last_ts_ms).max(0) as f64 / MS_PER_SEC;
+            (-age_s / self.recency_half_life_s).exp2()
+        };
+        match self.scheme {
+            WeightScheme::Jitter => 1.0 / jitter,
+            WeightScheme::Samples => samples / jitter,
+            WeightScheme::Recency => decay / jitter,
+            WeightScheme::Combined => samples * decay / jitter,
+        }
+    }
+}
+
+/// The best-fitting location for a capture.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Estimate {
+    pub lat: f64,
+    pub lon: f64,
+    /// Fitted delay common to every anchor (access link, host), in ms.
+    pub bias_ms: f64,
+    /// Weighted sum of squared RTT misfits at the estimate, in ms².
+    pub sse: f64,
+    /// Anchors in the fit.
+    pub points: usize,
+    pub band: Option<FitBand>,
+    /// How well the anchors in the fit surround the estimate; `None` when
+    /// their bearings cannot fix a position (e.g. all in a line).
+    pub geometry: Option<Dop>,
+}
+
+/// Grid points around the estimate that fit almost as well as it does: the
+/// area the data cannot tell apart from it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FitBand {
+    /// Farthest such point from the estimate.
+    pub radius_km: f64,
+    pub sse_threshold: f64,
+    /// Per-anchor RTT misfit allowed on top of the band factor (bufferbloat).
+    pub margin_ms: f64,
+    pub points: usize,
+    pub min_lat: f64,
+    pub max_lat: f64,
+    pub min_lon: f64,
+    pub max_lon: f64,
+    /// The band's outline as closed `[lon, lat]` vertices: each grid row's
+    /// westmost point going north, then its eastmost going south. Gaps
+    /// inside a row are filled in.
+    pub outline: Vec<[f64; 2]>,
+}
+
+/// Area a grid search covers, in degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub lat_min: f64,
+    pub lat_max: f64,
+    pub lon_min: f64,
+    pub lon_max: f64,
+}
+
+impl Bounds {
+    /// The whole globe.
+    pub const WORLD: Bounds = Bounds {
+        lat_min: -WORLD_LAT_MAX,
+        lat_max: WORLD_LAT_MAX,
+        lon_min: -WORLD_LON_MAX,
+        lon_max: WORLD_LON_MAX,
+    };
+
+    /// `window` degrees either way of a point.
+    pub fn around(lat: f64, lon: f64, window: f64) -> Self {
+        Bounds {
+            lat_min: lat - window,
+            lat_max: lat + window,
+            lon_min: lon - window,
+            lon_max: lon + window,
+        }
+    }
+}
+
+/// A candidate location with its weighted SSE and fitted common delay.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fit {
+    pub lat: f64,
+    pub lon: f64,
+    pub sse: f64,
+    pub bias_ms: f64,
+}
+
+/// Farthest an anchor can be for an RTT already floored and calibrated;
+/// `None` for an RTT or bound that is not positive and finite.
+pub fn max_distance_km(rtt_ms: f64, model: &dyn PropagationModel, leg: Leg) -> Option<f64> {
+    if !rtt_ms.is_finite() || rtt_ms <= 0.0 {
+        return None;
+    }
+    Some(model.max_distance_km(rtt_ms, leg)).filter(|d| d.is_finite())
+}
+
+/// Estimates the host's location from per-endpoint stats: a `grid`-degree
+/// search of the globe, refined at `refine` degrees around the best cell,
+/// then the fit band around the result. Each anchor's RTT is its p05 (or
+/// minimum) less its floors and calibration. Excluded endpoints and those
+/// without coordinates are left out; `None` with fewer than three anchors.
+#[allow(clippy::too_many_arguments)]
+pub fn estimate_location(
+    stats: &HashMap<String, EndpointStats>,
+    endpoints: &HashMap<String, Endpoint>,
+    model: &dyn PropagationModel,
+    grid: f64,
+    refine: f64,
+    band_factor: f64,
+    band_window_deg: f64,
+    band_margin_ms: f64,
+    calibration: Option<&Calibration>,
+    weighting: Weighting,
+    distance: DistanceModel,
+) -> Option<Estimate> {
+    // Sorted so the fit sums in the same order every run (bit-for-bit reproducible).
+    let mut ids: Vec<&String> = stats.keys().collect();
+    ids.sort();
+    let newest_ts_ms = stats
+        .values()
+        .filter(|st| st.excluded.is_none())
+        .map(|st| st.last_ts_ms)
+        .max()
+        .unwrap_or(0);
+    let mut obs = Vec::new();
+    for id in ids {
+        let st = &stats[id];
+        if st.excluded.is_some() {
+            continue;
+        }
+        let Some(ep) = stats_endpoint(endpoints, id) else {
+            continue;
+        };
+        let (Some(lat), Some(lon)) = (ep.lat, ep.lon) else {
+            continue;
+        };
+        let rtt = match st.p05.or(st.min) {
+            Some(v) if v.is_finite() && v > 0.0 => v,
+            _ => continue,
+        };
+        let rtt = adjust_rtt_ms(rtt, st.floor_ms(), id, calibration);
+        if !rtt.is_finite() || rtt <= 0.0 {
+            continue;
+        }
+        obs.push(Observation {
+            lat,
+            lon,
+            alt_km: anchor_alt_km(ep),
+            rtt_ms: rtt,
+            weight: weighting.weight(st, newest_ts_ms),
+            region: ep.region_hint.clone(),
+        });
+    }
+    if obs.len() < 3 {
+        return None;
+    }
+
+    let coarse = grid_search(&obs, model, grid, distance)?;
+    let window = grid.max(refine * REFINE_WINDOW_MULT);
+    let best = grid_search_bounds(
+        &obs,
+        model,
+        Bounds::around(coarse.lat, coarse.lon, window),
+        refine,
+        distance,
+    )?;
+
+    let band = fit_band(
+        &obs,
+        model,
+        &best,
+        refine,
+        band_factor,
+        band_window_deg.max(window),
+        band_margin_ms,
+        distance,
+    );
+
+    if ![best.lat, best.lon, best.bias_ms, best.sse]
+        .iter()
+        .all(|v| v.is_finite())
+    {
+        return None;
+    }
+    let anchors: Vec<(f64, f64)> = obs.iter().map(|o| (o.lat, o.lon)).collect();
+    Some(Estimate {
+        lat: best.lat,
+        lon: best.lon,
+        bias_ms: best.bias_ms,
+        sse: best.sse,
+        points: obs.len(),
+        band,
+        geometry: dop_at(&anchors, best.lat, best.lon),
+    })
+}
+
+/// Lowest-SSE candidate on a `step`-degree grid over the globe.
+pub fn grid_search(
+    obs: &[Observation],
+    model: &dyn PropagationModel,
+    step: f64,
+    distance: DistanceModel,
+) -> Option<Fit> {
+    grid_search_bounds(obs, model, Bounds::WORLD, step, distance)
+}
+
+/// Lowest-SSE candidate on a `step`-degree grid over `bounds`, latitudes
+/// clamped to the poles. `None` for a step that is not positive.
+pub fn grid_search_bounds(
+    obs: &[Observation],
+    model: &dyn PropagationModel,
+    bounds: Bounds,
+    step: f64,
+    distance: DistanceModel,
+) -> Option<Fit> {
+    if step <= 0.0 {
+        return None;
+    }
+    let mut best: Option<Fit> = None;
+    let mut lat = bounds.lat_min.max(-WORLD_LAT_MAX);
+    while lat <= bounds.lat_max.min(WORLD_LAT_MAX) {
+        let mut lon = bounds.lon_min;
+        while lon <= bounds.lon_max {
+            let fit = sse_for_candidate(lat, lon, obs, model, distance);
+            match best {
+                None => best = Some(fit),
+                Some(b) if fit.sse < b.sse => best = Some(fit),
+                _ => {}
+            }
+            lon += step;
+        }
+        lat += step;
+    }
+    best
+}
+
+/// Weighted SSE of the RTT model at one candidate, with the common delay
+/// fitted in closed form (never below zero).
+pub fn sse_for_candidate(
+    lat: f64,
+    lon: f64,
+    obs: &[Observation],
+    model: &dyn PropagationModel,
+    distance: DistanceModel,
+) -> Fit {
+    let preds: Vec<f64> = obs
+        .iter()
+        .map(|o| {
+            let leg = Leg {
+                region: o.region.as_deref(),
+                anchor: Some((o.lat, o.lon)),
+                host: Some((lat, lon)),
+            };
+            model.rtt_ms(
+                distance.distance_3d_km(lat, lon, o.lat, o.lon, o.alt_km),
+                leg,
+            )
+        })
+        .collect();
+    let mut sum_w = 0.0;
+    let mut sum_wx = 0.0;
+    for (o, pred_no_bias) in obs.iter().zip(&preds) {
+        let w = o.weight;
+        sum_w += w;
+        sum_wx += w * (o.rtt_ms - pred_no_bias);
+    }
+    let mut bias = if sum_w > 0.0 { sum_wx / sum_w } else { 0.0 };
+    if bias < 0.0 {
+        bias = 0.0;
+    }
+    let mut sse = 0.0;
+    for (o, pred_no_bias) in obs.iter().zip(&preds) {
+        let pred = pred_no_bias + bias;
+        let w = o.weight;
+        let err = o.rtt_ms - pred;
+        sse += w * err * err;
+    }
+    Fit {
+        lat,
+        lon,
+        sse,
+        bias_ms: bias,
+    }
+}
+
+/// The fit band around `best`: grid points within `window_deg` whose SSE is
+/// at most `best.sse * (1 + factor)` plus `margin_ms` per anchor.
+#[allow(clippy::too_many_arguments)]
+pub fn fit_band(
+    obs: &[Observation],
+    model: &dyn PropagationModel,
+    best: &Fit,
+    step: f64,
+    factor: f64,
+    window_deg: f64,
+    margin_ms: f64,
+    distance: DistanceModel,
+) -> Option<FitBand> {
+    if step <= 0.0 {
+        return None;
+    }
+    let (center_lat, center_lon) = (best.lat, best.lon);
+    // Each anchor may miss by `margin_ms` more; the SSE is weighted, so the
+    // allowance scales with the total weight.
+    let total_weight: f64 = obs.iter().map(|o| o.weight).sum();
+    let threshold = (best.sse * (1.0 + factor)).max(best.sse + SSE_EPSILON)
+        + margin_ms * margin_ms * total_weight;
+    let mut min_lat = center_lat;
+    let mut max_lat = center_lat;
+    let mut min_lon = center_lon;
+    let mut max_lon = center_lon;
+    let mut max_dist = 0.0;
+    let mut points = 0usize;
+    // Per grid row with band points: latitude, westmost and eastmost lon.
+    let mut rows: Vec<(f64, f64, f64)> = Vec::new();
+
+    let lat_min = (center_lat - window_deg).max(-WORLD_LAT_MAX);
+    let lat_max = (center_lat + window_deg).min(WORLD_LAT_MAX);
+    let lon_min = center_lon - window_deg;
+    let lon_max = center_lon + window_deg;
+
+    let mut lat = lat_min;
+    while lat <= lat_max {
+        let mut lon = lon_min;
+        let mut row: Option<(f64, f64)> = None;
+        while lon <= lon_max {
+            let fit = sse_for_candidate(lat, lon, obs, model, distance);
+            if fit.sse <= threshold {
+                points += 1;
+                row = Some(row.map_or((lon, lon), |(west, _)| (west, lon)));
+                let dist = distance.distance_km(center_lat, center_lon, lat, lon);
+                if dist > max_dist {
+                    max_dist = dist;
+                }
+                if lat < min_lat {
+                    min_lat = lat;
+                }
+                if lat > max_lat {
+                    max_lat = lat;
+                }
+                if lon < min_lon {
+                    min_lon = lon;
+                }
+                if lon > max_lon {
+                    max_lon = lon;
+                }
+            }
+            lon += step;
+        }
+        if let Some((west, east)) = row {
+            rows.push((lat, west, east));
+        }
+        lat += step;
+    }
+
+    if points == 0 {
+        return None;
+    }
+    let mut outline: Vec<[f64; 2]> = rows.iter().map(|&(lat, west, _)| [west, lat]).collect();
+    outline.extend(rows.iter().rev().map(|&(lat, _, east)| [east, lat]));
+    outline.push(outline[0]);
+    Some(FitBand {
+        radius_km: max_dist,
+        sse_threshold: threshold,
+        margin_ms,
+        points,
+        min_lat,
+        max_lat,
+        min_lon,
+        max_lon,
+        outline,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calibration::EndpointCalibration;
+    use crate::testutil::*;
+    use std::collections::BTreeMap;
+
+    const TEST_BIAS_MS: f64 = 1000.0;
+
+    #[test]
+    fn weighting_schemes_scale_by_samples_and_age() {
+        let mut st = stats_with_p05("a", 10.0).remove("a").unwrap();
+        st.count = 100;
+        st.jitter_ms = Some(2.0);
+        st.last_ts_ms = 1_000_000;
+        let newest = st.last_ts_ms + 2 * 3_600_000;
+        let w = |scheme, st: &EndpointStats| {
+            Weighting {
+                scheme,
+                recency_half_life_s: 3600.0,
+            }
+            .weight(st, newest)
+        };
+        assert_eq!(w(WeightScheme::Jitter, &st), 0.5);
+        assert_eq!(w(WeightScheme::Samples, &st), 5.0);
+        assert_eq!(w(WeightScheme::Recency, &st), 0.125);
+        assert_eq!(w(WeightScheme::Combined, &st), 1.25);
+
+        // Records without timestamps are not penalized for age.
+        st.last_ts_ms = 0;
+        assert_eq!(w(WeightScheme::Recency, &st), 0.5);
+    }
+
+    #[test]
+    fn estimate_location_respects_calibration() {
+        let mut stats = HashMap::new();
+        stats.insert(
+            "a".to_string(),
+            EndpointStats {
+                count: 10,
+                min: Some(10.0),
+                p05: Some(10.0),
+                p50: Some(10.0),
+                p95: Some(10.0),
+                jitter_ms: Some(MIN_JITTER_MS),
+                bursts: 10,
+                ..Default::default()
+            },
+        );
+        stats.insert(
+            "b".to_string(),
+            EndpointStats {
+                count: 10,
+                min: Some(10.0),
+                p05: Some(10.0),
+                p50: Some(10.0),
+                p95: Some(10.0),
+                jitter_ms: Some(MIN_JITTER_MS),
+                bursts: 10,
+                ..Default::default()
+            },
+        );
+        stats.insert(
+            "c".to_string(),
+            EndpointStats {
+                count: 10,
+                min: Some(10.0),
+                p05: Some(10.0),
+                p50: Some(10.0),
+                p95: Some(10.0),
+                jitter_ms: Some(MIN_JITTER_MS),
+                bursts: 10,
+                ..Default::default()
+            },
+        );
+        let mut endpoints = HashMap::new();
+        endpoints.insert("a".to_string(), endpoint("a", 0.0, 0.0));
+        endpoints.insert("b".to_string(), endpoint("b", 0.0, 1.0));
+        endpoints.insert("c".to_string(), endpoint("c", 1.0, 0.0));
+
+        let est = estimate_location(
+            &stats,
+            &endpoints,
+            &TEST_MODEL,
+            TEST_GRID_DEG,
+            TEST_REFINE_DEG,
+            DEFAULT_BAND_FACTOR,
+            DEFAULT_BAND_WINDOW_DEG,
+            0.0,
+            None,
+            Weighting::default(),
+            DistanceModel::Sphere,
+        );
+        assert!(est.is_some());
+
+        let mut cal_eps = BTreeMap::new();
+        for id in ["a", "b", "c"] {
+            cal_eps.insert(
+                id.to_string(),
+                EndpointCalibration {
+                    bias_ms: TEST_BIAS_MS,
+                    bias_us: 0,
+                    scale: 1.0,
+                    transports: BTreeMap::new(),
+                },
+            );
+        }
+        let cal = Calibration {
+            generated_at: "0".to_string(),
+            calibration_lat: 0.0,
+            calibration_lon: 0.0,
+            speed_km_s: DEFAULT_SPEED_KM_S,
+            path_stretch: DEFAULT_PATH_STRETCH,
+            distance_model: DistanceModel::Sphere,
+            propagation_model: None,
+            endpoints: cal_eps,
+        };
+        let est2 = estimate_location(
+            &stats,
+            &endpoints,
+            &TEST_MODEL,
+            TEST_GRID_DEG,
+            TEST_REFINE_DEG,
+            DEFAULT_BAND_FACTOR,
+            DEFAULT_BAND_WINDOW_DEG,
+            0.0,
+            Some(&cal),
+            Weighting::default(),
+            DistanceModel::Sphere,
+        );
+        assert!(est2.is_none());
+    }
+}
@@ -0,0 +1,107 @@
+//! How well a set of anchors can fix a position: dilution of precision and
+//! the error ellipse.
+
+use lattice_core::haversine_km;
+use serde::Serialize;
+
+/// Anchors this close to a grid point give no usable bearing there.
+const MIN_BEARING_DISTANCE_KM: f64 = 1.0;
+/// Distance at which an anchor's range error has doubled: routing detours
+/// (path stretch) grow with distance, so far anchors constrain less than
+/// their bearing alone suggests.
+pub const RANGE_ERROR_DOUBLING_KM: f64 = 1000.0;
+const SINGULAR_DET: f64 = 1e-9;
+/// Ellipse axes are undirected, so bearings wrap at 180°.
+const HALF_TURN_DEG: f64 = 180.0;
+
+/// Initial great-circle bearing from (lat1, lon1) to (lat2, lon2), radians.
+fn bearing_rad(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (p1, p2) = (lat1.to_radians(), lat2.to_radians());
+    let dl = (lon2 - lon1).to_radians();
+    (dl.sin() * p2.cos()).atan2(p1.cos() * p2.sin() - p1.sin() * p2.cos() * dl.cos())
+}
+
+/// Dilution of precision at one point, in units of a nearby anchor's range
+/// error, with the 1-sigma error ellipse of the horizontal position.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Dop {
+    /// Position and the common delay together.
+    pub gdop: f64,
+    pub hdop: f64,
+    pub ellipse_major: f64,
+    pub ellipse_minor: f64,
+    /// Direction of the major axis (the worst-constrained one), degrees
+    /// clockwise from north in `[0, 180)`.
+    pub ellipse_bearing_deg: f64,
+}
+
+/// DOP at one point for RTT ranging with an unknown common delay (the
+/// access link), i.e. the GNSS pseudo-range model with rows
+/// `[sin(bearing), cos(bearing), 1]`, each weighted by the inverse variance
+/// of a range error that grows linearly with distance. `None` with fewer
+/// than three usable bearings or collinear anchors.
+pub fn dop_at(anchors: &[(f64, f64)], lat: f64, lon: f64) -> Option<Dop> {
+    let mut g = [[0.0f64; 3]; 3];
+    let mut used = 0;
+    for &(a_lat, a_lon) in anchors {
+        let distance = haversine_km(lat, lon, a_lat, a_lon);
+        if distance < MIN_BEARING_DISTANCE_KM {
+            continue;
+        }
+        let weight = (1.0 + distance / RANGE_ERROR_DOUBLING_KM).powi(-2);
+        let b = bearing_rad(lat, lon, a_lat, a_lon);
+        let row = [b.sin(), b.cos(), 1.0];
+        for (i, gi) in g.iter_mut().enumerate() {
+            for (j, gij) in gi.iter_mut().enumerate() {
+                *gij += weight * row[i] * row[j];
+            }
+        }
+        used += 1;
+    }
+    if used < 3 {
+        return None;
+    }
+    let inv = invert3(&g)?;
+    // East-north covariance block: eigenvalues give the ellipse axes, the
+    // major eigenvector its direction.
+    let (ee, nn, en) = (inv[0][0], inv[1][1], inv[0][1]);
+    let mid = (ee + nn) / 2.0;
+    let spread = (((ee - nn) / 2.0).powi(2) + en * en).sqrt();
+    let major_from_east = 0.5 * (2.0 * en).atan2(ee - nn);
+    let dop = Dop {
+        gdop: (inv[0][0] + inv[1][1] + inv[2][2]).sqrt(),
+        hdop: (ee + nn).sqrt(),
+        ellipse_major: (mid + spread).sqrt(),
+        ellipse_minor: (mid - spread).max(0.0).sqrt(),
+        ellipse_bearing_deg: (90.0 - major_from_east.to_degrees()).rem_euclid(HALF_TURN_DEG),
+    };
+    [
+        dop.gdop,
+        dop.hdop,
+        dop.ellipse_major,
+        dop.ellipse_bearing_deg,
+    ]
+    .iter()
+    .all(|v| v.is_finite())
+    .then_some(dop)
+}
+
+fn invert3(m: &[[f64; 3]; 3]) -> Option<[[f64; 3]; 3]> {
+    let c = |r: usize, k: usize| {
+        let (r1, r2) = ((r + 1) % 3, (r + 2) % 3);
+        let (k1, k2) = ((k + 1) % 3, (k + 2) % 3);
+        m[r1][k1] * m[r2][k2] - m[r1][k2] * m[r2][k1]
+    };
+    let det = m[0][0] * c(0, 0) + m[0][1] * c(0, 1) + m[0][2] * c(0, 2);
+    if det.abs() < SINGULAR_DET {
+        return None;
+    }
+    let mut inv = [[0.0; 3]; 3];
+    for (r, row) in inv.iter_mut().enumerate() {
+        for (k, v) in row.iter_mut().enumerate() {
+            *v = c(k, r) / det;
+        }
+    }
+    Some(inv)
+}
@@ -0,0 +1,42 @@
+//! The LATTICE location estimator as a library: per-endpoint RTT stats from
+//! burst records, calibration, physics bounds on a claimed location, and the
+//! grid least-squares estimate. `lattice analyze` is built on it; dashboards
+//! and CI checks can call it directly instead of parsing the CLI's output.
+//!
+//! A typical run: load records, `build_stats`, gate them with `SampleGate`,
+//! then `estimate_location` and `claim_checks` against the config's
+//! endpoints (`endpoints_by_id`) under a `PropagationSpec`'s model.
+
+pub mod calibration;
+pub mod claim;
+pub mod constants;
+pub mod distance;
+pub mod estimate;
+pub mod geometry;
+pub mod propagation;
+pub mod stats;
+
+#[cfg(any(test, feature = "testing"))]
+#[doc(hidden)]
+pub mod testutil;
+
+pub use calibration::{
+    adjust_rtt_ms, build_calibration, calibration_entry, load_calibration, save_calibration,
+    Calibration, EndpointCalibration, TransportCalibration,
+};
+pub use claim::{claim_check, claim_checks, ClaimCheck};
+pub use distance::{anchor_alt_km, vincenty_km, DistanceModel};
+pub use estimate::{
+    estimate_location, fit_band, grid_search, grid_search_bounds, max_distance_km,
+    sse_for_candidate, Bounds, Estimate, Fit, FitBand, Observation, WeightScheme, Weighting,
+};
+pub use geometry::{dop_at, Dop};
+pub use propagation::{Leg, PropagationModel, PropagationSpec};
+pub use stats::{
+    build_stats, clear_self_floor, endpoints_by_id, quantile, record_samples, set_access_floor,
+    stats_endpoint, EndpointStats, SampleGate,
+};
+
+pub const ESTIMATOR_NAME: &str = "grid-least-squares";
+/// Bump when a change to the estimator can move an estimate for the same inputs.
+pub const ESTIMATOR_VERSION: u32 = 1;
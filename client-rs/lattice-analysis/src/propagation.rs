@@ -0,0 +1,544 @@
+//! How a distance to an anchor turns into an RTT, and back into a distance
+//! bound. The built-in model is a straight line at one effective speed (the
+//! fitted common delay supplies the intercept); `--propagation-model` swaps
+//! in a piecewise, per-region, or region-pair one.
+
+use crate::constants::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Anchors closer than this say more about the access line than the path.
+const MIN_LEARN_DISTANCE_KM: f64 = 100.0;
+/// Shipped effective speeds by region pair, from the best observed RTTs
+/// between well-known city pairs (Frankfurt–London, New York–London,
+/// Los Angeles–Tokyo) so that bounds stay valid on good routes.
+const INTRA_EU_KM_S: f64 = 140_000.0;
+const TRANS_ATLANTIC_KM_S: f64 = 170_000.0;
+const TRANS_PACIFIC_KM_S: f64 = 175_000.0;
+/// Coarse continent boxes as (lat min, lat max, lon min, lon max).
+const EUROPE_BOX: (f64, f64, f64, f64) = (35.0, 72.0, -25.0, 45.0);
+const NORTH_AMERICA_BOX: (f64, f64, f64, f64) = (15.0, 75.0, -170.0, -50.0);
+/// Asia-Pacific wraps the antimeridian, so it is two boxes.
+const ASIA_PACIFIC_BOXES: [(f64, f64, f64, f64); 2] =
+    [(-50.0, 75.0, 60.0, 180.0), (-50.0, 75.0, -180.0, -170.0)];
+
+/// What a model may know about a path besides its length.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Leg<'a> {
+    /// The anchor's `regionHint`.
+    pub region: Option<&'a str>,
+    pub anchor: Option<(f64, f64)>,
+    /// The host end: a fit candidate, claim, or calibration site. Unknown
+    /// for plain distance bounds.
+    pub host: Option<(f64, f64)>,
+}
+
+/// Distance-to-RTT model used by the location fit, the distance bounds, and
+/// calibration. Delays common to every anchor are fitted separately, so a
+/// model only has to say how RTT grows with distance.
+pub trait PropagationModel: fmt::Debug {
+    /// Round-trip propagation time over `dist_km`, without fixed delays.
+    fn rtt_ms(&self, dist_km: f64, leg: Leg) -> f64;
+
+    /// Farthest an anchor can be for a given RTT; the inverse of `rtt_ms`.
+    fn max_distance_km(&self, rtt_ms: f64, leg: Leg) -> f64;
+}
+
+fn linear_rtt_ms(dist_km: f64, speed_km_s: f64) -> f64 {
+    RTT_FACTOR * dist_km / (speed_km_s / MS_PER_SEC)
+}
+
+fn linear_distance_km(rtt_ms: f64, speed_km_s: f64) -> f64 {
+    speed_km_s / MS_PER_SEC * (rtt_ms / RTT_FACTOR)
+}
+
+/// One effective speed everywhere (fiber speed over path stretch); the
+/// fitted common delay is the intercept.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Linear {
+    pub speed_km_s: f64,
+}
+
+impl PropagationModel for Linear {
+    fn rtt_ms(&self, dist_km: f64, _leg: Leg) -> f64 {
+        linear_rtt_ms(dist_km, self.speed_km_s)
+    }
+
+    fn max_distance_km(&self, rtt_ms: f64, _leg: Leg) -> f64 {
+        linear_distance_km(rtt_ms, self.speed_km_s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Segment {
+    /// End of the segment; null for the last, open-ended one.
+    pub up_to_km: Option<f64>,
+    pub speed_km_s: f64,
+}
+
+/// Effective speed changing with distance, e.g. slow metro paths and fast
+/// long-haul ones. Each segment's speed applies to the part of the path
+/// inside it, so RTT stays continuous and increasing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Piecewise {
+    pub segments: Vec<Segment>,
+}
+
+impl Piecewise {
+    /// Each segment's start, length, and speed, in order.
+    fn spans(&self) -> impl Iterator<Item = (f64, f64, f64)> + '_ {
+        let mut start = 0.0;
+        self.segments.iter().map(move |seg| {
+            let end = seg.up_to_km.unwrap_or(f64::INFINITY);
+            let span = (start, end - start, seg.speed_km_s);
+            start = end;
+            span
+        })
+    }
+}
+
+impl PropagationModel for Piecewise {
+    fn rtt_ms(&self, dist_km: f64, _leg: Leg) -> f64 {
+        self.spans()
+            .map(|(start, len, speed)| linear_rtt_ms((dist_km - start).clamp(0.0, len), speed))
+            .sum()
+    }
+
+    fn max_distance_km(&self, rtt_ms: f64, _leg: Leg) -> f64 {
+        let mut budget = rtt_ms;
+        for (start, len, speed) in self.spans() {
+            let cost = linear_rtt_ms(len, speed);
+            if budget <= cost {
+                return start + linear_distance_km(budget, speed);
+            }
+            budget -= cost;
+        }
+        f64::NAN
+    }
+}
+
+/// Effective speed per anchor region (the endpoint's `regionHint`), e.g.
+/// learned by `calibrate --learn-region-speeds`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PerRegion {
+    pub speeds_km_s: BTreeMap<String, f64>,
+    /// For anchors without a region or with one not in the table.
+    pub default_speed_km_s: f64,
+}
+
+impl PerRegion {
+    fn speed(&self, region: Option<&str>) -> f64 {
+        region
+            .and_then(|r| self.speeds_km_s.get(r))
+            .copied()
+            .unwrap_or(self.default_speed_km_s)
+    }
+}
+
+impl PropagationModel for PerRegion {
+    fn rtt_ms(&self, dist_km: f64, leg: Leg) -> f64 {
+        linear_rtt_ms(dist_km, self.speed(leg.region))
+    }
+
+    fn max_distance_km(&self, rtt_ms: f64, leg: Leg) -> f64 {
+        linear_distance_km(rtt_ms, self.speed(leg.region))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Zone {
+    Europe,
+    NorthAmerica,
+    AsiaPacific,
+}
+
+fn in_box(lat: f64, lon: f64, b: (f64, f64, f64, f64)) -> bool {
+    (b.0..=b.1).contains(&lat) && (b.2..=b.3).contains(&lon)
+}
+
+fn zone(lat: f64, lon: f64) -> Option<Zone> {
+    if in_box(lat, lon, EUROPE_BOX) {
+        Some(Zone::Europe)
+    } else if in_box(lat, lon, NORTH_AMERICA_BOX) {
+        Some(Zone::NorthAmerica)
+    } else if ASIA_PACIFIC_BOXES.iter().any(|b| in_box(lat, lon, *b)) {
+        Some(Zone::AsiaPacific)
+    } else {
+        None
+    }
+}
+
+/// Effective speeds for the region pairs with well-known fiber routes.
+/// Any left out of an override keeps its shipped value.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PairSpeeds {
+    pub intra_eu: f64,
+    pub trans_atlantic: f64,
+    pub trans_pacific: f64,
+}
+
+impl Default for PairSpeeds {
+    fn default() -> Self {
+        PairSpeeds {
+            intra_eu: INTRA_EU_KM_S,
+            trans_atlantic: TRANS_ATLANTIC_KM_S,
+            trans_pacific: TRANS_PACIFIC_KM_S,
+        }
+    }
+}
+
+/// Effective speed by where the two ends are: intra-EU, trans-Atlantic, or
+/// trans-Pacific when both are placed in those zones, the default speed
+/// otherwise. Without a host end (plain bounds) the fastest speed the
+/// anchor could see is used, so bounds never tighten on a guess.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegionPairs {
+    #[serde(default)]
+    pub speeds_km_s: PairSpeeds,
+    /// For every other pair, e.g. the straight-line speed.
+    pub default_speed_km_s: f64,
+}
+
+impl RegionPairs {
+    pub fn shipped(default_speed_km_s: f64) -> Self {
+        RegionPairs {
+            speeds_km_s: PairSpeeds::default(),
+            default_speed_km_s,
+        }
+    }
+
+    fn pair_speed(&self, a: Option<Zone>, b: Option<Zone>) -> f64 {
+        let s = &self.speeds_km_s;
+        match (a, b) {
+            (Some(Zone::Europe), Some(Zone::Europe)) => s.intra_eu,
+            (Some(Zone::Europe), Some(Zone::NorthAmerica))
+            | (Some(Zone::NorthAmerica), Some(Zone::Europe)) => s.trans_atlantic,
+            (Some(Zone::NorthAmerica), Some(Zone::AsiaPacific))
+            | (Some(Zone::AsiaPacific), Some(Zone::NorthAmerica)) => s.trans_pacific,
+            _ => self.default_speed_km_s,
+        }
+    }
+
+    fn speed(&self, leg: Leg) -> f64 {
+        let anchor = leg.anchor.and_then(|(lat, lon)| zone(lat, lon));
+        match leg.host {
+            Some((lat, lon)) => self.pair_speed(zone(lat, lon), anchor),
+            None => [
+                None,
+                Some(Zone::Europe),
+                Some(Zone::NorthAmerica),
+                Some(Zone::AsiaPacific),
+            ]
+            .into_iter()
+            .map(|host| self.pair_speed(host, anchor))
+            .fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+}
+
+impl PropagationModel for RegionPairs {
+    fn rtt_ms(&self, dist_km: f64, leg: Leg) -> f64 {
+        linear_rtt_ms(dist_km, self.speed(leg))
+    }
+
+    fn max_distance_km(&self, rtt_ms: f64, leg: Leg) -> f64 {
+        linear_distance_km(rtt_ms, self.speed(leg))
+    }
+}
+
+/// A model as stored in a `--propagation-model` file and in calibrations:
+/// `{"kind": "linear" | "piecewise" | "perRegion" | "regionPairs", ...}` with the fields of
+/// that model. Speeds are effective ones, path stretch already applied.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum PropagationSpec {
+    Linear(Linear),
+    Piecewise(Piecewise),
+    PerRegion(PerRegion),
+    RegionPairs(RegionPairs),
+}
+
+impl PropagationSpec {
+    pub fn linear(speed_km_s: f64) -> Self {
+        PropagationSpec::Linear(Linear { speed_km_s })
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let spec: Self = serde_json::from_slice(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let data = serde_json::to_vec_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, data)
+    }
+
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid = |msg: &str| {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("propagation model: {msg}"),
+            ))
+        };
+        let speed_ok = |v: f64| v.is_finite() && v > 0.0;
+        match self {
+            PropagationSpec::Linear(m) if !speed_ok(m.speed_km_s) => {
+                invalid("speedKmS must be > 0")
+            }
+            PropagationSpec::Piecewise(m) => {
+                let Some((last, rest)) = m.segments.split_last() else {
+                    return invalid("piecewise needs at least one segment");
+                };
+                if last.up_to_km.is_some() || rest.iter().any(|s| s.up_to_km.is_none()) {
+                    return invalid("only the last segment may (and must) leave upToKm null");
+                }
+                let ends: Vec<f64> = rest.iter().filter_map(|s| s.up_to_km).collect();
+                let increasing =
+                    ends.first().is_none_or(|e| *e > 0.0) && ends.windows(2).all(|w| w[0] < w[1]);
+                if !increasing || ends.iter().any(|e| !e.is_finite()) {
+                    return invalid("segment upToKm must be finite and increasing from > 0");
+                }
+                if m.segments.iter().any(|s| !speed_ok(s.speed_km_s)) {
+                    return invalid("segment speedKmS must be > 0");
+                }
+                Ok(())
+            }
+            PropagationSpec::PerRegion(m) => {
+                if !speed_ok(m.default_speed_km_s) || m.speeds_km_s.values().any(|v| !speed_ok(*v))
+                {
+                    return invalid("per-region speeds must be > 0");
+                }
+                Ok(())
+            }
+            PropagationSpec::RegionPairs(m) => {
+                let s = &m.speeds_km_s;
+                if ![
+                    m.default_speed_km_s,
+                    s.intra_eu,
+                    s.trans_atlantic,
+                    s.trans_pacific,
+                ]
+                .into_iter()
+                .all(speed_ok)
+                {
+                    return invalid("region-pair speeds must be > 0");
+                }
+                Ok(())
+            }
+            PropagationSpec::Linear(_) => Ok(()),
+        }
+    }
+
+    pub fn model(&self) -> &dyn PropagationModel {
+        match self {
+            PropagationSpec::Linear(m) => m,
+            PropagationSpec::Piecewise(m) => m,
+            PropagationSpec::PerRegion(m) => m,
+            PropagationSpec::RegionPairs(m) => m,
+        }
+    }
+}
+
+/// One anchor seen from a known location: its region, the distance, and
+/// the RTT after floors.
+pub struct KnownPath<'a> {
+    pub region: Option<&'a str>,
+    pub dist_km: f64,
+    pub rtt_ms: f64,
+}
+
+/// Per-region effective speeds from a capture at a known location: the
+/// median of distance over RTT across the region's anchors, never faster
+/// than `max_speed_km_s`. Fixed delays end up in the speed, so the result
+/// suits bounds and fits near the calibration site best.
+pub fn learn_region_speeds(paths: &[KnownPath], max_speed_km_s: f64) -> PerRegion {
+    let mut by_region: BTreeMap<&str, Vec<f64>> = BTreeMap::new();
+    for p in paths {
+        let Some(region) = p.region else { continue };
+        if p.dist_km < MIN_LEARN_DISTANCE_KM || !p.rtt_ms.is_finite() || p.rtt_ms <= 0.0 {
+            continue;
+        }
+        let speed = RTT_FACTOR * p.dist_km / p.rtt_ms * MS_PER_SEC;
+        by_region
+            .entry(region)
+            .or_default()
+            .push(speed.min(max_speed_km_s));
+    }
+    let speeds_km_s = by_region
+        .into_iter()
+        .filter_map(|(region, mut v)| {
+            v.sort_by(f64::total_cmp);
+            crate::quantile(&v, 0.5).map(|m| (region.to_string(), m))
+        })
+        .collect();
+    PerRegion {
+        speeds_km_s,
+        default_speed_km_s: max_speed_km_s,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calibration::build_calibration;
+    use crate::claim::claim_checks;
+    use crate::distance::DistanceModel;
+    use crate::stats::endpoints_by_id;
+    use crate::testutil::*;
+    use lattice_core::Transport;
+
+    #[test]
+    fn propagation_models_invert_and_pick_speeds_by_distance_and_region() {
+        let linear = PropagationSpec::linear(DEFAULT_SPEED_KM_S);
+        let rtt = linear.model().rtt_ms(1000.0, Leg::default());
+        assert!(
+            (rtt - RTT_FACTOR * 1000.0 / (DEFAULT_SPEED_KM_S / MS_PER_SEC)).abs() < TEST_EPSILON
+        );
+        assert!(
+            (linear.model().max_distance_km(rtt, Leg::default()) - 1000.0).abs() < TEST_EPSILON
+        );
+
+        let piecewise: PropagationSpec = serde_json::from_str(
+            r#"{"kind":"piecewise","segments":[{"upToKm":500,"speedKmS":100000},{"upToKm":null,"speedKmS":200000}]}"#,
+        )
+        .unwrap();
+        piecewise.validate().unwrap();
+        let m = piecewise.model();
+        assert!((m.rtt_ms(500.0, Leg::default()) - 10.0).abs() < TEST_EPSILON);
+        assert!((m.rtt_ms(1500.0, Leg::default()) - 20.0).abs() < TEST_EPSILON);
+        for km in [0.0, 250.0, 500.0, 1500.0] {
+            assert!(
+                (m.max_distance_km(m.rtt_ms(km, Leg::default()), Leg::default()) - km).abs()
+                    < TEST_EPSILON
+            );
+        }
+        let open_middle: PropagationSpec = serde_json::from_str(
+            r#"{"kind":"piecewise","segments":[{"upToKm":null,"speedKmS":1},{"upToKm":null,"speedKmS":2}]}"#,
+        )
+        .unwrap();
+        assert!(open_middle.validate().is_err());
+
+        let learned = learn_region_speeds(
+            &[
+                KnownPath {
+                    region: Some("far"),
+                    dist_km: 1000.0,
+                    rtt_ms: 20.0,
+                },
+                KnownPath {
+                    region: Some("near"),
+                    dist_km: 50.0,
+                    rtt_ms: 5.0,
+                },
+                KnownPath {
+                    region: None,
+                    dist_km: 1000.0,
+                    rtt_ms: 20.0,
+                },
+            ],
+            DEFAULT_SPEED_KM_S,
+        );
+        assert_eq!(learned.speeds_km_s.keys().collect::<Vec<_>>(), vec!["far"]);
+        assert!((learned.speeds_km_s["far"] - 100_000.0).abs() < TEST_EPSILON);
+        let per_region = PropagationSpec::PerRegion(learned);
+        let m = per_region.model();
+        let in_region = |region| Leg {
+            region: Some(region),
+            ..Leg::default()
+        };
+        assert!((m.rtt_ms(1000.0, in_region("far")) - 20.0).abs() < TEST_EPSILON);
+        assert!(
+            (m.rtt_ms(1000.0, in_region("near")) - linear.model().rtt_ms(1000.0, Leg::default()))
+                .abs()
+                < TEST_EPSILON
+        );
+
+        let cfg = sample_config(vec![endpoint("a", 0.0, 0.0)]);
+        let stats = BTreeMap::from([(Transport::Udp, stats_with_p05("a", 12.5))]);
+        let cal = build_calibration(
+            &cfg,
+            &stats,
+            0.0,
+            0.0,
+            DEFAULT_SPEED_KM_S,
+            TEST_PATH_STRETCH,
+            DistanceModel::Sphere,
+            &piecewise,
+        );
+        assert_eq!(cal.propagation_model, Some(piecewise));
+    }
+
+    #[test]
+    fn region_pair_speeds_follow_both_ends_and_stay_loose_without_a_host() {
+        let (london, frankfurt, new_york, tokyo, lagos) = (
+            (51.5, -0.1),
+            (50.1, 8.7),
+            (40.7, -74.0),
+            (35.7, 139.7),
+            (6.5, 3.4),
+        );
+        let shipped = RegionPairs::shipped(DEFAULT_SPEED_KM_S);
+        let speed = |host: Option<(f64, f64)>, anchor: (f64, f64)| {
+            let leg = Leg {
+                region: None,
+                anchor: Some(anchor),
+                host,
+            };
+            shipped.max_distance_km(RTT_FACTOR * MS_PER_SEC, leg)
+        };
+        let s = shipped.speeds_km_s;
+        assert!((speed(Some(london), frankfurt) - s.intra_eu).abs() < TEST_EPSILON);
+        assert!((speed(Some(new_york), london) - s.trans_atlantic).abs() < TEST_EPSILON);
+        assert!((speed(Some(tokyo), new_york) - s.trans_pacific).abs() < TEST_EPSILON);
+        assert!((speed(Some(lagos), london) - DEFAULT_SPEED_KM_S).abs() < TEST_EPSILON);
+        assert!((speed(None, frankfurt) - DEFAULT_SPEED_KM_S).abs() < TEST_EPSILON);
+
+        let spec: PropagationSpec = serde_json::from_str(
+            r#"{"kind":"regionPairs","speedsKmS":{"intraEu":100000},"defaultSpeedKmS":150000}"#,
+        )
+        .unwrap();
+        spec.validate().unwrap();
+        let PropagationSpec::RegionPairs(pairs) = &spec else {
+            panic!("wrong kind")
+        };
+        assert_eq!(pairs.speeds_km_s.intra_eu, 100_000.0);
+        assert_eq!(pairs.speeds_km_s.trans_atlantic, s.trans_atlantic);
+
+        let mut a = endpoint("a", frankfurt.0, frankfurt.1);
+        a.region_hint = Some("eu-central-1".into());
+        let endpoints = endpoints_by_id(&[a]);
+        let stats = stats_with_p05("a", 8.0);
+        let check = |model: &dyn PropagationModel| {
+            claim_checks(
+                &stats,
+                &endpoints,
+                london.0,
+                london.1,
+                model,
+                None,
+                DistanceModel::Sphere,
+            )[0]
+            .clone()
+        };
+        let tight = check(spec.model());
+        let loose = check(&Linear {
+            speed_km_s: DEFAULT_SPEED_KM_S,
+        });
+        assert!(tight.max_tight_km.unwrap() < loose.max_tight_km.unwrap());
+        assert_eq!(tight.falsify_tight, Some(true));
+        assert_eq!(loose.falsify_tight, Some(false));
+    }
+}
@@ -0,0 +1,404 @@
+//! Per-endpoint RTT statistics from burst records, the inputs to every
+//! bound and estimate.
+
+use lattice_core::{hampel_filter, suggested_access_floor_ms, BurstRecord, Endpoint};
+use std::collections::HashMap;
+
+/// RTT distribution of one endpoint (or `endpoint@path`) over a capture, in
+/// ms, before any floor or calibration.
+#[derive(Debug, Clone, Default)]
+pub struct EndpointStats {
+    /// Valid samples. Summary-only bursts add none.
+    pub count: usize,
+    pub min: Option<f64>,
+    pub p05: Option<f64>,
+    pub p50: Option<f64>,
+    pub p95: Option<f64>,
+    /// `p95 - p05`.
+    pub jitter_ms: Option<f64>,
+    /// Samples dropped by the `outlier_mad_k` filter.
+    pub outliers_filtered: usize,
+    /// Samples dropped because the interleaved LAN reference spiked with them.
+    pub interference_filtered: usize,
+    /// Smallest configured access floor across the endpoint's records (0 if any lacks one).
+    pub access_floor_ms: f64,
+    /// Floor suggested by the interface type when none is configured.
+    pub suggested_access_floor_ms: Option<f64>,
+    /// Host latency from the loopback self-test: the smallest `selfFloorMs`
+    /// across records, 0 if none carry one.
+    pub self_floor_ms: f64,
+    /// Bursts that contributed at least one valid sample, or valid summary
+    /// values when they carried no samples.
+    pub bursts: usize,
+    /// Of `bursts`, those with summary values only (`recordRawSamples: false`
+    /// without a histogram). Their `minMs` counts toward `min`; their `p05Ms`
+    /// and `medianMs` give `p05` and `p50`, as medians across bursts, only
+    /// when the endpoint has no samples at all.
+    pub summary_bursts: usize,
+    /// Why the endpoint is left out of estimates and claim checks, if it is.
+    pub excluded: Option<String>,
+    /// Timestamp of the newest burst with a valid sample (0 if unknown).
+    pub last_ts_ms: i64,
+    /// How much longer one leg of the path is than the other, from the
+    /// reflector-split legs of v2 samples (0 if unknown). The fit's RTT/2
+    /// one-way assumption is off by up to this much, so it widens the
+    /// anchor's tolerance.
+    pub asymmetry_ms: f64,
+}
+
+impl EndpointStats {
+    /// Everything subtracted from RTTs before they become distances.
+    pub fn floor_ms(&self) -> f64 {
+        self.access_floor_ms + self.self_floor_ms
+    }
+}
+
+/// Stats per `endpointId` across `records`. Samples that are negative or not
+/// finite are dropped; with `outlier_mad_k`, so is any sample more than that
+/// many scaled MADs above its burst's median. Summary-only records are
+/// aggregated from their summary values, never as samples.
+pub fn build_stats(
+    records: &[BurstRecord],
+    outlier_mad_k: Option<f64>,
+) -> HashMap<String, EndpointStats> {
+    let mut samples: HashMap<String, Vec<f64>> = HashMap::new();
+    let mut filtered: HashMap<String, usize> = HashMap::new();
+    let mut floors: HashMap<String, f64> = HashMap::new();
+    let mut self_floors: HashMap<String, f64> = HashMap::new();
+    let mut suggested: HashMap<String, Option<f64>> = HashMap::new();
+    let mut bursts: HashMap<String, usize> = HashMap::new();
+    let mut last_ts: HashMap<String, i64> = HashMap::new();
+    let mut summaries: HashMap<String, Summaries> = HashMap::new();
+    for rec in records {
+        let floor = floors
+            .entry(rec.endpoint_id.clone())
+            .or_insert(f64::INFINITY);
+        *floor = floor.min(rec.access_floor_ms.unwrap_or(0.0));
+        if let Some(ms) = rec.self_floor_ms.filter(|v| v.is_finite() && *v >= 0.0) {
+            let floor = self_floors.entry(rec.endpoint_id.clone()).or_insert(ms);
+            *floor = floor.min(ms);
+        }
+        let hint = suggested_access_floor_ms(&rec.iface).map(|(_, ms)| ms);
+        suggested
+            .entry(rec.endpoint_id.clone())
+            .and_modify(|s| {
+                if *s != hint {
+                    *s = None;
+                }
+            })
+            .or_insert(hint);
+        let raw = record_samples(rec);
+        if raw.is_empty() {
+            if summaries
+                .entry(rec.endpoint_id.clone())
+                .or_default()
+                .add(rec)
+            {
+                *bursts.entry(rec.endpoint_id.clone()).or_default() += 1;
+                let ts = last_ts.entry(rec.endpoint_id.clone()).or_default();
+                *ts = (*ts).max(rec.ts_unix_ms);
+            }
+            samples.entry(rec.endpoint_id.clone()).or_default();
+            continue;
+        }
+        let valid: Vec<f64> = raw
+            .into_iter()
+            .filter(|v| v.is_finite() && *v >= 0.0)
+            .collect();
+        let valid = match outlier_mad_k {
+            Some(k) => {
+                let (kept, dropped) = hampel_filter(&valid, k);
+                *filtered.entry(rec.endpoint_id.clone()).or_default() += dropped;
+                kept
+            }
+            None => valid,
+        };
+        if !valid.is_empty() {
+            *bursts.entry(rec.endpoint_id.clone()).or_default() += 1;
+            let ts = last_ts.entry(rec.endpoint_id.clone()).or_default();
+            *ts = (*ts).max(rec.ts_unix_ms);
+        }
+        samples
+            .entry(rec.endpoint_id.clone())
+            .or_default()
+            .extend(valid);
+    }
+
+    let mut stats = HashMap::new();
+    for (id, mut s) in samples {
+        s.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let count = s.len();
+        let summary = summaries.remove(&id).unwrap_or_default();
+        let summary_bursts = summary
+            .min
+            .len()
+            .max(summary.p05.len())
+            .max(summary.median.len());
+        let min = s
+            .first()
+            .copied()
+            .into_iter()
+            .chain(summary.min.iter().copied())
+            .min_by(f64::total_cmp);
+        let (p05, p50) = if s.is_empty() {
+            (median_of(summary.p05), median_of(summary.median))
+        } else {
+            (quantile(&s, 0.05), quantile(&s, 0.50))
+        };
+        let p95 = quantile(&s, 0.95);
+        let jitter_ms = match (p05, p95) {
+            (Some(a), Some(b)) if b >= a => Some(b - a),
+            _ => None,
+        };
+        let outliers_filtered = filtered.get(&id).copied().unwrap_or(0);
+        let access_floor_ms = floors.get(&id).copied().unwrap_or(0.0);
+        let suggested_access_floor_ms = suggested
+            .get(&id)
+            .copied()
+            .flatten()
+            .filter(|_| access_floor_ms == 0.0);
+        let self_floor_ms = self_floors.get(&id).copied().unwrap_or(0.0);
+        let bursts = bursts.get(&id).copied().unwrap_or(0);
+        let last_ts_ms = last_ts.get(&id).copied().unwrap_or(0);
+        stats.insert(
+            id,
+            EndpointStats {
+                count,
+                min,
+                p05,
+                p50,
+                p95,
+                jitter_ms,
+                outliers_filtered,
+                interference_filtered: 0,
+                access_floor_ms,
+                suggested_access_floor_ms,
+                self_floor_ms,
+                bursts,
+                summary_bursts,
+                excluded: None,
+                last_ts_ms,
+                asymmetry_ms: 0.0,
+            },
+        );
+    }
+    stats
+}
+
+/// Minimum evidence for an endpoint to constrain a location: one lucky
+/// packet should not weigh as much as a thousand.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SampleGate {
+    pub min_samples: usize,
+    pub min_bursts: usize,
+}
+
+impl SampleGate {
+    /// Sets or clears `excluded` on every endpoint.
+    pub fn apply(&self, stats: &mut HashMap<String, EndpointStats>) {
+        for st in stats.values_mut() {
+            st.excluded = if st.count < self.min_samples {
+                Some(format!("{} samples < {}", st.count, self.min_samples))
+            } else if st.bursts < self.min_bursts {
+                Some(format!("{} bursts < {}", st.bursts, self.min_bursts))
+            } else {
+                None
+            };
+        }
+    }
+}
+
+/// Applies one access floor to every endpoint, e.g. from `--access-floor-ms`.
+pub fn set_access_floor(stats: &mut HashMap<String, EndpointStats>, floor_ms: f64) {
+    for st in stats.values_mut() {
+        st.access_floor_ms = floor_ms;
+        st.suggested_access_floor_ms = None;
+    }
+}
+
+/// Drops the loopback self-test floor, e.g. for `--no-self-floor`.
+pub fn clear_self_floor(stats: &mut HashMap<String, EndpointStats>) {
+    for st in stats.values_mut() {
+        st.self_floor_ms = 0.0;
+    }
+}
+
+/// Samples for one burst. Records written with `recordRawSamples: false` carry
+/// no `samplesMs`, so fall back to the histogram's bucket midpoints. A record
+/// with neither has no samples: its `minMs`/`p05Ms`/`medianMs` summarize the
+/// burst and are not three of its RTTs.
+pub fn record_samples(rec: &BurstRecord) -> Vec<f64> {
+    if !rec.samples_ms.is_empty() {
+        return rec.samples_ms.clone();
+    }
+    if let Some(hist) = &rec.histogram {
+        return hist.representative_samples();
+    }
+    Vec::new()
+}
+
+/// A summary-only burst's `minMs`, `p05Ms`, and `medianMs`, the valid ones.
+#[derive(Default)]
+struct Summaries {
+    min: Vec<f64>,
+    p05: Vec<f64>,
+    median: Vec<f64>,
+}
+
+impl Summaries {
+    /// Whether `rec` added anything.
+    fn add(&mut self, rec: &BurstRecord) -> bool {
+        let valid = |v: Option<f64>| v.filter(|v| v.is_finite() && *v >= 0.0);
+        let mut added = false;
+        for (values, v) in [
+            (&mut self.min, rec.min_ms),
+            (&mut self.p05, rec.p05_ms),
+            (&mut self.median, rec.median_ms),
+        ] {
+            if let Some(v) = valid(v) {
+                values.push(v);
+                added = true;
+            }
+        }
+        added
+    }
+}
+
+fn median_of(mut values: Vec<f64>) -> Option<f64> {
+    values.sort_by(f64::total_cmp);
+    quantile(&values, 0.5)
+}
+
+/// Nearest-rank quantile of an ascending slice.
+pub fn quantile(sorted: &[f64], q: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let idx = ((sorted.len() - 1) as f64 * q).round() as usize;
+    sorted.get(idx).copied()
+}
+
+/// Config endpoints keyed by id, as the estimate and claim checks look them up.
+pub fn endpoints_by_id(endpoints: &[Endpoint]) -> HashMap<String, Endpoint> {
+    let mut map = HashMap::new();
+    for ep in endpoints {
+        map.insert(ep.id.clone(), ep.clone());
+    }
+    map
+}
+
+/// The endpoint behind a stats id, which may carry a `@path` suffix.
+pub fn stats_endpoint<'a>(
+    endpoints: &'a HashMap<String, Endpoint>,
+    id: &str,
+) -> Option<&'a Endpoint> {
+    endpoints.get(id).or_else(|| {
+        if let Some(base) = id.split('@').next() {
+            endpoints.get(base)
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calibration::adjust_rtt_ms;
+    use crate::testutil::*;
+
+    #[test]
+    fn build_stats_drops_stall_outliers_per_burst() {
+        let records = vec![
+            record("a", vec![10.0, 10.2, 9.9, 10.1, 250.0]),
+            record("a", vec![10.3, 10.0, 10.2, 9.8, 10.1]),
+        ];
+        let raw = build_stats(&records, None);
+        assert_eq!(raw["a"].count, 10);
+        assert_eq!(raw["a"].outliers_filtered, 0);
+
+        let filtered = build_stats(&records, Some(3.0));
+        assert_eq!(filtered["a"].count, 9);
+        assert_eq!(filtered["a"].outliers_filtered, 1);
+        assert!(filtered["a"].p95.unwrap() < 11.0);
+    }
+
+    #[test]
+    fn build_stats_handles_records_without_raw_samples() {
+        let mut summary_only = record("a", Vec::new());
+        summary_only.min_ms = Some(10.0);
+        summary_only.p05_ms = Some(10.5);
+        summary_only.median_ms = Some(12.0);
+        let mut with_hist = record("b", Vec::new());
+        with_hist.histogram = Some(lattice_core::Histogram::from_samples(&[20.0, 21.0, 22.0]));
+        let empty = record("c", Vec::new());
+
+        let mut faster = record("a", Vec::new());
+        faster.min_ms = Some(9.0);
+        faster.p05_ms = Some(9.5);
+        faster.median_ms = Some(11.0);
+        let mut slower = faster.clone();
+        slower.min_ms = Some(15.0);
+        slower.p05_ms = Some(16.0);
+        slower.median_ms = Some(20.0);
+
+        let stats = build_stats(&[summary_only, faster, slower, with_hist, empty], None);
+        // Summary values are aggregated as such, not counted as samples.
+        assert_eq!(stats["a"].count, 0);
+        assert_eq!((stats["a"].bursts, stats["a"].summary_bursts), (3, 3));
+        assert_eq!(stats["a"].min, Some(9.0));
+        assert_eq!(stats["a"].p05, Some(10.5));
+        assert_eq!(stats["a"].p50, Some(12.0));
+        assert!(stats["a"].p95.is_none());
+        assert_eq!(stats["b"].count, 3);
+        assert_eq!(stats["b"].summary_bursts, 0);
+        assert!((stats["b"].p50.unwrap() - 21.0).abs() < 1.0);
+        assert_eq!(stats["c"].count, 0);
+        assert!(stats["c"].p05.is_none());
+    }
+
+    #[test]
+    fn access_floor_is_per_path_and_subtracted_before_bounds() {
+        let mut fiber = record("a@fiber", vec![12.0, 12.5]);
+        fiber.access_floor_ms = Some(1.0);
+        let mut lte = record("a@lte", vec![30.0, 31.0]);
+        lte.access_floor_ms = Some(20.0);
+        let mut phone = record("a@phone", vec![30.0, 31.0]);
+        phone.iface = "cellular".to_string();
+        let stats = build_stats(&[fiber, lte, phone], None);
+
+        assert_eq!(stats["a@fiber"].access_floor_ms, 1.0);
+        assert_eq!(stats["a@lte"].access_floor_ms, 20.0);
+        assert_eq!(stats["a@phone"].access_floor_ms, 0.0);
+        assert_eq!(stats["a@phone"].suggested_access_floor_ms, Some(20.0));
+        assert_eq!(stats["a@lte"].suggested_access_floor_ms, None);
+
+        let st = &stats["a@lte"];
+        assert_eq!(
+            adjust_rtt_ms(st.p05.unwrap(), st.access_floor_ms, "a@lte", None),
+            10.0
+        );
+        assert_eq!(adjust_rtt_ms(5.0, 20.0, "a@lte", None), 0.0);
+    }
+
+    #[test]
+    fn self_floor_is_smallest_recorded_and_added_to_access_floor() {
+        let mut first = record("a", vec![12.0]);
+        first.self_floor_ms = Some(0.3);
+        first.access_floor_ms = Some(1.0);
+        let mut second = record("a", vec![12.5]);
+        second.self_floor_ms = Some(0.2);
+        second.access_floor_ms = Some(1.0);
+        let mut older = record("a", vec![13.0]);
+        older.access_floor_ms = Some(1.0);
+        let mut stats = build_stats(&[first, second, older], None);
+
+        let st = &stats["a"];
+        assert_eq!(st.self_floor_ms, 0.2);
+        assert_eq!(st.floor_ms(), 1.2);
+        assert!((adjust_rtt_ms(12.0, st.floor_ms(), "a", None) - 10.8).abs() < TEST_EPSILON);
+
+        clear_self_floor(&mut stats);
+        assert_eq!(stats["a"].floor_ms(), 1.0);
+    }
+}
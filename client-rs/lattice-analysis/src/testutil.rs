@@ -0,0 +1,82 @@
+//! Fixtures shared by the estimator's unit tests and, through the `testing`
+//! feature, by `lattice-analyze`'s.
+
+use crate::propagation;
+use crate::stats::EndpointStats;
+use lattice_core::{BurstRecord, Config, Endpoint, LATO_VERSION};
+use std::collections::HashMap;
+
+pub use crate::constants::DEFAULT_SPEED_KM_S;
+
+pub const DEFAULT_PORT: u16 = 9000;
+pub const TEST_GRID_DEG: f64 = 5.0;
+pub const TEST_REFINE_DEG: f64 = 1.0;
+pub const TEST_PATH_STRETCH: f64 = 1.0;
+pub const TEST_EPSILON: f64 = 1e-6;
+pub const TEST_MODEL: propagation::Linear = propagation::Linear {
+    speed_km_s: DEFAULT_SPEED_KM_S,
+};
+
+/// A config probing `endpoints` 10 times every 10 s, the rest defaulted.
+pub fn sample_config(endpoints: Vec<Endpoint>) -> Config {
+    serde_json::from_value(serde_json::json!({
+        "secretHex": "00",
+        "endpoints": endpoints,
+        "samplesPerEndpoint": 10,
+        "spacingMs": 10,
+        "timeoutMs": 1000,
+        "intervalSeconds": 10,
+        "outputPath": "out.jsonl",
+        "claimedEgressRegion": null,
+        "physicsMismatchThresholdMs": 5.0,
+    }))
+    .unwrap()
+}
+
+pub fn endpoint(id: &str, lat: f64, lon: f64) -> Endpoint {
+    Endpoint {
+        id: id.to_string(),
+        host: "127.0.0.1".to_string(),
+        port: DEFAULT_PORT,
+        region_hint: None,
+        lat: Some(lat),
+        lon: Some(lon),
+        alt_m: None,
+        consented: true,
+        blackouts: Vec::new(),
+        transports: Vec::new(),
+        https_port: None,
+        https_ca_file: None,
+        packet_version: LATO_VERSION,
+    }
+}
+
+/// A UDP burst to `id` on the default path, with nothing but `samples`.
+pub fn record(id: &str, samples: Vec<f64>) -> BurstRecord {
+    BurstRecord {
+        endpoint_id: id.to_string(),
+        host: "127.0.0.1".to_string(),
+        port: DEFAULT_PORT,
+        probe_path: "default".to_string(),
+        samples_ms: samples,
+        iface: "other".to_string(),
+        ..BurstRecord::default()
+    }
+}
+
+/// Ten bursts of ten samples, every quantile at `p05`.
+pub fn stats_with_p05(id: &str, p05: f64) -> HashMap<String, EndpointStats> {
+    HashMap::from([(
+        id.to_string(),
+        EndpointStats {
+            count: 10,
+            min: Some(p05),
+            p05: Some(p05),
+            p50: Some(p05),
+            p95: Some(p05),
+            jitter_ms: Some(0.0),
+            bursts: 10,
+            ..EndpointStats::default()
+        },
+    )])
+}
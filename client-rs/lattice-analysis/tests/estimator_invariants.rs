@@ -0,0 +1,166 @@
+//! Invariants of the grid least-squares estimator, over random anchor
+//! layouts. A custom estimator built on `lattice_analysis` should pass the
+//! same ones.
+
+use lattice_analysis::constants::{
+    DEFAULT_PATH_STRETCH, DEFAULT_SPEED_KM_S, MS_PER_SEC, RTT_FACTOR,
+};
+use lattice_analysis::propagation::Linear;
+use lattice_analysis::{
+    fit_band, grid_search_bounds, sse_for_candidate, Bounds, DistanceModel, Observation,
+};
+use lattice_core::haversine_km;
+use proptest::prelude::*;
+
+const SPEED_KM_S: f64 = DEFAULT_SPEED_KM_S / DEFAULT_PATH_STRETCH;
+const MODEL: Linear = Linear {
+    speed_km_s: SPEED_KM_S,
+};
+const STEP_DEG: f64 = 0.5;
+const WINDOW_DEG: f64 = 20.0;
+const BAND_WINDOW_DEG: f64 = 3.0;
+/// Numerical slack on ms and ms² comparisons.
+const EPSILON: f64 = 1e-6;
+
+/// RTT the model predicts from `(lat, lon)` to an anchor, before any delay.
+fn model_rtt_ms(lat: f64, lon: f64, a_lat: f64, a_lon: f64) -> f64 {
+    RTT_FACTOR * haversine_km(lat, lon, a_lat, a_lon) / (SPEED_KM_S / MS_PER_SEC)
+}
+
+/// `n` anchors evenly spaced on a circle of `radius_deg` around `center`,
+/// starting at `rotation_deg`, each with the RTT the model predicts from
+/// the centre plus `delay_ms`.
+fn ring(
+    center: (f64, f64),
+    n: usize,
+    radius_deg: f64,
+    rotation_deg: f64,
+    delay_ms: f64,
+) -> Vec<Observation> {
+    (0..n)
+        .map(|i| {
+            let angle = (rotation_deg + 360.0 * i as f64 / n as f64).to_radians();
+            let lat = center.0 + radius_deg * angle.sin();
+            let lon = center.1 + radius_deg * angle.cos();
+            Observation {
+                lat,
+                lon,
+                alt_km: 0.0,
+                rtt_ms: model_rtt_ms(center.0, center.1, lat, lon) + delay_ms,
+                weight: 1.0,
+                region: None,
+            }
+        })
+        .collect()
+}
+
+fn around(center: (f64, f64)) -> Bounds {
+    Bounds {
+        lat_min: center.0 - WINDOW_DEG,
+        lat_max: center.0 + WINDOW_DEG,
+        lon_min: center.1 - WINDOW_DEG,
+        lon_max: center.1 + WINDOW_DEG,
+    }
+}
+
+/// Whether `p` is inside (or on) the convex polygon `ring`, whose vertices
+/// run counter-clockwise in (lon, lat).
+fn in_convex_hull(p: (f64, f64), ring: &[Observation], slack_deg: f64) -> bool {
+    ring.iter().zip(ring.iter().cycle().skip(1)).all(|(a, b)| {
+        let cross = (b.lon - a.lon) * (p.0 - a.lat) - (b.lat - a.lat) * (p.1 - a.lon);
+        let edge = ((b.lon - a.lon).powi(2) + (b.lat - a.lat).powi(2)).sqrt();
+        cross >= -slack_deg * edge
+    })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(48))]
+
+    /// Anchors surrounding the host at equal RTTs put the estimate inside
+    /// their hull, one grid step of slack aside.
+    #[test]
+    fn symmetric_anchors_keep_the_estimate_inside_their_hull(
+        lat in -45.0f64..45.0,
+        lon in -150.0f64..150.0,
+        n in 3usize..8,
+        radius_deg in 3.0f64..10.0,
+        rotation_deg in 0.0f64..360.0,
+        delay_ms in 0.0f64..20.0,
+    ) {
+        let obs = ring((lat, lon), n, radius_deg, rotation_deg, delay_ms);
+        let fit = grid_search_bounds(&obs, &MODEL, around((lat, lon)), STEP_DEG, DistanceModel::Sphere).unwrap();
+        prop_assert!(in_convex_hull((fit.lat, fit.lon), &obs, STEP_DEG), "fit {:?} outside {:?}", fit, obs);
+        prop_assert!(fit.bias_ms >= 0.0);
+    }
+
+    /// Adding the same delay to every RTT is absorbed by the common delay:
+    /// the SSE landscape and the best candidate stay put, and the fitted
+    /// delay grows by exactly that much.
+    #[test]
+    fn uniform_rtt_inflation_only_moves_the_common_delay(
+        lat in -45.0f64..45.0,
+        lon in -150.0f64..150.0,
+        n in 3usize..8,
+        radius_deg in 3.0f64..10.0,
+        rotation_deg in 0.0f64..360.0,
+        delay_ms in 0.0f64..20.0,
+        extra_ms in 0.0f64..50.0,
+        probe_lat in -5.0f64..5.0,
+        probe_lon in -5.0f64..5.0,
+    ) {
+        let obs = ring((lat, lon), n, radius_deg, rotation_deg, delay_ms);
+        let inflated: Vec<Observation> = obs.iter().map(|o| Observation { rtt_ms: o.rtt_ms + extra_ms, ..o.clone() }).collect();
+
+        let (p_lat, p_lon) = (lat + probe_lat, lon + probe_lon);
+        let before = sse_for_candidate(p_lat, p_lon, &obs, &MODEL, DistanceModel::Sphere);
+        let after = sse_for_candidate(p_lat, p_lon, &inflated, &MODEL, DistanceModel::Sphere);
+        prop_assert!(after.bias_ms >= before.bias_ms);
+        if before.bias_ms > 0.0 {
+            prop_assert!((after.bias_ms - before.bias_ms - extra_ms).abs() < EPSILON);
+            prop_assert!((after.sse - before.sse).abs() < EPSILON * (1.0 + before.sse));
+        }
+
+        let best = grid_search_bounds(&obs, &MODEL, around((lat, lon)), STEP_DEG, DistanceModel::Sphere).unwrap();
+        let best_inflated =
+            grid_search_bounds(&inflated, &MODEL, around((lat, lon)), STEP_DEG, DistanceModel::Sphere).unwrap();
+        // Exact ties may break either way in floating point, so compare
+        // the inflated winner on the original data rather than its grid cell.
+        let same_cell = sse_for_candidate(best_inflated.lat, best_inflated.lon, &obs, &MODEL, DistanceModel::Sphere);
+        prop_assert!((same_cell.sse - best.sse).abs() < EPSILON * (1.0 + best.sse));
+        prop_assert!((best_inflated.bias_ms - same_cell.bias_ms - extra_ms).abs() < EPSILON);
+    }
+
+    /// A looser band factor or a larger per-anchor margin never shrinks
+    /// the band, and the band always holds the fit it was drawn around.
+    #[test]
+    fn fit_band_grows_with_factor_and_margin(
+        lat in -45.0f64..45.0,
+        lon in -150.0f64..150.0,
+        n in 3usize..8,
+        radius_deg in 3.0f64..10.0,
+        rotation_deg in 0.0f64..360.0,
+        noise_ms in prop::collection::vec(0.0f64..5.0, 8),
+        factor in 0.0f64..1.0,
+        extra_factor in 0.0f64..1.0,
+        margin_ms in 0.0f64..5.0,
+    ) {
+        let obs: Vec<Observation> = ring((lat, lon), n, radius_deg, rotation_deg, 0.0)
+            .into_iter()
+            .zip(&noise_ms)
+            .map(|(o, noise)| Observation { rtt_ms: o.rtt_ms + noise, ..o })
+            .collect();
+        let best = grid_search_bounds(&obs, &MODEL, around((lat, lon)), STEP_DEG, DistanceModel::Sphere).unwrap();
+        let band = |factor: f64, margin_ms: f64| {
+            fit_band(&obs, &MODEL, &best, STEP_DEG, factor, BAND_WINDOW_DEG, margin_ms, DistanceModel::Sphere).unwrap()
+        };
+        let tight = band(factor, 0.0);
+        prop_assert!(tight.points >= 1);
+        prop_assert!(tight.min_lat <= best.lat && best.lat <= tight.max_lat);
+        prop_assert!(tight.min_lon <= best.lon && best.lon <= tight.max_lon);
+        for loose in [band(factor + extra_factor, 0.0), band(factor, margin_ms)] {
+            prop_assert!(loose.sse_threshold >= tight.sse_threshold);
+            prop_assert!(loose.points >= tight.points);
+            prop_assert!(loose.radius_km >= tight.radius_km);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use crate::constants::*;
+use crate::propagation::{Leg, Linear};
+use crate::{load_jsonl, max_distance_km, quantile, record_samples};
+use clap::Parser;
+use lattice_core::{utc_minute, BurstRecord, Config};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const AGGREGATE_SCHEMA: &str = "lattice-aggregate/1";
+const HOURS_PER_DAY: usize = 24;
+const MS_PER_MIN: i64 = 60_000;
+/// Anchors are published at ~10 km precision; they are public reflectors, but
+/// there is no reason to ship more precision than the bounds can use.
+const ANCHOR_COORDINATE_DECIMALS: i32 = 1;
+/// Hour buckets with fewer samples than this are left out rather than
+/// published as a noisy p05.
+const MIN_HOUR_SAMPLES: usize = 10;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lattice-analyze aggregate",
+    about = "Export per-anchor aggregates with no raw samples, host identifiers, or exact timestamps"
+)]
+pub struct AggregateArgs {
+    #[arg(long)]
+    config: PathBuf,
+
+    #[arg(long)]
+    session: PathBuf,
+
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    #[arg(long, default_value_t = DEFAULT_SPEED_KM_S)]
+    speed_km_s: f64,
+
+    #[arg(long, default_value_t = DEFAULT_PATH_STRETCH)]
+    path_stretch: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregateExport {
+    pub schema: &'static str,
+    pub samples_per_burst: usize,
+    pub speed_km_s: f64,
+    pub path_stretch: f64,
+    pub anchors: Vec<AnchorAggregate>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnchorAggregate {
+    pub anchor: String,
+    pub path: Option<String>,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    pub bursts: usize,
+    pub samples: usize,
+    /// Fraction of probes with no reply; `None` when the session kept no
+    /// per-probe data (`recordRawSamples: false` without histograms).
+    pub loss: Option<f64>,
+    pub p05_ms: Option<f64>,
+    pub max_distance_km: Option<f64>,
+    /// UTC hour of day -> p05 over every burst in that hour.
+    pub p05_ms_by_hour: BTreeMap<usize, f64>,
+}
+
+pub fn run(args: AggregateArgs) -> io::Result<()> {
+    let cfg = Config::load(&args.config)?;
+    let records = load_jsonl(&args.session)?;
+    let path_stretch = args.path_stretch.max(MIN_PATH_STRETCH);
+    let export = build(&cfg, &records, args.speed_km_s, path_stretch);
+    let text = serde_json::to_string_pretty(&export).map_err(io::Error::other)?;
+    match &args.out {
+        Some(path) => {
+            fs::write(path, text)?;
+            eprintln!(
+                "wrote {} anchor aggregate(s) to {}",
+                export.anchors.len(),
+                path.display()
+            );
+        }
+        None => println!("{text}"),
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Accum {
+    bursts: usize,
+    probes_known: usize,
+    replies_known: usize,
+    samples: Vec<f64>,
+    by_hour: Vec<Vec<f64>>,
+}
+
+pub fn build(
+    cfg: &Config,
+    records: &[BurstRecord],
+    speed_km_s: f64,
+    path_stretch: f64,
+) -> AggregateExport {
+    let mut accums: HashMap<&str, Accum> = HashMap::new();
+    for rec in records {
+        let acc = accums.entry(rec.endpoint_id.as_str()).or_default();
+        if acc.by_hour.is_empty() {
+            acc.by_hour = vec![Vec::new(); HOURS_PER_DAY];
+        }
+        acc.bursts += 1;
+        let replies = if !rec.samples_ms.is_empty() {
+            Some(rec.samples_ms.len())
+        } else {
+            rec.histogram.as_ref().map(|h| h.count())
+        };
+        if let Some(n) = replies {
+            acc.probes_known += cfg.samples_per_endpoint.max(n);
+            acc.replies_known += n;
+        }
+        let hour = utc_minute(rec.ts_unix_ms.div_euclid(MS_PER_MIN)).hour as usize;
+        let valid = record_samples(rec)
+            .into_iter()
+            .filter(|v| v.is_finite() && *v >= 0.0);
+        for v in valid {
+            acc.samples.push(v);
+            acc.by_hour[hour].push(v);
+        }
+    }
+
+    let mut ids: Vec<&str> = accums.keys().copied().collect();
+    ids.sort();
+    let effective_speed = speed_km_s / path_stretch;
+    let scale = 10f64.powi(ANCHOR_COORDINATE_DECIMALS);
+    let round = |x: f64| (x * scale).round() / scale;
+
+    let mut anchors = Vec::new();
+    for (idx, id) in ids.iter().enumerate() {
+        let mut acc = accums.remove(id).unwrap_or_default();
+        let (base_id, path) = match id.split_once('@') {
+            Some((base, path)) => (base, Some(path.to_string())),
+            None => (*id, None),
+        };
+        let ep = cfg.endpoints.iter().find(|e| e.id == base_id);
+        let anchor = ep
+            .and_then(|e| e.region_hint.clone())
+            .unwrap_or_else(|| format!("anchor-{}", idx + 1));
+
+        acc.samples.sort_by(|a, b| a.total_cmp(b));
+        let p05 = quantile(&acc.samples, 0.05);
+        let mut p05_by_hour = BTreeMap::new();
+        for (hour, vals) in acc.by_hour.iter_mut().enumerate() {
+            if vals.len() < MIN_HOUR_SAMPLES {
+                continue;
+            }
+            vals.sort_by(|a, b| a.total_cmp(b));
+            if let Some(v) = quantile(vals, 0.05) {
+                p05_by_hour.insert(hour, v);
+            }
+        }
+        let loss = if acc.probes_known > 0 {
+            Some(1.0 - acc.replies_known as f64 / acc.probes_known as f64)
+        } else {
+            None
+        };
+        anchors.push(AnchorAggregate {
+            anchor,
+            path,
+            lat: ep.and_then(|e| e.lat).map(round),
+            lon: ep.and_then(|e| e.lon).map(round),
+            bursts: acc.bursts,
+            samples: acc.samples.len(),
+            loss,
+            p05_ms: p05,
+            max_distance_km: p05.and_then(|v| {
+                max_distance_km(
+                    v,
+                    &Linear {
+                        speed_km_s: effective_speed,
+                    },
+                    Leg::default(),
+                )
+            }),
+            p05_ms_by_hour: p05_by_hour,
+        });
+    }
+
+    AggregateExport {
+        schema: AGGREGATE_SCHEMA,
+        samples_per_burst: cfg.samples_per_endpoint,
+        speed_km_s,
+        path_stretch,
+        anchors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn aggregate_export_buckets_by_hour_and_counts_loss() {
+        let cfg = sample_config(vec![endpoint("a", 40.0, -74.0)]);
+        let hour_ms = 3_600_000;
+        let mut records = Vec::new();
+        for i in 0..4 {
+            let mut rec = record("a", vec![10.0 + i as f64; 5]);
+            rec.ts_unix_ms = (i % 2) * hour_ms;
+            records.push(rec);
+        }
+        let export = build(&cfg, &records, DEFAULT_SPEED_KM_S, DEFAULT_PATH_STRETCH);
+        let a = &export.anchors[0];
+        assert_eq!(a.bursts, 4);
+        assert_eq!(a.samples, 20);
+        assert_eq!(
+            a.p05_ms_by_hour.keys().copied().collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+        let expected_loss = 1.0 - 5.0 / cfg.samples_per_endpoint.max(5) as f64;
+        assert!((a.loss.unwrap() - expected_loss).abs() < TEST_EPSILON);
+        let text = serde_json::to_string(&export).unwrap();
+        assert!(!text.contains("127.0.0.1"));
+    }
+}
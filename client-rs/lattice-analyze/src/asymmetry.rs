@@ -0,0 +1,280 @@
+use crate::units::ReportFormat;
+use crate::{quantile, EndpointStats};
+use lattice_core::BurstRecord;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Anchors with legs needed before the client's clock offset can be told
+/// apart from one anchor's asymmetric path.
+const MIN_OFFSET_ANCHORS: usize = 3;
+/// Asymmetry the text report calls out; below it, the two minima are within
+/// what one queueing spike or NTP slew moves them.
+const NOTABLE_ASYMMETRY_MS: f64 = 2.0;
+
+/// One anchor's forward and reverse legs, from the v2 samples that carried
+/// the reflector's stamps.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AnchorAsymmetry {
+    pub endpoint: String,
+    pub samples: usize,
+    /// Fastest forward and reverse legs, each on its own: queueing only adds
+    /// delay, so the minima are the legs' propagation plus the clock offset.
+    pub min_forward_ms: f64,
+    pub min_reverse_ms: f64,
+    /// Forward minus reverse leg once the client's clock offset is taken
+    /// out; null with too few anchors to estimate the offset.
+    pub asymmetry_ms: Option<f64>,
+}
+
+/// Forward/reverse asymmetry across a capture's anchors.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AsymmetryReport {
+    /// The client's clock minus the reflectors', from the median anchor: the
+    /// reflectors keep UTC and most paths are symmetric, so what every
+    /// anchor shares is the client's offset. Null with fewer than 3 anchors.
+    pub clock_offset_ms: Option<f64>,
+    pub anchors: Vec<AnchorAsymmetry>,
+    /// Anchors whose legs were all left out: their reflector did not report
+    /// an NTP-synced clock, so its stamps say nothing about either leg.
+    pub unsynced: Vec<String>,
+}
+
+/// Per-anchor legs of `records`, keyed like the stats. Only legs stamped by
+/// a synced reflector count, each corrected by the offset it reported.
+/// `None` when no record carries `sampleLegs`.
+pub(crate) fn asymmetry(records: &[BurstRecord]) -> Option<AsymmetryReport> {
+    // Per endpoint: samples, fastest forward leg, fastest reverse leg.
+    let mut legs: BTreeMap<&str, (usize, f64, f64)> = BTreeMap::new();
+    let mut unsynced: BTreeSet<&str> = BTreeSet::new();
+    for rec in records {
+        if rec.sample_legs.iter().all(Option::is_none) {
+            continue;
+        }
+        let offset_ms = match rec.reflector_clock {
+            Some(clock) if clock.synced() => clock.offset_ms.unwrap_or(0.0),
+            _ => {
+                unsynced.insert(rec.endpoint_id.as_str());
+                continue;
+            }
+        };
+        for leg in rec.sample_legs.iter().flatten() {
+            if !leg.forward_ms.is_finite() || !leg.reverse_ms.is_finite() {
+                continue;
+            }
+            let entry =
+                legs.entry(rec.endpoint_id.as_str())
+                    .or_insert((0, f64::INFINITY, f64::INFINITY));
+            entry.0 += 1;
+            // The reflector's stamps run `offset_ms` ahead of UTC.
+            entry.1 = entry.1.min(leg.forward_ms - offset_ms);
+            entry.2 = entry.2.min(leg.reverse_ms + offset_ms);
+        }
+    }
+    unsynced.retain(|id| !legs.contains_key(id));
+    if legs.is_empty() && unsynced.is_empty() {
+        return None;
+    }
+    // Forward minus reverse carries twice the offset with the opposite
+    // sign: the forward leg is read on the reflector's clock less ours.
+    let mut skews: Vec<f64> = legs.values().map(|&(_, fwd, rev)| fwd - rev).collect();
+    skews.sort_by(f64::total_cmp);
+    let shared_skew = quantile(&skews, 0.5).filter(|_| skews.len() >= MIN_OFFSET_ANCHORS);
+    let anchors = legs
+        .into_iter()
+        .map(|(id, (samples, fwd, rev))| AnchorAsymmetry {
+            endpoint: id.to_string(),
+            samples,
+            min_forward_ms: fwd,
+            min_reverse_ms: rev,
+            asymmetry_ms: shared_skew.map(|shared| fwd - rev - shared),
+        })
+        .collect();
+    Some(AsymmetryReport {
+        clock_offset_ms: shared_skew.map(|shared| -shared / 2.0),
+        anchors,
+        unsynced: unsynced.into_iter().map(str::to_string).collect(),
+    })
+}
+
+/// Widens each measured anchor's tolerance in the fit by its asymmetry.
+pub(crate) fn widen(stats: &mut HashMap<String, EndpointStats>, report: &AsymmetryReport) {
+    for anchor in &report.anchors {
+        if let (Some(st), Some(asymmetry)) = (stats.get_mut(&anchor.endpoint), anchor.asymmetry_ms)
+        {
+            st.asymmetry_ms = asymmetry;
+        }
+    }
+}
+
+pub(crate) fn print_asymmetry(label: &str, report: &AsymmetryReport, fmt: &ReportFormat) {
+    println!(
+        "\n{label} path asymmetry (reflector-split legs, ms): clock_offset={}",
+        fmt.ms_opt(report.clock_offset_ms)
+    );
+    for a in &report.anchors {
+        let flag = match a.asymmetry_ms {
+            Some(v) if v.abs() >= NOTABLE_ASYMMETRY_MS => " [!] asymmetric, fit tolerance widened",
+            _ => "",
+        };
+        println!(
+            "- {} fwd_min={} rev_min={} asymmetry={} n={}{}",
+            a.endpoint,
+            fmt.ms(a.min_forward_ms),
+            fmt.ms(a.min_reverse_ms),
+            fmt.ms_opt(a.asymmetry_ms),
+            a.samples,
+            flag
+        );
+    }
+    if !report.unsynced.is_empty() {
+        println!(
+            "  [!] legs left out, reflector clock not NTP-synced: {}",
+            report.unsynced.join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+    use crate::{build_stats, Weighting};
+    use lattice_core::{ReflectorClock, SampleLegs};
+
+    /// A v2 burst whose legs are `true_legs` read with the client's clock
+    /// `client_offset_ms` ahead of the reflector's, plus `queued_ms` of
+    /// queueing on the second sample's forward leg, stamped by a synced
+    /// reflector.
+    fn legs_record(id: &str, true_legs: (f64, f64), client_offset_ms: f64) -> BurstRecord {
+        let (fwd, rev) = true_legs;
+        let queued_ms = 7.0;
+        let legs = [
+            SampleLegs {
+                hold_ms: 0.1,
+                forward_ms: fwd - client_offset_ms,
+                reverse_ms: rev + client_offset_ms,
+            },
+            SampleLegs {
+                hold_ms: 0.1,
+                forward_ms: fwd + queued_ms - client_offset_ms,
+                reverse_ms: rev + client_offset_ms,
+            },
+        ];
+        let mut rec = record(id, vec![fwd + rev, fwd + rev + queued_ms]);
+        rec.sample_legs = legs.into_iter().map(Some).chain([None]).collect();
+        rec.samples_ms.push(fwd + rev);
+        rec.reflector_clock = Some(ReflectorClock {
+            offset_ms: Some(0.0),
+            stratum: Some(2),
+        });
+        rec
+    }
+
+    #[test]
+    fn asymmetry_separates_one_asymmetric_anchor_from_the_clock_offset() {
+        let offset = 5.0;
+        let records = vec![
+            legs_record("a", (10.0, 10.0), offset),
+            legs_record("b", (20.0, 20.0), offset),
+            legs_record("c", (15.0, 15.0), offset),
+            // A satellite uplink: 20 ms more on the way out.
+            legs_record("sat", (30.0, 10.0), offset),
+            record("v1", vec![12.0]),
+        ];
+        let report = asymmetry(&records).unwrap();
+        assert!((report.clock_offset_ms.unwrap() - offset).abs() < TEST_EPSILON);
+        let by_id: HashMap<&str, &AnchorAsymmetry> = report
+            .anchors
+            .iter()
+            .map(|a| (a.endpoint.as_str(), a))
+            .collect();
+        assert!(!by_id.contains_key("v1"));
+        assert_eq!(by_id["sat"].samples, 2);
+        assert!((by_id["sat"].min_forward_ms - 25.0).abs() < TEST_EPSILON);
+        assert!((by_id["sat"].asymmetry_ms.unwrap() - 20.0).abs() < TEST_EPSILON);
+        for id in ["a", "b", "c"] {
+            assert!(by_id[id].asymmetry_ms.unwrap().abs() < TEST_EPSILON, "{id}");
+        }
+
+        let mut stats = build_stats(&records, None);
+        widen(&mut stats, &report);
+        assert!((stats["sat"].asymmetry_ms - 20.0).abs() < TEST_EPSILON);
+        assert_eq!(stats["v1"].asymmetry_ms, 0.0);
+        // Same jitter, so the widened anchor counts for less in the fit.
+        let weighting = Weighting::default();
+        assert!(weighting.weight(&stats["sat"], 0) < weighting.weight(&stats["a"], 0) / 3.0);
+    }
+
+    #[test]
+    fn asymmetry_needs_three_anchors_to_estimate_the_offset() {
+        let records = vec![
+            legs_record("a", (10.0, 10.0), 5.0),
+            legs_record("sat", (30.0, 10.0), 5.0),
+        ];
+        let report = asymmetry(&records).unwrap();
+        assert_eq!(report.clock_offset_ms, None);
+        assert!(report.anchors.iter().all(|a| a.asymmetry_ms.is_none()));
+        let mut stats = build_stats(&records, None);
+        widen(&mut stats, &report);
+        assert_eq!(stats["sat"].asymmetry_ms, 0.0);
+
+        assert!(asymmetry(&[record("v1", vec![12.0])]).is_none());
+    }
+
+    #[test]
+    fn asymmetry_uses_only_legs_from_synced_reflectors() {
+        let offset = 5.0;
+        // Stamped 3 ms ahead of UTC, and saying so.
+        let mut drifting = legs_record("drift", (12.0, 12.0), offset);
+        for leg in drifting.sample_legs.iter_mut().flatten() {
+            leg.forward_ms += 3.0;
+            leg.reverse_ms -= 3.0;
+        }
+        drifting.reflector_clock = Some(ReflectorClock {
+            offset_ms: Some(3.0),
+            stratum: None,
+        });
+        let mut sat = legs_record("sat", (30.0, 10.0), offset);
+        let mut records = vec![
+            legs_record("a", (10.0, 10.0), offset),
+            legs_record("b", (20.0, 20.0), offset),
+            legs_record("c", (15.0, 15.0), offset),
+            drifting,
+            sat.clone(),
+        ];
+        let report = asymmetry(&records).unwrap();
+        let drift = report
+            .anchors
+            .iter()
+            .find(|a| a.endpoint == "drift")
+            .unwrap();
+        assert!(drift.asymmetry_ms.unwrap().abs() < TEST_EPSILON);
+        assert!(report.unsynced.is_empty());
+
+        // The same asymmetric anchor behind a reflector that is unsynced, or
+        // that cannot say, is no evidence either way.
+        for clock in [
+            Some(ReflectorClock {
+                offset_ms: None,
+                stratum: Some(16),
+            }),
+            Some(ReflectorClock {
+                offset_ms: None,
+                stratum: Some(3),
+            }),
+            None,
+        ] {
+            sat.reflector_clock = clock;
+            *records.last_mut().unwrap() = sat.clone();
+            let report = asymmetry(&records).unwrap();
+            assert_eq!(report.unsynced, ["sat"]);
+            assert!(report.anchors.iter().all(|a| a.endpoint != "sat"));
+            assert!((report.clock_offset_ms.unwrap() - offset).abs() < TEST_EPSILON);
+            let mut stats = build_stats(&records, None);
+            widen(&mut stats, &report);
+            assert_eq!(stats["sat"].asymmetry_ms, 0.0);
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use crate::load_jsonl;
+use clap::Parser;
+use lattice_core::{BurstRecord, Config};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+
+/// Firmware version of the result format emitted; parsers such as Sagan pick
+/// the field layout from it.
+const ATLAS_FIRMWARE: u32 = 5020;
+/// LATO probes are a fixed 32-byte UDP payload.
+const LATO_PAYLOAD_BYTES: u32 = 32;
+/// Atlas writes -1 for min/avg/max when no reply came back.
+const ATLAS_NO_RTT: f64 = -1.0;
+const MS_PER_SEC: i64 = 1000;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lattice-analyze atlas",
+    about = "Convert bursts to RIPE Atlas ping results for existing Atlas tooling"
+)]
+pub struct AtlasArgs {
+    #[arg(long)]
+    config: PathBuf,
+
+    #[arg(long)]
+    session: PathBuf,
+
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    /// Measurement id for the first endpoint; each endpoint id (and path) gets the next one.
+    #[arg(long, default_value_t = 0)]
+    msm_id: u64,
+
+    /// Probe id to report as the source of every result.
+    #[arg(long, default_value_t = 0)]
+    prb_id: u64,
+
+    /// One result object per line instead of a JSON array.
+    #[arg(long)]
+    jsonl: bool,
+}
+
+/// One entry of `result`: a reply or a lost probe.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum AtlasReply {
+    Rtt { rtt: f64 },
+    Lost { x: &'static str },
+}
+
+/// The RIPE Atlas ping result shape, one per burst.
+#[derive(Debug, Serialize)]
+pub struct AtlasPing {
+    pub af: u8,
+    pub avg: f64,
+    pub dst_addr: String,
+    pub dst_name: String,
+    pub dup: u32,
+    pub from: String,
+    pub fw: u32,
+    pub group_id: u64,
+    pub lts: i64,
+    pub max: f64,
+    pub min: f64,
+    pub msm_id: u64,
+    pub msm_name: &'static str,
+    pub prb_id: u64,
+    pub proto: &'static str,
+    pub rcvd: usize,
+    pub result: Vec<AtlasReply>,
+    pub sent: usize,
+    pub size: u32,
+    pub src_addr: String,
+    pub step: u64,
+    pub timestamp: i64,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+}
+
+fn ip_of(addr: &str) -> Option<IpAddr> {
+    addr.parse::<SocketAddr>()
+        .map(|a| a.ip())
+        .or_else(|_| addr.parse::<IpAddr>())
+        .ok()
+}
+
+/// Converts records to Atlas pings. Bursts with neither raw samples nor a
+/// histogram carry no per-probe data and are skipped; the count is returned.
+pub(crate) fn build(
+    cfg: &Config,
+    records: &[BurstRecord],
+    msm_id: u64,
+    prb_id: u64,
+) -> (Vec<AtlasPing>, usize) {
+    let ids: BTreeSet<&str> = records.iter().map(|r| r.endpoint_id.as_str()).collect();
+    let msm_for = |id: &str| msm_id + ids.iter().position(|x| *x == id).unwrap_or(0) as u64;
+    let mut pings = Vec::new();
+    let mut skipped = 0;
+    for rec in records {
+        let rtts: Vec<f64> = if !rec.samples_ms.is_empty() {
+            rec.samples_ms.clone()
+        } else if let Some(hist) = &rec.histogram {
+            hist.representative_samples()
+        } else {
+            skipped += 1;
+            continue;
+        };
+        let rtts: Vec<f64> = rtts
+            .into_iter()
+            .filter(|v| v.is_finite() && *v >= 0.0)
+            .collect();
+        let sent = cfg.samples_per_endpoint.max(rtts.len());
+        let dst = ip_of(&rec.remote_addr);
+        let src = ip_of(&rec.local_addr);
+        let (min, avg, max) = if rtts.is_empty() {
+            (ATLAS_NO_RTT, ATLAS_NO_RTT, ATLAS_NO_RTT)
+        } else {
+            (
+                rtts.iter().copied().fold(f64::INFINITY, f64::min),
+                rtts.iter().sum::<f64>() / rtts.len() as f64,
+                rtts.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            )
+        };
+        let mut result: Vec<AtlasReply> = rtts.iter().map(|&rtt| AtlasReply::Rtt { rtt }).collect();
+        result.extend((rtts.len()..sent).map(|_| AtlasReply::Lost { x: "*" }));
+        let msm = msm_for(&rec.endpoint_id);
+        pings.push(AtlasPing {
+            af: match dst.or(src) {
+                Some(IpAddr::V6(_)) => 6,
+                _ => 4,
+            },
+            avg,
+            dst_addr: dst.map(|ip| ip.to_string()).unwrap_or_default(),
+            dst_name: rec.host.clone(),
+            dup: 0,
+            from: src.map(|ip| ip.to_string()).unwrap_or_default(),
+            fw: ATLAS_FIRMWARE,
+            group_id: msm,
+            lts: -1,
+            max,
+            min,
+            msm_id: msm,
+            msm_name: "Ping",
+            prb_id,
+            proto: "UDP",
+            rcvd: rtts.len(),
+            result,
+            sent,
+            size: LATO_PAYLOAD_BYTES,
+            src_addr: src.map(|ip| ip.to_string()).unwrap_or_default(),
+            step: cfg.interval_seconds,
+            timestamp: rec.ts_unix_ms.div_euclid(MS_PER_SEC),
+            kind: "ping",
+        });
+    }
+    (pings, skipped)
+}
+
+pub fn run(args: AtlasArgs) -> io::Result<()> {
+    let cfg = Config::load(&args.config)?;
+    let records = load_jsonl(&args.session)?;
+    let (pings, skipped) = build(&cfg, &records, args.msm_id, args.prb_id);
+    if skipped > 0 {
+        eprintln!("[!] skipped {} burst(s) with no per-probe samples", skipped);
+    }
+    let text = if args.jsonl {
+        let mut lines = Vec::new();
+        for ping in &pings {
+            lines.push(serde_json::to_string(ping).map_err(io::Error::other)?);
+        }
+        lines.join("\n") + "\n"
+    } else {
+        serde_json::to_string_pretty(&pings).map_err(io::Error::other)?
+    };
+    match &args.out {
+        Some(path) => {
+            fs::write(path, text)?;
+            eprintln!(
+                "wrote {} Atlas ping result(s) to {}",
+                pings.len(),
+                path.display()
+            );
+        }
+        None => print!("{text}"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn atlas_export_pads_lost_probes_and_numbers_measurements() {
+        let cfg = sample_config(Vec::new());
+        let mut a = record("a", vec![12.0, 10.0, 14.0]);
+        a.ts_unix_ms = 1_700_000_000_999;
+        a.remote_addr = "[2001:db8::1]:7777".to_string();
+        a.local_addr = "[2001:db8::2]:50000".to_string();
+        let b = record("b", Vec::new());
+        let (pings, skipped) = build(&cfg, &[a, b], 100, 7);
+        assert_eq!(skipped, 1);
+        assert_eq!(pings.len(), 1);
+        let json = serde_json::to_value(&pings[0]).unwrap();
+        assert_eq!(json["type"], "ping");
+        assert_eq!(json["af"], 6);
+        assert_eq!(json["dst_addr"], "2001:db8::1");
+        assert_eq!(json["src_addr"], "2001:db8::2");
+        assert_eq!(json["msm_id"], 100);
+        assert_eq!(json["prb_id"], 7);
+        assert_eq!(json["timestamp"], 1_700_000_000i64);
+        assert_eq!(json["sent"], 10);
+        assert_eq!(json["rcvd"], 3);
+        assert_eq!(json["min"], 10.0);
+        assert_eq!(json["avg"], 12.0);
+        assert_eq!(json["max"], 14.0);
+        let result = json["result"].as_array().unwrap();
+        assert_eq!(result.len(), 10);
+        assert_eq!(result[0]["rtt"], 12.0);
+        assert_eq!(result[9]["x"], "*");
+    }
+}
@@ -0,0 +1,144 @@
+use crate::quantile;
+use crate::units::ReportFormat;
+use lattice_core::{bloat_grade, BloatSource, BurstRecord};
+use serde::Serialize;
+
+/// Added latency under load from which the access link counts as heavily
+/// bloated (grade C and worse) and the fit band is widened by it.
+const HEAVY_BLOAT_MS: f64 = 30.0;
+
+/// The access link's bufferbloat over a capture, from every burst that
+/// recorded one. The queue sits in front of all anchors alike, so this is a
+/// per-capture figure, not a per-endpoint one.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BufferbloatSummary {
+    pub bursts: usize,
+    /// Bursts measured under the client's own `bufferbloatLoad`; the rest ran
+    /// under other traffic on the interface.
+    pub load_bursts: usize,
+    pub bloat_p50_ms: Option<f64>,
+    pub bloat_max_ms: Option<f64>,
+    pub grade: String,
+    /// Per-anchor RTT misfit the estimate's fit band tolerates on top of
+    /// `--band-factor`: `bloatP50Ms` once it reaches `HEAVY_BLOAT_MS`, else 0.
+    pub margin_ms: f64,
+}
+
+pub(crate) fn summarize(records: &[BurstRecord]) -> Option<BufferbloatSummary> {
+    let measured: Vec<_> = records
+        .iter()
+        .filter_map(|r| r.bufferbloat.as_ref())
+        .collect();
+    if measured.is_empty() {
+        return None;
+    }
+    let mut bloat: Vec<f64> = measured
+        .iter()
+        .map(|b| b.bloat_ms)
+        .filter(|v| v.is_finite())
+        .collect();
+    bloat.sort_by(f64::total_cmp);
+    let p50 = quantile(&bloat, 0.5);
+    Some(BufferbloatSummary {
+        bursts: measured.len(),
+        load_bursts: measured
+            .iter()
+            .filter(|b| b.source == BloatSource::Load)
+            .count(),
+        bloat_p50_ms: p50,
+        bloat_max_ms: bloat.last().copied(),
+        grade: bloat_grade(p50.unwrap_or(0.0)).to_string(),
+        margin_ms: p50.filter(|v| *v >= HEAVY_BLOAT_MS).unwrap_or(0.0),
+    })
+}
+
+pub(crate) fn print_bufferbloat(label: &str, summary: &BufferbloatSummary, fmt: &ReportFormat) {
+    println!(
+        "\n{label} bufferbloat: grade {} (added latency under load p50={} max={}ms; {} burst(s), {} under own load)",
+        summary.grade,
+        fmt.ms_opt(summary.bloat_p50_ms),
+        fmt.ms_opt(summary.bloat_max_ms),
+        summary.bursts,
+        summary.load_bursts
+    );
+    if summary.margin_ms > 0.0 {
+        println!(
+            "  [!] heavily bloated access link: fit band allows ±{}ms per anchor",
+            fmt.ms(summary.margin_ms)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::DEFAULT_BAND_FACTOR;
+    use crate::testutil::*;
+    use crate::DistanceModel;
+    use lattice_analysis::{fit_band, grid_search_bounds, Bounds, Observation};
+    use lattice_core::Bufferbloat;
+
+    #[test]
+    fn heavy_bufferbloat_widens_the_fit_band() {
+        let bloated = |source, loaded_ms| BurstRecord {
+            bufferbloat: Some(Bufferbloat::new(source, 10.0, loaded_ms)),
+            ..record("a", vec![10.0])
+        };
+        let records = vec![
+            bloated(BloatSource::Load, 90.0),
+            bloated(BloatSource::Traffic, 50.0),
+            bloated(BloatSource::Load, 70.0),
+            record("b", vec![10.0]),
+        ];
+        let summary = summarize(&records).unwrap();
+        assert_eq!((summary.bursts, summary.load_bursts), (3, 2));
+        assert_eq!(summary.bloat_p50_ms, Some(60.0));
+        assert_eq!(summary.bloat_max_ms, Some(80.0));
+        assert_eq!(summary.grade, "D");
+        assert_eq!(summary.margin_ms, 60.0);
+        assert!(summarize(&[record("a", vec![10.0])]).is_none());
+
+        let obs: Vec<Observation> = [(0.0, 0.0), (0.0, 5.0), (5.0, 0.0)]
+            .iter()
+            .map(|&(lat, lon)| Observation {
+                lat,
+                lon,
+                alt_km: 0.0,
+                rtt_ms: 20.0,
+                weight: 1.0,
+                region: None,
+            })
+            .collect();
+        let bounds = Bounds {
+            lat_min: -5.0,
+            lat_max: 10.0,
+            lon_min: -5.0,
+            lon_max: 10.0,
+        };
+        let best = grid_search_bounds(
+            &obs,
+            &TEST_MODEL,
+            bounds,
+            TEST_REFINE_DEG,
+            DistanceModel::Sphere,
+        )
+        .unwrap();
+        let band = |margin_ms| {
+            fit_band(
+                &obs,
+                &TEST_MODEL,
+                &best,
+                TEST_REFINE_DEG,
+                DEFAULT_BAND_FACTOR,
+                TEST_GRID_DEG,
+                margin_ms,
+                DistanceModel::Sphere,
+            )
+            .unwrap()
+        };
+        let (tight, wide) = (band(0.0), band(summary.margin_ms));
+        assert!(wide.points > tight.points);
+        assert!(wide.radius_km > tight.radius_km);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::constants::*;
+use crate::propagation::{learn_region_speeds, KnownPath, PropagationSpec, RegionPairs};
+use crate::transport::by_transport;
+use crate::{
+    anchor_alt_km, build_calibration, build_stats, load_jsonl, save_calibration, DistanceModel,
+    EndpointStats,
+};
+use clap::Parser;
+use lattice_core::{Config, Transport};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lattice-analyze calibrate",
+    about = "Build a per-endpoint, per-transport bias model from a capture taken at a known location"
+)]
+pub struct CalibrateArgs {
+    #[arg(long)]
+    config: PathBuf,
+
+    #[arg(long = "override")]
+    overrides: Vec<PathBuf>,
+
+    /// Capture taken at the known location (tunnel off).
+    #[arg(long)]
+    session: PathBuf,
+
+    #[arg(long)]
+    lat: f64,
+
+    #[arg(long)]
+    lon: f64,
+
+    #[arg(long)]
+    out: PathBuf,
+
+    #[arg(long, default_value_t = DEFAULT_SPEED_KM_S)]
+    speed_km_s: f64,
+
+    #[arg(long, default_value_t = DEFAULT_PATH_STRETCH)]
+    path_stretch: f64,
+
+    #[arg(long)]
+    outlier_mad_k: Option<f64>,
+
+    /// Recorded in the calibration; analyses should use the same model.
+    #[arg(long, value_enum, default_value_t = DistanceModel::Sphere)]
+    distance_model: DistanceModel,
+
+    /// Distance-to-RTT model to measure biases against, in place of the straight line at
+    /// `--speed-km-s / --path-stretch`; recorded in the calibration.
+    #[arg(long, conflicts_with = "learn_region_speeds")]
+    propagation_model: Option<PathBuf>,
+
+    /// Learn a per-region model from this capture instead, write it here, and measure
+    /// biases against it.
+    #[arg(long)]
+    learn_region_speeds: Option<PathBuf>,
+
+    /// Measure biases against the shipped region-pair speeds (see the main analyzer).
+    #[arg(long, conflicts_with_all = ["propagation_model", "learn_region_speeds"])]
+    region_pair_speeds: bool,
+}
+
+/// Anchors with coordinates, seen from the calibration site: the first
+/// transport's stats (UDP when it answered), after floors.
+fn known_paths<'a>(
+    cfg: &'a Config,
+    stats: &BTreeMap<Transport, HashMap<String, EndpointStats>>,
+    lat: f64,
+    lon: f64,
+    distance: DistanceModel,
+) -> Vec<KnownPath<'a>> {
+    let Some(stats) = stats.values().next() else {
+        return Vec::new();
+    };
+    cfg.endpoints
+        .iter()
+        .filter_map(|ep| {
+            let st = stats.get(&ep.id)?;
+            let rtt = st.p05.or(st.min)?;
+            Some(KnownPath {
+                region: ep.region_hint.as_deref(),
+                dist_km: distance.distance_3d_km(lat, lon, ep.lat?, ep.lon?, anchor_alt_km(ep)),
+                rtt_ms: (rtt - st.floor_ms()).max(0.0),
+            })
+        })
+        .collect()
+}
+
+pub fn run(args: CalibrateArgs) -> io::Result<()> {
+    let cfg = Config::load_layered(&args.config, &args.overrides)?;
+    let records = load_jsonl(&args.session)?;
+    let stats: BTreeMap<Transport, HashMap<String, EndpointStats>> = by_transport(&records)
+        .into_iter()
+        .map(|(t, recs)| (t, build_stats(&recs, args.outlier_mad_k)))
+        .collect();
+    let path_stretch = args.path_stretch.max(MIN_PATH_STRETCH);
+    let effective_speed = args.speed_km_s / path_stretch;
+    let propagation = match (&args.propagation_model, &args.learn_region_speeds) {
+        (Some(path), _) => PropagationSpec::load(path)?,
+        (None, Some(out)) => {
+            let learned = PropagationSpec::PerRegion(learn_region_speeds(
+                &known_paths(&cfg, &stats, args.lat, args.lon, args.distance_model),
+                effective_speed,
+            ));
+            learned.save(out)?;
+            learned
+        }
+        (None, None) if args.region_pair_speeds => {
+            PropagationSpec::RegionPairs(RegionPairs::shipped(effective_speed))
+        }
+        (None, None) => PropagationSpec::linear(effective_speed),
+    };
+    let cal = build_calibration(
+        &cfg,
+        &stats,
+        args.lat,
+        args.lon,
+        args.speed_km_s,
+        path_stretch,
+        args.distance_model,
+        &propagation,
+    );
+    save_calibration(&args.out, &cal)?;
+
+    let mut ids: Vec<&String> = cal.endpoints.keys().collect();
+    ids.sort();
+    println!(
+        "Calibration from {} records at lat={:.4}, lon={:.4}:",
+        records.len(),
+        args.lat,
+        args.lon
+    );
+    for id in ids {
+        let entry = &cal.endpoints[id];
+        if entry.transports.len() > 1 {
+            let per: Vec<String> = entry
+                .transports
+                .iter()
+                .map(|(t, c)| format!("{}={:.3}ms", t.as_str(), c.bias_ms))
+                .collect();
+            println!("- {} bias={:.3}ms ({})", id, entry.bias_ms, per.join(" "));
+        } else {
+            println!("- {} bias={:.3}ms", id, entry.bias_ms);
+        }
+    }
+    if let (Some(out), PropagationSpec::PerRegion(m)) = (&args.learn_region_speeds, &propagation) {
+        println!(
+            "Learned region speeds (default {:.0} km/s):",
+            m.default_speed_km_s
+        );
+        for (region, speed) in &m.speeds_km_s {
+            println!("- {} {:.0} km/s", region, speed);
+        }
+        println!("Wrote {}", out.display());
+    }
+    println!("Wrote {}", args.out.display());
+    Ok(())
+}
@@ -0,0 +1,573 @@
+use crate::constants::*;
+use crate::interference;
+use crate::plan::{named_area, parse_bbox, Area};
+use crate::propagation::{Leg, Linear};
+use crate::quality::{self, DataQuality, QualityInputs, QualityThresholds};
+use crate::transport;
+use crate::units::{DistanceUnit, ReportFormat};
+use crate::{
+    adjust_rtt_ms, anchor_alt_km, build_stats, clear_self_floor, endpoints_by_id, fmt_opt,
+    load_calibration, load_clock_quality, load_jsonl, max_distance_km, set_access_floor,
+    Calibration, DistanceModel, EndpointStats, SampleGate,
+};
+use clap::{Parser, ValueEnum};
+use lattice_core::{now_unix_ms, Config, Endpoint};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Exit status when the verdict reaches `--fail-on`. Distinct from 1
+/// (runtime error) and 2 (usage error) so a gate can tell them apart.
+pub const CHECK_FAILED_EXIT_CODE: i32 = 3;
+const JUNIT_SUITE: &str = "lattice-check";
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lattice-analyze check",
+    about = "Policy gate: can this session have been captured inside the claimed region?"
+)]
+pub struct CheckArgs {
+    #[arg(long)]
+    config: PathBuf,
+
+    #[arg(long = "override")]
+    overrides: Vec<PathBuf>,
+
+    #[arg(long)]
+    session: PathBuf,
+
+    /// One of EU, NA, US, SA, AF, ME, AS, OC, WORLD.
+    #[arg(long, required_unless_present = "claim_bbox")]
+    claim_region: Option<String>,
+
+    /// `LAT_MIN,LON_MIN,LAT_MAX,LON_MAX` instead of a named region.
+    #[arg(long, conflicts_with = "claim_region")]
+    claim_bbox: Option<String>,
+
+    /// Lowest verdict that fails the check (exit status 3).
+    #[arg(long, value_enum, default_value_t = Verdict::Inconsistent)]
+    fail_on: Verdict,
+
+    /// Also write the per-anchor results as JUnit XML.
+    #[arg(long)]
+    junit: Option<PathBuf>,
+
+    #[arg(long)]
+    calibration: Option<PathBuf>,
+
+    /// Grade limits layered over the built-in data-quality thresholds.
+    #[arg(long)]
+    quality_thresholds: Option<PathBuf>,
+
+    #[arg(long, default_value_t = DEFAULT_SPEED_KM_S)]
+    speed_km_s: f64,
+
+    #[arg(long, default_value_t = DEFAULT_PATH_STRETCH)]
+    path_stretch: f64,
+
+    #[arg(long)]
+    outlier_mad_k: Option<f64>,
+
+    #[arg(long)]
+    access_floor_ms: Option<f64>,
+
+    #[arg(long)]
+    no_self_floor: bool,
+
+    #[arg(long)]
+    no_transport_correction: bool,
+
+    #[arg(long)]
+    interference_ms: Option<f64>,
+
+    #[arg(long, value_enum, default_value_t = DistanceModel::Sphere)]
+    distance_model: DistanceModel,
+
+    /// Overrides `minSamplesPerEndpoint` from the config.
+    #[arg(long)]
+    min_samples: Option<usize>,
+
+    /// Overrides `minBurstsPerEndpoint` from the config.
+    #[arg(long)]
+    min_bursts: Option<usize>,
+
+    /// Unit for distances in text output; JSON stays in km.
+    #[arg(long, value_enum, default_value_t = DistanceUnit::Km)]
+    units: DistanceUnit,
+
+    /// Locale for number separators in text output (default: `LC_ALL`,
+    /// `LC_NUMERIC`, or `LANG`).
+    #[arg(long)]
+    locale: Option<String>,
+
+    #[arg(long)]
+    json: bool,
+}
+
+/// Ordered by severity, so `--fail-on X` fails on X and everything after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    /// Every anchor's bounds reach the region.
+    Consistent,
+    /// No anchor with coordinates and enough samples produced a bound.
+    Insufficient,
+    /// Some anchor's p05 bound misses the region, but its p50 bound does not.
+    Suspect,
+    /// Some anchor's p50 bound misses the region.
+    Inconsistent,
+}
+
+impl Verdict {
+    fn label(self) -> &'static str {
+        match self {
+            Verdict::Consistent => "consistent",
+            Verdict::Insufficient => "insufficient",
+            Verdict::Suspect => "suspect",
+            Verdict::Inconsistent => "inconsistent",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnchorCheck {
+    pub id: String,
+    /// Distance from the anchor to the nearest point of the claimed region,
+    /// including the anchor's altitude.
+    pub region_dist_km: f64,
+    pub max_tight_km: Option<f64>,
+    pub max_loose_km: Option<f64>,
+    pub verdict: Verdict,
+    /// Set when the sample gate left this anchor out; its verdict is then `insufficient`.
+    pub excluded: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckResult {
+    pub claim: String,
+    pub verdict: Verdict,
+    pub fail_on: Verdict,
+    pub failed: bool,
+    pub anchors: Vec<AnchorCheck>,
+    /// Graded at the centre of the claimed region. Informational: it does
+    /// not move the verdict or the exit status.
+    pub quality: DataQuality,
+}
+
+/// Distance from a point to the nearest point of `area`; zero inside. The
+/// nearest point is found on the sphere, then measured with `distance`.
+pub(crate) fn area_distance_km(area: Area, lat: f64, lon: f64, distance: DistanceModel) -> f64 {
+    if (area.lon_min..=area.lon_max).contains(&lon) {
+        let nearest_lat = lat.clamp(area.lat_min, area.lat_max);
+        return distance.distance_km(lat, lon, nearest_lat, lon);
+    }
+    // Outside the longitude span the nearest point is on the closer edge
+    // meridian: where the perpendicular from the point meets it, or the
+    // pole-side end when the point is more than 90° of longitude away.
+    let lon_gap = |edge: f64| (lon - edge).rem_euclid(WORLD_LON_MAX * 2.0);
+    let east_gap = lon_gap(area.lon_max).min(WORLD_LON_MAX * 2.0 - lon_gap(area.lon_max));
+    let west_gap = lon_gap(area.lon_min).min(WORLD_LON_MAX * 2.0 - lon_gap(area.lon_min));
+    let (edge, gap) = if east_gap <= west_gap {
+        (area.lon_max, east_gap)
+    } else {
+        (area.lon_min, west_gap)
+    };
+    let cos_gap = gap.to_radians().cos();
+    let foot_lat = if cos_gap > 0.0 {
+        (lat.to_radians().tan() / cos_gap).atan().to_degrees()
+    } else {
+        WORLD_LAT_MAX.copysign(lat)
+    };
+    let nearest_lat = foot_lat.clamp(area.lat_min, area.lat_max);
+    distance.distance_km(lat, lon, nearest_lat, edge)
+}
+
+/// Falsification-only: an anchor whose RTT bound cannot reach the region
+/// rules it out, but reaching it proves nothing (a tunnel only adds latency).
+pub(crate) fn check_region(
+    stats: &HashMap<String, EndpointStats>,
+    endpoints: &HashMap<String, Endpoint>,
+    area: Area,
+    speed_km_s: f64,
+    calibration: Option<&Calibration>,
+    distance: DistanceModel,
+) -> (Verdict, Vec<AnchorCheck>) {
+    let mut ids: Vec<&String> = stats.keys().collect();
+    ids.sort();
+    let mut anchors = Vec::new();
+    for id in ids {
+        let st = &stats[id];
+        let base = id.split('@').next().unwrap_or(id);
+        let Some(ep) = endpoints.get(id).or_else(|| endpoints.get(base)) else {
+            continue;
+        };
+        let (Some(ep_lat), Some(ep_lon)) = (ep.lat, ep.lon) else {
+            continue;
+        };
+        if let Some(reason) = &st.excluded {
+            anchors.push(AnchorCheck {
+                id: id.clone(),
+                region_dist_km: area_distance_km(area, ep_lat, ep_lon, distance)
+                    .hypot(anchor_alt_km(ep)),
+                max_tight_km: None,
+                max_loose_km: None,
+                verdict: Verdict::Insufficient,
+                excluded: Some(reason.clone()),
+            });
+            continue;
+        }
+        let bound = |v: Option<f64>| {
+            v.map(|v| adjust_rtt_ms(v, st.floor_ms(), id, calibration))
+                .and_then(|v| {
+                    let leg = Leg {
+                        region: ep.region_hint.as_deref(),
+                        anchor: Some((ep_lat, ep_lon)),
+                        host: None,
+                    };
+                    max_distance_km(v, &Linear { speed_km_s }, leg)
+                })
+        };
+        let max_tight_km = bound(st.p05);
+        let max_loose_km = bound(st.p50);
+        let region_dist_km =
+            area_distance_km(area, ep_lat, ep_lon, distance).hypot(anchor_alt_km(ep));
+        let verdict = match (max_tight_km, max_loose_km) {
+            (_, Some(loose)) if region_dist_km > loose => Verdict::Inconsistent,
+            (Some(tight), _) if region_dist_km > tight => Verdict::Suspect,
+            (None, None) => Verdict::Insufficient,
+            _ => Verdict::Consistent,
+        };
+        anchors.push(AnchorCheck {
+            id: id.clone(),
+            region_dist_km,
+            max_tight_km,
+            max_loose_km,
+            verdict,
+            excluded: None,
+        });
+    }
+    let bounded = anchors
+        .iter()
+        .filter(|a| a.verdict != Verdict::Insufficient);
+    let verdict = bounded
+        .map(|a| a.verdict)
+        .max()
+        .unwrap_or(Verdict::Insufficient);
+    (verdict, anchors)
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// One test case per anchor; an anchor fails when its own verdict reaches
+/// `fail_on`. A run with no usable anchor gets a single `coverage` case.
+pub(crate) fn junit_xml(result: &CheckResult) -> String {
+    let suite = xml_escape(&format!("{} {}", JUNIT_SUITE, result.claim));
+    let mut cases = Vec::new();
+    for a in result
+        .anchors
+        .iter()
+        .filter(|a| a.verdict != Verdict::Insufficient)
+    {
+        let body = if a.verdict >= result.fail_on {
+            format!(
+                "<failure message=\"{}\">anchor {:.0} km from the region; bounds tight={} km loose={} km</failure>",
+                a.verdict.label(),
+                a.region_dist_km,
+                fmt_opt(a.max_tight_km, 0),
+                fmt_opt(a.max_loose_km, 0),
+            )
+        } else {
+            String::new()
+        };
+        cases.push((xml_escape(&a.id), body));
+    }
+    if cases.is_empty() {
+        let body = if result.verdict >= result.fail_on {
+            "<failure message=\"insufficient\">no anchor with coordinates produced a bound</failure>"
+        } else {
+            "<skipped/>"
+        };
+        cases.push(("coverage".to_string(), body.to_string()));
+    }
+    let failures = cases
+        .iter()
+        .filter(|(_, body)| body.starts_with("<failure"))
+        .count();
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!(
+        "<testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">\n",
+        suite,
+        cases.len(),
+        failures
+    ));
+    for (name, body) in cases {
+        out.push_str(&format!(
+            "  <testcase classname=\"{}\" name=\"{}\">{}</testcase>\n",
+            suite, name, body
+        ));
+    }
+    out.push_str("</testsuite>\n");
+    out
+}
+
+pub fn run(args: CheckArgs) -> io::Result<()> {
+    let (claim, area) = match (&args.claim_region, &args.claim_bbox) {
+        (_, Some(bbox)) => (bbox.clone(), parse_bbox(bbox)),
+        (Some(name), None) => (name.to_ascii_uppercase(), named_area(name)),
+        (None, None) => (String::new(), None),
+    };
+    let area = area.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "unknown --claim-region or malformed --claim-bbox",
+        )
+    })?;
+    let cfg = Config::load_layered(&args.config, &args.overrides)?;
+    let endpoints = endpoints_by_id(&cfg.endpoints);
+    let calibration = match &args.calibration {
+        Some(path) => Some(load_calibration(path)?),
+        None => None,
+    };
+    let mut records = load_jsonl(&args.session)?;
+    let interfered = args
+        .interference_ms
+        .map(|t| interference::drop_host_interference(&mut records, t));
+    transport::correct_transport_bias(
+        &mut records,
+        calibration.as_ref(),
+        !args.no_transport_correction,
+    );
+    let mut stats = build_stats(&records, args.outlier_mad_k);
+    if let Some(dropped) = &interfered {
+        interference::note_interference(&mut stats, dropped);
+    }
+    if let Some(floor) = args.access_floor_ms {
+        set_access_floor(&mut stats, floor);
+    }
+    if args.no_self_floor {
+        clear_self_floor(&mut stats);
+    }
+    SampleGate {
+        min_samples: args.min_samples.unwrap_or(cfg.min_samples_per_endpoint),
+        min_bursts: args.min_bursts.unwrap_or(cfg.min_bursts_per_endpoint),
+    }
+    .apply(&mut stats);
+    let speed = args.speed_km_s / args.path_stretch.max(MIN_PATH_STRETCH);
+    let (verdict, anchors) = check_region(
+        &stats,
+        &endpoints,
+        area,
+        speed,
+        calibration.as_ref(),
+        args.distance_model,
+    );
+    let thresholds = QualityThresholds::load_or_default(args.quality_thresholds.as_deref())?;
+    let clock = load_clock_quality(&args.session)?;
+    let quality = quality::assess(
+        &thresholds,
+        &QualityInputs {
+            stats: &stats,
+            endpoints: &endpoints,
+            records: &records,
+            samples_per_burst: cfg.samples_per_endpoint,
+            at: Some((
+                (area.lat_min + area.lat_max) / 2.0,
+                (area.lon_min + area.lon_max) / 2.0,
+            )),
+            calibration: calibration.as_ref(),
+            clock: clock.as_ref(),
+            now_ms: now_unix_ms(),
+        },
+    );
+    let result = CheckResult {
+        claim,
+        verdict,
+        fail_on: args.fail_on,
+        failed: verdict >= args.fail_on,
+        anchors,
+        quality,
+    };
+
+    if let Some(path) = &args.junit {
+        fs::write(path, junit_xml(&result))?;
+    }
+    if args.json {
+        let text = serde_json::to_string_pretty(&result).map_err(io::Error::other)?;
+        println!("{text}");
+    } else {
+        let fmt = ReportFormat::new(args.units, args.locale.as_deref(), DEFAULT_DISPLAY_DECIMALS);
+        let unit = fmt.unit.label();
+        println!("Claim {}: {}", result.claim, result.verdict.label());
+        for a in &result.anchors {
+            if let Some(reason) = &a.excluded {
+                println!("- {} excluded ({})", a.id, reason);
+                continue;
+            }
+            println!(
+                "- {} {} region_dist_{unit}={} max_tight={} max_loose={}",
+                a.id,
+                a.verdict.label(),
+                fmt.dist(a.region_dist_km, 1),
+                fmt.dist_opt(a.max_tight_km, 1),
+                fmt.dist_opt(a.max_loose_km, 1),
+            );
+        }
+        quality::print_quality("Session", &result.quality, &fmt);
+        if result.failed {
+            println!("FAIL (--fail-on {})", result.fail_on.label());
+        } else {
+            println!("PASS (--fail-on {})", result.fail_on.label());
+        }
+    }
+    if result.failed {
+        io::stdout().flush()?;
+        std::process::exit(CHECK_FAILED_EXIT_CODE);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+    use crate::{claim_checks, plan};
+    use lattice_core::haversine_km;
+
+    #[test]
+    fn sample_gate_excludes_thin_endpoints_from_estimate_and_claims() {
+        let records = vec![
+            record("a", vec![10.0, 10.5, 11.0]),
+            record("a", vec![10.2, 10.4]),
+            record("b", vec![20.0, 20.5, 21.0, 21.5, 22.0]),
+            record("c", vec![f64::NAN]),
+        ];
+        let mut stats = build_stats(&records, None);
+        assert_eq!(stats["a"].bursts, 2);
+        assert_eq!(stats["b"].bursts, 1);
+        assert_eq!(stats["c"].bursts, 0);
+
+        SampleGate {
+            min_samples: 4,
+            min_bursts: 2,
+        }
+        .apply(&mut stats);
+        assert_eq!(stats["a"].excluded, None);
+        assert_eq!(stats["b"].excluded.as_deref(), Some("1 bursts < 2"));
+        assert_eq!(stats["c"].excluded.as_deref(), Some("0 samples < 4"));
+
+        let mut endpoints = HashMap::new();
+        endpoints.insert("a".to_string(), endpoint("a", 0.0, 0.0));
+        endpoints.insert("b".to_string(), endpoint("b", 10.0, 10.0));
+        let checks = claim_checks(
+            &stats,
+            &endpoints,
+            0.0,
+            0.0,
+            &TEST_MODEL,
+            None,
+            DistanceModel::Sphere,
+        );
+        assert_eq!(
+            checks.iter().map(|c| c.id.as_str()).collect::<Vec<_>>(),
+            vec!["a"]
+        );
+
+        let (_, anchors) = check_region(
+            &stats,
+            &endpoints,
+            plan::named_area("WORLD").unwrap(),
+            DEFAULT_SPEED_KM_S,
+            None,
+            DistanceModel::Sphere,
+        );
+        let b = anchors.iter().find(|a| a.id == "b").unwrap();
+        assert_eq!(b.verdict, Verdict::Insufficient);
+        assert!(b.excluded.is_some() && b.max_loose_km.is_none());
+    }
+
+    #[test]
+    fn check_region_grades_anchor_bounds_and_writes_junit() {
+        let area = plan::named_area("eu").unwrap();
+        assert_eq!(
+            area_distance_km(area, 50.0, 8.0, DistanceModel::Sphere),
+            0.0
+        );
+        let south = area_distance_km(area, 0.0, 10.0, DistanceModel::Sphere);
+        assert!((south - haversine_km(0.0, 10.0, 35.0, 10.0)).abs() < 1e-6);
+
+        let bounded = |p05: f64, p50: f64| EndpointStats {
+            count: 10,
+            min: Some(p05),
+            p05: Some(p05),
+            p50: Some(p50),
+            p95: Some(p50),
+            jitter_ms: Some(p50 - p05),
+            bursts: 10,
+            ..Default::default()
+        };
+        let eps = endpoints_by_id(&[
+            endpoint("fra", 50.11, 8.68),
+            endpoint("mid", 50.0, -40.0),
+            endpoint("syd", -33.87, 151.21),
+        ]);
+        // 1 ms of RTT is 100 km of one-way range at the default speed.
+        let mut stats = HashMap::new();
+        stats.insert("fra".to_string(), bounded(5.0, 6.0));
+        stats.insert("mid".to_string(), bounded(15.0, 30.0));
+        let (verdict, anchors) = check_region(
+            &stats,
+            &eps,
+            area,
+            DEFAULT_SPEED_KM_S,
+            None,
+            DistanceModel::Sphere,
+        );
+        assert_eq!(verdict, Verdict::Suspect);
+        assert_eq!(anchors[0].verdict, Verdict::Consistent);
+
+        stats.insert("syd".to_string(), bounded(10.0, 10.0));
+        let (verdict, anchors) = check_region(
+            &stats,
+            &eps,
+            area,
+            DEFAULT_SPEED_KM_S,
+            None,
+            DistanceModel::Sphere,
+        );
+        assert_eq!(verdict, Verdict::Inconsistent);
+
+        let result = CheckResult {
+            claim: "EU".to_string(),
+            verdict,
+            fail_on: Verdict::Suspect,
+            failed: true,
+            anchors,
+            quality: quality::DataQuality {
+                grade: 'A',
+                factors: Vec::new(),
+            },
+        };
+        let xml = junit_xml(&result);
+        assert!(xml.contains("tests=\"3\" failures=\"2\""));
+        assert!(xml.contains("<failure message=\"inconsistent\">"));
+
+        let (verdict, _) = check_region(
+            &HashMap::new(),
+            &eps,
+            area,
+            DEFAULT_SPEED_KM_S,
+            None,
+            DistanceModel::Sphere,
+        );
+        assert_eq!(verdict, Verdict::Insufficient);
+    }
+}
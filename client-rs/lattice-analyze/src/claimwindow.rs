@@ -0,0 +1,214 @@
+use crate::coverage::fmt_utc;
+use crate::fleet::claim_verdict;
+use crate::propagation::PropagationModel;
+use crate::units::ReportFormat;
+use crate::{
+    adjust_rtt_ms, claim_check, quantile, record_samples, stats_endpoint, Calibration, ClaimCheck,
+    DistanceModel, EndpointStats,
+};
+use lattice_core::{BurstRecord, Endpoint};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// A stretch of the session over which the windowed claim verdict held.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ClaimSpan {
+    /// The burst that first gave this verdict.
+    pub from_ts_ms: i64,
+    /// The last burst before the verdict changed.
+    pub to_ts_ms: i64,
+    /// Bursts in the span; each one moved its anchor's window.
+    pub bursts: usize,
+    /// As in `analyze fleet`: `consistent`, `suspect`, `inconsistent`, or
+    /// null while no anchor has a bound yet.
+    pub verdict: Option<&'static str>,
+    /// Anchors whose bound missed the claim at the end of the span.
+    pub falsifying: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ClaimWindow {
+    /// Bursts per anchor in each window (`--claim-window`).
+    pub bursts: usize,
+    /// How the verdict moved through the session, oldest first.
+    pub timeline: Vec<ClaimSpan>,
+    /// Share of bursts after which the windows were consistent with the claim.
+    pub consistent_share: Option<f64>,
+}
+
+/// Claim checks over each anchor's last `window` bursts instead of the whole
+/// session: the tight bound from the window's minimum RTT (a burst minimum
+/// carries no retransmission or queueing), the loose one from its median.
+/// A pooled p05 hides a host that was at the claimed location for only part
+/// of the capture; replaying the records in time order shows when the claim
+/// held. Returns the checks for the final windows and the timeline.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn claim_window(
+    records: &[BurstRecord],
+    stats: &HashMap<String, EndpointStats>,
+    endpoints: &HashMap<String, Endpoint>,
+    claim: (f64, f64),
+    window: usize,
+    model: &dyn PropagationModel,
+    calibration: Option<&Calibration>,
+    distance: DistanceModel,
+) -> (Vec<ClaimCheck>, ClaimWindow) {
+    let mut order: Vec<&BurstRecord> = records.iter().collect();
+    order.sort_by_key(|r| r.ts_unix_ms);
+
+    let mut bursts: HashMap<&str, VecDeque<Vec<f64>>> = HashMap::new();
+    let mut current: BTreeMap<String, ClaimCheck> = BTreeMap::new();
+    let mut timeline: Vec<ClaimSpan> = Vec::new();
+    for rec in order {
+        let id = rec.endpoint_id.as_str();
+        let Some(st) = stats.get(id).filter(|st| st.excluded.is_none()) else {
+            continue;
+        };
+        let Some(ep) = stats_endpoint(endpoints, id) else {
+            continue;
+        };
+        let valid: Vec<f64> = record_samples(rec)
+            .into_iter()
+            .filter(|v| v.is_finite() && *v >= 0.0)
+            .collect();
+        if valid.is_empty() {
+            continue;
+        }
+        let held = bursts.entry(id).or_default();
+        held.push_back(valid);
+        if held.len() > window {
+            held.pop_front();
+        }
+        let mut samples: Vec<f64> = held.iter().flatten().copied().collect();
+        samples.sort_by(f64::total_cmp);
+        let adjust = |v: f64| adjust_rtt_ms(v, st.floor_ms(), id, calibration);
+        let check = claim_check(
+            id,
+            ep,
+            claim,
+            samples.first().copied().map(adjust),
+            quantile(&samples, 0.5).map(adjust),
+            model,
+            distance,
+        );
+        let Some(check) = check else { continue };
+        current.insert(id.to_string(), check);
+
+        let checks: Vec<ClaimCheck> = current.values().cloned().collect();
+        let verdict = claim_verdict(&checks);
+        let falsifying: Vec<String> = checks
+            .iter()
+            .filter(|c| c.falsify_tight == Some(true) || c.falsify_loose == Some(true))
+            .map(|c| c.id.clone())
+            .collect();
+        match timeline.last_mut() {
+            Some(span) if span.verdict == verdict => {
+                span.to_ts_ms = rec.ts_unix_ms;
+                span.bursts += 1;
+                span.falsifying = falsifying;
+            }
+            _ => timeline.push(ClaimSpan {
+                from_ts_ms: rec.ts_unix_ms,
+                to_ts_ms: rec.ts_unix_ms,
+                bursts: 1,
+                verdict,
+                falsifying,
+            }),
+        }
+    }
+
+    let total: usize = timeline.iter().map(|s| s.bursts).sum();
+    let consistent: usize = timeline
+        .iter()
+        .filter(|s| s.verdict == Some("consistent"))
+        .map(|s| s.bursts)
+        .sum();
+    let consistent_share = (total > 0).then(|| consistent as f64 / total as f64);
+    (
+        current.into_values().collect(),
+        ClaimWindow {
+            bursts: window,
+            timeline,
+            consistent_share,
+        },
+    )
+}
+
+pub(crate) fn print_claim_window(claim_window: &ClaimWindow, fmt: &ReportFormat) {
+    println!(
+        "\nClaim over time (each anchor's last {} bursts; consistent after {}% of bursts):",
+        claim_window.bursts,
+        fmt.opt(claim_window.consistent_share.map(|s| s * 100.0), 0)
+    );
+    for span in &claim_window.timeline {
+        let falsifying = if span.falsifying.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", span.falsifying.join(", "))
+        };
+        println!(
+            "- {} .. {} bursts={} verdict={}{}",
+            fmt_utc(span.from_ts_ms),
+            fmt_utc(span.to_ts_ms),
+            span.bursts,
+            span.verdict.unwrap_or("unbounded"),
+            falsifying
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::endpoints_by_id;
+    use crate::testutil::*;
+
+    #[test]
+    fn claim_window_follows_the_verdict_through_the_session() {
+        let (frankfurt, new_york) = ((50.1, 8.7), (40.7, -74.0));
+        let endpoints = endpoints_by_id(&[
+            endpoint("fra", frankfurt.0, frankfurt.1),
+            endpoint("nyc", new_york.0, new_york.1),
+        ]);
+        let mut stats = stats_with_p05("fra", 2.0);
+        stats.extend(stats_with_p05("nyc", 90.0));
+        // The host starts next to New York and moves to Frankfurt at burst 3.
+        let nyc_rtts = [5.0, 5.0, 90.0, 90.0, 90.0];
+        let mut records = Vec::new();
+        for (i, nyc_ms) in nyc_rtts.into_iter().enumerate() {
+            let ts = i as i64 * 1_000;
+            let mut rec = record("nyc", vec![nyc_ms, nyc_ms + 1.0]);
+            rec.ts_unix_ms = ts;
+            records.push(rec);
+            let mut rec = record("fra", vec![2.0, 3.0]);
+            rec.ts_unix_ms = ts + 500;
+            records.push(rec);
+        }
+
+        let (checks, window) = claim_window(
+            &records,
+            &stats,
+            &endpoints,
+            frankfurt,
+            2,
+            &TEST_MODEL,
+            None,
+            DistanceModel::Sphere,
+        );
+        let verdicts: Vec<_> = window.timeline.iter().map(|s| s.verdict).collect();
+        // Right after the move the window still holds one 5 ms burst: its
+        // minimum misses the claim while the median no longer does.
+        assert_eq!(
+            verdicts,
+            vec![Some("inconsistent"), Some("suspect"), Some("consistent")]
+        );
+        assert_eq!(window.timeline[0].falsifying, vec!["nyc".to_string()]);
+        assert_eq!(window.timeline[1].from_ts_ms, 2_000);
+        assert_eq!(window.timeline[2].from_ts_ms, 3_000);
+        assert!(checks.iter().all(|c| c.falsify_tight == Some(false)));
+        let share = window.consistent_share.unwrap();
+        assert!((share - 0.4).abs() < TEST_EPSILON);
+    }
+}
@@ -0,0 +1,330 @@
+use crate::constants::DEFAULT_DISPLAY_DECIMALS;
+use crate::units::{DistanceUnit, ReportFormat};
+use crate::{build_stats, load_jsonl, EndpointStats};
+use clap::{Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const CLASSIFIER_SCHEMA: &str = "lattice-classifier/1";
+/// Spread floor in log-RTT space (about 10%), so a class trained from a single
+/// session does not reject captures that differ from it by a few percent.
+const MIN_LOG_STD: f64 = 0.1;
+/// Fewer shared anchors than this cannot separate neighbouring regions.
+const MIN_SHARED_ANCHORS: usize = 2;
+const PERCENT: f64 = 100.0;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lattice-analyze classify",
+    about = "Latency-only country/region classifier over a standard anchor set"
+)]
+pub struct ClassifyArgs {
+    #[command(subcommand)]
+    action: ClassifyAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum ClassifyAction {
+    /// Build a model from sessions captured in known countries or regions.
+    Train {
+        /// A labelled capture, e.g. `DE=berlin.jsonl` (repeatable; several per label is better).
+        #[arg(long = "label", value_name = "LABEL=SESSION", required = true)]
+        labelled: Vec<String>,
+
+        #[arg(long)]
+        out: PathBuf,
+
+        #[arg(long)]
+        outlier_mad_k: Option<f64>,
+    },
+    /// Score a session against a trained model.
+    Predict {
+        #[arg(long)]
+        model: PathBuf,
+
+        #[arg(long)]
+        session: PathBuf,
+
+        #[arg(long)]
+        outlier_mad_k: Option<f64>,
+
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Per-class Gaussian model of `ln(p05)` for each anchor (naive Bayes).
+/// Anchors are matched by endpoint id, so no coordinates are needed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClassifierModel {
+    pub schema: String,
+    pub anchors: Vec<String>,
+    pub classes: BTreeMap<String, ClassModel>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClassModel {
+    pub examples: usize,
+    pub log_p05_mean: BTreeMap<String, f64>,
+    pub log_p05_std: BTreeMap<String, f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Classification {
+    pub anchors_used: Vec<String>,
+    pub classes: Vec<ClassScore>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClassScore {
+    pub label: String,
+    pub probability: f64,
+}
+
+pub fn run(args: ClassifyArgs) -> io::Result<()> {
+    match args.action {
+        ClassifyAction::Train {
+            labelled,
+            out,
+            outlier_mad_k,
+        } => {
+            let mut examples = Vec::new();
+            for entry in &labelled {
+                let (label, path) = entry.split_once('=').ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("--label expects LABEL=SESSION, got '{}'", entry),
+                    )
+                })?;
+                let records = load_jsonl(&PathBuf::from(path))?;
+                let features = features(&build_stats(&records, outlier_mad_k));
+                examples.push((label.to_string(), features));
+            }
+            let model =
+                train(&examples).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let text = serde_json::to_string_pretty(&model).map_err(io::Error::other)?;
+            fs::write(&out, text)?;
+            println!(
+                "Trained {} class(es) over {} anchor(s) from {} session(s); wrote {}",
+                model.classes.len(),
+                model.anchors.len(),
+                examples.len(),
+                out.display()
+            );
+            Ok(())
+        }
+        ClassifyAction::Predict {
+            model,
+            session,
+            outlier_mad_k,
+            json,
+        } => {
+            let model = load_model(&model)?;
+            let records = load_jsonl(&session)?;
+            let result = classify(&model, &features(&build_stats(&records, outlier_mad_k)));
+            if json {
+                let text = serde_json::to_string_pretty(&result).map_err(io::Error::other)?;
+                println!("{text}");
+            } else {
+                let fmt = ReportFormat::new(DistanceUnit::Km, None, DEFAULT_DISPLAY_DECIMALS);
+                print_classification(result.as_ref(), &fmt);
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn load_model(path: &PathBuf) -> io::Result<ClassifierModel> {
+    let data = fs::read(path)?;
+    let model: ClassifierModel =
+        serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if model.schema != CLASSIFIER_SCHEMA {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported classifier schema '{}'", model.schema),
+        ));
+    }
+    Ok(model)
+}
+
+/// `ln(p05)` per anchor; RTT errors are closer to multiplicative than additive.
+pub(crate) fn features(stats: &HashMap<String, EndpointStats>) -> HashMap<String, f64> {
+    stats
+        .iter()
+        .filter_map(|(id, s)| s.p05.filter(|p| *p > 0.0).map(|p| (id.clone(), p.ln())))
+        .collect()
+}
+
+/// Fits one Gaussian per class and anchor. Only anchors seen in every class
+/// are kept, so every class is scored on the same evidence.
+pub(crate) fn train(
+    examples: &[(String, HashMap<String, f64>)],
+) -> Result<ClassifierModel, String> {
+    let mut by_class: BTreeMap<&str, Vec<&HashMap<String, f64>>> = BTreeMap::new();
+    for (label, features) in examples {
+        by_class.entry(label.as_str()).or_default().push(features);
+    }
+    if by_class.len() < 2 {
+        return Err("need sessions from at least two labels".to_string());
+    }
+
+    let mut anchors: Vec<String> = Vec::new();
+    if let Some(first) = by_class.values().next() {
+        anchors = first.iter().flat_map(|f| f.keys().cloned()).collect();
+        anchors.sort();
+        anchors.dedup();
+    }
+    anchors.retain(|id| {
+        by_class
+            .values()
+            .all(|sessions| sessions.iter().any(|f| f.contains_key(id)))
+    });
+    if anchors.len() < MIN_SHARED_ANCHORS {
+        return Err(format!(
+            "only {} anchor(s) appear under every label; need at least {}",
+            anchors.len(),
+            MIN_SHARED_ANCHORS
+        ));
+    }
+
+    let mut classes = BTreeMap::new();
+    for (label, sessions) in by_class {
+        let mut log_p05_mean = BTreeMap::new();
+        let mut log_p05_std = BTreeMap::new();
+        for id in &anchors {
+            let values: Vec<f64> = sessions.iter().filter_map(|f| f.get(id).copied()).collect();
+            let n = values.len() as f64;
+            let mean = values.iter().sum::<f64>() / n;
+            let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+            log_p05_mean.insert(id.clone(), mean);
+            log_p05_std.insert(id.clone(), var.sqrt().max(MIN_LOG_STD));
+        }
+        classes.insert(
+            label.to_string(),
+            ClassModel {
+                examples: sessions.len(),
+                log_p05_mean,
+                log_p05_std,
+            },
+        );
+    }
+    Ok(ClassifierModel {
+        schema: CLASSIFIER_SCHEMA.to_string(),
+        anchors,
+        classes,
+    })
+}
+
+/// Posterior over the model's classes with a uniform prior, most likely first.
+/// `None` when the session shares too few anchors with the model.
+pub(crate) fn classify(
+    model: &ClassifierModel,
+    features: &HashMap<String, f64>,
+) -> Option<Classification> {
+    let used: Vec<String> = model
+        .anchors
+        .iter()
+        .filter(|id| features.contains_key(*id))
+        .cloned()
+        .collect();
+    if used.len() < MIN_SHARED_ANCHORS || model.classes.is_empty() {
+        return None;
+    }
+
+    let log_likelihoods: Vec<(String, f64)> = model
+        .classes
+        .iter()
+        .map(|(label, class)| {
+            let ll = used
+                .iter()
+                .map(|id| {
+                    let mean = class.log_p05_mean[id];
+                    let std = class.log_p05_std[id];
+                    let z = (features[id] - mean) / std;
+                    -std.ln() - 0.5 * z * z
+                })
+                .sum::<f64>();
+            (label.clone(), ll)
+        })
+        .collect();
+
+    let max = log_likelihoods
+        .iter()
+        .map(|(_, ll)| *ll)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let total: f64 = log_likelihoods.iter().map(|(_, ll)| (ll - max).exp()).sum();
+    let mut classes: Vec<ClassScore> = log_likelihoods
+        .into_iter()
+        .map(|(label, ll)| ClassScore {
+            label,
+            probability: (ll - max).exp() / total,
+        })
+        .collect();
+    classes.sort_by(|a, b| b.probability.total_cmp(&a.probability));
+    Some(Classification {
+        anchors_used: used,
+        classes,
+    })
+}
+
+pub(crate) fn print_classification(result: Option<&Classification>, fmt: &ReportFormat) {
+    match result {
+        Some(c) => {
+            println!(
+                "Latency-only region classification ({} anchors):",
+                c.anchors_used.len()
+            );
+            for score in &c.classes {
+                println!("- {} {}%", score.label, fmt.num(score.probability * PERCENT, 1));
+            }
+        }
+        None => println!(
+            "Latency-only region classification: session shares fewer than {} anchors with the model.",
+            MIN_SHARED_ANCHORS
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn classifier_prefers_class_with_matching_rtt_profile() {
+        let session = |a: f64, b: f64, c: f64| {
+            let records = vec![
+                record("a", vec![a; 5]),
+                record("b", vec![b; 5]),
+                record("c", vec![c; 5]),
+            ];
+            features(&build_stats(&records, None))
+        };
+        let examples = vec![
+            ("DE".to_string(), session(10.0, 30.0, 90.0)),
+            ("DE".to_string(), session(11.0, 32.0, 95.0)),
+            ("US".to_string(), session(90.0, 80.0, 12.0)),
+        ];
+        let model = train(&examples).unwrap();
+        assert_eq!(model.anchors, vec!["a", "b", "c"]);
+
+        let result = classify(&model, &session(12.0, 31.0, 88.0)).unwrap();
+        assert_eq!(result.classes[0].label, "DE");
+        assert!(result.classes[0].probability > 0.99);
+        let total: f64 = result.classes.iter().map(|c| c.probability).sum();
+        assert!((total - 1.0).abs() < TEST_EPSILON);
+
+        let sparse = session(12.0, 31.0, 88.0)
+            .into_iter()
+            .filter(|(id, _)| id == "a")
+            .collect();
+        assert!(classify(&model, &sparse).is_none());
+    }
+}
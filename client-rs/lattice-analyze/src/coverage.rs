@@ -0,0 +1,225 @@
+use crate::finite;
+use crate::units::ReportFormat;
+use lattice_core::{civil_from_days, BurstRecord, EventRecord, EVENT_RECORD_TYPE, RESUME_EVENT};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+/// A hole must swallow at least this many whole intervals to count as a gap;
+/// anything shorter is a burst running late, not downtime.
+const MIN_GAP_INTERVALS: f64 = 1.0;
+/// Gaps listed per endpoint, longest first.
+const LARGEST_GAPS: usize = 3;
+const MS_PER_SEC: f64 = 1000.0;
+const MS_PER_MIN: i64 = 60_000;
+const MINUTES_PER_DAY: i64 = 24 * 60;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Gap {
+    /// When the missing burst was due: the last burst before plus one interval
+    /// (or the capture start for a leading gap).
+    pub start_ts_ms: i64,
+    /// The next burst (or the capture end for a trailing gap).
+    pub end_ts_ms: i64,
+    pub duration_s: f64,
+    /// The client logged a resume whose sleep overlaps this gap.
+    pub suspended: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct EndpointCoverage {
+    pub endpoint: String,
+    pub bursts: usize,
+    /// Share of the capture's span not inside a gap, as a percentage.
+    pub coverage_pct: Option<f64>,
+    pub gaps: usize,
+    pub missing_s: f64,
+    pub largest_gaps: Vec<Gap>,
+}
+
+/// Sleeps the client recorded, as `(start_ms, end_ms)`, from each `resume`
+/// event's time and `detail.suspendedMs`.
+pub(crate) fn load_suspends(path: &Path) -> io::Result<Vec<(i64, i64)>> {
+    let reader = crate::open_log(path)?;
+    let mut out = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if !line.contains(RESUME_EVENT) {
+            continue;
+        }
+        let Ok(ev) = serde_json::from_str::<EventRecord>(&line) else {
+            continue;
+        };
+        if ev.record_type != EVENT_RECORD_TYPE || ev.event != RESUME_EVENT {
+            continue;
+        }
+        if let Some(ms) = ev.detail.get("suspendedMs").and_then(|v| v.as_f64()) {
+            out.push((ev.ts_unix_ms - ms.round() as i64, ev.ts_unix_ms));
+        }
+    }
+    Ok(out)
+}
+
+/// Finds each endpoint's gaps against the whole capture's span, first burst to
+/// last burst plus one interval, so an endpoint that started late or stopped
+/// early is charged for it. Records without a timestamp are ignored.
+pub(crate) fn coverage(
+    records: &[BurstRecord],
+    interval_s: u64,
+    suspends: &[(i64, i64)],
+) -> Vec<EndpointCoverage> {
+    let interval_ms = (interval_s as f64 * MS_PER_SEC) as i64;
+    let mut by_endpoint: BTreeMap<&str, Vec<i64>> = BTreeMap::new();
+    for rec in records.iter().filter(|r| r.ts_unix_ms > 0) {
+        by_endpoint
+            .entry(rec.endpoint_id.as_str())
+            .or_default()
+            .push(rec.ts_unix_ms);
+    }
+    let all = by_endpoint.values().flatten();
+    let (Some(&start), Some(&last)) = (all.clone().min(), all.max()) else {
+        return Vec::new();
+    };
+    let end = last + interval_ms;
+    let span_ms = (end - start) as f64;
+    let min_gap_ms = (MIN_GAP_INTERVALS * interval_ms as f64) as i64;
+
+    by_endpoint
+        .into_iter()
+        .map(|(id, mut ts)| {
+            ts.sort_unstable();
+            let mut gaps = Vec::new();
+            let mut due = start;
+            for t in ts.iter().copied().chain([end]) {
+                if t - due >= min_gap_ms {
+                    gaps.push(Gap {
+                        start_ts_ms: due,
+                        end_ts_ms: t,
+                        duration_s: (t - due) as f64 / MS_PER_SEC,
+                        suspended: suspends.iter().any(|&(a, b)| a < t && b > due),
+                    });
+                }
+                due = due.max(t + interval_ms);
+            }
+            let missing_ms: i64 = gaps.iter().map(|g| g.end_ts_ms - g.start_ts_ms).sum();
+            let count = gaps.len();
+            gaps.sort_by(|a, b| b.duration_s.total_cmp(&a.duration_s));
+            gaps.truncate(LARGEST_GAPS);
+            EndpointCoverage {
+                endpoint: id.to_string(),
+                bursts: ts.len(),
+                coverage_pct: finite(100.0 * (1.0 - missing_ms as f64 / span_ms)),
+                gaps: count,
+                missing_s: missing_ms as f64 / MS_PER_SEC,
+                largest_gaps: gaps,
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn fmt_utc(ts_ms: i64) -> String {
+    let minutes = ts_ms.div_euclid(MS_PER_MIN);
+    let (year, month, day) = civil_from_days(minutes.div_euclid(MINUTES_PER_DAY));
+    let minute_of_day = minutes.rem_euclid(MINUTES_PER_DAY);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}Z",
+        year,
+        month,
+        day,
+        minute_of_day / 60,
+        minute_of_day % 60
+    )
+}
+
+fn fmt_duration(seconds: f64) -> String {
+    if seconds >= 3600.0 {
+        format!("{:.1}h", seconds / 3600.0)
+    } else if seconds >= 60.0 {
+        format!("{:.1}min", seconds / 60.0)
+    } else {
+        format!("{:.0}s", seconds)
+    }
+}
+
+pub(crate) fn print_coverage(
+    label: &str,
+    coverage: &[EndpointCoverage],
+    interval_s: u64,
+    fmt: &ReportFormat,
+) {
+    if coverage.iter().all(|c| c.gaps == 0) {
+        println!("\n{label} coverage: no gaps (bursts every {interval_s}s)");
+        return;
+    }
+    println!("\n{label} coverage (gaps of {interval_s}s or more with no burst; sleep, client down, blackouts):");
+    for c in coverage {
+        let pct = c
+            .coverage_pct
+            .map_or("-".to_string(), |p| format!("{}%", fmt.num(p, 1)));
+        if c.gaps == 0 {
+            println!("- {} {} no gaps", c.endpoint, pct);
+            continue;
+        }
+        let largest: Vec<String> = c
+            .largest_gaps
+            .iter()
+            .map(|g| {
+                let asleep = if g.suspended { " (asleep)" } else { "" };
+                format!(
+                    "{} from {}{}",
+                    fmt_duration(g.duration_s),
+                    fmt_utc(g.start_ts_ms),
+                    asleep
+                )
+            })
+            .collect();
+        println!(
+            "- {} {} {} gap(s), {} missing; largest: {}",
+            c.endpoint,
+            pct,
+            c.gaps,
+            fmt_duration(c.missing_s),
+            largest.join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn coverage_reports_sleep_gaps_and_late_starts_but_not_jitter() {
+        let base = 1_700_000_000_000i64;
+        let at = |id: &str, s: i64| BurstRecord {
+            ts_unix_ms: base + s * 1000,
+            ..record(id, vec![10.0])
+        };
+        // a sleeps from 30s to 60s and runs 5s late once; b starts at 20s.
+        let mut records: Vec<BurstRecord> = [0, 10, 20, 60, 75, 80, 90]
+            .iter()
+            .map(|&s| at("a", s))
+            .collect();
+        records.extend((2..10).map(|i| at("b", i * 10)));
+        records.push(record("b", vec![10.0]));
+        let cov = coverage(&records, 10, &[(base + 35_000, base + 55_000)]);
+
+        assert_eq!(cov.len(), 2);
+        let (a, b) = (&cov[0], &cov[1]);
+        assert_eq!((a.endpoint.as_str(), a.bursts, a.gaps), ("a", 7, 1));
+        assert_eq!(a.largest_gaps[0].start_ts_ms, base + 30_000);
+        assert_eq!(a.largest_gaps[0].end_ts_ms, base + 60_000);
+        assert!((a.coverage_pct.unwrap() - 70.0).abs() < 1e-9);
+        assert!(a.largest_gaps[0].suspended);
+        assert_eq!((b.bursts, b.gaps), (8, 1));
+        assert_eq!(b.largest_gaps[0].start_ts_ms, base);
+        assert!(!b.largest_gaps[0].suspended);
+        assert!((b.missing_s - 20.0).abs() < 1e-9);
+        assert!((b.coverage_pct.unwrap() - 80.0).abs() < 1e-9);
+        assert!(coverage(&[record("a", vec![1.0])], 10, &[]).is_empty());
+    }
+}
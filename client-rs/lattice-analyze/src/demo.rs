@@ -0,0 +1,347 @@
+use crate::constants::*;
+use crate::propagation::Linear;
+use crate::{
+    build_stats, endpoints_by_id, estimate_location, load_jsonl, DistanceModel, Estimate,
+    SampleGate, Weighting,
+};
+use clap::Parser;
+use lattice_core::{cloud_region, haversine_km, BurstRecord, CloudRegion, Endpoint, LATO_VERSION};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const DEMO_PROVIDER: &str = "aws";
+/// Bundled regions the synthetic anchors sit in: enough around Europe and
+/// eastern North America to place a host in either.
+const DEMO_ANCHORS: [&str; 10] = [
+    "eu-west-1",
+    "eu-west-2",
+    "eu-west-3",
+    "eu-north-1",
+    "eu-south-1",
+    "eu-central-2",
+    "us-east-1",
+    "us-east-2",
+    "ca-central-1",
+    "us-west-2",
+];
+/// Where the synthetic host really is (Frankfurt).
+const DEMO_HOST: (f64, f64) = (50.1109, 8.6821);
+/// Where its VPN exits during the session; also the location it claims.
+const DEMO_VPN_EXIT: &str = "us-east-1";
+const DEMO_BURSTS: usize = 12;
+const DEMO_SAMPLES: usize = 10;
+const DEMO_INTERVAL_S: u64 = 10;
+const DEMO_START_MS: i64 = 1_700_000_000_000;
+/// Last-mile delay on every round trip, as a home line would add.
+const DEMO_ACCESS_MS: f64 = 4.0;
+/// Mean of the exponential queueing delay on each sample.
+const DEMO_JITTER_MS: f64 = 1.5;
+/// Fixed so every run writes the same files and gets the same report.
+const DEMO_SEED: u64 = 0x1a77_1ce5;
+/// Documentation addresses (RFC 5737); nothing is ever sent to them.
+const DEMO_HOST_PREFIX: &str = "192.0.2.";
+const DEMO_PORT: u16 = 9000;
+const DEMO_SECRET_HEX: &str = "00112233445566778899aabbccddeeff";
+/// Farthest an estimate may land from where the data put it for the demo to pass.
+const DEMO_MAX_ERROR_KM: f64 = 300.0;
+const CONFIG_FILE: &str = "config.json";
+const BASELINE_FILE: &str = "baseline.jsonl";
+const SESSION_FILE: &str = "session.jsonl";
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lattice-analyze demo",
+    about = "Run the full analysis on bundled synthetic captures and check it finds what they were built from"
+)]
+pub struct DemoArgs {
+    /// Keep the generated config and sessions here; a temporary directory
+    /// is used and removed otherwise.
+    #[arg(long)]
+    out_dir: Option<PathBuf>,
+
+    /// Print the analyzer's report as JSON; the demo's own check goes to stderr.
+    #[arg(long)]
+    json: bool,
+}
+
+fn demo_region(code: &str) -> io::Result<&'static CloudRegion> {
+    cloud_region(Some(DEMO_PROVIDER), code).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("bundled region table has no {DEMO_PROVIDER} {code}"),
+        )
+    })
+}
+
+/// The demo anchors as config endpoints.
+pub(crate) fn demo_endpoints() -> io::Result<Vec<Endpoint>> {
+    DEMO_ANCHORS
+        .iter()
+        .enumerate()
+        .map(|(i, code)| {
+            let region = demo_region(code)?;
+            Ok(Endpoint {
+                id: format!("demo-{code}"),
+                host: format!("{DEMO_HOST_PREFIX}{}", i + 1),
+                port: DEMO_PORT,
+                region_hint: Some(code.to_string()),
+                lat: Some(region.lat),
+                lon: Some(region.lon),
+                alt_m: None,
+                consented: true,
+                blackouts: Vec::new(),
+                transports: Vec::new(),
+                https_port: None,
+                https_ca_file: None,
+                packet_version: LATO_VERSION,
+            })
+        })
+        .collect()
+}
+
+/// Bursts from a host at `host` to every anchor, as the client would log
+/// them. With `vpn_exit`, each round trip first crosses to the exit and the
+/// bursts go over a tunnel interface. RTTs follow the analyzer's own model
+/// (default fiber speed and path stretch) plus an access delay and jitter.
+pub(crate) fn synth_session(
+    endpoints: &[Endpoint],
+    host: (f64, f64),
+    vpn_exit: Option<(f64, f64)>,
+    session_id: &str,
+    rng: &mut StdRng,
+) -> Vec<Value> {
+    let rtt_ms = |km: f64| RTT_FACTOR * km * DEFAULT_PATH_STRETCH / DEFAULT_SPEED_KM_S * MS_PER_SEC;
+    let (iface, iface_name) = if vpn_exit.is_some() {
+        ("other", "utun3")
+    } else {
+        ("ethernet", "eth0")
+    };
+    let mut lines = Vec::new();
+    for burst in 0..DEMO_BURSTS {
+        let ts = DEMO_START_MS + (burst as u64 * DEMO_INTERVAL_S) as i64 * MS_PER_SEC as i64;
+        for ep in endpoints {
+            let (Some(lat), Some(lon)) = (ep.lat, ep.lon) else {
+                continue;
+            };
+            let path_ms = match vpn_exit {
+                Some((x_lat, x_lon)) => {
+                    rtt_ms(haversine_km(host.0, host.1, x_lat, x_lon))
+                        + rtt_ms(haversine_km(x_lat, x_lon, lat, lon))
+                }
+                None => rtt_ms(haversine_km(host.0, host.1, lat, lon)),
+            };
+            let samples: Vec<f64> = (0..DEMO_SAMPLES)
+                .map(|_| {
+                    let u: f64 = rng.gen_range(f64::EPSILON..1.0);
+                    path_ms + DEMO_ACCESS_MS - u.ln() * DEMO_JITTER_MS
+                })
+                .collect();
+            lines.push(json!({
+                "tsUnixMs": ts,
+                "sessionId": session_id,
+                "burstId": format!("{session_id}-{burst}-{}", ep.id),
+                "endpointId": ep.id,
+                "host": ep.host,
+                "port": ep.port,
+                "regionHint": ep.region_hint,
+                "samplesMs": samples,
+                "iface": iface,
+                "ifaceName": iface_name,
+                "ifaceIsTunnel": vpn_exit.is_some(),
+                "utunPresent": vpn_exit.is_some(),
+                "utunActive": vpn_exit.is_some(),
+                "claimedEgressRegion": DEMO_VPN_EXIT,
+                "notes": [],
+            }));
+        }
+    }
+    lines
+}
+
+/// The estimate the main analyzer makes with its defaults.
+pub(crate) fn place(
+    records: &[BurstRecord],
+    endpoints: &HashMap<String, Endpoint>,
+) -> Option<Estimate> {
+    let mut stats = build_stats(records, None);
+    SampleGate {
+        min_samples: 0,
+        min_bursts: 0,
+    }
+    .apply(&mut stats);
+    estimate_location(
+        &stats,
+        endpoints,
+        &Linear {
+            speed_km_s: DEFAULT_SPEED_KM_S / DEFAULT_PATH_STRETCH,
+        },
+        DEFAULT_GRID_DEG,
+        DEFAULT_REFINE_DEG,
+        DEFAULT_BAND_FACTOR,
+        DEFAULT_BAND_WINDOW_DEG,
+        0.0,
+        None,
+        Weighting::default(),
+        DistanceModel::Sphere,
+    )
+}
+
+fn write_jsonl(path: &Path, lines: &[Value]) -> io::Result<()> {
+    let mut out = io::BufWriter::new(fs::File::create(path)?);
+    for line in lines {
+        serde_json::to_writer(&mut out, line)?;
+        out.write_all(b"\n")?;
+    }
+    out.flush()
+}
+
+/// How far the estimate from `path` landed from `expected`, or an error
+/// saying why the demo failed.
+fn check_estimate(
+    label: &str,
+    path: &Path,
+    endpoints: &HashMap<String, Endpoint>,
+    expected: (f64, f64),
+) -> io::Result<f64> {
+    let records = load_jsonl(path)?;
+    let estimate = place(&records, endpoints).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("demo: no {label} estimate"),
+        )
+    })?;
+    let off_km = haversine_km(estimate.lat, estimate.lon, expected.0, expected.1);
+    if off_km > DEMO_MAX_ERROR_KM {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "demo: {label} estimate is {off_km:.0} km off (limit {DEMO_MAX_ERROR_KM:.0} km)"
+            ),
+        ));
+    }
+    Ok(off_km)
+}
+
+pub fn run(args: DemoArgs) -> io::Result<()> {
+    let dir = match &args.out_dir {
+        Some(dir) => dir.clone(),
+        None => env::temp_dir().join(format!("lattice-demo-{}", std::process::id())),
+    };
+    fs::create_dir_all(&dir)?;
+    let endpoints = demo_endpoints()?;
+    let exit = demo_region(DEMO_VPN_EXIT)?;
+    let mut rng = StdRng::seed_from_u64(DEMO_SEED);
+    let config = json!({
+        "secretHex": DEMO_SECRET_HEX,
+        "endpoints": endpoints,
+        "samplesPerEndpoint": DEMO_SAMPLES,
+        "spacingMs": 10,
+        "timeoutMs": 1000,
+        "intervalSeconds": DEMO_INTERVAL_S,
+        "outputPath": dir.join(SESSION_FILE).display().to_string(),
+        "claimedEgressRegion": DEMO_VPN_EXIT,
+        "physicsMismatchThresholdMs": 5.0,
+    });
+    let config_path = dir.join(CONFIG_FILE);
+    fs::write(
+        &config_path,
+        serde_json::to_vec_pretty(&config).map_err(io::Error::other)?,
+    )?;
+    let baseline_path = dir.join(BASELINE_FILE);
+    write_jsonl(
+        &baseline_path,
+        &synth_session(&endpoints, DEMO_HOST, None, "demo-baseline", &mut rng),
+    )?;
+    let session_path = dir.join(SESSION_FILE);
+    write_jsonl(
+        &session_path,
+        &synth_session(
+            &endpoints,
+            DEMO_HOST,
+            Some((exit.lat, exit.lon)),
+            "demo-session",
+            &mut rng,
+        ),
+    )?;
+    eprintln!(
+        "demo: host at {:.2},{:.2} with a VPN exiting in {DEMO_PROVIDER} {DEMO_VPN_EXIT}; files in {}",
+        DEMO_HOST.0,
+        DEMO_HOST.1,
+        dir.display()
+    );
+
+    let mut argv: Vec<String> = vec!["lattice-analyze".to_string()];
+    for (flag, path) in [
+        ("--config", &config_path),
+        ("--baseline", &baseline_path),
+        ("--session", &session_path),
+    ] {
+        argv.push(flag.to_string());
+        argv.push(path.display().to_string());
+    }
+    argv.push(format!("--claim-lat={}", exit.lat));
+    argv.push(format!("--claim-lon={}", exit.lon));
+    if args.json {
+        argv.push("--json".to_string());
+    }
+    let analyzed = crate::run(&argv);
+
+    let by_id = endpoints_by_id(&endpoints);
+    let checked = analyzed.and_then(|()| {
+        let baseline_km = check_estimate("baseline", &baseline_path, &by_id, DEMO_HOST)?;
+        let session_km = check_estimate("session", &session_path, &by_id, (exit.lat, exit.lon))?;
+        Ok((baseline_km, session_km))
+    });
+    if args.out_dir.is_none() {
+        fs::remove_dir_all(&dir)?;
+    }
+    let (baseline_km, session_km) = checked?;
+    let verdict = format!(
+        "demo: OK. Baseline placed {baseline_km:.0} km from the host, VPN session {session_km:.0} km from the exit (limit {DEMO_MAX_ERROR_KM:.0} km)."
+    );
+    if args.json {
+        eprintln!("{verdict}");
+    } else {
+        println!("\n{verdict}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn demo_sessions_place_the_host_and_then_the_vpn_exit() {
+        use rand::SeedableRng;
+        let endpoints = demo_endpoints().unwrap();
+        let by_id = endpoints_by_id(&endpoints);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let host = (50.1109, 8.6821);
+        let exit = (38.9940, -77.4524);
+        let parse = |lines: Vec<serde_json::Value>| -> Vec<BurstRecord> {
+            lines
+                .into_iter()
+                .map(|v| serde_json::from_value(v).unwrap())
+                .collect()
+        };
+        let baseline = parse(synth_session(&endpoints, host, None, "b", &mut rng));
+        assert_eq!(baseline.len(), endpoints.len() * 12);
+        assert!(baseline
+            .iter()
+            .all(|r| !r.utun_active && r.samples_ms.len() == 10));
+        let est = place(&baseline, &by_id).unwrap();
+        assert!(haversine_km(est.lat, est.lon, host.0, host.1) < 100.0);
+
+        let session = parse(synth_session(&endpoints, host, Some(exit), "s", &mut rng));
+        assert!(session.iter().all(|r| r.iface_is_tunnel));
+        let est = place(&session, &by_id).unwrap();
+        assert!(haversine_km(est.lat, est.lon, exit.0, exit.1) < 100.0);
+    }
+}
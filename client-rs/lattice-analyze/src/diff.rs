@@ -0,0 +1,418 @@
+use crate::{fmt_opt, haversine_km};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const DEFAULT_MIN_DELTA_MS: f64 = 0.5;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lattice-analyze diff",
+    about = "Compare two `--json` analysis outputs: estimates, verdicts, and endpoint stats"
+)]
+pub struct DiffArgs {
+    /// Earlier (or reference) analysis.
+    a: PathBuf,
+
+    /// Later (or other party's) analysis.
+    b: PathBuf,
+
+    /// Endpoint p05 changes smaller than this are left out of the text output.
+    #[arg(long, default_value_t = DEFAULT_MIN_DELTA_MS)]
+    min_delta_ms: f64,
+
+    #[arg(long)]
+    json: bool,
+}
+
+/// The parts of an `AnalysisOutput` that `diff` reads. Everything is optional
+/// so outputs from older or newer analyzers still load.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct AnalysisView {
+    params: BTreeMap<String, Value>,
+    session: Option<SessionView>,
+    baseline: Option<SessionView>,
+    claim_checks: Option<Vec<ClaimView>>,
+    classification: Option<ClassificationView>,
+    identity_warnings: Option<Vec<Value>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct SessionView {
+    records: usize,
+    endpoint_stats: Vec<EndpointView>,
+    estimate: Option<EstimateView>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct EndpointView {
+    id: String,
+    p05_ms: Option<f64>,
+    p50_ms: Option<f64>,
+    max_dist_km_tight: Option<f64>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EstimateView {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct ClaimView {
+    id: String,
+    falsify_tight: Option<bool>,
+    falsify_loose: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct ClassificationView {
+    classes: Vec<ClassScoreView>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ClassScoreView {
+    label: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParamChange {
+    pub name: String,
+    pub a: Value,
+    pub b: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointChange {
+    pub id: String,
+    pub p05_ms_a: Option<f64>,
+    pub p05_ms_b: Option<f64>,
+    pub delta_p05_ms: Option<f64>,
+    pub delta_p50_ms: Option<f64>,
+    pub max_dist_km_tight_a: Option<f64>,
+    pub max_dist_km_tight_b: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionDiff {
+    pub label: String,
+    pub records_a: usize,
+    pub records_b: usize,
+    pub estimate_a: Option<EstimateView>,
+    pub estimate_b: Option<EstimateView>,
+    pub estimate_shift_km: Option<f64>,
+    pub endpoints_only_a: Vec<String>,
+    pub endpoints_only_b: Vec<String>,
+    pub endpoints: Vec<EndpointChange>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerdictChange {
+    pub id: String,
+    pub bound: &'static str,
+    pub falsified_a: Option<bool>,
+    pub falsified_b: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffReport {
+    pub params: Vec<ParamChange>,
+    pub sessions: Vec<SessionDiff>,
+    pub verdicts: Vec<VerdictChange>,
+    pub top_class_a: Option<String>,
+    pub top_class_b: Option<String>,
+    pub identity_warnings_a: usize,
+    pub identity_warnings_b: usize,
+}
+
+fn load_view(path: &PathBuf) -> io::Result<AnalysisView> {
+    let data = fs::read(path)?;
+    serde_json::from_slice(&data).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: not an analysis --json output: {}", path.display(), e),
+        )
+    })
+}
+
+fn sub(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    Some(b? - a?)
+}
+
+fn endpoints_by_id(s: &SessionView) -> BTreeMap<String, &EndpointView> {
+    s.endpoint_stats.iter().map(|e| (e.id.clone(), e)).collect()
+}
+
+fn diff_session(
+    label: &str,
+    a: Option<&SessionView>,
+    b: Option<&SessionView>,
+) -> Option<SessionDiff> {
+    if a.is_none() && b.is_none() {
+        return None;
+    }
+    let empty = SessionView::default();
+    let (a, b) = (a.unwrap_or(&empty), b.unwrap_or(&empty));
+    let (ea, eb) = (endpoints_by_id(a), endpoints_by_id(b));
+    let ids_a: BTreeSet<&String> = ea.keys().collect();
+    let ids_b: BTreeSet<&String> = eb.keys().collect();
+    let endpoints = ids_a
+        .intersection(&ids_b)
+        .map(|id| {
+            let (x, y) = (ea[*id], eb[*id]);
+            EndpointChange {
+                id: (*id).clone(),
+                p05_ms_a: x.p05_ms,
+                p05_ms_b: y.p05_ms,
+                delta_p05_ms: sub(x.p05_ms, y.p05_ms),
+                delta_p50_ms: sub(x.p50_ms, y.p50_ms),
+                max_dist_km_tight_a: x.max_dist_km_tight,
+                max_dist_km_tight_b: y.max_dist_km_tight,
+            }
+        })
+        .collect();
+    let estimate_shift_km = match (a.estimate, b.estimate) {
+        (Some(x), Some(y)) => Some(haversine_km(x.lat, x.lon, y.lat, y.lon)),
+        _ => None,
+    };
+    Some(SessionDiff {
+        label: label.to_string(),
+        records_a: a.records,
+        records_b: b.records,
+        estimate_a: a.estimate,
+        estimate_b: b.estimate,
+        estimate_shift_km,
+        endpoints_only_a: ids_a.difference(&ids_b).map(|s| (*s).clone()).collect(),
+        endpoints_only_b: ids_b.difference(&ids_a).map(|s| (*s).clone()).collect(),
+        endpoints,
+    })
+}
+
+fn diff_verdicts(a: &[ClaimView], b: &[ClaimView]) -> Vec<VerdictChange> {
+    let by_id = |checks: &[ClaimView]| -> BTreeMap<String, (Option<bool>, Option<bool>)> {
+        checks
+            .iter()
+            .map(|c| (c.id.clone(), (c.falsify_tight, c.falsify_loose)))
+            .collect()
+    };
+    let (va, vb) = (by_id(a), by_id(b));
+    let ids: BTreeSet<&String> = va.keys().chain(vb.keys()).collect();
+    let mut out = Vec::new();
+    for id in ids {
+        let x = va.get(id).copied().unwrap_or_default();
+        let y = vb.get(id).copied().unwrap_or_default();
+        for (bound, fa, fb) in [("tight", x.0, y.0), ("loose", x.1, y.1)] {
+            if fa != fb {
+                out.push(VerdictChange {
+                    id: id.clone(),
+                    bound,
+                    falsified_a: fa,
+                    falsified_b: fb,
+                });
+            }
+        }
+    }
+    out
+}
+
+pub(crate) fn diff_views(a: &AnalysisView, b: &AnalysisView) -> DiffReport {
+    let names: BTreeSet<&String> = a.params.keys().chain(b.params.keys()).collect();
+    let params = names
+        .into_iter()
+        .filter_map(|name| {
+            let x = a.params.get(name).cloned().unwrap_or(Value::Null);
+            let y = b.params.get(name).cloned().unwrap_or(Value::Null);
+            (x != y).then(|| ParamChange {
+                name: name.clone(),
+                a: x,
+                b: y,
+            })
+        })
+        .collect();
+    let sessions = [
+        diff_session("session", a.session.as_ref(), b.session.as_ref()),
+        diff_session("baseline", a.baseline.as_ref(), b.baseline.as_ref()),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    let top = |v: &AnalysisView| {
+        v.classification
+            .as_ref()
+            .and_then(|c| c.classes.first())
+            .map(|c| c.label.clone())
+    };
+    DiffReport {
+        params,
+        sessions,
+        verdicts: diff_verdicts(
+            a.claim_checks.as_deref().unwrap_or_default(),
+            b.claim_checks.as_deref().unwrap_or_default(),
+        ),
+        top_class_a: top(a),
+        top_class_b: top(b),
+        identity_warnings_a: a.identity_warnings.as_ref().map_or(0, Vec::len),
+        identity_warnings_b: b.identity_warnings.as_ref().map_or(0, Vec::len),
+    }
+}
+
+fn falsified(v: Option<bool>) -> &'static str {
+    match v {
+        Some(true) => "falsified",
+        Some(false) => "ok",
+        None => "-",
+    }
+}
+
+fn print_report(report: &DiffReport, min_delta_ms: f64) {
+    if !report.params.is_empty() {
+        println!("Parameters:");
+        for p in &report.params {
+            println!("- {}: {} -> {}", p.name, p.a, p.b);
+        }
+    }
+    for s in &report.sessions {
+        println!(
+            "\n{} ({} -> {} records):",
+            s.label, s.records_a, s.records_b
+        );
+        let fmt = |e: Option<EstimateView>| {
+            e.map_or("none".to_string(), |e| format!("{:.4},{:.4}", e.lat, e.lon))
+        };
+        match s.estimate_shift_km {
+            Some(km) => println!(
+                "- estimate {} -> {} (moved {:.1} km)",
+                fmt(s.estimate_a),
+                fmt(s.estimate_b),
+                km
+            ),
+            None => println!("- estimate {} -> {}", fmt(s.estimate_a), fmt(s.estimate_b)),
+        }
+        for id in &s.endpoints_only_a {
+            println!("- {} only in a", id);
+        }
+        for id in &s.endpoints_only_b {
+            println!("- {} only in b", id);
+        }
+        let changed = s
+            .endpoints
+            .iter()
+            .filter(|e| e.delta_p05_ms.is_some_and(|d| d.abs() >= min_delta_ms));
+        for e in changed {
+            println!(
+                "- {} p05 {} -> {} ms ({:+.2}), max_dist_km_tight {} -> {}",
+                e.id,
+                fmt_opt(e.p05_ms_a, 2),
+                fmt_opt(e.p05_ms_b, 2),
+                e.delta_p05_ms.unwrap_or(0.0),
+                fmt_opt(e.max_dist_km_tight_a, 1),
+                fmt_opt(e.max_dist_km_tight_b, 1)
+            );
+        }
+    }
+    if !report.verdicts.is_empty() {
+        println!("\nClaim verdict changes:");
+        for v in &report.verdicts {
+            println!(
+                "- {} {}: {} -> {}",
+                v.id,
+                v.bound,
+                falsified(v.falsified_a),
+                falsified(v.falsified_b)
+            );
+        }
+    }
+    if report.top_class_a != report.top_class_b {
+        println!(
+            "\nTop classification: {} -> {}",
+            report.top_class_a.as_deref().unwrap_or("-"),
+            report.top_class_b.as_deref().unwrap_or("-")
+        );
+    }
+    if report.identity_warnings_a != report.identity_warnings_b {
+        println!(
+            "\nIdentity warnings: {} -> {}",
+            report.identity_warnings_a, report.identity_warnings_b
+        );
+    }
+}
+
+pub fn run(args: DiffArgs) -> io::Result<()> {
+    let a = load_view(&args.a)?;
+    let b = load_view(&args.b)?;
+    let report = diff_views(&a, &b);
+    if args.json {
+        let text = serde_json::to_string_pretty(&report).map_err(io::Error::other)?;
+        println!("{text}");
+    } else {
+        println!("{} -> {}", args.a.display(), args.b.display());
+        print_report(&report, args.min_delta_ms);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_reports_estimate_shift_stat_and_verdict_changes() {
+        let a: AnalysisView = serde_json::from_str(
+            r#"{
+                "params": {"speedKmS": 200000.0, "gridDeg": 1.0},
+                "session": {
+                    "records": 10,
+                    "endpointStats": [
+                        {"id": "fra", "p05Ms": 10.0, "p50Ms": 11.0},
+                        {"id": "old", "p05Ms": 5.0}
+                    ],
+                    "estimate": {"lat": 50.0, "lon": 8.0}
+                },
+                "claimChecks": [{"id": "fra", "falsifyTight": false, "falsifyLoose": false}]
+            }"#,
+        )
+        .unwrap();
+        let b: AnalysisView = serde_json::from_str(
+            r#"{
+                "params": {"speedKmS": 200000.0, "gridDeg": 0.5},
+                "session": {
+                    "records": 12,
+                    "endpointStats": [{"id": "fra", "p05Ms": 12.5, "p50Ms": 11.0}],
+                    "estimate": {"lat": 51.0, "lon": 8.0}
+                },
+                "claimChecks": [{"id": "fra", "falsifyTight": true, "falsifyLoose": false}],
+                "futureField": {"ignored": true}
+            }"#,
+        )
+        .unwrap();
+        let report = diff_views(&a, &b);
+        assert_eq!(report.params.len(), 1);
+        assert_eq!(report.params[0].name, "gridDeg");
+        let session = &report.sessions[0];
+        assert!(
+            (session.estimate_shift_km.unwrap() - haversine_km(50.0, 8.0, 51.0, 8.0)).abs() < 1e-9
+        );
+        assert_eq!(session.endpoints_only_a, vec!["old".to_string()]);
+        assert_eq!(session.endpoints[0].delta_p05_ms, Some(2.5));
+        assert_eq!(session.endpoints[0].delta_p50_ms, Some(0.0));
+        assert_eq!(report.verdicts.len(), 1);
+        assert_eq!(report.verdicts[0].bound, "tight");
+        assert_eq!(report.verdicts[0].falsified_b, Some(true));
+    }
+}
@@ -0,0 +1,186 @@
+use crate::quantile;
+use crate::units::ReportFormat;
+use lattice_core::BurstRecord;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FlowRtt {
+    /// Null when the records didn't vary the source port.
+    pub port: Option<u16>,
+    /// Null when the records didn't vary the IPv6 flow label.
+    pub flow_label: Option<u32>,
+    pub samples: usize,
+    pub min_ms: Option<f64>,
+    pub p50_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct EndpointEcmp {
+    pub endpoint: String,
+    /// One per source port and flow label seen, ordered by `minMs`, fastest
+    /// first.
+    pub flows: Vec<FlowRtt>,
+    /// Slowest flow's minimum minus the fastest's: path diversity that a
+    /// single socket would have hidden in one of them.
+    pub min_spread_ms: Option<f64>,
+    pub p50_spread_ms: Option<f64>,
+}
+
+fn spread(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let values: Vec<f64> = values.collect();
+    let lo = values.iter().copied().min_by(f64::total_cmp)?;
+    let hi = values.iter().copied().max_by(f64::total_cmp)?;
+    Some(hi - lo)
+}
+
+/// Per-sample values of `field` when it lines up with `samplesMs`.
+fn per_sample<T: Copy>(field: &[T], samples: usize) -> Option<&[T]> {
+    (!field.is_empty() && field.len() == samples).then_some(field)
+}
+
+/// Groups each endpoint's samples by the flow they went out on: source port
+/// and IPv6 flow label, whichever the records vary. Records with neither
+/// `sampleSourcePorts` nor `sampleFlowLabels` are skipped, and endpoints
+/// seen on a single flow are left out.
+pub(crate) fn path_diversity(records: &[BurstRecord]) -> Vec<EndpointEcmp> {
+    type Flow = (Option<u16>, Option<u32>);
+    let mut by_endpoint: BTreeMap<&str, BTreeMap<Flow, Vec<f64>>> = BTreeMap::new();
+    for rec in records {
+        let n = rec.samples_ms.len();
+        let ports = per_sample(&rec.sample_source_ports, n);
+        let labels = per_sample(&rec.sample_flow_labels, n);
+        if ports.is_none() && labels.is_none() {
+            continue;
+        }
+        let flows = by_endpoint.entry(rec.endpoint_id.as_str()).or_default();
+        for (i, &rtt) in rec.samples_ms.iter().enumerate() {
+            if rtt.is_finite() && rtt >= 0.0 {
+                let flow = (ports.map(|p| p[i]), labels.map(|l| l[i]));
+                flows.entry(flow).or_default().push(rtt);
+            }
+        }
+    }
+    by_endpoint
+        .into_iter()
+        .filter(|(_, flows)| flows.len() > 1)
+        .map(|(id, flows)| {
+            let mut flows: Vec<FlowRtt> = flows
+                .into_iter()
+                .map(|((port, flow_label), mut rtts)| {
+                    rtts.sort_by(f64::total_cmp);
+                    FlowRtt {
+                        port,
+                        flow_label,
+                        samples: rtts.len(),
+                        min_ms: rtts.first().copied(),
+                        p50_ms: quantile(&rtts, 0.5),
+                    }
+                })
+                .collect();
+            flows.sort_by(|a, b| {
+                a.min_ms
+                    .unwrap_or(f64::INFINITY)
+                    .total_cmp(&b.min_ms.unwrap_or(f64::INFINITY))
+            });
+            EndpointEcmp {
+                endpoint: id.to_string(),
+                min_spread_ms: spread(flows.iter().filter_map(|f| f.min_ms)),
+                p50_spread_ms: spread(flows.iter().filter_map(|f| f.p50_ms)),
+                flows,
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn print_path_diversity(label: &str, ecmp: &[EndpointEcmp], fmt: &ReportFormat) {
+    if ecmp.is_empty() {
+        return;
+    }
+    println!("\n{label} ECMP path diversity (RTT per source port / flow label, ms):");
+    for e in ecmp {
+        let flows: Vec<String> = e
+            .flows
+            .iter()
+            .map(|p| {
+                let flow = match (p.port, p.flow_label) {
+                    (Some(port), Some(fl)) => format!("{port}/fl{fl:05x}"),
+                    (Some(port), None) => port.to_string(),
+                    (None, Some(fl)) => format!("fl{fl:05x}"),
+                    (None, None) => "-".to_string(),
+                };
+                format!(
+                    "{}: min={} p50={} n={}",
+                    flow,
+                    fmt.ms_opt(p.min_ms),
+                    fmt.ms_opt(p.p50_ms),
+                    p.samples
+                )
+            })
+            .collect();
+        println!(
+            "- {} flows={} spread min={} p50={}",
+            e.endpoint,
+            e.flows.len(),
+            fmt.ms_opt(e.min_spread_ms),
+            fmt.ms_opt(e.p50_spread_ms)
+        );
+        println!("    {}", flows.join(" | "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn ecmp_report_spreads_rtts_by_source_port() {
+        let burst = |samples: Vec<f64>, ports: Vec<u16>| BurstRecord {
+            sample_source_ports: ports,
+            ..record("a", samples)
+        };
+        let records = vec![
+            burst(vec![20.0, 26.0, 21.0, 27.0], vec![4000, 4001, 4000, 4001]),
+            burst(vec![22.0, 25.0], vec![4000, 4001]),
+            // Ports that don't line up with the samples are ignored.
+            burst(vec![1.0, 1.0], vec![4000]),
+            // A single-port endpoint has no diversity to report.
+            BurstRecord {
+                sample_source_ports: vec![5000, 5000],
+                ..record("b", vec![30.0, 31.0])
+            },
+        ];
+        let ecmp = path_diversity(&records);
+        assert_eq!(ecmp.len(), 1);
+        let a = &ecmp[0];
+        assert_eq!(a.endpoint, "a");
+        let flows: Vec<(Option<u16>, usize, Option<f64>)> = a
+            .flows
+            .iter()
+            .map(|f| (f.port, f.samples, f.min_ms))
+            .collect();
+        assert_eq!(
+            flows,
+            vec![(Some(4000), 3, Some(20.0)), (Some(4001), 3, Some(25.0))]
+        );
+        assert_eq!(a.min_spread_ms, Some(5.0));
+        assert_eq!(a.p50_spread_ms, Some(5.0));
+
+        // IPv6 flow labels split a single socket's samples the same way.
+        let labelled = BurstRecord {
+            sample_flow_labels: vec![1, 2, 1, 2],
+            ..record("c", vec![40.0, 43.0, 41.0, 44.0])
+        };
+        let ecmp = path_diversity(&[labelled]);
+        let flows: Vec<(Option<u16>, Option<u32>)> = ecmp[0]
+            .flows
+            .iter()
+            .map(|f| (f.port, f.flow_label))
+            .collect();
+        assert_eq!(flows, vec![(None, Some(1)), (None, Some(2))]);
+        assert_eq!(ecmp[0].min_spread_ms, Some(3.0));
+    }
+}
@@ -0,0 +1,722 @@
+use crate::constants::*;
+use crate::fusion::TUNNEL_ACTIVE_SHARE;
+use crate::propagation::Linear;
+use crate::quality::{self, DataQuality, QualityInputs, QualityThresholds};
+use crate::units::{DistanceUnit, ReportFormat};
+use crate::{
+    build_stats, claim_checks, endpoints_by_id, estimate_location, finite, haversine_km,
+    load_calibration, load_clock_quality, load_jsonl, quantile, Calibration, ClaimCheck,
+    DistanceModel, SampleGate, WeightScheme, Weighting,
+};
+use crate::{redact, transport};
+use clap::{Parser, ValueEnum};
+use lattice_core::{
+    bytes_to_hex, hex_to_bytes, now_unix_ms, pseudonym, BurstRecord, ClockQuality, Config,
+    Endpoint, EventRecord, EVENT_RECORD_TYPE, SESSION_START_EVENT,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufRead};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+const FLEET_SCHEMA: &str = "lattice-fleet/1";
+const SESSION_EXTENSION: &str = "jsonl";
+/// Upper edges of the estimate-vs-claim histogram; the last bucket is open.
+const CLAIM_DISTANCE_BUCKETS_KM: [f64; 4] = [100.0, 500.0, 1000.0, 5000.0];
+/// Machines at this grade or worse are counted as low quality.
+const LOW_QUALITY_GRADE: char = 'C';
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lattice-analyze fleet",
+    about = "Roll up many probers' sessions into one report, one analysis per machine"
+)]
+pub struct FleetArgs {
+    #[arg(long)]
+    config: PathBuf,
+
+    #[arg(long = "override")]
+    overrides: Vec<PathBuf>,
+
+    /// Directory of session files (`*.jsonl`); sessions are grouped by the
+    /// `machineId` in their header.
+    #[arg(long)]
+    inputs: PathBuf,
+
+    /// Claimed location for every machine without an entry in `--claims`.
+    #[arg(long, requires = "claim_lon")]
+    claim_lat: Option<f64>,
+
+    #[arg(long, requires = "claim_lat")]
+    claim_lon: Option<f64>,
+
+    /// JSON object of machine id -> `{"lat": .., "lon": ..}`.
+    #[arg(long)]
+    claims: Option<PathBuf>,
+
+    #[arg(long)]
+    calibration: Option<PathBuf>,
+
+    /// Grade limits layered over the built-in data-quality thresholds.
+    #[arg(long)]
+    quality_thresholds: Option<PathBuf>,
+
+    #[arg(long, default_value_t = DEFAULT_GRID_DEG)]
+    grid: f64,
+
+    #[arg(long, default_value_t = DEFAULT_REFINE_DEG)]
+    refine: f64,
+
+    #[arg(long, default_value_t = DEFAULT_SPEED_KM_S)]
+    speed_km_s: f64,
+
+    #[arg(long, default_value_t = DEFAULT_PATH_STRETCH)]
+    path_stretch: f64,
+
+    #[arg(long)]
+    outlier_mad_k: Option<f64>,
+
+    #[arg(long, value_enum, default_value_t = WeightScheme::Jitter)]
+    weighting: WeightScheme,
+
+    #[arg(long, default_value_t = DEFAULT_RECENCY_HALF_LIFE_S)]
+    recency_half_life_s: f64,
+
+    #[arg(long, value_enum, default_value_t = DistanceModel::Sphere)]
+    distance_model: DistanceModel,
+
+    /// Who the report is for: `admin` sees machine ids and local IPs,
+    /// `auditor` pseudonymous ids and verdicts only.
+    #[arg(long, value_enum, default_value_t = Audience::Admin)]
+    audience: Audience,
+
+    /// Hex key for auditor pseudonyms. Reuse it to keep them stable across
+    /// reports; a random key is used when omitted.
+    #[arg(long)]
+    salt_hex: Option<String>,
+
+    /// Unit for distances in text output; JSON stays in km.
+    #[arg(long, value_enum, default_value_t = DistanceUnit::Km)]
+    units: DistanceUnit,
+
+    /// Locale for number separators in text output (default: `LC_ALL`,
+    /// `LC_NUMERIC`, or `LANG`).
+    #[arg(long)]
+    locale: Option<String>,
+
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Audience {
+    Admin,
+    Auditor,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct ClaimPoint {
+    lat: f64,
+    lon: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MachineReport {
+    pub machine_id: String,
+    /// Distinct local addresses the machine probed from.
+    pub local_ips: Vec<String>,
+    pub sessions: usize,
+    pub bursts: usize,
+    /// Share of bursts sent over a tunnel interface.
+    pub tunnel_share: Option<f64>,
+    /// `active`, `partial`, or `none`, as in the fusion score.
+    pub tunnel: &'static str,
+    pub estimate_lat: Option<f64>,
+    pub estimate_lon: Option<f64>,
+    pub claim_lat: Option<f64>,
+    pub claim_lon: Option<f64>,
+    pub estimate_claim_km: Option<f64>,
+    /// `inconsistent` when some anchor's p50 bound misses the claim,
+    /// `suspect` when only a p05 bound does, `consistent` otherwise; null
+    /// without a claim or a bounded anchor.
+    pub claim_verdict: Option<&'static str>,
+    pub quality: DataQuality,
+}
+
+/// What an auditor sees of a machine: a keyed pseudonym for its id and the
+/// verdicts, no addresses or coordinates.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AuditedMachine {
+    pub machine_id: String,
+    pub tunnel: &'static str,
+    pub claim_verdict: Option<&'static str>,
+    pub quality_grade: char,
+}
+
+/// One machine as the report's audience may see it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub(crate) enum MachineView {
+    Admin(MachineReport),
+    Auditor(AuditedMachine),
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DistanceBucket {
+    /// Null for the open last bucket.
+    pub up_to_km: Option<f64>,
+    pub machines: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FleetReport<M = MachineReport> {
+    pub schema: &'static str,
+    pub audience: Audience,
+    pub machines: usize,
+    pub sessions: usize,
+    pub tunnel_active: usize,
+    pub tunnel_partial: usize,
+    pub with_estimate: usize,
+    pub claim_inconsistent: usize,
+    pub claim_suspect: usize,
+    /// Machines whose data-quality grade is `C` or worse.
+    pub low_quality: usize,
+    /// Machines with both an estimate and a claim.
+    pub compared: usize,
+    pub estimate_claim_p50_km: Option<f64>,
+    pub estimate_claim_p90_km: Option<f64>,
+    pub estimate_claim_max_km: Option<f64>,
+    pub estimate_claim_buckets: Vec<DistanceBucket>,
+    pub per_machine: Vec<M>,
+}
+
+impl FleetReport {
+    /// The report as `audience` may see it. The fleet-wide counts are kept
+    /// as they are; each machine is rebuilt from only the fields the
+    /// audience is allowed, with `key` naming machines for auditors.
+    pub fn for_audience(self, audience: Audience, key: &[u8]) -> FleetReport<MachineView> {
+        let per_machine = self
+            .per_machine
+            .into_iter()
+            .map(|m| match audience {
+                Audience::Admin => MachineView::Admin(m),
+                Audience::Auditor => MachineView::Auditor(AuditedMachine {
+                    machine_id: pseudonym(key, &m.machine_id),
+                    tunnel: m.tunnel,
+                    claim_verdict: m.claim_verdict,
+                    quality_grade: m.quality.grade,
+                }),
+            })
+            .collect();
+        FleetReport {
+            schema: self.schema,
+            audience,
+            machines: self.machines,
+            sessions: self.sessions,
+            tunnel_active: self.tunnel_active,
+            tunnel_partial: self.tunnel_partial,
+            with_estimate: self.with_estimate,
+            claim_inconsistent: self.claim_inconsistent,
+            claim_suspect: self.claim_suspect,
+            low_quality: self.low_quality,
+            compared: self.compared,
+            estimate_claim_p50_km: self.estimate_claim_p50_km,
+            estimate_claim_p90_km: self.estimate_claim_p90_km,
+            estimate_claim_max_km: self.estimate_claim_max_km,
+            estimate_claim_buckets: self.estimate_claim_buckets,
+            per_machine,
+        }
+    }
+}
+
+/// `detail.machineId` of the session header, if the client wrote one.
+fn load_machine_id(path: &Path) -> io::Result<Option<String>> {
+    let reader = crate::open_log(path)?;
+    for line in reader.lines() {
+        let line = line?;
+        if !line.contains(SESSION_START_EVENT) {
+            continue;
+        }
+        let Ok(ev) = serde_json::from_str::<EventRecord>(&line) else {
+            continue;
+        };
+        if ev.record_type != EVENT_RECORD_TYPE || ev.event != SESSION_START_EVENT {
+            continue;
+        }
+        return Ok(ev
+            .detail
+            .get("machineId")
+            .and_then(|v| v.as_str())
+            .map(str::to_string));
+    }
+    Ok(None)
+}
+
+/// Session files under `dir` grouped by machine. A session from a client
+/// that wrote no `machineId` stands alone under `file:<name>`.
+fn group_sessions(dir: &Path) -> io::Result<BTreeMap<String, Vec<PathBuf>>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == SESSION_EXTENSION))
+        .collect();
+    paths.sort();
+    let mut groups: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for path in paths {
+        let id = match load_machine_id(&path)? {
+            Some(id) => id,
+            None => format!(
+                "file:{}",
+                path.file_name().unwrap_or_default().to_string_lossy()
+            ),
+        };
+        groups.entry(id).or_default().push(path);
+    }
+    Ok(groups)
+}
+
+/// Share of bursts sent over a tunnel interface, and `active` (at least
+/// `TUNNEL_ACTIVE_SHARE`), `partial`, or `none`, as in the fusion score.
+pub(crate) fn tunnel_state(records: &[BurstRecord]) -> (Option<f64>, &'static str) {
+    let tunneled = records
+        .iter()
+        .filter(|r| r.iface_is_tunnel || r.utun_active)
+        .count();
+    let share = (!records.is_empty()).then(|| tunneled as f64 / records.len() as f64);
+    let state = match share {
+        Some(share) if share >= TUNNEL_ACTIVE_SHARE => "active",
+        _ if tunneled > 0 => "partial",
+        _ => "none",
+    };
+    (share, state)
+}
+
+/// `inconsistent` when some anchor's p50 bound misses the claim, `suspect`
+/// when only a p05 bound does, `consistent` otherwise; `None` without a
+/// bounded anchor.
+pub(crate) fn claim_verdict(checks: &[ClaimCheck]) -> Option<&'static str> {
+    if checks.iter().any(|c| c.falsify_loose == Some(true)) {
+        Some("inconsistent")
+    } else if checks.iter().any(|c| c.falsify_tight == Some(true)) {
+        Some("suspect")
+    } else if checks.iter().any(|c| c.max_tight_km.is_some()) {
+        Some("consistent")
+    } else {
+        None
+    }
+}
+
+pub(crate) struct MachineAnalysis<'a> {
+    pub endpoints: &'a HashMap<String, Endpoint>,
+    pub gate: SampleGate,
+    pub outlier_mad_k: Option<f64>,
+    pub speed_km_s: f64,
+    pub grid: f64,
+    pub refine: f64,
+    pub calibration: Option<&'a Calibration>,
+    pub weighting: Weighting,
+    pub distance: DistanceModel,
+    pub quality: &'a QualityThresholds,
+    /// `samplesPerEndpoint` from the config, for the loss factor.
+    pub samples_per_burst: usize,
+}
+
+impl MachineAnalysis<'_> {
+    /// The main analyzer's estimate and claim check over every burst the
+    /// machine recorded, plus how much of it went over a tunnel and how far
+    /// the data can be trusted. `clock` is the latest session's self-test.
+    pub fn analyze(
+        &self,
+        machine_id: &str,
+        sessions: usize,
+        records: &mut [BurstRecord],
+        claim: Option<(f64, f64)>,
+        clock: Option<&ClockQuality>,
+    ) -> MachineReport {
+        let bursts = records.len();
+        let local_ips: BTreeSet<String> = records
+            .iter()
+            .filter_map(|r| r.local_addr.parse::<SocketAddr>().ok())
+            .map(|a| a.ip().to_string())
+            .collect();
+        let (tunnel_share, tunnel) = tunnel_state(records);
+        transport::correct_transport_bias(records, self.calibration, true);
+        let mut stats = build_stats(records, self.outlier_mad_k);
+        self.gate.apply(&mut stats);
+        let estimate = estimate_location(
+            &stats,
+            self.endpoints,
+            &Linear {
+                speed_km_s: self.speed_km_s,
+            },
+            self.grid,
+            self.refine,
+            DEFAULT_BAND_FACTOR,
+            DEFAULT_BAND_WINDOW_DEG,
+            0.0,
+            self.calibration,
+            self.weighting,
+            self.distance,
+        );
+        let claim_verdict = claim.and_then(|(lat, lon)| {
+            claim_verdict(&claim_checks(
+                &stats,
+                self.endpoints,
+                lat,
+                lon,
+                &Linear {
+                    speed_km_s: self.speed_km_s,
+                },
+                self.calibration,
+                self.distance,
+            ))
+        });
+        let estimate_claim_km = match (&estimate, claim) {
+            (Some(est), Some((lat, lon))) => finite(haversine_km(est.lat, est.lon, lat, lon)),
+            _ => None,
+        };
+        let quality = quality::assess(
+            self.quality,
+            &QualityInputs {
+                stats: &stats,
+                endpoints: self.endpoints,
+                records,
+                samples_per_burst: self.samples_per_burst,
+                at: estimate.as_ref().map(|e| (e.lat, e.lon)).or(claim),
+                calibration: self.calibration,
+                clock,
+                now_ms: now_unix_ms(),
+            },
+        );
+        MachineReport {
+            machine_id: machine_id.to_string(),
+            local_ips: local_ips.into_iter().collect(),
+            sessions,
+            bursts,
+            tunnel_share,
+            tunnel,
+            estimate_lat: estimate.as_ref().map(|e| e.lat),
+            estimate_lon: estimate.as_ref().map(|e| e.lon),
+            claim_lat: claim.map(|c| c.0),
+            claim_lon: claim.map(|c| c.1),
+            estimate_claim_km,
+            claim_verdict,
+            quality,
+        }
+    }
+}
+
+/// Fleet-wide counts and the spread of estimate-vs-claim distances.
+pub(crate) fn roll_up(per_machine: Vec<MachineReport>) -> FleetReport {
+    let count = |f: &dyn Fn(&MachineReport) -> bool| per_machine.iter().filter(|m| f(m)).count();
+    let mut distances: Vec<f64> = per_machine
+        .iter()
+        .filter_map(|m| m.estimate_claim_km)
+        .collect();
+    distances.sort_by(f64::total_cmp);
+    let mut buckets: Vec<DistanceBucket> = CLAIM_DISTANCE_BUCKETS_KM
+        .iter()
+        .map(|&km| Some(km))
+        .chain([None])
+        .map(|up_to_km| DistanceBucket {
+            up_to_km,
+            machines: 0,
+        })
+        .collect();
+    for d in &distances {
+        let idx = CLAIM_DISTANCE_BUCKETS_KM
+            .iter()
+            .position(|&edge| *d < edge)
+            .unwrap_or(CLAIM_DISTANCE_BUCKETS_KM.len());
+        buckets[idx].machines += 1;
+    }
+    FleetReport {
+        schema: FLEET_SCHEMA,
+        audience: Audience::Admin,
+        machines: per_machine.len(),
+        sessions: per_machine.iter().map(|m| m.sessions).sum(),
+        tunnel_active: count(&|m| m.tunnel == "active"),
+        tunnel_partial: count(&|m| m.tunnel == "partial"),
+        with_estimate: count(&|m| m.estimate_lat.is_some()),
+        claim_inconsistent: count(&|m| m.claim_verdict == Some("inconsistent")),
+        claim_suspect: count(&|m| m.claim_verdict == Some("suspect")),
+        low_quality: count(&|m| m.quality.grade >= LOW_QUALITY_GRADE),
+        compared: distances.len(),
+        estimate_claim_p50_km: quantile(&distances, 0.5),
+        estimate_claim_p90_km: quantile(&distances, 0.9),
+        estimate_claim_max_km: distances.last().copied(),
+        estimate_claim_buckets: buckets,
+        per_machine,
+    }
+}
+
+fn print_report(report: &FleetReport<MachineView>, fmt: &ReportFormat) {
+    let unit = fmt.unit.label();
+    println!(
+        "Fleet: {} machine(s), {} session(s)",
+        report.machines, report.sessions
+    );
+    println!(
+        "- tunnel: {} active, {} partial",
+        report.tunnel_active, report.tunnel_partial
+    );
+    println!(
+        "- estimates: {} of {}; compared with a claim: {}",
+        report.with_estimate, report.machines, report.compared
+    );
+    if report.compared > 0 {
+        println!(
+            "- estimate vs claim {unit}: p50={} p90={} max={}",
+            fmt.dist_opt(report.estimate_claim_p50_km, 1),
+            fmt.dist_opt(report.estimate_claim_p90_km, 1),
+            fmt.dist_opt(report.estimate_claim_max_km, 1)
+        );
+        let buckets: Vec<String> = report
+            .estimate_claim_buckets
+            .iter()
+            .map(|b| match b.up_to_km {
+                Some(km) => format!("<{}: {}", fmt.dist(km, 0), b.machines),
+                None => format!("more: {}", b.machines),
+            })
+            .collect();
+        println!("    {}", buckets.join(" | "));
+    }
+    println!(
+        "- claim ruled out by RTT: {} inconsistent, {} suspect",
+        report.claim_inconsistent, report.claim_suspect
+    );
+    println!("- data quality C or worse: {}", report.low_quality);
+    println!("Per machine:");
+    for view in &report.per_machine {
+        let m = match view {
+            MachineView::Admin(m) => m,
+            MachineView::Auditor(m) => {
+                println!(
+                    "- {} tunnel={} claim={} quality={}",
+                    m.machine_id,
+                    m.tunnel,
+                    m.claim_verdict.unwrap_or("-"),
+                    m.quality_grade
+                );
+                continue;
+            }
+        };
+        let estimate = match (m.estimate_lat, m.estimate_lon) {
+            (Some(lat), Some(lon)) => format!(
+                "{}{}{}",
+                fmt.num(lat, 2),
+                fmt.list_separator(),
+                fmt.num(lon, 2)
+            ),
+            _ => "-".to_string(),
+        };
+        let ips = if m.local_ips.is_empty() {
+            "-".to_string()
+        } else {
+            m.local_ips.join(",")
+        };
+        println!(
+            "- {} ips={} sessions={} bursts={} tunnel={} est={} claim_{unit}={} claim={} quality={}",
+            m.machine_id,
+            ips,
+            m.sessions,
+            m.bursts,
+            m.tunnel,
+            estimate,
+            fmt.dist_opt(m.estimate_claim_km, 1),
+            m.claim_verdict.unwrap_or("-"),
+            m.quality.grade
+        );
+    }
+}
+
+pub fn run(args: FleetArgs) -> io::Result<()> {
+    let cfg = Config::load_layered(&args.config, &args.overrides)?;
+    let key = match &args.salt_hex {
+        Some(hex) => {
+            hex_to_bytes(hex).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+        }
+        None => redact::random_key(),
+    };
+    let endpoints = endpoints_by_id(&cfg.endpoints);
+    let calibration = match &args.calibration {
+        Some(path) => Some(load_calibration(path)?),
+        None => None,
+    };
+    let claims: BTreeMap<String, ClaimPoint> = match &args.claims {
+        Some(path) => serde_json::from_reader(File::open(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+        None => BTreeMap::new(),
+    };
+    let thresholds = QualityThresholds::load_or_default(args.quality_thresholds.as_deref())?;
+    let default_claim = args.claim_lat.zip(args.claim_lon);
+    let path_stretch = args.path_stretch.max(MIN_PATH_STRETCH);
+    let analysis = MachineAnalysis {
+        endpoints: &endpoints,
+        gate: SampleGate {
+            min_samples: cfg.min_samples_per_endpoint,
+            min_bursts: cfg.min_bursts_per_endpoint,
+        },
+        outlier_mad_k: args.outlier_mad_k,
+        speed_km_s: args.speed_km_s / path_stretch,
+        grid: args.grid,
+        refine: args.refine,
+        calibration: calibration.as_ref(),
+        weighting: Weighting {
+            scheme: args.weighting,
+            recency_half_life_s: args.recency_half_life_s,
+        },
+        distance: args.distance_model,
+        quality: &thresholds,
+        samples_per_burst: cfg.samples_per_endpoint,
+    };
+
+    let mut per_machine = Vec::new();
+    for (machine_id, paths) in group_sessions(&args.inputs)? {
+        let mut records = Vec::new();
+        let mut clock = None;
+        for path in &paths {
+            records.extend(load_jsonl(path)?);
+            clock = load_clock_quality(path)?.or(clock);
+        }
+        let claim = claims
+            .get(&machine_id)
+            .map(|c| (c.lat, c.lon))
+            .or(default_claim);
+        per_machine.push(analysis.analyze(
+            &machine_id,
+            paths.len(),
+            &mut records,
+            claim,
+            clock.as_ref(),
+        ));
+    }
+    let report = roll_up(per_machine).for_audience(args.audience, &key);
+
+    if args.json {
+        let text = serde_json::to_string_pretty(&report).map_err(io::Error::other)?;
+        println!("{text}");
+    } else {
+        let fmt = ReportFormat::new(args.units, args.locale.as_deref(), DEFAULT_DISPLAY_DECIMALS);
+        print_report(&report, &fmt);
+    }
+    if args.audience == Audience::Auditor && args.salt_hex.is_none() {
+        eprintln!(
+            "pseudonym key (keep private; pass as --salt-hex to reuse pseudonyms): {}",
+            bytes_to_hex(&key)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn fleet_rolls_up_tunnels_and_claim_distances_per_machine() {
+        let endpoints = endpoints_by_id(&[endpoint("a", 0.0, 0.0)]);
+        let analysis = MachineAnalysis {
+            endpoints: &endpoints,
+            gate: SampleGate {
+                min_samples: 0,
+                min_bursts: 0,
+            },
+            outlier_mad_k: None,
+            speed_km_s: DEFAULT_SPEED_KM_S,
+            grid: DEFAULT_GRID_DEG,
+            refine: DEFAULT_REFINE_DEG,
+            calibration: None,
+            weighting: Weighting::default(),
+            distance: DistanceModel::Sphere,
+            quality: &quality::QualityThresholds::default(),
+            samples_per_burst: 1,
+        };
+        let tunneled = BurstRecord {
+            utun_active: true,
+            ..record("a", vec![10.0])
+        };
+        let mut records = vec![tunneled.clone(), tunneled, record("a", vec![10.0])];
+        let m = analysis.analyze("m1", 2, &mut records, Some((0.0, 0.0)), None);
+        assert_eq!((m.bursts, m.tunnel), (3, "active"));
+        assert_eq!(m.quality.grade, 'D');
+        assert_eq!(m.claim_verdict, Some("consistent"));
+        // One anchor can't place the machine.
+        assert_eq!(m.estimate_claim_km, None);
+
+        let placed = |id: &str, km: Option<f64>, tunnel: &'static str| MachineReport {
+            machine_id: id.to_string(),
+            sessions: 1,
+            estimate_claim_km: km,
+            tunnel,
+            ..m.clone()
+        };
+        let report = roll_up(vec![
+            m.clone(),
+            placed("m2", Some(50.0), "none"),
+            placed("m3", Some(700.0), "partial"),
+            placed("m4", Some(9000.0), "none"),
+        ]);
+        assert_eq!((report.machines, report.sessions), (4, 5));
+        assert_eq!(
+            (report.tunnel_active, report.tunnel_partial, report.compared),
+            (1, 1, 3)
+        );
+        assert_eq!(report.estimate_claim_p50_km, Some(700.0));
+        assert_eq!(report.estimate_claim_max_km, Some(9000.0));
+        let buckets: Vec<usize> = report
+            .estimate_claim_buckets
+            .iter()
+            .map(|b| b.machines)
+            .collect();
+        assert_eq!(buckets, vec![1, 0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn fleet_auditor_view_keeps_verdicts_and_pseudonymizes_machines() {
+        let machine = MachineReport {
+            machine_id: "host-1".to_string(),
+            local_ips: vec!["10.0.0.5".to_string()],
+            sessions: 2,
+            bursts: 40,
+            tunnel_share: Some(1.0),
+            tunnel: "active",
+            estimate_lat: Some(52.5),
+            estimate_lon: Some(13.4),
+            claim_lat: Some(48.9),
+            claim_lon: Some(2.4),
+            estimate_claim_km: Some(877.0),
+            claim_verdict: Some("inconsistent"),
+            quality: quality::DataQuality {
+                grade: 'B',
+                factors: Vec::new(),
+            },
+        };
+        let key = b"fleet-key";
+        let admin = roll_up(vec![machine.clone()]).for_audience(Audience::Admin, key);
+        let admin_json = serde_json::to_value(&admin).unwrap();
+        assert_eq!(admin_json["audience"], "admin");
+        assert_eq!(admin_json["perMachine"][0]["machineId"], "host-1");
+        assert_eq!(admin_json["perMachine"][0]["localIps"][0], "10.0.0.5");
+
+        let auditor = roll_up(vec![machine]).for_audience(Audience::Auditor, key);
+        assert_eq!((auditor.machines, auditor.claim_inconsistent), (1, 1));
+        let json = serde_json::to_value(&auditor).unwrap();
+        let m = json["perMachine"][0].as_object().unwrap();
+        let mut keys: Vec<&str> = m.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec!["claimVerdict", "machineId", "qualityGrade", "tunnel"]
+        );
+        assert_eq!(m["machineId"], lattice_core::pseudonym(key, "host-1"));
+        assert_eq!(m["claimVerdict"], "inconsistent");
+    }
+}
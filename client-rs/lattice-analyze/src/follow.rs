@@ -0,0 +1,220 @@
+//! `--follow`: tails a session the client is still writing and re-emits the
+//! estimate and claim checks every N new bursts, for checking a counterparty
+//! live instead of after the capture ends.
+
+use crate::fleet::claim_verdict;
+use crate::propagation::PropagationModel;
+use crate::units::ReportFormat;
+use crate::{
+    bufferbloat, capture_stats, claim_checks, estimate_location, print_claim_checks,
+    print_estimate, Args, Calibration, ClaimCheck, Estimate, SampleGate, Weighting,
+};
+use lattice_core::{now_unix_ms, BurstRecord, Endpoint};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// How often the session file is checked for new lines.
+const POLL: Duration = Duration::from_secs(1);
+
+/// New complete lines of a growing file. A line still being written stays
+/// buffered until its newline arrives.
+pub(crate) struct Tail {
+    path: PathBuf,
+    offset: u64,
+    partial: Vec<u8>,
+}
+
+impl Tail {
+    pub(crate) fn new(path: &Path) -> Self {
+        Tail {
+            path: path.to_path_buf(),
+            offset: 0,
+            partial: Vec::new(),
+        }
+    }
+
+    /// Lines appended since the last call. A file shorter than what was
+    /// already read was rotated or truncated, and is read from its start.
+    pub(crate) fn poll(&mut self) -> io::Result<Vec<String>> {
+        let mut file = File::open(&self.path)?;
+        let len = file.metadata()?.len();
+        if len < self.offset {
+            self.offset = 0;
+            self.partial.clear();
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut chunk = Vec::new();
+        file.take(len - self.offset).read_to_end(&mut chunk)?;
+        self.offset += chunk.len() as u64;
+        self.partial.extend_from_slice(&chunk);
+        let Some(end) = self.partial.iter().rposition(|&b| b == b'\n') else {
+            return Ok(Vec::new());
+        };
+        let complete: Vec<u8> = self.partial.drain(..=end).collect();
+        Ok(String::from_utf8_lossy(&complete)
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+}
+
+/// One `--json` line per update.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FollowUpdate {
+    ts_unix_ms: i64,
+    records: usize,
+    newest_burst_unix_ms: Option<i64>,
+    estimate: Option<Estimate>,
+    claim_checks: Option<Vec<ClaimCheck>>,
+    claim_verdict: Option<&'static str>,
+}
+
+/// Everything fixed for the run; only the records grow.
+pub(crate) struct Follow<'a> {
+    pub args: &'a Args,
+    pub endpoints: &'a HashMap<String, Endpoint>,
+    pub model: &'a dyn PropagationModel,
+    pub calibration: Option<&'a Calibration>,
+    pub gate: SampleGate,
+    pub weighting: Weighting,
+    pub every: usize,
+}
+
+impl Follow<'_> {
+    /// Reads what the session already holds, reports on it, then reports
+    /// again after every `every` new bursts until interrupted.
+    pub(crate) fn run(&self) -> io::Result<()> {
+        let path = &self.args.session;
+        if matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("zst" | "gz")
+        ) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--follow needs the live JSONL file, not a compressed archive",
+            ));
+        }
+        let fmt = ReportFormat::new(
+            self.args.units,
+            self.args.locale.as_deref(),
+            self.args.precision,
+        );
+        let mut tail = Tail::new(path);
+        let mut records: Vec<BurstRecord> = Vec::new();
+        let mut seen = HashSet::new();
+        let mut since_update = 0;
+        let mut first = true;
+        loop {
+            for line in tail.poll()? {
+                let Ok(rec) = serde_json::from_str::<BurstRecord>(&line) else {
+                    continue;
+                };
+                if !rec.burst_id.is_empty() && !seen.insert(rec.burst_id.clone()) {
+                    continue;
+                }
+                records.push(rec);
+                since_update += 1;
+            }
+            if (first && !records.is_empty()) || since_update >= self.every {
+                self.report(&records, &fmt)?;
+                since_update = 0;
+                first = false;
+            }
+            thread::sleep(POLL);
+        }
+    }
+
+    fn report(&self, records: &[BurstRecord], fmt: &ReportFormat) -> io::Result<()> {
+        let args = self.args;
+        let mut records = records.to_vec();
+        let (stats, _) = capture_stats(
+            &mut records,
+            args,
+            args.access_floor_ms,
+            self.gate,
+            self.calibration,
+        );
+        let margin_ms = bufferbloat::summarize(&records).map_or(0.0, |b| b.margin_ms);
+        let estimate = estimate_location(
+            &stats,
+            self.endpoints,
+            self.model,
+            args.grid,
+            args.refine,
+            args.band_factor,
+            args.band_window_deg,
+            margin_ms,
+            self.calibration,
+            self.weighting,
+            args.distance_model,
+        );
+        let checks = args.claim_lat.zip(args.claim_lon).map(|(lat, lon)| {
+            claim_checks(
+                &stats,
+                self.endpoints,
+                lat,
+                lon,
+                self.model,
+                self.calibration,
+                args.distance_model,
+            )
+        });
+        let update = FollowUpdate {
+            ts_unix_ms: now_unix_ms(),
+            records: records.len(),
+            newest_burst_unix_ms: records.iter().map(|r| r.ts_unix_ms).max(),
+            claim_verdict: checks.as_deref().and_then(claim_verdict),
+            estimate,
+            claim_checks: checks,
+        };
+        if args.json {
+            println!(
+                "{}",
+                serde_json::to_string(&update).map_err(io::Error::other)?
+            );
+            return Ok(());
+        }
+        println!("\n[{} records]", update.records);
+        match &update.estimate {
+            Some(est) => print_estimate(est, fmt),
+            None => println!("- estimate: insufficient endpoint data"),
+        }
+        if let Some(checks) = &update.claim_checks {
+            println!("Claim: {}", update.claim_verdict.unwrap_or("undetermined"));
+            print_claim_checks(checks, fmt);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn follow_tail_returns_complete_lines_and_restarts_after_rotation() {
+        use std::io::Write;
+        let path =
+            std::env::temp_dir().join(format!("lattice-follow-{}.jsonl", std::process::id()));
+        fs::write(&path, "a\nb").unwrap();
+        let mut tail = Tail::new(&path);
+        assert_eq!(tail.poll().unwrap(), vec!["a"]);
+        assert!(tail.poll().unwrap().is_empty());
+
+        let mut file = File::options().append(true).open(&path).unwrap();
+        file.write_all(b"c\n\nd\n").unwrap();
+        assert_eq!(tail.poll().unwrap(), vec!["bc", "d"]);
+
+        fs::write(&path, "e\n").unwrap();
+        assert_eq!(tail.poll().unwrap(), vec!["e"]);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,379 @@
+use crate::timezone::TimezoneCheck;
+use crate::units::ReportFormat;
+use crate::{finite, ClaimCheck};
+use lattice_core::BurstRecord;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const FUSION_SCHEMA: &str = "lattice-fusion/1";
+/// Neutral: with no evidence the score says nothing either way.
+const DEFAULT_PRIOR: f64 = 0.5;
+/// At least this share of bursts over a tunnel counts as probing through it.
+pub(crate) const TUNNEL_ACTIVE_SHARE: f64 = 0.5;
+
+/// Likelihood ratios, P(outcome | host is not where it appears or claims) over
+/// P(outcome | it is), keyed `signal.outcome`. Hand-set from how strongly each
+/// check separates the two cases; refit them on labelled captures for a
+/// calibrated score. `egressGeo` and `mtu` come from `--evidence` files.
+const DEFAULT_LIKELIHOOD_RATIOS: &[(&str, f64)] = &[
+    // A p50 bound that misses the claim is close to proof; a p05 miss alone
+    // can be a congested anchor.
+    ("rtt.inconsistent", 20.0),
+    ("rtt.suspect", 4.0),
+    ("rtt.consistent", 0.5),
+    // Tunnels are common for honest reasons, so they only lean.
+    ("tunnel.active", 3.0),
+    ("tunnel.partial", 1.5),
+    ("tunnel.none", 0.8),
+    // Timezones are user-set and travellers keep their home zone.
+    ("timezone.mismatch", 3.0),
+    ("timezone.consistent", 0.7),
+    ("egressGeo.mismatch", 5.0),
+    ("egressGeo.consistent", 0.6),
+    ("mtu.tunnel", 2.5),
+    ("mtu.clean", 0.9),
+];
+
+/// How signals combine: naive Bayes in log-odds. Each signal adds
+/// `weight * ln(likelihood ratio)` to the prior's log-odds; the score is the
+/// resulting probability that the host is not where it appears (or claims)
+/// to be.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FusionModel {
+    pub schema: String,
+    pub prior: f64,
+    /// Per-signal multiplier on its log-likelihood ratio; 0 turns a signal
+    /// off. Signals not listed weigh 1.
+    pub weights: BTreeMap<String, f64>,
+    pub likelihood_ratios: BTreeMap<String, f64>,
+}
+
+impl Default for FusionModel {
+    fn default() -> Self {
+        Self {
+            schema: FUSION_SCHEMA.to_string(),
+            prior: DEFAULT_PRIOR,
+            weights: BTreeMap::new(),
+            likelihood_ratios: DEFAULT_LIKELIHOOD_RATIOS
+                .iter()
+                .map(|(k, v)| (k.to_string(), *v))
+                .collect(),
+        }
+    }
+}
+
+/// A `--fusion-model` file: any field it sets replaces the default, and its
+/// maps are merged key by key over the default ones.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct FusionModelFile {
+    prior: Option<f64>,
+    weights: BTreeMap<String, f64>,
+    likelihood_ratios: BTreeMap<String, f64>,
+}
+
+impl FusionModel {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let file: FusionModelFile = serde_json::from_slice(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut model = Self::default();
+        if let Some(prior) = file.prior {
+            model.prior = prior;
+        }
+        model.weights.extend(file.weights);
+        model.likelihood_ratios.extend(file.likelihood_ratios);
+        model.validate()?;
+        Ok(model)
+    }
+
+    fn validate(&self) -> io::Result<()> {
+        let invalid = |msg: String| Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+        if !(self.prior > 0.0 && self.prior < 1.0) {
+            return invalid(format!(
+                "fusion prior must be between 0 and 1 (exclusive), got {}",
+                self.prior
+            ));
+        }
+        if let Some((k, w)) = self
+            .weights
+            .iter()
+            .find(|(_, w)| !w.is_finite() || **w < 0.0)
+        {
+            return invalid(format!(
+                "fusion weight {k} must be a finite number >= 0, got {w}"
+            ));
+        }
+        if let Some((k, lr)) = self
+            .likelihood_ratios
+            .iter()
+            .find(|(_, lr)| !lr.is_finite() || **lr <= 0.0)
+        {
+            return invalid(format!(
+                "likelihood ratio {k} must be a finite number > 0, got {lr}"
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// One observed signal outcome. `--evidence` files hold a JSON array of these;
+/// a `likelihoodRatio` given there overrides the model's for that outcome.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Evidence {
+    pub signal: String,
+    pub outcome: String,
+    #[serde(default)]
+    pub detail: String,
+    #[serde(default)]
+    pub likelihood_ratio: Option<f64>,
+}
+
+pub(crate) fn load_evidence(path: &Path) -> io::Result<Vec<Evidence>> {
+    let data = fs::read(path)?;
+    let items: Vec<Evidence> =
+        serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if let Some(e) = items.iter().find(|e| {
+        e.likelihood_ratio
+            .is_some_and(|lr| !lr.is_finite() || lr <= 0.0)
+    }) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{}: likelihoodRatio for {}.{} must be > 0",
+                path.display(),
+                e.signal,
+                e.outcome
+            ),
+        ));
+    }
+    Ok(items)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Contribution {
+    pub signal: String,
+    pub outcome: String,
+    pub detail: String,
+    /// `None` when neither the model nor the evidence gives one; the signal
+    /// then contributes nothing.
+    pub likelihood_ratio: Option<f64>,
+    pub weight: f64,
+    /// `weight * ln(likelihoodRatio)`: what this signal added to the log-odds.
+    pub log_odds: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Fusion {
+    pub model: FusionModel,
+    /// Probability that the host is not where it appears or claims to be.
+    pub score: Option<f64>,
+    pub contributions: Vec<Contribution>,
+}
+
+/// Evidence from the session itself: RTT bounds against the claim, tunnel use
+/// while probing, and the host timezone against the claim (or the estimate).
+pub(crate) fn session_evidence(
+    claim_checks: Option<&[ClaimCheck]>,
+    records: &[BurstRecord],
+    timezone: Option<&TimezoneCheck>,
+) -> Vec<Evidence> {
+    let mut out = Vec::new();
+    let evidence = |signal: &str, outcome: &str, detail: String| Evidence {
+        signal: signal.to_string(),
+        outcome: outcome.to_string(),
+        detail,
+        likelihood_ratio: None,
+    };
+
+    if let Some(checks) = claim_checks {
+        let loose = checks
+            .iter()
+            .filter(|c| c.falsify_loose == Some(true))
+            .count();
+        let tight = checks
+            .iter()
+            .filter(|c| c.falsify_tight == Some(true))
+            .count();
+        let bounded = checks.iter().filter(|c| c.max_tight_km.is_some()).count();
+        if loose > 0 {
+            out.push(evidence(
+                "rtt",
+                "inconsistent",
+                format!("{loose} anchor(s) rule out the claim at p50"),
+            ));
+        } else if tight > 0 {
+            out.push(evidence(
+                "rtt",
+                "suspect",
+                format!("{tight} anchor(s) rule out the claim at p05 only"),
+            ));
+        } else if bounded > 0 {
+            out.push(evidence(
+                "rtt",
+                "consistent",
+                format!("all {bounded} bounded anchor(s) reach the claim"),
+            ));
+        }
+    }
+
+    let bursts = records.len();
+    if bursts > 0 {
+        let tunneled = records
+            .iter()
+            .filter(|r| r.iface_is_tunnel || r.utun_active)
+            .count();
+        let share = tunneled as f64 / bursts as f64;
+        let outcome = if share >= TUNNEL_ACTIVE_SHARE {
+            "active"
+        } else if tunneled > 0 {
+            "partial"
+        } else {
+            "none"
+        };
+        out.push(evidence(
+            "tunnel",
+            outcome,
+            format!("{tunneled} of {bursts} bursts over a tunnel"),
+        ));
+    }
+
+    if let Some(tz) = timezone {
+        let against = tz
+            .comparisons
+            .iter()
+            .find(|c| c.against == "claim")
+            .or_else(|| tz.comparisons.first());
+        if let Some(c) = against {
+            let zone = tz.timezone.as_deref().unwrap_or("host offset");
+            if c.mismatch {
+                out.push(evidence(
+                    "timezone",
+                    "mismatch",
+                    format!("{zone} vs {}: {}", c.against, c.reasons.join("; ")),
+                ));
+            } else {
+                out.push(evidence(
+                    "timezone",
+                    "consistent",
+                    format!("{zone} fits the {}", c.against),
+                ));
+            }
+        }
+    }
+    out
+}
+
+/// Combines `evidence` under `model`. Later evidence for a signal already
+/// seen replaces the earlier one, so an `--evidence` file can override a
+/// session-derived signal.
+pub(crate) fn fuse(model: &FusionModel, evidence: Vec<Evidence>) -> Fusion {
+    let mut by_signal: BTreeMap<String, Evidence> = BTreeMap::new();
+    for e in evidence {
+        by_signal.insert(e.signal.clone(), e);
+    }
+    let prior_log_odds = (model.prior / (1.0 - model.prior)).ln();
+    let contributions: Vec<Contribution> = by_signal
+        .into_values()
+        .map(|e| {
+            let key = format!("{}.{}", e.signal, e.outcome);
+            let lr = e
+                .likelihood_ratio
+                .or_else(|| model.likelihood_ratios.get(&key).copied());
+            let weight = model.weights.get(&e.signal).copied().unwrap_or(1.0);
+            Contribution {
+                log_odds: lr.map_or(0.0, |lr| weight * lr.ln()),
+                signal: e.signal,
+                outcome: e.outcome,
+                detail: e.detail,
+                likelihood_ratio: lr,
+                weight,
+            }
+        })
+        .collect();
+    let log_odds = prior_log_odds + contributions.iter().map(|c| c.log_odds).sum::<f64>();
+    Fusion {
+        model: model.clone(),
+        score: finite(1.0 / (1.0 + (-log_odds).exp())),
+        contributions,
+    }
+}
+
+pub(crate) fn print_fusion(fusion: &Fusion, fmt: &ReportFormat) {
+    println!(
+        "\nEvidence fusion: score={} (probability the host is not where it appears or claims; prior {})",
+        fmt.opt(fusion.score, 2),
+        fmt.num(fusion.model.prior, 2)
+    );
+    for c in &fusion.contributions {
+        println!(
+            "- {}.{} lr={} weight={} logOdds={}: {}",
+            c.signal,
+            c.outcome,
+            fmt.opt(c.likelihood_ratio, 2),
+            fmt.num(c.weight, 2),
+            fmt.signed(c.log_odds, 2),
+            c.detail
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn fusion_adds_weighted_log_likelihood_ratios_per_signal() {
+        let checks = vec![ClaimCheck {
+            id: "a".to_string(),
+            dist_km: 5000.0,
+            max_tight_km: Some(1000.0),
+            max_loose_km: Some(2000.0),
+            falsify_tight: Some(true),
+            falsify_loose: Some(true),
+        }];
+        let tunneled = BurstRecord {
+            iface_is_tunnel: true,
+            ..record("a", vec![10.0])
+        };
+        let records = vec![tunneled, record("a", vec![10.0])];
+        let mut evidence = session_evidence(Some(&checks), &records, None);
+        evidence.push(Evidence {
+            signal: "egressGeo".to_string(),
+            outcome: "consistent".to_string(),
+            detail: String::new(),
+            likelihood_ratio: None,
+        });
+        let mut model = FusionModel::default();
+        model.weights.insert("tunnel".to_string(), 0.0);
+        let fused = fuse(&model, evidence);
+
+        let outcomes: Vec<(&str, &str)> = fused
+            .contributions
+            .iter()
+            .map(|c| (c.signal.as_str(), c.outcome.as_str()))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                ("egressGeo", "consistent"),
+                ("rtt", "inconsistent"),
+                ("tunnel", "active")
+            ]
+        );
+        assert_eq!(fused.contributions[2].log_odds, 0.0);
+        // ln(20) + ln(0.6) = ln(12) on even prior odds.
+        assert!((fused.score.unwrap() - 12.0 / 13.0).abs() < 1e-9);
+
+        let none = fuse(&FusionModel::default(), Vec::new());
+        assert_eq!(none.score, Some(0.5));
+    }
+}
@@ -0,0 +1,152 @@
+//! `--geojson`: the estimate, its fit band, and each anchor's max-distance
+//! circles as a GeoJSON FeatureCollection, for any map viewer.
+
+use crate::{EndpointReport, Estimate};
+use lattice_core::{Endpoint, EARTH_RADIUS_KM};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Vertices per max-distance circle.
+const CIRCLE_VERTICES: usize = 72;
+const FULL_TURN_DEG: f64 = 360.0;
+const HALF_TURN_DEG: f64 = 180.0;
+
+/// One capture's results: `session` or `baseline`.
+pub(crate) struct Capture<'a> {
+    pub label: &'static str,
+    pub estimate: Option<&'a Estimate>,
+    pub reports: &'a [EndpointReport],
+}
+
+pub(crate) fn write(
+    path: &Path,
+    captures: &[Capture],
+    endpoints: &HashMap<String, Endpoint>,
+    claim: Option<(f64, f64)>,
+) -> io::Result<()> {
+    let mut features = Vec::new();
+    for capture in captures {
+        for report in capture.reports {
+            let Some((lat, lon)) = endpoints.get(&report.id).and_then(|e| e.lat.zip(e.lon)) else {
+                continue;
+            };
+            features.push(feature(
+                json!({ "type": "Point", "coordinates": [lon, lat] }),
+                json!({
+                    "kind": "anchor",
+                    "capture": capture.label,
+                    "endpoint": report.id,
+                    "p05Ms": report.p05_ms,
+                    "excluded": report.excluded,
+                }),
+            ));
+            for (bound, radius_km) in [
+                ("tight", report.max_dist_km_tight),
+                ("loose", report.max_dist_km_loose),
+            ] {
+                let Some(radius_km) = radius_km.filter(|r| r.is_finite() && *r > 0.0) else {
+                    continue;
+                };
+                features.push(feature(
+                    json!({ "type": "Polygon", "coordinates": [circle(lat, lon, radius_km)] }),
+                    json!({
+                        "kind": "maxDistance",
+                        "capture": capture.label,
+                        "endpoint": report.id,
+                        "bound": bound,
+                        "radiusKm": radius_km,
+                    }),
+                ));
+            }
+        }
+        let Some(est) = capture.estimate else {
+            continue;
+        };
+        if let Some(band) = &est.band {
+            features.push(feature(
+                json!({ "type": "Polygon", "coordinates": [band.outline] }),
+                json!({
+                    "kind": "fitBand",
+                    "capture": capture.label,
+                    "radiusKm": band.radius_km,
+                    "points": band.points,
+                    "marginMs": band.margin_ms,
+                }),
+            ));
+        }
+        features.push(feature(
+            json!({ "type": "Point", "coordinates": [est.lon, est.lat] }),
+            json!({
+                "kind": "estimate",
+                "capture": capture.label,
+                "biasMs": est.bias_ms,
+                "sse": est.sse,
+                "points": est.points,
+                "radiusKm": est.band.as_ref().map(|b| b.radius_km),
+            }),
+        ));
+    }
+    if let Some((lat, lon)) = claim {
+        features.push(feature(
+            json!({ "type": "Point", "coordinates": [lon, lat] }),
+            json!({ "kind": "claim" }),
+        ));
+    }
+    let collection = json!({ "type": "FeatureCollection", "features": features });
+    fs::write(
+        path,
+        serde_json::to_vec_pretty(&collection).map_err(io::Error::other)?,
+    )
+}
+
+fn feature(geometry: Value, properties: Value) -> Value {
+    json!({ "type": "Feature", "geometry": geometry, "properties": properties })
+}
+
+/// Closed ring of points `radius_km` from the center along great circles.
+/// Longitudes run on without jumping at the antimeridian, so a circle across
+/// it draws whole instead of as a band around the world.
+pub(crate) fn circle(lat: f64, lon: f64, radius_km: f64) -> Vec<[f64; 2]> {
+    let angular = radius_km / EARTH_RADIUS_KM;
+    let (lat1, lon1) = (lat.to_radians(), lon.to_radians());
+    let mut ring: Vec<[f64; 2]> = Vec::with_capacity(CIRCLE_VERTICES + 1);
+    for i in 0..CIRCLE_VERTICES {
+        let bearing = (i as f64 * FULL_TURN_DEG / CIRCLE_VERTICES as f64).to_radians();
+        let lat2 = (lat1.sin() * angular.cos() + lat1.cos() * angular.sin() * bearing.cos()).asin();
+        let lon2 = lon1
+            + (bearing.sin() * angular.sin() * lat1.cos())
+                .atan2(angular.cos() - lat1.sin() * lat2.sin());
+        let mut lon2 = lon2.to_degrees();
+        if let Some(&[prev, _]) = ring.last() {
+            while lon2 - prev > HALF_TURN_DEG {
+                lon2 -= FULL_TURN_DEG;
+            }
+            while prev - lon2 > HALF_TURN_DEG {
+                lon2 += FULL_TURN_DEG;
+            }
+        }
+        ring.push([lon2, lat2.to_degrees()]);
+    }
+    ring.push(ring[0]);
+    ring
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lattice_core::haversine_km;
+
+    #[test]
+    fn geojson_circles_close_at_the_radius_and_cross_the_antimeridian_whole() {
+        let ring = circle(10.0, 179.0, 500.0);
+        assert_eq!(ring.first(), ring.last());
+        for [lon, lat] in &ring {
+            assert!((haversine_km(10.0, 179.0, *lat, *lon) - 500.0).abs() < 0.5);
+        }
+        assert!(ring.windows(2).all(|w| (w[0][0] - w[1][0]).abs() < 180.0));
+        assert!(ring.iter().any(|[lon, _]| *lon > 180.0));
+    }
+}
@@ -0,0 +1,336 @@
+use crate::{load_jsonl, quantile, record_samples, transport};
+use clap::{Parser, ValueEnum};
+use lattice_core::{civil_from_days, BurstRecord};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const DEFAULT_INTERVAL_S: u64 = 60;
+const MS_PER_SEC: i64 = 1000;
+const SECS_PER_DAY: i64 = 86_400;
+const P05: f64 = 0.05;
+
+const SVG_CELL_W: usize = 10;
+const SVG_CELL_H: usize = 18;
+const SVG_LABEL_W: usize = 160;
+/// Right of the grid: each row's p05 range.
+const SVG_RANGE_W: usize = 130;
+const SVG_HEADER_H: usize = 40;
+/// Room for the caption over a short capture.
+const SVG_MIN_WIDTH: usize = 560;
+const SVG_FOOTER_H: usize = 8;
+const SVG_FONT_PX: usize = 11;
+/// Time labels at least this far apart, so they never overlap.
+const SVG_MIN_LABEL_SPACING_PX: usize = 120;
+const SVG_EMPTY_FILL: &str = "#eeeeee";
+/// Low, middle, and high end of each row's colour scale.
+const SVG_SCALE: [(u8, u8, u8); 3] = [(44, 123, 182), (255, 255, 191), (215, 25, 28)];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum HeatmapFormat {
+    Csv,
+    Svg,
+}
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lattice-analyze heatmap",
+    about = "Endpoints x time matrix of p05 RTT per interval, as CSV or SVG"
+)]
+pub struct HeatmapArgs {
+    #[arg(long)]
+    session: PathBuf,
+
+    /// Width of each time column.
+    #[arg(long, default_value_t = DEFAULT_INTERVAL_S)]
+    interval_s: u64,
+
+    #[arg(long, value_enum, default_value_t = HeatmapFormat::Csv)]
+    format: HeatmapFormat,
+
+    /// Written to stdout when omitted.
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    /// Keep TCP bursts as measured instead of subtracting their bias against UDP.
+    #[arg(long)]
+    no_transport_correction: bool,
+}
+
+/// p05 RTT per endpoint and time column. Columns run from the interval
+/// holding the first burst to the one holding the last; a cell with no
+/// burst is `None`.
+#[derive(Debug, Clone)]
+pub(crate) struct HeatMatrix {
+    pub interval_s: u64,
+    /// Start of the first column, aligned to a multiple of the interval.
+    pub start_ts_ms: i64,
+    pub endpoints: Vec<String>,
+    /// `p05_ms[endpoint][column]`.
+    pub p05_ms: Vec<Vec<Option<f64>>>,
+}
+
+impl HeatMatrix {
+    pub fn build(records: &[BurstRecord], interval_s: u64) -> Self {
+        let interval_ms = interval_s.max(1) as i64 * MS_PER_SEC;
+        let timed: Vec<&BurstRecord> = records.iter().filter(|r| r.ts_unix_ms > 0).collect();
+        let start = timed
+            .iter()
+            .map(|r| r.ts_unix_ms)
+            .min()
+            .unwrap_or(0)
+            .div_euclid(interval_ms);
+        let end = timed
+            .iter()
+            .map(|r| r.ts_unix_ms)
+            .max()
+            .unwrap_or(0)
+            .div_euclid(interval_ms);
+        let columns = if timed.is_empty() {
+            0
+        } else {
+            (end - start + 1) as usize
+        };
+        let mut cells: BTreeMap<&str, Vec<Vec<f64>>> = BTreeMap::new();
+        for rec in timed {
+            let column = (rec.ts_unix_ms.div_euclid(interval_ms) - start) as usize;
+            let row = cells
+                .entry(rec.endpoint_id.as_str())
+                .or_insert_with(|| vec![Vec::new(); columns]);
+            row[column].extend(
+                record_samples(rec)
+                    .into_iter()
+                    .filter(|v| v.is_finite() && *v >= 0.0),
+            );
+        }
+        let endpoints = cells.keys().map(|id| id.to_string()).collect();
+        let p05_ms = cells
+            .into_values()
+            .map(|row| {
+                row.into_iter()
+                    .map(|mut samples| {
+                        samples.sort_by(f64::total_cmp);
+                        quantile(&samples, P05)
+                    })
+                    .collect()
+            })
+            .collect();
+        HeatMatrix {
+            interval_s: interval_s.max(1),
+            start_ts_ms: start * interval_ms,
+            endpoints,
+            p05_ms,
+        }
+    }
+
+    fn columns(&self) -> usize {
+        self.p05_ms.first().map_or(0, Vec::len)
+    }
+
+    fn column_ts_ms(&self, column: usize) -> i64 {
+        self.start_ts_ms + column as i64 * self.interval_s as i64 * MS_PER_SEC
+    }
+
+    /// One row per endpoint, one column per interval headed by its UTC
+    /// start; empty cells are left blank.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("endpoint");
+        for column in 0..self.columns() {
+            out.push(',');
+            out.push_str(&fmt_utc_seconds(self.column_ts_ms(column)));
+        }
+        out.push('\n');
+        for (id, row) in self.endpoints.iter().zip(&self.p05_ms) {
+            out.push_str(&csv_field(id));
+            for cell in row {
+                out.push(',');
+                if let Some(v) = cell {
+                    let _ = write!(out, "{v:.3}");
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Each row coloured on its own scale, lowest p05 blue to highest red,
+    /// so a step that moves every anchor at once (a VPN toggle) shows as a
+    /// vertical edge whatever the anchors' distances.
+    pub fn to_svg(&self) -> String {
+        let columns = self.columns();
+        let width = (SVG_LABEL_W + columns * SVG_CELL_W + SVG_RANGE_W).max(SVG_MIN_WIDTH);
+        let height = SVG_HEADER_H + self.endpoints.len() * SVG_CELL_H + SVG_FOOTER_H;
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" font-family=\"monospace\" font-size=\"{SVG_FONT_PX}\">"
+        );
+        let _ = writeln!(
+            out,
+            "<text x=\"4\" y=\"{}\">p05 RTT per {}s; each row scaled from its own min (blue) to max (red)</text>",
+            SVG_FONT_PX + 4,
+            self.interval_s
+        );
+        let label_every = SVG_MIN_LABEL_SPACING_PX.div_ceil(SVG_CELL_W).max(1);
+        for column in (0..columns).step_by(label_every) {
+            let x = SVG_LABEL_W + column * SVG_CELL_W;
+            let _ = writeln!(
+                out,
+                "<text x=\"{x}\" y=\"{}\">{}</text>",
+                SVG_HEADER_H - 6,
+                fmt_utc_seconds(self.column_ts_ms(column))
+            );
+        }
+        for (i, (id, row)) in self.endpoints.iter().zip(&self.p05_ms).enumerate() {
+            let y = SVG_HEADER_H + i * SVG_CELL_H;
+            let _ = writeln!(
+                out,
+                "<text x=\"4\" y=\"{}\">{}</text>",
+                y + SVG_CELL_H - 5,
+                xml_escape(id)
+            );
+            let values = row.iter().flatten();
+            let lo = values.clone().copied().fold(f64::INFINITY, f64::min);
+            let hi = values.copied().fold(f64::NEG_INFINITY, f64::max);
+            for (column, cell) in row.iter().enumerate() {
+                let x = SVG_LABEL_W + column * SVG_CELL_W;
+                let at = fmt_utc_seconds(self.column_ts_ms(column));
+                let (fill, title) = match cell {
+                    Some(v) => {
+                        let t = if hi > lo { (v - lo) / (hi - lo) } else { 0.0 };
+                        (scale_colour(t), format!("{id} {at} p05={v:.3}ms"))
+                    }
+                    None => (SVG_EMPTY_FILL.to_string(), format!("{id} {at} no bursts")),
+                };
+                let _ = writeln!(
+                    out,
+                    "<rect x=\"{x}\" y=\"{y}\" width=\"{SVG_CELL_W}\" height=\"{SVG_CELL_H}\" fill=\"{fill}\"><title>{}</title></rect>",
+                    xml_escape(&title)
+                );
+            }
+            if lo <= hi {
+                let _ = writeln!(
+                    out,
+                    "<text x=\"{}\" y=\"{}\">{lo:.2}-{hi:.2}ms</text>",
+                    SVG_LABEL_W + columns * SVG_CELL_W + 6,
+                    y + SVG_CELL_H - 5
+                );
+            }
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+}
+
+/// `t` in 0..=1 along `SVG_SCALE`.
+fn scale_colour(t: f64) -> String {
+    let t = t.clamp(0.0, 1.0) * (SVG_SCALE.len() - 1) as f64;
+    let i = (t.floor() as usize).min(SVG_SCALE.len() - 2);
+    let f = t - i as f64;
+    let (a, b) = (SVG_SCALE[i], SVG_SCALE[i + 1]);
+    let mix = |x: u8, y: u8| (x as f64 + (y as f64 - x as f64) * f).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        mix(a.0, b.0),
+        mix(a.1, b.1),
+        mix(a.2, b.2)
+    )
+}
+
+fn fmt_utc_seconds(ts_ms: i64) -> String {
+    let secs = ts_ms.div_euclid(MS_PER_SEC);
+    let (year, month, day) = civil_from_days(secs.div_euclid(SECS_PER_DAY));
+    let s = secs.rem_euclid(SECS_PER_DAY);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        s / 3600,
+        s / 60 % 60,
+        s % 60
+    )
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+pub(crate) fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn run(args: HeatmapArgs) -> io::Result<()> {
+    if args.interval_s == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--interval-s must be > 0",
+        ));
+    }
+    let mut records = load_jsonl(&args.session)?;
+    transport::correct_transport_bias(&mut records, None, !args.no_transport_correction);
+    let matrix = HeatMatrix::build(&records, args.interval_s);
+    let text = match args.format {
+        HeatmapFormat::Csv => matrix.to_csv(),
+        HeatmapFormat::Svg => matrix.to_svg(),
+    };
+    match &args.out {
+        Some(path) => {
+            fs::write(path, text)?;
+            eprintln!(
+                "wrote {} endpoint(s) x {} interval(s) to {}",
+                matrix.endpoints.len(),
+                matrix.columns(),
+                path.display()
+            );
+        }
+        None => print!("{text}"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn heatmap_takes_p05_per_interval_and_leaves_gaps_blank() {
+        let at = |id: &str, ts: i64, samples: Vec<f64>| BurstRecord {
+            ts_unix_ms: ts,
+            ..record(id, samples)
+        };
+        let records = vec![
+            at("b", 120_500, vec![30.0, 31.0]),
+            at("a", 61_000, (1..=20).map(f64::from).collect()),
+            at("a", 119_999, vec![50.0]),
+            at("a", 185_000, vec![12.5]),
+        ];
+        let m = HeatMatrix::build(&records, 60);
+        assert_eq!(m.start_ts_ms, 60_000);
+        assert_eq!(m.endpoints, vec!["a", "b"]);
+        // 21 samples in the first column: p05 is the second smallest.
+        assert_eq!(m.p05_ms[0], vec![Some(2.0), None, Some(12.5)]);
+        assert_eq!(m.p05_ms[1], vec![None, Some(30.0), None]);
+        let csv = m.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "endpoint,1970-01-01T00:01:00Z,1970-01-01T00:02:00Z,1970-01-01T00:03:00Z"
+        );
+        assert_eq!(lines[1], "a,2.000,,12.500");
+        assert_eq!(lines[2], "b,,30.000,");
+        let svg = m.to_svg();
+        assert_eq!(svg.matches("<rect").count(), 6);
+        assert!(svg.contains("a 1970-01-01T00:02:00Z no bursts"));
+    }
+}
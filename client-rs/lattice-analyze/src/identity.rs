@@ -0,0 +1,225 @@
+use crate::{build_stats, load_jsonl};
+use clap::Parser;
+use lattice_core::BurstRecord;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+/// A p05 change smaller than this is ordinary day-to-day variation.
+const RTT_STEP_MIN_MS: f64 = 5.0;
+/// ...and so is one smaller than this fraction of the earlier p05.
+const RTT_STEP_FRACTION: f64 = 0.25;
+const IPV4_PREFIX_OCTETS: usize = 3;
+const IPV6_PREFIX_SEGMENTS: usize = 3;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lattice-analyze identity",
+    about = "Check that each endpoint id is the same anchor across sessions"
+)]
+pub struct IdentityArgs {
+    /// Sessions in time order, ideally from one vantage point (repeatable).
+    #[arg(long = "session", required = true, num_args = 1)]
+    sessions: Vec<PathBuf>,
+
+    /// Skip the p05 step check (sessions from different vantage points or tunnel states).
+    #[arg(long)]
+    no_rtt_check: bool,
+
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentityWarning {
+    pub endpoint_id: String,
+    pub kind: &'static str,
+    pub detail: String,
+}
+
+/// Per-session view of one endpoint.
+struct Sighting<'a> {
+    label: &'a str,
+    addrs: BTreeSet<String>,
+    p05: Option<f64>,
+}
+
+/// `/24` for IPv4, `/48` for IPv6: a change inside the prefix is usually
+/// the same provider network; a change across it is a likely re-provision.
+fn network_prefix(addr: &str) -> String {
+    match addr.parse::<IpAddr>() {
+        Ok(IpAddr::V4(v4)) => v4.octets()[..IPV4_PREFIX_OCTETS]
+            .iter()
+            .map(u8::to_string)
+            .collect::<Vec<_>>()
+            .join("."),
+        Ok(IpAddr::V6(v6)) => v6.segments()[..IPV6_PREFIX_SEGMENTS]
+            .iter()
+            .map(|s| format!("{:x}", s))
+            .collect::<Vec<_>>()
+            .join(":"),
+        Err(_) => addr.to_string(),
+    }
+}
+
+fn join(set: &BTreeSet<String>) -> String {
+    set.iter().cloned().collect::<Vec<_>>().join(",")
+}
+
+/// Flags endpoint ids whose resolved address changes within or between
+/// sessions, and (with `rtt_check`) whose p05 jumps between consecutive
+/// sessions. Sessions are compared in the order given.
+pub(crate) fn identity_warnings(
+    sessions: &[(&str, &[BurstRecord])],
+    rtt_check: bool,
+) -> Vec<IdentityWarning> {
+    let mut by_endpoint: BTreeMap<String, Vec<Sighting>> = BTreeMap::new();
+    for (label, records) in sessions {
+        let stats = build_stats(records, None);
+        let mut addrs: BTreeMap<&str, BTreeSet<String>> = BTreeMap::new();
+        for rec in records.iter() {
+            let entry = addrs.entry(rec.endpoint_id.as_str()).or_default();
+            if !rec.remote_addr.is_empty() {
+                entry.insert(rec.remote_addr.clone());
+            }
+        }
+        for (id, addrs) in addrs {
+            by_endpoint
+                .entry(id.to_string())
+                .or_default()
+                .push(Sighting {
+                    label,
+                    addrs,
+                    p05: stats.get(id).and_then(|s| s.p05),
+                });
+        }
+    }
+
+    let mut out = Vec::new();
+    for (id, sightings) in &by_endpoint {
+        for s in sightings.iter().filter(|s| s.addrs.len() > 1) {
+            out.push(IdentityWarning {
+                endpoint_id: id.clone(),
+                kind: "address_change",
+                detail: format!(
+                    "{} resolved to {} within one session",
+                    s.label,
+                    join(&s.addrs)
+                ),
+            });
+        }
+        for pair in sightings.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            if !a.addrs.is_empty() && !b.addrs.is_empty() && a.addrs.is_disjoint(&b.addrs) {
+                let prefixes = |s: &Sighting| -> BTreeSet<String> {
+                    s.addrs.iter().map(|x| network_prefix(x)).collect()
+                };
+                let scope = if prefixes(a).is_disjoint(&prefixes(b)) {
+                    "different network prefix"
+                } else {
+                    "same network prefix"
+                };
+                out.push(IdentityWarning {
+                    endpoint_id: id.clone(),
+                    kind: "address_change",
+                    detail: format!(
+                        "{} {} -> {} {} ({})",
+                        a.label,
+                        join(&a.addrs),
+                        b.label,
+                        join(&b.addrs),
+                        scope
+                    ),
+                });
+            }
+            if !rtt_check {
+                continue;
+            }
+            if let (Some(pa), Some(pb)) = (a.p05, b.p05) {
+                let step = pb - pa;
+                if step.abs() > RTT_STEP_MIN_MS.max(RTT_STEP_FRACTION * pa) {
+                    out.push(IdentityWarning {
+                        endpoint_id: id.clone(),
+                        kind: "rtt_step",
+                        detail: format!(
+                            "p05 {} {:.2}ms -> {} {:.2}ms ({:+.2}ms); anchor moved or re-provisioned?",
+                            a.label, pa, b.label, pb, step
+                        ),
+                    });
+                }
+            }
+        }
+    }
+    out
+}
+
+pub(crate) fn print_identity_warnings(warnings: &[IdentityWarning]) {
+    for w in warnings {
+        println!("- [!] {} {}: {}", w.endpoint_id, w.kind, w.detail);
+    }
+}
+
+pub fn run(args: IdentityArgs) -> io::Result<()> {
+    let mut loaded = Vec::new();
+    for path in &args.sessions {
+        loaded.push((path.display().to_string(), load_jsonl(path)?));
+    }
+    let sessions: Vec<(&str, &[BurstRecord])> = loaded
+        .iter()
+        .map(|(label, records)| (label.as_str(), records.as_slice()))
+        .collect();
+    let warnings = identity_warnings(&sessions, !args.no_rtt_check);
+
+    if args.json {
+        let text = serde_json::to_string_pretty(&warnings).map_err(io::Error::other)?;
+        println!("{text}");
+    } else if warnings.is_empty() {
+        println!("No identity changes across {} session(s).", sessions.len());
+    } else {
+        println!(
+            "Endpoint identity warnings (do not mix these sessions for the listed endpoints):"
+        );
+        print_identity_warnings(&warnings);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn identity_flags_address_changes_and_rtt_steps() {
+        let with_addr = |id: &str, rtt: f64, addr: &str| {
+            let mut rec = record(id, vec![rtt; 5]);
+            rec.remote_addr = addr.to_string();
+            rec
+        };
+        let first = vec![
+            with_addr("a", 20.0, "203.0.113.5"),
+            with_addr("b", 40.0, "198.51.100.7"),
+        ];
+        let second = vec![
+            with_addr("a", 20.5, "203.0.113.9"),
+            with_addr("b", 60.0, "198.51.100.7"),
+        ];
+        let warnings = identity_warnings(&[("first", &first), ("second", &second)], true);
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(
+            (warnings[0].endpoint_id.as_str(), warnings[0].kind),
+            ("a", "address_change")
+        );
+        assert!(warnings[0].detail.contains("same network prefix"));
+        assert_eq!(
+            (warnings[1].endpoint_id.as_str(), warnings[1].kind),
+            ("b", "rtt_step")
+        );
+
+        let addresses_only = identity_warnings(&[("first", &first), ("second", &second)], false);
+        assert_eq!(addresses_only.len(), 1);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::{quantile, EndpointStats};
+use lattice_core::BurstRecord;
+use std::collections::HashMap;
+
+fn median(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let mut v: Vec<f64> = values.filter(|x| x.is_finite()).collect();
+    v.sort_by(f64::total_cmp);
+    quantile(&v, 0.5)
+}
+
+/// Removes remote samples that spiked together with the LAN reference probed
+/// right after them (`interleave` in the config). A spike is more than
+/// `threshold_ms` above the burst's own median; when both series spike at
+/// once the host stalled, not the path. Records without a reference series
+/// aligned with `samplesMs` are left alone. Returns drops per endpoint.
+pub(crate) fn drop_host_interference(
+    records: &mut [BurstRecord],
+    threshold_ms: f64,
+) -> HashMap<String, usize> {
+    let mut dropped: HashMap<String, usize> = HashMap::new();
+    for rec in records.iter_mut() {
+        let references = &rec.reference_samples_ms;
+        if references.is_empty() || references.len() != rec.samples_ms.len() {
+            continue;
+        }
+        let (Some(remote_median), Some(reference_median)) = (
+            median(rec.samples_ms.iter().copied()),
+            median(references.iter().flatten().copied()),
+        ) else {
+            continue;
+        };
+        let before = rec.samples_ms.len();
+        let kept: Vec<f64> = rec
+            .samples_ms
+            .iter()
+            .zip(references)
+            .filter(|(remote, reference)| {
+                let host_stall = reference.is_some_and(|r| r > reference_median + threshold_ms);
+                !(host_stall && **remote > remote_median + threshold_ms)
+            })
+            .map(|(remote, _)| *remote)
+            .collect();
+        if kept.len() < before {
+            *dropped.entry(rec.endpoint_id.clone()).or_default() += before - kept.len();
+            rec.samples_ms = kept;
+            rec.reference_samples_ms.clear();
+        }
+    }
+    dropped
+}
+
+pub(crate) fn note_interference(
+    stats: &mut HashMap<String, EndpointStats>,
+    dropped: &HashMap<String, usize>,
+) {
+    for (id, st) in stats.iter_mut() {
+        st.interference_filtered = dropped.get(id).copied().unwrap_or(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_stats;
+    use crate::testutil::*;
+
+    #[test]
+    fn interleaved_reference_spikes_drop_matching_remote_samples() {
+        let mut stalled = record("a", vec![20.0, 20.1, 35.0, 34.0, 20.2]);
+        // Sample 2 spiked with the LAN reference (host stall); sample 3 spiked alone (path).
+        stalled.reference_samples_ms = vec![Some(0.5), Some(0.4), Some(12.0), Some(0.5), None];
+        let mut unpaired = record("b", vec![20.0, 40.0]);
+        unpaired.reference_samples_ms = vec![Some(0.5)];
+        let mut records = vec![stalled, unpaired];
+
+        let dropped = drop_host_interference(&mut records, 5.0);
+        assert_eq!(dropped.get("a"), Some(&1));
+        assert_eq!(dropped.get("b"), None);
+        assert_eq!(records[0].samples_ms, vec![20.0, 20.1, 34.0, 20.2]);
+        assert_eq!(records[1].samples_ms, vec![20.0, 40.0]);
+
+        let mut stats = build_stats(&records, None);
+        note_interference(&mut stats, &dropped);
+        assert_eq!(stats["a"].interference_filtered, 1);
+        assert_eq!(stats["a"].count, 4);
+    }
+}
@@ -0,0 +1,1497 @@
+//! Log analysis for LATTICE sessions: physics bounds, location estimates,
+//! calibration, and sharing exports. `run` is the whole CLI so the
+//! `lattice-analyze` binary and `lattice analyze` share one implementation.
+//! `rolling` is the same estimate as the client runs it on-device. The
+//! estimator itself lives in `lattice-analysis`.
+
+mod aggregate;
+mod asymmetry;
+mod atlas;
+mod bufferbloat;
+mod calibrate;
+mod check;
+mod claimwindow;
+mod classify;
+mod constants;
+mod coverage;
+mod demo;
+mod diff;
+mod ecmp;
+mod fleet;
+mod follow;
+mod fusion;
+mod geojson;
+mod heatmap;
+mod identity;
+mod interference;
+mod plan;
+mod presence;
+mod quality;
+mod redact;
+mod repro;
+pub mod rolling;
+mod subset;
+mod summary;
+mod timezone;
+mod traffic;
+mod transport;
+mod tune;
+mod units;
+mod upstream;
+
+#[cfg(test)]
+use lattice_analysis::testutil;
+
+use clap::Parser;
+pub(crate) use lattice_analysis::{
+    adjust_rtt_ms, anchor_alt_km, build_calibration, build_stats, calibration_entry, claim_check,
+    claim_checks, clear_self_floor, endpoints_by_id, estimate_location, load_calibration,
+    max_distance_km, propagation, quantile, record_samples, save_calibration, set_access_floor,
+    stats_endpoint, Calibration, ClaimCheck, DistanceModel, EndpointStats, Estimate, SampleGate,
+    WeightScheme, Weighting, ESTIMATOR_NAME, ESTIMATOR_VERSION,
+};
+use lattice_core::{
+    haversine_km, ms_to_us, now_unix_ms, sha256_hex, BurstRecord, ClockQuality, Config, Endpoint,
+    EventRecord, Transport, EVENT_RECORD_TYPE, SESSION_START_EVENT,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use constants::*;
+use propagation::{Leg, PropagationModel, PropagationSpec};
+use units::{DistanceUnit, ReportFormat};
+
+#[derive(Parser, Debug)]
+#[command(about = "Analyze LATTICE JSONL logs for physics bounds and location estimates")]
+struct Args {
+    #[arg(long)]
+    config: PathBuf,
+
+    /// Host-specific config layered over `--config` (repeatable; later files win).
+    #[arg(long = "override")]
+    overrides: Vec<PathBuf>,
+
+    #[arg(long)]
+    session: PathBuf,
+
+    #[arg(long)]
+    baseline: Option<PathBuf>,
+
+    #[arg(long)]
+    claim_lat: Option<f64>,
+
+    #[arg(long)]
+    claim_lon: Option<f64>,
+
+    /// Check the claim against each anchor's last N bursts (minimum and
+    /// median RTT) instead of the pooled session, and report how the verdict
+    /// changed over the capture.
+    #[arg(long, value_name = "N", requires_all = ["claim_lat", "claim_lon"])]
+    claim_window: Option<usize>,
+
+    #[arg(long)]
+    calibration: Option<PathBuf>,
+
+    #[arg(long)]
+    calib_lat: Option<f64>,
+
+    #[arg(long)]
+    calib_lon: Option<f64>,
+
+    #[arg(long)]
+    calibration_out: Option<PathBuf>,
+
+    /// Model from `classify train`; adds a latency-only region distribution for the session.
+    #[arg(long)]
+    classifier: Option<PathBuf>,
+
+    /// Prior, weights, and likelihood ratios layered over the built-in evidence fusion model.
+    #[arg(long)]
+    fusion_model: Option<PathBuf>,
+
+    /// Grade limits layered over the built-in data-quality thresholds.
+    #[arg(long)]
+    quality_thresholds: Option<PathBuf>,
+
+    /// Extra signals for the fusion score, e.g. egress IP geolocation or an MTU fingerprint:
+    /// a JSON array of `{signal, outcome, detail, likelihoodRatio}` (repeatable).
+    #[arg(long)]
+    evidence: Vec<PathBuf>,
+
+    #[arg(long, default_value_t = DEFAULT_GRID_DEG)]
+    grid: f64,
+
+    #[arg(long, default_value_t = DEFAULT_REFINE_DEG)]
+    refine: f64,
+
+    #[arg(long, default_value_t = DEFAULT_SPEED_KM_S)]
+    speed_km_s: f64,
+
+    #[arg(long, default_value_t = DEFAULT_PATH_STRETCH)]
+    path_stretch: f64,
+
+    #[arg(long, default_value_t = DEFAULT_BAND_FACTOR)]
+    band_factor: f64,
+
+    #[arg(long, default_value_t = DEFAULT_BAND_WINDOW_DEG)]
+    band_window_deg: f64,
+
+    /// Leave endpoints with fewer valid samples than this out of estimates and claim checks
+    /// (default: `minSamplesPerEndpoint` from the config).
+    #[arg(long)]
+    min_samples: Option<usize>,
+
+    /// Same for bursts (default: `minBurstsPerEndpoint` from the config).
+    #[arg(long)]
+    min_bursts: Option<usize>,
+
+    /// Earth model for anchor distances: a sphere (fast) or the WGS-84 ellipsoid (accurate
+    /// to a few mm; the sphere is off by up to ~0.5% at continental scales).
+    #[arg(long, value_enum, default_value_t = DistanceModel::Sphere)]
+    distance_model: DistanceModel,
+
+    /// Distance-to-RTT model (JSON: linear, piecewise by distance, or per region) in place of
+    /// the straight line at `--speed-km-s / --path-stretch`. Defaults to the one the
+    /// `--calibration` table was measured against.
+    #[arg(long)]
+    propagation_model: Option<PathBuf>,
+
+    /// Use the shipped intra-EU, trans-Atlantic, and trans-Pacific speeds where both ends
+    /// fall in those regions, and `--speed-km-s / --path-stretch` elsewhere.
+    #[arg(long, conflicts_with = "propagation_model")]
+    region_pair_speeds: bool,
+
+    /// How anchors are weighted in the location fit.
+    #[arg(long, value_enum, default_value_t = WeightScheme::Jitter)]
+    weighting: WeightScheme,
+
+    /// Age at which `recency`/`combined` weighting halves an anchor's weight, measured from
+    /// the newest burst in the session.
+    #[arg(long, default_value_t = DEFAULT_RECENCY_HALF_LIFE_S)]
+    recency_half_life_s: f64,
+
+    /// Access-latency floor for the session, replacing the per-path `accessFloorMs` in its records.
+    #[arg(long)]
+    access_floor_ms: Option<f64>,
+
+    /// Same as `--access-floor-ms` for the baseline capture.
+    #[arg(long)]
+    baseline_access_floor_ms: Option<f64>,
+
+    /// Keep the host's own latency (`selfFloorMs` in the records) in the RTTs.
+    #[arg(long)]
+    no_self_floor: bool,
+
+    /// Keep TCP bursts as measured instead of subtracting their bias against UDP.
+    #[arg(long)]
+    no_transport_correction: bool,
+
+    /// Drop remote samples that rose more than this over their burst median while the
+    /// interleaved LAN reference did the same (host stall, not path latency).
+    #[arg(long)]
+    interference_ms: Option<f64>,
+
+    /// Drop samples more than K scaled MADs above each burst's median before computing stats.
+    #[arg(long)]
+    outlier_mad_k: Option<f64>,
+
+    /// Decimal places for millisecond values in text output.
+    #[arg(long, default_value_t = DEFAULT_DISPLAY_DECIMALS)]
+    precision: usize,
+
+    /// Unit for distances in text output; JSON stays in km.
+    #[arg(long, value_enum, default_value_t = DistanceUnit::Km)]
+    units: DistanceUnit,
+
+    /// Locale for number separators in text output, e.g. `de_DE` or `en-US`
+    /// (default: `LC_ALL`, `LC_NUMERIC`, or `LANG`).
+    #[arg(long)]
+    locale: Option<String>,
+
+    #[arg(long)]
+    json: bool,
+
+    /// Also write the estimates, fit bands, and anchor max-distance circles
+    /// as GeoJSON.
+    #[arg(long, value_name = "PATH")]
+    geojson: Option<PathBuf>,
+
+    /// Keep reading the session as the client writes it and print the
+    /// estimate and claim checks again every N new bursts (default 10);
+    /// `--json` prints one line per update.
+    #[arg(
+        long,
+        value_name = "N",
+        num_args = 0..=1,
+        default_missing_value = "10",
+        conflicts_with_all = ["baseline", "calibration_out", "claim_window", "geojson"]
+    )]
+    follow: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EndpointReport {
+    id: String,
+    host: String,
+    count: usize,
+    p05_ms: Option<f64>,
+    p50_ms: Option<f64>,
+    p95_ms: Option<f64>,
+    jitter_ms: Option<f64>,
+    p05_us: Option<i64>,
+    p50_us: Option<i64>,
+    /// Clock-limited uncertainty of `p05_ms` from the session header.
+    rtt_error_ms: Option<f64>,
+    outliers_filtered: usize,
+    interference_filtered: usize,
+    bursts: usize,
+    /// Bursts with summary values only, aggregated without samples.
+    summary_bursts: usize,
+    excluded: Option<String>,
+    access_floor_ms: Option<f64>,
+    suggested_access_floor_ms: Option<f64>,
+    self_floor_ms: Option<f64>,
+    p05_adj_ms: Option<f64>,
+    p50_adj_ms: Option<f64>,
+    max_dist_km_tight: Option<f64>,
+    max_dist_km_loose: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Delta {
+    id: String,
+    delta_p05_ms: f64,
+    baseline_p05_ms: f64,
+    session_p05_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionOutput {
+    label: String,
+    records: usize,
+    clock_quality: Option<ClockQuality>,
+    /// Per-endpoint gaps in the burst timeline (sleep, client down).
+    coverage: Vec<coverage::EndpointCoverage>,
+    /// Per-endpoint RTTs with and without heavy traffic on the probing interface.
+    cross_traffic: Vec<traffic::EndpointTraffic>,
+    /// Added latency under load; `None` when no burst recorded `bufferbloat`.
+    bufferbloat: Option<bufferbloat::BufferbloatSummary>,
+    /// Per-source-port RTTs of endpoints probed from several ports.
+    ecmp: Vec<ecmp::EndpointEcmp>,
+    /// Forward/reverse legs per anchor; `None` when no burst recorded
+    /// `sampleLegs`.
+    asymmetry: Option<asymmetry::AsymmetryReport>,
+    /// Extra latency of each non-UDP transport in the capture against UDP.
+    transport_bias: Vec<transport::TransportBias>,
+    endpoint_stats: Vec<EndpointReport>,
+    estimate: Option<Estimate>,
+    /// Each probe path's own estimate when the capture used several.
+    upstreams: Vec<upstream::UpstreamEstimate>,
+    quality: quality::DataQuality,
+}
+
+/// `--json` output policy, shared by every subcommand: fields are never
+/// omitted, a value that could not be computed is an explicit `null`, and
+/// every number is finite. Computed floats pass through `finite` so NaN or
+/// infinity becomes `None` instead of a bare `null` in a non-optional field.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AnalysisOutput {
+    params: Params,
+    session: SessionOutput,
+    baseline: Option<SessionOutput>,
+    claim_checks: Option<Vec<ClaimCheck>>,
+    /// Windowed claim verdicts over time; `None` without `--claim-window`.
+    claim_window: Option<claimwindow::ClaimWindow>,
+    deltas: Option<Vec<Delta>>,
+    estimate_separation_km: Option<f64>,
+    classification: Option<classify::Classification>,
+    identity_warnings: Option<Vec<identity::IdentityWarning>>,
+    /// Every signal combined into one score, with each signal's share.
+    fusion: fusion::Fusion,
+    /// Host timezone against the estimate and claim; `None` when the session
+    /// header has no `hostLocale`.
+    timezone_check: Option<timezone::TimezoneCheck>,
+    provenance: Provenance,
+}
+
+const PROVENANCE_SCHEMA: &str = "lattice-provenance/1";
+
+/// Everything needed to rerun an analysis and get the same output: content
+/// hashes of every input file, the exact arguments, and the code versions.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Provenance {
+    schema: &'static str,
+    crate_name: &'static str,
+    crate_version: &'static str,
+    estimator: &'static str,
+    estimator_version: u32,
+    arguments: Vec<String>,
+    inputs: Vec<InputHash>,
+    /// Hash of the calibration applied, whether loaded or built in this run.
+    calibration_sha256: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InputHash {
+    role: &'static str,
+    path: String,
+    bytes: usize,
+    sha256: String,
+}
+
+fn finite(v: f64) -> Option<f64> {
+    v.is_finite().then_some(v)
+}
+
+/// Text-output counterpart of `finite`: missing or non-finite values print as `-`, never `NaN`.
+fn fmt_opt(v: Option<f64>, decimals: usize) -> String {
+    match v.and_then(finite) {
+        Some(v) => format!("{:.*}", decimals, v),
+        None => "-".to_string(),
+    }
+}
+
+fn hash_input(role: &'static str, path: &PathBuf) -> io::Result<InputHash> {
+    let data = fs::read(path)?;
+    Ok(InputHash {
+        role,
+        path: path.display().to_string(),
+        bytes: data.len(),
+        sha256: sha256_hex(&data),
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Params {
+    speed_km_s: f64,
+    effective_speed_km_s: f64,
+    path_stretch: f64,
+    grid_deg: f64,
+    refine_deg: f64,
+    band_factor: f64,
+    band_window_deg: f64,
+    outlier_mad_k: Option<f64>,
+    access_floor_ms: Option<f64>,
+    baseline_access_floor_ms: Option<f64>,
+    self_floor: bool,
+    interference_ms: Option<f64>,
+    min_samples: usize,
+    min_bursts: usize,
+    weighting: WeightScheme,
+    recency_half_life_s: f64,
+    distance_model: DistanceModel,
+    propagation_model: PropagationSpec,
+}
+
+/// Runs the analyzer CLI; `argv[0]` is the program name shown in help.
+pub fn run(argv: &[String]) -> io::Result<()> {
+    match argv.get(1).map(String::as_str) {
+        Some("redact") => return redact::run(redact::RedactArgs::parse_from(&argv[1..])),
+        Some("aggregate") => {
+            return aggregate::run(aggregate::AggregateArgs::parse_from(&argv[1..]))
+        }
+        Some("atlas") => return atlas::run(atlas::AtlasArgs::parse_from(&argv[1..])),
+        Some("calibrate") => {
+            return calibrate::run(calibrate::CalibrateArgs::parse_from(&argv[1..]))
+        }
+        Some("check") => return check::run(check::CheckArgs::parse_from(&argv[1..])),
+        Some("demo") => return demo::run(demo::DemoArgs::parse_from(&argv[1..])),
+        Some("diff") => return diff::run(diff::DiffArgs::parse_from(&argv[1..])),
+        Some("fleet") => return fleet::run(fleet::FleetArgs::parse_from(&argv[1..])),
+        Some("heatmap") => return heatmap::run(heatmap::HeatmapArgs::parse_from(&argv[1..])),
+        Some("classify") => return classify::run(classify::ClassifyArgs::parse_from(&argv[1..])),
+        Some("plan") => return plan::run(plan::PlanArgs::parse_from(&argv[1..])),
+        Some("presence") => return presence::run(presence::PresenceArgs::parse_from(&argv[1..])),
+        Some("identity") => return identity::run(identity::IdentityArgs::parse_from(&argv[1..])),
+        Some("repro-bundle") => return repro::run(repro::ReproBundleArgs::parse_from(&argv[1..])),
+        Some("subset") => return subset::run(subset::SubsetArgs::parse_from(&argv[1..])),
+        Some("summary") => return summary::run(summary::SummaryArgs::parse_from(&argv[1..])),
+        Some("tune") => return tune::run(tune::TuneArgs::parse_from(&argv[1..])),
+        _ => {}
+    }
+    analyze(argv)
+}
+
+/// The analysis proper: stats, bounds, and estimates for the session, and
+/// the baseline when given.
+fn analyze(argv: &[String]) -> io::Result<()> {
+    let args = Args::parse_from(argv);
+
+    let cfg = Config::load_layered(&args.config, &args.overrides)?;
+    let endpoints = endpoints_by_id(&cfg.endpoints);
+    args.validate()?;
+
+    let path_stretch = if args.path_stretch < 1.0 {
+        1.0
+    } else {
+        args.path_stretch
+    };
+    let effective_speed = args.speed_km_s / path_stretch;
+    let chosen_propagation = match (&args.propagation_model, args.region_pair_speeds) {
+        (Some(path), _) => Some(PropagationSpec::load(path)?),
+        (None, true) => Some(PropagationSpec::RegionPairs(
+            propagation::RegionPairs::shipped(effective_speed),
+        )),
+        (None, false) => None,
+    };
+    let weighting = Weighting {
+        scheme: args.weighting,
+        recency_half_life_s: args.recency_half_life_s,
+    };
+
+    let quality_thresholds =
+        quality::QualityThresholds::load_or_default(args.quality_thresholds.as_deref())?;
+    let session_records = load_jsonl(&args.session)?;
+    let gate = SampleGate {
+        min_samples: args.min_samples.unwrap_or(cfg.min_samples_per_endpoint),
+        min_bursts: args.min_bursts.unwrap_or(cfg.min_bursts_per_endpoint),
+    };
+    let mut calibration = match &args.calibration {
+        Some(path) => load_calibration(path).ok(),
+        None => None,
+    };
+    let mut calibration_input = match (&args.calibration, &calibration) {
+        (Some(path), Some(_)) => Some(hash_input("calibration", path)?),
+        _ => None,
+    };
+
+    if let Some(out_path) = &args.calibration_out {
+        let cal = calibrate_from_capture(
+            &args,
+            &cfg,
+            &session_records,
+            gate,
+            path_stretch,
+            chosen_propagation
+                .as_ref()
+                .unwrap_or(&PropagationSpec::linear(effective_speed)),
+        )?;
+        save_calibration(out_path, &cal)?;
+        calibration = Some(cal);
+        calibration_input = Some(hash_input("calibrationOut", out_path)?);
+    }
+    let propagation = chosen_propagation
+        .or_else(|| {
+            calibration
+                .as_ref()
+                .and_then(|c| c.propagation_model.clone())
+        })
+        .unwrap_or(PropagationSpec::linear(effective_speed));
+    if let Some(every) = args.follow {
+        return follow::Follow {
+            args: &args,
+            endpoints: &endpoints,
+            model: propagation.model(),
+            calibration: calibration.as_ref(),
+            gate,
+            weighting,
+            every,
+        }
+        .run();
+    }
+
+    let analysis = Analysis {
+        args: &args,
+        cfg: &cfg,
+        endpoints: &endpoints,
+        model: propagation.model(),
+        calibration: calibration.as_ref(),
+        gate,
+        weighting,
+        quality_thresholds: &quality_thresholds,
+    };
+    let claim = match (args.claim_lat, args.claim_lon) {
+        (Some(lat), Some(lon)) => Some((lat, lon)),
+        _ => None,
+    };
+    let session = analysis.capture(
+        "session",
+        &args.session,
+        session_records,
+        args.access_floor_ms,
+        claim,
+    )?;
+    let provenance = provenance(&args, argv, calibration_input)?;
+    let (claim_checks, claim_window) = match claim {
+        Some(at) => analysis.claim(&session, at),
+        None => (None, None),
+    };
+
+    let (classification, timezone_check, fusion) =
+        signals(&args, &session, claim, claim_checks.as_deref())?;
+
+    let baseline = match &args.baseline {
+        Some(path) => Some(analysis.capture(
+            "baseline",
+            path,
+            load_jsonl(path)?,
+            args.baseline_access_floor_ms,
+            None,
+        )?),
+        None => None,
+    };
+    let (identity_warnings, deltas_out, estimate_separation_km) = match &baseline {
+        Some(baseline) => {
+            let (warnings, deltas, separation) = compare(baseline, &session);
+            (Some(warnings), Some(deltas), separation)
+        }
+        None => (None, None, None),
+    };
+
+    let output = AnalysisOutput {
+        params: Params {
+            speed_km_s: args.speed_km_s,
+            effective_speed_km_s: effective_speed,
+            path_stretch,
+            grid_deg: args.grid,
+            refine_deg: args.refine,
+            band_factor: args.band_factor,
+            band_window_deg: args.band_window_deg,
+            outlier_mad_k: args.outlier_mad_k,
+            access_floor_ms: args.access_floor_ms,
+            baseline_access_floor_ms: args.baseline_access_floor_ms,
+            self_floor: !args.no_self_floor,
+            interference_ms: args.interference_ms,
+            min_samples: gate.min_samples,
+            min_bursts: gate.min_bursts,
+            weighting: weighting.scheme,
+            recency_half_life_s: weighting.recency_half_life_s,
+            distance_model: args.distance_model,
+            propagation_model: propagation.clone(),
+        },
+        session: session.output,
+        baseline: baseline.map(|b| b.output),
+        claim_checks,
+        claim_window,
+        deltas: deltas_out,
+        estimate_separation_km,
+        classification,
+        identity_warnings,
+        fusion,
+        timezone_check,
+        provenance,
+    };
+
+    if let Some(path) = &args.geojson {
+        let mut captures = vec![geojson::Capture {
+            label: "session",
+            estimate: output.session.estimate.as_ref(),
+            reports: &output.session.endpoint_stats,
+        }];
+        if let Some(baseline) = &output.baseline {
+            captures.push(geojson::Capture {
+                label: "baseline",
+                estimate: baseline.estimate.as_ref(),
+                reports: &baseline.endpoint_stats,
+            });
+        }
+        geojson::write(path, &captures, &endpoints, claim)?;
+        eprintln!("wrote GeoJSON to {}", path.display());
+    }
+
+    if args.json {
+        let text = serde_json::to_string_pretty(&output).map_err(io::Error::other)?;
+        println!("{text}");
+        return Ok(());
+    }
+    print_report(&output, &args, claim, cfg.interval_seconds);
+    Ok(())
+}
+
+impl Args {
+    /// Rejects flag values no analysis can use.
+    fn validate(&self) -> io::Result<()> {
+        let invalid = |msg: String| Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        if let Some(k) = self.outlier_mad_k {
+            if !k.is_finite() || k <= 0.0 {
+                return invalid("--outlier-mad-k must be > 0".to_string());
+            }
+        }
+
+        let positive = [
+            ("--speed-km-s", self.speed_km_s),
+            ("--grid", self.grid),
+            ("--refine", self.refine),
+            ("--path-stretch", self.path_stretch),
+            ("--recency-half-life-s", self.recency_half_life_s),
+        ];
+        let positive = positive
+            .into_iter()
+            .chain(self.interference_ms.map(|v| ("--interference-ms", v)));
+        for (flag, v) in positive {
+            if !v.is_finite() || v <= 0.0 {
+                return invalid(format!("{} must be a finite number > 0", flag));
+            }
+        }
+        let finite_args = [
+            ("--band-factor", Some(self.band_factor)),
+            ("--band-window-deg", Some(self.band_window_deg)),
+            ("--claim-lat", self.claim_lat),
+            ("--claim-lon", self.claim_lon),
+            ("--calib-lat", self.calib_lat),
+            ("--calib-lon", self.calib_lon),
+        ];
+        for (flag, v) in finite_args {
+            if v.is_some_and(|v| !v.is_finite()) {
+                return invalid(format!("{} must be a finite number", flag));
+            }
+        }
+        if self.claim_window == Some(0) {
+            return invalid("--claim-window must be at least 1".to_string());
+        }
+        if self.follow == Some(0) {
+            return invalid("--follow must be at least 1".to_string());
+        }
+        for floor in [self.access_floor_ms, self.baseline_access_floor_ms]
+            .into_iter()
+            .flatten()
+        {
+            if !floor.is_finite() || floor < 0.0 {
+                return invalid("--access-floor-ms must be >= 0".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `--calibration-out`: a calibration from the baseline if given, else the
+/// session, taken at `--calib-lat`/`--calib-lon`.
+fn calibrate_from_capture(
+    args: &Args,
+    cfg: &Config,
+    session_records: &[BurstRecord],
+    gate: SampleGate,
+    path_stretch: f64,
+    propagation: &PropagationSpec,
+) -> io::Result<Calibration> {
+    let (lat, lon) = match (args.calib_lat, args.calib_lon) {
+        (Some(lat), Some(lon)) => (lat, lon),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "calibrationOut requires --calib-lat and --calib-lon",
+            ));
+        }
+    };
+    // Each transport on its own, uncorrected, so the table keeps their
+    // biases apart.
+    let (records, floor) = match &args.baseline {
+        Some(baseline_path) => (load_jsonl(baseline_path)?, args.baseline_access_floor_ms),
+        None => (session_records.to_vec(), args.access_floor_ms),
+    };
+    let stats: BTreeMap<Transport, HashMap<String, EndpointStats>> =
+        transport::by_transport(&records)
+            .into_iter()
+            .map(|(t, mut recs)| (t, capture_stats(&mut recs, args, floor, gate, None).0))
+            .collect();
+    Ok(build_calibration(
+        cfg,
+        &stats,
+        lat,
+        lon,
+        args.speed_km_s,
+        path_stretch,
+        args.distance_model,
+        propagation,
+    ))
+}
+
+/// Hashes every input file the analysis read, the calibration last.
+fn provenance(
+    args: &Args,
+    argv: &[String],
+    calibration_input: Option<InputHash>,
+) -> io::Result<Provenance> {
+    let mut inputs = vec![hash_input("config", &args.config)?];
+    for path in &args.overrides {
+        inputs.push(hash_input("override", path)?);
+    }
+    inputs.push(hash_input("session", &args.session)?);
+    let optional = [
+        ("baseline", &args.baseline),
+        ("classifier", &args.classifier),
+        ("fusionModel", &args.fusion_model),
+        ("qualityThresholds", &args.quality_thresholds),
+        ("propagationModel", &args.propagation_model),
+    ];
+    for (role, path) in optional {
+        if let Some(path) = path {
+            inputs.push(hash_input(role, path)?);
+        }
+    }
+    for path in &args.evidence {
+        inputs.push(hash_input("evidence", path)?);
+    }
+    let calibration_sha256 = calibration_input.as_ref().map(|c| c.sha256.clone());
+    inputs.extend(calibration_input);
+    Ok(Provenance {
+        schema: PROVENANCE_SCHEMA,
+        crate_name: env!("CARGO_PKG_NAME"),
+        crate_version: env!("CARGO_PKG_VERSION"),
+        estimator: ESTIMATOR_NAME,
+        estimator_version: ESTIMATOR_VERSION,
+        arguments: argv.iter().skip(1).cloned().collect(),
+        inputs,
+        calibration_sha256,
+    })
+}
+
+/// One capture after analysis: its records and stats for the cross-capture
+/// checks, and its report.
+struct Capture {
+    records: Vec<BurstRecord>,
+    stats: HashMap<String, EndpointStats>,
+    output: SessionOutput,
+}
+
+/// Everything fixed for the run; the session and baseline each go through
+/// it the same way.
+struct Analysis<'a> {
+    args: &'a Args,
+    cfg: &'a Config,
+    endpoints: &'a HashMap<String, Endpoint>,
+    model: &'a dyn PropagationModel,
+    calibration: Option<&'a Calibration>,
+    gate: SampleGate,
+    weighting: Weighting,
+    quality_thresholds: &'a quality::QualityThresholds,
+}
+
+impl Analysis<'_> {
+    fn estimate(&self, stats: &HashMap<String, EndpointStats>, margin_ms: f64) -> Option<Estimate> {
+        estimate_location(
+            stats,
+            self.endpoints,
+            self.model,
+            self.args.grid,
+            self.args.refine,
+            self.args.band_factor,
+            self.args.band_window_deg,
+            margin_ms,
+            self.calibration,
+            self.weighting,
+            self.args.distance_model,
+        )
+    }
+
+    /// Stats, estimate, and per-capture reports for the log at `path`.
+    /// Data quality is graded at the estimate, or at `claim` without one.
+    fn capture(
+        &self,
+        label: &str,
+        path: &Path,
+        mut records: Vec<BurstRecord>,
+        access_floor_ms: Option<f64>,
+        claim: Option<(f64, f64)>,
+    ) -> io::Result<Capture> {
+        let (stats, transport_bias) = capture_stats(
+            &mut records,
+            self.args,
+            access_floor_ms,
+            self.gate,
+            self.calibration,
+        );
+        let clock = load_clock_quality(path)?;
+        let endpoint_stats = endpoint_reports(
+            &stats,
+            self.endpoints,
+            self.model,
+            self.calibration,
+            clock.as_ref(),
+        );
+        let bufferbloat = bufferbloat::summarize(&records);
+        let margin_ms = bufferbloat.as_ref().map_or(0.0, |b| b.margin_ms);
+        let estimate = self.estimate(&stats, margin_ms);
+        let quality = quality::assess(
+            self.quality_thresholds,
+            &quality::QualityInputs {
+                stats: &stats,
+                endpoints: self.endpoints,
+                records: &records,
+                samples_per_burst: self.cfg.samples_per_endpoint,
+                at: estimate.as_ref().map(|e| (e.lat, e.lon)).or(claim),
+                calibration: self.calibration,
+                clock: clock.as_ref(),
+                now_ms: now_unix_ms(),
+            },
+        );
+        let output = SessionOutput {
+            label: label.to_string(),
+            records: records.len(),
+            clock_quality: clock,
+            coverage: coverage::coverage(
+                &records,
+                self.cfg.interval_seconds,
+                &coverage::load_suspends(path)?,
+            ),
+            cross_traffic: traffic::cross_traffic(&records),
+            bufferbloat,
+            ecmp: ecmp::path_diversity(&records),
+            asymmetry: asymmetry::asymmetry(&records),
+            transport_bias,
+            endpoint_stats,
+            upstreams: upstream::compare(&stats, estimate.as_ref(), |stats| {
+                self.estimate(stats, margin_ms)
+            }),
+            estimate,
+            quality,
+        };
+        Ok(Capture {
+            records,
+            stats,
+            output,
+        })
+    }
+
+    /// Claim checks against the whole session, or per window with
+    /// `--claim-window`.
+    fn claim(
+        &self,
+        session: &Capture,
+        (lat, lon): (f64, f64),
+    ) -> (Option<Vec<ClaimCheck>>, Option<claimwindow::ClaimWindow>) {
+        match self.args.claim_window {
+            Some(window) => {
+                let (checks, window) = claimwindow::claim_window(
+                    &session.records,
+                    &session.stats,
+                    self.endpoints,
+                    (lat, lon),
+                    window,
+                    self.model,
+                    self.calibration,
+                    self.args.distance_model,
+                );
+                (Some(checks), Some(window))
+            }
+            None => {
+                let checks = claim_checks(
+                    &session.stats,
+                    self.endpoints,
+                    lat,
+                    lon,
+                    self.model,
+                    self.calibration,
+                    self.args.distance_model,
+                );
+                (Some(checks), None)
+            }
+        }
+    }
+}
+
+/// What else the session says about where it was taken: the classifier's
+/// match, the host timezone against the estimate and claim, and every
+/// signal fused into one score.
+fn signals(
+    args: &Args,
+    session: &Capture,
+    claim: Option<(f64, f64)>,
+    claim_checks: Option<&[ClaimCheck]>,
+) -> io::Result<(
+    Option<classify::Classification>,
+    Option<timezone::TimezoneCheck>,
+    fusion::Fusion,
+)> {
+    let classification = match &args.classifier {
+        Some(path) => {
+            let model = classify::load_model(path)?;
+            classify::classify(&model, &classify::features(&session.stats))
+        }
+        None => None,
+    };
+
+    let mut timezone_targets = Vec::new();
+    if let Some(est) = &session.output.estimate {
+        timezone_targets.push(("estimate", est.lat, est.lon));
+    }
+    if let Some((lat, lon)) = claim {
+        timezone_targets.push(("claim", lat, lon));
+    }
+    let timezone_check = timezone::load_host_locale(&args.session)?
+        .map(|host| timezone::check(&host, &timezone_targets));
+
+    let fusion_model = match &args.fusion_model {
+        Some(path) => fusion::FusionModel::load(path)?,
+        None => fusion::FusionModel::default(),
+    };
+    let mut evidence =
+        fusion::session_evidence(claim_checks, &session.records, timezone_check.as_ref());
+    for path in &args.evidence {
+        evidence.extend(fusion::load_evidence(path)?);
+    }
+    let fusion = fusion::fuse(&fusion_model, evidence);
+    Ok((classification, timezone_check, fusion))
+}
+
+/// Identity warnings, p05 deltas, and how far apart the estimates landed,
+/// from `baseline` to `session`.
+fn compare(
+    baseline: &Capture,
+    session: &Capture,
+) -> (Vec<identity::IdentityWarning>, Vec<Delta>, Option<f64>) {
+    // Tunnel state changes RTTs by design, so only addresses are compared here.
+    let warnings = identity::identity_warnings(
+        &[
+            ("baseline", &baseline.records),
+            ("session", &session.records),
+        ],
+        false,
+    );
+    let separation = match (&baseline.output.estimate, &session.output.estimate) {
+        (Some(b), Some(s)) => Some(haversine_km(b.lat, b.lon, s.lat, s.lon)),
+        _ => None,
+    };
+    (
+        warnings,
+        deltas(&baseline.stats, &session.stats),
+        separation,
+    )
+}
+
+/// The text report: the session, the claim checks, then the baseline and how
+/// the two compare.
+fn print_report(
+    output: &AnalysisOutput,
+    args: &Args,
+    claim: Option<(f64, f64)>,
+    interval_seconds: u64,
+) {
+    let fmt = ReportFormat::new(args.units, args.locale.as_deref(), args.precision);
+    let path_stretch = output.params.path_stretch;
+    if path_stretch != args.path_stretch {
+        println!(
+            "Note: path_stretch < 1.0 is invalid; clamped to {}",
+            fmt.num(path_stretch, 2)
+        );
+    }
+    let session = &output.session;
+    println!("Session: {} records", session.records);
+    print_capture("Session", session, interval_seconds, &fmt);
+
+    if let Some((lat, lon)) = claim {
+        println!(
+            "\nClaim check: lat={}, lon={}",
+            fmt.num(lat, 4),
+            fmt.num(lon, 4)
+        );
+        if let Some(ref checks) = output.claim_checks {
+            print_claim_checks(checks, &fmt);
+        }
+        if let Some(ref window) = output.claim_window {
+            claimwindow::print_claim_window(window, &fmt);
+        }
+    }
+
+    if let Some(est) = &session.estimate {
+        println!(
+            "\nSession estimate (treats RTTs as direct path; for VPN this approximates exit):"
+        );
+        print_estimate(est, &fmt);
+    } else {
+        println!("\nSession estimate: insufficient endpoint data (need lat/lon + RTTs).")
+    }
+    upstream::print_upstreams("Session", &session.upstreams, &fmt);
+    quality::print_quality("Session", &session.quality, &fmt);
+    if args.classifier.is_some() {
+        println!();
+        classify::print_classification(output.classification.as_ref(), &fmt);
+    }
+    if let Some(check) = &output.timezone_check {
+        timezone::print_timezone_check(check, &fmt);
+    }
+    if !output.fusion.contributions.is_empty() {
+        fusion::print_fusion(&output.fusion, &fmt);
+    }
+
+    if let Some(baseline) = &output.baseline {
+        println!("\nBaseline: {} records", baseline.records);
+        print_capture("Baseline", baseline, interval_seconds, &fmt);
+
+        if let Some(est) = &baseline.estimate {
+            println!("\nBaseline estimate (best-effort physical location):");
+            print_estimate(est, &fmt);
+        } else {
+            println!("\nBaseline estimate: insufficient endpoint data (need lat/lon + RTTs).")
+        }
+        upstream::print_upstreams("Baseline", &baseline.upstreams, &fmt);
+        quality::print_quality("Baseline", &baseline.quality, &fmt);
+
+        if let Some(warnings) = output.identity_warnings.as_ref().filter(|w| !w.is_empty()) {
+            println!(
+                "\nEndpoint identity warnings (baseline and session may not share these anchors):"
+            );
+            identity::print_identity_warnings(warnings);
+        }
+
+        println!("\nBaseline vs Session deltas (p05):");
+        if let Some(ref d) = output.deltas {
+            print_deltas(d, &fmt);
+        }
+
+        if let Some(dist) = output.estimate_separation_km {
+            println!(
+                "\nBaseline vs Session estimate separation: {} {} (VPN on often shifts toward exit)",
+                fmt.dist(dist, 1),
+                fmt.unit.label()
+            );
+        }
+    }
+
+    let provenance = &output.provenance;
+    println!(
+        "\nProvenance: {} {}, estimator {} v{}, {} input file(s) hashed (see --json)",
+        provenance.crate_name,
+        provenance.crate_version,
+        provenance.estimator,
+        provenance.estimator_version,
+        provenance.inputs.len()
+    );
+}
+
+/// A capture's per-endpoint stats and what was measured alongside them,
+/// under `title` ("Session" or "Baseline").
+fn print_capture(title: &str, capture: &SessionOutput, interval_seconds: u64, fmt: &ReportFormat) {
+    if let Some(clock) = &capture.clock_quality {
+        print_clock_quality(clock, fmt);
+    }
+    print_stats_summary(&capture.label, &capture.endpoint_stats, fmt);
+    coverage::print_coverage(title, &capture.coverage, interval_seconds, fmt);
+    traffic::print_cross_traffic(title, &capture.cross_traffic, fmt);
+    if let Some(bloat) = &capture.bufferbloat {
+        bufferbloat::print_bufferbloat(title, bloat, fmt);
+    }
+    ecmp::print_path_diversity(title, &capture.ecmp, fmt);
+    if let Some(report) = &capture.asymmetry {
+        asymmetry::print_asymmetry(title, report, fmt);
+    }
+    transport::print_transport_bias(title, &capture.transport_bias, fmt);
+}
+
+/// Reads burst records, skipping events and repeated `burstId`s (merged or
+/// concatenated logs). Records from older clients have no id and are all kept.
+/// Opens a capture log for reading by line. Archives the client rotated out
+/// (`.zst`, `.gz`) are decompressed on the fly.
+pub(crate) fn open_log(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let file = File::open(path)?;
+    Ok(match path.extension().and_then(|e| e.to_str()) {
+        Some("zst") => Box::new(BufReader::new(zstd::Decoder::new(file)?)),
+        Some("gz") => Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(file))),
+        _ => Box::new(BufReader::new(file)),
+    })
+}
+
+fn load_jsonl(path: &Path) -> io::Result<Vec<BurstRecord>> {
+    let reader = open_log(path)?;
+    let mut out = Vec::new();
+    let mut seen = HashSet::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Ok(rec) = serde_json::from_str::<BurstRecord>(&line) {
+            if !rec.burst_id.is_empty() && !seen.insert(rec.burst_id.clone()) {
+                continue;
+            }
+            out.push(rec);
+        }
+    }
+    Ok(out)
+}
+
+/// Clock self-test from the most recent `session_start` header in a log.
+/// Logs written before headers existed return `None`.
+fn load_clock_quality(path: &Path) -> io::Result<Option<ClockQuality>> {
+    let reader = open_log(path)?;
+    let mut found = None;
+    for line in reader.lines() {
+        let line = line?;
+        if !line.contains(SESSION_START_EVENT) {
+            continue;
+        }
+        let Ok(ev) = serde_json::from_str::<EventRecord>(&line) else {
+            continue;
+        };
+        if ev.record_type != EVENT_RECORD_TYPE || ev.event != SESSION_START_EVENT {
+            continue;
+        }
+        if let Some(clock) = ev.detail.get("clockQuality") {
+            found = serde_json::from_value(clock.clone()).ok();
+        }
+    }
+    Ok(found)
+}
+
+/// Per-endpoint stats for one capture with the command-line adjustments
+/// applied: interference filtering, transport bias (from `calibration` where
+/// it has the burst's transport), floors, and the sample gate. Also returns
+/// the transport biases it measured.
+fn capture_stats(
+    records: &mut [BurstRecord],
+    args: &Args,
+    access_floor_ms: Option<f64>,
+    gate: SampleGate,
+    calibration: Option<&Calibration>,
+) -> (
+    HashMap<String, EndpointStats>,
+    Vec<transport::TransportBias>,
+) {
+    let interfered = args
+        .interference_ms
+        .map(|t| interference::drop_host_interference(records, t));
+    let transport_bias =
+        transport::correct_transport_bias(records, calibration, !args.no_transport_correction);
+    let mut stats = build_stats(records, args.outlier_mad_k);
+    if let Some(dropped) = &interfered {
+        interference::note_interference(&mut stats, dropped);
+    }
+    if let Some(floor) = access_floor_ms {
+        set_access_floor(&mut stats, floor);
+    }
+    if args.no_self_floor {
+        clear_self_floor(&mut stats);
+    }
+    if let Some(report) = asymmetry::asymmetry(records) {
+        asymmetry::widen(&mut stats, &report);
+    }
+    gate.apply(&mut stats);
+    (stats, transport_bias)
+}
+
+fn endpoint_reports(
+    stats: &HashMap<String, EndpointStats>,
+    endpoints: &HashMap<String, Endpoint>,
+    model: &dyn PropagationModel,
+    calibration: Option<&Calibration>,
+    clock: Option<&ClockQuality>,
+) -> Vec<EndpointReport> {
+    let mut ids: Vec<&String> = stats.keys().collect();
+    ids.sort();
+    let mut out = Vec::new();
+    for id in ids {
+        let st = &stats[id];
+        let ep = endpoints.get(id).or_else(|| {
+            if let Some(base) = id.split('@').next() {
+                endpoints.get(base)
+            } else {
+                None
+            }
+        });
+        let host = ep
+            .map(|e| e.host.clone())
+            .unwrap_or_else(|| "?".to_string());
+        let leg = Leg {
+            region: ep.and_then(|e| e.region_hint.as_deref()),
+            anchor: ep.and_then(|e| e.lat.zip(e.lon)),
+            host: None,
+        };
+        let p05_adj = st
+            .p05
+            .map(|v| adjust_rtt_ms(v, st.floor_ms(), id, calibration));
+        let p50_adj = st
+            .p50
+            .map(|v| adjust_rtt_ms(v, st.floor_ms(), id, calibration));
+        let max_dist_km_tight = st
+            .p05
+            .map(|v| adjust_rtt_ms(v, st.floor_ms(), id, calibration))
+            .and_then(|v| max_distance_km(v, model, leg));
+        let max_dist_km_loose = st
+            .p50
+            .map(|v| adjust_rtt_ms(v, st.floor_ms(), id, calibration))
+            .and_then(|v| max_distance_km(v, model, leg));
+        out.push(EndpointReport {
+            id: id.clone(),
+            host,
+            count: st.count,
+            p05_ms: st.p05,
+            p50_ms: st.p50,
+            p95_ms: st.p95,
+            jitter_ms: st.jitter_ms,
+            p05_us: st.p05.map(ms_to_us),
+            p50_us: st.p50.map(ms_to_us),
+            rtt_error_ms: clock.zip(st.p05).and_then(|(c, v)| c.rtt_error_ms(v)),
+            outliers_filtered: st.outliers_filtered,
+            interference_filtered: st.interference_filtered,
+            bursts: st.bursts,
+            summary_bursts: st.summary_bursts,
+            excluded: st.excluded.clone(),
+            access_floor_ms: Some(st.access_floor_ms).filter(|f| *f > 0.0),
+            suggested_access_floor_ms: st.suggested_access_floor_ms,
+            self_floor_ms: Some(st.self_floor_ms).filter(|f| *f > 0.0),
+            p05_adj_ms: p05_adj,
+            p50_adj_ms: p50_adj,
+            max_dist_km_tight,
+            max_dist_km_loose,
+        });
+    }
+    out
+}
+
+fn print_clock_quality(clock: &ClockQuality, fmt: &ReportFormat) {
+    let ntp = match clock.ntp_synced {
+        Some(true) => "synced",
+        Some(false) => "UNSYNCED",
+        None => "unknown",
+    };
+    println!(
+        "Clock: step={}ns skew={}ppm ntp={} rtt_error@10ms={}ms",
+        clock
+            .observed_granularity_ns
+            .map(|g| g.to_string())
+            .unwrap_or_else(|| "?".to_string()),
+        fmt.opt(clock.realtime_vs_monotonic_ppm, 1),
+        ntp,
+        fmt.opt(
+            clock.rtt_error_ms(CLOCK_ERROR_REFERENCE_RTT_MS),
+            CLOCK_ERROR_MIN_DECIMALS
+        )
+    );
+}
+
+fn print_stats_summary(label: &str, reports: &[EndpointReport], fmt: &ReportFormat) {
+    println!("\n{} endpoint stats (p05/p50/p95 in ms):", label);
+    for r in reports {
+        println!(
+            "- {} ({}) count={} p05={} p50={} p95={} jitter={}",
+            r.id,
+            r.host,
+            r.count,
+            fmt.ms_opt(r.p05_ms),
+            fmt.ms_opt(r.p50_ms),
+            fmt.ms_opt(r.p95_ms),
+            fmt.ms_opt(r.jitter_ms)
+        );
+        if let Some(err) = r.rtt_error_ms {
+            println!(
+                "  clock_error_ms=±{}",
+                fmt.num(err, fmt.ms_decimals().max(CLOCK_ERROR_MIN_DECIMALS))
+            );
+        }
+        if r.outliers_filtered > 0 {
+            println!("  outliers_filtered={}", r.outliers_filtered);
+        }
+        if r.interference_filtered > 0 {
+            println!(
+                "  interference_filtered={} (host stalls seen on the LAN reference)",
+                r.interference_filtered
+            );
+        }
+        if let Some(reason) = &r.excluded {
+            println!("  [!] excluded from estimate and claim checks ({})", reason);
+        }
+        if let Some(floor) = r.access_floor_ms {
+            println!(
+                "  access_floor_ms={} (subtracted before distance bounds)",
+                fmt.ms(floor)
+            );
+        } else if let Some(floor) = r.suggested_access_floor_ms {
+            println!(
+                "  [!] cellular interface without accessFloorMs; ~{}ms of last-mile latency is counted as distance",
+                fmt.num(floor, 0)
+            );
+        }
+        if let Some(floor) = r.self_floor_ms {
+            println!(
+                "  self_floor_ms={} (host latency, subtracted)",
+                fmt.ms(floor)
+            );
+        }
+        if let (Some(tight), Some(loose)) = (r.max_dist_km_tight, r.max_dist_km_loose) {
+            println!(
+                "  max_dist_{} tight={} loose={}",
+                fmt.unit.label(),
+                fmt.dist(tight, 1),
+                fmt.dist(loose, 1)
+            );
+        }
+    }
+}
+
+fn print_claim_checks(checks: &[ClaimCheck], fmt: &ReportFormat) {
+    for c in checks {
+        let max_tight = fmt.dist_opt(c.max_tight_km, 1);
+        let max_loose = fmt.dist_opt(c.max_loose_km, 1);
+        let falsify_tight = c.falsify_tight.unwrap_or(false);
+        let falsify_loose = c.falsify_loose.unwrap_or(false);
+        println!(
+            "- {} dist={}{} max_tight={} max_loose={} falsify_tight={} falsify_loose={}",
+            c.id,
+            fmt.dist(c.dist_km, 1),
+            fmt.unit.label(),
+            max_tight,
+            max_loose,
+            falsify_tight,
+            falsify_loose
+        );
+    }
+}
+
+fn print_estimate(est: &Estimate, fmt: &ReportFormat) {
+    println!(
+        "- lat={}, lon={}, bias={}ms, sse={}, endpoints_used={}",
+        fmt.num(est.lat, 4),
+        fmt.num(est.lon, 4),
+        fmt.ms(est.bias_ms),
+        fmt.num(est.sse, 2),
+        est.points
+    );
+    if let Some(band) = &est.band {
+        println!(
+            "  fit_band: radius={}{} points={} sse_threshold={}",
+            fmt.dist(band.radius_km, 1),
+            fmt.unit.label(),
+            band.points,
+            fmt.num(band.sse_threshold, 2)
+        );
+        if band.margin_ms > 0.0 {
+            println!(
+                "  fit_band_margin: ±{}ms per anchor (bufferbloat)",
+                fmt.ms(band.margin_ms)
+            );
+        }
+        let sep = fmt.list_separator();
+        println!(
+            "  fit_band_bounds: lat[{}{sep}{}] lon[{}{sep}{}]",
+            fmt.num(band.min_lat, 2),
+            fmt.num(band.max_lat, 2),
+            fmt.num(band.min_lon, 2),
+            fmt.num(band.max_lon, 2)
+        );
+    }
+    match &est.geometry {
+        Some(g) => {
+            println!(
+                "  geometry: gdop={} hdop={} ellipse={}x{} major_axis={}°",
+                fmt.num(g.gdop, 2),
+                fmt.num(g.hdop, 2),
+                fmt.num(g.ellipse_major, 2),
+                fmt.num(g.ellipse_minor, 2),
+                fmt.num(g.ellipse_bearing_deg, 0)
+            );
+            if g.ellipse_minor > 0.0 && g.ellipse_major / g.ellipse_minor >= LOPSIDED_ELLIPSE_RATIO
+            {
+                println!(
+                    "  geometry_warning: anchors sit mostly to one side; the position is {}x less certain along {}° than across it",
+                    fmt.num(g.ellipse_major / g.ellipse_minor, 1),
+                    fmt.num(g.ellipse_bearing_deg, 0)
+                );
+            }
+        }
+        None => println!(
+            "  geometry: anchors cannot fix a position here (too few bearings or all in a line)"
+        ),
+    }
+}
+
+fn deltas(
+    base: &HashMap<String, EndpointStats>,
+    sess: &HashMap<String, EndpointStats>,
+) -> Vec<Delta> {
+    let mut ids: Vec<&String> = base.keys().collect();
+    ids.sort();
+    let mut out = Vec::new();
+    for id in ids {
+        let b = &base[id];
+        let s = match sess.get(id) {
+            Some(v) => v,
+            None => continue,
+        };
+        let (Some(bv), Some(sv)) = (b.p05, s.p05) else {
+            continue;
+        };
+        out.push(Delta {
+            id: id.clone(),
+            delta_p05_ms: sv - bv,
+            baseline_p05_ms: bv,
+            session_p05_ms: sv,
+        });
+    }
+    out
+}
+
+fn print_deltas(deltas: &[Delta], fmt: &ReportFormat) {
+    for d in deltas {
+        println!(
+            "- {} delta_p05={}ms (baseline {} -> session {})",
+            d.id,
+            fmt.ms(d.delta_p05_ms),
+            fmt.ms(d.baseline_p05_ms),
+            fmt.ms(d.session_p05_ms)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn json_output_uses_explicit_nulls_and_no_non_finite_numbers() {
+        assert_eq!(fmt_opt(Some(1.234), 2), "1.23");
+        assert_eq!(fmt_opt(Some(f64::NAN), 2), "-");
+        assert_eq!(fmt_opt(None, 2), "-");
+        assert_eq!(
+            max_distance_km(
+                10.0,
+                &propagation::Linear {
+                    speed_km_s: f64::NAN
+                },
+                Leg::default()
+            ),
+            None
+        );
+
+        let stats = build_stats(&[record("a", Vec::new())], None);
+        let endpoints = endpoints_by_id(&[endpoint("a", 0.0, 0.0)]);
+        let reports = endpoint_reports(&stats, &endpoints, &TEST_MODEL, None, None);
+        let value = serde_json::to_value(&reports[0]).unwrap();
+        for key in [
+            "p05Ms",
+            "p50Ms",
+            "jitterMs",
+            "maxDistKmTight",
+            "accessFloorMs",
+        ] {
+            assert_eq!(value.get(key), Some(&serde_json::Value::Null), "{key}");
+        }
+
+        // One anchor cannot be estimated, so the median error is unknown, not infinite.
+        let sessions = vec![subset::TruthSession {
+            lat: 0.0,
+            lon: 0.0,
+            stats: stats_with_p05("a", 10.0),
+        }];
+        let eval = subset::Evaluator {
+            sessions: &sessions,
+            endpoints: &endpoints,
+            speed_km_s: DEFAULT_SPEED_KM_S,
+            grid: DEFAULT_GRID_DEG,
+            refine: DEFAULT_REFINE_DEG,
+            calibration: None,
+            weighting: Weighting::default(),
+            distance: DistanceModel::Sphere,
+        };
+        let result = subset::select(&eval, 100.0);
+        let value = serde_json::to_value(&result).unwrap();
+        assert_eq!(
+            value.get("fullMedianErrorKm"),
+            Some(&serde_json::Value::Null)
+        );
+        assert_eq!(
+            value.get("meetsTarget"),
+            Some(&serde_json::Value::Bool(false))
+        );
+    }
+}
@@ -0,0 +1,407 @@
+use crate::{fmt_opt, haversine_km};
+use clap::Parser;
+pub(crate) use lattice_analysis::geometry::{dop_at, RANGE_ERROR_DOUBLING_KM};
+use lattice_core::{expand_endpoint_templates, CLOUD_REGIONS};
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Named target areas as (name, lat min, lat max, lon min, lon max).
+const TARGET_REGIONS: &[(&str, f64, f64, f64, f64)] = &[
+    ("EU", 35.0, 71.0, -11.0, 40.0),
+    ("NA", 15.0, 72.0, -168.0, -52.0),
+    ("US", 24.0, 50.0, -125.0, -66.0),
+    ("SA", -56.0, 13.0, -82.0, -34.0),
+    ("AF", -35.0, 37.0, -18.0, 52.0),
+    ("ME", 12.0, 42.0, 34.0, 63.0),
+    ("AS", -11.0, 55.0, 60.0, 150.0),
+    ("OC", -47.0, -10.0, 110.0, 180.0),
+    ("WORLD", -60.0, 75.0, -180.0, 180.0),
+];
+const DEFAULT_PLAN_GRID_DEG: f64 = 2.0;
+const DEFAULT_SUGGESTIONS: usize = 3;
+/// Candidate regions this close to an existing anchor add nothing new.
+const CANDIDATE_MIN_SEPARATION_KM: f64 = 300.0;
+const P90: f64 = 0.9;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lattice-analyze plan",
+    about = "Dilution of precision of an anchor set over a target area, with suggested additions"
+)]
+pub struct PlanArgs {
+    /// Config or registry JSON whose `endpoints` (and `endpointTemplates`) carry lat/lon.
+    #[arg(long)]
+    anchors: PathBuf,
+
+    /// One of EU, NA, US, SA, AF, ME, AS, OC, WORLD.
+    #[arg(long, required_unless_present = "target_bbox")]
+    target_region: Option<String>,
+
+    /// `LAT_MIN,LON_MIN,LAT_MAX,LON_MAX` instead of a named region.
+    #[arg(long, conflicts_with = "target_region")]
+    target_bbox: Option<String>,
+
+    #[arg(long, default_value_t = DEFAULT_PLAN_GRID_DEG)]
+    grid_deg: f64,
+
+    /// How many additional anchor locations to suggest.
+    #[arg(long, default_value_t = DEFAULT_SUGGESTIONS)]
+    suggest: usize,
+
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Area {
+    pub lat_min: f64,
+    pub lat_max: f64,
+    pub lon_min: f64,
+    pub lon_max: f64,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Anchor {
+    pub id: String,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// Horizontal DOP over the target grid. Points where the anchors cannot fix
+/// a position (fewer than three usable bearings, or all collinear) count as
+/// uncovered rather than entering the averages.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DopSummary {
+    pub points: usize,
+    pub covered_fraction: f64,
+    pub mean_hdop: Option<f64>,
+    pub median_hdop: Option<f64>,
+    pub p90_hdop: Option<f64>,
+    pub worst_hdop: Option<f64>,
+    pub worst_lat: Option<f64>,
+    pub worst_lon: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Suggestion {
+    pub provider: String,
+    pub region: String,
+    pub lat: f64,
+    pub lon: f64,
+    pub dop: DopSummary,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PlanOutput {
+    anchors: Vec<String>,
+    current: DopSummary,
+    suggestions: Vec<Suggestion>,
+}
+
+pub fn run(args: PlanArgs) -> io::Result<()> {
+    let area = match (&args.target_region, &args.target_bbox) {
+        (_, Some(bbox)) => parse_bbox(bbox),
+        (Some(name), None) => named_area(name),
+        (None, None) => None,
+    }
+    .ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "unknown --target-region or malformed --target-bbox",
+        )
+    })?;
+    if !(args.grid_deg.is_finite() && args.grid_deg > 0.0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--grid-deg must be > 0",
+        ));
+    }
+    let anchors = load_anchors(&args.anchors)?;
+    let current = dop_summary(&anchors, area, args.grid_deg);
+    let suggestions = suggest(&anchors, area, args.grid_deg, args.suggest);
+
+    if args.json {
+        let output = PlanOutput {
+            anchors: anchors.iter().map(|a| a.id.clone()).collect(),
+            current,
+            suggestions,
+        };
+        let text = serde_json::to_string_pretty(&output).map_err(io::Error::other)?;
+        println!("{text}");
+        return Ok(());
+    }
+
+    println!("Anchors with coordinates: {}", anchors.len());
+    print_summary("Current", &current);
+    if suggestions.is_empty() {
+        println!("\nNo bundled cloud region improves coverage.");
+    } else {
+        println!("\nSuggested additions (greedy, each assumes the ones above are added):");
+    }
+    for (i, s) in suggestions.iter().enumerate() {
+        println!(
+            "{}. {} {} ({:.2}, {:.2})",
+            i + 1,
+            s.provider,
+            s.region,
+            s.lat,
+            s.lon
+        );
+        print_summary("   after", &s.dop);
+    }
+    println!("\nHDOP multiplies the range error of a nearby anchor; an anchor's error is taken to double every {:.0} km.", RANGE_ERROR_DOUBLING_KM);
+    println!("1 ms of RTT error is ~100 km of range in fibre, so HDOP 2 means ~200 km of position error.");
+    Ok(())
+}
+
+fn print_summary(label: &str, dop: &DopSummary) {
+    match (dop.mean_hdop, dop.p90_hdop, dop.worst_hdop) {
+        (Some(mean), Some(p90), Some(worst)) => println!(
+            "{}: covered {:.0}% of {} points, HDOP mean={:.2} p90={:.2} worst={:.2} at ({}, {})",
+            label,
+            dop.covered_fraction * 100.0,
+            dop.points,
+            mean,
+            p90,
+            worst,
+            fmt_opt(dop.worst_lat, 1),
+            fmt_opt(dop.worst_lon, 1)
+        ),
+        _ => println!(
+            "{}: no point of the {} in the area is covered (need three anchors that are not collinear)",
+            label, dop.points
+        ),
+    }
+}
+
+pub(crate) fn named_area(name: &str) -> Option<Area> {
+    TARGET_REGIONS
+        .iter()
+        .find(|(n, ..)| n.eq_ignore_ascii_case(name))
+        .map(|&(_, lat_min, lat_max, lon_min, lon_max)| Area {
+            lat_min,
+            lat_max,
+            lon_min,
+            lon_max,
+        })
+}
+
+pub(crate) fn parse_bbox(text: &str) -> Option<Area> {
+    let v: Vec<f64> = text
+        .split(',')
+        .map(|p| p.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    match v[..] {
+        [lat_min, lon_min, lat_max, lon_max] if lat_min < lat_max && lon_min < lon_max => {
+            Some(Area {
+                lat_min,
+                lat_max,
+                lon_min,
+                lon_max,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Reads endpoints with coordinates from any config-shaped JSON; a registry
+/// need not carry the rest of a client config.
+fn load_anchors(path: &PathBuf) -> io::Result<Vec<Anchor>> {
+    let data = fs::read(path)?;
+    let mut doc: Value =
+        serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    expand_endpoint_templates(&mut doc)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let anchors: Vec<Anchor> = doc
+        .get("endpoints")
+        .and_then(Value::as_array)
+        .map(|eps| {
+            eps.iter()
+                .filter_map(|ep| {
+                    Some(Anchor {
+                        id: ep.get("id")?.as_str()?.to_string(),
+                        lat: ep.get("lat")?.as_f64()?,
+                        lon: ep.get("lon")?.as_f64()?,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if anchors.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has no endpoints with lat/lon", path.display()),
+        ));
+    }
+    Ok(anchors)
+}
+
+/// HDOP alone, as `dop_at`.
+pub(crate) fn hdop_at(anchors: &[Anchor], lat: f64, lon: f64) -> Option<f64> {
+    let points: Vec<(f64, f64)> = anchors.iter().map(|a| (a.lat, a.lon)).collect();
+    dop_at(&points, lat, lon).map(|d| d.hdop)
+}
+
+pub(crate) fn dop_summary(anchors: &[Anchor], area: Area, grid_deg: f64) -> DopSummary {
+    let mut values: Vec<(f64, f64, f64)> = Vec::new();
+    let mut points = 0usize;
+    let mut lat = area.lat_min;
+    while lat <= area.lat_max {
+        let mut lon = area.lon_min;
+        while lon <= area.lon_max {
+            points += 1;
+            if let Some(h) = hdop_at(anchors, lat, lon) {
+                values.push((h, lat, lon));
+            }
+            lon += grid_deg;
+        }
+        lat += grid_deg;
+    }
+    let mut sorted: Vec<f64> = values.iter().map(|v| v.0).collect();
+    sorted.sort_by(f64::total_cmp);
+    let worst = values.iter().copied().max_by(|a, b| a.0.total_cmp(&b.0));
+    DopSummary {
+        points,
+        covered_fraction: if points == 0 {
+            0.0
+        } else {
+            values.len() as f64 / points as f64
+        },
+        mean_hdop: (!sorted.is_empty()).then(|| sorted.iter().sum::<f64>() / sorted.len() as f64),
+        median_hdop: crate::quantile(&sorted, 0.5),
+        p90_hdop: crate::quantile(&sorted, P90),
+        worst_hdop: worst.map(|w| w.0),
+        worst_lat: worst.map(|w| w.1),
+        worst_lon: worst.map(|w| w.2),
+    }
+}
+
+/// Coverage first, then mean HDOP: an anchor that makes more of the area
+/// solvable beats one that sharpens an already covered part.
+fn better(a: &DopSummary, b: &DopSummary) -> bool {
+    match a.covered_fraction.total_cmp(&b.covered_fraction) {
+        std::cmp::Ordering::Equal => {
+            a.mean_hdop.unwrap_or(f64::INFINITY) < b.mean_hdop.unwrap_or(f64::INFINITY)
+        }
+        ord => ord.is_gt(),
+    }
+}
+
+/// Greedily picks bundled cloud regions that most improve the area's DOP.
+pub(crate) fn suggest(
+    anchors: &[Anchor],
+    area: Area,
+    grid_deg: f64,
+    count: usize,
+) -> Vec<Suggestion> {
+    let mut current: Vec<Anchor> = anchors.to_vec();
+    let mut baseline = dop_summary(&current, area, grid_deg);
+    let mut out = Vec::new();
+    for _ in 0..count {
+        let mut best: Option<Suggestion> = None;
+        for region in CLOUD_REGIONS {
+            let near_existing = current.iter().any(|a| {
+                haversine_km(a.lat, a.lon, region.lat, region.lon) < CANDIDATE_MIN_SEPARATION_KM
+            });
+            if near_existing {
+                continue;
+            }
+            let mut trial = current.clone();
+            trial.push(Anchor {
+                id: region.code.to_string(),
+                lat: region.lat,
+                lon: region.lon,
+            });
+            let dop = dop_summary(&trial, area, grid_deg);
+            if better(&dop, best.as_ref().map_or(&baseline, |b| &b.dop)) {
+                best = Some(Suggestion {
+                    provider: region.provider.to_string(),
+                    region: region.code.to_string(),
+                    lat: region.lat,
+                    lon: region.lon,
+                    dop,
+                });
+            }
+        }
+        let Some(pick) = best else { break };
+        current.push(Anchor {
+            id: pick.region.clone(),
+            lat: pick.lat,
+            lon: pick.lon,
+        });
+        baseline = pick.dop.clone();
+        out.push(pick);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::LOPSIDED_ELLIPSE_RATIO;
+
+    #[test]
+    fn plan_dop_prefers_surrounding_anchors_and_suggests_improvements() {
+        let anchor = |id: &str, lat: f64, lon: f64| Anchor {
+            id: id.to_string(),
+            lat,
+            lon,
+        };
+        let area = Area {
+            lat_min: 45.0,
+            lat_max: 55.0,
+            lon_min: 0.0,
+            lon_max: 15.0,
+        };
+        // Three anchors on one meridian leave the east-west axis unresolved.
+        let collinear = vec![
+            anchor("a", 40.0, 7.0),
+            anchor("b", 50.0, 7.0),
+            anchor("c", 60.0, 7.0),
+        ];
+        let around = vec![
+            anchor("n", 60.0, 7.0),
+            anchor("sw", 40.0, -5.0),
+            anchor("se", 40.0, 20.0),
+        ];
+        assert!(hdop_at(&collinear, 50.0, 7.0).is_none());
+        let centre = hdop_at(&around, 50.0, 7.0).unwrap();
+        assert!(centre > 0.0 && centre < 3.0);
+
+        let current = dop_summary(&around, area, 2.5);
+        assert_eq!(current.covered_fraction, 1.0);
+        let picks = suggest(&collinear, area, 2.5, 1);
+        assert_eq!(picks.len(), 1);
+        let improved = &picks[0].dop;
+        assert!(improved.covered_fraction > 0.0);
+    }
+
+    #[test]
+    fn geometry_ellipse_stretches_away_from_one_sided_anchors() {
+        let around = dop_at(
+            &[(5.0, 0.0), (-5.0, 0.0), (0.0, 5.0), (0.0, -5.0)],
+            0.0,
+            0.0,
+        )
+        .unwrap();
+        assert!((around.ellipse_major / around.ellipse_minor - 1.0).abs() < 0.01);
+        assert!(around.gdop > around.hdop);
+
+        // All anchors to the east: ranges can't tell an east-west shift from
+        // the common access delay, so the ellipse runs east-west.
+        let east = dop_at(&[(0.0, 5.0), (4.0, 5.0), (-4.0, 5.0)], 0.0, 0.0).unwrap();
+        assert!(east.ellipse_major / east.ellipse_minor > LOPSIDED_ELLIPSE_RATIO);
+        assert!((east.ellipse_bearing_deg - 90.0).abs() < 1.0);
+        assert!(east.hdop > around.hdop);
+
+        let north = dop_at(&[(5.0, 0.0), (5.0, 4.0), (5.0, -4.0)], 0.0, 0.0).unwrap();
+        assert!(north.ellipse_bearing_deg < 1.0 || north.ellipse_bearing_deg > 179.0);
+        assert!(dop_at(&[(1.0, 1.0), (2.0, 2.0)], 0.0, 0.0).is_none());
+    }
+}
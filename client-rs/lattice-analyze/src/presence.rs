@@ -0,0 +1,678 @@
+use crate::constants::*;
+use crate::coverage::fmt_utc;
+use crate::fleet::tunnel_state;
+use crate::heatmap::xml_escape;
+use crate::propagation::{Linear, PropagationModel};
+use crate::units::{DistanceUnit, ReportFormat};
+use crate::{
+    adjust_rtt_ms, build_stats, claim_check, endpoints_by_id, estimate_location, haversine_km,
+    load_calibration, load_jsonl, stats_endpoint, transport, Calibration, DistanceModel,
+    EndpointStats, SampleGate, Weighting,
+};
+use clap::{Parser, ValueEnum};
+use lattice_core::{BurstRecord, Config, Endpoint};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const PRESENCE_SCHEMA: &str = "lattice-presence/1";
+const DEFAULT_WINDOW_S: u64 = 3600;
+/// Cell size of the feasible-region scan; coarser than the fit, which only
+/// needs to find one point, because every cell is checked against every anchor.
+const DEFAULT_FEASIBLE_GRID_DEG: f64 = 1.0;
+const MS_PER_SEC: i64 = 1000;
+const KM_PER_DEG: f64 = 111.195;
+/// An estimate further than this from every reference point (mid-ocean, say)
+/// names no country.
+const MAX_COUNTRY_DISTANCE_KM: f64 = 1500.0;
+/// Saturation and lightness of the HTML row colours; the hue comes from the
+/// country code.
+const HTML_ROW_SL: &str = "70%, 88%";
+const HTML_UNKNOWN_FILL: &str = "#eeeeee";
+
+/// Reference points as (ISO 3166 alpha-2 code, lat, lon): capitals and, for
+/// large countries, a few more cities. The nearest one names the country;
+/// there are no borders offline, so a host near a frontier may get its
+/// neighbour.
+const COUNTRY_POINTS: &[(&str, f64, f64)] = &[
+    ("AE", 25.20, 55.27),
+    ("AR", -34.60, -58.38),
+    ("AR", -31.42, -64.18),
+    ("AT", 48.21, 16.37),
+    ("AU", -33.87, 151.21),
+    ("AU", -37.81, 144.96),
+    ("AU", -31.95, 115.86),
+    ("AU", -27.47, 153.03),
+    ("BD", 23.81, 90.41),
+    ("BE", 50.85, 4.35),
+    ("BG", 42.70, 23.32),
+    ("BR", -23.55, -46.63),
+    ("BR", -15.79, -47.88),
+    ("BR", -3.12, -60.02),
+    ("BR", -8.05, -34.88),
+    ("CA", 43.65, -79.38),
+    ("CA", 45.50, -73.57),
+    ("CA", 49.28, -123.12),
+    ("CA", 51.05, -114.07),
+    ("CH", 47.38, 8.54),
+    ("CL", -33.45, -70.67),
+    ("CN", 39.90, 116.40),
+    ("CN", 31.23, 121.47),
+    ("CN", 23.13, 113.26),
+    ("CN", 30.57, 104.07),
+    ("CO", 4.71, -74.07),
+    ("CZ", 50.08, 14.44),
+    ("DE", 52.52, 13.40),
+    ("DE", 50.11, 8.68),
+    ("DE", 48.14, 11.58),
+    ("DK", 55.68, 12.57),
+    ("EE", 59.44, 24.75),
+    ("EG", 30.04, 31.24),
+    ("ES", 40.42, -3.70),
+    ("ES", 41.39, 2.17),
+    ("FI", 60.17, 24.94),
+    ("FR", 48.86, 2.35),
+    ("FR", 43.30, 5.37),
+    ("GB", 51.51, -0.13),
+    ("GB", 53.48, -2.24),
+    ("GR", 37.98, 23.73),
+    ("HK", 22.32, 114.17),
+    ("HR", 45.81, 15.98),
+    ("HU", 47.50, 19.04),
+    ("ID", -6.21, 106.85),
+    ("IE", 53.35, -6.26),
+    ("IL", 32.09, 34.78),
+    ("IN", 28.61, 77.21),
+    ("IN", 19.08, 72.88),
+    ("IN", 12.97, 77.59),
+    ("IN", 22.57, 88.36),
+    ("IR", 35.69, 51.39),
+    ("IS", 64.15, -21.94),
+    ("IT", 41.90, 12.50),
+    ("IT", 45.46, 9.19),
+    ("JP", 35.68, 139.65),
+    ("JP", 34.69, 135.50),
+    ("KE", -1.29, 36.82),
+    ("KR", 37.57, 126.98),
+    ("KZ", 43.24, 76.89),
+    ("LT", 54.69, 25.28),
+    ("LU", 49.61, 6.13),
+    ("LV", 56.95, 24.11),
+    ("MA", 33.57, -7.59),
+    ("MX", 19.43, -99.13),
+    ("MX", 25.69, -100.32),
+    ("MY", 3.139, 101.69),
+    ("NG", 6.52, 3.38),
+    ("NL", 52.37, 4.90),
+    ("NO", 59.91, 10.75),
+    ("NZ", -36.85, 174.76),
+    ("PE", -12.05, -77.04),
+    ("PH", 14.60, 120.98),
+    ("PK", 24.86, 67.01),
+    ("PL", 52.23, 21.01),
+    ("PT", 38.72, -9.14),
+    ("QA", 25.29, 51.53),
+    ("RO", 44.43, 26.10),
+    ("RS", 44.79, 20.45),
+    ("RU", 55.76, 37.62),
+    ("RU", 59.93, 30.34),
+    ("RU", 55.03, 82.92),
+    ("RU", 43.12, 131.89),
+    ("SA", 24.71, 46.68),
+    ("SE", 59.33, 18.07),
+    ("SG", 1.35, 103.82),
+    ("SK", 48.15, 17.11),
+    ("TH", 13.76, 100.50),
+    ("TR", 41.01, 28.98),
+    ("TR", 39.93, 32.86),
+    ("TW", 25.03, 121.57),
+    ("UA", 50.45, 30.52),
+    ("US", 40.71, -74.01),
+    ("US", 38.90, -77.04),
+    ("US", 41.88, -87.63),
+    ("US", 33.75, -84.39),
+    ("US", 29.76, -95.37),
+    ("US", 39.74, -104.99),
+    ("US", 37.77, -122.42),
+    ("US", 34.05, -118.24),
+    ("US", 47.61, -122.33),
+    ("US", 25.76, -80.19),
+    ("US", 61.22, -149.90),
+    ("US", 21.31, -157.86),
+    ("VN", 10.82, 106.63),
+    ("ZA", -26.20, 28.05),
+    ("ZA", -33.92, 18.42),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum PresenceFormat {
+    Text,
+    Json,
+    Html,
+}
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lattice-analyze presence",
+    about = "Where the host was over time: estimate, feasible region, and likely country per window"
+)]
+pub struct PresenceArgs {
+    #[arg(long)]
+    config: PathBuf,
+
+    #[arg(long = "override")]
+    overrides: Vec<PathBuf>,
+
+    /// Session file (repeatable): a week of daily captures reads as one timeline.
+    #[arg(long, required = true)]
+    session: Vec<PathBuf>,
+
+    #[arg(long)]
+    calibration: Option<PathBuf>,
+
+    /// Length of each window; windows are aligned to multiples of it.
+    #[arg(long, default_value_t = DEFAULT_WINDOW_S)]
+    window_s: u64,
+
+    /// Cell size for the feasible-region scan.
+    #[arg(long, default_value_t = DEFAULT_FEASIBLE_GRID_DEG)]
+    grid: f64,
+
+    #[arg(long, default_value_t = DEFAULT_SPEED_KM_S)]
+    speed_km_s: f64,
+
+    #[arg(long, default_value_t = DEFAULT_PATH_STRETCH)]
+    path_stretch: f64,
+
+    #[arg(long, value_enum, default_value_t = DistanceModel::Sphere)]
+    distance_model: DistanceModel,
+
+    #[arg(long, value_enum, default_value_t = PresenceFormat::Text)]
+    format: PresenceFormat,
+
+    /// Written to stdout when omitted.
+    #[arg(long)]
+    out: Option<PathBuf>,
+
+    /// Unit for distances in text and HTML output; JSON stays in km.
+    #[arg(long, value_enum, default_value_t = DistanceUnit::Km)]
+    units: DistanceUnit,
+
+    /// Locale for number separators in text and HTML output (default:
+    /// `LC_ALL`, `LC_NUMERIC`, or `LANG`).
+    #[arg(long)]
+    locale: Option<String>,
+}
+
+/// Bounding box of the grid cells whose centre every anchor's p05 bound
+/// reaches.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FeasibleRegion {
+    pub lat_min: f64,
+    pub lat_max: f64,
+    pub lon_min: f64,
+    pub lon_max: f64,
+    /// Area of the feasible cells themselves, not of the box.
+    pub area_km2: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PresenceWindow {
+    pub from_ts_ms: i64,
+    /// Exclusive.
+    pub to_ts_ms: i64,
+    pub bursts: usize,
+    /// `active`, `partial`, or `none`, as in `fleet`.
+    pub tunnel: &'static str,
+    pub anchors_used: usize,
+    pub estimate_lat: Option<f64>,
+    pub estimate_lon: Option<f64>,
+    pub radius_km: Option<f64>,
+    /// Nearest reference country to the estimate.
+    pub country: Option<&'static str>,
+    /// Countries with a reference point inside the feasible region.
+    pub feasible_countries: Vec<&'static str>,
+    /// Null when no anchor is bounded, or when the bounds have no point in
+    /// common (a tunnel mixing two paths, say).
+    pub feasible: Option<FeasibleRegion>,
+}
+
+/// Consecutive windows that named the same country.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Stay {
+    pub country: Option<&'static str>,
+    pub from_ts_ms: i64,
+    pub to_ts_ms: i64,
+    pub windows: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PresenceTimeline {
+    pub schema: &'static str,
+    pub window_s: u64,
+    pub windows: Vec<PresenceWindow>,
+    pub stays: Vec<Stay>,
+}
+
+pub(crate) struct PresenceAnalysis<'a> {
+    pub endpoints: &'a HashMap<String, Endpoint>,
+    pub gate: SampleGate,
+    pub model: &'a dyn PropagationModel,
+    pub calibration: Option<&'a Calibration>,
+    pub distance: DistanceModel,
+    pub grid: f64,
+}
+
+impl PresenceAnalysis<'_> {
+    /// One main-analyzer estimate per window with bursts; windows without
+    /// any are left out, so gaps show in the timestamps.
+    pub fn timeline(&self, records: &[BurstRecord], window_s: u64) -> PresenceTimeline {
+        let window_ms = window_s.max(1) as i64 * MS_PER_SEC;
+        let mut by_window: BTreeMap<i64, Vec<BurstRecord>> = BTreeMap::new();
+        for rec in records.iter().filter(|r| r.ts_unix_ms > 0) {
+            by_window
+                .entry(rec.ts_unix_ms.div_euclid(window_ms))
+                .or_default()
+                .push(rec.clone());
+        }
+        let windows: Vec<PresenceWindow> = by_window
+            .into_iter()
+            .map(|(index, mut records)| self.window(index * window_ms, window_ms, &mut records))
+            .collect();
+        let mut stays: Vec<Stay> = Vec::new();
+        for w in &windows {
+            match stays.last_mut() {
+                Some(stay) if stay.country == w.country => {
+                    stay.to_ts_ms = w.to_ts_ms;
+                    stay.windows += 1;
+                }
+                _ => stays.push(Stay {
+                    country: w.country,
+                    from_ts_ms: w.from_ts_ms,
+                    to_ts_ms: w.to_ts_ms,
+                    windows: 1,
+                }),
+            }
+        }
+        PresenceTimeline {
+            schema: PRESENCE_SCHEMA,
+            window_s: window_s.max(1),
+            windows,
+            stays,
+        }
+    }
+
+    fn window(
+        &self,
+        from_ts_ms: i64,
+        window_ms: i64,
+        records: &mut [BurstRecord],
+    ) -> PresenceWindow {
+        let (_, tunnel) = tunnel_state(records);
+        transport::correct_transport_bias(records, self.calibration, true);
+        let mut stats = build_stats(records, None);
+        self.gate.apply(&mut stats);
+        let estimate = estimate_location(
+            &stats,
+            self.endpoints,
+            self.model,
+            DEFAULT_GRID_DEG,
+            DEFAULT_REFINE_DEG,
+            DEFAULT_BAND_FACTOR,
+            DEFAULT_BAND_WINDOW_DEG,
+            0.0,
+            self.calibration,
+            Weighting::default(),
+            self.distance,
+        );
+        let bounds = self.tight_bounds(&stats);
+        let feasible = |point: (f64, f64)| {
+            bounds.iter().all(|&(ep, rtt)| {
+                claim_check("", ep, point, Some(rtt), None, self.model, self.distance)
+                    .and_then(|c| c.falsify_tight)
+                    != Some(true)
+            })
+        };
+        let feasible_countries: BTreeSet<&'static str> = if bounds.is_empty() {
+            BTreeSet::new()
+        } else {
+            COUNTRY_POINTS
+                .iter()
+                .filter(|&&(_, lat, lon)| feasible((lat, lon)))
+                .map(|&(code, ..)| code)
+                .collect()
+        };
+        PresenceWindow {
+            from_ts_ms,
+            to_ts_ms: from_ts_ms + window_ms,
+            bursts: records.len(),
+            tunnel,
+            anchors_used: estimate.as_ref().map_or(0, |e| e.points),
+            estimate_lat: estimate.as_ref().map(|e| e.lat),
+            estimate_lon: estimate.as_ref().map(|e| e.lon),
+            radius_km: estimate
+                .as_ref()
+                .and_then(|e| e.band.as_ref())
+                .map(|b| b.radius_km),
+            country: estimate
+                .as_ref()
+                .and_then(|e| nearest_country(e.lat, e.lon)),
+            feasible_countries: feasible_countries.into_iter().collect(),
+            feasible: if bounds.is_empty() {
+                None
+            } else {
+                scan_feasible(self.grid, feasible)
+            },
+        }
+    }
+
+    /// Each bounded anchor with its floored, calibrated p05, as the claim
+    /// check's tight bound uses it.
+    fn tight_bounds<'s>(
+        &'s self,
+        stats: &HashMap<String, EndpointStats>,
+    ) -> Vec<(&'s Endpoint, f64)> {
+        let mut ids: Vec<&String> = stats.keys().collect();
+        ids.sort();
+        ids.into_iter()
+            .filter_map(|id| {
+                let st = &stats[id];
+                if st.excluded.is_some() {
+                    return None;
+                }
+                let ep = stats_endpoint(self.endpoints, id)?;
+                let rtt = adjust_rtt_ms(st.p05?, st.floor_ms(), id, self.calibration);
+                (ep.lat.is_some() && ep.lon.is_some()).then_some((ep, rtt))
+            })
+            .collect()
+    }
+}
+
+fn nearest_country(lat: f64, lon: f64) -> Option<&'static str> {
+    COUNTRY_POINTS
+        .iter()
+        .map(|&(code, clat, clon)| (code, haversine_km(lat, lon, clat, clon)))
+        .filter(|&(_, km)| km <= MAX_COUNTRY_DISTANCE_KM)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(code, _)| code)
+}
+
+/// Checks the centre of every `grid`-degree cell of the globe; the box
+/// spans the whole of each feasible cell.
+fn scan_feasible(grid: f64, feasible: impl Fn((f64, f64)) -> bool) -> Option<FeasibleRegion> {
+    let rows = (2.0 * WORLD_LAT_MAX / grid).ceil() as usize;
+    let columns = (2.0 * WORLD_LON_MAX / grid).ceil() as usize;
+    let mut region: Option<FeasibleRegion> = None;
+    for row in 0..rows {
+        let lat = -WORLD_LAT_MAX + (row as f64 + 0.5) * grid;
+        let cell_km2 = (grid * KM_PER_DEG).powi(2) * lat.to_radians().cos();
+        for column in 0..columns {
+            let lon = -WORLD_LON_MAX + (column as f64 + 0.5) * grid;
+            if !feasible((lat, lon)) {
+                continue;
+            }
+            let half = grid / 2.0;
+            let r = region.get_or_insert(FeasibleRegion {
+                lat_min: lat - half,
+                lat_max: lat + half,
+                lon_min: lon - half,
+                lon_max: lon + half,
+                area_km2: 0.0,
+            });
+            r.lat_min = r.lat_min.min(lat - half);
+            r.lat_max = r.lat_max.max(lat + half);
+            r.lon_min = r.lon_min.min(lon - half);
+            r.lon_max = r.lon_max.max(lon + half);
+            r.area_km2 += cell_km2;
+        }
+    }
+    region
+}
+
+fn window_line(w: &PresenceWindow, fmt: &ReportFormat) -> (String, String, String) {
+    let estimate = match (w.estimate_lat, w.estimate_lon) {
+        (Some(lat), Some(lon)) => {
+            let radius = match w.radius_km {
+                Some(km) => format!(" ± {} {}", fmt.dist(km, 0), fmt.unit.label()),
+                None => String::new(),
+            };
+            format!(
+                "{}{} {}{}",
+                fmt.num(lat, 2),
+                fmt.list_separator(),
+                fmt.num(lon, 2),
+                radius
+            )
+        }
+        _ => "-".to_string(),
+    };
+    let region = match &w.feasible {
+        Some(r) => format!(
+            "lat {}..{} lon {}..{} ({} {}²)",
+            fmt.num(r.lat_min, 1),
+            fmt.num(r.lat_max, 1),
+            fmt.num(r.lon_min, 1),
+            fmt.num(r.lon_max, 1),
+            fmt.num(r.area_km2 * fmt.in_unit(1.0).powi(2), 0),
+            fmt.unit.label()
+        ),
+        None => "-".to_string(),
+    };
+    let countries = if w.feasible_countries.is_empty() {
+        "-".to_string()
+    } else {
+        w.feasible_countries.join(", ")
+    };
+    (estimate, region, countries)
+}
+
+fn to_text(timeline: &PresenceTimeline, fmt: &ReportFormat) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "Presence ({}s windows):", timeline.window_s);
+    for w in &timeline.windows {
+        let (estimate, region, countries) = window_line(w, fmt);
+        let _ = writeln!(
+            out,
+            "- {} bursts={} tunnel={} country={} estimate={} feasible={} [{}]",
+            fmt_utc(w.from_ts_ms),
+            w.bursts,
+            w.tunnel,
+            w.country.unwrap_or("-"),
+            estimate,
+            region,
+            countries
+        );
+    }
+    let _ = writeln!(out, "\nStays:");
+    for s in &timeline.stays {
+        let _ = writeln!(
+            out,
+            "- {} .. {} {} ({} window(s))",
+            fmt_utc(s.from_ts_ms),
+            fmt_utc(s.to_ts_ms),
+            s.country.unwrap_or("unknown"),
+            s.windows
+        );
+    }
+    out
+}
+
+/// Row colour keyed on the country code, so the same country reads the same
+/// throughout the page.
+fn country_fill(country: Option<&str>) -> String {
+    match country {
+        Some(code) => {
+            let hue = code
+                .bytes()
+                .fold(0u32, |h, b| h.wrapping_mul(31).wrapping_add(b as u32))
+                % 360;
+            format!("hsl({hue}, {HTML_ROW_SL})")
+        }
+        None => HTML_UNKNOWN_FILL.to_string(),
+    }
+}
+
+/// A standalone page: the stays first, then every window.
+fn to_html(timeline: &PresenceTimeline, fmt: &ReportFormat) -> String {
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>LATTICE presence timeline</title>\n");
+    out.push_str(
+        "<style>body{font-family:sans-serif;margin:1.5em}table{border-collapse:collapse;margin-bottom:2em}\
+         td,th{padding:3px 10px;border-bottom:1px solid #ccc;text-align:left;font-size:13px}</style>\n</head><body>\n",
+    );
+    let _ = writeln!(
+        out,
+        "<h1>Presence timeline</h1>\n<p>{} window(s) of {}s. Country is the nearest reference point to the estimate; the feasible region is where every anchor's p05 bound reaches.</p>",
+        timeline.windows.len(),
+        timeline.window_s
+    );
+    out.push_str("<h2>Stays</h2>\n<table><tr><th>From</th><th>To</th><th>Country</th><th>Windows</th></tr>\n");
+    for s in &timeline.stays {
+        let _ = writeln!(
+            out,
+            "<tr style=\"background:{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            country_fill(s.country),
+            fmt_utc(s.from_ts_ms),
+            fmt_utc(s.to_ts_ms),
+            xml_escape(s.country.unwrap_or("unknown")),
+            s.windows
+        );
+    }
+    out.push_str("</table>\n<h2>Windows</h2>\n<table><tr><th>Start</th><th>Bursts</th><th>Tunnel</th><th>Country</th><th>Estimate</th><th>Feasible region</th><th>Feasible countries</th></tr>\n");
+    for w in &timeline.windows {
+        let (estimate, region, countries) = window_line(w, fmt);
+        let _ = writeln!(
+            out,
+            "<tr style=\"background:{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            country_fill(w.country),
+            fmt_utc(w.from_ts_ms),
+            w.bursts,
+            w.tunnel,
+            xml_escape(w.country.unwrap_or("-")),
+            xml_escape(&estimate),
+            xml_escape(&region),
+            xml_escape(&countries)
+        );
+    }
+    out.push_str("</table>\n</body></html>\n");
+    out
+}
+
+pub fn run(args: PresenceArgs) -> io::Result<()> {
+    if args.window_s == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--window-s must be > 0",
+        ));
+    }
+    if !args.grid.is_finite() || args.grid <= 0.0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--grid must be a finite number > 0",
+        ));
+    }
+    let cfg = Config::load_layered(&args.config, &args.overrides)?;
+    let endpoints = endpoints_by_id(&cfg.endpoints);
+    let calibration = match &args.calibration {
+        Some(path) => Some(load_calibration(path)?),
+        None => None,
+    };
+    let model = Linear {
+        speed_km_s: args.speed_km_s / args.path_stretch.max(MIN_PATH_STRETCH),
+    };
+    let mut records = Vec::new();
+    for path in &args.session {
+        records.extend(load_jsonl(path)?);
+    }
+    let analysis = PresenceAnalysis {
+        endpoints: &endpoints,
+        gate: SampleGate {
+            min_samples: cfg.min_samples_per_endpoint,
+            min_bursts: cfg.min_bursts_per_endpoint,
+        },
+        model: &model,
+        calibration: calibration.as_ref(),
+        distance: args.distance_model,
+        grid: args.grid,
+    };
+    let timeline = analysis.timeline(&records, args.window_s);
+    let fmt = ReportFormat::new(args.units, args.locale.as_deref(), DEFAULT_DISPLAY_DECIMALS);
+    let text = match args.format {
+        PresenceFormat::Text => to_text(&timeline, &fmt),
+        PresenceFormat::Json => {
+            serde_json::to_string_pretty(&timeline).map_err(io::Error::other)? + "\n"
+        }
+        PresenceFormat::Html => to_html(&timeline, &fmt),
+    };
+    match &args.out {
+        Some(path) => {
+            fs::write(path, text)?;
+            eprintln!(
+                "wrote {} window(s) to {}",
+                timeline.windows.len(),
+                path.display()
+            );
+        }
+        None => print!("{text}"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn presence_timeline_follows_a_move_between_windows() {
+        let anchors = [
+            ("fra", 50.11, 8.68),
+            ("lon", 51.51, -0.13),
+            ("nyc", 40.71, -74.01),
+            ("chi", 41.88, -87.63),
+        ];
+        let endpoints = endpoints_by_id(
+            &anchors
+                .iter()
+                .map(|&(id, lat, lon)| endpoint(id, lat, lon))
+                .collect::<Vec<_>>(),
+        );
+        // An hour near Frankfurt, then an hour near Washington.
+        let mut records = Vec::new();
+        for (hour, (lat, lon)) in [(49.5, 8.5), (38.9, -77.0)].into_iter().enumerate() {
+            for &(id, alat, alon) in &anchors {
+                let rtt = RTT_FACTOR * haversine_km(lat, lon, alat, alon) / DEFAULT_SPEED_KM_S
+                    * MS_PER_SEC as f64;
+                let mut rec = record(id, vec![rtt + 0.5, rtt + 1.0, rtt + 2.0]);
+                rec.ts_unix_ms = (hour as i64 + 1) * 3_600_000 + 60_000;
+                records.push(rec);
+            }
+        }
+        let analysis = PresenceAnalysis {
+            endpoints: &endpoints,
+            gate: SampleGate::default(),
+            model: &TEST_MODEL,
+            calibration: None,
+            distance: DistanceModel::Sphere,
+            grid: 2.0,
+        };
+        let timeline = analysis.timeline(&records, 3600);
+        let countries: Vec<_> = timeline.windows.iter().map(|w| w.country).collect();
+        assert_eq!(countries, vec![Some("DE"), Some("US")]);
+        assert_eq!(timeline.windows[0].from_ts_ms, 3_600_000);
+        assert_eq!(timeline.windows[1].to_ts_ms, 3 * 3_600_000);
+        assert!(timeline.windows[0].feasible_countries.contains(&"DE"));
+        assert!(!timeline.windows[0].feasible_countries.contains(&"US"));
+        let region = timeline.windows[1].feasible.as_ref().unwrap();
+        assert!(region.lat_min <= 38.9 && 38.9 <= region.lat_max);
+        assert!(region.lon_min <= -77.0 && -77.0 <= region.lon_max);
+        assert_eq!(timeline.stays.len(), 2);
+    }
+}
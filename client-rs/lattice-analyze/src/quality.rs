@@ -0,0 +1,380 @@
+use crate::constants::*;
+use crate::plan::{hdop_at, Anchor};
+use crate::units::ReportFormat;
+use crate::{quantile, Calibration, EndpointStats};
+use lattice_core::{BurstRecord, ClockQuality, Endpoint};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const GRADES: [char; 4] = ['A', 'B', 'C', 'D'];
+const MS_PER_DAY: f64 = 86_400_000.0;
+const PERCENT: f64 = 100.0;
+/// Factors that are whole counts, printed without decimals.
+const COUNT_FACTORS: [&str; 2] = ["anchors", "samples"];
+const FACTOR_DECIMALS: usize = 2;
+
+/// Limits for grades `A`, `B`, and `C` per factor; anything worse than the
+/// `C` limit is `D`. `--quality-thresholds` takes a JSON object with any of
+/// these keys and keeps the defaults for the rest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct QualityThresholds {
+    /// Fewest anchors with a usable p05 and coordinates.
+    pub anchors: [f64; 3],
+    /// Largest HDOP at the estimate (or claim): how well the anchors
+    /// surround it.
+    pub hdop: [f64; 3],
+    /// Fewest valid samples on any usable anchor.
+    pub samples: [f64; 3],
+    /// Largest share of probes lost, over usable anchors.
+    pub loss_pct: [f64; 3],
+    /// Largest median jitter over usable anchors.
+    pub jitter_ms: [f64; 3],
+    /// Oldest `--calibration` table; not graded without one.
+    pub calibration_age_days: [f64; 3],
+    /// Largest clock-limited error on a 10 ms RTT. An unsynced clock is one
+    /// grade worse.
+    pub clock_error_ms: [f64; 3],
+}
+
+impl Default for QualityThresholds {
+    fn default() -> Self {
+        QualityThresholds {
+            anchors: [5.0, 4.0, 3.0],
+            hdop: [2.0, 4.0, 8.0],
+            samples: [200.0, 100.0, 30.0],
+            loss_pct: [1.0, 5.0, 15.0],
+            jitter_ms: [2.0, 5.0, 15.0],
+            calibration_age_days: [7.0, 30.0, 90.0],
+            clock_error_ms: [0.01, 0.1, 1.0],
+        }
+    }
+}
+
+impl QualityThresholds {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let thresholds: Self = serde_json::from_slice(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        thresholds.validate()?;
+        Ok(thresholds)
+    }
+
+    pub fn load_or_default(path: Option<&Path>) -> io::Result<Self> {
+        match path {
+            Some(path) => Self::load(path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn validate(&self) -> io::Result<()> {
+        let checks: [(&str, [f64; 3], bool); 7] = [
+            ("anchors", self.anchors, true),
+            ("hdop", self.hdop, false),
+            ("samples", self.samples, true),
+            ("lossPct", self.loss_pct, false),
+            ("jitterMs", self.jitter_ms, false),
+            ("calibrationAgeDays", self.calibration_age_days, false),
+            ("clockErrorMs", self.clock_error_ms, false),
+        ];
+        for (name, limits, higher_is_better) in checks {
+            let ordered = limits.windows(2).all(|w| {
+                if higher_is_better {
+                    w[0] >= w[1]
+                } else {
+                    w[0] <= w[1]
+                }
+            });
+            if limits.iter().any(|v| !v.is_finite() || *v < 0.0) || !ordered {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("quality threshold {name} must be three finite numbers >= 0, from the A limit to the C limit"),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One graded input. `grade` is null when the factor could not be measured
+/// (no calibration, no clock self-test).
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct QualityFactor {
+    pub factor: &'static str,
+    pub value: Option<f64>,
+    pub grade: Option<char>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DataQuality {
+    /// The worst factor's grade, `A` (best) to `D`.
+    pub grade: char,
+    pub factors: Vec<QualityFactor>,
+}
+
+impl DataQuality {
+    /// Factors below `A`, worst first.
+    pub fn weakest(&self) -> Vec<&QualityFactor> {
+        let mut weak: Vec<&QualityFactor> = self
+            .factors
+            .iter()
+            .filter(|f| f.grade.is_some_and(|g| g != 'A'))
+            .collect();
+        weak.sort_by_key(|f| std::cmp::Reverse(f.grade));
+        weak
+    }
+}
+
+fn grade(value: f64, limits: [f64; 3], higher_is_better: bool) -> char {
+    let passes = |limit: f64| {
+        if higher_is_better {
+            value >= limit
+        } else {
+            value <= limit
+        }
+    };
+    limits
+        .iter()
+        .position(|&limit| passes(limit))
+        .map_or(GRADES[GRADES.len() - 1], |i| GRADES[i])
+}
+
+fn one_worse(g: char) -> char {
+    let i = GRADES
+        .iter()
+        .position(|&x| x == g)
+        .unwrap_or(GRADES.len() - 1);
+    GRADES[(i + 1).min(GRADES.len() - 1)]
+}
+
+/// What the grade is computed from: the gated stats the estimate used, the
+/// records behind them, and where the host is placed (estimate, else claim).
+pub(crate) struct QualityInputs<'a> {
+    pub stats: &'a HashMap<String, EndpointStats>,
+    pub endpoints: &'a HashMap<String, Endpoint>,
+    pub records: &'a [BurstRecord],
+    /// `samplesPerEndpoint` from the config: probes sent per burst.
+    pub samples_per_burst: usize,
+    pub at: Option<(f64, f64)>,
+    pub calibration: Option<&'a Calibration>,
+    pub clock: Option<&'a ClockQuality>,
+    pub now_ms: i64,
+}
+
+/// Grades each factor against `thresholds`; the session's grade is the
+/// worst of them, so one weak input is enough to mark an estimate as soft.
+pub(crate) fn assess(thresholds: &QualityThresholds, inputs: &QualityInputs) -> DataQuality {
+    let usable: Vec<(&String, &EndpointStats)> = inputs
+        .stats
+        .iter()
+        .filter(|(_, st)| st.excluded.is_none() && st.p05.is_some())
+        .collect();
+    let anchors: Vec<Anchor> = usable
+        .iter()
+        .filter_map(|(id, _)| {
+            let base = id.split('@').next().unwrap_or(id);
+            let ep = inputs
+                .endpoints
+                .get(id.as_str())
+                .or_else(|| inputs.endpoints.get(base))?;
+            Some(Anchor {
+                id: id.to_string(),
+                lat: ep.lat?,
+                lon: ep.lon?,
+            })
+        })
+        .collect();
+    let used: HashSet<&str> = usable.iter().map(|(id, _)| id.as_str()).collect();
+
+    let mut probes = 0usize;
+    let mut replies = 0usize;
+    for rec in inputs
+        .records
+        .iter()
+        .filter(|r| used.contains(r.endpoint_id.as_str()))
+    {
+        let n = if !rec.samples_ms.is_empty() {
+            Some(rec.samples_ms.len())
+        } else {
+            rec.histogram.as_ref().map(|h| h.count())
+        };
+        if let Some(n) = n {
+            probes += inputs.samples_per_burst.max(n);
+            replies += n;
+        }
+    }
+    let loss_pct = (probes > 0).then(|| (1.0 - replies as f64 / probes as f64) * PERCENT);
+    let mut jitters: Vec<f64> = usable.iter().filter_map(|(_, st)| st.jitter_ms).collect();
+    jitters.sort_by(f64::total_cmp);
+    let fewest_samples = usable.iter().map(|(_, st)| st.count).min();
+    let hdop = inputs.at.and_then(|(lat, lon)| hdop_at(&anchors, lat, lon));
+    let calibration_age_days = inputs.calibration.and_then(|c| {
+        let generated: i64 = c.generated_at.parse().ok()?;
+        Some((inputs.now_ms - generated).max(0) as f64 / MS_PER_DAY)
+    });
+    let clock_error = inputs
+        .clock
+        .and_then(|c| c.rtt_error_ms(CLOCK_ERROR_REFERENCE_RTT_MS));
+    let unsynced = inputs.clock.is_some_and(|c| c.ntp_synced == Some(false));
+
+    let t = thresholds;
+    let factor = |factor, value: Option<f64>, limits, higher: bool| QualityFactor {
+        factor,
+        value,
+        grade: value.map(|v| grade(v, limits, higher)),
+    };
+    let anchor_count = anchors.len() as f64;
+    let mut factors = vec![
+        factor("anchors", Some(anchor_count), t.anchors, true),
+        // Too few anchors to fix a position is as bad as geometry gets.
+        QualityFactor {
+            factor: "hdop",
+            value: hdop,
+            grade: Some(hdop.map_or(GRADES[GRADES.len() - 1], |h| grade(h, t.hdop, false))),
+        },
+        factor(
+            "samples",
+            Some(fewest_samples.unwrap_or(0) as f64),
+            t.samples,
+            true,
+        ),
+        factor("lossPct", loss_pct, t.loss_pct, false),
+        factor("jitterMs", quantile(&jitters, 0.5), t.jitter_ms, false),
+        factor(
+            "calibrationAgeDays",
+            calibration_age_days,
+            t.calibration_age_days,
+            false,
+        ),
+        factor("clockErrorMs", clock_error, t.clock_error_ms, false),
+    ];
+    if unsynced {
+        if let Some(clock) = factors.last_mut() {
+            clock.grade = Some(clock.grade.map_or('B', one_worse));
+        }
+    }
+    let grade = factors
+        .iter()
+        .filter_map(|f| f.grade)
+        .max()
+        .unwrap_or(GRADES[GRADES.len() - 1]);
+    DataQuality { grade, factors }
+}
+
+pub(crate) fn print_quality(label: &str, quality: &DataQuality, fmt: &ReportFormat) {
+    let weak: Vec<String> = quality
+        .weakest()
+        .iter()
+        .map(|f| {
+            let decimals = if COUNT_FACTORS.contains(&f.factor) {
+                0
+            } else {
+                FACTOR_DECIMALS
+            };
+            format!(
+                "{}={} ({})",
+                f.factor,
+                fmt.opt(f.value, decimals),
+                f.grade.unwrap_or('-')
+            )
+        })
+        .collect();
+    if weak.is_empty() {
+        println!("\n{label} data quality: {}", quality.grade);
+    } else {
+        println!(
+            "\n{label} data quality: {} (weakest: {})",
+            quality.grade,
+            weak.join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+    use crate::{build_stats, endpoints_by_id, DistanceModel};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn data_quality_grades_each_factor_and_takes_the_worst() {
+        let eps = endpoints_by_id(&[
+            endpoint("n", 5.0, 0.0),
+            endpoint("s", -5.0, 0.0),
+            endpoint("e", 0.0, 5.0),
+            endpoint("w", 0.0, -5.0),
+        ]);
+        let records: Vec<BurstRecord> = ["n", "s", "e", "w"]
+            .iter()
+            .map(|id| record(id, vec![10.0; 250]))
+            .collect();
+        let stats = build_stats(&records, None);
+        let thresholds = QualityThresholds::default();
+        let inputs = QualityInputs {
+            stats: &stats,
+            endpoints: &eps,
+            records: &records,
+            samples_per_burst: 250,
+            at: Some((0.0, 0.0)),
+            calibration: None,
+            clock: None,
+            now_ms: 0,
+        };
+        let q = assess(&thresholds, &inputs);
+        let grade_of = |q: &DataQuality, name: &str| {
+            q.factors.iter().find(|f| f.factor == name).unwrap().grade
+        };
+        // Four well-spread anchors with full, steady bursts: only the anchor
+        // count keeps it from an A; calibration and clock are not graded.
+        assert_eq!(q.grade, 'B');
+        assert_eq!(grade_of(&q, "hdop"), Some('A'));
+        assert_eq!(grade_of(&q, "calibrationAgeDays"), None);
+        assert_eq!(
+            q.weakest().iter().map(|f| f.factor).collect::<Vec<_>>(),
+            vec!["anchors"]
+        );
+
+        let cal = Calibration {
+            generated_at: "0".to_string(),
+            calibration_lat: 0.0,
+            calibration_lon: 0.0,
+            speed_km_s: DEFAULT_SPEED_KM_S,
+            path_stretch: DEFAULT_PATH_STRETCH,
+            distance_model: DistanceModel::Sphere,
+            propagation_model: None,
+            endpoints: BTreeMap::new(),
+        };
+        let clock = ClockQuality {
+            ntp_synced: Some(false),
+            ..ClockQuality::default()
+        };
+        let q = assess(
+            &thresholds,
+            &QualityInputs {
+                samples_per_burst: 500,
+                calibration: Some(&cal),
+                clock: Some(&clock),
+                now_ms: 60 * 86_400_000,
+                ..inputs
+            },
+        );
+        assert_eq!(grade_of(&q, "calibrationAgeDays"), Some('C'));
+        assert_eq!(grade_of(&q, "clockErrorMs"), Some('B'));
+        // Half the probes went unanswered.
+        assert_eq!(grade_of(&q, "lossPct"), Some('D'));
+        assert_eq!(q.grade, 'D');
+
+        let lenient: QualityThresholds = serde_json::from_str(r#"{"anchors": [4, 3, 2]}"#).unwrap();
+        assert_eq!(lenient.hdop, thresholds.hdop);
+        assert_eq!(assess(&lenient, &inputs).grade, 'A');
+        let unordered: QualityThresholds =
+            serde_json::from_str(r#"{"lossPct": [5, 1, 15]}"#).unwrap();
+        assert!(unordered.validate().is_err());
+    }
+}
@@ -0,0 +1,263 @@
+use clap::Parser;
+use lattice_core::{bytes_to_hex, hex_to_bytes, pseudonym};
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::PathBuf;
+
+pub(crate) const SHARE_PROFILE: &str = "share";
+const SALT_BYTES: usize = 32;
+/// Config keys that are never safe to share, whatever the profile says.
+const CONFIG_SECRET_FIELDS: [&str; 5] = [
+    "secretHex",
+    "outputPath",
+    "controlSocket",
+    "localApi",
+    "stateDir",
+];
+const COORDINATE_FIELDS: [&str; 2] = ["lat", "lon"];
+/// Two decimals is roughly 1 km; enough to keep a city, not a building.
+const SHARE_COORDINATE_DECIMALS: u32 = 2;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lattice-analyze redact",
+    about = "Write a copy of a session (and optionally its config) that is safe to share"
+)]
+pub struct RedactArgs {
+    /// Built-in profile name (`share`) or a path to a JSON redaction profile.
+    #[arg(long, default_value = SHARE_PROFILE)]
+    profile: String,
+
+    #[arg(long)]
+    session: PathBuf,
+
+    #[arg(long)]
+    out: PathBuf,
+
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    #[arg(long, requires = "config")]
+    config_out: Option<PathBuf>,
+
+    /// Hex key for hashed fields. Reuse it to keep pseudonyms stable across
+    /// exports; a random key is used when omitted.
+    #[arg(long)]
+    salt_hex: Option<String>,
+}
+
+/// Which fields to drop, hash, or clear. Rules apply to every JSON object in
+/// the input, including nested ones such as config endpoints and probe paths.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RedactionProfile {
+    pub remove_fields: Vec<String>,
+    pub hash_fields: Vec<String>,
+    pub clear_fields: Vec<String>,
+    pub coordinate_decimals: Option<u32>,
+}
+
+impl RedactionProfile {
+    /// Local addressing removed, hostnames hashed, coordinates rounded, and
+    /// free-text annotations cleared.
+    pub fn share() -> Self {
+        let list = |v: &[&str]| v.iter().map(|s| s.to_string()).collect();
+        Self {
+            remove_fields: list(&[
+                "localAddr",
+                "probeBindIp",
+                "probeBindIface",
+                "ifaceName",
+                "utunInterfaces",
+                "bindIp",
+                "bindInterface",
+            ]),
+            hash_fields: list(&["host", "remoteAddr", "machineId"]),
+            clear_fields: list(&["notes", "claimedEgressRegion", "label"]),
+            coordinate_decimals: Some(SHARE_COORDINATE_DECIMALS),
+        }
+    }
+
+    pub fn load(name_or_path: &str) -> io::Result<Self> {
+        if name_or_path == SHARE_PROFILE {
+            return Ok(Self::share());
+        }
+        let data = fs::read(name_or_path)?;
+        serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn apply(&self, value: &mut Value, key: &[u8]) {
+        match value {
+            Value::Object(obj) => {
+                self.apply_object(obj, key);
+                for v in obj.values_mut() {
+                    self.apply(v, key);
+                }
+            }
+            Value::Array(items) => {
+                for v in items {
+                    self.apply(v, key);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn apply_object(&self, obj: &mut Map<String, Value>, key: &[u8]) {
+        for field in &self.remove_fields {
+            obj.remove(field);
+        }
+        for field in &self.clear_fields {
+            if let Some(v) = obj.get_mut(field) {
+                *v = match v {
+                    Value::Array(_) => Value::Array(Vec::new()),
+                    _ => Value::Null,
+                };
+            }
+        }
+        for field in &self.hash_fields {
+            if let Some(Value::String(s)) = obj.get_mut(field) {
+                *s = pseudonym(key, s);
+            }
+        }
+        if self.coordinate_decimals.is_some() {
+            for field in COORDINATE_FIELDS {
+                if let Some(x) = obj.get(field).and_then(Value::as_f64) {
+                    obj.insert(field.to_string(), self.round_coordinate(x).into());
+                }
+            }
+        }
+    }
+
+    /// `x` at the profile's coordinate precision, or unchanged if it sets none.
+    pub fn round_coordinate(&self, x: f64) -> f64 {
+        match self.coordinate_decimals {
+            Some(decimals) => {
+                let scale = 10f64.powi(decimals as i32);
+                (x * scale).round() / scale
+            }
+            None => x,
+        }
+    }
+}
+
+/// Redacts a JSONL session line by line; returns records written and
+/// unparseable lines dropped.
+pub(crate) fn redact_session(
+    profile: &RedactionProfile,
+    key: &[u8],
+    reader: impl BufRead,
+    writer: &mut impl Write,
+) -> io::Result<(usize, usize)> {
+    let mut written = 0usize;
+    let mut skipped = 0usize;
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Ok(mut rec) = serde_json::from_str::<Value>(&line) else {
+            skipped += 1;
+            continue;
+        };
+        profile.apply(&mut rec, key);
+        serde_json::to_writer(&mut *writer, &rec)?;
+        writer.write_all(b"\n")?;
+        written += 1;
+    }
+    Ok((written, skipped))
+}
+
+/// A config with its secrets removed and the profile applied.
+pub(crate) fn redact_config(
+    profile: &RedactionProfile,
+    key: &[u8],
+    data: &[u8],
+) -> io::Result<Value> {
+    let mut cfg: Value =
+        serde_json::from_slice(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if let Some(obj) = cfg.as_object_mut() {
+        for field in CONFIG_SECRET_FIELDS {
+            obj.remove(field);
+        }
+    }
+    profile.apply(&mut cfg, key);
+    Ok(cfg)
+}
+
+/// Random key for hashed fields when the caller did not supply one.
+pub(crate) fn random_key() -> Vec<u8> {
+    let mut bytes = vec![0u8; SALT_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+pub fn run(args: RedactArgs) -> io::Result<()> {
+    let profile = RedactionProfile::load(&args.profile)?;
+    let key = match &args.salt_hex {
+        Some(hex) => {
+            hex_to_bytes(hex).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+        }
+        None => random_key(),
+    };
+
+    let reader = crate::open_log(&args.session)?;
+    let mut writer = BufWriter::new(File::create(&args.out)?);
+    let (written, skipped) = redact_session(&profile, &key, reader, &mut writer)?;
+    writer.flush()?;
+    eprintln!(
+        "redacted {} record(s) to {} ({} unparseable line(s) dropped)",
+        written,
+        args.out.display(),
+        skipped
+    );
+
+    if let (Some(config), Some(config_out)) = (&args.config, &args.config_out) {
+        let cfg = redact_config(&profile, &key, &fs::read(config)?)?;
+        let text = serde_json::to_string_pretty(&cfg).map_err(io::Error::other)?;
+        fs::write(config_out, text)?;
+        eprintln!(
+            "redacted config to {} (secretHex removed)",
+            config_out.display()
+        );
+    }
+
+    if args.salt_hex.is_none() && !profile.hash_fields.is_empty() {
+        eprintln!(
+            "hash key (keep private; pass as --salt-hex to reuse pseudonyms): {}",
+            bytes_to_hex(&key)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn share_profile_strips_local_and_identifying_fields() {
+        let profile = RedactionProfile::share();
+        let key = b"test-key";
+        let mut rec = serde_json::json!({
+            "endpointId": "a",
+            "host": "anchor.example.net",
+            "localAddr": "192.168.1.20:50000",
+            "notes": ["physics_mismatch: claimed SE"],
+            "endpoints": [{ "host": "anchor.example.net", "lat": 37.774929, "lon": -122.419416 }],
+        });
+        profile.apply(&mut rec, key);
+
+        let host = rec["host"].as_str().unwrap();
+        assert_ne!(host, "anchor.example.net");
+        assert_eq!(rec["endpoints"][0]["host"], host);
+        assert!(rec.get("localAddr").is_none());
+        assert_eq!(rec["notes"], serde_json::json!([]));
+        assert_eq!(rec["endpoints"][0]["lat"], 37.77);
+        assert_eq!(rec["endpoints"][0]["lon"], -122.42);
+        assert_eq!(rec["endpointId"], "a");
+    }
+}
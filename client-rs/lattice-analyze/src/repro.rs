@@ -0,0 +1,371 @@
+use crate::redact::{self, RedactionProfile, SHARE_PROFILE};
+use crate::Args;
+use clap::Parser;
+use lattice_core::{hex_to_bytes, now_unix_ms, sha256_hex};
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const REPRO_SCHEMA: &str = "lattice-repro/1";
+/// Everything is unpacked under this directory so a bundle never spills into
+/// the directory it is extracted in.
+const BUNDLE_DIR: &str = "lattice-repro";
+const INVOCATION_FILE: &str = "invocation.json";
+/// Stand-ins for required config fields `redact` removes, so the bundled
+/// config still loads; the analyzer reads neither.
+const CONFIG_PLACEHOLDERS: [(&str, &str); 2] =
+    [("secretHex", "00"), ("outputPath", "session.jsonl")];
+const TAR_BLOCK: usize = 512;
+/// ustar keeps names in a 100-byte field; bundle names are far shorter.
+const TAR_NAME_LEN: usize = 100;
+const TAR_FILE_MODE: u32 = 0o644;
+/// Calibration keys holding the location the calibration was taken at.
+const CALIBRATION_COORDINATE_FIELDS: [&str; 2] = ["calibrationLat", "calibrationLon"];
+/// Free text in `--evidence` entries; cleared in bundles.
+const EVIDENCE_DETAIL_FIELD: &str = "detail";
+/// Analyzer flags whose value is a location; rounded like config coordinates.
+const COORDINATE_FLAGS: [&str; 4] = ["--claim-lat", "--claim-lon", "--calib-lat", "--calib-lon"];
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lattice-analyze repro-bundle",
+    about = "Package a redacted session, config, calibration, and analyzer invocation for a bug report"
+)]
+pub struct ReproBundleArgs {
+    /// Tarball to write.
+    #[arg(long)]
+    out: PathBuf,
+
+    /// Redaction profile for the session and config, as in `redact`.
+    #[arg(long, default_value = SHARE_PROFILE)]
+    profile: String,
+
+    /// Hex key for hashed fields; a random key is used when omitted.
+    #[arg(long)]
+    salt_hex: Option<String>,
+
+    /// The analyzer arguments that show the problem, after `--`.
+    #[arg(last = true, required = true)]
+    analyze: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum InputKind {
+    /// JSONL records, redacted line by line.
+    Session,
+    /// Secrets dropped, then redacted; required fields get placeholders.
+    Config,
+    /// A layered config: secrets dropped, then redacted.
+    Override,
+    /// Bias table kept; the calibration site is rounded.
+    Calibration,
+    /// `--evidence` signals: redacted, and free-text details (which may name
+    /// an egress IP or city) cleared.
+    Evidence,
+    /// Copied as-is (classifier and fusion models hold no local data).
+    Verbatim,
+    /// Written by the analyzer, not read; only the path is rewritten.
+    Output,
+}
+
+/// An input file and the name it gets in the bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BundleInput {
+    pub source: PathBuf,
+    pub name: String,
+    pub kind: InputKind,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BundledFile {
+    name: String,
+    sha256: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Invocation {
+    schema: &'static str,
+    analyzer_version: &'static str,
+    created_unix_ms: i64,
+    /// `share`, or `custom` for a profile file (its path is not recorded).
+    profile: String,
+    /// Analyzer arguments with paths pointing into the bundle; run from inside it.
+    args: Vec<String>,
+    files: Vec<BundledFile>,
+}
+
+fn file_flag(flag: &str, overrides: usize, evidence: usize) -> Option<(String, InputKind)> {
+    Some(match flag {
+        "--config" => ("config.json".to_string(), InputKind::Config),
+        "--override" => (
+            format!("override-{}.json", overrides + 1),
+            InputKind::Override,
+        ),
+        "--session" => ("session.jsonl".to_string(), InputKind::Session),
+        "--baseline" => ("baseline.jsonl".to_string(), InputKind::Session),
+        "--calibration" => ("calibration.json".to_string(), InputKind::Calibration),
+        "--classifier" => ("classifier.json".to_string(), InputKind::Verbatim),
+        "--fusion-model" => ("fusion-model.json".to_string(), InputKind::Verbatim),
+        "--evidence" => (
+            format!("evidence-{}.json", evidence + 1),
+            InputKind::Evidence,
+        ),
+        "--calibration-out" => ("calibration-out.json".to_string(), InputKind::Output),
+        _ => return None,
+    })
+}
+
+/// Rewrites analyzer arguments to refer to bundle files and rounds location
+/// flags with the profile. Returns the new arguments and the files to pack.
+pub(crate) fn rewrite_args(
+    analyze: &[String],
+    profile: &RedactionProfile,
+) -> io::Result<(Vec<String>, Vec<BundleInput>)> {
+    Args::try_parse_from(
+        std::iter::once("lattice-analyze").chain(analyze.iter().map(String::as_str)),
+    )
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    let mut args = Vec::new();
+    let mut inputs: Vec<BundleInput> = Vec::new();
+    let mut iter = analyze.iter();
+    while let Some(arg) = iter.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let overrides = inputs
+            .iter()
+            .filter(|i| i.kind == InputKind::Override)
+            .count();
+        let evidence = inputs
+            .iter()
+            .filter(|i| i.kind == InputKind::Evidence)
+            .count();
+        let file = file_flag(flag, overrides, evidence);
+        let coordinate = COORDINATE_FLAGS.contains(&flag);
+        if file.is_none() && !coordinate {
+            args.push(arg.clone());
+            continue;
+        }
+        let value = match inline {
+            Some(value) => value,
+            None => iter.next().cloned().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("{flag} needs a value"))
+            })?,
+        };
+        let value = match file {
+            Some((name, kind)) => {
+                inputs.push(BundleInput {
+                    source: PathBuf::from(value),
+                    name: name.clone(),
+                    kind,
+                });
+                name
+            }
+            None => {
+                let x: f64 = value.parse().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, format!("{flag}: not a number"))
+                })?;
+                profile.round_coordinate(x).to_string()
+            }
+        };
+        args.push(format!("{flag}={value}"));
+    }
+    Ok((args, inputs))
+}
+
+fn bundle_bytes(
+    input: &BundleInput,
+    profile: &RedactionProfile,
+    key: &[u8],
+) -> io::Result<Vec<u8>> {
+    let pretty = |v: &Value| serde_json::to_vec_pretty(v).map_err(io::Error::other);
+    match input.kind {
+        InputKind::Session => {
+            let mut out = Vec::new();
+            let reader = crate::open_log(&input.source)?;
+            redact::redact_session(profile, key, reader, &mut out)?;
+            Ok(out)
+        }
+        InputKind::Config | InputKind::Override => {
+            let mut cfg = redact::redact_config(profile, key, &fs::read(&input.source)?)?;
+            if let (InputKind::Config, Some(obj)) = (input.kind, cfg.as_object_mut()) {
+                for (field, placeholder) in CONFIG_PLACEHOLDERS {
+                    obj.insert(field.to_string(), placeholder.into());
+                }
+            }
+            pretty(&cfg)
+        }
+        InputKind::Calibration => {
+            let mut cal: Value = serde_json::from_slice(&fs::read(&input.source)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            if let Some(obj) = cal.as_object_mut() {
+                for field in CALIBRATION_COORDINATE_FIELDS {
+                    if let Some(x) = obj.get(field).and_then(Value::as_f64) {
+                        obj.insert(field.to_string(), profile.round_coordinate(x).into());
+                    }
+                }
+            }
+            pretty(&cal)
+        }
+        InputKind::Evidence => {
+            let mut items: Value = serde_json::from_slice(&fs::read(&input.source)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            profile.apply(&mut items, key);
+            for item in items.as_array_mut().into_iter().flatten() {
+                if let Some(detail) = item.get_mut(EVIDENCE_DETAIL_FIELD) {
+                    *detail = Value::String(String::new());
+                }
+            }
+            pretty(&items)
+        }
+        InputKind::Verbatim => fs::read(&input.source),
+        InputKind::Output => unreachable!("outputs are not packed"),
+    }
+}
+
+fn octal_field(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    let text = format!("{:0width$o}", value, width = digits);
+    field[..digits].copy_from_slice(text.as_bytes());
+    field[digits] = 0;
+}
+
+/// Appends one regular file to a ustar archive.
+pub(crate) fn tar_append(tar: &mut Vec<u8>, name: &str, data: &[u8], mtime: u64) -> io::Result<()> {
+    if name.len() >= TAR_NAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("tar entry name too long: {name}"),
+        ));
+    }
+    let mut header = [0u8; TAR_BLOCK];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    octal_field(&mut header[100..108], TAR_FILE_MODE as u64);
+    octal_field(&mut header[108..116], 0);
+    octal_field(&mut header[116..124], 0);
+    octal_field(&mut header[124..136], data.len() as u64);
+    octal_field(&mut header[136..148], mtime);
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    // The checksum is computed with its own field read as spaces.
+    header[148..156].fill(b' ');
+    let sum: u64 = header.iter().map(|&b| b as u64).sum();
+    octal_field(&mut header[148..155], sum);
+    tar.extend_from_slice(&header);
+    tar.extend_from_slice(data);
+    tar.resize(tar.len().next_multiple_of(TAR_BLOCK), 0);
+    Ok(())
+}
+
+pub fn run(args: ReproBundleArgs) -> io::Result<()> {
+    let profile = RedactionProfile::load(&args.profile)?;
+    let key = match &args.salt_hex {
+        Some(hex) => {
+            hex_to_bytes(hex).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+        }
+        None => redact::random_key(),
+    };
+    let (analyze_args, inputs) = rewrite_args(&args.analyze, &profile)?;
+
+    let created_unix_ms = now_unix_ms();
+    let mtime = (created_unix_ms / 1000).max(0) as u64;
+    let mut tar = Vec::new();
+    let mut files = Vec::new();
+    for input in inputs.iter().filter(|i| i.kind != InputKind::Output) {
+        let data = bundle_bytes(input, &profile, &key)?;
+        tar_append(
+            &mut tar,
+            &format!("{BUNDLE_DIR}/{}", input.name),
+            &data,
+            mtime,
+        )?;
+        files.push(BundledFile {
+            name: input.name.clone(),
+            sha256: sha256_hex(&data),
+        });
+    }
+    let invocation = Invocation {
+        schema: REPRO_SCHEMA,
+        analyzer_version: env!("CARGO_PKG_VERSION"),
+        created_unix_ms,
+        profile: if args.profile == SHARE_PROFILE {
+            SHARE_PROFILE.to_string()
+        } else {
+            "custom".to_string()
+        },
+        args: analyze_args,
+        files,
+    };
+    let text = serde_json::to_vec_pretty(&invocation).map_err(io::Error::other)?;
+    tar_append(
+        &mut tar,
+        &format!("{BUNDLE_DIR}/{INVOCATION_FILE}"),
+        &text,
+        mtime,
+    )?;
+    // End of archive: two zero blocks.
+    tar.resize(tar.len() + 2 * TAR_BLOCK, 0);
+    fs::write(&args.out, tar)?;
+
+    eprintln!(
+        "wrote {} with {} file(s); replay from {}/ with: lattice analyze {}",
+        args.out.display(),
+        invocation.files.len() + 1,
+        BUNDLE_DIR,
+        invocation.args.join(" ")
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repro_bundle_points_args_at_bundle_files_and_rounds_locations() {
+        let profile = redact::RedactionProfile::share();
+        let argv: Vec<String> = [
+            "--config=/home/me/lattice/config.json",
+            "--override",
+            "/home/me/host.json",
+            "--session",
+            "/home/me/s.jsonl",
+            "--claim-lat=47.61234",
+            "--json",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let (args, inputs) = rewrite_args(&argv, &profile).unwrap();
+        assert_eq!(
+            args,
+            vec![
+                "--config=config.json",
+                "--override=override-1.json",
+                "--session=session.jsonl",
+                "--claim-lat=47.61",
+                "--json"
+            ]
+        );
+        assert_eq!(inputs.len(), 3);
+        assert_eq!(inputs[1].source, PathBuf::from("/home/me/host.json"));
+        assert_eq!(inputs[2].kind, InputKind::Session);
+        assert!(rewrite_args(&["redact".to_string()], &profile).is_err());
+
+        let mut tar = Vec::new();
+        tar_append(&mut tar, "lattice-repro/a.txt", b"hello", 0).unwrap();
+        assert_eq!(tar.len(), 1024);
+        assert_eq!(&tar[124..136], b"00000000005\0");
+        let mut header = tar[..512].to_vec();
+        header[148..156].fill(b' ');
+        let sum: u64 = header.iter().map(|&b| b as u64).sum();
+        let stored = std::str::from_utf8(&tar[148..154]).unwrap();
+        assert_eq!(u64::from_str_radix(stored, 8).unwrap(), sum);
+    }
+}
@@ -0,0 +1,199 @@
+//! The analyzer's estimate and claim verdict over a client's newest bursts,
+//! for `rollingEstimate`: the client feeds every burst it writes and gets an
+//! `estimate` record back every `everyBursts` bursts.
+
+use crate::constants::*;
+use crate::fleet::{claim_verdict, tunnel_state};
+use crate::propagation::PropagationSpec;
+use crate::{
+    build_stats, claim_checks, endpoints_by_id, estimate_location, transport, Calibration,
+    DistanceModel, SampleGate, Weighting,
+};
+use lattice_core::{
+    cloud_region, now_unix_ms, BurstRecord, Config, Endpoint, EstimateRecord, RollingEstimate,
+    ESTIMATE_RECORD_TYPE,
+};
+use std::collections::{HashMap, VecDeque};
+
+pub struct RollingEstimator {
+    endpoints: HashMap<String, Endpoint>,
+    gate: SampleGate,
+    model: PropagationSpec,
+    calibration: Option<Calibration>,
+    claim: Option<(f64, f64)>,
+    every_bursts: usize,
+    window: VecDeque<BurstRecord>,
+    window_bursts: usize,
+    since_estimate: usize,
+}
+
+impl RollingEstimator {
+    /// Uses the analyzer's defaults and the config's sample gate. The claim
+    /// is `claimLat`/`claimLon`, or else the coordinates of
+    /// `claimedEgressRegion`.
+    pub fn new(cfg: &Config, settings: &RollingEstimate) -> Self {
+        let region_claim = || {
+            let region = cloud_region(None, cfg.claimed_egress_region.as_deref()?)?;
+            Some((region.lat, region.lon))
+        };
+        RollingEstimator {
+            endpoints: endpoints_by_id(&cfg.endpoints),
+            gate: SampleGate {
+                min_samples: cfg.min_samples_per_endpoint,
+                min_bursts: cfg.min_bursts_per_endpoint,
+            },
+            model: PropagationSpec::linear(DEFAULT_SPEED_KM_S / DEFAULT_PATH_STRETCH),
+            calibration: None,
+            claim: settings
+                .claim_lat
+                .zip(settings.claim_lon)
+                .or_else(region_claim),
+            every_bursts: settings.every_bursts.max(1),
+            window: VecDeque::new(),
+            window_bursts: settings.window_bursts.max(1),
+            since_estimate: 0,
+        }
+    }
+
+    /// Maps RTTs through `calibration` and fits with the propagation model
+    /// it was measured against, as `analyze --calibration` does.
+    pub fn with_calibration(mut self, calibration: Calibration) -> Self {
+        if let Some(model) = &calibration.propagation_model {
+            self.model = model.clone();
+        }
+        self.calibration = Some(calibration);
+        self
+    }
+
+    /// Anchor coordinates after the client reloaded its config. Bursts
+    /// already in the window from anchors that are gone stay out of the fit.
+    pub fn set_endpoints(&mut self, endpoints: &[Endpoint]) {
+        self.endpoints = endpoints_by_id(endpoints);
+    }
+
+    /// Adds a burst; returns a record when this one completes `everyBursts`.
+    pub fn push(&mut self, rec: &BurstRecord) -> Option<EstimateRecord> {
+        self.window.push_back(rec.clone());
+        if self.window.len() > self.window_bursts {
+            self.window.pop_front();
+        }
+        self.since_estimate += 1;
+        if self.since_estimate < self.every_bursts {
+            return None;
+        }
+        self.since_estimate = 0;
+        Some(self.estimate(&rec.session_id))
+    }
+
+    fn estimate(&self, session_id: &str) -> EstimateRecord {
+        let mut records: Vec<BurstRecord> = self.window.iter().cloned().collect();
+        let (_, tunnel) = tunnel_state(&records);
+        transport::correct_transport_bias(&mut records, self.calibration.as_ref(), true);
+        let mut stats = build_stats(&records, None);
+        self.gate.apply(&mut stats);
+        let estimate = estimate_location(
+            &stats,
+            &self.endpoints,
+            self.model.model(),
+            DEFAULT_GRID_DEG,
+            DEFAULT_REFINE_DEG,
+            DEFAULT_BAND_FACTOR,
+            DEFAULT_BAND_WINDOW_DEG,
+            0.0,
+            self.calibration.as_ref(),
+            Weighting::default(),
+            DistanceModel::Sphere,
+        );
+        let calibration = self.calibration.as_ref();
+        let checks = self
+            .claim
+            .map(|(lat, lon)| {
+                let model = self.model.model();
+                claim_checks(
+                    &stats,
+                    &self.endpoints,
+                    lat,
+                    lon,
+                    model,
+                    calibration,
+                    DistanceModel::Sphere,
+                )
+            })
+            .unwrap_or_default();
+        EstimateRecord {
+            record_type: ESTIMATE_RECORD_TYPE.to_string(),
+            ts_unix_ms: now_unix_ms(),
+            session_id: session_id.to_string(),
+            bursts: records.len(),
+            anchors_used: estimate.as_ref().map_or(0, |e| e.points),
+            estimate_lat: estimate.as_ref().map(|e| e.lat),
+            estimate_lon: estimate.as_ref().map(|e| e.lon),
+            radius_km: estimate
+                .as_ref()
+                .and_then(|e| e.band.as_ref())
+                .map(|b| b.radius_km),
+            claim_lat: self.claim.map(|c| c.0),
+            claim_lon: self.claim.map(|c| c.1),
+            claim_verdict: claim_verdict(&checks).map(str::to_string),
+            falsifying: checks
+                .iter()
+                .filter(|c| c.falsify_tight == Some(true) || c.falsify_loose == Some(true))
+                .map(|c| c.id.clone())
+                .collect(),
+            tunnel: tunnel.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+    use lattice_core::haversine_km;
+
+    #[test]
+    fn rolling_estimator_reports_every_n_bursts_over_its_window() {
+        let anchors = [
+            ("fra", 50.11, 8.68),
+            ("lon", 51.51, -0.13),
+            ("par", 48.86, 2.35),
+        ];
+        let cfg = sample_config(
+            anchors
+                .iter()
+                .map(|&(id, lat, lon)| endpoint(id, lat, lon))
+                .collect(),
+        );
+        let settings = RollingEstimate {
+            every_bursts: 2,
+            window_bursts: 4,
+            claim_lat: Some(40.71),
+            claim_lon: Some(-74.01),
+        };
+        let mut estimator = RollingEstimator::new(&cfg, &settings);
+        // A host next to Frankfurt, claiming New York.
+        let mut reports = Vec::new();
+        for i in 0..6 {
+            let (id, lat, lon) = anchors[i % anchors.len()];
+            let rtt =
+                RTT_FACTOR * haversine_km(50.0, 8.5, lat, lon) / DEFAULT_SPEED_KM_S * MS_PER_SEC;
+            reports.push(estimator.push(&record(id, vec![rtt + 0.5, rtt + 1.0])));
+        }
+        let due: Vec<_> = reports.iter().map(Option::is_some).collect();
+        assert_eq!(due, vec![false, true, false, true, false, true]);
+        let last = reports[5].as_ref().unwrap();
+        assert_eq!(last.record_type, ESTIMATE_RECORD_TYPE);
+        assert_eq!(last.bursts, 4);
+        assert_eq!(last.anchors_used, 3);
+        assert!(
+            haversine_km(
+                50.0,
+                8.5,
+                last.estimate_lat.unwrap(),
+                last.estimate_lon.unwrap()
+            ) < 500.0
+        );
+        assert_eq!(last.claim_verdict.as_deref(), Some("inconsistent"));
+        assert!(last.falsifying.contains(&"fra".to_string()));
+    }
+}
@@ -0,0 +1,341 @@
+use crate::constants::*;
+use crate::propagation::Linear;
+use crate::{
+    build_stats, endpoints_by_id, estimate_location, finite, fmt_opt, haversine_km,
+    load_calibration, load_jsonl, quantile, Calibration, DistanceModel, EndpointStats,
+    WeightScheme, Weighting,
+};
+use clap::Parser;
+use lattice_core::{Config, Endpoint};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lattice-analyze subset",
+    about = "Smallest anchor subset whose estimates stay within a target median error"
+)]
+pub struct SubsetArgs {
+    #[arg(long)]
+    config: PathBuf,
+
+    #[arg(long = "override")]
+    overrides: Vec<PathBuf>,
+
+    /// A capture from a known location, e.g. `52.52,13.40=berlin.jsonl` (repeatable).
+    #[arg(long = "truth", value_name = "LAT,LON=SESSION", required = true)]
+    truths: Vec<String>,
+
+    /// Largest acceptable median estimate error across the truth sessions.
+    #[arg(long)]
+    target_median_km: f64,
+
+    #[arg(long)]
+    calibration: Option<PathBuf>,
+
+    #[arg(long, default_value_t = DEFAULT_GRID_DEG)]
+    grid: f64,
+
+    #[arg(long, default_value_t = DEFAULT_REFINE_DEG)]
+    refine: f64,
+
+    #[arg(long, default_value_t = DEFAULT_SPEED_KM_S)]
+    speed_km_s: f64,
+
+    #[arg(long, default_value_t = DEFAULT_PATH_STRETCH)]
+    path_stretch: f64,
+
+    #[arg(long)]
+    outlier_mad_k: Option<f64>,
+
+    #[arg(long, value_enum, default_value_t = WeightScheme::Jitter)]
+    weighting: WeightScheme,
+
+    #[arg(long, default_value_t = DEFAULT_RECENCY_HALF_LIFE_S)]
+    recency_half_life_s: f64,
+
+    #[arg(long, value_enum, default_value_t = DistanceModel::Sphere)]
+    distance_model: DistanceModel,
+
+    #[arg(long)]
+    json: bool,
+}
+
+/// One capture with its ground truth and precomputed per-endpoint stats.
+pub(crate) struct TruthSession {
+    pub lat: f64,
+    pub lon: f64,
+    pub stats: HashMap<String, EndpointStats>,
+}
+
+pub(crate) struct Evaluator<'a> {
+    pub sessions: &'a [TruthSession],
+    pub endpoints: &'a HashMap<String, Endpoint>,
+    pub speed_km_s: f64,
+    pub grid: f64,
+    pub refine: f64,
+    pub calibration: Option<&'a Calibration>,
+    pub weighting: Weighting,
+    pub distance: DistanceModel,
+}
+
+impl Evaluator<'_> {
+    /// Median estimate error over the truth sessions using only `anchors`.
+    /// Sessions with no estimate (fewer than three anchors) count as infinitely wrong.
+    pub fn median_error_km(&self, anchors: &BTreeSet<String>) -> f64 {
+        let mut errors: Vec<f64> = self
+            .sessions
+            .iter()
+            .map(|s| {
+                let stats: HashMap<String, EndpointStats> = s
+                    .stats
+                    .iter()
+                    .filter(|(id, _)| anchors.contains(base_id(id)))
+                    .map(|(id, st)| (id.clone(), st.clone()))
+                    .collect();
+                estimate_location(
+                    &stats,
+                    self.endpoints,
+                    &Linear {
+                        speed_km_s: self.speed_km_s,
+                    },
+                    self.grid,
+                    self.refine,
+                    DEFAULT_BAND_FACTOR,
+                    DEFAULT_BAND_WINDOW_DEG,
+                    0.0,
+                    self.calibration,
+                    self.weighting,
+                    self.distance,
+                )
+                .map_or(f64::INFINITY, |est| {
+                    haversine_km(s.lat, s.lon, est.lat, est.lon)
+                })
+            })
+            .collect();
+        errors.sort_by(f64::total_cmp);
+        quantile(&errors, 0.5).unwrap_or(f64::INFINITY)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubsetStep {
+    pub dropped: String,
+    pub median_error_km: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubsetResult {
+    pub target_median_km: f64,
+    /// `null` when some truth session could not be estimated at all.
+    pub full_median_error_km: Option<f64>,
+    pub full_anchors: Vec<String>,
+    pub kept_anchors: Vec<String>,
+    pub median_error_km: Option<f64>,
+    pub meets_target: bool,
+    pub steps: Vec<SubsetStep>,
+}
+
+/// Probe paths are reported as `anchor@path`; subsets are chosen per anchor.
+fn base_id(id: &str) -> &str {
+    id.split('@').next().unwrap_or(id)
+}
+
+/// Greedy backward elimination: repeatedly drop the anchor whose removal
+/// hurts the median error least, while the target still holds. Not
+/// guaranteed minimal, but each step is checked against the data.
+pub(crate) fn select(eval: &Evaluator, target_median_km: f64) -> SubsetResult {
+    let mut current: BTreeSet<String> = eval
+        .sessions
+        .iter()
+        .flat_map(|s| s.stats.keys())
+        .map(|id| base_id(id).to_string())
+        .filter(|id| {
+            eval.endpoints
+                .get(id)
+                .is_some_and(|ep| ep.lat.is_some() && ep.lon.is_some())
+        })
+        .collect();
+    let full_anchors: Vec<String> = current.iter().cloned().collect();
+    let full = eval.median_error_km(&current);
+    let mut median = full;
+    let mut steps = Vec::new();
+
+    if full <= target_median_km {
+        loop {
+            let best = current
+                .iter()
+                .map(|id| {
+                    let mut trial = current.clone();
+                    trial.remove(id);
+                    (id.clone(), eval.median_error_km(&trial))
+                })
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            match best {
+                Some((id, err)) if err <= target_median_km => {
+                    current.remove(&id);
+                    median = err;
+                    steps.push(SubsetStep {
+                        dropped: id,
+                        median_error_km: finite(err),
+                    });
+                }
+                _ => break,
+            }
+        }
+    }
+
+    SubsetResult {
+        target_median_km,
+        full_median_error_km: finite(full),
+        full_anchors,
+        kept_anchors: current.into_iter().collect(),
+        median_error_km: finite(median),
+        meets_target: median <= target_median_km,
+        steps,
+    }
+}
+
+fn parse_truth(entry: &str) -> io::Result<(f64, f64, PathBuf)> {
+    let bad = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("--truth expects LAT,LON=SESSION, got '{}'", entry),
+        )
+    };
+    let (coords, path) = entry.split_once('=').ok_or_else(bad)?;
+    let (lat, lon) = coords.split_once(',').ok_or_else(bad)?;
+    let lat: f64 = lat.trim().parse().map_err(|_| bad())?;
+    let lon: f64 = lon.trim().parse().map_err(|_| bad())?;
+    Ok((lat, lon, PathBuf::from(path)))
+}
+
+pub fn run(args: SubsetArgs) -> io::Result<()> {
+    if !(args.target_median_km.is_finite() && args.target_median_km > 0.0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--target-median-km must be > 0",
+        ));
+    }
+    let cfg = Config::load_layered(&args.config, &args.overrides)?;
+    let endpoints = endpoints_by_id(&cfg.endpoints);
+    let calibration = match &args.calibration {
+        Some(path) => Some(load_calibration(path)?),
+        None => None,
+    };
+    let mut sessions = Vec::new();
+    for entry in &args.truths {
+        let (lat, lon, path) = parse_truth(entry)?;
+        let records = load_jsonl(&path)?;
+        sessions.push(TruthSession {
+            lat,
+            lon,
+            stats: build_stats(&records, args.outlier_mad_k),
+        });
+    }
+    let path_stretch = args.path_stretch.max(MIN_PATH_STRETCH);
+    let eval = Evaluator {
+        sessions: &sessions,
+        endpoints: &endpoints,
+        speed_km_s: args.speed_km_s / path_stretch,
+        grid: args.grid,
+        refine: args.refine,
+        calibration: calibration.as_ref(),
+        weighting: Weighting {
+            scheme: args.weighting,
+            recency_half_life_s: args.recency_half_life_s,
+        },
+        distance: args.distance_model,
+    };
+    let result = select(&eval, args.target_median_km);
+
+    if args.json {
+        let text = serde_json::to_string_pretty(&result).map_err(io::Error::other)?;
+        println!("{text}");
+        return Ok(());
+    }
+    println!(
+        "Full set: {} anchors, median error {} km over {} truth session(s)",
+        result.full_anchors.len(),
+        fmt_opt(result.full_median_error_km, 1),
+        sessions.len()
+    );
+    if !result.meets_target {
+        println!(
+            "The full set misses the {:.1} km target; add anchors or calibrate before trimming.",
+            result.target_median_km
+        );
+        return Ok(());
+    }
+    for step in &result.steps {
+        println!(
+            "- drop {} -> median {} km",
+            step.dropped,
+            fmt_opt(step.median_error_km, 1)
+        );
+    }
+    println!(
+        "Keep {} of {} anchors ({} km median, target {:.1} km): {}",
+        result.kept_anchors.len(),
+        result.full_anchors.len(),
+        fmt_opt(result.median_error_km, 1),
+        result.target_median_km,
+        result.kept_anchors.join(", ")
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+    use lattice_core::BurstRecord;
+
+    #[test]
+    fn subset_drops_redundant_anchors_within_target() {
+        let eps = vec![
+            endpoint("fra", 50.11, 8.68),
+            endpoint("ams", 52.37, 4.90),
+            endpoint("lon", 51.51, -0.13),
+            endpoint("mad", 40.42, -3.70),
+            endpoint("war", 52.23, 21.01),
+        ];
+        let endpoints = endpoints_by_id(&eps);
+        let speed = DEFAULT_SPEED_KM_S / DEFAULT_PATH_STRETCH;
+        let truth = |lat: f64, lon: f64| {
+            let records: Vec<BurstRecord> = eps
+                .iter()
+                .map(|ep| {
+                    let d = haversine_km(lat, lon, ep.lat.unwrap(), ep.lon.unwrap());
+                    let rtt = RTT_FACTOR * d / speed * MS_PER_SEC + 2.0;
+                    record(&ep.id, vec![rtt; 5])
+                })
+                .collect();
+            TruthSession {
+                lat,
+                lon,
+                stats: build_stats(&records, None),
+            }
+        };
+        let sessions = vec![truth(48.86, 2.35), truth(48.14, 11.58), truth(50.85, 4.35)];
+        let eval = Evaluator {
+            sessions: &sessions,
+            endpoints: &endpoints,
+            speed_km_s: speed,
+            grid: DEFAULT_GRID_DEG,
+            refine: DEFAULT_REFINE_DEG,
+            calibration: None,
+            weighting: Weighting::default(),
+            distance: DistanceModel::Sphere,
+        };
+        let result = select(&eval, 200.0);
+        assert!(result.meets_target);
+        assert_eq!(result.full_anchors.len(), 5);
+        assert!(result.kept_anchors.len() >= 3 && result.kept_anchors.len() < 5);
+        assert!(result.median_error_km.unwrap() <= 200.0);
+    }
+}
@@ -0,0 +1,427 @@
+use crate::constants::*;
+use crate::fleet::{claim_verdict, tunnel_state};
+use crate::propagation::Linear;
+use crate::quality::{self, DataQuality, QualityInputs, QualityThresholds};
+use crate::units::{DistanceUnit, ReportFormat};
+use crate::{
+    bufferbloat, build_stats, claim_checks, coverage, endpoints_by_id, estimate_location,
+    load_calibration, load_clock_quality, load_jsonl, transport, DistanceModel, Estimate,
+    SampleGate, Weighting,
+};
+use clap::Parser;
+use lattice_core::{now_unix_ms, BurstRecord, ClockQuality, Config, PRIVATE_RELAY};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+
+const SUMMARY_SCHEMA: &str = "lattice-summary/1";
+/// Fewer anchors than this and the location is called a rough guess.
+const ROUGH_GUESS_ANCHORS: usize = 3;
+/// Below this coverage on any anchor the capture is called patchy.
+const MIN_COVERAGE_PCT: f64 = 90.0;
+/// Grades at or below this get a caveat of their own.
+const SOFT_GRADE: char = 'C';
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lattice-analyze summary",
+    about = "Five-line answer: where, whether the claim holds, tunnel use, data quality, caveats"
+)]
+pub struct SummaryArgs {
+    #[arg(long)]
+    config: PathBuf,
+
+    #[arg(long = "override")]
+    overrides: Vec<PathBuf>,
+
+    #[arg(long)]
+    session: PathBuf,
+
+    #[arg(long, requires = "claim_lon")]
+    claim_lat: Option<f64>,
+
+    #[arg(long, requires = "claim_lat")]
+    claim_lon: Option<f64>,
+
+    #[arg(long)]
+    calibration: Option<PathBuf>,
+
+    /// Grade limits layered over the built-in data-quality thresholds.
+    #[arg(long)]
+    quality_thresholds: Option<PathBuf>,
+
+    #[arg(long, default_value_t = DEFAULT_SPEED_KM_S)]
+    speed_km_s: f64,
+
+    #[arg(long, default_value_t = DEFAULT_PATH_STRETCH)]
+    path_stretch: f64,
+
+    #[arg(long, value_enum, default_value_t = DistanceModel::Sphere)]
+    distance_model: DistanceModel,
+
+    /// Unit for distances in text output; JSON stays in km.
+    #[arg(long, value_enum, default_value_t = DistanceUnit::Km)]
+    units: DistanceUnit,
+
+    /// Locale for number separators in text output (default: `LC_ALL`,
+    /// `LC_NUMERIC`, or `LANG`).
+    #[arg(long)]
+    locale: Option<String>,
+
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Summary {
+    pub schema: &'static str,
+    pub estimate_lat: Option<f64>,
+    pub estimate_lon: Option<f64>,
+    /// Radius of the fit band around the estimate.
+    pub radius_km: Option<f64>,
+    /// `consistent`, `suspect`, or `inconsistent`; null without a claim or a
+    /// bounded anchor.
+    pub claim_verdict: Option<&'static str>,
+    pub claimed: bool,
+    pub tunnel: &'static str,
+    pub tunnel_bursts: usize,
+    /// The system relay seen most often (`privateRelay`), null when none was.
+    pub relay: Option<String>,
+    pub relay_bursts: usize,
+    pub bursts: usize,
+    pub anchors_used: usize,
+    pub quality: DataQuality,
+    /// Lowest coverage of any anchor.
+    pub coverage_pct: Option<f64>,
+    pub ntp_synced: Option<bool>,
+    pub caveats: Vec<String>,
+}
+
+/// The answer from the main analyzer's estimate and claim verdict for the
+/// session, with the caveats a reader needs to weigh them.
+pub(crate) fn summarize(
+    records: &[BurstRecord],
+    estimate: Option<&Estimate>,
+    claim_verdict: Option<&'static str>,
+    claimed: bool,
+    coverage_pct: Option<f64>,
+    clock: Option<&ClockQuality>,
+    quality: DataQuality,
+) -> Summary {
+    let (_, tunnel) = tunnel_state(records);
+    let (relay, relay_bursts) = relay_state(records);
+    let anchors_used = estimate.map_or(0, |e| e.points);
+    let ntp_synced = clock.and_then(|c| c.ntp_synced);
+    let mut caveats = Vec::new();
+    if tunnel != "none" {
+        caveats.push(
+            "traffic went through a tunnel, so the location is likely the tunnel's exit"
+                .to_string(),
+        );
+    }
+    if relay.as_deref() == Some(PRIVATE_RELAY) {
+        caveats.push(
+            "iCloud Private Relay was on, so web services and IP geolocation see a relay exit, not this host".to_string(),
+        );
+    }
+    match estimate {
+        None => caveats.push("too few anchors answered to place the host".to_string()),
+        Some(_) if anchors_used < ROUGH_GUESS_ANCHORS => caveats.push(format!(
+            "only {anchors_used} anchors: the location is a rough guess"
+        )),
+        Some(_) => {}
+    }
+    if claimed && claim_verdict.is_none() {
+        caveats
+            .push("no anchor gave a distance bound, so the claim could not be checked".to_string());
+    }
+    if coverage_pct.is_some_and(|p| p < MIN_COVERAGE_PCT) {
+        caveats.push("the capture has gaps (host asleep or client down)".to_string());
+    }
+    if ntp_synced == Some(false) {
+        caveats.push("the host clock was not NTP-synced".to_string());
+    }
+    if quality.grade >= SOFT_GRADE {
+        let weakest: Vec<&str> = quality.weakest().iter().map(|f| f.factor).collect();
+        caveats.push(format!(
+            "data quality {} ({}): treat the answer as indicative only",
+            quality.grade,
+            weakest.join(", ")
+        ));
+    }
+    Summary {
+        schema: SUMMARY_SCHEMA,
+        estimate_lat: estimate.map(|e| e.lat),
+        estimate_lon: estimate.map(|e| e.lon),
+        radius_km: estimate.and_then(|e| e.band.as_ref()).map(|b| b.radius_km),
+        claim_verdict,
+        claimed,
+        tunnel,
+        tunnel_bursts: records
+            .iter()
+            .filter(|r| r.iface_is_tunnel || r.utun_active)
+            .count(),
+        relay,
+        relay_bursts,
+        bursts: records.len(),
+        anchors_used,
+        quality,
+        coverage_pct,
+        ntp_synced,
+        caveats,
+    }
+}
+
+/// The relay most bursts reported and how many did. Relays never carry the
+/// probes, so unlike a tunnel this does not move the estimate.
+fn relay_state(records: &[BurstRecord]) -> (Option<String>, usize) {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for relay in records.iter().filter_map(|r| r.relay.as_deref()) {
+        *counts.entry(relay).or_default() += 1;
+    }
+    let bursts = counts.values().sum();
+    let top = counts
+        .into_iter()
+        .max_by_key(|&(_, n)| n)
+        .map(|(relay, _)| relay.to_string());
+    (top, bursts)
+}
+
+fn print_summary(s: &Summary, fmt: &ReportFormat) {
+    match (s.estimate_lat, s.estimate_lon) {
+        (Some(lat), Some(lon)) => {
+            let radius = match s.radius_km {
+                Some(km) => format!(" ± {} {}", fmt.dist(km, 0), fmt.unit.label()),
+                None => String::new(),
+            };
+            println!(
+                "Location: about {}{} {}{}",
+                fmt.num(lat, 2),
+                fmt.list_separator(),
+                fmt.num(lon, 2),
+                radius
+            );
+        }
+        _ => println!("Location: could not be estimated"),
+    }
+    let claim = match (s.claimed, s.claim_verdict) {
+        (false, _) => "not checked (no claimed location given)",
+        (true, Some("consistent")) => "consistent with the claimed location",
+        (true, Some("suspect")) => {
+            "suspect: the claimed location is at the edge of what the latencies allow"
+        }
+        (true, Some(_)) => "inconsistent: the latencies rule out the claimed location",
+        (true, None) => "could not be checked",
+    };
+    println!("Claim: {claim}");
+    let tunnel = match s.tunnel {
+        "active" => "in use",
+        "partial" => "used part of the time",
+        _ => "not seen",
+    };
+    let relay = match s.relay.as_deref() {
+        Some(PRIVATE_RELAY) => format!("; iCloud Private Relay on in {}", s.relay_bursts),
+        Some(other) => format!("; relay {other} on in {}", s.relay_bursts),
+        None => String::new(),
+    };
+    println!(
+        "Tunnel/VPN: {tunnel} ({} of {} bursts over a tunnel interface{relay})",
+        s.tunnel_bursts, s.bursts
+    );
+    let clock = match s.ntp_synced {
+        Some(true) => "clock synced",
+        Some(false) => "clock NOT synced",
+        None => "clock sync unknown",
+    };
+    let coverage = match s.coverage_pct {
+        Some(pct) => format!("{}% coverage", fmt.num(pct, 0)),
+        None => "coverage unknown".to_string(),
+    };
+    println!(
+        "Data quality: {} ({} anchors, {coverage}, {clock})",
+        s.quality.grade, s.anchors_used
+    );
+    if s.caveats.is_empty() {
+        println!("Caveats: none");
+    } else {
+        println!("Caveats: {}", s.caveats.join("; "));
+    }
+}
+
+pub fn run(args: SummaryArgs) -> io::Result<()> {
+    let cfg = Config::load_layered(&args.config, &args.overrides)?;
+    let endpoints = endpoints_by_id(&cfg.endpoints);
+    let calibration = match &args.calibration {
+        Some(path) => Some(load_calibration(path)?),
+        None => None,
+    };
+    let model = Linear {
+        speed_km_s: args.speed_km_s / args.path_stretch.max(MIN_PATH_STRETCH),
+    };
+    let mut records = load_jsonl(&args.session)?;
+    transport::correct_transport_bias(&mut records, calibration.as_ref(), true);
+    let mut stats = build_stats(&records, None);
+    SampleGate {
+        min_samples: cfg.min_samples_per_endpoint,
+        min_bursts: cfg.min_bursts_per_endpoint,
+    }
+    .apply(&mut stats);
+    let margin_ms = bufferbloat::summarize(&records).map_or(0.0, |b| b.margin_ms);
+    let estimate = estimate_location(
+        &stats,
+        &endpoints,
+        &model,
+        DEFAULT_GRID_DEG,
+        DEFAULT_REFINE_DEG,
+        DEFAULT_BAND_FACTOR,
+        DEFAULT_BAND_WINDOW_DEG,
+        margin_ms,
+        calibration.as_ref(),
+        Weighting::default(),
+        args.distance_model,
+    );
+    let claim = args.claim_lat.zip(args.claim_lon);
+    let verdict = claim.and_then(|(lat, lon)| {
+        claim_verdict(&claim_checks(
+            &stats,
+            &endpoints,
+            lat,
+            lon,
+            &model,
+            calibration.as_ref(),
+            args.distance_model,
+        ))
+    });
+    let coverage_pct = coverage::coverage(
+        &records,
+        cfg.interval_seconds,
+        &coverage::load_suspends(&args.session)?,
+    )
+    .iter()
+    .filter_map(|c| c.coverage_pct)
+    .min_by(f64::total_cmp);
+    let clock = load_clock_quality(&args.session)?;
+    let thresholds = QualityThresholds::load_or_default(args.quality_thresholds.as_deref())?;
+    let quality = quality::assess(
+        &thresholds,
+        &QualityInputs {
+            stats: &stats,
+            endpoints: &endpoints,
+            records: &records,
+            samples_per_burst: cfg.samples_per_endpoint,
+            at: estimate.as_ref().map(|e| (e.lat, e.lon)).or(claim),
+            calibration: calibration.as_ref(),
+            clock: clock.as_ref(),
+            now_ms: now_unix_ms(),
+        },
+    );
+    let summary = summarize(
+        &records,
+        estimate.as_ref(),
+        verdict,
+        claim.is_some(),
+        coverage_pct,
+        clock.as_ref(),
+        quality,
+    );
+
+    if args.json {
+        let text = serde_json::to_string_pretty(&summary).map_err(io::Error::other)?;
+        println!("{text}");
+    } else {
+        print_summary(
+            &summary,
+            &ReportFormat::new(args.units, args.locale.as_deref(), DEFAULT_DISPLAY_DECIMALS),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn summary_grades_data_and_lists_caveats() {
+        let tunneled = BurstRecord {
+            iface_is_tunnel: true,
+            ..record("a", vec![10.0])
+        };
+        let records = vec![tunneled.clone(), tunneled, record("a", vec![10.0])];
+        let estimate = Estimate {
+            lat: 52.5,
+            lon: 13.4,
+            bias_ms: 0.0,
+            sse: 0.0,
+            points: 4,
+            band: None,
+            geometry: None,
+        };
+        let graded = |grade| quality::DataQuality {
+            grade,
+            factors: vec![quality::QualityFactor {
+                factor: "samples",
+                value: Some(40.0),
+                grade: Some(grade),
+            }],
+        };
+        let clean = summarize(
+            &records[2..],
+            Some(&estimate),
+            Some("consistent"),
+            true,
+            Some(99.0),
+            None,
+            graded('A'),
+        );
+        assert_eq!((clean.quality.grade, clean.tunnel), ('A', "none"));
+        assert!(clean.caveats.is_empty());
+
+        let clock = ClockQuality {
+            ntp_synced: Some(false),
+            ..ClockQuality::default()
+        };
+        let s = summarize(
+            &records,
+            Some(&estimate),
+            None,
+            true,
+            Some(80.0),
+            Some(&clock),
+            graded('C'),
+        );
+        assert_eq!((s.tunnel, s.tunnel_bursts, s.bursts), ("active", 2, 3));
+        // Tunnel, unchecked claim, gaps, clock, and the grade itself.
+        assert_eq!(s.caveats.len(), 5);
+        assert!(s.caveats[4].starts_with("data quality C (samples)"));
+
+        let none = summarize(&records[2..], None, None, false, None, None, graded('A'));
+        assert_eq!(none.anchors_used, 0);
+        assert_eq!(
+            none.caveats,
+            vec!["too few anchors answered to place the host"]
+        );
+
+        let relayed = BurstRecord {
+            relay: Some(lattice_core::PRIVATE_RELAY.to_string()),
+            ..record("a", vec![10.0])
+        };
+        let r = summarize(
+            &[relayed],
+            Some(&estimate),
+            None,
+            false,
+            None,
+            None,
+            graded('A'),
+        );
+        assert_eq!(
+            (r.tunnel, r.relay.as_deref(), r.relay_bursts),
+            ("none", Some("privateRelay"), 1)
+        );
+        assert!(r.caveats[0].starts_with("iCloud Private Relay was on"));
+    }
+}
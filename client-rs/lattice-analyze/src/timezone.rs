@@ -0,0 +1,262 @@
+use crate::units::ReportFormat;
+use lattice_core::{haversine_km, EventRecord, HostLocale, EVENT_RECORD_TYPE, SESSION_START_EVENT};
+use serde::Serialize;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+/// The sun crosses 15 degrees of longitude per hour: 4 minutes per degree.
+const MINUTES_PER_DEGREE: f64 = 4.0;
+const MINUTES_PER_DAY: i32 = 24 * 60;
+/// Civil time runs up to about two hours off solar time (western China,
+/// Spain, Argentina) before daylight saving; beyond this the offset points
+/// somewhere else.
+const MAX_SOLAR_OFFSET_DIFF_MINUTES: i32 = 150;
+/// How far a location can be from a zone's namesake city and still plausibly
+/// use that zone; the large zones (America/Chicago, Asia/Shanghai) reach this.
+const MAX_ZONE_DISTANCE_KM: f64 = 3000.0;
+
+/// Namesake city of common IANA zones, for the distance check. Zones not
+/// listed get the UTC-offset check only.
+const ZONE_CITIES: &[(&str, f64, f64)] = &[
+    ("Africa/Cairo", 30.04, 31.24),
+    ("Africa/Casablanca", 33.57, -7.59),
+    ("Africa/Johannesburg", -26.20, 28.05),
+    ("Africa/Lagos", 6.52, 3.38),
+    ("Africa/Nairobi", -1.29, 36.82),
+    ("America/Anchorage", 61.22, -149.90),
+    ("America/Argentina/Buenos_Aires", -34.60, -58.38),
+    ("America/Bogota", 4.71, -74.07),
+    ("America/Chicago", 41.88, -87.63),
+    ("America/Denver", 39.74, -104.99),
+    ("America/Halifax", 44.65, -63.58),
+    ("America/Lima", -12.05, -77.04),
+    ("America/Los_Angeles", 34.05, -118.24),
+    ("America/Mexico_City", 19.43, -99.13),
+    ("America/New_York", 40.71, -74.01),
+    ("America/Phoenix", 33.45, -112.07),
+    ("America/Santiago", -33.45, -70.67),
+    ("America/Sao_Paulo", -23.55, -46.63),
+    ("America/Toronto", 43.65, -79.38),
+    ("America/Vancouver", 49.28, -123.12),
+    ("Asia/Bangkok", 13.76, 100.50),
+    ("Asia/Dhaka", 23.81, 90.41),
+    ("Asia/Dubai", 25.20, 55.27),
+    ("Asia/Ho_Chi_Minh", 10.82, 106.63),
+    ("Asia/Hong_Kong", 22.32, 114.17),
+    ("Asia/Jakarta", -6.21, 106.85),
+    ("Asia/Jerusalem", 31.77, 35.21),
+    ("Asia/Karachi", 24.86, 67.01),
+    ("Asia/Kolkata", 22.57, 88.36),
+    ("Asia/Manila", 14.60, 120.98),
+    ("Asia/Riyadh", 24.71, 46.68),
+    ("Asia/Seoul", 37.57, 126.98),
+    ("Asia/Shanghai", 31.23, 121.47),
+    ("Asia/Singapore", 1.35, 103.82),
+    ("Asia/Taipei", 25.03, 121.57),
+    ("Asia/Tehran", 35.69, 51.39),
+    ("Asia/Tokyo", 35.68, 139.65),
+    ("Atlantic/Reykjavik", 64.15, -21.94),
+    ("Australia/Brisbane", -27.47, 153.03),
+    ("Australia/Melbourne", -37.81, 144.96),
+    ("Australia/Perth", -31.95, 115.86),
+    ("Australia/Sydney", -33.87, 151.21),
+    ("Europe/Amsterdam", 52.37, 4.90),
+    ("Europe/Athens", 37.98, 23.73),
+    ("Europe/Berlin", 52.52, 13.40),
+    ("Europe/Brussels", 50.85, 4.35),
+    ("Europe/Dublin", 53.35, -6.26),
+    ("Europe/Helsinki", 60.17, 24.94),
+    ("Europe/Istanbul", 41.01, 28.98),
+    ("Europe/Kyiv", 50.45, 30.52),
+    ("Europe/Lisbon", 38.72, -9.14),
+    ("Europe/London", 51.51, -0.13),
+    ("Europe/Madrid", 40.42, -3.70),
+    ("Europe/Moscow", 55.76, 37.62),
+    ("Europe/Oslo", 59.91, 10.75),
+    ("Europe/Paris", 48.86, 2.35),
+    ("Europe/Prague", 50.08, 14.44),
+    ("Europe/Rome", 41.90, 12.50),
+    ("Europe/Stockholm", 59.33, 18.07),
+    ("Europe/Vienna", 48.21, 16.37),
+    ("Europe/Warsaw", 52.23, 21.01),
+    ("Europe/Zurich", 47.38, 8.54),
+    ("Pacific/Auckland", -36.85, 174.76),
+    ("Pacific/Honolulu", 21.31, -157.86),
+];
+
+/// Old names the tz database still links to current ones.
+const ZONE_ALIASES: &[(&str, &str)] = &[
+    ("Asia/Calcutta", "Asia/Kolkata"),
+    ("Asia/Saigon", "Asia/Ho_Chi_Minh"),
+    ("Europe/Kiev", "Europe/Kyiv"),
+    ("US/Eastern", "America/New_York"),
+    ("US/Central", "America/Chicago"),
+    ("US/Mountain", "America/Denver"),
+    ("US/Pacific", "America/Los_Angeles"),
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TimezoneComparison {
+    /// `estimate` (RTT-derived) or `claim`.
+    pub against: String,
+    pub lat: f64,
+    pub lon: f64,
+    /// Host UTC offset minus the solar offset at `lon`, wrapped to ±12h.
+    pub offset_diff_minutes: Option<i32>,
+    /// Distance from the zone's namesake city, when the zone is known.
+    pub zone_distance_km: Option<f64>,
+    pub mismatch: bool,
+    pub reasons: Vec<String>,
+}
+
+/// Host timezone against the estimated and claimed locations. The timezone is
+/// user-set, so a mismatch is supporting evidence, never a verdict on its own.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TimezoneCheck {
+    pub timezone: Option<String>,
+    pub utc_offset_minutes: Option<i32>,
+    pub locale: Option<String>,
+    pub zone_lat: Option<f64>,
+    pub zone_lon: Option<f64>,
+    pub comparisons: Vec<TimezoneComparison>,
+}
+
+fn zone_city(name: &str) -> Option<(f64, f64)> {
+    let name = ZONE_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, canonical)| canonical);
+    ZONE_CITIES
+        .iter()
+        .find(|(zone, _, _)| *zone == name)
+        .map(|&(_, lat, lon)| (lat, lon))
+}
+
+fn wrap_minutes(diff: i32) -> i32 {
+    let half = MINUTES_PER_DAY / 2;
+    (diff + half).rem_euclid(MINUTES_PER_DAY) - half
+}
+
+/// Compares the host's timezone with each `(label, lat, lon)` target.
+pub(crate) fn check(host: &HostLocale, targets: &[(&str, f64, f64)]) -> TimezoneCheck {
+    let zone = host.timezone.as_deref().and_then(zone_city);
+    let comparisons = targets
+        .iter()
+        .map(|&(label, lat, lon)| {
+            let mut reasons = Vec::new();
+            let offset_diff = host.utc_offset_minutes.map(|offset| {
+                let solar = (lon * MINUTES_PER_DEGREE).round() as i32;
+                wrap_minutes(offset - solar)
+            });
+            if let Some(diff) = offset_diff.filter(|d| d.abs() > MAX_SOLAR_OFFSET_DIFF_MINUTES) {
+                reasons.push(format!(
+                    "UTC offset is {:+.1}h from solar time at lon {:.1}",
+                    diff as f64 / 60.0,
+                    lon
+                ));
+            }
+            let zone_distance = zone.map(|(zlat, zlon)| haversine_km(zlat, zlon, lat, lon));
+            if let Some(km) = zone_distance.filter(|km| *km > MAX_ZONE_DISTANCE_KM) {
+                reasons.push(format!(
+                    "{} is {:.0} km away",
+                    host.timezone.as_deref().unwrap_or("zone"),
+                    km
+                ));
+            }
+            TimezoneComparison {
+                against: label.to_string(),
+                lat,
+                lon,
+                offset_diff_minutes: offset_diff,
+                zone_distance_km: zone_distance,
+                mismatch: !reasons.is_empty(),
+                reasons,
+            }
+        })
+        .collect();
+    TimezoneCheck {
+        timezone: host.timezone.clone(),
+        utc_offset_minutes: host.utc_offset_minutes,
+        locale: host.locale.clone(),
+        zone_lat: zone.map(|z| z.0),
+        zone_lon: zone.map(|z| z.1),
+        comparisons,
+    }
+}
+
+/// `detail.hostLocale` from the session's last `session_start` header, if the
+/// client recorded one.
+pub(crate) fn load_host_locale(path: &Path) -> io::Result<Option<HostLocale>> {
+    let reader = crate::open_log(path)?;
+    let mut found = None;
+    for line in reader.lines() {
+        let line = line?;
+        if !line.contains(SESSION_START_EVENT) {
+            continue;
+        }
+        let Ok(ev) = serde_json::from_str::<EventRecord>(&line) else {
+            continue;
+        };
+        if ev.record_type != EVENT_RECORD_TYPE || ev.event != SESSION_START_EVENT {
+            continue;
+        }
+        if let Some(host) = ev.detail.get("hostLocale") {
+            found = serde_json::from_value(host.clone()).ok();
+        }
+    }
+    Ok(found)
+}
+
+pub(crate) fn print_timezone_check(check: &TimezoneCheck, fmt: &ReportFormat) {
+    let offset = check.utc_offset_minutes.map_or("-".to_string(), |m| {
+        format!("UTC{}h", fmt.signed(m as f64 / 60.0, 1))
+    });
+    println!(
+        "\nTimezone cross-check (supporting evidence only; the host's timezone is user-set): {} {} locale={}",
+        check.timezone.as_deref().unwrap_or("-"),
+        offset,
+        check.locale.as_deref().unwrap_or("-")
+    );
+    for c in &check.comparisons {
+        if c.mismatch {
+            println!("- {}: MISMATCH ({})", c.against, c.reasons.join("; "));
+        } else {
+            println!("- {}: consistent", c.against);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timezone_check_flags_zones_far_from_the_estimate() {
+        let host = HostLocale {
+            timezone: Some("Asia/Calcutta".to_string()),
+            utc_offset_minutes: Some(330),
+            locale: Some("en_IN.UTF-8".to_string()),
+        };
+        let result = check(&host, &[("estimate", 48.86, 2.35), ("claim", 28.61, 77.21)]);
+        assert_eq!(result.zone_lat, Some(22.57));
+        let (paris, delhi) = (&result.comparisons[0], &result.comparisons[1]);
+        assert!(paris.mismatch);
+        assert_eq!(paris.offset_diff_minutes, Some(321));
+        assert_eq!(paris.reasons.len(), 2);
+        assert!(!delhi.mismatch, "{:?}", delhi.reasons);
+        assert!(delhi.zone_distance_km.unwrap() < 1500.0);
+
+        // Across the date line the offset wraps instead of reading as a day off.
+        let fiji_ish = HostLocale {
+            timezone: None,
+            utc_offset_minutes: Some(780),
+            locale: None,
+        };
+        let result = check(&fiji_ish, &[("estimate", -17.7, -178.0)]);
+        assert_eq!(result.comparisons[0].offset_diff_minutes, Some(52));
+        assert!(!result.comparisons[0].mismatch);
+        assert_eq!(result.zone_lat, None);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::units::ReportFormat;
+use crate::{quantile, record_samples};
+use lattice_core::{BurstRecord, BUSY_IFACE_BYTES_PER_SEC};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+const BYTES_PER_MB: f64 = 1_000_000.0;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct EndpointTraffic {
+    pub endpoint: String,
+    /// Bursts that carry `ifaceTraffic`.
+    pub bursts: usize,
+    /// Bursts at or above `BUSY_IFACE_BYTES_PER_SEC`.
+    pub busy_bursts: usize,
+    pub max_bytes_per_sec: Option<f64>,
+    /// Median of the per-burst median RTTs, busy and quiet bursts apart.
+    pub busy_p50_ms: Option<f64>,
+    pub quiet_p50_ms: Option<f64>,
+    /// `busyP50Ms - quietP50Ms`: RTT inflation that coincides with cross-traffic.
+    pub inflation_ms: Option<f64>,
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    values.sort_by(f64::total_cmp);
+    quantile(&values, 0.5)
+}
+
+/// Splits each endpoint's bursts by the traffic their interface carried and
+/// compares the RTTs. Endpoints whose records have no counters are left out.
+pub(crate) fn cross_traffic(records: &[BurstRecord]) -> Vec<EndpointTraffic> {
+    let mut by_endpoint: BTreeMap<&str, Vec<(f64, Option<f64>)>> = BTreeMap::new();
+    for rec in records {
+        let Some(rate) = rec.iface_traffic.and_then(|t| t.bytes_per_sec()) else {
+            continue;
+        };
+        let mut samples: Vec<f64> = record_samples(rec)
+            .into_iter()
+            .filter(|v| v.is_finite() && *v >= 0.0)
+            .collect();
+        samples.sort_by(f64::total_cmp);
+        let median = quantile(&samples, 0.5).or(rec.median_ms);
+        by_endpoint
+            .entry(rec.endpoint_id.as_str())
+            .or_default()
+            .push((rate, median));
+    }
+    by_endpoint
+        .into_iter()
+        .map(|(id, bursts)| {
+            let (busy, quiet): (Vec<(f64, Option<f64>)>, Vec<_>) = bursts
+                .iter()
+                .partition(|(rate, _)| *rate >= BUSY_IFACE_BYTES_PER_SEC);
+            let busy_p50 = median(busy.iter().filter_map(|b| b.1).collect());
+            let quiet_p50 = median(quiet.iter().filter_map(|b| b.1).collect());
+            EndpointTraffic {
+                endpoint: id.to_string(),
+                bursts: bursts.len(),
+                busy_bursts: busy.len(),
+                max_bytes_per_sec: bursts.iter().map(|b| b.0).max_by(f64::total_cmp),
+                busy_p50_ms: busy_p50,
+                quiet_p50_ms: quiet_p50,
+                inflation_ms: busy_p50.zip(quiet_p50).map(|(b, q)| b - q),
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn print_cross_traffic(label: &str, traffic: &[EndpointTraffic], fmt: &ReportFormat) {
+    let busy: Vec<&EndpointTraffic> = traffic.iter().filter(|t| t.busy_bursts > 0).collect();
+    if busy.is_empty() {
+        return;
+    }
+    println!(
+        "\n{label} cross-traffic (bursts while the interface moved {}+ MB/s; p50 busy vs quiet in ms):",
+        fmt.num(BUSY_IFACE_BYTES_PER_SEC / BYTES_PER_MB, 0)
+    );
+    for t in busy {
+        println!(
+            "- {} busy={}/{} peak={}MB/s p50 busy={} quiet={} inflation={}",
+            t.endpoint,
+            t.busy_bursts,
+            t.bursts,
+            fmt.num(t.max_bytes_per_sec.unwrap_or(0.0) / BYTES_PER_MB, 1),
+            fmt.ms_opt(t.busy_p50_ms),
+            fmt.ms_opt(t.quiet_p50_ms),
+            fmt.ms_opt(t.inflation_ms)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn cross_traffic_compares_busy_and_quiet_burst_rtts() {
+        let burst = |rx_bytes: u64, samples: Vec<f64>| BurstRecord {
+            iface_traffic: Some(lattice_core::IfaceTraffic {
+                rx_bytes,
+                tx_bytes: 0,
+                rx_packets: 0,
+                tx_packets: 0,
+                duration_ms: 500.0,
+            }),
+            ..record("a", samples)
+        };
+        let records = vec![
+            burst(1_000, vec![20.0, 21.0, 22.0]),
+            burst(2_000, vec![21.0, 22.0, 23.0]),
+            burst(3_000, vec![19.0, 21.0, 30.0]),
+            burst(4_000_000, vec![60.0, 80.0, 70.0]),
+            record("b", vec![10.0]),
+        ];
+        let traffic = cross_traffic(&records);
+        assert_eq!(traffic.len(), 1);
+        let a = &traffic[0];
+        assert_eq!((a.bursts, a.busy_bursts), (4, 1));
+        assert_eq!(a.max_bytes_per_sec, Some(8_000_000.0));
+        assert_eq!(a.busy_p50_ms, Some(70.0));
+        assert_eq!(a.quiet_p50_ms, Some(21.0));
+        assert_eq!(a.inflation_ms, Some(49.0));
+    }
+}
@@ -0,0 +1,272 @@
+use crate::units::ReportFormat;
+use crate::{calibration_entry, quantile, record_samples, Calibration};
+use lattice_core::{BurstRecord, Transport};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// How much slower a fallback transport measured than UDP.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TransportBias {
+    pub transport: Transport,
+    /// Bursts corrected by `biasMs`, or left as measured.
+    pub bursts: usize,
+    /// Bursts whose endpoint has this transport in the calibration, mapped
+    /// through that instead.
+    pub calibrated_bursts: usize,
+    /// Endpoints that answered over both UDP and this transport; the bias is
+    /// measured on them alone.
+    pub paired_endpoints: usize,
+    /// Median over paired endpoints of this transport's minimum RTT minus
+    /// UDP's, never below 0. Null without a paired endpoint.
+    pub bias_ms: Option<f64>,
+    /// Whether `biasMs` was subtracted from this transport's samples.
+    pub applied: bool,
+}
+
+fn min_rtt(rec: &BurstRecord) -> Option<f64> {
+    record_samples(rec)
+        .into_iter()
+        .chain(rec.min_ms)
+        .filter(|v| v.is_finite() && *v >= 0.0)
+        .min_by(f64::total_cmp)
+}
+
+/// Replaces a record's samples and summary values with `f` of them.
+fn map_record(rec: &mut BurstRecord, f: impl Fn(f64) -> f64) {
+    rec.samples_ms = record_samples(rec).into_iter().map(&f).collect();
+    rec.histogram = None;
+    rec.min_ms = rec.min_ms.map(&f);
+    rec.p05_ms = rec.p05_ms.map(&f);
+    rec.median_ms = rec.median_ms.map(&f);
+}
+
+/// Splits a capture by transport, for stats kept per (endpoint, transport).
+pub(crate) fn by_transport(records: &[BurstRecord]) -> BTreeMap<Transport, Vec<BurstRecord>> {
+    let mut out: BTreeMap<Transport, Vec<BurstRecord>> = BTreeMap::new();
+    for rec in records {
+        out.entry(rec.transport).or_default().push(rec.clone());
+    }
+    out
+}
+
+/// Takes each burst whose endpoint has its transport in `calibration` from
+/// that transport's bias and scale to the endpoint's own, which the bounds
+/// then apply. Returns which records it mapped.
+fn apply_calibration(records: &mut [BurstRecord], calibration: Option<&Calibration>) -> Vec<bool> {
+    let scale = |s: f64| if s > 0.0 { s } else { 1.0 };
+    records
+        .iter_mut()
+        .map(|rec| {
+            let Some(entry) = calibration_entry(calibration, &rec.endpoint_id) else {
+                return false;
+            };
+            let Some(own) = entry.transports.get(&rec.transport) else {
+                return false;
+            };
+            let ratio = scale(entry.scale) / scale(own.scale);
+            let (from, to) = (own.bias_ms, entry.bias_ms);
+            map_record(rec, |v| (to + (v - from) * ratio).max(0.0));
+            true
+        })
+        .collect()
+}
+
+/// Measures each non-UDP transport's extra latency against UDP on endpoints
+/// that answered over both (after a fallback and a retry, say), and with
+/// `apply` subtracts it from every burst that transport produced, so TCP
+/// bursts do not read as extra distance. Bursts the calibration covers are
+/// mapped through it first and left out of the measurement. Records without
+/// raw samples get their histogram or summary values as corrected
+/// `samplesMs`.
+pub(crate) fn correct_transport_bias(
+    records: &mut [BurstRecord],
+    calibration: Option<&Calibration>,
+    apply: bool,
+) -> Vec<TransportBias> {
+    let calibrated = apply_calibration(records, calibration);
+    let mut mins: BTreeMap<(&str, Transport), f64> = BTreeMap::new();
+    let mut bursts: BTreeMap<Transport, (usize, usize)> = BTreeMap::new();
+    for (rec, &calibrated) in records.iter().zip(&calibrated) {
+        let counts = bursts.entry(rec.transport).or_default();
+        if calibrated {
+            counts.1 += 1;
+        } else {
+            counts.0 += 1;
+        }
+        // Calibrated UDP stays a reference: it now reads like the endpoint's
+        // own calibration, which is UDP's whenever it has one.
+        if calibrated && rec.transport != Transport::Udp {
+            continue;
+        }
+        if let Some(ms) = min_rtt(rec) {
+            let min = mins
+                .entry((rec.endpoint_id.as_str(), rec.transport))
+                .or_insert(ms);
+            *min = min.min(ms);
+        }
+    }
+    let biases: Vec<TransportBias> = bursts
+        .into_iter()
+        .filter(|(transport, _)| *transport != Transport::Udp)
+        .map(|(transport, (bursts, calibrated_bursts))| {
+            let mut offsets: Vec<f64> = mins
+                .iter()
+                .filter(|((_, t), _)| *t == transport)
+                .filter_map(|((id, _), ms)| Some(ms - mins.get(&(*id, Transport::Udp))?))
+                .collect();
+            offsets.sort_by(f64::total_cmp);
+            let bias_ms = quantile(&offsets, 0.5).map(|b| b.max(0.0));
+            TransportBias {
+                transport,
+                bursts,
+                calibrated_bursts,
+                paired_endpoints: offsets.len(),
+                bias_ms,
+                applied: apply && bias_ms.is_some(),
+            }
+        })
+        .collect();
+    for bias in biases.iter().filter(|b| b.applied) {
+        let Some(ms) = bias.bias_ms else { continue };
+        for (rec, _) in records
+            .iter_mut()
+            .zip(&calibrated)
+            .filter(|(r, calibrated)| r.transport == bias.transport && !**calibrated)
+        {
+            map_record(rec, |v| (v - ms).max(0.0));
+        }
+    }
+    biases
+}
+
+pub(crate) fn print_transport_bias(label: &str, biases: &[TransportBias], fmt: &ReportFormat) {
+    if biases.is_empty() {
+        return;
+    }
+    println!("\n{label} transport bias (vs UDP, ms):");
+    for b in biases {
+        let status = match (b.applied, b.bias_ms) {
+            (true, _) => "subtracted",
+            (false, Some(_)) => "not applied",
+            (false, None) if b.bursts == 0 => "all calibrated",
+            (false, None) => "uncorrected: no endpoint answered over both",
+        };
+        println!(
+            "- {} bursts={} calibrated={} paired endpoints={} bias={} ({})",
+            b.transport.as_str(),
+            b.bursts,
+            b.calibrated_bursts,
+            b.paired_endpoints,
+            fmt.ms_opt(b.bias_ms),
+            status
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+    use crate::{build_calibration, DistanceModel, PropagationSpec};
+
+    #[test]
+    fn tcp_bursts_are_corrected_by_their_bias_against_udp() {
+        let tcp = |id: &str, samples: Vec<f64>| BurstRecord {
+            transport: Transport::Tcp,
+            ..record(id, samples)
+        };
+        let mut records = vec![
+            record("a", vec![20.0, 22.0]),
+            tcp("a", vec![23.0, 25.0]),
+            record("b", vec![40.0]),
+            tcp("b", vec![45.0]),
+            record("c", vec![50.0]),
+            tcp("c", vec![54.0]),
+            // Only reachable over TCP: corrected by the others' median.
+            BurstRecord {
+                samples_ms: Vec::new(),
+                min_ms: Some(60.0),
+                ..tcp("d", Vec::new())
+            },
+        ];
+        let kept = records.clone();
+        let biases = correct_transport_bias(&mut records, None, true);
+        assert_eq!(biases.len(), 1);
+        let b = &biases[0];
+        assert_eq!(
+            (b.transport, b.bursts, b.paired_endpoints),
+            (Transport::Tcp, 4, 3)
+        );
+        assert_eq!(b.bias_ms, Some(4.0));
+        assert!(b.applied);
+        assert_eq!(records[1].samples_ms, vec![19.0, 21.0]);
+        assert_eq!(records[0].samples_ms, kept[0].samples_ms);
+        assert!(records[6].samples_ms.is_empty());
+        assert_eq!(records[6].min_ms, Some(56.0));
+
+        // Without the flag the bias is still reported, but records stay put.
+        let mut records = kept.clone();
+        let biases = correct_transport_bias(&mut records, None, false);
+        assert_eq!(biases[0].bias_ms, Some(4.0));
+        assert!(!biases[0].applied);
+        assert_eq!(records[1].samples_ms, kept[1].samples_ms);
+
+        // TCP with no UDP pair can't be corrected.
+        let mut lone = vec![tcp("d", vec![60.0])];
+        let biases = correct_transport_bias(&mut lone, None, true);
+        assert_eq!((biases[0].bias_ms, biases[0].applied), (None, false));
+        assert_eq!(lone[0].samples_ms, vec![60.0]);
+    }
+
+    #[test]
+    fn calibration_keeps_a_bias_per_transport_and_maps_bursts_through_it() {
+        let cfg = sample_config(vec![endpoint("a", 0.0, 0.0), endpoint("b", 0.0, 0.0)]);
+        let mut udp = stats_with_p05("a", 10.0);
+        udp.extend(stats_with_p05("b", 20.0));
+        let stats = BTreeMap::from([
+            (Transport::Udp, udp),
+            (Transport::Tcp, stats_with_p05("a", 16.0)),
+        ]);
+        let cal = build_calibration(
+            &cfg,
+            &stats,
+            0.0,
+            0.0,
+            DEFAULT_SPEED_KM_S,
+            TEST_PATH_STRETCH,
+            DistanceModel::Sphere,
+            &PropagationSpec::linear(DEFAULT_SPEED_KM_S / TEST_PATH_STRETCH),
+        );
+        let a = &cal.endpoints["a"];
+        assert!((a.bias_ms - 10.0).abs() < TEST_EPSILON);
+        assert!((a.transports[&Transport::Tcp].bias_ms - 16.0).abs() < TEST_EPSILON);
+        assert_eq!(cal.endpoints["b"].transports.len(), 1);
+        let json = serde_json::to_string(&cal).unwrap();
+        assert!(json.contains(r#""transports":{"udp":"#));
+        let cal: Calibration = serde_json::from_str(&json).unwrap();
+
+        // A's TCP bursts go through its calibration and stay out of the
+        // measured bias; B's still get the median from the pair left over.
+        let tcp = |id: &str, samples: Vec<f64>| BurstRecord {
+            transport: Transport::Tcp,
+            ..record(id, samples)
+        };
+        let mut records = vec![
+            record("a", vec![10.0]),
+            tcp("a", vec![16.0, 18.0]),
+            record("b", vec![20.0]),
+            tcp("b", vec![23.0]),
+        ];
+        let biases = correct_transport_bias(&mut records, Some(&cal), true);
+        let b = &biases[0];
+        assert_eq!(
+            (b.bursts, b.calibrated_bursts, b.paired_endpoints),
+            (1, 1, 1)
+        );
+        assert_eq!(b.bias_ms, Some(3.0));
+        assert_eq!(records[1].samples_ms, vec![10.0, 12.0]);
+        assert_eq!(records[3].samples_ms, vec![20.0]);
+        assert_eq!(records[0].samples_ms, vec![10.0]);
+    }
+}
@@ -0,0 +1,347 @@
+use crate::{fmt_opt, load_jsonl, quantile, record_samples};
+use clap::Parser;
+use lattice_core::{BurstRecord, Config};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Replies slower than this share of the observed RTTs are rare enough to
+/// give up on; the timeout sits just past it.
+const TIMEOUT_QUANTILE: f64 = 0.99;
+const TIMEOUT_MARGIN_FRACTION: f64 = 0.25;
+const TIMEOUT_MIN_MARGIN_MS: f64 = 20.0;
+/// A sample within this of the endpoint's p05 counts as reaching the floor.
+const FLOOR_TOLERANCE_MS: f64 = 1.0;
+/// Chance each burst should have of seeing at least one floor sample.
+const FLOOR_HIT_CONFIDENCE: f64 = 0.95;
+const MIN_SUGGESTED_SAMPLES: usize = 5;
+const MAX_SUGGESTED_SAMPLES: usize = 100;
+/// Above this loss, probes may be tripping a policer; spread them out.
+const HIGH_LOSS: f64 = 0.02;
+const HIGH_LOSS_SPACING_FACTOR: u64 = 2;
+/// Endpoints with fewer replies than this get no suggestion.
+const MIN_TUNE_SAMPLES: usize = 20;
+const MS_PER_SEC: f64 = 1000.0;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lattice-analyze tune",
+    about = "Suggest samplesPerEndpoint, spacingMs, and timeoutMs from a session's RTTs and loss"
+)]
+pub struct TuneArgs {
+    #[arg(long)]
+    config: PathBuf,
+
+    #[arg(long)]
+    session: PathBuf,
+
+    /// Write a copy of the config with the suggested values applied.
+    #[arg(long)]
+    config_out: Option<PathBuf>,
+
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Settings {
+    pub samples_per_endpoint: usize,
+    pub spacing_ms: u64,
+    pub timeout_ms: u64,
+    pub interval_seconds: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointTuning {
+    pub endpoint: String,
+    pub bursts: usize,
+    pub samples: usize,
+    /// `None` when the session kept no per-probe data.
+    pub loss: Option<f64>,
+    pub p50_ms: Option<f64>,
+    pub p99_ms: Option<f64>,
+    /// Share of replies within `FLOOR_TOLERANCE_MS` of the endpoint's p05.
+    pub floor_hit_rate: Option<f64>,
+    pub timeout_ms: Option<u64>,
+    pub samples_per_endpoint: Option<usize>,
+    pub spacing_ms: Option<u64>,
+    pub reasons: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tuning {
+    pub current: Settings,
+    /// One value per setting for the whole config: the most demanding endpoint wins.
+    pub suggested: Settings,
+    pub endpoints: Vec<EndpointTuning>,
+}
+
+/// Probes needed per burst so that, at hit rate `hit` and loss `loss`, a
+/// burst sees at least one floor sample with `FLOOR_HIT_CONFIDENCE`.
+fn samples_for_floor(hit: f64, loss: f64) -> usize {
+    if hit >= 1.0 {
+        return MIN_SUGGESTED_SAMPLES;
+    }
+    let replies = (1.0 - FLOOR_HIT_CONFIDENCE).ln() / (1.0 - hit).ln();
+    let sent = replies / (1.0 - loss).max(f64::EPSILON);
+    if !sent.is_finite() {
+        return MAX_SUGGESTED_SAMPLES;
+    }
+    (sent.ceil() as usize).clamp(MIN_SUGGESTED_SAMPLES, MAX_SUGGESTED_SAMPLES)
+}
+
+fn tune_endpoint(
+    id: &str,
+    recs: &[&BurstRecord],
+    cfg: &Config,
+    min_spacing_ms: u64,
+) -> EndpointTuning {
+    let mut samples = Vec::new();
+    let mut probes_known = 0usize;
+    let mut replies_known = 0usize;
+    for rec in recs {
+        let replies = if !rec.samples_ms.is_empty() {
+            Some(rec.samples_ms.len())
+        } else {
+            rec.histogram.as_ref().map(|h| h.count())
+        };
+        if let Some(n) = replies {
+            probes_known += cfg.samples_per_endpoint.max(n);
+            replies_known += n;
+        }
+        samples.extend(
+            record_samples(rec)
+                .into_iter()
+                .filter(|v| v.is_finite() && *v >= 0.0),
+        );
+    }
+    samples.sort_by(f64::total_cmp);
+    let loss = (probes_known > 0).then(|| 1.0 - replies_known as f64 / probes_known as f64);
+    let p05 = quantile(&samples, 0.05);
+    let p50 = quantile(&samples, 0.5);
+    let p99 = quantile(&samples, TIMEOUT_QUANTILE);
+    let floor_hit_rate = p05.map(|floor| {
+        samples
+            .iter()
+            .filter(|v| **v <= floor + FLOOR_TOLERANCE_MS)
+            .count() as f64
+            / samples.len() as f64
+    });
+
+    let mut tuning = EndpointTuning {
+        endpoint: id.to_string(),
+        bursts: recs.len(),
+        samples: samples.len(),
+        loss,
+        p50_ms: p50,
+        p99_ms: p99,
+        floor_hit_rate,
+        timeout_ms: None,
+        samples_per_endpoint: None,
+        spacing_ms: None,
+        reasons: Vec::new(),
+    };
+    if samples.len() < MIN_TUNE_SAMPLES {
+        tuning.reasons.push(format!(
+            "only {} replies; need {} to suggest values",
+            samples.len(),
+            MIN_TUNE_SAMPLES
+        ));
+        return tuning;
+    }
+    if let Some(p99) = p99 {
+        let margin = (p99 * TIMEOUT_MARGIN_FRACTION).max(TIMEOUT_MIN_MARGIN_MS);
+        let timeout = (p99 + margin).ceil() as u64;
+        tuning.reasons.push(format!(
+            "timeout: p99 {:.1}ms + {:.1}ms margin",
+            p99, margin
+        ));
+        tuning.timeout_ms = Some(timeout);
+    }
+    if let Some(hit) = floor_hit_rate {
+        let loss = loss.unwrap_or(0.0);
+        tuning.samples_per_endpoint = Some(samples_for_floor(hit, loss));
+        tuning.reasons.push(format!(
+            "samples: {:.0}% of replies reach the floor, {:.1}% loss; enough for a {:.0}% chance per burst",
+            hit * 100.0,
+            loss * 100.0,
+            FLOOR_HIT_CONFIDENCE * 100.0
+        ));
+    }
+    let mut spacing = cfg.spacing_ms.max(min_spacing_ms);
+    if loss.is_some_and(|l| l > HIGH_LOSS) {
+        spacing *= HIGH_LOSS_SPACING_FACTOR;
+        tuning.reasons.push(format!(
+            "spacing: loss above {:.0}%; probes spread out in case a policer drops them",
+            HIGH_LOSS * 100.0
+        ));
+    }
+    tuning.spacing_ms = Some(spacing);
+    tuning
+}
+
+pub fn build(cfg: &Config, records: &[BurstRecord]) -> Tuning {
+    let mut by_endpoint: BTreeMap<&str, Vec<&BurstRecord>> = BTreeMap::new();
+    for rec in records {
+        by_endpoint
+            .entry(rec.endpoint_id.as_str())
+            .or_default()
+            .push(rec);
+    }
+    // Spacing below the per-destination cap is raised by the client anyway.
+    let min_spacing_ms = (MS_PER_SEC / cfg.max_probes_per_sec_per_dest).ceil() as u64;
+    let endpoints: Vec<EndpointTuning> = by_endpoint
+        .iter()
+        .map(|(id, recs)| tune_endpoint(id, recs, cfg, min_spacing_ms))
+        .collect();
+
+    let current = Settings {
+        samples_per_endpoint: cfg.samples_per_endpoint,
+        spacing_ms: cfg.spacing_ms,
+        timeout_ms: cfg.timeout_ms,
+        interval_seconds: cfg.interval_seconds,
+    };
+    let samples_per_endpoint = endpoints
+        .iter()
+        .filter_map(|e| e.samples_per_endpoint)
+        .max()
+        .unwrap_or(cfg.samples_per_endpoint);
+    let spacing_ms = endpoints
+        .iter()
+        .filter_map(|e| e.spacing_ms)
+        .max()
+        .unwrap_or(cfg.spacing_ms);
+    let timeout_ms = endpoints
+        .iter()
+        .filter_map(|e| e.timeout_ms)
+        .max()
+        .unwrap_or(cfg.timeout_ms);
+    // Probes wait for their reply, so a burst lasts at least samples x the
+    // slower of spacing and the typical RTT; keep the interval above that.
+    let slowest_p50 = endpoints
+        .iter()
+        .filter_map(|e| e.p50_ms)
+        .fold(0.0, f64::max);
+    let burst_ms = samples_per_endpoint as f64 * (spacing_ms as f64).max(slowest_p50);
+    let interval_seconds = cfg
+        .interval_seconds
+        .max((burst_ms / MS_PER_SEC).ceil() as u64);
+    Tuning {
+        current,
+        suggested: Settings {
+            samples_per_endpoint,
+            spacing_ms,
+            timeout_ms,
+            interval_seconds,
+        },
+        endpoints,
+    }
+}
+
+/// The config file as written, with the suggested settings replaced.
+fn apply(config: &PathBuf, suggested: &Settings) -> io::Result<String> {
+    let data = fs::read(config)?;
+    let mut value: Value =
+        serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let obj = value
+        .as_object_mut()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "config is not a JSON object"))?;
+    obj.insert(
+        "samplesPerEndpoint".to_string(),
+        suggested.samples_per_endpoint.into(),
+    );
+    obj.insert("spacingMs".to_string(), suggested.spacing_ms.into());
+    obj.insert("timeoutMs".to_string(), suggested.timeout_ms.into());
+    obj.insert(
+        "intervalSeconds".to_string(),
+        suggested.interval_seconds.into(),
+    );
+    serde_json::to_string_pretty(&value).map_err(io::Error::other)
+}
+
+pub fn run(args: TuneArgs) -> io::Result<()> {
+    let cfg = Config::load(&args.config)?;
+    let records = load_jsonl(&args.session)?;
+    let tuning = build(&cfg, &records);
+
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&tuning).map_err(io::Error::other)?
+        );
+    } else {
+        println!("per-endpoint (loss, p50/p99 ms, suggested samples/spacing/timeout):");
+        for e in &tuning.endpoints {
+            let opt = |v: Option<u64>| v.map_or("-".to_string(), |v| v.to_string());
+            println!(
+                "- {} bursts={} replies={} loss={} p50={} p99={} -> samples={} spacing={} timeout={}",
+                e.endpoint,
+                e.bursts,
+                e.samples,
+                e.loss.map_or("-".to_string(), |l| format!("{:.1}%", l * 100.0)),
+                fmt_opt(e.p50_ms, 1),
+                fmt_opt(e.p99_ms, 1),
+                opt(e.samples_per_endpoint.map(|n| n as u64)),
+                opt(e.spacing_ms),
+                opt(e.timeout_ms),
+            );
+            for reason in &e.reasons {
+                println!("    {reason}");
+            }
+        }
+        let (c, s) = (&tuning.current, &tuning.suggested);
+        println!("\nconfig (current -> suggested):");
+        println!(
+            "  samplesPerEndpoint {} -> {}",
+            c.samples_per_endpoint, s.samples_per_endpoint
+        );
+        println!("  spacingMs {} -> {}", c.spacing_ms, s.spacing_ms);
+        println!("  timeoutMs {} -> {}", c.timeout_ms, s.timeout_ms);
+        println!(
+            "  intervalSeconds {} -> {}",
+            c.interval_seconds, s.interval_seconds
+        );
+    }
+
+    if let Some(out) = &args.config_out {
+        fs::write(out, apply(&args.config, &tuning.suggested)?)?;
+        eprintln!("wrote tuned config to {}", out.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn tune_suggests_timeout_samples_and_spacing_from_loss_and_spread() {
+        let cfg = sample_config(Vec::new());
+        let mut records: Vec<BurstRecord> = (0..10)
+            .map(|_| record("a", vec![20.0, 20.5, 30.0, 40.0, 50.0, 60.0, 70.0, 80.0]))
+            .collect();
+        records.push(record("b", vec![5.0, 6.0, 7.0]));
+        let tuning = build(&cfg, &records);
+
+        let a = &tuning.endpoints[0];
+        assert!((a.loss.unwrap() - 0.2).abs() < 1e-9);
+        assert!((a.floor_hit_rate.unwrap() - 0.25).abs() < 1e-9);
+        assert_eq!(a.timeout_ms, Some(100));
+        assert_eq!(a.samples_per_endpoint, Some(14));
+        assert_eq!(a.spacing_ms, Some(20));
+        let b = &tuning.endpoints[1];
+        assert_eq!((b.timeout_ms, b.samples_per_endpoint), (None, None));
+
+        assert_eq!(tuning.suggested.timeout_ms, 100);
+        assert_eq!(tuning.suggested.samples_per_endpoint, 14);
+        assert_eq!(tuning.suggested.spacing_ms, 20);
+        assert_eq!(tuning.suggested.interval_seconds, 10);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::constants::*;
+use clap::ValueEnum;
+use std::env;
+
+/// Languages that write `1.234,5`.
+const COMMA_DOT_LANGS: [&str; 14] = [
+    "de", "es", "it", "nl", "pt", "da", "el", "id", "ro", "tr", "hr", "sl", "sr", "vi",
+];
+/// Languages that write `1 234,5`, grouped with a no-break space.
+const COMMA_SPACE_LANGS: [&str; 13] = [
+    "fr", "ru", "uk", "pl", "cs", "sk", "sv", "fi", "nb", "nn", "no", "hu", "bg",
+];
+const NO_BREAK_SPACE: char = '\u{a0}';
+/// Checked in order, as the C library does for `LC_NUMERIC`.
+const LOCALE_ENV: [&str; 3] = ["LC_ALL", "LC_NUMERIC", "LANG"];
+const GROUP_DIGITS: usize = 3;
+
+/// Unit for distances in text reports. JSON stays in km.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum DistanceUnit {
+    #[default]
+    Km,
+    Mi,
+}
+
+impl DistanceUnit {
+    pub fn label(self) -> &'static str {
+        match self {
+            DistanceUnit::Km => "km",
+            DistanceUnit::Mi => "mi",
+        }
+    }
+}
+
+/// How text reports write numbers: distance unit, decimal and grouping
+/// separators, and the decimals for millisecond values (`--precision`).
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ReportFormat {
+    pub unit: DistanceUnit,
+    decimal: char,
+    group: Option<char>,
+    ms_decimals: usize,
+}
+
+impl ReportFormat {
+    /// `locale` is a POSIX or BCP 47 tag (`de_DE.UTF-8`, `en-US`); without
+    /// one it comes from `LC_ALL`, `LC_NUMERIC`, or `LANG`. `C`, `POSIX`, or
+    /// no locale at all keeps plain numbers.
+    pub fn new(unit: DistanceUnit, locale: Option<&str>, ms_decimals: usize) -> Self {
+        let from_env = || {
+            LOCALE_ENV
+                .iter()
+                .filter_map(|key| env::var(key).ok())
+                .find(|v| !v.is_empty())
+        };
+        let locale = locale
+            .map(str::to_string)
+            .or_else(from_env)
+            .unwrap_or_default();
+        let lang = locale
+            .split(['_', '-', '.', '@'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let (decimal, group) = match lang.as_str() {
+            "" | "c" | "posix" => ('.', None),
+            l if COMMA_DOT_LANGS.contains(&l) => (',', Some('.')),
+            l if COMMA_SPACE_LANGS.contains(&l) => (',', Some(NO_BREAK_SPACE)),
+            _ => ('.', Some(',')),
+        };
+        ReportFormat {
+            unit,
+            decimal,
+            group,
+            ms_decimals,
+        }
+    }
+
+    pub fn num(&self, v: f64, decimals: usize) -> String {
+        self.localize(&format!("{:.*}", decimals, v))
+    }
+
+    /// With a sign even when positive, for offsets.
+    pub fn signed(&self, v: f64, decimals: usize) -> String {
+        self.localize(&format!("{:+.*}", decimals, v))
+    }
+
+    /// `-` when there is no finite value.
+    pub fn opt(&self, v: Option<f64>, decimals: usize) -> String {
+        match v.filter(|v| v.is_finite()) {
+            Some(v) => self.num(v, decimals),
+            None => "-".to_string(),
+        }
+    }
+
+    pub fn ms_decimals(&self) -> usize {
+        self.ms_decimals
+    }
+
+    pub fn ms(&self, v: f64) -> String {
+        self.num(v, self.ms_decimals)
+    }
+
+    pub fn ms_opt(&self, v: Option<f64>) -> String {
+        self.opt(v, self.ms_decimals)
+    }
+
+    /// A distance given in km, in the report's unit, without the unit label.
+    pub fn dist(&self, km: f64, decimals: usize) -> String {
+        self.num(self.in_unit(km), decimals)
+    }
+
+    pub fn dist_opt(&self, km: Option<f64>, decimals: usize) -> String {
+        self.opt(km.map(|km| self.in_unit(km)), decimals)
+    }
+
+    pub fn in_unit(&self, km: f64) -> f64 {
+        match self.unit {
+            DistanceUnit::Km => km,
+            DistanceUnit::Mi => km / KM_PER_MILE,
+        }
+    }
+
+    /// Separates numbers in a list (`lat[a,b]`); `;` where `,` is the
+    /// decimal separator.
+    pub fn list_separator(&self) -> char {
+        if self.decimal == ',' {
+            ';'
+        } else {
+            ','
+        }
+    }
+
+    /// Swaps the separators into a number `format!` wrote.
+    fn localize(&self, plain: &str) -> String {
+        let (sign, digits) = match plain.strip_prefix(['-', '+']) {
+            Some(rest) => (&plain[..1], rest),
+            None => ("", plain),
+        };
+        let (int, frac) = match digits.split_once('.') {
+            Some((int, frac)) => (int, Some(frac)),
+            None => (digits, None),
+        };
+        let mut out = String::from(sign);
+        for (i, c) in int.chars().enumerate() {
+            let remaining = int.len() - i;
+            if let Some(group) = self
+                .group
+                .filter(|_| i > 0 && remaining % GROUP_DIGITS == 0)
+            {
+                out.push(group);
+            }
+            out.push(c);
+        }
+        if let Some(frac) = frac {
+            out.push(self.decimal);
+            out.push_str(frac);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_format_converts_units_and_localizes_separators() {
+        let plain = ReportFormat::new(DistanceUnit::Km, Some("C"), 2);
+        assert_eq!(plain.num(12345.678, 1), "12345.7");
+        assert_eq!(plain.ms_opt(Some(4.56789)), "4.57");
+        assert_eq!(plain.list_separator(), ',');
+
+        let us = ReportFormat::new(DistanceUnit::Mi, Some("en_US.UTF-8"), 2);
+        assert_eq!(us.dist(1609.344, 1), "1,000.0");
+        assert_eq!(us.dist_opt(Some(160934.4), 0), "100,000");
+        assert_eq!(us.unit.label(), "mi");
+
+        let de = ReportFormat::new(DistanceUnit::Km, Some("de-DE"), 1);
+        assert_eq!(de.num(-1234567.25, 2), "-1.234.567,25");
+        assert_eq!(de.signed(5.5, 1), "+5,5");
+        assert_eq!(de.ms(0.04), "0,0");
+        assert_eq!(de.opt(Some(f64::NAN), 1), "-");
+        assert_eq!(de.list_separator(), ';');
+
+        let fr = ReportFormat::new(DistanceUnit::Km, Some("fr_FR"), 2);
+        assert_eq!(fr.num(1234.5, 1), "1\u{a0}234,5");
+    }
+}
@@ -0,0 +1,159 @@
+use crate::units::ReportFormat;
+use crate::{print_estimate, EndpointStats, Estimate};
+use lattice_core::haversine_km;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Path id of records without an `@path` suffix.
+const DEFAULT_PATH: &str = "default";
+
+/// One probe path's own estimate, for captures probed over several
+/// upstreams (`probePaths`, `autoProbePaths`).
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct UpstreamEstimate {
+    pub path: String,
+    /// Endpoints probed over this path.
+    pub endpoints: usize,
+    pub estimate: Option<Estimate>,
+    /// Distance to the estimate over every path together.
+    pub separation_km: Option<f64>,
+}
+
+/// Fits each probe path's endpoints on their own with `estimate`. Empty
+/// unless the stats cover at least two paths. Paths that leave the same
+/// network agree up to the fit band; one that lands far from the rest goes
+/// out somewhere else.
+pub(crate) fn compare(
+    stats: &HashMap<String, EndpointStats>,
+    combined: Option<&Estimate>,
+    estimate: impl Fn(&HashMap<String, EndpointStats>) -> Option<Estimate>,
+) -> Vec<UpstreamEstimate> {
+    let mut by_path: BTreeMap<&str, HashMap<String, EndpointStats>> = BTreeMap::new();
+    for (id, st) in stats {
+        let path = id.split_once('@').map_or(DEFAULT_PATH, |(_, path)| path);
+        by_path
+            .entry(path)
+            .or_default()
+            .insert(id.clone(), st.clone());
+    }
+    if by_path.len() < 2 {
+        return Vec::new();
+    }
+    by_path
+        .into_iter()
+        .map(|(path, stats)| {
+            let est = estimate(&stats);
+            UpstreamEstimate {
+                path: path.to_string(),
+                endpoints: stats.len(),
+                separation_km: est
+                    .as_ref()
+                    .zip(combined)
+                    .map(|(e, c)| haversine_km(e.lat, e.lon, c.lat, c.lon)),
+                estimate: est,
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn print_upstreams(label: &str, upstreams: &[UpstreamEstimate], fmt: &ReportFormat) {
+    if upstreams.is_empty() {
+        return;
+    }
+    println!("\n{label} estimate per upstream (probe path):");
+    for u in upstreams {
+        match &u.estimate {
+            Some(est) => {
+                println!(
+                    "{} ({} endpoints, {}{} from the combined estimate):",
+                    u.path,
+                    u.endpoints,
+                    fmt.dist_opt(u.separation_km, 1),
+                    fmt.unit.label()
+                );
+                print_estimate(est, fmt);
+            }
+            None => println!(
+                "{} ({} endpoints): insufficient endpoint data",
+                u.path, u.endpoints
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::*;
+    use crate::testutil::*;
+    use crate::{build_stats, endpoints_by_id, estimate_location, DistanceModel, Weighting};
+    use lattice_core::BurstRecord;
+
+    #[test]
+    fn upstreams_are_estimated_per_probe_path() {
+        let anchors = [
+            ("fra", 50.11, 8.68),
+            ("lon", 51.51, -0.13),
+            ("par", 48.86, 2.35),
+            ("mad", 40.42, -3.70),
+        ];
+        let cfg = sample_config(
+            anchors
+                .iter()
+                .map(|&(id, lat, lon)| endpoint(id, lat, lon))
+                .collect(),
+        );
+        let endpoints = endpoints_by_id(&cfg.endpoints);
+        // Fiber leaves next to Frankfurt, LTE through Madrid.
+        let mut records = Vec::new();
+        for (path, at) in [("fiber", (50.0, 8.5)), ("lte", (40.5, -3.6))] {
+            for &(id, lat, lon) in &anchors {
+                let rtt = RTT_FACTOR * haversine_km(at.0, at.1, lat, lon) / DEFAULT_SPEED_KM_S
+                    * MS_PER_SEC;
+                records.push(record(&format!("{id}@{path}"), vec![rtt + 0.5, rtt + 1.0]));
+            }
+        }
+        let estimate = |stats: &HashMap<String, EndpointStats>| {
+            estimate_location(
+                stats,
+                &endpoints,
+                &TEST_MODEL,
+                TEST_GRID_DEG,
+                TEST_REFINE_DEG,
+                DEFAULT_BAND_FACTOR,
+                DEFAULT_BAND_WINDOW_DEG,
+                0.0,
+                None,
+                Weighting::default(),
+                DistanceModel::Sphere,
+            )
+        };
+        let stats = build_stats(&records, None);
+        let combined = estimate(&stats);
+        let upstreams = compare(&stats, combined.as_ref(), estimate);
+        let paths: Vec<&str> = upstreams.iter().map(|u| u.path.as_str()).collect();
+        assert_eq!(paths, vec!["fiber", "lte"]);
+        let at = |path: &str| {
+            let est = upstreams
+                .iter()
+                .find(|u| u.path == path)
+                .unwrap()
+                .estimate
+                .as_ref()
+                .unwrap();
+            (est.lat, est.lon)
+        };
+        assert!(haversine_km(50.0, 8.5, at("fiber").0, at("fiber").1) < 500.0);
+        assert!(haversine_km(40.5, -3.6, at("lte").0, at("lte").1) < 500.0);
+        assert!(upstreams
+            .iter()
+            .all(|u| u.endpoints == 4 && u.separation_km.is_some()));
+
+        let single: Vec<BurstRecord> = records
+            .into_iter()
+            .filter(|r| r.endpoint_id.ends_with("@fiber"))
+            .collect();
+        assert!(compare(&build_stats(&single, None), None, estimate).is_empty());
+    }
+}
@@ -0,0 +1,664 @@
+use crate::cli::{AnchorAddArgs, AnchorVerifyArgs, AnchorsAction};
+use crate::lint::{LAT_MAX, LON_MAX};
+use crate::transport::{describe_transports, Prober};
+use crate::{is_loopback_host, os, write_atomic};
+use lattice_core::{
+    build_packet, find_cloud_region, interpolate_env, ns_to_ms, Endpoint, Secret, SecretHex,
+    Transport, MIN_SECRET_BYTES,
+};
+use rand::Rng;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The port `lattice reflect` deploys reflectors on.
+pub const DEFAULT_ANCHOR_PORT: u16 = 9000;
+pub const DEFAULT_VERIFY_TIMEOUT_MS: u64 = 1000;
+
+pub fn run(action: &AnchorsAction) -> io::Result<()> {
+    match action {
+        AnchorsAction::List { registry } => list(registry),
+        AnchorsAction::Add(args) => add(args),
+        AnchorsAction::Remove {
+            registry,
+            id,
+            configs,
+        } => remove(registry, id, configs),
+        AnchorsAction::Verify(args) => verify(args),
+    }
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg.into())
+}
+
+fn read_doc(path: &Path) -> io::Result<Value> {
+    let data = fs::read(path)?;
+    let doc: Value = serde_json::from_slice(&data).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })?;
+    if !doc.is_object() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not a JSON object", path.display()),
+        ));
+    }
+    Ok(doc)
+}
+
+fn endpoints_mut<'a>(doc: &'a mut Value, path: &Path) -> io::Result<&'a mut Vec<Value>> {
+    let obj = doc.as_object_mut().expect("read_doc checks for an object");
+    obj.entry("endpoints")
+        .or_insert_with(|| Value::Array(Vec::new()))
+        .as_array_mut()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: endpoints is not an array", path.display()),
+            )
+        })
+}
+
+/// The registry's entries as the client reads them.
+fn parse_anchors(doc: &Value, path: &Path) -> io::Result<Vec<Endpoint>> {
+    let endpoints = doc
+        .get("endpoints")
+        .cloned()
+        .unwrap_or(Value::Array(Vec::new()));
+    serde_json::from_value(endpoints).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: endpoints: {}", path.display(), e),
+        )
+    })
+}
+
+fn entry_id(entry: &Value) -> Option<&str> {
+    entry.get("id").and_then(Value::as_str)
+}
+
+/// Problems that make the registry wrong, and ones that only make an anchor
+/// less useful.
+#[derive(Default)]
+struct Findings {
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+/// Unique ids and host:port pairs, and coordinates on the globe. Loopback
+/// anchors are test targets and skip the coordinate checks, as in the lint.
+fn check_anchors(anchors: &[Endpoint]) -> Findings {
+    let mut found = Findings::default();
+    let mut ids: HashMap<&str, usize> = HashMap::new();
+    let mut hosts: HashMap<(String, u16), &str> = HashMap::new();
+    for ep in anchors {
+        if ep.id.trim().is_empty() {
+            found
+                .errors
+                .push(format!("anchor at {}:{} has no id", ep.host, ep.port));
+        }
+        *ids.entry(ep.id.as_str()).or_default() += 1;
+        if ep.host.trim().is_empty() {
+            found.errors.push(format!("{}: empty host", ep.id));
+        }
+        if ep.port == 0 {
+            found.errors.push(format!("{}: port 0", ep.id));
+        }
+        if let Some(other) = hosts.insert((ep.host.to_ascii_lowercase(), ep.port), &ep.id) {
+            found.errors.push(format!(
+                "{} and {} both point at {}:{}; their bursts would measure one reflector twice",
+                other, ep.id, ep.host, ep.port
+            ));
+        }
+        if is_loopback_host(&ep.host) {
+            continue;
+        }
+        match (ep.lat, ep.lon) {
+            (Some(lat), Some(lon))
+                if !(lat.is_finite()
+                    && lon.is_finite()
+                    && lat.abs() <= LAT_MAX
+                    && lon.abs() <= LON_MAX) =>
+            {
+                found
+                    .errors
+                    .push(format!("{}: lat/lon {lat}, {lon} is off the globe", ep.id))
+            }
+            (Some(lat), Some(lon)) if lat == 0.0 && lon == 0.0 => found.warnings.push(format!(
+                "{}: lat/lon 0, 0 is in the Gulf of Guinea; a placeholder?",
+                ep.id
+            )),
+            (Some(_), Some(_)) => {}
+            (None, None) => found.warnings.push(format!(
+                "{}: no lat/lon; it gets no distance bound and no say in estimates",
+                ep.id
+            )),
+            _ => found
+                .errors
+                .push(format!("{}: lat and lon must be set together", ep.id)),
+        }
+    }
+    let mut dupes: Vec<(&str, usize)> = ids.into_iter().filter(|(_, n)| *n > 1).collect();
+    dupes.sort();
+    for (id, n) in dupes {
+        found.errors.push(format!("id '{id}' is used {n} times"));
+    }
+    found
+}
+
+/// The registry's `secretHex`, or else the first config's, with `${NAME}`
+/// expanded as at startup.
+fn probe_secret(registry: &Value, configs: &[PathBuf]) -> io::Result<Secret> {
+    let mut docs = vec![registry.clone()];
+    for path in configs {
+        docs.push(read_doc(path)?);
+    }
+    for doc in docs {
+        let Some(hex) = doc.get("secretHex").and_then(Value::as_str) else {
+            continue;
+        };
+        let mut hex = Value::String(hex.to_string());
+        interpolate_env(&mut hex, "secretHex", &|name| std::env::var(name).ok())
+            .map_err(invalid)?;
+        let secret = SecretHex::from(hex.as_str().unwrap_or_default())
+            .decode()
+            .map_err(invalid)?;
+        if secret.len() < MIN_SECRET_BYTES {
+            return Err(invalid(format!(
+                "secretHex must be at least {MIN_SECRET_BYTES} bytes"
+            )));
+        }
+        return Ok(secret);
+    }
+    Err(invalid(
+        "no secretHex in the registry or a --config to sign probes with (or use --no-verify / --offline)",
+    ))
+}
+
+/// One signed probe over each of the anchor's transports until one answers.
+fn probe_anchor(
+    ep: &Endpoint,
+    secret: &Secret,
+    timeout: Duration,
+    seq: u32,
+) -> Result<(Transport, u64), String> {
+    let mut misses = Vec::new();
+    for &transport in ep.transport_order() {
+        let send_realtime_ns = os::realtime_now_ns();
+        let send_mono_ns = os::monotonic_now_ns();
+        let msg = build_packet(
+            seq,
+            send_realtime_ns,
+            rand::thread_rng().gen(),
+            secret.as_bytes(),
+        );
+        let mut prober = match Prober::open(transport, ep, &ep.host, None, 0, timeout) {
+            Ok(p) => p,
+            Err(err) => {
+                misses.push(format!("{}: {}", transport.as_str(), err));
+                continue;
+            }
+        };
+        match prober.send_and_receive_rtt(&msg, send_realtime_ns, send_mono_ns, timeout) {
+            Ok(Some(rtt)) => return Ok((transport, rtt)),
+            Ok(None) => misses.push(format!(
+                "{}: no reply within {}ms (reflector down, firewall, or secret mismatch)",
+                transport.as_str(),
+                timeout.as_millis()
+            )),
+            Err(err) => misses.push(format!("{}: {}", transport.as_str(), err)),
+        }
+    }
+    Err(misses.join("; "))
+}
+
+fn may_probe(ep: &Endpoint, own_targets: bool) -> bool {
+    ep.consented || own_targets || is_loopback_host(&ep.host)
+}
+
+fn fmt_coords(ep: &Endpoint) -> String {
+    match (ep.lat, ep.lon) {
+        (Some(lat), Some(lon)) => format!("{lat:.4},{lon:.4}"),
+        _ => "-".to_string(),
+    }
+}
+
+fn list(registry: &Path) -> io::Result<()> {
+    let anchors = parse_anchors(&read_doc(registry)?, registry)?;
+    for ep in &anchors {
+        println!(
+            "{} {}:{}/{} {} {}{}",
+            ep.id,
+            ep.host,
+            ep.port,
+            describe_transports(ep),
+            fmt_coords(ep),
+            ep.region_hint.as_deref().unwrap_or("-"),
+            if ep.consented { " consented" } else { "" }
+        );
+    }
+    eprintln!("{} anchor(s) in {}", anchors.len(), registry.display());
+    Ok(())
+}
+
+/// Renders every changed file before writing any, so a bad config leaves
+/// the registry untouched too.
+fn write_all(staged: &[(PathBuf, Value)]) -> io::Result<()> {
+    let mut rendered = Vec::with_capacity(staged.len());
+    for (path, doc) in staged {
+        rendered.push((
+            path,
+            serde_json::to_string_pretty(doc).map_err(io::Error::other)?,
+        ));
+    }
+    for (path, text) in rendered {
+        write_atomic(path, text.as_bytes())?;
+        eprintln!("updated {}", path.display());
+    }
+    Ok(())
+}
+
+fn add(args: &AnchorAddArgs) -> io::Result<()> {
+    let mut registry = if args.registry.exists() {
+        read_doc(&args.registry)?
+    } else {
+        serde_json::json!({ "endpoints": [] })
+    };
+
+    let mut entry = Map::new();
+    entry.insert("id".to_string(), args.id.clone().into());
+    entry.insert("host".to_string(), args.host.clone().into());
+    entry.insert("port".to_string(), args.port.into());
+    if let Some(hint) = &args.region_hint {
+        entry.insert("regionHint".to_string(), hint.clone().into());
+    }
+    let coords = args.lat.zip(args.lon).or_else(|| {
+        let region = [Some(&args.host), Some(&args.id), args.region_hint.as_ref()]
+            .into_iter()
+            .flatten()
+            .find_map(|text| find_cloud_region(text))?;
+        eprintln!(
+            "[ok] {} -> {} {} ({:.4}, {:.4})",
+            args.id, region.provider, region.code, region.lat, region.lon
+        );
+        Some((region.lat, region.lon))
+    });
+    if let Some((lat, lon)) = coords {
+        entry.insert("lat".to_string(), lat.into());
+        entry.insert("lon".to_string(), lon.into());
+    }
+    if args.consented {
+        entry.insert("consented".to_string(), true.into());
+    }
+    if !args.transports.is_empty() {
+        let transports = serde_json::to_value(&args.transports).map_err(io::Error::other)?;
+        entry.insert("transports".to_string(), transports);
+    }
+    if let Some(port) = args.https_port {
+        entry.insert("httpsPort".to_string(), port.into());
+    }
+    let entry = Value::Object(entry);
+
+    let mut anchors = parse_anchors(&registry, &args.registry)?;
+    if anchors.iter().any(|ep| ep.id == args.id) {
+        return Err(invalid(format!(
+            "{} already has an anchor '{}'; remove it first",
+            args.registry.display(),
+            args.id
+        )));
+    }
+    let new: Endpoint = serde_json::from_value(entry.clone()).map_err(io::Error::other)?;
+    anchors.push(new.clone());
+    let found = check_anchors(&anchors);
+    for warning in &found.warnings {
+        eprintln!("[!]  {warning}");
+    }
+    if !found.errors.is_empty() {
+        for error in &found.errors {
+            eprintln!("[!!] {error}");
+        }
+        return Err(invalid(format!("not adding {}", args.id)));
+    }
+
+    if args.no_verify {
+        eprintln!("[--] {} not probed (--no-verify)", args.id);
+    } else if !may_probe(&new, args.own_targets) {
+        eprintln!(
+            "[--] {} not probed (not consented; pass --consented or --own-targets)",
+            args.id
+        );
+    } else {
+        let secret = probe_secret(&registry, &args.configs)?;
+        let timeout = Duration::from_millis(args.timeout_ms);
+        match probe_anchor(&new, &secret, timeout, 0) {
+            Ok((transport, rtt)) => eprintln!(
+                "[ok] {} {}:{} replied over {} in {:.2}ms",
+                args.id,
+                args.host,
+                args.port,
+                transport.as_str(),
+                ns_to_ms(rtt)
+            ),
+            Err(err) => {
+                return Err(io::Error::other(format!(
+                    "{} {}:{} unreachable ({err}); fix it or pass --no-verify",
+                    args.id, args.host, args.port
+                )))
+            }
+        }
+    }
+
+    endpoints_mut(&mut registry, &args.registry)?.push(entry.clone());
+    let mut staged = vec![(args.registry.clone(), registry)];
+    for path in &args.configs {
+        let mut doc = read_doc(path)?;
+        let endpoints = endpoints_mut(&mut doc, path)?;
+        match endpoints
+            .iter_mut()
+            .find(|e| entry_id(e) == Some(args.id.as_str()))
+        {
+            Some(existing) => *existing = entry.clone(),
+            None => endpoints.push(entry.clone()),
+        }
+        staged.push((path.clone(), doc));
+    }
+    write_all(&staged)?;
+    println!("added {} ({} anchor(s))", args.id, anchors.len());
+    Ok(())
+}
+
+fn remove(registry: &Path, id: &str, configs: &[PathBuf]) -> io::Result<()> {
+    let mut doc = read_doc(registry)?;
+    let endpoints = endpoints_mut(&mut doc, registry)?;
+    let before = endpoints.len();
+    endpoints.retain(|e| entry_id(e) != Some(id));
+    if endpoints.len() == before {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} has no anchor '{}'", registry.display(), id),
+        ));
+    }
+    let left = endpoints.len();
+    let mut staged = vec![(registry.to_path_buf(), doc)];
+    for path in configs {
+        let mut doc = read_doc(path)?;
+        let endpoints = endpoints_mut(&mut doc, path)?;
+        let before = endpoints.len();
+        endpoints.retain(|e| entry_id(e) != Some(id));
+        if endpoints.len() == before {
+            eprintln!("[--] {} has no '{}'", path.display(), id);
+            continue;
+        }
+        staged.push((path.clone(), doc));
+    }
+    write_all(&staged)?;
+    println!("removed {id} ({left} anchor(s) left)");
+    Ok(())
+}
+
+/// Fields whose drift changes what a config measures or how it is read.
+fn same_anchor(a: &Endpoint, b: &Endpoint) -> bool {
+    a.host == b.host
+        && a.port == b.port
+        && a.port_for(Transport::Https) == b.port_for(Transport::Https)
+        && a.lat == b.lat
+        && a.lon == b.lon
+        && a.transport_order() == b.transport_order()
+}
+
+fn verify(args: &AnchorVerifyArgs) -> io::Result<()> {
+    let registry = read_doc(&args.registry)?;
+    let anchors = parse_anchors(&registry, &args.registry)?;
+    let Findings {
+        mut errors,
+        mut warnings,
+    } = check_anchors(&anchors);
+
+    for path in &args.configs {
+        let copies: HashMap<String, Endpoint> = parse_anchors(&read_doc(path)?, path)?
+            .into_iter()
+            .map(|ep| (ep.id.clone(), ep))
+            .collect();
+        for ep in &anchors {
+            match copies.get(&ep.id) {
+                None => warnings.push(format!("{}: missing from {}", ep.id, path.display())),
+                Some(copy) if !same_anchor(ep, copy) => warnings.push(format!(
+                    "{}: differs in {} (host, ports, lat/lon, or transports)",
+                    ep.id,
+                    path.display()
+                )),
+                Some(_) => {}
+            }
+        }
+    }
+
+    let probing = if args.offline {
+        None
+    } else {
+        Some((
+            probe_secret(&registry, &args.configs)?,
+            Duration::from_millis(args.timeout_ms),
+        ))
+    };
+    for (seq, ep) in anchors.iter().enumerate() {
+        let Some((secret, timeout)) = &probing else {
+            break;
+        };
+        if !may_probe(ep, args.own_targets) {
+            println!("[--] {} skipped (not consented)", ep.id);
+            continue;
+        }
+        match probe_anchor(ep, secret, *timeout, seq as u32) {
+            Ok((transport, rtt)) => println!(
+                "[ok] {} {}:{} replied over {} in {:.2}ms",
+                ep.id,
+                ep.host,
+                ep.port,
+                transport.as_str(),
+                ns_to_ms(rtt)
+            ),
+            Err(err) => errors.push(format!(
+                "{} {}:{} unreachable: {}",
+                ep.id, ep.host, ep.port, err
+            )),
+        }
+    }
+
+    for warning in &warnings {
+        println!("[!]  {warning}");
+    }
+    for error in &errors {
+        println!("[!!] {error}");
+    }
+    println!(
+        "\n{} anchor(s), {} failure(s), {} warning(s)",
+        anchors.len(),
+        errors.len(),
+        warnings.len()
+    );
+    if !errors.is_empty() {
+        return Err(io::Error::other("registry check found problems"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::temp_dir;
+    use clap::Parser;
+    use serde_json::json;
+
+    #[derive(Parser)]
+    struct Anchors {
+        #[command(subcommand)]
+        action: AnchorsAction,
+    }
+
+    fn anchors(args: &[&str]) -> io::Result<()> {
+        run(&Anchors::parse_from(std::iter::once("anchors").chain(args.iter().copied())).action)
+    }
+
+    fn anchor(entry: Value) -> Endpoint {
+        serde_json::from_value(entry).unwrap()
+    }
+
+    fn ids(path: &Path) -> Vec<String> {
+        let doc = read_doc(path).unwrap();
+        parse_anchors(&doc, path)
+            .unwrap()
+            .into_iter()
+            .map(|ep| ep.id)
+            .collect()
+    }
+
+    #[test]
+    fn check_anchors_finds_clashes_and_bad_coordinates() {
+        let found = check_anchors(&[
+            anchor(
+                json!({ "id": "fra", "host": "fra.example", "port": 9000, "lat": 50.1, "lon": 8.7 }),
+            ),
+            anchor(
+                json!({ "id": "fra", "host": "FRA.example", "port": 9000, "lat": 50.1, "lon": 8.7 }),
+            ),
+            anchor(
+                json!({ "id": "off", "host": "off.example", "port": 9000, "lat": 91.0, "lon": 0.0 }),
+            ),
+            anchor(json!({ "id": "half", "host": "half.example", "port": 0, "lat": 1.0 })),
+            anchor(
+                json!({ "id": "null", "host": "null.example", "port": 9000, "lat": 0.0, "lon": 0.0 }),
+            ),
+            anchor(json!({ "id": "bare", "host": "bare.example", "port": 9000 })),
+            anchor(json!({ "id": "local", "host": "127.0.0.1", "port": 9000 })),
+        ]);
+        assert_eq!(
+            found.errors,
+            [
+                "fra and fra both point at FRA.example:9000; their bursts would measure one reflector twice",
+                "off: lat/lon 91, 0 is off the globe",
+                "half: port 0",
+                "half: lat and lon must be set together",
+                "id 'fra' is used 2 times",
+            ]
+        );
+        assert_eq!(
+            found.warnings,
+            [
+                "null: lat/lon 0, 0 is in the Gulf of Guinea; a placeholder?",
+                "bare: no lat/lon; it gets no distance bound and no say in estimates",
+            ]
+        );
+    }
+
+    #[test]
+    fn add_and_remove_keep_registry_and_configs_in_step() {
+        let dir = temp_dir("anchors-add-remove");
+        let registry = dir.join("anchors.json");
+        let config = dir.join("config.json");
+        let (registry_arg, config_arg) = (registry.to_str().unwrap(), config.to_str().unwrap());
+        fs::write(
+            &config,
+            json!({ "secretHex": "00", "endpoints": [] }).to_string(),
+        )
+        .unwrap();
+
+        anchors(&[
+            "add",
+            registry_arg,
+            "--id",
+            "fra",
+            "--host",
+            "fra.example",
+            "--lat",
+            "50.1",
+            "--lon",
+            "8.7",
+            "--consented",
+            "--config",
+            config_arg,
+            "--no-verify",
+        ])
+        .unwrap();
+        // Coordinates come from the region code when left out.
+        anchors(&[
+            "add",
+            registry_arg,
+            "--id",
+            "us-east-1",
+            "--host",
+            "b.example",
+            "--no-verify",
+        ])
+        .unwrap();
+        assert_eq!(ids(&registry), ["fra", "us-east-1"]);
+        assert_eq!(ids(&config), ["fra"]);
+        let doc = read_doc(&registry).unwrap();
+        let added = &parse_anchors(&doc, &registry).unwrap()[1];
+        assert!(added.lat.is_some() && added.lon.is_some());
+        assert!(!added.consented);
+
+        let before = fs::read_to_string(&registry).unwrap();
+        let err = anchors(&[
+            "add",
+            registry_arg,
+            "--id",
+            "fra",
+            "--host",
+            "c.example",
+            "--no-verify",
+        ])
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("already has an anchor 'fra'"),
+            "{err}"
+        );
+        let err = anchors(&[
+            "add",
+            registry_arg,
+            "--id",
+            "dup",
+            "--host",
+            "FRA.example",
+            "--no-verify",
+        ])
+        .unwrap_err();
+        assert_eq!(err.to_string(), "not adding dup");
+        assert_eq!(fs::read_to_string(&registry).unwrap(), before);
+
+        anchors(&["verify", registry_arg, "--config", config_arg, "--offline"]).unwrap();
+        anchors(&["remove", registry_arg, "fra", "--config", config_arg]).unwrap();
+        assert_eq!(ids(&registry), ["us-east-1"]);
+        assert!(ids(&config).is_empty());
+        let err = anchors(&["remove", registry_arg, "fra"]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn probes_are_signed_with_the_registry_or_config_secret() {
+        let dir = temp_dir("anchors-secret");
+        let config = dir.join("config.json");
+        let secret = "ab".repeat(MIN_SECRET_BYTES);
+        fs::write(&config, json!({ "secretHex": secret }).to_string()).unwrap();
+
+        let from_config = probe_secret(&json!({}), std::slice::from_ref(&config)).unwrap();
+        assert_eq!(from_config.len(), MIN_SECRET_BYTES);
+        let registry = json!({ "secretHex": "cd".repeat(MIN_SECRET_BYTES + 1) });
+        assert_eq!(
+            probe_secret(&registry, &[config]).unwrap().len(),
+            MIN_SECRET_BYTES + 1
+        );
+
+        let short = json!({ "secretHex": "ab" });
+        assert_eq!(
+            probe_secret(&short, &[]).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+        assert!(probe_secret(&json!({}), &[]).is_err());
+    }
+}
@@ -0,0 +1,850 @@
+//! The localhost HTTP API (`localApi` in the config): lets other software on
+//! the machine, such as a browser extension or an EDR agent, ask for a burst
+//! to a configured endpoint and get its summary back as JSON.
+//!
+//! Routes, all under `/v1/`:
+//! - `GET /v1/info`: the API version and the endpoints that can be asked for.
+//! - `POST /v1/burst` with `{"endpoint": "<id>"}`: runs the endpoint's next
+//!   burst now and answers with its summary once it is written.
+//!
+//! Every request but a CORS preflight carries `Authorization: Bearer <token>`
+//! with one of `localApi.tokens`, and each token's bursts are rate-limited.
+
+use crate::status::Status;
+use lattice_core::{
+    constant_time_eq, hex_to_bytes, ApiRateLimit, BurstRecord, LocalApi, Secret, Transport,
+    MIN_SECRET_BYTES,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const API_VERSION: u32 = 1;
+/// The whole request, head and body, must be in by then, however slowly
+/// its bytes come.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// Connections answered at once. Past this they are turned away before
+/// anything is read, as the reflector caps its TCP connections.
+const MAX_CONNECTIONS: usize = 32;
+/// Request line plus headers.
+const MAX_HEAD_BYTES: usize = 8 * 1024;
+const MAX_BODY_BYTES: usize = 4 * 1024;
+/// Slack on top of one interval while a burst request waits: the worker may
+/// be mid-burst when it is asked.
+const REPLY_GRACE: Duration = Duration::from_secs(10);
+/// Origins a browser may call from. Web pages are refused, so a site cannot
+/// make the visitor's machine probe on its behalf.
+const EXTENSION_ORIGINS: [&str; 3] = [
+    "chrome-extension://",
+    "moz-extension://",
+    "safari-web-extension://",
+];
+const SECS_PER_MIN: f64 = 60.0;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BurstRequest {
+    endpoint: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Info {
+    api_version: u32,
+    client_version: &'static str,
+    endpoints: Vec<String>,
+    interval_seconds: u64,
+}
+
+/// What `POST /v1/burst` answers with: the burst record without its raw
+/// samples and interface inventory.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BurstSummary {
+    api_version: u32,
+    endpoint_id: String,
+    probe_path: String,
+    session_id: String,
+    burst_id: String,
+    ts_unix_ms: i64,
+    transport: Transport,
+    samples: usize,
+    min_ms: Option<f64>,
+    p05_ms: Option<f64>,
+    median_ms: Option<f64>,
+    iface: String,
+    iface_is_tunnel: bool,
+    utun_active: bool,
+    relay: Option<String>,
+    notes: Vec<String>,
+}
+
+impl BurstSummary {
+    fn new(rec: &BurstRecord) -> Self {
+        BurstSummary {
+            api_version: API_VERSION,
+            endpoint_id: rec.endpoint_id.clone(),
+            probe_path: rec.probe_path.clone(),
+            session_id: rec.session_id.clone(),
+            burst_id: rec.burst_id.clone(),
+            ts_unix_ms: rec.ts_unix_ms,
+            transport: rec.transport,
+            samples: rec
+                .histogram
+                .as_ref()
+                .map_or(rec.samples_ms.len(), |h| h.count()),
+            min_ms: rec.min_ms,
+            p05_ms: rec.p05_ms,
+            median_ms: rec.median_ms,
+            iface: rec.iface.clone(),
+            iface_is_tunnel: rec.iface_is_tunnel,
+            utun_active: rec.utun_active,
+            relay: rec.relay.clone(),
+            notes: rec.notes.clone(),
+        }
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    host: Option<String>,
+    origin: Option<String>,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: serde_json::Value,
+}
+
+impl Response {
+    fn ok<T: Serialize>(value: &T) -> Self {
+        match serde_json::to_value(value) {
+            Ok(body) => Response {
+                status: 200,
+                headers: Vec::new(),
+                body,
+            },
+            Err(err) => Response::error(500, err.to_string()),
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: serde_json::json!({ "apiVersion": API_VERSION, "error": message.into() }),
+        }
+    }
+
+    fn with_header(mut self, name: &'static str, value: String) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+}
+
+/// The configured callers and their rate limits.
+struct Callers {
+    tokens: Vec<(String, Secret)>,
+    limiter: Mutex<Limiter>,
+}
+
+impl Callers {
+    /// The name of the caller whose token `authorization` carries.
+    fn authenticate(&self, authorization: Option<&str>) -> Option<&str> {
+        let presented = authorization?.strip_prefix("Bearer ")?.trim();
+        let presented = hex_to_bytes(presented).ok()?;
+        self.tokens
+            .iter()
+            .find(|(_, token)| constant_time_eq(token.as_bytes(), &presented))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/// Token buckets by caller name, as the reflector keeps them by source
+/// address.
+pub(crate) struct Limiter {
+    rate: ApiRateLimit,
+    buckets: HashMap<String, Bucket>,
+}
+
+impl Limiter {
+    pub(crate) fn new(rate: ApiRateLimit) -> Self {
+        Limiter {
+            rate,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Takes a token from `caller`'s bucket, or says how long until one is
+    /// back.
+    pub(crate) fn take(&mut self, caller: &str, now: Instant) -> Result<(), Duration> {
+        let rate = self.rate;
+        let bucket = self.buckets.entry(caller.to_string()).or_insert(Bucket {
+            tokens: rate.burst,
+            last: now,
+        });
+        let per_sec = rate.per_minute / SECS_PER_MIN;
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.last).as_secs_f64() * per_sec)
+            .min(rate.burst);
+        bucket.last = now;
+        if bucket.tokens < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec));
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+/// Checks `localApi` from the config: a loopback `listen`, at least one
+/// token, and a usable rate limit.
+pub fn validate(api: &LocalApi) -> io::Result<()> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    listen_addr(&api.listen)?;
+    if api.tokens.is_empty() {
+        return Err(invalid(
+            "localApi needs at least one entry in tokens".to_string(),
+        ));
+    }
+    let mut names = HashSet::new();
+    for token in &api.tokens {
+        if token.name.is_empty() || !names.insert(token.name.as_str()) {
+            return Err(invalid(
+                "localApi.tokens need distinct, non-empty names".to_string(),
+            ));
+        }
+        let bytes = token
+            .token_hex
+            .decode()
+            .map_err(|e| invalid(format!("localApi.tokens {}: {e}", token.name)))?;
+        if bytes.len() < MIN_SECRET_BYTES {
+            return Err(invalid(format!(
+                "localApi.tokens {}: tokenHex must be at least {MIN_SECRET_BYTES} bytes",
+                token.name
+            )));
+        }
+    }
+    let ApiRateLimit { burst, per_minute } = api.rate_limit;
+    if !(burst.is_finite() && burst >= 1.0 && per_minute.is_finite() && per_minute > 0.0) {
+        return Err(invalid(
+            "localApi.rateLimit needs burst >= 1 and perMinute > 0".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn listen_addr(listen: &str) -> io::Result<SocketAddr> {
+    let addr: SocketAddr = listen.parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("localApi.listen must be an ip:port, got {listen}"),
+        )
+    })?;
+    if !addr.ip().is_loopback() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("localApi.listen must be a loopback address, got {listen}"),
+        ));
+    }
+    Ok(addr)
+}
+
+/// Binds the API and answers each connection on its own thread, since a
+/// burst request holds its connection until the burst is written. At most
+/// `MAX_CONNECTIONS` are answered at once; the rest get a 503.
+pub fn serve(api: &LocalApi, status: Arc<Status>) -> io::Result<SocketAddr> {
+    let mut tokens = Vec::new();
+    for token in &api.tokens {
+        let secret = token
+            .token_hex
+            .decode()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        tokens.push((token.name.clone(), secret));
+    }
+    let callers = Arc::new(Callers {
+        tokens,
+        limiter: Mutex::new(Limiter::new(api.rate_limit)),
+    });
+    let listener = TcpListener::bind(listen_addr(&api.listen)?)?;
+    let addr = listener.local_addr()?;
+    let open = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let Some(slot) = Slot::take(&open) else {
+                let busy = Response::error(503, "too many connections");
+                let _ = stream.set_write_timeout(Some(READ_TIMEOUT));
+                let _ = write_response(&mut stream, &busy, None);
+                continue;
+            };
+            let status = Arc::clone(&status);
+            let callers = Arc::clone(&callers);
+            thread::spawn(move || {
+                answer(stream, &status, &callers);
+                drop(slot);
+            });
+        }
+    });
+    Ok(addr)
+}
+
+/// One of the `MAX_CONNECTIONS`, given back on drop.
+struct Slot(Arc<AtomicUsize>);
+
+impl Slot {
+    fn take(open: &Arc<AtomicUsize>) -> Option<Slot> {
+        open.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+            (n < MAX_CONNECTIONS).then_some(n + 1)
+        })
+        .ok()?;
+        Some(Slot(Arc::clone(open)))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Reads a stream until a deadline: each read may wait only for what is
+/// left of it, so a request trickled in a byte at a time still ends there.
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("request not read within {}s", READ_TIMEOUT.as_secs()),
+            ));
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        Read::read(&mut self.stream, buf)
+    }
+}
+
+fn answer(mut stream: TcpStream, status: &Status, callers: &Callers) {
+    let (response, origin) = match read_request(&stream) {
+        Ok(request) => {
+            let origin = request.origin.clone();
+            (route(&request, status, callers), origin)
+        }
+        Err(response) => (response, None),
+    };
+    let _ = write_response(&mut stream, &response, origin.as_deref());
+}
+
+fn route(request: &Request, status: &Status, callers: &Callers) -> Response {
+    // A page that rebinds its own name to 127.0.0.1 still sends that name.
+    match request.host.as_deref() {
+        None => return Response::error(400, "missing Host header"),
+        Some(host) if !is_loopback_authority(host) => {
+            return Response::error(403, format!("host {host} is not loopback"))
+        }
+        Some(_) => {}
+    }
+    if let Some(origin) = &request.origin {
+        if !EXTENSION_ORIGINS
+            .iter()
+            .any(|scheme| origin.starts_with(scheme))
+        {
+            return Response::error(403, format!("origin {origin} is not allowed"));
+        }
+    }
+    // Browsers send preflights without credentials.
+    if request.method == "OPTIONS" {
+        return Response {
+            status: 204,
+            headers: Vec::new(),
+            body: serde_json::Value::Null,
+        };
+    }
+    let Some(caller) = callers.authenticate(request.authorization.as_deref()) else {
+        return Response::error(401, "missing or unknown bearer token")
+            .with_header("WWW-Authenticate", "Bearer".to_string());
+    };
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/v1/info") => {
+            let (endpoints, interval) = status.probed();
+            Response::ok(&Info {
+                api_version: API_VERSION,
+                client_version: env!("CARGO_PKG_VERSION"),
+                endpoints,
+                interval_seconds: interval.as_secs(),
+            })
+        }
+        ("POST", "/v1/burst") => {
+            // Only a burst that would run costs a token.
+            let (endpoint, interval) = match burst_endpoint(request, status) {
+                Ok(found) => found,
+                Err(response) => return response,
+            };
+            let taken = callers
+                .limiter
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take(caller, Instant::now());
+            match taken {
+                Ok(()) => burst(status, &endpoint, interval),
+                Err(retry) => {
+                    let secs = retry.as_secs_f64().ceil() as u64;
+                    Response::error(429, format!("{caller}: too many bursts, retry in {secs}s"))
+                        .with_header("Retry-After", secs.to_string())
+                }
+            }
+        }
+        (_, "/v1/info" | "/v1/burst") => {
+            Response::error(405, format!("{} not allowed here", request.method))
+        }
+        (_, path) => Response::error(404, format!("no route {path}")),
+    }
+}
+
+/// The endpoint a burst request names and the probing interval, or why
+/// it cannot have a burst now.
+fn burst_endpoint(request: &Request, status: &Status) -> Result<(String, Duration), Response> {
+    let burst: BurstRequest = serde_json::from_slice(&request.body)
+        .map_err(|err| Response::error(400, format!("bad request: {err}")))?;
+    if status.features_only() {
+        return Err(Response::error(
+            403,
+            "privacyMode is features: the client keeps no bursts to answer with",
+        ));
+    }
+    let (endpoints, interval) = status.probed();
+    if !endpoints.contains(&burst.endpoint) {
+        return Err(Response::error(
+            404,
+            format!("unknown endpoint {}", burst.endpoint),
+        ));
+    }
+    if status
+        .report()
+        .endpoints
+        .get(&burst.endpoint)
+        .is_some_and(|e| e.in_blackout)
+    {
+        return Err(Response::error(
+            409,
+            format!("{} is in a blackout window", burst.endpoint),
+        ));
+    }
+    Ok((burst.endpoint, interval))
+}
+
+fn burst(status: &Status, endpoint: &str, interval: Duration) -> Response {
+    let wait = interval + REPLY_GRACE;
+    let (reply, result) = mpsc::channel();
+    status.request_burst(endpoint, reply);
+    match result.recv_timeout(wait) {
+        Ok(rec) => Response::ok(&BurstSummary::new(&rec)),
+        Err(_) => Response::error(
+            504,
+            format!("{endpoint}: no burst within {}s", wait.as_secs()),
+        ),
+    }
+}
+
+fn read_request(stream: &TcpStream) -> Result<Request, Response> {
+    let mut reader = BufReader::new(Deadline {
+        stream,
+        deadline: Instant::now() + READ_TIMEOUT,
+    });
+    let mut head_bytes = 0;
+    let mut line = String::new();
+    let mut next_line = |line: &mut String| -> Result<(), Response> {
+        line.clear();
+        let n = reader
+            .by_ref()
+            .take((MAX_HEAD_BYTES - head_bytes) as u64)
+            .read_line(line)
+            .map_err(|e| Response::error(400, e.to_string()))?;
+        head_bytes += n;
+        if !line.ends_with('\n') {
+            return Err(if head_bytes >= MAX_HEAD_BYTES {
+                Response::error(413, "request head too large")
+            } else {
+                Response::error(400, "truncated request")
+            });
+        }
+        Ok(())
+    };
+    next_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(Response::error(400, "malformed request line"));
+    };
+    let mut request = Request {
+        method: method.to_string(),
+        path: target.split('?').next().unwrap_or_default().to_string(),
+        host: None,
+        origin: None,
+        authorization: None,
+        body: Vec::new(),
+    };
+    let mut content_length = 0;
+    loop {
+        next_line(&mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            return Err(Response::error(400, "malformed header"));
+        };
+        let value = value.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "host" => request.host = Some(value.to_string()),
+            "origin" => request.origin = Some(value.to_string()),
+            "authorization" => request.authorization = Some(value.to_string()),
+            "content-length" => {
+                content_length = value
+                    .parse()
+                    .map_err(|_| Response::error(400, "bad Content-Length"))?
+            }
+            _ => {}
+        }
+    }
+    if content_length > MAX_BODY_BYTES {
+        return Err(Response::error(
+            413,
+            format!("body over {MAX_BODY_BYTES} bytes"),
+        ));
+    }
+    request.body = vec![0; content_length];
+    reader
+        .read_exact(&mut request.body)
+        .map_err(|_| Response::error(400, "truncated body"))?;
+    Ok(request)
+}
+
+/// `localhost`, a loopback address, either with or without a port.
+fn is_loopback_authority(authority: &str) -> bool {
+    let host = match authority.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => authority
+            .rsplit_once(':')
+            .map_or(authority, |(host, _)| host),
+    };
+    host.eq_ignore_ascii_case("localhost")
+        || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+fn write_response(
+    stream: &mut TcpStream,
+    response: &Response,
+    origin: Option<&str>,
+) -> io::Result<()> {
+    let body = if response.body.is_null() {
+        String::new()
+    } else {
+        response.body.to_string()
+    };
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason(response.status),
+        body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    // Only allowed origins get this far without a 403.
+    if let Some(origin) = origin.filter(|_| response.status != 403) {
+        head.push_str(&format!(
+            "Access-Control-Allow-Origin: {origin}\r\nAccess-Control-Allow-Methods: GET, POST\r\nAccess-Control-Allow-Headers: Authorization, Content-Type\r\n"
+        ));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body.as_bytes())?;
+    stream.flush()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        429 => "Too Many Requests",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lattice_core::{Config, PrivacyMode, SecretHex};
+    use std::path::Path;
+
+    const EXTENSION_TOKEN: &str = "00112233445566778899aabbccddeeff";
+    const AGENT_TOKEN: &str = "ffeeddccbbaa99887766554433221100";
+
+    fn callers(rate: ApiRateLimit) -> Callers {
+        let token = |hex: &str| SecretHex::from(hex).decode().unwrap();
+        Callers {
+            tokens: vec![
+                ("extension".to_string(), token(EXTENSION_TOKEN)),
+                ("agent".to_string(), token(AGENT_TOKEN)),
+            ],
+            limiter: Mutex::new(Limiter::new(rate)),
+        }
+    }
+
+    fn config(privacy_mode: &str) -> Config {
+        serde_json::from_value(serde_json::json!({
+            "secretHex": EXTENSION_TOKEN,
+            "endpoints": [{"id": "nyc", "host": "127.0.0.1", "port": 9000, "consented": true}],
+            "samplesPerEndpoint": 10,
+            "spacingMs": 20,
+            "timeoutMs": 1000,
+            "intervalSeconds": 60,
+            "outputPath": "out.jsonl",
+            "claimedEgressRegion": null,
+            "physicsMismatchThresholdMs": 5.0,
+            "privacyMode": privacy_mode,
+        }))
+        .unwrap()
+    }
+
+    fn request(method: &str, path: &str, host: Option<&str>, origin: Option<&str>) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            host: host.map(str::to_string),
+            origin: origin.map(str::to_string),
+            authorization: Some(format!("Bearer {EXTENSION_TOKEN}")),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn authenticates_only_a_configured_bearer_token() {
+        let callers = callers(ApiRateLimit::default());
+        let bearer = |token: &str| format!("Bearer {token}");
+        assert_eq!(
+            callers.authenticate(Some(&bearer(EXTENSION_TOKEN))),
+            Some("extension")
+        );
+        assert_eq!(
+            callers.authenticate(Some(&bearer(AGENT_TOKEN))),
+            Some("agent")
+        );
+        assert_eq!(
+            callers.authenticate(Some(&bearer(&EXTENSION_TOKEN.to_uppercase()))),
+            Some("extension")
+        );
+        for authorization in [
+            None,
+            Some(EXTENSION_TOKEN.to_string()),
+            Some(format!("Basic {EXTENSION_TOKEN}")),
+            Some(bearer(&EXTENSION_TOKEN[..30])),
+            Some(bearer(&format!("{EXTENSION_TOKEN}00"))),
+            Some(bearer("00112233445566778899aabbccddeefe")),
+            Some(bearer("not hex")),
+            Some(bearer("")),
+        ] {
+            assert_eq!(
+                callers.authenticate(authorization.as_deref()),
+                None,
+                "{authorization:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn limiter_refills_per_caller_up_to_the_burst() {
+        let mut limiter = Limiter {
+            rate: ApiRateLimit {
+                burst: 2.0,
+                per_minute: 6.0,
+            },
+            buckets: HashMap::new(),
+        };
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        assert_eq!(limiter.take("a", at(0)), Ok(()));
+        assert_eq!(limiter.take("a", at(0)), Ok(()));
+        // One every 10 s.
+        assert_eq!(limiter.take("a", at(0)), Err(Duration::from_secs(10)));
+        assert_eq!(limiter.take("a", at(4)), Err(Duration::from_secs(6)));
+        assert_eq!(limiter.take("b", at(4)), Ok(()));
+        assert_eq!(limiter.take("a", at(10)), Ok(()));
+        assert_eq!(limiter.take("a", at(10)), Err(Duration::from_secs(10)));
+        // An hour idle refills to the burst, not past it.
+        assert_eq!(limiter.take("a", at(3610)), Ok(()));
+        assert_eq!(limiter.take("a", at(3610)), Ok(()));
+        assert!(limiter.take("a", at(3610)).is_err());
+    }
+
+    #[test]
+    fn connections_past_the_cap_get_no_slot() {
+        let open = Arc::new(AtomicUsize::new(0));
+        let mut slots: Vec<Slot> = (0..MAX_CONNECTIONS)
+            .map(|_| Slot::take(&open).unwrap())
+            .collect();
+        assert!(Slot::take(&open).is_none());
+        slots.pop();
+        assert!(Slot::take(&open).is_some());
+    }
+
+    #[test]
+    fn a_request_still_unread_at_the_deadline_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        client.write_all(b"GET /v1/info HTTP/1.1\r\n").unwrap();
+        let mut deadline = Deadline {
+            stream: &server,
+            deadline: Instant::now() + Duration::from_millis(200),
+        };
+        let mut line = String::new();
+        let mut reader = BufReader::new(&mut deadline);
+        reader.read_line(&mut line).unwrap();
+        // The rest of the head never comes.
+        let err = reader.read_line(&mut line).unwrap_err();
+        assert!(
+            matches!(
+                err.kind(),
+                io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+            ),
+            "{err}"
+        );
+        let err = reader.read_line(&mut line).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn rate_limited_burst_says_when_to_retry() {
+        let callers = callers(ApiRateLimit {
+            burst: 1.0,
+            per_minute: 4.0,
+        });
+        let status = Status::new(Path::new("out.jsonl"));
+        status.set_config(&config("off"));
+        let mut post = request("POST", "/v1/burst", Some("127.0.0.1:7717"), None);
+        // Requests that could not run a burst cost nothing.
+        assert_eq!(route(&post, &status, &callers).status, 400);
+        post.body = br#"{"endpoint": "lax"}"#.to_vec();
+        assert_eq!(route(&post, &status, &callers).status, 404);
+        callers
+            .limiter
+            .lock()
+            .unwrap()
+            .take("extension", Instant::now())
+            .unwrap();
+        // A thread that panicked holding the limiter does not lift it.
+        thread::scope(|s| {
+            let _ = s
+                .spawn(|| {
+                    let _held = callers.limiter.lock();
+                    panic!("poison the limiter");
+                })
+                .join();
+        });
+        assert!(callers.limiter.is_poisoned());
+        post.body = br#"{"endpoint": "nyc"}"#.to_vec();
+        let response = route(&post, &status, &callers);
+        assert_eq!(response.status, 429);
+        assert_eq!(response.headers, [("Retry-After", "15".to_string())]);
+        assert!(!status.burst_requested("nyc"));
+        // The other caller's bucket is untouched.
+        assert_eq!(
+            callers
+                .limiter
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take("agent", Instant::now()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn features_mode_refuses_bursts() {
+        let callers = callers(ApiRateLimit::default());
+        let status = Status::new(Path::new("out.jsonl"));
+        let mut cfg = config("features");
+        status.set_config(&cfg);
+        let mut post = request("POST", "/v1/burst", Some("127.0.0.1:7717"), None);
+        post.body = br#"{"endpoint": "nyc"}"#.to_vec();
+        let response = route(&post, &status, &callers);
+        assert_eq!(response.status, 403);
+        assert!(!status.burst_requested("nyc"));
+
+        cfg.privacy_mode = PrivacyMode::Off;
+        status.set_config(&cfg);
+        assert!(!status.features_only());
+    }
+
+    #[test]
+    fn route_checks_host_and_origin_before_the_token() {
+        let callers = callers(ApiRateLimit::default());
+        let status = Status::new(Path::new("out.jsonl"));
+        let status_of = |host: Option<&str>, origin: Option<&str>| {
+            route(&request("GET", "/v1/info", host, origin), &status, &callers).status
+        };
+        for host in [
+            "127.0.0.1:7717",
+            "localhost",
+            "LOCALHOST:7717",
+            "[::1]:7717",
+        ] {
+            assert_eq!(status_of(Some(host), None), 200, "{host}");
+        }
+        assert_eq!(status_of(None, None), 400);
+        for host in [
+            "evil.example",
+            "evil.example:7717",
+            "192.168.1.2:7717",
+            "[::2]:7717",
+        ] {
+            assert_eq!(status_of(Some(host), None), 403, "{host}");
+        }
+
+        let host = Some("127.0.0.1:7717");
+        assert_eq!(status_of(host, Some("chrome-extension://abcdef")), 200);
+        assert_eq!(status_of(host, Some("moz-extension://abcdef")), 200);
+        assert_eq!(status_of(host, Some("https://evil.example")), 403);
+        assert_eq!(status_of(host, Some("null")), 403);
+
+        // Preflights carry no token; anything else needs one.
+        let mut preflight = request("OPTIONS", "/v1/burst", host, Some("chrome-extension://abc"));
+        preflight.authorization = None;
+        assert_eq!(route(&preflight, &status, &callers).status, 204);
+        let mut anonymous = request("GET", "/v1/info", host, None);
+        anonymous.authorization = None;
+        let response = route(&anonymous, &status, &callers);
+        assert_eq!(response.status, 401);
+        assert_eq!(
+            response.headers,
+            [("WWW-Authenticate", "Bearer".to_string())]
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use crate::sequence::Sequencer;
+use crate::transport::Prober;
+use crate::{os, sleep_until};
+use lattice_core::Secret;
+use std::collections::VecDeque;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Big enough to fill a queue quickly, small enough for any path MTU.
+const LOAD_DATAGRAM_BYTES: usize = 1200;
+/// How often the load thread tops up to its target rate.
+const LOAD_TICK: Duration = Duration::from_millis(1);
+const BITS_PER_BYTE: f64 = 8.0;
+const BITS_PER_MBIT: f64 = 1_000_000.0;
+/// Quiet bursts whose p05 is kept as the idle reference for busy ones.
+const IDLE_WINDOW_BURSTS: usize = 20;
+/// Share of a load spent filling the queue before probes start.
+const LOAD_RAMP_SHARE: f64 = 0.25;
+
+/// Upload toward the endpoint, split evenly over `streams` sockets.
+pub struct Load {
+    pub streams: usize,
+    pub mbps: f64,
+    pub duration: Duration,
+}
+
+/// Everything a probe needs besides the socket and the sequence number.
+pub struct Probing<'a> {
+    pub secret: &'a Secret,
+    pub spacing: Duration,
+    pub timeout: Duration,
+    pub pacing_spin_us: u64,
+}
+
+/// Probes every `spacing` from `start` until `until`. Returns the RTTs (ns)
+/// that came back and how many probes went out.
+pub fn probe_window(
+    prober: &mut Prober,
+    probing: &Probing,
+    sequencer: &mut Sequencer,
+    start: Instant,
+    until: Instant,
+) -> (Vec<u64>, usize) {
+    let mut samples = Vec::new();
+    let mut sent = 0;
+    let mut next_send = start;
+    while next_send < until {
+        sleep_until(next_send, probing.pacing_spin_us);
+        let send_realtime_ns = os::realtime_now_ns();
+        let send_mono_ns = os::monotonic_now_ns();
+        let msg = sequencer.packet(send_realtime_ns, probing.secret);
+        sent += 1;
+        let reply =
+            prober.send_and_receive_rtt(&msg, send_realtime_ns, send_mono_ns, probing.timeout);
+        if let Ok(Some(rtt)) = reply {
+            samples.push(rtt);
+        }
+        next_send += probing.spacing;
+    }
+    (samples, sent)
+}
+
+/// Runs `load` toward the prober's peer and probes through it once the queue
+/// has had `LOAD_RAMP_SHARE` of the duration to fill. Returns the RTTs (ns)
+/// that came back and how many probes went out.
+pub fn probe_under_load(
+    prober: &mut Prober,
+    bind_ip: Option<IpAddr>,
+    load: &Load,
+    probing: &Probing,
+    sequencer: &mut Sequencer,
+) -> io::Result<(Vec<u64>, usize)> {
+    let dest = prober.peer_addr()?;
+    let streams = load.streams.max(1);
+    let handles = (0..streams)
+        .map(|_| spawn_load(dest, bind_ip, load.mbps / streams as f64, load.duration))
+        .collect::<io::Result<Vec<_>>>()?;
+    let started = Instant::now();
+    let result = probe_window(
+        prober,
+        probing,
+        sequencer,
+        started + load.duration.mul_f64(LOAD_RAMP_SHARE),
+        started + load.duration,
+    );
+    for handle in handles {
+        let _ = handle.join();
+    }
+    Ok(result)
+}
+
+/// Sends zero-filled datagrams to `dest` at `mbps` for `duration` from a
+/// socket of its own. They fail the reflector's packet check and are dropped
+/// there, so what they load is the uplink queue and the path.
+fn spawn_load(
+    dest: SocketAddr,
+    bind_ip: Option<IpAddr>,
+    mbps: f64,
+    duration: Duration,
+) -> io::Result<JoinHandle<()>> {
+    let local = bind_ip.unwrap_or(match dest {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    });
+    let sock = UdpSocket::bind(SocketAddr::new(local, 0))?;
+    sock.connect(dest)?;
+    let bytes_per_sec = mbps * BITS_PER_MBIT / BITS_PER_BYTE;
+    thread::Builder::new()
+        .name("bufferbloat-load".to_string())
+        .spawn(move || {
+            let payload = [0u8; LOAD_DATAGRAM_BYTES];
+            let start = Instant::now();
+            let mut sent = 0.0;
+            while start.elapsed() < duration {
+                let due = bytes_per_sec * start.elapsed().as_secs_f64();
+                while sent + LOAD_DATAGRAM_BYTES as f64 <= due {
+                    // A full send buffer (ENOBUFS) is what the load is for;
+                    // count the datagram and keep the pace.
+                    let _ = sock.send(&payload);
+                    sent += LOAD_DATAGRAM_BYTES as f64;
+                }
+                thread::sleep(LOAD_TICK);
+            }
+        })
+}
+
+/// The lowest p05 over an endpoint's recent quiet bursts: the idle reference
+/// for a burst that ran while other traffic filled the interface.
+#[derive(Default)]
+pub struct IdleFloor {
+    recent: VecDeque<u64>,
+}
+
+impl IdleFloor {
+    pub fn observe(&mut self, p05_ns: u64) {
+        if self.recent.len() == IDLE_WINDOW_BURSTS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(p05_ns);
+    }
+
+    pub fn p05_ns(&self) -> Option<u64> {
+        self.recent.iter().copied().min()
+    }
+
+    /// The window, oldest first, for saving across restarts.
+    pub fn recent(&self) -> Vec<u64> {
+        self.recent.iter().copied().collect()
+    }
+
+    /// A window saved by `recent`, cut to the newest `IDLE_WINDOW_BURSTS`.
+    pub fn from_recent(recent: &[u64]) -> Self {
+        let keep = &recent[recent.len().saturating_sub(IDLE_WINDOW_BURSTS)..];
+        IdleFloor {
+            recent: keep.iter().copied().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_floor_is_the_lowest_p05_in_the_window() {
+        let mut floor = IdleFloor::default();
+        assert_eq!(floor.p05_ns(), None);
+        for p05 in (1..=25).rev() {
+            floor.observe(p05 * 1_000);
+        }
+        assert_eq!(floor.p05_ns(), Some(1_000));
+        // The five highest, oldest readings fell out of the window.
+        let recent = floor.recent();
+        assert_eq!(recent.len(), IDLE_WINDOW_BURSTS);
+        assert_eq!(recent[0], 20_000);
+
+        // A saved window longer than ours keeps its newest readings.
+        let saved: Vec<u64> = (0..25).collect();
+        let restored = IdleFloor::from_recent(&saved);
+        assert_eq!(restored.recent(), saved[5..]);
+        assert_eq!(restored.p05_ns(), Some(5));
+        assert_eq!(IdleFloor::from_recent(&[]).p05_ns(), None);
+    }
+
+    #[test]
+    fn load_paces_datagrams_to_the_rate() {
+        let sink = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        sink.set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let duration = Duration::from_millis(200);
+        let mbps = 1.0;
+        spawn_load(sink.local_addr().unwrap(), None, mbps, duration)
+            .unwrap()
+            .join()
+            .unwrap();
+
+        let mut buf = [0u8; 2 * LOAD_DATAGRAM_BYTES];
+        let mut datagrams = 0;
+        while let Ok(n) = sink.recv(&mut buf) {
+            assert_eq!(n, LOAD_DATAGRAM_BYTES);
+            assert!(buf[..n].iter().all(|&b| b == 0));
+            datagrams += 1;
+        }
+        let expected = mbps * BITS_PER_MBIT / BITS_PER_BYTE * duration.as_secs_f64()
+            / LOAD_DATAGRAM_BYTES as f64;
+        assert!(datagrams as f64 <= expected.ceil(), "{datagrams}");
+        assert!(datagrams as f64 >= expected / 2.0, "{datagrams}");
+    }
+}
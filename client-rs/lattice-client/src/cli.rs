@@ -0,0 +1,487 @@
+use crate::responsiveness;
+use clap::{Args, Parser, Subcommand};
+use lattice_core::{Config, Transport};
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const SECS_PER_MIN: u64 = 60;
+const SECS_PER_HOUR: u64 = 60 * SECS_PER_MIN;
+const SECS_PER_DAY: u64 = 24 * SECS_PER_HOUR;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "lattice",
+    version,
+    about = "LATTICE latency prober, analyzer, and tooling",
+    args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// `lattice <config.json>` predates subcommands and still means `probe`.
+    #[command(flatten)]
+    pub probe: ProbeArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Probe the configured endpoints and append bursts to the output JSONL.
+    Probe(ProbeArgs),
+    /// Capture a tunnel-off baseline; refuses to start while a tunnel is up.
+    Baseline(BaselineArgs),
+    /// Capture while toggling the tunnel; splits output into baseline and session.
+    Pair(PairArgs),
+    /// Physics bounds and location estimate (same options as lattice-analyze).
+    #[command(disable_help_flag = true)]
+    Analyze(PassThrough),
+    /// Build a bias model from a capture taken at a known location.
+    #[command(disable_help_flag = true)]
+    Calibrate(PassThrough),
+    /// Shareable copies of a session: `redact`, `aggregate`, or `atlas` (RIPE
+    /// Atlas ping results).
+    #[command(disable_help_flag = true)]
+    Export(PassThrough),
+    /// Run the full analysis on bundled synthetic data to check an install.
+    #[command(disable_help_flag = true)]
+    Demo(PassThrough),
+    /// Reflector deployment tooling (same options as lattice-reflector).
+    #[command(disable_help_flag = true)]
+    Reflect(PassThrough),
+    /// Run a UDP reflector for the shared secret (same options as lattice-server).
+    #[command(disable_help_flag = true)]
+    Serve(PassThrough),
+    /// Check config, output, clock, tunnel state, and reflector reachability.
+    Doctor(DoctorArgs),
+    /// Query a running prober over its control socket.
+    Status(StatusArgs),
+    /// Have a running prober measure latency under parallel upload (RPM).
+    Responsiveness(ResponsivenessArgs),
+    /// Config helpers for the endpoint list.
+    Endpoints {
+        #[command(subcommand)]
+        action: EndpointsAction,
+    },
+    /// Edit and check an anchor registry: a JSON file whose `endpoints` the
+    /// configs copy.
+    Anchors {
+        #[command(subcommand)]
+        action: AnchorsAction,
+    },
+    /// Generate a shared secret.
+    Keygen {
+        /// Also write the secret into this config's `secretHex`.
+        #[arg(long)]
+        config: Option<PathBuf>,
+    },
+    /// Replace `secretHex` in a config and its anchor registries.
+    RotateKeys {
+        config: PathBuf,
+        #[arg(long = "registry", value_name = "FILE")]
+        registries: Vec<PathBuf>,
+    },
+    /// Print a shell completion script.
+    Completions { shell: clap_complete::Shell },
+}
+
+/// Arguments forwarded untouched to the analyzer, reflector, or server CLI.
+#[derive(Args, Debug)]
+pub struct PassThrough {
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub args: Vec<String>,
+}
+
+/// Where the config comes from: a positional path or `--config`, plus overrides.
+#[derive(Args, Debug, Clone, Default)]
+pub struct ConfigSource {
+    /// Config file (JSON).
+    #[arg(value_name = "CONFIG")]
+    config_path: Option<PathBuf>,
+
+    /// Same as the positional CONFIG.
+    #[arg(long = "config", value_name = "CONFIG", conflicts_with = "config_path")]
+    config_flag: Option<PathBuf>,
+
+    /// Host-specific config layered over CONFIG (repeatable; later files win).
+    #[arg(long = "override", value_name = "FILE")]
+    overrides: Vec<PathBuf>,
+}
+
+impl ConfigSource {
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.config_path.as_ref().or(self.config_flag.as_ref())
+    }
+
+    pub fn overrides(&self) -> &[PathBuf] {
+        &self.overrides
+    }
+
+    pub fn load(&self) -> io::Result<Config> {
+        let path = self.path().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "a config file is required")
+        })?;
+        Config::load_layered(path, &self.overrides)
+    }
+
+    /// The same config selection as `lattice analyze` flags.
+    pub fn analyzer_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(path) = self.path() {
+            args.extend(["--config".to_string(), path.display().to_string()]);
+        }
+        for path in &self.overrides {
+            args.extend(["--override".to_string(), path.display().to_string()]);
+        }
+        args
+    }
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct ProbeArgs {
+    #[command(flatten)]
+    pub source: ConfigSource,
+
+    /// Write records here instead of the config's `outputPath`.
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+
+    /// Run one burst per endpoint, then exit (same as `--max-bursts 1`).
+    #[arg(long, conflicts_with = "max_bursts")]
+    pub once: bool,
+
+    /// Stop each endpoint after this many bursts.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_bursts: Option<u64>,
+
+    /// Stop after this long (e.g. 90s, 30m, 2h, 1d).
+    #[arg(long, value_parser = parse_duration)]
+    pub duration: Option<Duration>,
+
+    /// Only probe endpoints whose id matches one of these comma-separated
+    /// patterns (`*` matches any run of characters).
+    #[arg(long, value_delimiter = ',')]
+    pub endpoints_filter: Vec<String>,
+
+    /// Probe endpoints not marked `consented` (you operate them).
+    #[arg(long = "i-own-these-targets")]
+    pub own_targets: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct BaselineArgs {
+    #[command(flatten)]
+    pub source: ConfigSource,
+
+    /// Baseline JSONL to write (appended if it exists).
+    #[arg(long)]
+    pub out: PathBuf,
+
+    /// Clean bursts to collect per endpoint (defaults to `baselineBursts`).
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub bursts: Option<u64>,
+
+    /// Give up after this long even if some endpoints are short of clean bursts.
+    #[arg(long, value_parser = parse_duration)]
+    pub duration: Option<Duration>,
+
+    #[arg(long, value_delimiter = ',')]
+    pub endpoints_filter: Vec<String>,
+
+    #[arg(long = "i-own-these-targets")]
+    pub own_targets: bool,
+}
+
+impl BaselineArgs {
+    pub fn probe_args(&self) -> ProbeArgs {
+        ProbeArgs {
+            source: self.source.clone(),
+            output: Some(self.out.clone()),
+            once: false,
+            max_bursts: self.bursts,
+            duration: self.duration,
+            endpoints_filter: self.endpoints_filter.clone(),
+            own_targets: self.own_targets,
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct PairArgs {
+    #[command(flatten)]
+    pub source: ConfigSource,
+
+    /// Bursts taken with no tunnel up.
+    #[arg(long)]
+    pub baseline_out: PathBuf,
+
+    /// Bursts taken through a tunnel.
+    #[arg(long)]
+    pub session_out: PathBuf,
+
+    /// How long to capture (e.g. 30m); toggle the tunnel during this window.
+    #[arg(long, value_parser = parse_duration)]
+    pub duration: Duration,
+
+    /// Run `lattice analyze` on the two files when the capture ends.
+    #[arg(long)]
+    pub analyze: bool,
+
+    #[arg(long, value_delimiter = ',')]
+    pub endpoints_filter: Vec<String>,
+
+    #[arg(long = "i-own-these-targets")]
+    pub own_targets: bool,
+}
+
+impl PairArgs {
+    pub fn probe_args(&self) -> ProbeArgs {
+        ProbeArgs {
+            source: self.source.clone(),
+            output: Some(self.session_out.clone()),
+            duration: Some(self.duration),
+            endpoints_filter: self.endpoints_filter.clone(),
+            own_targets: self.own_targets,
+            ..ProbeArgs::default()
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DoctorArgs {
+    #[command(flatten)]
+    pub source: ConfigSource,
+
+    /// Also send a test probe to endpoints not marked `consented`.
+    #[arg(long = "i-own-these-targets")]
+    pub own_targets: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct StatusArgs {
+    #[command(flatten)]
+    pub source: ConfigSource,
+
+    /// Control socket path (defaults to the one derived from the config).
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+
+    /// Print the raw JSON snapshot.
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ResponsivenessArgs {
+    #[command(flatten)]
+    pub source: ConfigSource,
+
+    /// Control socket path (defaults to the one derived from the config).
+    #[arg(long)]
+    pub socket: Option<PathBuf>,
+
+    /// Endpoint to load and probe (default: the first in the config).
+    #[arg(long)]
+    pub endpoint: Option<String>,
+
+    /// How long the upload runs.
+    #[arg(long, default_value_t = responsiveness::DEFAULT_SECONDS)]
+    pub seconds: u64,
+
+    /// Parallel upload sockets.
+    #[arg(long, default_value_t = responsiveness::DEFAULT_STREAMS)]
+    pub streams: usize,
+
+    /// Total upload rate across all streams.
+    #[arg(long, default_value_t = responsiveness::DEFAULT_MBPS)]
+    pub mbps: f64,
+
+    /// Print the raw JSON record.
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum EndpointsAction {
+    /// Fill in missing lat/lon from the bundled cloud region table.
+    Annotate(EndpointsFile),
+    /// Replace `endpointTemplates` with the endpoints they expand to.
+    Expand(EndpointsFile),
+}
+
+#[derive(Args, Debug)]
+pub struct EndpointsFile {
+    pub config: PathBuf,
+
+    /// Update the file in place instead of printing it.
+    #[arg(long)]
+    pub write: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AnchorsAction {
+    /// Print the registry's anchors.
+    List { registry: PathBuf },
+    /// Add an anchor after checking it against the others and probing it.
+    Add(AnchorAddArgs),
+    /// Remove an anchor by id.
+    Remove {
+        registry: PathBuf,
+        id: String,
+        /// Config that copies the registry's endpoints; the anchor is
+        /// removed there too (repeatable).
+        #[arg(long = "config", value_name = "FILE")]
+        configs: Vec<PathBuf>,
+    },
+    /// Check every anchor and probe the ones that may be probed.
+    Verify(AnchorVerifyArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct AnchorAddArgs {
+    /// Registry JSON; created if missing.
+    pub registry: PathBuf,
+
+    #[arg(long)]
+    pub id: String,
+
+    #[arg(long)]
+    pub host: String,
+
+    #[arg(long, default_value_t = crate::anchors::DEFAULT_ANCHOR_PORT)]
+    pub port: u16,
+
+    /// Decimal degrees; looked up from cloud region codes in the host, id,
+    /// or region hint when left out.
+    #[arg(long, allow_hyphen_values = true, requires = "lon")]
+    pub lat: Option<f64>,
+
+    #[arg(long, allow_hyphen_values = true, requires = "lat")]
+    pub lon: Option<f64>,
+
+    #[arg(long)]
+    pub region_hint: Option<String>,
+
+    /// Transport to try, preferred first (repeatable; default udp).
+    #[arg(long = "transport", value_name = "TRANSPORT", value_parser = parse_transport)]
+    pub transports: Vec<Transport>,
+
+    /// Port of the anchor's HTTPS echo (default 443).
+    #[arg(long)]
+    pub https_port: Option<u16>,
+
+    /// The anchor is operated by you or its operator agreed to probing.
+    #[arg(long)]
+    pub consented: bool,
+
+    /// Config that copies the registry's endpoints; the anchor is added
+    /// there too (repeatable).
+    #[arg(long = "config", value_name = "FILE")]
+    pub configs: Vec<PathBuf>,
+
+    /// Add without probing, e.g. before its reflector is deployed.
+    #[arg(long)]
+    pub no_verify: bool,
+
+    /// Probe it even without `--consented`.
+    #[arg(long)]
+    pub own_targets: bool,
+
+    #[arg(long, default_value_t = crate::anchors::DEFAULT_VERIFY_TIMEOUT_MS)]
+    pub timeout_ms: u64,
+}
+
+#[derive(Args, Debug)]
+pub struct AnchorVerifyArgs {
+    pub registry: PathBuf,
+
+    /// Config that copies the registry's endpoints; copies that are missing
+    /// or differ are reported (repeatable).
+    #[arg(long = "config", value_name = "FILE")]
+    pub configs: Vec<PathBuf>,
+
+    /// Only check the entries; send no probes.
+    #[arg(long)]
+    pub offline: bool,
+
+    /// Probe anchors that are not marked `consented` too.
+    #[arg(long)]
+    pub own_targets: bool,
+
+    #[arg(long, default_value_t = crate::anchors::DEFAULT_VERIFY_TIMEOUT_MS)]
+    pub timeout_ms: u64,
+}
+
+fn parse_transport(text: &str) -> Result<Transport, String> {
+    serde_json::from_value(serde_json::Value::String(text.to_string()))
+        .map_err(|_| format!("unknown transport '{text}' (udp, tcp, tcpConnect, or https)"))
+}
+
+/// Parses `90`, `90s`, `30m`, `2h`, or `1d`.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let (digits, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => text.split_at(i),
+        None => (text, "s"),
+    };
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", text))?;
+    let scale = match unit {
+        "s" => 1,
+        "m" => SECS_PER_MIN,
+        "h" => SECS_PER_HOUR,
+        "d" => SECS_PER_DAY,
+        _ => {
+            return Err(format!(
+                "invalid duration unit in '{}' (use s, m, h, or d)",
+                text
+            ))
+        }
+    };
+    if value == 0 {
+        return Err("duration must be > 0".to_string());
+    }
+    value
+        .checked_mul(scale)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration '{}' is too long", text))
+}
+
+/// `*` wildcard match, used by `--endpoints-filter`.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_parse_and_overflow_is_an_error() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(1800)));
+        assert_eq!(parse_duration("2d"), Ok(Duration::from_secs(172_800)));
+        assert!(parse_duration("0h").is_err());
+        assert!(parse_duration("5w").is_err());
+        assert_eq!(
+            parse_duration("999999999999999999d"),
+            Err("duration '999999999999999999d' is too long".to_string())
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use crate::os;
+use lattice_core::ClockQuality;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+/// Back-to-back realtime reads used to find the smallest clock step.
+const GRANULARITY_READS: usize = 10_000;
+/// Window over which realtime is compared with monotonic.
+const SKEW_WINDOW: Duration = Duration::from_millis(200);
+const PPM_PER_UNIT: f64 = 1e6;
+const MS_PER_SEC: f64 = 1000.0;
+
+// `chronyc -c tracking` CSV columns.
+const CHRONY_SYSTEM_OFFSET: usize = 4;
+const CHRONY_ROOT_DELAY: usize = 10;
+const CHRONY_ROOT_DISPERSION: usize = 11;
+const CHRONY_LEAP_STATUS: usize = 13;
+
+/// Measures timestamp granularity, realtime-vs-monotonic rate, and NTP sync
+/// state. Takes about `SKEW_WINDOW`; run once at startup.
+pub fn self_test() -> ClockQuality {
+    let mut granularity: Option<u64> = None;
+    let mut prev = os::realtime_now_ns();
+    for _ in 0..GRANULARITY_READS {
+        let now = os::realtime_now_ns();
+        if now > prev {
+            let step = now - prev;
+            granularity = Some(granularity.map_or(step, |g| g.min(step)));
+        }
+        prev = now;
+    }
+
+    let rt0 = os::realtime_now_ns();
+    let mono0 = os::monotonic_now_ns();
+    thread::sleep(SKEW_WINDOW);
+    let rt1 = os::realtime_now_ns();
+    let mono1 = os::monotonic_now_ns();
+    let d_mono = mono1.saturating_sub(mono0) as f64;
+    let skew_ppm = if d_mono > 0.0 {
+        Some((rt1 as f64 - rt0 as f64 - d_mono) / d_mono * PPM_PER_UNIT)
+    } else {
+        None
+    };
+
+    let mut ntp = os::ntp_report();
+    if ntp.synced.is_none() {
+        if let Some(chrony) = chronyc_tracking() {
+            ntp = chrony;
+        }
+    }
+
+    ClockQuality {
+        realtime_resolution_ns: os::realtime_resolution_ns(),
+        observed_granularity_ns: granularity,
+        realtime_vs_monotonic_ppm: skew_ppm,
+        ntp_synced: ntp.synced,
+        ntp_offset_ms: ntp.offset_ms,
+        ntp_est_error_ms: ntp.est_error_ms,
+        ntp_source: ntp.source,
+    }
+}
+
+fn chronyc_tracking() -> Option<os::NtpReport> {
+    let out = Command::new("chronyc")
+        .args(["-c", "tracking"])
+        .output()
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&out.stdout);
+    let fields: Vec<&str> = text.trim().split(',').collect();
+    let num = |i: usize| fields.get(i).and_then(|v| v.parse::<f64>().ok());
+    let leap = fields.get(CHRONY_LEAP_STATUS)?;
+    let est_error_s = match (num(CHRONY_ROOT_DELAY), num(CHRONY_ROOT_DISPERSION)) {
+        (Some(delay), Some(disp)) => Some(disp + delay / 2.0),
+        _ => None,
+    };
+    Some(os::NtpReport {
+        synced: Some(!leap.eq_ignore_ascii_case("Not synchronised")),
+        offset_ms: num(CHRONY_SYSTEM_OFFSET).map(|s| s * MS_PER_SEC),
+        est_error_ms: est_error_s.map(|s| s * MS_PER_SEC),
+        source: Some("chronyc".to_string()),
+    })
+}
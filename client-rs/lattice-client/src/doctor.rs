@@ -0,0 +1,302 @@
+use crate::cli::DoctorArgs;
+use crate::transport::Prober;
+use crate::{
+    check_consent, clock, expand_probe_targets, expand_tilde, is_loopback_host, lint, os, status,
+    validate_config,
+};
+use lattice_core::{
+    build_packet, ns_to_ms, suggested_access_floor_ms, MIN_SECRET_BYTES, PRIVATE_RELAY,
+};
+use rand::Rng;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Offsets beyond this make realtime-stamped RTTs and cross-host comparisons suspect.
+const NTP_OFFSET_WARN_MS: f64 = 50.0;
+
+#[derive(Default)]
+struct Tally {
+    warnings: usize,
+    failures: usize,
+}
+
+impl Tally {
+    fn ok(&self, msg: impl AsRef<str>) {
+        println!("[ok] {}", msg.as_ref());
+    }
+
+    fn warn(&mut self, msg: impl AsRef<str>) {
+        self.warnings += 1;
+        println!("[!]  {}", msg.as_ref());
+    }
+
+    fn fail(&mut self, msg: impl AsRef<str>) {
+        self.failures += 1;
+        println!("[!!] {}", msg.as_ref());
+    }
+}
+
+/// `lattice doctor`: runs the startup checks without starting a capture, then
+/// sends one probe to each endpoint the consent rules allow.
+pub fn run(args: &DoctorArgs) -> io::Result<()> {
+    let mut t = Tally::default();
+
+    let config_path = args
+        .source
+        .path()
+        .map(|p| p.display().to_string())
+        .unwrap_or_default();
+    let cfg = match args.source.load() {
+        Ok(cfg) => {
+            t.ok(format!(
+                "config {} loads ({} endpoints)",
+                config_path,
+                cfg.endpoints.len()
+            ));
+            cfg
+        }
+        Err(err) => {
+            t.fail(format!("config {}: {}", config_path, err));
+            return finish(&t);
+        }
+    };
+    match validate_config(&cfg) {
+        Ok(()) => t.ok("config values valid"),
+        Err(err) => t.fail(err.to_string()),
+    }
+    for lint in lint::lint_config(&cfg) {
+        t.warn(lint.to_string());
+    }
+    let secret = match cfg.secret_hex.decode() {
+        Ok(bytes) if bytes.len() >= MIN_SECRET_BYTES => {
+            t.ok(format!("secretHex is {} bytes", bytes.len()));
+            Some(bytes)
+        }
+        Ok(bytes) => {
+            t.fail(format!(
+                "secretHex is {} bytes; need at least {}",
+                bytes.len(),
+                MIN_SECRET_BYTES
+            ));
+            None
+        }
+        Err(err) => {
+            t.fail(err);
+            None
+        }
+    };
+    if let Err(err) = check_consent(&cfg, args.own_targets) {
+        t.warn(err.to_string());
+    }
+
+    let output_path = expand_tilde(&cfg.output_path);
+    let writable = output_path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| File::options().create(true).append(true).open(&output_path));
+    match writable {
+        Ok(_) => t.ok(format!("output {} writable", output_path.display())),
+        Err(err) => t.fail(format!("output {}: {}", output_path.display(), err)),
+    }
+    let socket = status::socket_path(&cfg);
+    if status::connect(&socket).is_ok() {
+        t.warn(format!(
+            "an instance is already running on {}",
+            socket.display()
+        ));
+    }
+
+    let clock = clock::self_test();
+    match clock.observed_granularity_ns {
+        Some(g) => t.ok(format!("clock step {}ns", g)),
+        None => t.warn("realtime clock did not advance during the self-test"),
+    }
+    match clock.ntp_synced {
+        Some(true) => t.ok(format!(
+            "NTP synced via {}",
+            clock.ntp_source.as_deref().unwrap_or("?")
+        )),
+        Some(false) => t.warn("NTP not synchronised; realtime timestamps may drift"),
+        None => t.warn("NTP state unknown (no adjtimex/chronyc)"),
+    }
+    if let Some(offset) = clock.ntp_offset_ms.filter(|o| o.abs() > NTP_OFFSET_WARN_MS) {
+        t.warn(format!("NTP offset {:.1}ms", offset));
+    }
+
+    let utun = os::utun_report();
+    if utun.active {
+        let names: Vec<String> = utun.interfaces.iter().map(|i| i.name.clone()).collect();
+        t.warn(format!(
+            "tunnel interface active ({}); captures will measure the tunnel path",
+            names.join(", ")
+        ));
+    } else {
+        t.ok("no active tunnel interface");
+    }
+    if os::system_relay() == Some(PRIVATE_RELAY) {
+        t.warn(
+            "iCloud Private Relay is on; web services see a relay exit, but the probes bypass it",
+        );
+    }
+
+    let targets = match expand_probe_targets(&cfg, Uuid::new_v4()) {
+        Ok(targets) => targets,
+        Err(err) => {
+            t.fail(format!("probe paths: {}", err));
+            return finish(&t);
+        }
+    };
+    let Some(secret) = secret else {
+        return finish(&t);
+    };
+    let timeout = Duration::from_millis(cfg.timeout_ms);
+    let mut rng = rand::thread_rng();
+    let mut floor_hinted: HashSet<&str> = HashSet::new();
+    for (seq, target) in targets.iter().enumerate() {
+        let ep = &target.endpoint;
+        if !(ep.consented || is_loopback_host(&ep.host) || args.own_targets) {
+            println!("[--] {} skipped (not consented)", ep.id);
+            continue;
+        }
+        // Transports that failed before one answered are only warnings: the
+        // client falls back the same way.
+        let mut misses = Vec::new();
+        let mut answer = None;
+        for &transport in ep.transport_order() {
+            let send_realtime_ns = os::realtime_now_ns();
+            let send_mono_ns = os::monotonic_now_ns();
+            let msg = build_packet(seq as u32, send_realtime_ns, rng.gen(), secret.as_bytes());
+            let via = match ep.transport_order().len() {
+                1 => String::new(),
+                _ => format!(" over {}", transport.as_str()),
+            };
+            let mut prober = match Prober::open(transport, ep, &ep.host, target.bind_ip, 0, timeout)
+            {
+                Ok(p) => p,
+                Err(err) => {
+                    misses.push(format!("{} socket{}: {}", ep.id, via, err));
+                    continue;
+                }
+            };
+            match prober.send_and_receive_rtt(&msg, send_realtime_ns, send_mono_ns, timeout) {
+                Ok(Some(rtt)) => {
+                    answer = Some((prober, rtt, via));
+                    break;
+                }
+                Ok(None) => misses.push(format!(
+                    "{} {}:{}{} no reply within {}ms (reflector down, firewall, or secret mismatch)",
+                    ep.id, ep.host, ep.port, via, cfg.timeout_ms
+                )),
+                Err(err) => misses.push(format!("{} {}:{}{} {}", ep.id, ep.host, ep.port, via, err)),
+            }
+        }
+        let Some((prober, rtt, via)) = answer else {
+            for miss in misses {
+                t.fail(miss);
+            }
+            continue;
+        };
+        for miss in misses {
+            t.warn(miss);
+        }
+        t.ok(format!(
+            "{} {}:{}{} replied in {:.2}ms",
+            ep.id,
+            ep.host,
+            ep.port,
+            via,
+            ns_to_ms(rtt)
+        ));
+        let iface = prober
+            .iface_name()
+            .map(|name| os::iface_type(&name))
+            .unwrap_or_default();
+        if let Some((tech, floor)) = suggested_access_floor_ms(&iface) {
+            if target.access_floor_ms.is_none() && floor_hinted.insert(&target.path_id) {
+                t.warn(format!(
+                    "path {} is {} ({} floor ~{:.0}ms); set accessFloorMs so it is not read as distance",
+                    target.path_id, iface, tech, floor
+                ));
+            }
+        }
+    }
+    finish(&t)
+}
+
+fn finish(t: &Tally) -> io::Result<()> {
+    println!("\n{} failure(s), {} warning(s)", t.failures, t.warnings);
+    if t.failures > 0 {
+        return Err(io::Error::other("doctor found problems"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::temp_dir;
+    use clap::Parser;
+    use lattice_core::SecretHex;
+    use lattice_server::{Server, ServerConfig};
+    use serde_json::json;
+    use std::path::{Path, PathBuf};
+    use std::thread;
+
+    const SECRET: &str = "00112233445566778899aabbccddeeff";
+
+    #[derive(Parser)]
+    struct Doctor {
+        #[command(flatten)]
+        args: DoctorArgs,
+    }
+
+    fn doctor(config: &Path) -> io::Result<()> {
+        run(&Doctor::parse_from(["doctor", config.to_str().unwrap()]).args)
+    }
+
+    fn write_config(dir: &Path, secret: &str, port: u16) -> PathBuf {
+        let path = dir.join("config.json");
+        let cfg = json!({
+            "secretHex": secret,
+            "endpoints": [{ "id": "local", "host": "127.0.0.1", "port": port }],
+            "samplesPerEndpoint": 10,
+            "spacingMs": 10,
+            "timeoutMs": 1000,
+            "intervalSeconds": 10,
+            "outputPath": dir.join("out").join("session.jsonl"),
+            "physicsMismatchThresholdMs": 5.0,
+        });
+        fs::write(&path, cfg.to_string()).unwrap();
+        path
+    }
+
+    #[test]
+    fn passes_against_a_live_reflector_and_fails_without_one() {
+        let dir = temp_dir("doctor");
+
+        let server = Server::bind(&ServerConfig {
+            secret_hex: SecretHex::from(SECRET),
+            listen: vec!["127.0.0.1:0".to_string()],
+            ..ServerConfig::default()
+        })
+        .unwrap();
+        let port = server.local_addrs().unwrap()[0].port();
+        let shutdown = server.shutdown_handle();
+        let serving = thread::spawn(move || server.serve());
+
+        doctor(&write_config(&dir, SECRET, port)).unwrap();
+        assert!(dir.join("out").join("session.jsonl").exists());
+        // A wrong secret gets no echo.
+        let wrong = SECRET.replace("00", "ff");
+        assert!(doctor(&write_config(&dir, &wrong, port)).is_err());
+        shutdown.shutdown();
+        serving.join().unwrap().unwrap();
+
+        let err = doctor(&write_config(&dir, "0011", port)).unwrap_err();
+        assert_eq!(err.to_string(), "doctor found problems");
+        assert!(doctor(&dir.join("missing.json")).is_err());
+    }
+}
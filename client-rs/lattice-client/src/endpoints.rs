@@ -0,0 +1,96 @@
+use crate::cli::EndpointsAction;
+use crate::write_atomic;
+use lattice_core::{expand_endpoint_templates, find_cloud_region};
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub fn run(action: &EndpointsAction) -> io::Result<()> {
+    match action {
+        EndpointsAction::Annotate(file) => annotate(&file.config, file.write),
+        EndpointsAction::Expand(file) => expand(&file.config, file.write),
+    }
+}
+
+fn emit(path: &Path, cfg: &Value, write: bool) -> io::Result<()> {
+    let text = serde_json::to_string_pretty(cfg).map_err(io::Error::other)?;
+    if write {
+        write_atomic(path, text.as_bytes())
+    } else {
+        println!("{text}");
+        Ok(())
+    }
+}
+
+/// Replaces `endpointTemplates` with the literal endpoints they expand to, for
+/// tools (such as the dashboard) that only read `endpoints`.
+fn expand(path: &Path, write: bool) -> io::Result<()> {
+    let data = fs::read(path)?;
+    let mut cfg: Value =
+        serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    expand_endpoint_templates(&mut cfg)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let count = cfg
+        .get("endpoints")
+        .and_then(Value::as_array)
+        .map_or(0, Vec::len);
+    emit(path, &cfg, write)?;
+    if write {
+        eprintln!("expanded {} to {} endpoint(s)", path.display(), count);
+    }
+    Ok(())
+}
+
+/// Fills in missing endpoint lat/lon from the bundled cloud region table by
+/// matching region codes in the host, then the id, then the region hint.
+fn annotate(path: &Path, write: bool) -> io::Result<()> {
+    let data = fs::read(path)?;
+    let mut cfg: Value =
+        serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let endpoints = cfg
+        .get_mut("endpoints")
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "config has no endpoints array")
+        })?;
+
+    let mut annotated = 0usize;
+    for ep in endpoints.iter_mut() {
+        let Some(obj) = ep.as_object_mut() else {
+            continue;
+        };
+        let id = obj
+            .get("id")
+            .and_then(Value::as_str)
+            .unwrap_or("?")
+            .to_string();
+        let has_coords = obj.get("lat").is_some_and(Value::is_number)
+            && obj.get("lon").is_some_and(Value::is_number);
+        if has_coords {
+            continue;
+        }
+        let found = ["host", "id", "regionHint"]
+            .iter()
+            .filter_map(|key| obj.get(*key).and_then(Value::as_str))
+            .find_map(find_cloud_region);
+        match found {
+            Some(region) => {
+                obj.insert("lat".to_string(), region.lat.into());
+                obj.insert("lon".to_string(), region.lon.into());
+                annotated += 1;
+                eprintln!(
+                    "[ok] {} -> {} {} ({:.4}, {:.4})",
+                    id, region.provider, region.code, region.lat, region.lon
+                );
+            }
+            None => eprintln!("[??] {} no known cloud region in host/id/regionHint", id),
+        }
+    }
+
+    emit(path, &cfg, write)?;
+    if write {
+        eprintln!("annotated {} endpoint(s) in {}", annotated, path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,314 @@
+use lattice_core::{
+    EventRecord, SendErrors, ANCHOR_DOWN_EVENT, ENDPOINT_RECOVERED_EVENT, PATH_DOWN_EVENT,
+};
+#[cfg(windows)]
+use lattice_os_windows::ENOBUFS;
+#[cfg(unix)]
+use libc::ENOBUFS;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// First wait before reopening a socket after the path went down.
+const BACKOFF_INITIAL: Duration = Duration::from_secs(1);
+/// Longest wait between reconnect attempts.
+const BACKOFF_CAP: Duration = Duration::from_secs(300);
+
+/// Which side of an endpoint stopped working.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outage {
+    /// The local stack can't send: no route, interface down, address gone.
+    Path,
+    /// Sends go out but the anchor doesn't answer.
+    Anchor,
+}
+
+impl Outage {
+    /// A connected UDP socket reports an ICMP port unreachable as
+    /// ECONNREFUSED: the path worked and the reflector wasn't listening. A
+    /// TCP connection refused, reset, or closed by the far end is the same.
+    /// Every other send or socket error is the local path's.
+    pub fn of(err: &io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::UnexpectedEof => Outage::Anchor,
+            _ => Outage::Path,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Outage::Path => "path",
+            Outage::Anchor => "anchor",
+        }
+    }
+
+    fn event(self) -> &'static str {
+        match self {
+            Outage::Path => PATH_DOWN_EVENT,
+            Outage::Anchor => ANCHOR_DOWN_EVENT,
+        }
+    }
+}
+
+/// Why a send failed, for what the burst does next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendFailure {
+    /// The queue toward the interface is full. It drains on its own, so the
+    /// burst pauses and carries on rather than treating the path as down.
+    NoBuffer,
+    /// A firewall rule on this machine rejected the packet; retrying before
+    /// the reconnect backoff only gets it rejected again.
+    Denied,
+    Other,
+}
+
+impl SendFailure {
+    pub fn of(err: &io::Error) -> Self {
+        if err.raw_os_error() == Some(ENOBUFS) {
+            SendFailure::NoBuffer
+        } else if err.kind() == io::ErrorKind::PermissionDenied {
+            SendFailure::Denied
+        } else {
+            SendFailure::Other
+        }
+    }
+
+    pub fn count(self, errors: &mut SendErrors) {
+        match self {
+            SendFailure::NoBuffer => errors.no_buffer += 1,
+            SendFailure::Denied => errors.denied += 1,
+            SendFailure::Other => errors.other += 1,
+        }
+    }
+}
+
+struct Down {
+    outage: Outage,
+    since: Instant,
+    reconnects: u64,
+}
+
+/// An endpoint worker's outage state: turns send failures and silent bursts
+/// into down/recovered events, and spaces reconnects after a path failure
+/// with exponential backoff.
+pub struct Failover {
+    endpoint_id: String,
+    down: Option<Down>,
+    backoff: Duration,
+    retry_at: Option<Instant>,
+}
+
+impl Failover {
+    pub fn new(endpoint_id: &str) -> Self {
+        Failover {
+            endpoint_id: endpoint_id.to_string(),
+            down: None,
+            backoff: BACKOFF_INITIAL,
+            retry_at: None,
+        }
+    }
+
+    /// Whether a new socket may be opened yet.
+    pub fn may_connect(&self, now: Instant) -> bool {
+        self.retry_at.is_none_or(|at| now >= at)
+    }
+
+    /// The local end of the path changed, so the failure that set the backoff
+    /// may be gone: the next reconnect need not wait for it.
+    pub fn retry_now(&mut self) {
+        self.retry_at = None;
+    }
+
+    /// Counts a reconnect attempt toward the current outage.
+    pub fn reconnecting(&mut self) {
+        if let Some(down) = self.down.as_mut() {
+            down.reconnects += 1;
+        }
+    }
+
+    /// A send failed or the socket couldn't be opened. A path failure holds
+    /// off the next reconnect for the current backoff, then doubles it.
+    /// Returns the event to write when this starts an outage or changes its
+    /// kind.
+    pub fn failed(&mut self, err: &io::Error, now: Instant) -> Option<EventRecord> {
+        let outage = Outage::of(err);
+        let mut detail = serde_json::json!({
+            "errno": err.raw_os_error(),
+            "error": err.to_string(),
+        });
+        if outage == Outage::Path {
+            detail["retryInSeconds"] = self.backoff.as_secs().into();
+            self.retry_at = Some(now + self.backoff);
+            self.backoff = (self.backoff * 2).min(BACKOFF_CAP);
+        }
+        self.enter(outage, now, detail)
+    }
+
+    /// `bursts` in a row came back empty though every send went out.
+    pub fn silent(&mut self, bursts: usize, now: Instant) -> Option<EventRecord> {
+        self.enter(
+            Outage::Anchor,
+            now,
+            serde_json::json!({ "emptyBursts": bursts }),
+        )
+    }
+
+    /// A burst got replies: ends any outage and resets the backoff.
+    pub fn answered(&mut self, now: Instant) -> Option<EventRecord> {
+        self.backoff = BACKOFF_INITIAL;
+        self.retry_at = None;
+        let down = self.down.take()?;
+        Some(EventRecord::new(
+            ENDPOINT_RECOVERED_EVENT,
+            Some(self.endpoint_id.clone()),
+            serde_json::json!({
+                "outage": down.outage.as_str(),
+                "downSeconds": (now - down.since).as_secs_f64(),
+                "reconnects": down.reconnects,
+            }),
+        ))
+    }
+
+    fn enter(
+        &mut self,
+        outage: Outage,
+        now: Instant,
+        detail: serde_json::Value,
+    ) -> Option<EventRecord> {
+        match self.down.as_mut() {
+            Some(down) if down.outage == outage => return None,
+            Some(down) => down.outage = outage,
+            None => {
+                self.down = Some(Down {
+                    outage,
+                    since: now,
+                    reconnects: 0,
+                })
+            }
+        }
+        Some(EventRecord::new(
+            outage.event(),
+            Some(self.endpoint_id.clone()),
+            detail,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn os_error(errno: i32) -> io::Error {
+        io::Error::from_raw_os_error(errno)
+    }
+
+    fn path_error() -> io::Error {
+        io::Error::new(io::ErrorKind::NetworkUnreachable, "no route")
+    }
+
+    fn refused() -> io::Error {
+        io::Error::from(io::ErrorKind::ConnectionRefused)
+    }
+
+    fn retry_in(event: &EventRecord) -> u64 {
+        event.detail["retryInSeconds"].as_u64().unwrap()
+    }
+
+    #[test]
+    fn classifies_send_failures() {
+        let mut errors = SendErrors::default();
+        for (err, failure) in [
+            (os_error(ENOBUFS), SendFailure::NoBuffer),
+            #[cfg(unix)]
+            (os_error(libc::EPERM), SendFailure::Denied),
+            #[cfg(unix)]
+            (os_error(libc::EACCES), SendFailure::Denied),
+            (
+                io::Error::from(io::ErrorKind::PermissionDenied),
+                SendFailure::Denied,
+            ),
+            #[cfg(unix)]
+            (os_error(libc::ENETUNREACH), SendFailure::Other),
+            (refused(), SendFailure::Other),
+        ] {
+            assert_eq!(SendFailure::of(&err), failure, "{err}");
+            failure.count(&mut errors);
+        }
+        assert_eq!(errors.no_buffer, 1);
+        assert_eq!(errors.denied, if cfg!(unix) { 3 } else { 1 });
+        assert_eq!(errors.other, if cfg!(unix) { 2 } else { 1 });
+    }
+
+    #[test]
+    fn path_failures_back_off_doubling_up_to_the_cap() {
+        let mut failover = Failover::new("a");
+        let mut now = Instant::now();
+        let mut waits = Vec::new();
+        for _ in 0..12 {
+            let event = failover.failed(&path_error(), now);
+            let backoff = failover.retry_at.unwrap() - now;
+            assert!(!failover.may_connect(now));
+            assert!(!failover.may_connect(now + backoff - Duration::from_millis(1)));
+            assert!(failover.may_connect(now + backoff));
+            if let Some(event) = event {
+                assert_eq!(retry_in(&event), backoff.as_secs());
+            }
+            waits.push(backoff.as_secs());
+            now += backoff;
+        }
+        assert_eq!(waits, [1, 2, 4, 8, 16, 32, 64, 128, 256, 300, 300, 300]);
+
+        // An anchor that refuses leaves the backoff alone: the path works.
+        let mut failover = Failover::new("a");
+        failover.failed(&refused(), now);
+        assert!(failover.may_connect(now));
+        assert_eq!(failover.backoff, BACKOFF_INITIAL);
+
+        // A changed local address lifts the wait at once.
+        failover.failed(&path_error(), now);
+        assert!(!failover.may_connect(now));
+        failover.retry_now();
+        assert!(failover.may_connect(now));
+    }
+
+    #[test]
+    fn outage_is_reported_once_per_kind_and_ends_on_an_answer() {
+        let mut failover = Failover::new("a");
+        let start = Instant::now();
+        assert_eq!(Outage::of(&path_error()), Outage::Path);
+        for kind in [
+            io::ErrorKind::ConnectionRefused,
+            io::ErrorKind::ConnectionReset,
+            io::ErrorKind::UnexpectedEof,
+        ] {
+            assert_eq!(Outage::of(&io::Error::from(kind)), Outage::Anchor);
+        }
+
+        let down = failover.failed(&path_error(), start).unwrap();
+        assert_eq!(down.event, PATH_DOWN_EVENT);
+        assert_eq!(down.endpoint_id.as_deref(), Some("a"));
+        assert_eq!(retry_in(&down), 1);
+        assert!(failover.failed(&path_error(), start).is_none());
+        failover.reconnecting();
+        failover.reconnecting();
+        // The path is back but the anchor is not answering: a new kind.
+        let anchor = failover.silent(3, start + Duration::from_secs(5)).unwrap();
+        assert_eq!(anchor.event, ANCHOR_DOWN_EVENT);
+        assert_eq!(anchor.detail["emptyBursts"], 3);
+        assert!(failover.failed(&refused(), start).is_none());
+
+        let up = failover.answered(start + Duration::from_secs(10)).unwrap();
+        assert_eq!(up.event, ENDPOINT_RECOVERED_EVENT);
+        assert_eq!(up.detail["outage"], "anchor");
+        assert_eq!(up.detail["downSeconds"], 10.0);
+        assert_eq!(up.detail["reconnects"], 2);
+        assert!(failover.answered(start + Duration::from_secs(11)).is_none());
+
+        // Recovery resets the backoff: the next outage waits 1 s again.
+        let again = failover.failed(&path_error(), start).unwrap();
+        assert_eq!(again.event, PATH_DOWN_EVENT);
+        assert_eq!(retry_in(&again), 1);
+        assert_eq!(again.detail.get("emptyBursts"), None);
+    }
+}
@@ -0,0 +1,316 @@
+//! Probes over HTTPS, for networks that pass nothing but web traffic: each
+//! probe is POSTed to the reflector's `/v1/echo` and comes back as the
+//! response body, over one kept-alive TLS connection.
+
+use crate::tls::TlsStream;
+use lattice_core::{
+    parse_lato_packet, resolve_first_for_family, Packet, LATO_PACKET_LEN, MAX_STRAYS,
+};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Where the Go reflector answers HTTPS probes.
+pub const ECHO_PATH: &str = "/v1/echo";
+const READ_BUF_LEN: usize = 4096;
+/// Past this, a response's head is not one the reflector sent.
+const MAX_HEAD_LEN: usize = 8192;
+/// Error bodies are a line of text; echoes are one packet.
+const MAX_BODY_LEN: usize = 1024;
+const HTTP_OK: u16 = 200;
+const HTTP_TOO_MANY_REQUESTS: u16 = 429;
+
+/// One HTTP/1.1 response off the wire.
+#[derive(Debug, PartialEq, Eq)]
+struct Response {
+    status: u16,
+    body: Vec<u8>,
+}
+
+/// Splits the first whole response off `buf`, returning it and its length
+/// on the wire, or `None` until all of it has arrived. Only bodies sized by
+/// `Content-Length` are read, which is all the reflector sends.
+fn parse_response(buf: &[u8]) -> Result<Option<(Response, usize)>, String> {
+    let Some(head_len) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+        if buf.len() > MAX_HEAD_LEN {
+            return Err("response head too long".into());
+        }
+        return Ok(None);
+    };
+    let head = std::str::from_utf8(&buf[..head_len]).map_err(|_| "response head is not text")?;
+    let mut lines = head.split("\r\n");
+    let status_line = lines.next().unwrap_or_default();
+    let mut parts = status_line.splitn(3, ' ');
+    let status = match (parts.next(), parts.next()) {
+        (Some(version), Some(code)) if version.starts_with("HTTP/1.") => code
+            .parse::<u16>()
+            .map_err(|_| format!("bad status line {status_line:?}"))?,
+        _ => return Err(format!("bad status line {status_line:?}")),
+    };
+    let mut content_length = None;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(format!("bad header line {line:?}"));
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            let len = value
+                .parse::<usize>()
+                .map_err(|_| format!("bad Content-Length {value:?}"))?;
+            if len > MAX_BODY_LEN {
+                return Err(format!("{len}-byte response body"));
+            }
+            content_length = Some(len);
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            return Err(format!("unexpected Transfer-Encoding {value:?}"));
+        }
+    }
+    let body_len = content_length.ok_or("response without Content-Length")?;
+    let total = head_len + 4 + body_len;
+    if buf.len() < total {
+        return Ok(None);
+    }
+    let body = buf[head_len + 4..total].to_vec();
+    Ok(Some((Response { status, body }, total)))
+}
+
+/// Probes an endpoint's HTTPS echo. There is no kernel timestamp: the RTT
+/// runs from `send_mono_ns` to when the read that completed the response
+/// returned, and includes the reflector's HTTP handling.
+pub struct HttpsProber {
+    stream: TlsStream,
+    request_head: String,
+    clock: fn() -> u64,
+    /// Requests whose response has not come yet, this one included.
+    in_flight: usize,
+    /// Bytes read past the last whole response.
+    pending: Vec<u8>,
+    strays: Vec<Packet>,
+}
+
+impl HttpsProber {
+    /// Connects from `source_port` (0 lets the OS pick) and runs the TLS
+    /// handshake, each giving up after `timeout`. The certificate must name
+    /// `server_name` and chain to `ca_file`, or to the system roots when
+    /// there is none. `clock` reads monotonic nanoseconds.
+    #[allow(clippy::too_many_arguments)]
+    pub fn connect(
+        host: &str,
+        port: u16,
+        server_name: &str,
+        ca_file: Option<&Path>,
+        bind_ip: Option<IpAddr>,
+        source_port: u16,
+        timeout: Duration,
+        clock: fn() -> u64,
+    ) -> io::Result<Self> {
+        let addr = resolve_first_for_family(host, port, bind_ip)?;
+        let domain = match addr {
+            SocketAddr::V4(_) => Domain::IPV4,
+            SocketAddr::V6(_) => Domain::IPV6,
+        };
+        let socket = Socket::new(domain, Type::STREAM, Some(Protocol::TCP))?;
+        if bind_ip.is_some() || source_port != 0 {
+            socket.set_reuse_address(true)?;
+            let ip = bind_ip.unwrap_or(match addr {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            });
+            socket.bind(&SocketAddr::new(ip, source_port).into())?;
+        }
+        socket.connect_timeout(&addr.into(), timeout)?;
+        socket.set_nodelay(true)?;
+        socket.set_read_timeout(Some(timeout))?;
+        socket.set_write_timeout(Some(timeout))?;
+        let stream = TlsStream::connect(socket, server_name, ca_file)?;
+        let authority = match (server_name.parse::<Ipv6Addr>(), port) {
+            (Ok(_), 443) => format!("[{server_name}]"),
+            (Ok(_), _) => format!("[{server_name}]:{port}"),
+            (Err(_), 443) => server_name.to_string(),
+            (Err(_), _) => format!("{server_name}:{port}"),
+        };
+        Ok(Self {
+            stream,
+            request_head: format!(
+                "POST {ECHO_PATH} HTTP/1.1\r\nHost: {authority}\r\n\
+                 Content-Type: application/octet-stream\r\nContent-Length: {LATO_PACKET_LEN}\r\n\r\n"
+            ),
+            clock,
+            in_flight: 0,
+            pending: Vec::with_capacity(READ_BUF_LEN),
+            strays: Vec::new(),
+        })
+    }
+
+    /// POSTs `msg` and waits for its echo, in whole nanoseconds. Echoes of
+    /// earlier probes that come first are kept as strays; a rate-limited
+    /// probe counts as lost.
+    pub fn send_and_receive_rtt(
+        &mut self,
+        msg: &[u8],
+        _send_realtime_ns: u64,
+        send_mono_ns: u64,
+        timeout: Duration,
+    ) -> io::Result<Option<u64>> {
+        let probe =
+            parse_lato_packet(msg).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut request = Vec::with_capacity(self.request_head.len() + msg.len());
+        request.extend_from_slice(self.request_head.as_bytes());
+        request.extend_from_slice(msg);
+        self.stream.write_all(&request)?;
+        self.in_flight += 1;
+
+        let deadline = Instant::now() + timeout;
+        let mut recv_mono_ns: u64 = 0;
+        let mut buf = [0u8; READ_BUF_LEN];
+        loop {
+            while let Some((response, len)) = parse_response(&self.pending)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            {
+                self.pending.drain(..len);
+                self.in_flight = self.in_flight.saturating_sub(1);
+                match response.status {
+                    HTTP_OK => {}
+                    // Responses come back in request order, so with none
+                    // left outstanding this refusal was of this probe.
+                    HTTP_TOO_MANY_REQUESTS if self.in_flight == 0 => return Ok(None),
+                    HTTP_TOO_MANY_REQUESTS => continue,
+                    status => {
+                        return Err(io::Error::other(format!(
+                            "reflector answered HTTP {status}: {}",
+                            String::from_utf8_lossy(&response.body).trim()
+                        )))
+                    }
+                }
+                match parse_lato_packet(&response.body) {
+                    Ok(reply) if reply.echoes(&probe) => {
+                        return Ok(Some(recv_mono_ns.saturating_sub(send_mono_ns)));
+                    }
+                    Ok(reply) => {
+                        if self.strays.len() < MAX_STRAYS {
+                            self.strays.push(reply);
+                        }
+                    }
+                    Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+                }
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            self.stream
+                .socket()
+                .set_read_timeout(Some(deadline - now))?;
+            match self.stream.read(&mut buf) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "reflector closed the connection",
+                    ))
+                }
+                Ok(n) => {
+                    recv_mono_ns = (self.clock)();
+                    self.pending.extend_from_slice(&buf[..n]);
+                }
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Ok(None)
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.stream
+            .socket()
+            .local_addr()?
+            .as_socket()
+            .ok_or_else(|| io::Error::other("non-IP socket"))
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream
+            .socket()
+            .peer_addr()?
+            .as_socket()
+            .ok_or_else(|| io::Error::other("non-IP socket"))
+    }
+
+    /// Echoes of probes that had already timed out, oldest first; unverified.
+    pub fn take_strays(&mut self) -> Vec<Packet> {
+        std::mem::take(&mut self.strays)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ok_response(body: &[u8]) -> Vec<u8> {
+        let mut wire = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\n\
+             Cache-Control: no-store\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        wire.extend_from_slice(body);
+        wire
+    }
+
+    #[test]
+    fn parses_an_echo_only_once_all_of_it_arrived() {
+        let body = [7u8; LATO_PACKET_LEN];
+        let wire = ok_response(&body);
+        for cut in [0, 10, wire.len() - LATO_PACKET_LEN, wire.len() - 1] {
+            assert_eq!(parse_response(&wire[..cut]), Ok(None), "cut at {cut}");
+        }
+        let (response, len) = parse_response(&wire).unwrap().unwrap();
+        assert_eq!(len, wire.len());
+        assert_eq!(
+            response,
+            Response {
+                status: 200,
+                body: body.to_vec()
+            }
+        );
+    }
+
+    #[test]
+    fn splits_back_to_back_responses() {
+        let mut wire =
+            b"HTTP/1.1 429 Too Many Requests\r\ncontent-length: 13\r\n\r\nrate limited\n".to_vec();
+        let first_len = wire.len();
+        wire.extend(ok_response(&[1u8; LATO_PACKET_LEN]));
+        let (first, len) = parse_response(&wire).unwrap().unwrap();
+        assert_eq!((first.status, len), (429, first_len));
+        let (second, _) = parse_response(&wire[len..]).unwrap().unwrap();
+        assert_eq!(second.status, 200);
+        assert_eq!(second.body, [1u8; LATO_PACKET_LEN]);
+    }
+
+    #[test]
+    fn rejects_what_the_reflector_never_sends() {
+        for wire in [
+            &b"SSH-2.0-OpenSSH\r\n\r\n"[..],
+            b"HTTP/1.1 200 OK\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+            b"HTTP/1.1 200 OK\r\nContent-Length: 99999\r\n\r\n",
+            b"HTTP/1.1 OK\r\nContent-Length: 0\r\n\r\n",
+        ] {
+            assert!(
+                parse_response(wire).is_err(),
+                "{:?}",
+                String::from_utf8_lossy(wire)
+            );
+        }
+        assert!(parse_response(&[b'x'; MAX_HEAD_LEN + 1]).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+use crate::write_atomic;
+use lattice_core::{bytes_to_hex, expand_endpoint_templates, SECRET_BYTES};
+use rand::RngCore;
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub fn generate_secret_hex() -> String {
+    let mut bytes = [0u8; SECRET_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes_to_hex(&bytes)
+}
+
+/// `lattice keygen`: prints a fresh secret, optionally writing it into a config.
+pub fn keygen(config: Option<&Path>) -> io::Result<()> {
+    let secret = generate_secret_hex();
+    if let Some(path) = config {
+        let updated = with_secret(path, &secret)?;
+        write_atomic(path, updated.as_bytes())?;
+        println!("Wrote secretHex to {}", path.display());
+    }
+    println!("secretHex: {}", secret);
+    println!("export LATTICE_SECRET_HEX={}", secret);
+    Ok(())
+}
+
+/// `lattice rotate-keys`: replaces `secretHex` in the config and every listed
+/// anchor registry. Every file is rendered and written to a temp file beside
+/// it before any is replaced, so a bad input or a failed write leaves
+/// everything untouched; only the final renames can stop partway.
+pub fn rotate(config: &Path, registries: &[PathBuf]) -> io::Result<()> {
+    let secret = generate_secret_hex();
+    let mut staged: Vec<(PathBuf, String)> = Vec::new();
+    for path in std::iter::once(config).chain(registries.iter().map(PathBuf::as_path)) {
+        staged.push((path.to_path_buf(), with_secret(path, &secret)?));
+    }
+    let mut written: Vec<(&PathBuf, PathBuf)> = Vec::new();
+    for (path, text) in &staged {
+        let tmp = path.with_extension("tmp");
+        if let Err(err) = fs::write(&tmp, text) {
+            let _ = fs::remove_file(&tmp);
+            for (_, tmp) in &written {
+                let _ = fs::remove_file(tmp);
+            }
+            return Err(err);
+        }
+        written.push((path, tmp));
+    }
+    for (path, tmp) in &written {
+        fs::rename(tmp, path)?;
+        println!("Updated secretHex in {}", path.display());
+    }
+
+    println!();
+    println!("Reflector-side steps (each anchor must switch before clients resume):");
+    println!(
+        "  1. Set LATTICE_SECRET_HEX={} in /etc/lattice/reflector.env",
+        secret
+    );
+    println!("  2. systemctl restart lattice-reflector on:");
+    for host in endpoint_hosts(config)? {
+        println!("     - {}", host);
+    }
+    println!("  3. Restart lattice clients using {}", config.display());
+    Ok(())
+}
+
+fn with_secret(path: &Path, secret: &str) -> io::Result<String> {
+    let data = fs::read(path)?;
+    let mut doc: Value =
+        serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let obj = doc.as_object_mut().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not a JSON object", path.display()),
+        )
+    })?;
+    if let Some(current) = obj.get("secretHex").and_then(Value::as_str) {
+        if current.contains("${") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "secretHex in {} is injected from the environment ({}); set that variable instead",
+                    path.display(),
+                    current
+                ),
+            ));
+        }
+    }
+    obj.insert("secretHex".to_string(), Value::String(secret.to_string()));
+    serde_json::to_string_pretty(&doc).map_err(io::Error::other)
+}
+
+fn endpoint_hosts(path: &Path) -> io::Result<Vec<String>> {
+    let data = fs::read(path)?;
+    let mut doc: Value =
+        serde_json::from_slice(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    expand_endpoint_templates(&mut doc)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut hosts: Vec<String> = doc
+        .get("endpoints")
+        .and_then(Value::as_array)
+        .map(|eps| {
+            eps.iter()
+                .filter_map(|ep| ep.get("host").and_then(Value::as_str))
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    hosts.sort();
+    hosts.dedup();
+    Ok(hosts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::temp_dir;
+    use serde_json::json;
+
+    fn read(path: &Path) -> Value {
+        serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn secrets_are_fresh_hex() {
+        let secret = generate_secret_hex();
+        assert_eq!(secret.len(), SECRET_BYTES * 2);
+        assert!(secret.bytes().all(|b| b.is_ascii_hexdigit()));
+        assert_ne!(secret, generate_secret_hex());
+    }
+
+    #[test]
+    fn rotate_rewrites_config_and_registries_with_one_secret() {
+        let dir = temp_dir("keys-rotate");
+        let config = dir.join("config.json");
+        let registry = dir.join("anchors.json");
+        fs::write(
+            &config,
+            json!({
+                "secretHex": "00",
+                "endpoints": [
+                    { "id": "a", "host": "b.example", "port": 9000 },
+                    { "id": "b", "host": "a.example", "port": 9000 },
+                    { "id": "c", "host": "a.example", "port": 9001 },
+                ],
+            })
+            .to_string(),
+        )
+        .unwrap();
+        fs::write(&registry, json!({ "anchors": [] }).to_string()).unwrap();
+
+        assert_eq!(endpoint_hosts(&config).unwrap(), ["a.example", "b.example"]);
+        rotate(&config, std::slice::from_ref(&registry)).unwrap();
+        let secret = read(&config)["secretHex"].clone();
+        assert_ne!(secret, "00");
+        assert_eq!(read(&registry)["secretHex"], secret);
+        assert_eq!(read(&registry)["anchors"], json!([]));
+        assert_eq!(read(&config)["endpoints"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn a_bad_file_leaves_every_file_untouched() {
+        let dir = temp_dir("keys-untouched");
+        let config = dir.join("config.json");
+        let injected = dir.join("injected.json");
+        let listed = dir.join("list.json");
+        let original = json!({ "secretHex": "00", "endpoints": [] }).to_string();
+        fs::write(&config, &original).unwrap();
+        fs::write(&injected, r#"{ "secretHex": "${LATTICE_SECRET_HEX}" }"#).unwrap();
+        fs::write(&listed, "[]").unwrap();
+
+        let err = rotate(&config, std::slice::from_ref(&injected)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("${LATTICE_SECRET_HEX}"), "{err}");
+        let err = rotate(&config, &[listed]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read_to_string(&config).unwrap(), original);
+        assert!(with_secret(&injected, "11").is_err());
+    }
+
+    #[test]
+    fn a_failed_write_leaves_every_file_untouched() {
+        let dir = temp_dir("keys-failed-write");
+        let config = dir.join("config.json");
+        let registry = dir.join("anchors.json");
+        let original = json!({ "secretHex": "00", "endpoints": [] }).to_string();
+        fs::write(&config, &original).unwrap();
+        fs::write(&registry, &original).unwrap();
+        // The registry's temp file cannot be written over a directory.
+        fs::create_dir(dir.join("anchors.tmp")).unwrap();
+
+        assert!(rotate(&config, std::slice::from_ref(&registry)).is_err());
+        assert_eq!(fs::read_to_string(&config).unwrap(), original);
+        assert_eq!(fs::read_to_string(&registry).unwrap(), original);
+        assert!(!dir.join("config.tmp").exists());
+    }
+}
@@ -0,0 +1,250 @@
+use crate::{is_loopback_host, os};
+use lattice_core::{haversine_km, Config};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Light in fiber covers about 200 km per millisecond.
+const FIBER_KM_PER_MS: f64 = 200.0;
+/// Typical ratio of routed path length to great-circle distance.
+const TYPICAL_PATH_STRETCH: f64 = 1.5;
+/// A location fit needs at least this many anchors with coordinates.
+const MIN_ESTIMATE_ANCHORS: usize = 3;
+pub(crate) const LAT_MAX: f64 = 90.0;
+pub(crate) const LON_MAX: f64 = 180.0;
+const MS_PER_SEC: u64 = 1000;
+
+/// A config that loads and validates but will likely not do what was meant.
+pub struct Lint {
+    pub problem: String,
+    pub fix: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (fix: {})", self.problem, self.fix)
+    }
+}
+
+fn lint(problem: String, fix: impl Into<String>) -> Lint {
+    Lint {
+        problem,
+        fix: fix.into(),
+    }
+}
+
+/// Checks beyond `validate_config`: each is a warning, never fatal. Loopback
+/// endpoints are test targets and skip the coordinate checks.
+pub fn lint_config(cfg: &Config) -> Vec<Lint> {
+    let mut out = Vec::new();
+
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for ep in &cfg.endpoints {
+        *seen.entry(ep.id.as_str()).or_default() += 1;
+    }
+    let mut dupes: Vec<(&str, usize)> = seen.into_iter().filter(|(_, n)| *n > 1).collect();
+    dupes.sort();
+    for (id, n) in dupes {
+        out.push(lint(
+            format!("endpoint id '{id}' is used {n} times; their bursts merge into one anchor in analysis"),
+            "give each endpoint a unique id",
+        ));
+    }
+
+    let remote: Vec<_> = cfg
+        .endpoints
+        .iter()
+        .filter(|ep| !is_loopback_host(&ep.host))
+        .collect();
+    let mut placed = Vec::new();
+    for ep in &remote {
+        match (ep.lat, ep.lon) {
+            (Some(lat), Some(lon)) if lat.abs() <= LAT_MAX && lon.abs() <= LON_MAX => {
+                placed.push((ep.id.as_str(), lat, lon))
+            }
+            (Some(lat), Some(lon)) => out.push(lint(
+                format!("endpoint {}: lat/lon {lat}, {lon} is off the globe", ep.id),
+                "use decimal degrees, lat within ±90 and lon within ±180",
+            )),
+            _ => out.push(lint(
+                format!(
+                    "endpoint {} has no lat/lon; it gets no distance bound and no say in estimates",
+                    ep.id
+                ),
+                "add \"lat\" and \"lon\" (decimal degrees) for the reflector's location",
+            )),
+        }
+    }
+    if !remote.is_empty() && placed.len() < MIN_ESTIMATE_ANCHORS {
+        out.push(lint(
+            format!(
+                "only {} endpoint(s) have coordinates; a location estimate needs at least {}",
+                placed.len(),
+                MIN_ESTIMATE_ANCHORS
+            ),
+            "add lat/lon to more endpoints, ideally spread in different directions",
+        ));
+    }
+
+    let burst_ms = cfg
+        .spacing_ms
+        .saturating_mul(cfg.samples_per_endpoint as u64);
+    let interval_ms = cfg.interval_seconds.saturating_mul(MS_PER_SEC);
+    if burst_ms > interval_ms {
+        out.push(lint(
+            format!(
+                "a burst takes {burst_ms}ms (spacingMs {} x samplesPerEndpoint {}) but intervalSeconds is {}; bursts will run back to back",
+                cfg.spacing_ms, cfg.samples_per_endpoint, cfg.interval_seconds
+            ),
+            format!(
+                "raise intervalSeconds to at least {}, or lower spacingMs or samplesPerEndpoint",
+                burst_ms.div_ceil(MS_PER_SEC)
+            ),
+        ));
+    }
+
+    // Wherever the prober is, it is at least half the widest anchor pair away
+    // from one of them, so that anchor's RTT is at least the pair's distance
+    // at fiber speed; routing adds more.
+    let widest = placed
+        .iter()
+        .enumerate()
+        .flat_map(|(i, a)| {
+            placed[i + 1..]
+                .iter()
+                .map(move |b| (haversine_km(a.1, a.2, b.1, b.2), a.0, b.0))
+        })
+        .max_by(|x, y| x.0.total_cmp(&y.0));
+    if let Some((km, a, b)) = widest {
+        let floor_ms = km / FIBER_KM_PER_MS;
+        let realistic_ms = (floor_ms * TYPICAL_PATH_STRETCH).ceil() as u64;
+        if (cfg.timeout_ms as f64) < floor_ms * TYPICAL_PATH_STRETCH {
+            out.push(lint(
+                format!(
+                    "timeoutMs {} is shorter than a realistic RTT to the farthest anchor: {a} and {b} are {km:.0} km apart, so one of them is at least {floor_ms:.0}ms away by fiber from anywhere; slower replies count as loss",
+                    cfg.timeout_ms
+                ),
+                format!("set timeoutMs to at least {realistic_ms}"),
+            ));
+        }
+    }
+
+    let keepalives = std::iter::once(("", cfg.keepalive_seconds)).chain(
+        cfg.probe_paths
+            .iter()
+            .map(|p| (p.id.as_str(), p.keepalive_seconds)),
+    );
+    for (path, seconds) in keepalives {
+        let Some(seconds) = seconds.filter(|&s| s >= cfg.interval_seconds) else {
+            continue;
+        };
+        let whose = if path.is_empty() {
+            String::new()
+        } else {
+            format!("probe path {path}: ")
+        };
+        out.push(lint(
+            format!(
+                "{whose}keepaliveSeconds {seconds} is not shorter than intervalSeconds {}; no keepalive is ever sent",
+                cfg.interval_seconds
+            ),
+            "set keepaliveSeconds below intervalSeconds, e.g. under the NAT's UDP timeout (often 30s)",
+        ));
+    }
+
+    for path in &cfg.probe_paths {
+        let Some(iface) = &path.bind_interface else {
+            continue;
+        };
+        if path.bind_ip.is_none() && os::iface_ips(iface).map_or(true, |ips| ips.is_empty()) {
+            out.push(lint(
+                format!("probe path {} binds to interface {iface}, which has no addresses on this host", path.id),
+                "check the name with `ip addr` (Linux) or `ifconfig` (macOS), or set bindIp instead",
+            ));
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn config(endpoints: Value, extra: Value) -> Config {
+        let mut cfg = json!({
+            "secretHex": "00",
+            "endpoints": endpoints,
+            "samplesPerEndpoint": 10,
+            "spacingMs": 10,
+            "timeoutMs": 1000,
+            "intervalSeconds": 10,
+            "outputPath": "out.jsonl",
+            "physicsMismatchThresholdMs": 5.0,
+        });
+        cfg.as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(cfg).unwrap()
+    }
+
+    fn anchor(id: &str, lat: f64, lon: f64) -> Value {
+        json!({ "id": id, "host": format!("{id}.example"), "port": 9000, "lat": lat, "lon": lon })
+    }
+
+    fn problems(cfg: &Config) -> Vec<String> {
+        lint_config(cfg).into_iter().map(|l| l.problem).collect()
+    }
+
+    #[test]
+    fn a_spread_config_has_nothing_to_say() {
+        let cfg = config(
+            json!([
+                anchor("fra", 50.1, 8.7),
+                anchor("lon", 51.5, -0.1),
+                anchor("ams", 52.4, 4.9),
+                { "id": "local", "host": "127.0.0.1", "port": 9000 },
+            ]),
+            json!({ "keepaliveSeconds": 5 }),
+        );
+        assert!(problems(&cfg).is_empty(), "{:?}", problems(&cfg));
+    }
+
+    #[test]
+    fn flags_ids_coordinates_timing_and_keepalives() {
+        let cfg = config(
+            json!([
+                anchor("fra", 50.1, 8.7),
+                anchor("fra", 35.7, 139.7),
+                anchor("off", 120.0, 8.7),
+                { "id": "bare", "host": "bare.example", "port": 9000 },
+            ]),
+            json!({
+                "spacingMs": 2000,
+                "timeoutMs": 20,
+                "keepaliveSeconds": 10,
+                "probePaths": [{ "id": "wan", "keepaliveSeconds": 60 }],
+            }),
+        );
+        let found = problems(&cfg);
+        let has = |needle: &str| found.iter().any(|p| p.contains(needle));
+        assert!(has("endpoint id 'fra' is used 2 times"), "{found:?}");
+        assert!(has("endpoint off: lat/lon 120, 8.7 is off the globe"));
+        assert!(has("endpoint bare has no lat/lon"));
+        assert!(has("only 2 endpoint(s) have coordinates"));
+        assert!(has("a burst takes 20000ms"));
+        assert!(has("timeoutMs 20 is shorter than a realistic RTT"));
+        assert!(has("keepaliveSeconds 10 is not shorter"));
+        assert!(has("probe path wan: keepaliveSeconds 60"));
+        assert_eq!(found.len(), 8, "{found:?}");
+
+        let burst = lint_config(&cfg)
+            .into_iter()
+            .find(|l| l.problem.starts_with("a burst"))
+            .unwrap();
+        assert_eq!(
+            burst.fix,
+            "raise intervalSeconds to at least 20, or lower spacingMs or samplesPerEndpoint"
+        );
+    }
+}
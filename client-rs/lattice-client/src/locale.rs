@@ -0,0 +1,93 @@
+use lattice_core::HostLocale;
+use std::env;
+use std::fs;
+
+/// Zone databases live under a `zoneinfo` directory on Linux
+/// (`/usr/share/zoneinfo`) and macOS (`/var/db/timezone/zoneinfo`).
+const ZONEINFO_DIR: &str = "zoneinfo/";
+const LOCALTIME_LINK: &str = "/etc/localtime";
+/// Debian and Ubuntu also keep the zone name here.
+const TIMEZONE_FILE: &str = "/etc/timezone";
+const LOCALE_VARS: [&str; 3] = ["LC_ALL", "LC_TIME", "LANG"];
+const SECS_PER_MIN: i64 = 60;
+
+/// The host's timezone and locale as configured. Everything here is under the
+/// user's control, so the analyzer treats it as supporting evidence only.
+pub fn host_locale() -> HostLocale {
+    HostLocale {
+        timezone: timezone_name(),
+        utc_offset_minutes: utc_offset_minutes(),
+        locale: LOCALE_VARS
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|v| !v.is_empty()),
+    }
+}
+
+fn timezone_name() -> Option<String> {
+    if let Some(name) = env::var("TZ").ok().as_deref().and_then(tz_zone_name) {
+        return Some(name);
+    }
+    if let Ok(name) = fs::read_to_string(TIMEZONE_FILE) {
+        let name = name.trim();
+        if !name.is_empty() {
+            return Some(name.to_string());
+        }
+    }
+    let target = fs::read_link(LOCALTIME_LINK).ok()?;
+    let target = target.to_string_lossy();
+    target
+        .split_once(ZONEINFO_DIR)
+        .map(|(_, name)| name.to_string())
+}
+
+/// `TZ` names a zone outright (`Europe/Paris`), or a zone file by path,
+/// optionally behind glibc's `:` prefix.
+fn tz_zone_name(tz: &str) -> Option<String> {
+    let tz = tz.trim_start_matches(':');
+    let name = tz.split_once(ZONEINFO_DIR).map_or(tz, |(_, name)| name);
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+#[cfg(unix)]
+fn utc_offset_minutes() -> Option<i32> {
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return None;
+        }
+        Some((tm.tm_gmtoff as i64 / SECS_PER_MIN) as i32)
+    }
+}
+
+#[cfg(windows)]
+fn utc_offset_minutes() -> Option<i32> {
+    crate::os::utc_offset_minutes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tz_names_a_zone_or_a_zone_file() {
+        for (tz, name) in [
+            ("Europe/Paris", Some("Europe/Paris")),
+            (":America/New_York", Some("America/New_York")),
+            ("/usr/share/zoneinfo/Asia/Tokyo", Some("Asia/Tokyo")),
+            (":/var/db/timezone/zoneinfo/UTC", Some("UTC")),
+            ("", None),
+            (":", None),
+        ] {
+            assert_eq!(tz_zone_name(tz).as_deref(), name, "{tz:?}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn utc_offset_is_within_a_day() {
+        let offset = utc_offset_minutes().unwrap();
+        assert!(offset.abs() <= 14 * 60, "{offset}");
+    }
+}
@@ -0,0 +1,90 @@
+use std::fs;
+use uuid::Uuid;
+
+/// systemd's id, then the older D-Bus copy of it.
+#[cfg(target_os = "linux")]
+const MACHINE_ID_FILES: [&str; 2] = ["/etc/machine-id", "/var/lib/dbus/machine-id"];
+#[cfg(target_os = "macos")]
+const MACHINE_ID_FILES: [&str; 0] = [];
+#[cfg(windows)]
+const MACHINE_ID_FILES: [&str; 0] = [];
+#[cfg(target_os = "macos")]
+const IOREG_UUID_KEY: &str = "\"IOPlatformUUID\"";
+/// Set by Windows setup, one per install.
+#[cfg(windows)]
+const MACHINE_GUID_KEY: &str = r"HKLM\SOFTWARE\Microsoft\Cryptography";
+#[cfg(windows)]
+const MACHINE_GUID_VALUE: &str = "MachineGuid";
+/// Hashes the OS id, so a session never carries the raw value other software
+/// on the host may also report.
+const MACHINE_ID_NAMESPACE: Uuid = Uuid::from_u128(0x5f0c_19d4_7b2e_4c61_9a3f_2d8e_6b10_c7a5);
+
+/// Stable name for this host across sessions, for grouping a fleet's
+/// captures: `machineId` from the config as written, otherwise a hash of the
+/// OS machine id. `None` when the OS has none to offer.
+pub fn machine_id(configured: Option<&str>) -> Option<String> {
+    if let Some(id) = configured.filter(|id| !id.is_empty()) {
+        return Some(id.to_string());
+    }
+    let raw = os_machine_id()?;
+    Some(Uuid::new_v5(&MACHINE_ID_NAMESPACE, raw.as_bytes()).to_string())
+}
+
+fn os_machine_id() -> Option<String> {
+    let from_file = MACHINE_ID_FILES
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .map(|text| text.trim().to_string())
+        .find(|id| !id.is_empty());
+    from_file.or_else(platform_uuid)
+}
+
+#[cfg(target_os = "macos")]
+fn platform_uuid() -> Option<String> {
+    let out = std::process::Command::new("ioreg")
+        .args(["-rd1", "-c", "IOPlatformExpertDevice"])
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&out.stdout);
+    let line = text.lines().find(|l| l.contains(IOREG_UUID_KEY))?;
+    let value = line.rsplit('=').next()?.trim().trim_matches('"');
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// `reg query` prints `    MachineGuid    REG_SZ    <guid>`.
+#[cfg(windows)]
+fn platform_uuid() -> Option<String> {
+    let out = std::process::Command::new("reg")
+        .args(["query", MACHINE_GUID_KEY, "/v", MACHINE_GUID_VALUE])
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&out.stdout);
+    let line = text
+        .lines()
+        .find(|l| l.trim_start().starts_with(MACHINE_GUID_VALUE))?;
+    let value = line.split_whitespace().last()?;
+    (value != MACHINE_GUID_VALUE).then(|| value.to_string())
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn platform_uuid() -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configured_id_wins_and_the_os_id_is_hashed() {
+        assert_eq!(machine_id(Some("lab-3")).as_deref(), Some("lab-3"));
+        // An empty `machineId` is as good as none.
+        assert_eq!(machine_id(Some("")), machine_id(None));
+        if let Some(id) = machine_id(None) {
+            let uuid = Uuid::parse_str(&id).unwrap();
+            assert_eq!(uuid.get_version_num(), 5);
+            assert_ne!(Some(id.clone()), os_machine_id());
+            assert_eq!(machine_id(None), Some(id));
+        }
+    }
+}
@@ -1,74 +1,698 @@
 use lattice_core::{
-    build_packet, hex_to_bytes, now_unix_ms, physics_notes, summarize, BurstRecord, Config, ProbePath,
-    UtunInterface,
+    active_blackout, hampel_filter_ns, now_unix_ms, ns_to_ms, physics_notes, summarize,
+    BloatSource, Bufferbloat, BurstRecord, CompiledBlackout, Config, EcnCounts, EstimateRecord,
+    EventRecord, Histogram, IfaceTraffic, LogRotation, PrivacyMode, ProbePath, ReflectorClock,
+    ResponsivenessRecord, RotateEvery, SampleLegs, Secret, SendErrors, TimestampSource, Transport,
+    UtunInterface, BIND_ADDRESS_CHANGED_EVENT, BUSY_IFACE_BYTES_PER_SEC, CONFIG_RELOADED_EVENT,
+    DESTINATION_CHANGED_EVENT, LATO_V2_VERSION, LATO_VERSION, MIN_SECRET_BYTES, SESSION_END_EVENT,
+    SESSION_START_EVENT,
 };
-use rand::Rng;
+use std::collections::HashMap;
 use std::env;
-use std::fs::{self, File};
-use std::io::{self, BufWriter, Write};
-use std::path::PathBuf;
-use std::sync::{mpsc, Arc};
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, TryRecvError};
+use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
-use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use uuid::Uuid;
 
-#[cfg(target_os = "macos")]
-use lattice_os_macos as os;
 #[cfg(target_os = "linux")]
 use lattice_os_linux as os;
+#[cfg(target_os = "macos")]
+use lattice_os_macos as os;
+#[cfg(windows)]
+use lattice_os_windows as os;
+#[cfg(unix)]
+use libc::{IFF_LOOPBACK, IFF_MULTICAST, IFF_POINTOPOINT, IFF_RUNNING, IFF_UP};
+#[cfg(windows)]
+use os::{IFF_LOOPBACK, IFF_MULTICAST, IFF_POINTOPOINT, IFF_RUNNING, IFF_UP};
+
+mod anchors;
+mod api;
+mod bloat;
+mod cli;
+mod clock;
+mod doctor;
+mod endpoints;
+mod failover;
+mod https;
+mod keys;
+mod lint;
+mod locale;
+mod machine;
+mod power;
+mod privacy;
+mod reload;
+mod resolver;
+mod responsiveness;
+mod rotate;
+mod selffloor;
+mod sequence;
+mod state;
+mod status;
+#[cfg(test)]
+mod testutil;
+mod tls;
+mod transport;
+
+use clap::{CommandFactory, Parser};
+use cli::{BaselineArgs, Cli, Command, PairArgs, ProbeArgs};
+use failover::{Outage, SendFailure};
+use lattice_analysis::Calibration;
+use lattice_analyze::rolling::RollingEstimator;
+use privacy::FeatureLog;
+use resolver::Resolver;
+use rotate::LogFile;
+use state::{StateStore, WorkerState};
+use status::Status;
+use transport::{describe_transports, Prober, TransportLadder};
 
+const OWN_TARGETS_FLAG: &str = "--i-own-these-targets";
 const RECONNECT_EMPTY_BURSTS: usize = 2;
 const RECONNECT_INTERVAL_BURSTS: usize = 6;
+/// Pause after a send hits a full queue (ENOBUFS); it doubles with each
+/// one in a row.
+const NO_BUFFER_PAUSE: Duration = Duration::from_millis(10);
+/// Full-queue sends in a row a burst rides out before it gives up and the
+/// path counts as down.
+const NO_BUFFER_RETRIES: u32 = 3;
+/// Most source ports one endpoint is probed from.
+const MAX_ECMP_PORTS: usize = 16;
+const SEGMENT_START_EVENT: &str = "segment_start";
+const BASELINE_SEGMENT: &str = "baseline";
+const SESSION_SEGMENT: &str = "session";
+const BASELINE_TUNNEL_NOTE: &str = "baseline: tunnel active during burst; not counted";
+const MS_PER_SEC: f64 = 1000.0;
+/// Longest a worker sleeps between bursts without checking for Ctrl-C.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(100);
 
 fn main() -> io::Result<()> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: lattice-client <config.json>");
-        std::process::exit(1);
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Probe(args)) => probe(&args, &CaptureMode::Probe),
+        Some(Command::Baseline(args)) => baseline(&args),
+        Some(Command::Pair(args)) => pair(&args),
+        Some(Command::Analyze(pass)) => {
+            lattice_analyze::run(&with_program("lattice analyze", &pass.args))
+        }
+        Some(Command::Calibrate(pass)) => {
+            let mut argv = vec!["lattice".to_string(), "calibrate".to_string()];
+            argv.extend(pass.args);
+            lattice_analyze::run(&argv)
+        }
+        Some(Command::Export(pass)) => match pass.args.first().map(String::as_str) {
+            Some("redact") | Some("aggregate") | Some("atlas") => {
+                lattice_analyze::run(&with_program("lattice export", &pass.args))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Usage: lattice export <redact|aggregate|atlas> ...",
+            )),
+        },
+        Some(Command::Demo(pass)) => {
+            let mut argv = vec!["lattice".to_string(), "demo".to_string()];
+            argv.extend(pass.args);
+            lattice_analyze::run(&argv)
+        }
+        Some(Command::Reflect(pass)) => {
+            lattice_reflector::run(&with_program("lattice reflect", &pass.args))
+        }
+        Some(Command::Serve(pass)) => {
+            lattice_server::run(&with_program("lattice serve", &pass.args))
+        }
+        Some(Command::Doctor(args)) => doctor::run(&args),
+        Some(Command::Status(args)) => status::run(&args),
+        Some(Command::Responsiveness(args)) => responsiveness::run(&args),
+        Some(Command::Endpoints { action }) => endpoints::run(&action),
+        Some(Command::Anchors { action }) => anchors::run(&action),
+        Some(Command::Keygen { config }) => keys::keygen(config.as_deref()),
+        Some(Command::RotateKeys { config, registries }) => keys::rotate(&config, &registries),
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(shell, &mut Cli::command(), "lattice", &mut io::stdout());
+            Ok(())
+        }
+        // `lattice <config.json>` predates subcommands; keep it working.
+        None if cli.probe.source.path().is_some() => probe(&cli.probe, &CaptureMode::Probe),
+        None => {
+            Cli::command().print_help()?;
+            std::process::exit(1);
+        }
+    }
+}
+
+fn with_program(program: &str, args: &[String]) -> Vec<String> {
+    let mut argv = vec![program.to_string()];
+    argv.extend_from_slice(args);
+    argv
+}
+
+/// When a worker should stop on its own (`--once`, `--max-bursts`, `--duration`,
+/// or a SIGINT/SIGTERM). With `clean_only` (baseline mode) only bursts with
+/// samples and no tunnel count toward `max_bursts`.
+#[derive(Clone, Copy, Default)]
+struct RunLimits {
+    deadline: Option<Instant>,
+    max_bursts: Option<u64>,
+    clean_only: bool,
+}
+
+impl RunLimits {
+    fn is_bounded(&self) -> bool {
+        self.deadline.is_some() || self.max_bursts.is_some()
+    }
+
+    fn expired(&self) -> bool {
+        os::shutdown_requested() || self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+
+    fn reached(&self, bursts: u64) -> bool {
+        self.max_bursts.is_some_and(|n| bursts >= n)
+    }
+
+    /// Sleeps between bursts, waking at the deadline or soon after a stop
+    /// signal so workers notice either on time.
+    fn sleep_until(&self, wake: Instant, spin_us: u64) {
+        self.sleep_until_or(wake, spin_us, || false);
     }
 
-    let cfg = Config::load(&args[1])?;
+    /// `sleep_until` that also wakes once `early` holds, checked as often as
+    /// the stop signal. Returns whether it woke early.
+    fn sleep_until_or(&self, wake: Instant, spin_us: u64, early: impl Fn() -> bool) -> bool {
+        let wake = self.deadline.map_or(wake, |d| wake.min(d));
+        while !os::shutdown_requested() {
+            if early() {
+                return true;
+            }
+            let slice_end = Instant::now() + SHUTDOWN_POLL;
+            if slice_end >= wake {
+                sleep_until(wake, spin_us);
+                return false;
+            }
+            sleep_until(slice_end, 0);
+        }
+        false
+    }
+}
+
+/// Keeps only the endpoints whose id matches one of `--endpoints-filter`.
+fn filter_endpoints(cfg: &mut Config, patterns: &[String]) -> io::Result<()> {
+    if patterns.is_empty() {
+        return Ok(());
+    }
+    cfg.endpoints
+        .retain(|ep| patterns.iter().any(|p| cli::glob_match(p, &ep.id)));
+    if cfg.endpoints.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "no endpoints match --endpoints-filter {}",
+                patterns.join(",")
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// What a capture is for; decides which bursts count toward `--max-bursts`
+/// and where records go.
+enum CaptureMode {
+    Probe,
+    /// Tunnel-off reference: only clean bursts count.
+    Baseline,
+    /// Bursts split by tunnel state: down to `baseline`, up to the output path.
+    Paired {
+        baseline: PathBuf,
+    },
+}
+
+impl CaptureMode {
+    fn label(&self) -> &'static str {
+        match self {
+            CaptureMode::Probe => "probe",
+            CaptureMode::Baseline => "baseline",
+            CaptureMode::Paired { .. } => "paired",
+        }
+    }
+}
+
+/// `lattice baseline`: a capture that refuses to start with a tunnel up and
+/// stops once every endpoint has `baselineBursts` clean bursts.
+fn baseline(args: &BaselineArgs) -> io::Result<()> {
+    let utun = os::utun_report();
+    if utun.active {
+        let names: Vec<String> = utun.interfaces.iter().map(|i| i.name.clone()).collect();
+        return Err(io::Error::other(format!(
+            "tunnel interface active ({}); disconnect it before capturing a baseline",
+            names.join(", ")
+        )));
+    }
+    probe(&args.probe_args(), &CaptureMode::Baseline)
+}
+
+/// `lattice pair`: one capture split into baseline and session files as the
+/// tunnel goes down and up, optionally analyzed as a pair at the end.
+fn pair(args: &PairArgs) -> io::Result<()> {
+    let mode = CaptureMode::Paired {
+        baseline: args.baseline_out.clone(),
+    };
+    probe(&args.probe_args(), &mode)?;
+    if !args.analyze {
+        return Ok(());
+    }
+    println!();
+    let mut argv = vec!["lattice analyze".to_string()];
+    argv.extend(args.source.analyzer_args());
+    argv.extend([
+        "--session".to_string(),
+        args.session_out.display().to_string(),
+        "--baseline".to_string(),
+        args.baseline_out.display().to_string(),
+    ]);
+    lattice_analyze::run(&argv)
+}
+
+fn probe(args: &ProbeArgs, mode: &CaptureMode) -> io::Result<()> {
+    let baseline = matches!(mode, CaptureMode::Baseline);
+    let mut cfg = args.source.load()?;
+    if let Some(output) = &args.output {
+        cfg.output_path = output.display().to_string();
+    }
+    filter_endpoints(&mut cfg, &args.endpoints_filter)?;
     validate_config(&cfg)?;
+    check_consent(&cfg, args.own_targets)?;
+    for lint in lint::lint_config(&cfg) {
+        println!("[!] config: {lint}");
+    }
 
-    let secret = hex_to_bytes(&cfg.secret_hex).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-    if secret.len() < 16 {
+    let secret = cfg
+        .secret_hex
+        .decode()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if secret.len() < MIN_SECRET_BYTES {
         return Err(io::Error::new(
             io::ErrorKind::InvalidData,
-            "secretHex must be at least 16 bytes",
+            format!("secretHex must be at least {MIN_SECRET_BYTES} bytes"),
         ));
     }
+    let started = Instant::now();
+    let limits = RunLimits {
+        deadline: args.duration.and_then(|d| started.checked_add(d)),
+        max_bursts: match (args.once, args.max_bursts) {
+            (true, _) => Some(1),
+            (false, Some(n)) => Some(n),
+            (false, None) if baseline => Some(cfg.baseline_bursts),
+            (false, None) => None,
+        },
+        clean_only: baseline,
+    };
 
     let output_path = expand_tilde(&cfg.output_path);
+    let socket_path = status::socket_path(&cfg);
+    let status = Arc::new(Status::new(&output_path));
+    status::serve(&socket_path, Arc::clone(&status), &cfg)?;
+    let store = Arc::new(StateStore::open(&state::state_dir(&cfg))?);
+    let calibration = store.calibration()?;
+    let api_addr = match &cfg.local_api {
+        Some(local_api) => Some(api::serve(local_api, Arc::clone(&status))?),
+        None => None,
+    };
     println!("LATTICE (Rust) running");
     println!("  endpoints: {}", cfg.endpoints.len());
     println!("  interval:  {}s", cfg.interval_seconds);
     println!("  output:    {}", output_path.display());
-    if let Some(claimed) = &cfg.claimed_egress_region {
-        println!("  claimed:   {}", claimed);
+    println!("  control:   {}", socket_path.display());
+    println!("  state:     {}", store.dir().display());
+    if let Some(addr) = api_addr {
+        println!("  api:       http://{addr}/v1/");
     }
+    print_settings(&cfg, calibration.as_ref().map(|(path, _)| path.as_path()));
+    print_run(&cfg, mode, &limits, args.duration);
 
-    let (tx, rx) = mpsc::channel::<BurstRecord>();
-    let writer_path = output_path.clone();
-    let writer_handle = thread::spawn(move || writer_thread(writer_path, rx));
+    if let Err(err) = os::catch_shutdown() {
+        eprintln!("[!] Ctrl-C handling unavailable ({err}); stopping will not flush a session_end");
+    }
+    let (tx, rx) = mpsc::channel::<OutputRecord>();
+    let writer_handle = spawn_writer(
+        &cfg,
+        mode,
+        &output_path,
+        calibration.map(|(_, calibration)| calibration),
+        Arc::clone(&status),
+        rx,
+    );
 
-    let targets = expand_probe_targets(&cfg)?;
-    let cfg = Arc::new(cfg);
-    let secret = Arc::new(secret);
+    let session_id = Uuid::new_v4();
+    let _ = tx.send(OutputRecord::Event(session_start(&cfg, mode, session_id)));
+    let power = power::watch(tx.clone());
 
-    for target in targets {
-        let tx = tx.clone();
-        let cfg = Arc::clone(&cfg);
-        let secret = Arc::clone(&secret);
-        thread::spawn(move || endpoint_worker(target, cfg, secret, tx));
-    }
+    let targets = expand_probe_targets(&cfg, session_id)?;
+    let resolver = Resolver::start(
+        cfg.endpoints.iter().map(|ep| ep.host.as_str()),
+        &store.addresses(),
+    );
+    let mut cfg = Arc::new(cfg);
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    let spawner = Spawner {
+        shared: WorkerShared {
+            tx: tx.clone(),
+            secret: Arc::new(secret),
+            status: Arc::clone(&status),
+            state: Arc::clone(&store),
+            resolver,
+            limits,
+        },
+        done: done_tx,
+    };
+    let mut workers: HashMap<String, Worker> = targets
+        .into_iter()
+        .map(|target| {
+            (
+                target.endpoint.id.clone(),
+                spawner.spawn(target, Arc::clone(&cfg)),
+            )
+        })
+        .collect();
+    let mut retired = Vec::new();
+    let mut watch = reload::ConfigWatch::new(&args.source);
+    let mut stopping = false;
 
+    // Workers only return on their own when a run limit is reached or a
+    // signal asks them to stop; until then, edits to the config are handed
+    // to them.
+    while !workers.values().all(|w| w.handle.is_finished()) {
+        let _ = done_rx.recv_timeout(reload::POLL);
+        if os::shutdown_requested() {
+            if !stopping {
+                println!("Stopping after the bursts in flight (Ctrl-C again to quit now)...");
+                stopping = true;
+            }
+            continue;
+        }
+        if !watch.changed() {
+            continue;
+        }
+        let reloaded = reload::load(args, &cfg).and_then(|(next, restart_needed)| {
+            let targets = expand_probe_targets(&next, session_id)?;
+            Ok((next, targets, restart_needed))
+        });
+        match reloaded {
+            Ok((next, targets, restart_needed)) => {
+                cfg = Arc::new(next);
+                status.set_config(&cfg);
+                reload_workers(
+                    &cfg,
+                    targets,
+                    restart_needed,
+                    &spawner,
+                    &mut workers,
+                    &mut retired,
+                );
+            }
+            Err(err) => eprintln!(
+                "[!] config reload failed, still running the previous config: {}",
+                err
+            ),
+        }
+    }
+    for handle in workers.into_values().map(|w| w.handle).chain(retired) {
+        let _ = handle.join();
+    }
+    if let Err(err) = store.save() {
+        eprintln!(
+            "[!] saving state to {} failed: {}",
+            store.dir().display(),
+            err
+        );
+    }
+    if limits.is_bounded() || os::shutdown_requested() {
+        let report = status.report();
+        let bursts: u64 = report.endpoints.values().map(|e| e.bursts).sum();
+        let end = EventRecord::new(
+            SESSION_END_EVENT,
+            None,
+            serde_json::json!({
+                "sessionId": session_id.to_string(),
+                "durationSeconds": started.elapsed().as_secs_f64(),
+                "bursts": bursts,
+                "reason": if os::shutdown_requested() { "signal" } else { "limit" },
+            }),
+        );
+        let _ = tx.send(OutputRecord::Event(end));
+    }
+    power.stop();
+    // The writer ends once every sender is gone.
+    drop(spawner);
     drop(tx);
-
-    // Keep the main thread alive; the writer thread runs until all workers exit.
     let _ = writer_handle.join();
+    status::print_summary(&status.report(), started.elapsed());
     Ok(())
 }
 
+/// The optional parts of the config in effect, one line each.
+fn print_settings(cfg: &Config, calibration: Option<&Path>) {
+    if let Some(claimed) = &cfg.claimed_egress_region {
+        println!("  claimed:   {}", claimed);
+    }
+    println!(
+        "  rate cap:  {}/s per destination",
+        cfg.max_probes_per_sec_per_dest
+    );
+    if let Some(target) = &cfg.interleave {
+        println!(
+            "  interleave: {}:{} after every sample",
+            target.host, target.port
+        );
+    }
+    if let Some(load) = &cfg.bufferbloat_load {
+        println!(
+            "  bufferbloat: {} Mbit/s upload for {}ms every {} burst(s) per endpoint",
+            load.mbps, load.duration_ms, load.every_bursts
+        );
+    }
+    if let Some(rolling) = &cfg.rolling_estimate {
+        println!(
+            "  estimate:  every {} burst(s) over the newest {}",
+            rolling.every_bursts, rolling.window_bursts
+        );
+        if let Some(path) = calibration {
+            println!("  calibration: {}", path.display());
+        }
+    }
+    if let Some(rotation) = &cfg.rotate {
+        let mut when = Vec::new();
+        match rotation.every {
+            Some(RotateEvery::Hour) => when.push("hourly".to_string()),
+            Some(RotateEvery::Day) => when.push("daily".to_string()),
+            None => {}
+        }
+        if rotation.max_bytes > 0 {
+            when.push(format!("at {} bytes", rotation.max_bytes));
+        }
+        let keep = match rotation.keep {
+            0 => "all".to_string(),
+            n => n.to_string(),
+        };
+        println!(
+            "  rotate:    {}, {} compression, keeping {} archive(s)",
+            when.join(" or "),
+            rotation.compression.as_str(),
+            keep
+        );
+    }
+    if cfg.privacy_mode == PrivacyMode::Features {
+        println!(
+            "  privacy:   features only (hourly p05 per anchor; no bursts, samples, or addresses)"
+        );
+    }
+    if cfg.ecmp_ports > 1 {
+        println!(
+            "  ecmp:      {} source ports per endpoint, round-robin",
+            cfg.ecmp_ports
+        );
+    }
+    if !cfg.ipv6_flow_labels.is_empty() {
+        println!(
+            "  flow labels: {:?} round-robin on IPv6 endpoints",
+            cfg.ipv6_flow_labels
+        );
+    }
+    if cfg.ecn {
+        println!("  ecn:       UDP probes sent ECT(0); replies counted by codepoint");
+    }
+}
+
+/// The capture mode, its limits, and every endpoint about to be probed with
+/// the basis for probing it.
+fn print_run(cfg: &Config, mode: &CaptureMode, limits: &RunLimits, duration: Option<Duration>) {
+    match mode {
+        CaptureMode::Probe => {}
+        CaptureMode::Baseline => {
+            println!("  mode:      baseline (bursts with a tunnel up are flagged and not counted)")
+        }
+        CaptureMode::Paired { baseline } => println!(
+            "  mode:      paired (tunnel down -> {}, tunnel up -> output)",
+            baseline.display()
+        ),
+    }
+    match (limits.max_bursts, duration) {
+        (Some(n), Some(d)) => println!(
+            "  run:       {} burst(s) per endpoint, at most {}s",
+            n,
+            d.as_secs()
+        ),
+        (Some(n), None) => println!("  run:       {} burst(s) per endpoint", n),
+        (None, Some(d)) => println!("  run:       {}s", d.as_secs()),
+        (None, None) => {}
+    }
+    println!("Probing only the endpoints below; each must be operated by you or consented. Ctrl-C to stop.");
+    for ep in &cfg.endpoints {
+        let basis = if ep.consented {
+            "consented"
+        } else if is_loopback_host(&ep.host) {
+            "loopback"
+        } else {
+            "owner-asserted"
+        };
+        let version = if ep.packet_version == LATO_V2_VERSION {
+            " v2"
+        } else {
+            ""
+        };
+        println!(
+            "  - {} {}:{}/{}{} [{}]",
+            ep.id,
+            ep.host,
+            ep.port,
+            describe_transports(ep),
+            version,
+            basis
+        );
+    }
+}
+
+/// Starts the thread that writes everything sent on `rx` to the capture's
+/// log (or logs, when paired), with the rolling estimate if configured.
+fn spawn_writer(
+    cfg: &Config,
+    mode: &CaptureMode,
+    output_path: &Path,
+    calibration: Option<Calibration>,
+    status: Arc<Status>,
+    rx: mpsc::Receiver<OutputRecord>,
+) -> thread::JoinHandle<()> {
+    let sink = match mode {
+        CaptureMode::Paired { baseline } => Sink::Split {
+            baseline: baseline.clone(),
+            session: output_path.to_path_buf(),
+        },
+        _ => Sink::Single(output_path.to_path_buf()),
+    };
+    let decimals = cfg.display_decimals;
+    let rolling = cfg.rolling_estimate.as_ref().map(|settings| {
+        let rolling = RollingEstimator::new(cfg, settings);
+        match calibration {
+            Some(calibration) => rolling.with_calibration(calibration),
+            None => rolling,
+        }
+    });
+    let features = (cfg.privacy_mode == PrivacyMode::Features).then(FeatureLog::default);
+    let rotation = cfg.rotate.clone();
+    thread::spawn(move || writer_thread(sink, rx, status, decimals, rolling, features, rotation))
+}
+
+/// Tests the clock and prints what it found, then builds the
+/// `session_start` header.
+fn session_start(cfg: &Config, mode: &CaptureMode, session_id: Uuid) -> EventRecord {
+    let clock = clock::self_test();
+    println!(
+        "  clock:     step={}ns skew={}ppm ntp={}",
+        clock
+            .observed_granularity_ns
+            .map(|g| g.to_string())
+            .unwrap_or_else(|| "?".to_string()),
+        clock
+            .realtime_vs_monotonic_ppm
+            .map(|p| format!("{:.1}", p))
+            .unwrap_or_else(|| "?".to_string()),
+        match clock.ntp_synced {
+            Some(true) => "synced",
+            Some(false) => "UNSYNCED",
+            None => "unknown",
+        }
+    );
+    println!("  session:   {}", session_id);
+    EventRecord::new(
+        SESSION_START_EVENT,
+        None,
+        serde_json::json!({
+            "sessionId": session_id.to_string(),
+            "clientVersion": env!("CARGO_PKG_VERSION"),
+            "mode": mode.label(),
+            "clockQuality": clock,
+            "hostLocale": locale::host_locale(),
+            "machineId": machine::machine_id(cfg.machine_id.as_deref()),
+            "privacyMode": cfg.privacy_mode.as_str(),
+        }),
+    )
+}
+
+/// Hands a reloaded config to the running workers: kept endpoints get it
+/// before their next burst, new ones get a worker, and removed ones stop.
+/// Writes a `config_reloaded` event.
+fn reload_workers(
+    cfg: &Arc<Config>,
+    targets: Vec<ProbeTarget>,
+    restart_needed: Vec<&str>,
+    spawner: &Spawner,
+    workers: &mut HashMap<String, Worker>,
+    retired: &mut Vec<thread::JoinHandle<()>>,
+) {
+    let mut added = Vec::new();
+    let mut keep = Vec::new();
+    for target in targets {
+        let id = target.endpoint.id.clone();
+        match workers.remove(&id) {
+            Some(worker) => {
+                let _ = worker.reloads.send(Reload {
+                    target,
+                    cfg: Arc::clone(cfg),
+                });
+                keep.push((id, worker));
+            }
+            None => {
+                keep.push((id.clone(), spawner.spawn(target, Arc::clone(cfg))));
+                added.push(id);
+            }
+        }
+    }
+    // Dropping a worker's reload channel stops it at its next burst.
+    let mut removed: Vec<String> = workers.keys().cloned().collect();
+    removed.sort();
+    retired.extend(workers.drain().map(|(_, w)| w.handle));
+    workers.extend(keep);
+    let tx = &spawner.shared.tx;
+    let _ = tx.send(OutputRecord::Endpoints(cfg.endpoints.clone()));
+    let ev = EventRecord::new(
+        CONFIG_RELOADED_EVENT,
+        None,
+        serde_json::json!({
+            "added": added,
+            "removed": removed,
+            "restartNeeded": restart_needed,
+        }),
+    );
+    let _ = tx.send(OutputRecord::Event(ev));
+    if !restart_needed.is_empty() {
+        println!(
+            "[!] config: {} only change on restart",
+            restart_needed.join(", ")
+        );
+    }
+}
+
 fn validate_config(cfg: &Config) -> io::Result<()> {
     if cfg.endpoints.is_empty() {
         return Err(io::Error::new(
@@ -88,6 +712,152 @@ fn validate_config(cfg: &Config) -> io::Result<()> {
             "timeoutMs and intervalSeconds must be > 0",
         ));
     }
+    if let Some(k) = cfg.outlier_mad_k {
+        if !k.is_finite() || k <= 0.0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "outlierMadK must be > 0 when set",
+            ));
+        }
+    }
+    if cfg.baseline_bursts == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "baselineBursts must be > 0",
+        ));
+    }
+    if !cfg.max_probes_per_sec_per_dest.is_finite() || cfg.max_probes_per_sec_per_dest <= 0.0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "maxProbesPerSecPerDest must be > 0",
+        ));
+    }
+    let floors = cfg
+        .probe_paths
+        .iter()
+        .map(|p| p.access_floor_ms)
+        .chain([cfg.access_floor_ms]);
+    for floor in floors.flatten() {
+        if !floor.is_finite() || floor < 0.0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "accessFloorMs must be >= 0 when set",
+            ));
+        }
+    }
+    if let Some(ep) = cfg
+        .endpoints
+        .iter()
+        .find(|ep| ep.alt_m.is_some_and(|a| !a.is_finite()))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("endpoint {}: altM must be a finite number of metres", ep.id),
+        ));
+    }
+    if cfg
+        .interleave
+        .as_ref()
+        .is_some_and(|t| t.host.trim().is_empty())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "interleave.host must not be empty",
+        ));
+    }
+    if let Some(load) = &cfg.bufferbloat_load {
+        if load.duration_ms == 0
+            || load.every_bursts == 0
+            || !load.mbps.is_finite()
+            || load.mbps <= 0.0
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "bufferbloatLoad needs durationMs, mbps, and everyBursts > 0",
+            ));
+        }
+        if load.duration_ms >= cfg.interval_seconds * MS_PER_SEC as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "bufferbloatLoad.durationMs must be shorter than intervalSeconds",
+            ));
+        }
+    }
+    if let Some(rotation) = &cfg.rotate {
+        if rotation.max_bytes == 0 && rotation.every.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "rotate needs maxBytes > 0 or every (\"hour\" or \"day\")",
+            ));
+        }
+    }
+    if let Some(local_api) = &cfg.local_api {
+        api::validate(local_api)?;
+    }
+    if let Some(rolling) = &cfg.rolling_estimate {
+        if rolling.every_bursts == 0 || rolling.window_bursts == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "rollingEstimate needs everyBursts and windowBursts > 0",
+            ));
+        }
+        let claim_ok = match (rolling.claim_lat, rolling.claim_lon) {
+            (None, None) => true,
+            (Some(lat), Some(lon)) => lat.abs() <= lint::LAT_MAX && lon.abs() <= lint::LON_MAX,
+            _ => false,
+        };
+        if !claim_ok {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "rollingEstimate needs both claimLat and claimLon, within -90..90 and -180..180",
+            ));
+        }
+    }
+    if cfg.ecmp_ports > MAX_ECMP_PORTS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("ecmpPorts must be at most {MAX_ECMP_PORTS}"),
+        ));
+    }
+    if let Some(label) = cfg
+        .ipv6_flow_labels
+        .iter()
+        .find(|&&l| l == 0 || l > os::MAX_FLOW_LABEL)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("ipv6FlowLabels: {label} is not a 20-bit non-zero flow label"),
+        ));
+    }
+    for ep in &cfg.endpoints {
+        let mut transports = ep.transports.clone();
+        transports.sort_by_key(|t| t.as_str());
+        transports.dedup();
+        if transports.len() != ep.transports.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("endpoint {}: transports must not repeat", ep.id),
+            ));
+        }
+        if ep.packet_version != LATO_VERSION && ep.packet_version != LATO_V2_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "endpoint {}: packetVersion must be {LATO_VERSION} or {LATO_V2_VERSION}",
+                    ep.id
+                ),
+            ));
+        }
+        if ep.packet_version == LATO_V2_VERSION && ep.transport_order() != [Transport::Udp] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "endpoint {}: packetVersion {LATO_V2_VERSION} is UDP only",
+                    ep.id
+                ),
+            ));
+        }
+    }
     for path in &cfg.probe_paths {
         if path.id.trim().is_empty() {
             return Err(io::Error::new(
@@ -95,48 +865,323 @@ fn validate_config(cfg: &Config) -> io::Result<()> {
                 "probePaths entries must include a non-empty id",
             ));
         }
+        let mut ports = path.source_ports.clone();
+        ports.sort_unstable();
+        ports.dedup();
+        if ports.len() != path.source_ports.len()
+            || ports.first() == Some(&0)
+            || ports.len() > MAX_ECMP_PORTS
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "probePaths {}: sourcePorts must be distinct, non-zero, and at most {MAX_ECMP_PORTS}",
+                    path.id
+                ),
+            ));
+        }
     }
     Ok(())
 }
 
+/// Refuses to probe endpoints that are not marked `consented` (loopback is
+/// always allowed) unless the operator passes `--i-own-these-targets`.
+fn check_consent(cfg: &Config, own_targets: bool) -> io::Result<()> {
+    let unconsented: Vec<&str> = cfg
+        .endpoints
+        .iter()
+        .filter(|ep| !ep.consented && !is_loopback_host(&ep.host))
+        .map(|ep| ep.id.as_str())
+        .collect();
+    if unconsented.is_empty() || own_targets {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!(
+            "endpoints without \"consented\": true: {} (mark endpoints you operate or have permission to probe, or pass {})",
+            unconsented.join(", "),
+            OWN_TARGETS_FLAG
+        ),
+    ))
+}
+
+/// Everything the writer thread appends to the JSONL output.
+enum OutputRecord {
+    Burst(Box<BurstRecord>),
+    Event(EventRecord),
+    Responsiveness(Box<ResponsivenessRecord>),
+    /// The reloaded config's endpoints, for the rolling estimate; not written.
+    Endpoints(Vec<lattice_core::Endpoint>),
+}
+
+/// A reloaded config for a running worker, applied before its next burst.
+struct Reload {
+    target: ProbeTarget,
+    cfg: Arc<Config>,
+}
+
+struct Worker {
+    reloads: mpsc::Sender<Reload>,
+    handle: thread::JoinHandle<()>,
+}
+
+/// What every endpoint worker gets besides its target and config.
+#[derive(Clone)]
+struct WorkerShared {
+    tx: mpsc::Sender<OutputRecord>,
+    secret: Arc<Secret>,
+    status: Arc<Status>,
+    state: Arc<StateStore>,
+    resolver: Arc<Resolver>,
+    limits: RunLimits,
+}
+
+/// Starts workers, also for endpoints added to the config while the
+/// capture runs.
+struct Spawner {
+    shared: WorkerShared,
+    /// Wakes the main thread when a worker returns.
+    done: mpsc::Sender<()>,
+}
+
+impl Spawner {
+    fn spawn(&self, target: ProbeTarget, cfg: Arc<Config>) -> Worker {
+        let (reloads, reload_rx) = mpsc::channel();
+        let shared = self.shared.clone();
+        let done = self.done.clone();
+        let handle = thread::spawn(move || {
+            endpoint_worker(target, cfg, reload_rx, shared);
+            let _ = done.send(());
+        });
+        Worker { reloads, handle }
+    }
+}
+
 #[derive(Clone)]
 struct ProbeTarget {
     endpoint: lattice_core::Endpoint,
     path_id: String,
+    path_uuid: Uuid,
+    session_id: Uuid,
     bind_iface: Option<String>,
     bind_ip: Option<IpAddr>,
+    /// `bindIp` was configured. Otherwise `bind_ip` is an address of
+    /// `bind_iface` and follows it when the address changes.
+    bind_ip_pinned: bool,
+    access_floor_ms: Option<f64>,
+    spacing: Duration,
+    blackouts: Vec<CompiledBlackout>,
+    /// One socket per entry, probed round-robin; 0 lets the OS pick the port.
+    /// Never empty.
+    source_ports: Vec<u16>,
+    /// How often to send keepalives between bursts.
+    keepalive: Option<Duration>,
+    /// Mark UDP probes ECT(0).
+    ecn: bool,
+}
+
+/// The endpoint's primary socket to `destination`, plus one more per extra
+/// source port. With `packetVersion` 2 they take only signed replies; with
+/// `ecn` they send ECT(0) where the OS lets them.
+fn open_probers(
+    target: &ProbeTarget,
+    destination: IpAddr,
+    transport: Transport,
+    timeout: Duration,
+    secret: &Secret,
+) -> io::Result<(Prober, Vec<Prober>)> {
+    let ep = &target.endpoint;
+    let host = destination.to_string();
+    let open = |source_port| {
+        let mut prober = Prober::open(transport, ep, &host, target.bind_ip, source_port, timeout)?;
+        if ep.packet_version == LATO_V2_VERSION {
+            prober.check_replies(secret);
+        }
+        if target.ecn {
+            if let Err(err) = prober.mark_ecn() {
+                eprintln!("[!] {} ECN off: {}", ep.id, err);
+            }
+        }
+        Ok(prober)
+    };
+    let primary = open(target.source_ports[0])?;
+    let extra = target.source_ports[1..]
+        .iter()
+        .map(|&port| open(port))
+        .collect::<io::Result<_>>()?;
+    Ok((primary, extra))
 }
 
-fn expand_probe_targets(cfg: &Config) -> io::Result<Vec<ProbeTarget>> {
+/// One keepalive (`sequence::keepalive`) per UDP socket.
+fn send_keepalives<'a>(
+    probers: impl Iterator<Item = &'a mut Prober>,
+    secret: &Secret,
+    endpoint_id: &str,
+) -> usize {
+    let mut sent = 0;
+    for prober in probers {
+        let msg = sequence::keepalive(os::realtime_now_ns(), secret);
+        match prober.keepalive(&msg) {
+            Ok(true) => sent += 1,
+            Ok(false) => {}
+            Err(err) => eprintln!("[!] {} keepalive failed: {}", endpoint_id, err),
+        }
+    }
+    sent
+}
+
+fn compile_blackouts(
+    cfg: &Config,
+    ep: &lattice_core::Endpoint,
+) -> io::Result<Vec<CompiledBlackout>> {
+    cfg.blackouts
+        .iter()
+        .chain(ep.blackouts.iter())
+        .map(|b| {
+            CompiledBlackout::compile(b).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", ep.id, e))
+            })
+        })
+        .collect()
+}
+
+/// Spacing that keeps the combined rate of every probe path hitting the same
+/// destination under `maxProbesPerSecPerDest`.
+fn capped_spacing(cfg: &Config, paths_per_dest: usize) -> Duration {
+    let min_spacing_s = paths_per_dest as f64 / cfg.max_probes_per_sec_per_dest;
+    Duration::from_millis(cfg.spacing_ms).max(Duration::from_secs_f64(min_spacing_s))
+}
+
+/// Paths without a configured `uuid` get one derived from the session, so it
+/// is stable within a capture but not across captures.
+fn expand_probe_targets(cfg: &Config, session_id: Uuid) -> io::Result<Vec<ProbeTarget>> {
     let mut out = Vec::new();
-    let paths: Vec<ProbePath> = if cfg.probe_paths.is_empty() {
-        vec![ProbePath {
+    let mut paths = cfg.probe_paths.clone();
+    if cfg.auto_probe_paths {
+        let auto = auto_probe_paths(&paths)?;
+        match auto.len() {
+            0 => println!("  [!] autoProbePaths: no other up interface has a routable address"),
+            _ => {
+                let ids: Vec<&str> = auto.iter().map(|p| p.id.as_str()).collect();
+                println!("  auto paths: {}", ids.join(", "));
+            }
+        }
+        paths.extend(auto);
+    }
+    if paths.is_empty() {
+        paths.push(ProbePath {
             id: "default".to_string(),
             bind_interface: None,
             bind_ip: None,
-        }]
-    } else {
-        cfg.probe_paths.clone()
-    };
+            access_floor_ms: None,
+            uuid: None,
+            source_ports: Vec::new(),
+            keepalive_seconds: None,
+        });
+    }
 
-    for path in paths {
+    let mut paths_per_dest: HashMap<(String, u16), usize> = HashMap::new();
+    for ep in &cfg.endpoints {
+        *paths_per_dest
+            .entry((ep.host.to_ascii_lowercase(), ep.port))
+            .or_default() += paths.len();
+    }
+
+    for path in paths.iter() {
+        let path_uuid = match &path.uuid {
+            Some(text) => Uuid::parse_str(text).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("probePaths {}: invalid uuid '{}'", path.id, text),
+                )
+            })?,
+            None => Uuid::new_v5(&session_id, path.id.as_bytes()),
+        };
         for ep in &cfg.endpoints {
+            let dest_paths = paths_per_dest[&(ep.host.to_ascii_lowercase(), ep.port)];
+            let spacing = capped_spacing(cfg, dest_paths);
+            if spacing > Duration::from_millis(cfg.spacing_ms) && path.id == paths[0].id {
+                println!(
+                    "  [!] {} spacing raised to {:.1}ms to respect the per-destination rate cap",
+                    ep.id,
+                    spacing.as_secs_f64() * 1000.0
+                );
+            }
             let mut endpoint = ep.clone();
             if path.id != "default" {
                 endpoint.id = format!("{}@{}", endpoint.id, path.id);
             }
-            let bind_ip = resolve_bind_ip(&path, &endpoint.host, endpoint.port)?;
+            let bind_ip = resolve_bind_ip(path, &endpoint.host, endpoint.port)?;
+            let blackouts = compile_blackouts(cfg, ep)?;
             out.push(ProbeTarget {
                 endpoint,
                 path_id: path.id.clone(),
+                path_uuid,
+                session_id,
                 bind_iface: path.bind_interface.clone(),
                 bind_ip,
+                bind_ip_pinned: path.bind_ip.is_some(),
+                access_floor_ms: path.access_floor_ms.or(cfg.access_floor_ms),
+                spacing,
+                blackouts,
+                source_ports: if path.source_ports.is_empty() {
+                    vec![0; cfg.ecmp_ports.max(1)]
+                } else {
+                    path.source_ports.clone()
+                },
+                keepalive: path
+                    .keepalive_seconds
+                    .or(cfg.keepalive_seconds)
+                    .filter(|&s| s > 0)
+                    .map(Duration::from_secs),
+                ecn: cfg.ecn,
             });
         }
     }
     Ok(out)
 }
 
+/// `autoProbePaths`: a path per up interface with a routable address, named
+/// after the interface and bound to it. Tunnels are left out, and so are
+/// interfaces that a configured path already binds or is named after.
+fn auto_probe_paths(configured: &[ProbePath]) -> io::Result<Vec<ProbePath>> {
+    let mut names: Vec<String> = Vec::new();
+    for (name, ip) in os::up_iface_ips()? {
+        let taken = configured
+            .iter()
+            .any(|p| p.id == name || p.bind_interface.as_deref() == Some(name.as_str()));
+        if is_routable(ip) && !is_tunnel_iface_name(&name) && !taken && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names
+        .into_iter()
+        .map(|name| ProbePath {
+            id: name.clone(),
+            bind_interface: Some(name),
+            bind_ip: None,
+            access_floor_ms: None,
+            uuid: None,
+            source_ports: Vec::new(),
+            keepalive_seconds: None,
+        })
+        .collect())
+}
+
+/// An address that can reach anchors: private IPv4 counts (it is NATed),
+/// link-local and unique-local IPv6 do not.
+fn is_routable(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            !(v4.is_loopback() || v4.is_link_local() || v4.is_unspecified() || v4.is_broadcast())
+        }
+        // 2000::/3, global unicast.
+        IpAddr::V6(v6) => v6.segments()[0] & 0xe000 == 0x2000,
+    }
+}
+
 fn resolve_bind_ip(path: &ProbePath, host: &str, port: u16) -> io::Result<Option<IpAddr>> {
     if let Some(ip_str) = &path.bind_ip {
         let ip = ip_str
@@ -186,152 +1231,963 @@ fn expand_tilde(path: &str) -> PathBuf {
     PathBuf::from(path)
 }
 
-fn writer_thread(path: PathBuf, rx: mpsc::Receiver<BurstRecord>) {
-    if let Some(parent) = path.parent() {
-        if let Err(err) = fs::create_dir_all(parent) {
-            eprintln!("[!!] failed to create log dir: {}", err);
-            return;
+/// Writes via a sibling temp file and rename so readers never see a partial file.
+fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)
+}
+
+/// Where the writer appends records. `Split` routes each burst by tunnel
+/// state (down to `baseline`, up to `session`); events without an endpoint
+/// go to both, endpoint events to the segment currently being written.
+enum Sink {
+    Single(PathBuf),
+    Split { baseline: PathBuf, session: PathBuf },
+}
+
+fn print_event(ev: &EventRecord) {
+    println!(
+        "[ev] {} {} {}",
+        ev.endpoint_id.as_deref().unwrap_or("-"),
+        ev.event,
+        ev.detail
+    );
+}
+
+fn writer_thread(
+    sink: Sink,
+    rx: mpsc::Receiver<OutputRecord>,
+    status: Arc<Status>,
+    decimals: usize,
+    mut rolling: Option<RollingEstimator>,
+    mut features: Option<FeatureLog>,
+    rotation: Option<LogRotation>,
+) {
+    let (paths, labels) = match sink {
+        Sink::Single(path) => (vec![path], Vec::new()),
+        Sink::Split { baseline, session } => (
+            vec![baseline, session],
+            vec![BASELINE_SEGMENT, SESSION_SEGMENT],
+        ),
+    };
+    let mut writers = Vec::with_capacity(paths.len());
+    for path in &paths {
+        match LogFile::open(path, rotation.clone()) {
+            Ok(w) => writers.push(w),
+            Err(err) => {
+                eprintln!("[!!] failed to open log file {}: {}", path.display(), err);
+                return;
+            }
         }
     }
-    let file = match File::options().create(true).append(true).open(&path) {
-        Ok(f) => f,
-        Err(err) => {
-            eprintln!("[!!] failed to open log file: {}", err);
-            return;
-        }
-    };
-    let mut writer = BufWriter::new(file);
+    let split = writers.len() > 1;
+    let mut segment: Option<usize> = None;
 
-    for rec in rx {
-        if let Err(err) = serde_json::to_writer(&mut writer, &rec) {
-            eprintln!("[!!] log write failed: {}", err);
-            continue;
+    for out in rx {
+        if let OutputRecord::Event(ev) = &out {
+            if features.is_some() && !privacy::keeps_event(ev) {
+                continue;
+            }
         }
-        if let Err(err) = writer.write_all(b"\n") {
-            eprintln!("[!!] log write failed: {}", err);
-            continue;
+        let targets: Vec<usize> = match &out {
+            OutputRecord::Burst(rec) if split => {
+                let idx = usize::from(rec.utun_active || rec.iface_is_tunnel);
+                if segment != Some(idx) {
+                    segment = Some(idx);
+                    let ev = EventRecord::new(
+                        SEGMENT_START_EVENT,
+                        None,
+                        serde_json::json!({ "segment": labels[idx] }),
+                    );
+                    match writers[idx].write_line(&ev) {
+                        Ok(()) => {
+                            status.event_written(&ev);
+                            print_event(&ev);
+                        }
+                        Err(err) => status.sink_error(&err),
+                    }
+                }
+                vec![idx]
+            }
+            OutputRecord::Event(ev) if ev.endpoint_id.is_none() => (0..writers.len()).collect(),
+            _ => vec![segment.unwrap_or(0)],
+        };
+        let mut failed = false;
+        for idx in targets {
+            let written = match &out {
+                OutputRecord::Burst(rec) => match features.as_mut() {
+                    Some(log) => match log.push(rec) {
+                        Some(hour) => writers[idx].write_line(&hour),
+                        None => Ok(()),
+                    },
+                    None => writers[idx].write_line(rec.as_ref()),
+                },
+                OutputRecord::Event(ev) if ev.event == SESSION_START_EVENT => {
+                    writers[idx].write_header(ev)
+                }
+                OutputRecord::Event(ev) => writers[idx].write_line(ev),
+                OutputRecord::Responsiveness(rec) if features.is_some() => {
+                    let mut rec = rec.as_ref().clone();
+                    rec.idle_samples_ms.clear();
+                    rec.loaded_samples_ms.clear();
+                    writers[idx].write_line(&rec)
+                }
+                OutputRecord::Responsiveness(rec) => writers[idx].write_line(rec.as_ref()),
+                OutputRecord::Endpoints(_) => Ok(()),
+            };
+            if let Err(err) = written {
+                eprintln!("[!!] log write failed: {}", err);
+                status.sink_error(&err);
+                failed = true;
+            }
         }
-        if let Err(err) = writer.flush() {
-            eprintln!("[!!] log flush failed: {}", err);
+        if failed {
+            continue;
         }
 
+        let rec = match out {
+            OutputRecord::Burst(rec) => {
+                status.burst_written(&rec);
+                rec
+            }
+            OutputRecord::Event(ev) => {
+                status.event_written(&ev);
+                print_event(&ev);
+                continue;
+            }
+            OutputRecord::Responsiveness(rec) => {
+                status.responsiveness_written();
+                responsiveness::print_record(&rec);
+                continue;
+            }
+            OutputRecord::Endpoints(endpoints) => {
+                if let Some(rolling) = rolling.as_mut() {
+                    rolling.set_endpoints(&endpoints);
+                }
+                continue;
+            }
+        };
         if !rec.notes.is_empty() {
             println!("[!] {} {}", rec.endpoint_id, rec.notes.join(" | "));
         } else if let (Some(min), Some(p05), Some(med)) = (rec.min_ms, rec.p05_ms, rec.median_ms) {
             println!(
-                "[ok] {} min={:.1}ms p05={:.1}ms med={:.1}ms",
-                rec.endpoint_id, min, p05, med
+                "[ok] {} min={:.*}ms p05={:.*}ms med={:.*}ms",
+                rec.endpoint_id, decimals, min, decimals, p05, decimals, med
             );
         } else {
             println!("[??] {} no samples (timeout?)", rec.endpoint_id);
         }
+        if let Some(est) = rolling.as_mut().and_then(|r| r.push(&rec)) {
+            let idx = segment.unwrap_or(0);
+            match writers[idx].write_line(&est) {
+                Ok(()) => print_estimate(&est),
+                Err(err) => {
+                    eprintln!("[!!] log write failed: {}", err);
+                    status.sink_error(&err);
+                }
+            }
+        }
     }
+    let idx = segment.unwrap_or(0);
+    for hour in features.map(FeatureLog::finish).unwrap_or_default() {
+        if let Err(err) = writers[idx].write_line(&hour) {
+            eprintln!("[!!] log write failed: {}", err);
+            status.sink_error(&err);
+        }
+    }
+}
+
+fn print_estimate(est: &EstimateRecord) {
+    let at = match (est.estimate_lat, est.estimate_lon) {
+        (Some(lat), Some(lon)) => format!("{:.2},{:.2}", lat, lon),
+        _ => "-".to_string(),
+    };
+    let falsifying = if est.falsifying.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", est.falsifying.join(", "))
+    };
+    println!(
+        "[est] {} anchors={} claim={}{} tunnel={}",
+        at,
+        est.anchors_used,
+        est.claim_verdict.as_deref().unwrap_or("-"),
+        falsifying,
+        est.tunnel
+    );
 }
 
+/// Probes one target until a run limit is reached or its reload channel is
+/// dropped. A reloaded target or config takes effect before the next burst;
+/// sockets are only reopened when the reload changed where or how they
+/// connect.
 fn endpoint_worker(
     target: ProbeTarget,
     cfg: Arc<Config>,
-    secret: Arc<Vec<u8>>,
-    tx: mpsc::Sender<BurstRecord>,
+    reloads: mpsc::Receiver<Reload>,
+    shared: WorkerShared,
 ) {
-    let mut prober_opt: Option<os::UdpProber> = None;
-    let mut last_utun_active: Option<bool> = None;
-    let mut burst_since_refresh: usize = 0;
-    let mut empty_burst_streak: usize = 0;
+    let mut worker = EndpointWorker::new(target, cfg, shared);
+    while !worker.shared.limits.expired() {
+        let mut reload = None;
+        loop {
+            match reloads.try_recv() {
+                Ok(next) => reload = Some(next),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+        if let Some(reload) = reload {
+            worker.reload(reload);
+        }
+        if worker.tick().is_err() {
+            break;
+        }
+    }
+    let limits = worker.shared.limits;
+    if limits.clean_only && !limits.reached(worker.bursts) {
+        eprintln!(
+            "[!] {} baseline stopped with {}/{} clean bursts",
+            worker.target.endpoint.id,
+            worker.bursts,
+            limits.max_bursts.unwrap_or_default()
+        );
+    }
+}
 
-    let interval = Duration::from_secs(cfg.interval_seconds);
-    let spacing = Duration::from_millis(cfg.spacing_ms);
-    let timeout = Duration::from_millis(cfg.timeout_ms);
-    let mut rng = rand::thread_rng();
-    let mut seq: u32 = 0;
+/// Why a worker stops before its run limits expire: the writer is gone, or
+/// `--max-bursts` is reached.
+struct Stop;
 
-    let mut next_tick = Instant::now() + interval;
+/// Where a burst's probes left from, read off the primary socket.
+struct LocalPath {
+    iface_name: String,
+    /// The interface's type, or `loopback`.
+    iface: String,
+    iface_is_tunnel: bool,
+    local_addr: String,
+    remote_addr: String,
+    dest_is_loopback: bool,
+    network_path: Option<lattice_core::NetworkPath>,
+}
+
+/// What one burst collected, RTTs still in integer ns.
+struct Burst {
+    burst_seq: u16,
+    samples: Vec<u64>,
+    reference_samples: Vec<Option<u64>>,
+    loaded_samples: Vec<u64>,
+    sample_ports: Vec<u16>,
+    sample_labels: Vec<u32>,
+    sample_legs: Vec<Option<SampleLegs>>,
+    reflector_clock: Option<ReflectorClock>,
+    ecn: Option<EcnCounts>,
+    timestamp_source: Option<TimestampSource>,
+    self_floor_ms: Option<f64>,
+    iface_traffic: Option<IfaceTraffic>,
+    late: sequence::LateReplies,
+    send_errors: SendErrors,
+    /// The first send or receive that failed for good.
+    send_failure: Option<io::Error>,
+}
+
+/// One target's sockets and everything carried from one burst to the next.
+struct EndpointWorker {
+    target: ProbeTarget,
+    cfg: Arc<Config>,
+    shared: WorkerShared,
+    prober: Option<Prober>,
+    ecmp_probers: Vec<Prober>,
+    /// Address the open sockets point at.
+    destination: Option<IpAddr>,
+    reference: Option<os::UdpProber>,
+    self_floor: Option<selffloor::SelfFloorProbe>,
+    /// What it watches: the destination and the local address bound to.
+    path_monitor: Option<((SocketAddr, Option<IpAddr>), os::PathMonitor)>,
+    last_utun_active: Option<bool>,
+    burst_since_refresh: usize,
+    empty_burst_streak: usize,
+    idle_floor: bloat::IdleFloor,
+    bursts_since_load: u64,
+    failover: failover::Failover,
+    transports: TransportLadder,
+    flow_labels_on: bool,
+    interval: Duration,
+    spacing: Duration,
+    timeout: Duration,
+    sequencer: sequence::Sequencer,
+    reference_sequencer: sequence::Sequencer,
+    next_tick: Instant,
+    blackout_skipped: usize,
+    bursts: u64,
+    keepalives_sent: usize,
+}
+
+impl EndpointWorker {
+    fn new(target: ProbeTarget, cfg: Arc<Config>, shared: WorkerShared) -> Self {
+        let saved = shared.state.worker(&target.endpoint.id);
+        let interval = Duration::from_secs(cfg.interval_seconds);
+        EndpointWorker {
+            prober: None,
+            ecmp_probers: Vec::new(),
+            destination: None,
+            reference: None,
+            self_floor: open_self_floor(&target, &cfg),
+            path_monitor: None,
+            last_utun_active: None,
+            burst_since_refresh: 0,
+            empty_burst_streak: 0,
+            idle_floor: saved.as_ref().map_or_else(bloat::IdleFloor::default, |s| {
+                bloat::IdleFloor::from_recent(&s.idle_p05_ns)
+            }),
+            bursts_since_load: 0,
+            failover: failover::Failover::new(&target.endpoint.id),
+            transports: TransportLadder::new(
+                &target.endpoint.id,
+                target.endpoint.transport_order(),
+            ),
+            flow_labels_on: true,
+            interval,
+            spacing: target.spacing,
+            timeout: Duration::from_millis(cfg.timeout_ms),
+            sequencer: saved.map_or_else(sequence::Sequencer::new, |s| {
+                sequence::Sequencer::resume(s.socket_epoch, s.burst_seq)
+            }),
+            reference_sequencer: sequence::Sequencer::new(),
+            next_tick: Instant::now() + interval,
+            blackout_skipped: 0,
+            bursts: 0,
+            keepalives_sent: 0,
+            target,
+            cfg,
+            shared,
+        }
+    }
+
+    fn event(&self, ev: EventRecord) -> Result<(), Stop> {
+        self.shared
+            .tx
+            .send(OutputRecord::Event(ev))
+            .map_err(|_| Stop)
+    }
+
+    /// Waits out this tick without a burst.
+    fn skip(&mut self) {
+        self.shared
+            .limits
+            .sleep_until(self.next_tick, self.cfg.pacing_spin_us);
+        self.next_tick += self.interval;
+    }
+
+    /// Drops the sockets; the next burst opens new ones.
+    fn close(&mut self) {
+        self.prober = None;
+        self.ecmp_probers.clear();
+        self.burst_since_refresh = 0;
+    }
+
+    fn reload(&mut self, Reload { target, cfg }: Reload) {
+        if !same_sockets(&self.target, &target) || cfg.timeout_ms != self.cfg.timeout_ms {
+            self.close();
+            self.destination = None;
+            self.empty_burst_streak = 0;
+        }
+        if target.endpoint.transport_order() != self.target.endpoint.transport_order() {
+            self.transports =
+                TransportLadder::new(&target.endpoint.id, target.endpoint.transport_order());
+        }
+        if reload::differs(&cfg.interleave, &self.cfg.interleave) {
+            self.reference = None;
+        }
+        if (cfg.self_floor_probes == 0) != (self.cfg.self_floor_probes == 0) {
+            self.self_floor = open_self_floor(&target, &cfg);
+        }
+        if cfg.interval_seconds != self.cfg.interval_seconds {
+            self.interval = Duration::from_secs(cfg.interval_seconds);
+            self.next_tick = Instant::now() + self.interval;
+        }
+        self.spacing = target.spacing;
+        self.timeout = Duration::from_millis(cfg.timeout_ms);
+        self.flow_labels_on = true;
+        self.target = target;
+        self.cfg = cfg;
+    }
+
+    /// One burst, or one tick waited out in a blackout, on an unresolved
+    /// host, or after a failed connect.
+    fn tick(&mut self) -> Result<(), Stop> {
+        if self.blacked_out()? {
+            self.skip();
+            return Ok(());
+        }
+        // Only a host added after startup can still be waiting on its first
+        // answer.
+        let Some(resolved) = self.shared.resolver.lookup(&self.target.endpoint.host) else {
+            self.skip();
+            return Ok(());
+        };
+        let rebound = self.follow_bind_ip()?;
+        let resolved = resolved.and_then(|r| Ok((r.pick(self.target.bind_ip)?, r.ttl)));
+        let moved = self.follow_destination(&resolved)?;
 
-    loop {
         let utun_report = os::utun_report();
-        let mut refresh_socket = false;
-        if let Some(prev) = last_utun_active {
-            if prev != utun_report.active {
-                refresh_socket = true;
+        let utun_changed = self
+            .last_utun_active
+            .is_some_and(|prev| prev != utun_report.active);
+        if moved || rebound || utun_changed || self.burst_since_refresh >= RECONNECT_INTERVAL_BURSTS
+        {
+            self.close();
+            self.empty_burst_streak = 0;
+        }
+        if self.prober.is_none() && !self.connect(resolved)? {
+            self.last_utun_active = Some(utun_report.active);
+            self.skip();
+            return Ok(());
+        }
+        self.open_reference();
+        self.run_job()?;
+
+        let path = self.local_path();
+        let burst = self.run_burst(&path.iface_name);
+        let (path_down, switch_transport) = self.track_outage(&burst)?;
+        let utun_active = utun_report.active;
+        let rec = self.build_record(burst, path, utun_report);
+        let tunnel_up = rec.utun_active || rec.iface_is_tunnel;
+        let counted = !self.shared.limits.clean_only || (rec.min_ms.is_some() && !tunnel_up);
+        self.save(&rec);
+        self.shared
+            .tx
+            .send(OutputRecord::Burst(Box::new(rec)))
+            .map_err(|_| Stop)?;
+        if counted {
+            self.bursts += 1;
+        }
+        if self.shared.limits.reached(self.bursts) {
+            return Err(Stop);
+        }
+
+        if self.empty_burst_streak >= RECONNECT_EMPTY_BURSTS || path_down || switch_transport {
+            self.close();
+        } else {
+            self.burst_since_refresh += 1;
+        }
+        self.last_utun_active = Some(utun_active);
+        self.keepalive_wait();
+        Ok(())
+    }
+
+    /// Whether a blackout is on, writing `blackout_start` as it begins and
+    /// `blackout_end` once it is over.
+    fn blacked_out(&mut self) -> Result<bool, Stop> {
+        let id = &self.target.endpoint.id;
+        if let Some((blackout, until_ms)) = active_blackout(&self.target.blackouts, now_unix_ms()) {
+            if self.blackout_skipped == 0 {
+                self.event(EventRecord::new(
+                    "blackout_start",
+                    Some(id.clone()),
+                    serde_json::json!({ "label": blackout.label, "untilUnixMs": until_ms }),
+                ))?;
             }
+            self.blackout_skipped += 1;
+            return Ok(true);
         }
-        if burst_since_refresh >= RECONNECT_INTERVAL_BURSTS {
-            refresh_socket = true;
+        if self.blackout_skipped > 0 {
+            self.event(EventRecord::new(
+                "blackout_end",
+                Some(id.clone()),
+                serde_json::json!({ "skippedBursts": self.blackout_skipped }),
+            ))?;
+            self.blackout_skipped = 0;
         }
-        if refresh_socket {
-            prober_opt = None;
-            burst_since_refresh = 0;
-            empty_burst_streak = 0;
+        Ok(false)
+    }
+
+    /// Moves to the interface's new address once it lost the bound one.
+    /// Returns whether it moved.
+    fn follow_bind_ip(&mut self) -> Result<bool, Stop> {
+        let (Some(from), Some(to)) = (self.target.bind_ip, new_bind_ip(&self.target)) else {
+            return Ok(false);
+        };
+        self.target.bind_ip = Some(to);
+        self.failover.retry_now();
+        self.event(EventRecord::new(
+            BIND_ADDRESS_CHANGED_EVENT,
+            Some(self.target.endpoint.id.clone()),
+            serde_json::json!({
+                "interface": self.target.bind_iface,
+                "from": from.to_string(),
+                "to": to.to_string(),
+            }),
+        ))?;
+        Ok(true)
+    }
+
+    /// Moves to the host's new address once the resolver has one. Returns
+    /// whether it moved.
+    fn follow_destination(
+        &mut self,
+        resolved: &io::Result<(IpAddr, Duration)>,
+    ) -> Result<bool, Stop> {
+        let (Ok((to, ttl)), Some(from)) = (resolved, self.destination) else {
+            return Ok(false);
+        };
+        if *to == from {
+            return Ok(false);
         }
+        self.destination = Some(*to);
+        self.event(EventRecord::new(
+            DESTINATION_CHANGED_EVENT,
+            Some(self.target.endpoint.id.clone()),
+            serde_json::json!({
+                "from": from.to_string(),
+                "to": to.to_string(),
+                "ttlSeconds": ttl.as_secs(),
+            }),
+        ))?;
+        Ok(true)
+    }
 
-        if prober_opt.is_none() {
-            match os::UdpProber::new(&target.endpoint.host, target.endpoint.port, target.bind_ip) {
-                Ok(p) => prober_opt = Some(p),
-                Err(err) => {
-                    eprintln!("[!!] {} probe init failed: {}", target.endpoint.id, err);
-                    last_utun_active = Some(utun_report.active);
-                    sleep_until(next_tick, cfg.pacing_spin_us);
-                    next_tick += interval;
-                    continue;
+    /// Opens the sockets unless failover holds off. Returns whether they
+    /// are open.
+    fn connect(&mut self, resolved: io::Result<(IpAddr, Duration)>) -> Result<bool, Stop> {
+        if !self.failover.may_connect(Instant::now()) {
+            return Ok(false);
+        }
+        self.failover.reconnecting();
+        let opened = resolved.and_then(|(ip, _)| {
+            let transport = self.transports.current();
+            open_probers(
+                &self.target,
+                ip,
+                transport,
+                self.timeout,
+                &self.shared.secret,
+            )
+            .map(|p| (ip, p))
+        });
+        match opened {
+            Ok((ip, (primary, extra))) => {
+                self.destination = Some(ip);
+                let watched = (
+                    SocketAddr::new(ip, self.target.endpoint.port),
+                    self.target.bind_ip,
+                );
+                if self
+                    .path_monitor
+                    .as_ref()
+                    .is_none_or(|(w, _)| *w != watched)
+                {
+                    self.path_monitor = os::PathMonitor::new(watched.0, watched.1)
+                        .ok()
+                        .map(|m| (watched, m));
+                }
+                self.prober = Some(primary);
+                self.ecmp_probers = extra;
+                self.sequencer.new_epoch();
+                self.sequencer
+                    .set_version(self.target.endpoint.packet_version);
+                Ok(true)
+            }
+            Err(err) => {
+                let id = &self.target.endpoint.id;
+                eprintln!("[!!] {} probe init failed: {}", id, err);
+                self.shared.status.init_error(id);
+                let mut events = Vec::new();
+                events.extend(self.failover.failed(&err, Instant::now()));
+                if Outage::of(&err) == Outage::Anchor {
+                    events.extend(self.transports.silent());
+                }
+                for ev in events {
+                    self.event(ev)?;
                 }
+                Ok(false)
             }
         }
+    }
 
-        let prober = prober_opt.as_mut().unwrap();
-        let iface_name = prober.iface_name().unwrap_or_else(|_| "unknown".to_string());
+    /// Opens the `interleave` reference socket if it is configured and not
+    /// open yet.
+    fn open_reference(&mut self) {
+        let (Some(target), None) = (&self.cfg.interleave, &self.reference) else {
+            return;
+        };
+        match os::UdpProber::new(&target.host, target.port, None) {
+            Ok(p) => {
+                self.reference = Some(p);
+                self.reference_sequencer.new_epoch();
+            }
+            Err(err) => eprintln!(
+                "[!] interleave reference {} unavailable: {}",
+                target.host, err
+            ),
+        }
+    }
+
+    /// Runs a responsiveness test asked for over the control socket or the
+    /// local API, if there is one.
+    fn run_job(&mut self) -> Result<(), Stop> {
+        let Some(job) = self.shared.status.take_job(&self.target.endpoint.id) else {
+            return Ok(());
+        };
+        let probing = bloat::Probing {
+            secret: &self.shared.secret,
+            spacing: self.spacing,
+            timeout: self.timeout,
+            pacing_spin_us: self.cfg.pacing_spin_us,
+        };
+        let prober = self.prober.as_mut().expect("connected");
+        match responsiveness::run_test(
+            prober,
+            &self.target,
+            &job.request,
+            &probing,
+            &mut self.sequencer,
+        ) {
+            Ok(rec) => {
+                let _ = job.reply.send(rec.clone());
+                self.shared
+                    .tx
+                    .send(OutputRecord::Responsiveness(Box::new(rec)))
+                    .map_err(|_| Stop)?;
+            }
+            // Dropping the job tells the requester it failed.
+            Err(err) => eprintln!(
+                "[!] {} responsiveness test failed: {}",
+                self.target.endpoint.id, err
+            ),
+        }
+        Ok(())
+    }
+
+    fn local_path(&self) -> LocalPath {
+        let prober = self.prober.as_ref().expect("connected");
+        let iface_name = prober
+            .iface_name()
+            .unwrap_or_else(|_| "unknown".to_string());
         let local_addr = prober
             .local_addr()
             .map(|a| a.to_string())
             .unwrap_or_else(|_| "unknown".to_string());
-        let dest_is_loopback = is_loopback_host(&target.endpoint.host)
+        let remote_addr = prober
+            .peer_addr()
+            .map(|a| a.ip().to_string())
+            .unwrap_or_default();
+        let dest_is_loopback = is_loopback_host(&self.target.endpoint.host)
             || local_addr
                 .parse::<IpAddr>()
                 .map(|ip| ip.is_loopback())
                 .unwrap_or(false);
+        let network_path = self.path_monitor.as_ref().and_then(|(_, m)| m.current());
+        // The OS knows the interface's type; the name only hints at it.
+        let path_iface_type = network_path
+            .as_ref()
+            .and_then(|p| p.interfaces.iter().find(|i| i.iface_name == iface_name))
+            .map(|i| i.kind.clone());
         let iface = if dest_is_loopback {
             "loopback".to_string()
         } else {
-            os::iface_type(&iface_name)
+            path_iface_type.unwrap_or_else(|| os::iface_type(&iface_name))
         };
-        let iface_is_tunnel = is_tunnel_iface_name(&iface_name);
+        LocalPath {
+            iface_is_tunnel: is_tunnel_iface_name(&iface_name),
+            iface_name,
+            iface,
+            local_addr,
+            remote_addr,
+            dest_is_loopback,
+            network_path,
+        }
+    }
 
+    /// Sends `samplesPerEndpoint` probes round-robin over the sockets, then
+    /// the bufferbloat load if one is due, and collects the stray replies.
+    fn run_burst(&mut self, iface_name: &str) -> Burst {
+        let cfg = Arc::clone(&self.cfg);
+        let secret = Arc::clone(&self.shared.secret);
+        let id = &self.target.endpoint.id;
+        let self_floor_ms = self
+            .self_floor
+            .as_mut()
+            .and_then(|p| p.measure(cfg.self_floor_probes))
+            .map(ns_to_ms);
+        let burst_seq = self.sequencer.next_burst();
+        self.reference_sequencer.next_burst();
+        let prober = self.prober.as_mut().expect("connected");
         let mut samples = Vec::with_capacity(cfg.samples_per_endpoint);
+        let mut reference_samples = Vec::new();
+        let mut send_failure: Option<io::Error> = None;
+        let mut send_errors = SendErrors::default();
+        let mut no_buffer_streak: u32 = 0;
+        let sockets = 1 + self.ecmp_probers.len();
+        let source_ports: Vec<u16> = std::iter::once(&*prober)
+            .chain(&self.ecmp_probers)
+            .map(|p| p.local_addr().map_or(0, |a| a.port()))
+            .collect();
+        let mut sample_ports = Vec::new();
+        let flow_labels: &[u32] = if self.flow_labels_on {
+            &cfg.ipv6_flow_labels
+        } else {
+            &[]
+        };
+        let labelled = !flow_labels.is_empty() && prober.peer_addr().is_ok_and(|a| a.is_ipv6());
+        let mut sample_labels = Vec::new();
+        let stamped = self.target.endpoint.packet_version == LATO_V2_VERSION;
+        let mut sample_legs = Vec::new();
+        let mut reflector_clock: Option<ReflectorClock> = None;
+        let mut ecn: Option<EcnCounts> = None;
+        let mut timestamp_source: Option<TimestampSource> = None;
+        let counters_before = os::iface_counters(iface_name);
         let mut next_send = Instant::now();
+        let burst_started = next_send;
 
         for i in 0..cfg.samples_per_endpoint {
             if i > 0 {
-                next_send += spacing;
+                next_send += self.spacing;
                 sleep_until(next_send, cfg.pacing_spin_us);
             }
 
-            let nonce: u64 = rng.gen();
             let send_realtime_ns = os::realtime_now_ns();
             let send_mono_ns = os::monotonic_now_ns();
-            let msg = build_packet(seq, send_realtime_ns, nonce, secret.as_ref());
-            seq = seq.wrapping_add(1);
+            let msg = self.sequencer.packet(send_realtime_ns, &secret);
 
-            match prober.send_and_receive_rtt(&msg, send_realtime_ns, send_mono_ns, timeout) {
-                Ok(Some(rtt)) => samples.push(rtt),
-                Ok(None) => {}
+            let slot = i % sockets;
+            let socket = if slot == 0 {
+                &mut *prober
+            } else {
+                &mut self.ecmp_probers[slot - 1]
+            };
+            let mut label = 0;
+            if labelled && self.flow_labels_on {
+                let next = flow_labels[i % flow_labels.len()];
+                match socket.set_flow_label(next) {
+                    Ok(()) => label = next,
+                    Err(err) => {
+                        eprintln!("[!] {} flow labels off: {}", id, err);
+                        self.flow_labels_on = false;
+                    }
+                }
+            }
+            match socket.send_and_receive_rtt(&msg, send_realtime_ns, send_mono_ns, self.timeout) {
+                Ok(Some(rtt)) => {
+                    no_buffer_streak = 0;
+                    self.sequencer.answered();
+                    samples.push(rtt);
+                    let source = socket.timestamp_source();
+                    timestamp_source = Some(timestamp_source.map_or(source, |s| s.min(source)));
+                    if sockets > 1 {
+                        sample_ports.push(source_ports[slot]);
+                    }
+                    if labelled {
+                        sample_labels.push(label);
+                    }
+                    if stamped {
+                        let timing = socket.reflector_timing();
+                        if let Some(t) = timing {
+                            reflector_clock = Some(t.sync.into());
+                        }
+                        sample_legs.push(timing.map(SampleLegs::from));
+                    }
+                    if let Some(mark) = socket.reply_ecn() {
+                        ecn.get_or_insert_default().count(mark);
+                    }
+                    if let Some(reference) = self.reference.as_mut() {
+                        let send_realtime_ns = os::realtime_now_ns();
+                        let send_mono_ns = os::monotonic_now_ns();
+                        let msg = self.reference_sequencer.packet(send_realtime_ns, &secret);
+                        let rtt = reference
+                            .send_and_receive_rtt(
+                                &msg,
+                                send_realtime_ns,
+                                send_mono_ns,
+                                self.timeout,
+                            )
+                            .unwrap_or(None);
+                        reference_samples.push(rtt);
+                    }
+                }
+                Ok(None) => no_buffer_streak = 0,
                 Err(err) => {
-                    eprintln!("[!!] {} send/recv failed: {}", target.endpoint.id, err);
+                    self.shared.status.send_error(id);
+                    let failure = SendFailure::of(&err);
+                    failure.count(&mut send_errors);
+                    if failure == SendFailure::NoBuffer && no_buffer_streak < NO_BUFFER_RETRIES {
+                        // Give the queue time to drain before the next probe.
+                        next_send += NO_BUFFER_PAUSE * (1 << no_buffer_streak);
+                        no_buffer_streak += 1;
+                        continue;
+                    }
+                    eprintln!("[!!] {} send/recv failed: {}", id, err);
+                    let outage = Outage::of(&err);
+                    send_failure.get_or_insert(err);
+                    // Without a route the rest of the burst fails the same way.
+                    if outage == Outage::Path {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let burst_ms = burst_started.elapsed().as_secs_f64() * MS_PER_SEC;
+        let iface_traffic = counters_before
+            .zip(os::iface_counters(iface_name))
+            .and_then(|(before, after)| after.since(&before, burst_ms));
+
+        let mut loaded_samples = Vec::new();
+        if let Some(load) = &cfg.bufferbloat_load {
+            if self.bursts_since_load == 0 {
+                let probing = bloat::Probing {
+                    secret: &secret,
+                    spacing: self.spacing,
+                    timeout: self.timeout,
+                    pacing_spin_us: cfg.pacing_spin_us,
+                };
+                let load = bloat::Load {
+                    streams: 1,
+                    mbps: load.mbps,
+                    duration: Duration::from_millis(load.duration_ms),
+                };
+                match bloat::probe_under_load(
+                    prober,
+                    self.target.bind_ip,
+                    &load,
+                    &probing,
+                    &mut self.sequencer,
+                ) {
+                    Ok((samples, _)) => loaded_samples = samples,
+                    Err(err) => eprintln!("[!] {} bufferbloat load failed: {}", id, err),
                 }
             }
+            self.bursts_since_load = (self.bursts_since_load + 1) % load.every_bursts;
+        }
+
+        let mut strays = prober.take_strays();
+        for extra in &mut self.ecmp_probers {
+            strays.extend(extra.take_strays());
+        }
+        let late = self.sequencer.count_late(&strays, &secret);
+        if let Some(reference) = self.reference.as_mut() {
+            // Its late echoes say nothing about the endpoint.
+            reference.take_strays();
         }
 
-        if samples.is_empty() {
-            empty_burst_streak += 1;
+        Burst {
+            burst_seq,
+            samples,
+            reference_samples,
+            loaded_samples,
+            sample_ports,
+            sample_labels,
+            sample_legs,
+            reflector_clock,
+            ecn,
+            timestamp_source,
+            self_floor_ms,
+            iface_traffic,
+            late,
+            send_errors,
+            send_failure,
+        }
+    }
+
+    /// Hands the burst's outcome to failover and the transport ladder.
+    /// Returns whether the path is down and whether the transport moved.
+    fn track_outage(&mut self, burst: &Burst) -> Result<(bool, bool), Stop> {
+        let answered = !burst.samples.is_empty();
+        if answered {
+            self.empty_burst_streak = 0;
         } else {
-            empty_burst_streak = 0;
+            self.empty_burst_streak += 1;
+        }
+        let outage_event = match &burst.send_failure {
+            Some(err) => self.failover.failed(err, Instant::now()),
+            None if answered => self.failover.answered(Instant::now()),
+            None if self.empty_burst_streak >= RECONNECT_EMPTY_BURSTS => self
+                .failover
+                .silent(self.empty_burst_streak, Instant::now()),
+            None => None,
+        };
+        if let Some(ev) = outage_event {
+            self.event(ev)?;
+        }
+        let path_down = burst
+            .send_failure
+            .as_ref()
+            .is_some_and(|err| Outage::of(err) == Outage::Path);
+        // A local outage says nothing about the transport.
+        let transport_event = match (answered, path_down) {
+            (true, _) => self.transports.answered(),
+            (false, false) => self.transports.silent(),
+            (false, true) => None,
+        };
+        let switch_transport = transport_event.is_some();
+        if let Some(ev) = transport_event {
+            self.event(ev)?;
         }
+        Ok((path_down, switch_transport))
+    }
 
-        let (mn, p05, med) = summarize(&samples);
-        let notes = physics_notes(
+    fn build_record(
+        &mut self,
+        burst: Burst,
+        path: LocalPath,
+        utun_report: os::UtunReport,
+    ) -> BurstRecord {
+        let cfg = &self.cfg;
+        let target = &self.target;
+        let ((mn, p05, med), outliers_filtered) = match cfg.outlier_mad_k {
+            Some(k) => {
+                let (kept, dropped) = hampel_filter_ns(&burst.samples, k);
+                (summarize(&kept), dropped)
+            }
+            None => (summarize(&burst.samples), 0),
+        };
+        let busy = burst
+            .iface_traffic
+            .and_then(|t| t.bytes_per_sec())
+            .is_some_and(|rate| rate >= BUSY_IFACE_BYTES_PER_SEC);
+        let bufferbloat = match (p05, summarize(&burst.loaded_samples).2) {
+            (Some(idle), Some(loaded)) => Some(Bufferbloat::new(
+                BloatSource::Load,
+                ns_to_ms(idle),
+                ns_to_ms(loaded),
+            )),
+            _ => match (busy, self.idle_floor.p05_ns(), med) {
+                (true, Some(idle), Some(loaded)) => Some(Bufferbloat::new(
+                    BloatSource::Traffic,
+                    ns_to_ms(idle),
+                    ns_to_ms(loaded),
+                )),
+                _ => None,
+            },
+        };
+        if let Some(p05) = p05.filter(|_| !busy) {
+            self.idle_floor.observe(p05);
+        }
+        // Records carry ms; everything above stayed in integer ns.
+        let (mn, p05, med) = (mn.map(ns_to_ms), p05.map(ns_to_ms), med.map(ns_to_ms));
+        let samples: Vec<f64> = burst.samples.into_iter().map(ns_to_ms).collect();
+        let reference_samples: Vec<Option<f64>> = burst
+            .reference_samples
+            .into_iter()
+            .map(|rtt| rtt.map(ns_to_ms))
+            .collect();
+        let loaded_samples: Vec<f64> = burst.loaded_samples.into_iter().map(ns_to_ms).collect();
+        let histogram = if cfg.record_histogram {
+            Some(Histogram::from_samples(&samples))
+        } else {
+            None
+        };
+        let tunnel_up = utun_report.active || path.iface_is_tunnel;
+        let mut notes = physics_notes(
             &target.endpoint.region_hint,
             &cfg.claimed_egress_region,
             mn,
             cfg.physics_mismatch_threshold_ms,
         );
+        if self.shared.limits.clean_only && tunnel_up {
+            notes.push(BASELINE_TUNNEL_NOTE.to_string());
+        }
 
         let utun_interfaces: Vec<UtunInterface> = utun_report
             .interfaces
@@ -343,60 +2199,176 @@ fn endpoint_worker(
                 has_non_loopback_addr: i.has_non_loopback_addr,
             })
             .collect();
+        let raw = cfg.record_raw_samples;
 
-        let rec = BurstRecord {
+        BurstRecord {
             ts_unix_ms: now_unix_ms(),
+            session_id: target.session_id.to_string(),
+            burst_id: Uuid::new_v4().to_string(),
             endpoint_id: target.endpoint.id.clone(),
             host: target.endpoint.host.clone(),
             port: target.endpoint.port,
+            transport: self.prober.as_ref().expect("connected").transport(),
+            timestamp_source: burst.timestamp_source,
             probe_path: target.path_id.clone(),
-            probe_bind_iface: target
-                .bind_iface
-                .clone()
-                .unwrap_or_else(String::new),
-            probe_bind_ip: target
-                .bind_ip
-                .map(|ip| ip.to_string())
-                .unwrap_or_else(String::new),
-            local_addr,
+            probe_path_uuid: target.path_uuid.to_string(),
+            probe_bind_iface: target.bind_iface.clone().unwrap_or_default(),
+            probe_bind_ip: target.bind_ip.map(|ip| ip.to_string()).unwrap_or_default(),
+            local_addr: path.local_addr,
+            remote_addr: path.remote_addr,
             region_hint: target.endpoint.region_hint.clone(),
-            samples_ms: samples,
+            // The writer needs them for the hourly p05 and drops them.
+            samples_ms: if raw || cfg.privacy_mode == PrivacyMode::Features {
+                samples
+            } else {
+                Vec::new()
+            },
             min_ms: mn,
             p05_ms: p05,
             median_ms: med,
-            iface,
-            iface_name: iface_name.clone(),
-            iface_is_tunnel,
+            outliers_filtered,
+            histogram,
+            iface: path.iface,
+            iface_name: path.iface_name,
+            iface_is_tunnel: path.iface_is_tunnel,
             utun_present: utun_report.present,
             utun_active: utun_report.active,
             utun_interfaces,
-            dest_is_loopback,
+            dest_is_loopback: path.dest_is_loopback,
+            access_floor_ms: target.access_floor_ms,
+            self_floor_ms: burst.self_floor_ms,
+            reference_samples_ms: if raw { reference_samples } else { Vec::new() },
+            iface_traffic: burst.iface_traffic,
+            network_path: path.network_path,
+            relay: os::system_relay().map(str::to_string),
+            loaded_samples_ms: if raw { loaded_samples } else { Vec::new() },
+            bufferbloat,
+            socket_epoch: self.sequencer.epoch(),
+            burst_seq: burst.burst_seq,
+            late_replies: burst.late.this_burst,
+            late_replies_prior: burst.late.prior_bursts,
+            duplicate_replies: burst.late.duplicates,
+            keepalives_sent: std::mem::take(&mut self.keepalives_sent),
+            send_errors: burst.send_errors,
+            ecn: burst.ecn,
+            sample_source_ports: if raw { burst.sample_ports } else { Vec::new() },
+            sample_flow_labels: if raw { burst.sample_labels } else { Vec::new() },
+            // A reflector that leaves the stamps at zero gives all nulls.
+            sample_legs: if raw && burst.sample_legs.iter().any(Option::is_some) {
+                burst.sample_legs
+            } else {
+                Vec::new()
+            },
+            reflector_clock: burst.reflector_clock,
             claimed_egress_region: cfg.claimed_egress_region.clone(),
             notes,
-        };
-
-        if tx.send(rec).is_err() {
-            break;
         }
+    }
 
-        if empty_burst_streak >= RECONNECT_EMPTY_BURSTS {
-            prober_opt = None;
-            burst_since_refresh = 0;
-        } else {
-            burst_since_refresh += 1;
+    /// Saves where the sequence stands, and answers bursts asked for over
+    /// the local API.
+    fn save(&self, rec: &BurstRecord) {
+        let id = &self.target.endpoint.id;
+        self.shared.state.update(
+            id,
+            WorkerState {
+                socket_epoch: self.sequencer.epoch(),
+                burst_seq: rec.burst_seq,
+                idle_p05_ns: self.idle_floor.recent(),
+            },
+            self.destination
+                .filter(|_| !rec.samples_ms.is_empty())
+                .map(|ip| (self.target.endpoint.host.as_str(), ip)),
+        );
+        // Features mode hands no burst out, not even to a local API caller.
+        for reply in self.shared.status.take_burst_requests(id) {
+            if self.cfg.privacy_mode != PrivacyMode::Features {
+                let _ = reply.send(rec.clone());
+            }
         }
-        last_utun_active = Some(utun_report.active);
+    }
 
+    /// Sleeps to the next tick, sending keepalives on the way, and starts
+    /// the interval over when a burst is asked for over the local API.
+    fn keepalive_wait(&mut self) {
         let now = Instant::now();
-        if now < next_tick {
-            sleep_until(next_tick, cfg.pacing_spin_us);
-            next_tick += interval;
+        if now >= self.next_tick {
+            self.next_tick = now + self.interval;
+            return;
+        }
+        let limits = self.shared.limits;
+        let id = &self.target.endpoint.id;
+        let status = &self.shared.status;
+        let mut keepalive_at = self.target.keepalive.map(|every| now + every);
+        let requested = loop {
+            let wake = keepalive_at.map_or(self.next_tick, |at| at.min(self.next_tick));
+            if limits.sleep_until_or(wake, self.cfg.pacing_spin_us, || status.burst_requested(id)) {
+                break true;
+            }
+            match (keepalive_at, self.target.keepalive) {
+                (Some(at), Some(every))
+                    if at < self.next_tick && !limits.expired() && !os::shutdown_requested() =>
+                {
+                    let probers = self.prober.iter_mut().chain(self.ecmp_probers.iter_mut());
+                    self.keepalives_sent += send_keepalives(probers, &self.shared.secret, id);
+                    keepalive_at = Some(at + every);
+                }
+                _ => break false,
+            }
+        };
+        if requested {
+            self.next_tick = Instant::now() + self.interval;
         } else {
-            next_tick = now + interval;
+            self.next_tick += self.interval;
+        }
+    }
+}
+
+fn open_self_floor(target: &ProbeTarget, cfg: &Config) -> Option<selffloor::SelfFloorProbe> {
+    if cfg.self_floor_probes == 0 {
+        return None;
+    }
+    match selffloor::SelfFloorProbe::new() {
+        Ok(p) => Some(p),
+        Err(err) => {
+            eprintln!(
+                "[!] {} self-floor reflector unavailable: {}",
+                target.endpoint.id, err
+            );
+            None
         }
     }
 }
 
+/// The address to bind `target` to when its interface lost the one its
+/// sockets use, e.g. after a DHCP lease changed it. `None` while the old
+/// address is still there, and while the interface has no address of the
+/// same family, in which case sends keep failing as `path_down`.
+fn new_bind_ip(target: &ProbeTarget) -> Option<IpAddr> {
+    if target.bind_ip_pinned {
+        return None;
+    }
+    let (iface, bound) = (target.bind_iface.as_deref()?, target.bind_ip?);
+    let ips = os::iface_ips(iface).ok()?;
+    if ips.contains(&bound) {
+        return None;
+    }
+    ips.into_iter().find(|ip| ip.is_ipv4() == bound.is_ipv4())
+}
+
+/// Whether two versions of a target open the same sockets.
+fn same_sockets(a: &ProbeTarget, b: &ProbeTarget) -> bool {
+    a.endpoint.host == b.endpoint.host
+        && a.endpoint.port == b.endpoint.port
+        && a.endpoint.https_port == b.endpoint.https_port
+        && a.endpoint.https_ca_file == b.endpoint.https_ca_file
+        && a.endpoint.transport_order() == b.endpoint.transport_order()
+        && a.bind_ip == b.bind_ip
+        && a.source_ports == b.source_ports
+        && a.endpoint.packet_version == b.endpoint.packet_version
+        && a.ecn == b.ecn
+}
+
 fn sleep_until(target: Instant, spin_us: u64) {
     let spin = Duration::from_micros(spin_us);
     loop {
@@ -438,20 +2410,79 @@ fn is_tunnel_iface_name(name: &str) -> bool {
 
 fn decode_if_flags(flags: u32) -> Vec<String> {
     let mut out = Vec::new();
-    if (flags & (libc::IFF_UP as u32)) != 0 {
+    if (flags & (IFF_UP as u32)) != 0 {
         out.push("UP".to_string());
     }
-    if (flags & (libc::IFF_RUNNING as u32)) != 0 {
+    if (flags & (IFF_RUNNING as u32)) != 0 {
         out.push("RUNNING".to_string());
     }
-    if (flags & (libc::IFF_LOOPBACK as u32)) != 0 {
+    if (flags & (IFF_LOOPBACK as u32)) != 0 {
         out.push("LOOPBACK".to_string());
     }
-    if (flags & (libc::IFF_POINTOPOINT as u32)) != 0 {
+    if (flags & (IFF_POINTOPOINT as u32)) != 0 {
         out.push("POINTOPOINT".to_string());
     }
-    if (flags & (libc::IFF_MULTICAST as u32)) != 0 {
+    if (flags & (IFF_MULTICAST as u32)) != 0 {
         out.push("MULTICAST".to_string());
     }
     out
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn features_mode_writes_no_addresses() {
+        let dir = testutil::temp_dir("main-features");
+        let path = dir.join("lattice.jsonl");
+        let (tx, rx) = mpsc::channel();
+        let hour = 3_600_000;
+        for ts_unix_ms in [hour, 2 * hour] {
+            let rec = BurstRecord {
+                endpoint_id: "a".to_string(),
+                session_id: "s".to_string(),
+                ts_unix_ms,
+                host: "192.0.2.7".to_string(),
+                probe_bind_iface: "en0".to_string(),
+                probe_bind_ip: "198.51.100.4".to_string(),
+                local_addr: "198.51.100.4:40000".to_string(),
+                remote_addr: "192.0.2.7:7".to_string(),
+                iface: "en0".to_string(),
+                samples_ms: vec![10.0, 12.0],
+                ..BurstRecord::default()
+            };
+            tx.send(OutputRecord::Burst(Box::new(rec))).unwrap();
+        }
+        for (event, detail) in [
+            (
+                BIND_ADDRESS_CHANGED_EVENT,
+                serde_json::json!({ "interface": "en0", "from": "198.51.100.4", "to": "198.51.100.5" }),
+            ),
+            (
+                DESTINATION_CHANGED_EVENT,
+                serde_json::json!({ "from": "192.0.2.7", "to": "192.0.2.8", "ttlSeconds": 60 }),
+            ),
+        ] {
+            let ev = EventRecord::new(event, Some("a".to_string()), detail);
+            tx.send(OutputRecord::Event(ev)).unwrap();
+        }
+        drop(tx);
+        writer_thread(
+            Sink::Single(path.clone()),
+            rx,
+            Arc::new(Status::new(&path)),
+            2,
+            None,
+            Some(FeatureLog::default()),
+            None,
+        );
+
+        let out = fs::read_to_string(&path).unwrap();
+        assert!(out.contains(lattice_core::FEATURE_RECORD_TYPE), "{out}");
+        for needle in ["192.0.2.", "198.51.100.", "en0"] {
+            assert!(!out.contains(needle), "{needle} in {out}");
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
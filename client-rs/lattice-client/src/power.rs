@@ -0,0 +1,127 @@
+use crate::{os, OutputRecord};
+use lattice_core::{EventRecord, PowerEvent, RESUME_EVENT, SUSPEND_EVENT};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How often the suspended-time clock is read between OS notifications.
+const CLOCK_POLL: Duration = Duration::from_millis(500);
+/// Suspended time gained between two polls beyond this is a sleep the OS did
+/// not announce (no notifier, or the watcher missed it).
+const MIN_UNANNOUNCED_SUSPEND_NS: u64 = 1_000_000_000;
+/// `source` of a resume found only from the clock.
+const CLOCK_SOURCE: &str = "clock";
+const NS_PER_MS: f64 = 1_000_000.0;
+
+/// Writes `suspend` and `resume` events for the rest of the capture. `None`
+/// on the channel stops the watcher.
+pub struct PowerWatch {
+    stop: mpsc::Sender<Option<PowerEvent>>,
+    handle: JoinHandle<()>,
+}
+
+impl PowerWatch {
+    pub fn stop(self) {
+        let _ = self.stop.send(None);
+        let _ = self.handle.join();
+    }
+}
+
+/// Follows OS sleep notifications where available and, on every platform,
+/// the clock that keeps running through suspend, so a resume is recorded
+/// with how long the machine slept even when no notification arrived.
+pub fn watch(tx: mpsc::Sender<OutputRecord>) -> PowerWatch {
+    let (event_tx, event_rx) = mpsc::channel::<Option<PowerEvent>>();
+    let notify = event_tx.clone();
+    let source = match os::watch_power(Box::new(move |ev| {
+        let _ = notify.send(Some(ev));
+    })) {
+        Ok(()) => os::POWER_SOURCE,
+        Err(err) => {
+            eprintln!("[!] sleep notifications unavailable ({err}); resumes are found from the clock only");
+            CLOCK_SOURCE
+        }
+    };
+
+    let handle = thread::spawn(move || {
+        let mut last = os::suspended_ns();
+        // Suspended-time reading when the OS announced a suspend.
+        let mut announced: Option<Option<u64>> = None;
+        loop {
+            let received = match event_rx.recv_timeout(CLOCK_POLL) {
+                Ok(None) | Err(RecvTimeoutError::Disconnected) => return,
+                Ok(Some(ev)) => Some(ev),
+                Err(RecvTimeoutError::Timeout) => None,
+            };
+            let now = os::suspended_ns();
+            let event = match received {
+                Some(PowerEvent::Suspending) => {
+                    announced = Some(now);
+                    Some(EventRecord::new(
+                        SUSPEND_EVENT,
+                        None,
+                        serde_json::json!({ "source": source }),
+                    ))
+                }
+                Some(PowerEvent::Resumed) => {
+                    let since = announced.take().flatten().or(last);
+                    Some(resume_event(source, since, now))
+                }
+                // Once a suspend is announced, the OS resume reports it.
+                None if announced.is_some() => None,
+                None => match (last, now) {
+                    (Some(prev), Some(cur))
+                        if cur.saturating_sub(prev) >= MIN_UNANNOUNCED_SUSPEND_NS =>
+                    {
+                        Some(resume_event(CLOCK_SOURCE, last, now))
+                    }
+                    _ => None,
+                },
+            };
+            last = now;
+            if let Some(ev) = event {
+                if tx.send(OutputRecord::Event(ev)).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    PowerWatch {
+        stop: event_tx,
+        handle,
+    }
+}
+
+fn resume_event(source: &str, since: Option<u64>, now: Option<u64>) -> EventRecord {
+    let suspended_ms = match (since, now) {
+        (Some(since), Some(now)) => Some(now.saturating_sub(since) as f64 / NS_PER_MS),
+        _ => None,
+    };
+    EventRecord::new(
+        RESUME_EVENT,
+        None,
+        serde_json::json!({ "source": source, "suspendedMs": suspended_ms }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resume_reports_the_suspended_time_when_the_clock_has_it() {
+        let ev = resume_event(CLOCK_SOURCE, Some(1_000_000_000), Some(3_500_000_000));
+        assert_eq!(ev.event, RESUME_EVENT);
+        assert_eq!(ev.endpoint_id, None);
+        assert_eq!(ev.detail["source"], CLOCK_SOURCE);
+        assert_eq!(ev.detail["suspendedMs"], 2500.0);
+
+        // A clock that went backwards is no sleep at all.
+        let ev = resume_event(CLOCK_SOURCE, Some(2), Some(1));
+        assert_eq!(ev.detail["suspendedMs"], 0.0);
+
+        let ev = resume_event(os::POWER_SOURCE, None, Some(1));
+        assert_eq!(ev.detail["source"], os::POWER_SOURCE);
+        assert!(ev.detail["suspendedMs"].is_null());
+    }
+}
@@ -0,0 +1,161 @@
+use lattice_core::{
+    now_unix_ms, p05_index, BurstRecord, EventRecord, FeatureRecord, BIND_ADDRESS_CHANGED_EVENT,
+    DESTINATION_CHANGED_EVENT, FEATURE_RECORD_TYPE,
+};
+use std::collections::BTreeMap;
+
+const MS_PER_HOUR: i64 = 3_600_000;
+
+/// Events whose detail names the host's or an anchor's addresses, or the
+/// interface they belong to.
+const ADDRESS_EVENTS: &[&str] = &[BIND_ADDRESS_CHANGED_EVENT, DESTINATION_CHANGED_EVENT];
+
+/// Whether `ev` may go into a features log, which keeps no addresses.
+pub fn keeps_event(ev: &EventRecord) -> bool {
+    !ADDRESS_EVENTS.contains(&ev.event.as_str())
+}
+
+/// One anchor's current hour.
+struct Hour {
+    start_ms: i64,
+    session_id: String,
+    bursts: usize,
+    samples: Vec<f64>,
+    tunnel_bursts: usize,
+}
+
+impl Hour {
+    fn new(start_ms: i64, session_id: &str) -> Self {
+        Hour {
+            start_ms,
+            session_id: session_id.to_string(),
+            bursts: 0,
+            samples: Vec::new(),
+            tunnel_bursts: 0,
+        }
+    }
+
+    fn into_record(self, endpoint_id: String) -> FeatureRecord {
+        let mut samples = self.samples;
+        samples.sort_by(f64::total_cmp);
+        let p05_ms = (!samples.is_empty()).then(|| samples[p05_index(samples.len())]);
+        FeatureRecord {
+            record_type: FEATURE_RECORD_TYPE.to_string(),
+            ts_unix_ms: now_unix_ms(),
+            session_id: self.session_id,
+            endpoint_id,
+            hour_start_unix_ms: self.start_ms,
+            bursts: self.bursts,
+            samples: samples.len(),
+            p05_ms,
+            tunnel_bursts: self.tunnel_bursts,
+        }
+    }
+}
+
+/// `privacyMode: "features"`: folds each burst into its anchor's hour and
+/// hands back the hour's record once a burst from a later hour arrives.
+/// Nothing else of a burst outlives the call.
+#[derive(Default)]
+pub struct FeatureLog {
+    hours: BTreeMap<String, Hour>,
+}
+
+impl FeatureLog {
+    pub fn push(&mut self, rec: &BurstRecord) -> Option<FeatureRecord> {
+        let start_ms = rec.ts_unix_ms - rec.ts_unix_ms.rem_euclid(MS_PER_HOUR);
+        let mut done = None;
+        if self
+            .hours
+            .get(&rec.endpoint_id)
+            .is_some_and(|h| h.start_ms != start_ms)
+        {
+            let hour = self.hours.remove(&rec.endpoint_id).expect("checked above");
+            done = Some(hour.into_record(rec.endpoint_id.clone()));
+        }
+        let hour = self
+            .hours
+            .entry(rec.endpoint_id.clone())
+            .or_insert_with(|| Hour::new(start_ms, &rec.session_id));
+        hour.bursts += 1;
+        hour.samples
+            .extend(rec.samples_ms.iter().copied().filter(|v| v.is_finite()));
+        if rec.utun_active || rec.iface_is_tunnel {
+            hour.tunnel_bursts += 1;
+        }
+        done
+    }
+
+    /// The unfinished hours, at the end of the capture.
+    pub fn finish(self) -> Vec<FeatureRecord> {
+        self.hours
+            .into_iter()
+            .map(|(id, hour)| hour.into_record(id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn burst(id: &str, ts_unix_ms: i64, samples: Vec<f64>) -> BurstRecord {
+        BurstRecord {
+            endpoint_id: id.to_string(),
+            session_id: "s".to_string(),
+            ts_unix_ms,
+            samples_ms: samples,
+            ..BurstRecord::default()
+        }
+    }
+
+    #[test]
+    fn events_naming_addresses_are_left_out() {
+        let event = |name| EventRecord::new(name, Some("a".to_string()), serde_json::json!({}));
+        assert!(!keeps_event(&event(BIND_ADDRESS_CHANGED_EVENT)));
+        assert!(!keeps_event(&event(DESTINATION_CHANGED_EVENT)));
+        assert!(keeps_event(&event(lattice_core::TRANSPORT_CHANGED_EVENT)));
+    }
+
+    #[test]
+    fn folds_bursts_into_hourly_features_per_anchor() {
+        let mut log = FeatureLog::default();
+        let hour = 10 * MS_PER_HOUR;
+        assert!(log.push(&burst("a", hour + 1, vec![12.0, 10.0])).is_none());
+        assert!(log.push(&burst("b", hour + 2, vec![30.0])).is_none());
+        let mut tunneled = burst("a", hour + 3, vec![f64::NAN, 11.0]);
+        tunneled.iface_is_tunnel = true;
+        assert!(log.push(&tunneled).is_none());
+
+        // A burst from the next hour closes only its own anchor's hour.
+        let done = log
+            .push(&burst("a", hour + MS_PER_HOUR, vec![20.0]))
+            .unwrap();
+        assert_eq!(done.record_type, FEATURE_RECORD_TYPE);
+        assert_eq!(done.endpoint_id, "a");
+        assert_eq!(done.session_id, "s");
+        assert_eq!(done.hour_start_unix_ms, hour);
+        assert_eq!(done.bursts, 2);
+        assert_eq!(done.samples, 3);
+        assert_eq!(done.p05_ms, Some(10.0));
+        assert_eq!(done.tunnel_bursts, 1);
+
+        let rest = log.finish();
+        assert_eq!(rest.len(), 2);
+        assert_eq!(
+            (rest[0].endpoint_id.as_str(), rest[0].hour_start_unix_ms),
+            ("a", hour + MS_PER_HOUR)
+        );
+        assert_eq!((rest[1].endpoint_id.as_str(), rest[1].bursts), ("b", 1));
+    }
+
+    #[test]
+    fn an_hour_of_lost_bursts_has_no_p05() {
+        let mut log = FeatureLog::default();
+        log.push(&burst("a", -1, Vec::new()));
+        let hours = log.finish();
+        assert_eq!(hours[0].hour_start_unix_ms, -MS_PER_HOUR);
+        assert_eq!((hours[0].bursts, hours[0].samples), (1, 0));
+        assert_eq!(hours[0].p05_ms, None);
+    }
+}
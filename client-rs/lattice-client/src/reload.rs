@@ -0,0 +1,160 @@
+//! Picks up edits to a running capture's config. The config and its
+//! overrides are polled rather than watched, which behaves the same on every
+//! platform and survives editors that replace the file on save.
+
+use crate::cli::{ConfigSource, ProbeArgs};
+use crate::{check_consent, filter_endpoints, lint, validate_config};
+use lattice_core::Config;
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How often the config files are checked for changes.
+pub const POLL: Duration = Duration::from_secs(1);
+
+/// Modification time and length of each file the config is layered from.
+pub struct ConfigWatch {
+    files: Vec<PathBuf>,
+    seen: Vec<Option<(SystemTime, u64)>>,
+}
+
+impl ConfigWatch {
+    pub fn new(source: &ConfigSource) -> Self {
+        let files: Vec<PathBuf> = source
+            .path()
+            .into_iter()
+            .chain(source.overrides())
+            .cloned()
+            .collect();
+        let seen = files.iter().map(|f| stamp(f)).collect();
+        ConfigWatch { files, seen }
+    }
+
+    /// Whether any file changed since the last call (or since `new`).
+    pub fn changed(&mut self) -> bool {
+        let now: Vec<_> = self.files.iter().map(|f| stamp(f)).collect();
+        if now == self.seen {
+            return false;
+        }
+        self.seen = now;
+        true
+    }
+}
+
+fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+/// Loads and checks the edited config the way `lattice probe` does at
+/// startup. Settings the writer or the control socket fixed at startup keep
+/// their running values; the names of the ones that were edited come back
+/// with the config.
+pub fn load(args: &ProbeArgs, running: &Config) -> io::Result<(Config, Vec<&'static str>)> {
+    let mut cfg = args.source.load()?;
+    if let Some(output) = &args.output {
+        cfg.output_path = output.display().to_string();
+    }
+    filter_endpoints(&mut cfg, &args.endpoints_filter)?;
+    validate_config(&cfg)?;
+    check_consent(&cfg, args.own_targets)?;
+    for lint in lint::lint_config(&cfg) {
+        println!("[!] config: {lint}");
+    }
+
+    let mut restart_needed = Vec::new();
+    let mut keep = |name: &'static str, edited: bool| {
+        if edited {
+            restart_needed.push(name);
+        }
+    };
+    keep("secretHex", cfg.secret_hex != running.secret_hex);
+    keep("outputPath", cfg.output_path != running.output_path);
+    keep(
+        "controlSocket",
+        cfg.control_socket != running.control_socket,
+    );
+    keep("localApi", cfg.local_api != running.local_api);
+    keep("stateDir", cfg.state_dir != running.state_dir);
+    keep("privacyMode", cfg.privacy_mode != running.privacy_mode);
+    keep(
+        "rollingEstimate",
+        differs(&cfg.rolling_estimate, &running.rolling_estimate),
+    );
+    keep("rotate", cfg.rotate != running.rotate);
+    keep(
+        "displayDecimals",
+        cfg.display_decimals != running.display_decimals,
+    );
+    keep("machineId", cfg.machine_id != running.machine_id);
+    cfg.secret_hex = running.secret_hex.clone();
+    cfg.output_path = running.output_path.clone();
+    cfg.control_socket = running.control_socket.clone();
+    cfg.local_api = running.local_api.clone();
+    cfg.state_dir = running.state_dir.clone();
+    cfg.privacy_mode = running.privacy_mode;
+    cfg.rolling_estimate = running.rolling_estimate.clone();
+    cfg.rotate = running.rotate.clone();
+    cfg.display_decimals = running.display_decimals;
+    cfg.machine_id = running.machine_id.clone();
+    Ok((cfg, restart_needed))
+}
+
+/// Compares through JSON, for config types without `PartialEq`.
+pub fn differs<T: Serialize>(a: &T, b: &T) -> bool {
+    serde_json::to_value(a).ok() != serde_json::to_value(b).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::temp_dir;
+    use clap::Parser;
+    use serde_json::json;
+
+    #[derive(Parser)]
+    struct Probe {
+        #[command(flatten)]
+        args: ProbeArgs,
+    }
+
+    fn write_config(path: &Path, secret: &str, interval_seconds: u64) {
+        let cfg = json!({
+            "secretHex": secret,
+            "endpoints": [{ "id": "a", "host": "127.0.0.1", "port": 9000 }],
+            "samplesPerEndpoint": 10,
+            "spacingMs": 10,
+            "timeoutMs": 1000,
+            "intervalSeconds": interval_seconds,
+            "outputPath": "out.jsonl",
+            "physicsMismatchThresholdMs": 5.0,
+        });
+        fs::write(path, serde_json::to_string_pretty(&cfg).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn edits_apply_except_for_settings_fixed_at_startup() {
+        let dir = temp_dir("reload");
+        let path = dir.join("config.json");
+        write_config(&path, &"00".repeat(32), 10);
+        let args = Probe::parse_from(["probe", path.to_str().unwrap()]).args;
+        let mut watch = ConfigWatch::new(&args.source);
+        assert!(!watch.changed());
+        let running = args.source.load().unwrap();
+
+        write_config(&path, &"11".repeat(32), 300);
+        assert!(watch.changed());
+        assert!(!watch.changed());
+        let (cfg, restart_needed) = load(&args, &running).unwrap();
+        assert_eq!(cfg.interval_seconds, 300);
+        assert_eq!(restart_needed, ["secretHex"]);
+        assert!(!differs(&cfg.secret_hex, &running.secret_hex));
+
+        // A broken edit is refused and the running config stays.
+        fs::write(&path, "{").unwrap();
+        assert!(watch.changed());
+        assert!(load(&args, &running).is_err());
+    }
+}
@@ -0,0 +1,579 @@
+use dns_parser::{Builder, Packet, QueryClass, QueryType, RData, ResponseCode};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::{mpsc, Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Answers are re-resolved no sooner than this, whatever their TTL.
+const MIN_TTL: Duration = Duration::from_secs(5);
+/// Nor later than this.
+const MAX_TTL: Duration = Duration::from_secs(3600);
+/// TTL assumed when no nameserver answered with one (hosts file, mDNS, no
+/// `/etc/resolv.conf`).
+const FALLBACK_TTL: Duration = Duration::from_secs(60);
+/// Wait before trying again after a lookup failed.
+const RETRY_AFTER_FAILURE: Duration = Duration::from_secs(10);
+/// How long one nameserver gets to answer the TTL query.
+const QUERY_TIMEOUT: Duration = Duration::from_millis(1500);
+/// How long a refresh waits on one host, the system lookup and the TTL
+/// queries together. A host still unanswered then counts as failed.
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(5);
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+const DNS_PORT: u16 = 53;
+const MAX_DNS_PACKET: usize = 1232;
+
+/// What a host last resolved to.
+#[derive(Debug, Clone)]
+pub struct Resolved {
+    pub addrs: Vec<IpAddr>,
+    pub ttl: Duration,
+}
+
+impl Resolved {
+    /// The first address in the bind family, the same pick as a connect by
+    /// name.
+    pub fn pick(&self, bind_ip: Option<IpAddr>) -> io::Result<IpAddr> {
+        match bind_ip {
+            Some(bind) => self
+                .addrs
+                .iter()
+                .find(|ip| ip.is_ipv4() == bind.is_ipv4())
+                .copied()
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "no resolved addresses for bind family",
+                    )
+                }),
+            None => self
+                .addrs
+                .first()
+                .copied()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no resolved addresses")),
+        }
+    }
+}
+
+struct Entry {
+    /// Last good answer, kept through failed refreshes.
+    answer: Option<Resolved>,
+    /// Why the first lookup failed, while there is no answer yet.
+    error: Option<(io::ErrorKind, String)>,
+    refresh_at: Instant,
+}
+
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<String, Entry>,
+}
+
+/// Where answers come from: the system resolver and the nameservers in
+/// `/etc/resolv.conf` (`SystemLookup`).
+trait HostLookup: Send + Sync {
+    /// The host's addresses, without waiting on a TTL query.
+    fn addrs(&self, host: &str) -> io::Result<Vec<IpAddr>>;
+    /// The host's addresses and how long to keep them.
+    fn resolve(&self, host: &str) -> io::Result<Resolved>;
+}
+
+struct SystemLookup;
+
+impl HostLookup for SystemLookup {
+    fn addrs(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        system_addrs(host)
+    }
+
+    fn resolve(&self, host: &str) -> io::Result<Resolved> {
+        resolve(host)
+    }
+}
+
+/// Resolves endpoint hosts on a background thread and caches each answer
+/// for its DNS TTL, so workers never wait on DNS between bursts. Expired
+/// entries keep serving the old answer until the refresh lands.
+pub struct Resolver {
+    cache: Mutex<Cache>,
+    wake: Condvar,
+    source: Arc<dyn HostLookup>,
+    /// `LOOKUP_TIMEOUT`, shorter in tests.
+    timeout: Duration,
+}
+
+impl Resolver {
+    /// Seeds the cache with the system resolver's answer for each of `hosts`
+    /// so the first bursts need not wait, then learns their TTLs and keeps
+    /// them fresh in the background. A host the system cannot resolve starts
+    /// from the addresses in `known` (saved by an earlier run), if any.
+    pub fn start<'a>(
+        hosts: impl IntoIterator<Item = &'a str>,
+        known: &BTreeMap<String, Vec<IpAddr>>,
+    ) -> Arc<Resolver> {
+        let resolver = Arc::new(Resolver::new(
+            hosts,
+            known,
+            Arc::new(SystemLookup),
+            Instant::now(),
+        ));
+        let background = Arc::clone(&resolver);
+        thread::spawn(move || background.refresh_loop());
+        resolver
+    }
+
+    /// `start` without the background thread, answering from `source`; each
+    /// seeded host is due for a refresh at `now`.
+    fn new<'a>(
+        hosts: impl IntoIterator<Item = &'a str>,
+        known: &BTreeMap<String, Vec<IpAddr>>,
+        source: Arc<dyn HostLookup>,
+        now: Instant,
+    ) -> Resolver {
+        let mut cache = Cache::default();
+        for host in hosts {
+            if host.parse::<IpAddr>().is_ok() || cache.entries.contains_key(host) {
+                continue;
+            }
+            let (answer, error) = match (source.addrs(host), known.get(host)) {
+                (Ok(addrs), _) => (
+                    Some(Resolved {
+                        addrs,
+                        ttl: FALLBACK_TTL,
+                    }),
+                    None,
+                ),
+                (Err(err), Some(addrs)) if !addrs.is_empty() => {
+                    eprintln!("[!] {host}: lookup failed ({err}); using the last known address until it resolves");
+                    (
+                        Some(Resolved {
+                            addrs: addrs.clone(),
+                            ttl: FALLBACK_TTL,
+                        }),
+                        None,
+                    )
+                }
+                (Err(err), _) => (None, Some((err.kind(), err.to_string()))),
+            };
+            cache.entries.insert(
+                host.to_string(),
+                Entry {
+                    answer,
+                    error,
+                    refresh_at: now,
+                },
+            );
+        }
+        Resolver {
+            cache: Mutex::new(cache),
+            wake: Condvar::new(),
+            source,
+            timeout: LOOKUP_TIMEOUT,
+        }
+    }
+
+    /// The cache, also after a thread panicked holding it: every entry is
+    /// whole between statements.
+    fn cache(&self) -> MutexGuard<'_, Cache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The cached answer for `host`, never blocking on DNS. `None` while the
+    /// first lookup is still running; an error when it failed. IP literals
+    /// answer at once.
+    pub fn lookup(&self, host: &str) -> Option<io::Result<Resolved>> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Some(Ok(Resolved {
+                addrs: vec![ip],
+                ttl: MAX_TTL,
+            }));
+        }
+        let mut cache = self.cache();
+        let Some(entry) = cache.entries.get(host) else {
+            cache.entries.insert(
+                host.to_string(),
+                Entry {
+                    answer: None,
+                    error: None,
+                    refresh_at: Instant::now(),
+                },
+            );
+            self.wake.notify_one();
+            return None;
+        };
+        match (&entry.answer, &entry.error) {
+            (Some(answer), _) => Some(Ok(answer.clone())),
+            (None, Some((kind, msg))) => Some(Err(io::Error::new(*kind, msg.clone()))),
+            (None, None) => None,
+        }
+    }
+
+    fn refresh_loop(&self) {
+        loop {
+            self.refresh_due(Instant::now());
+            // Hosts `lookup` added since are due at once, so none is missed
+            // between the refresh and the wait.
+            let cache = self.cache();
+            let now = Instant::now();
+            match cache.entries.values().map(|e| e.refresh_at).min() {
+                Some(at) if at <= now => {}
+                Some(at) => drop(
+                    self.wake
+                        .wait_timeout(cache, at - now)
+                        .unwrap_or_else(|e| e.into_inner()),
+                ),
+                None => drop(self.wake.wait(cache).unwrap_or_else(|e| e.into_inner())),
+            }
+        }
+    }
+
+    /// Re-resolves every host due by `now`, each on its own thread and
+    /// without holding the cache, so neither a slow nameserver nor a slow
+    /// host holds up the others or the workers. A host not answered within
+    /// the timeout fails; its thread is left to finish on its own. Each is
+    /// due again a TTL after `now`, or `RETRY_AFTER_FAILURE` after a failure.
+    fn refresh_due(&self, now: Instant) {
+        let due: Vec<String> = self
+            .cache()
+            .entries
+            .iter()
+            .filter(|(_, e)| e.refresh_at <= now)
+            .map(|(host, _)| host.clone())
+            .collect();
+        if due.is_empty() {
+            return;
+        }
+        let (tx, rx) = mpsc::channel();
+        for host in &due {
+            let (tx, host, source) = (tx.clone(), host.clone(), Arc::clone(&self.source));
+            thread::spawn(move || {
+                let result = source.resolve(&host);
+                let _ = tx.send((host, result));
+            });
+        }
+        drop(tx);
+        let deadline = Instant::now() + self.timeout;
+        let mut results = HashMap::with_capacity(due.len());
+        while results.len() < due.len() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match rx.recv_timeout(left) {
+                Ok((host, result)) => {
+                    results.insert(host, result);
+                }
+                Err(_) => break,
+            }
+        }
+        let mut cache = self.cache();
+        for host in due {
+            let result = results.remove(&host).unwrap_or_else(|| {
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("no answer within {}ms", self.timeout.as_millis()),
+                ))
+            });
+            let Some(entry) = cache.entries.get_mut(&host) else {
+                continue;
+            };
+            match result {
+                Ok(answer) => {
+                    entry.refresh_at = now + answer.ttl;
+                    entry.answer = Some(answer);
+                    entry.error = None;
+                }
+                Err(err) => {
+                    eprintln!("[!] resolving {} failed: {}", host, err);
+                    entry.refresh_at = now + RETRY_AFTER_FAILURE;
+                    if entry.answer.is_none() {
+                        entry.error = Some((err.kind(), err.to_string()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Addresses in the system resolver's order (hosts file and address
+/// selection included), held for the TTL the nameservers give them.
+fn resolve(host: &str) -> io::Result<Resolved> {
+    let addrs = system_addrs(host)?;
+    let ttl = query_ttl(host)
+        .unwrap_or(FALLBACK_TTL)
+        .clamp(MIN_TTL, MAX_TTL);
+    Ok(Resolved { addrs, ttl })
+}
+
+fn system_addrs(host: &str) -> io::Result<Vec<IpAddr>> {
+    let addrs: Vec<IpAddr> = (host, 0).to_socket_addrs()?.map(|a| a.ip()).collect();
+    if addrs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no resolved addresses",
+        ));
+    }
+    Ok(addrs)
+}
+
+fn nameservers() -> Vec<IpAddr> {
+    fs::read_to_string(RESOLV_CONF)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.strip_prefix("nameserver"))
+        .filter_map(|rest| rest.trim().parse().ok())
+        .collect()
+}
+
+/// The smallest TTL on the host's A and AAAA answers (CNAMEs included),
+/// from the first nameserver in `/etc/resolv.conf` that answers both.
+fn query_ttl(host: &str) -> Option<Duration> {
+    nameservers().into_iter().find_map(|ns| {
+        let a = query_min_ttl(ns, host, QueryType::A).ok()?;
+        let aaaa = query_min_ttl(ns, host, QueryType::AAAA).ok()?;
+        a.into_iter()
+            .chain(aaaa)
+            .min()
+            .map(|s| Duration::from_secs(s.into()))
+    })
+}
+
+/// `None` when the name has no records of `qtype`.
+fn query_min_ttl(ns: IpAddr, host: &str, qtype: QueryType) -> io::Result<Option<u32>> {
+    let id: u16 = rand::random();
+    let mut query = Builder::new_query(id, true);
+    query.add_question(host, false, qtype, QueryClass::IN);
+    let query = query
+        .build()
+        .map_err(|_| io::Error::other("DNS query truncated"))?;
+    let bind: SocketAddr = match ns {
+        IpAddr::V4(_) => (IpAddr::from([0u8; 4]), 0).into(),
+        IpAddr::V6(_) => (IpAddr::from([0u16; 8]), 0).into(),
+    };
+    let socket = UdpSocket::bind(bind)?;
+    socket.connect((ns, DNS_PORT))?;
+    socket.set_read_timeout(Some(QUERY_TIMEOUT))?;
+    socket.send(&query)?;
+    let mut buf = [0u8; MAX_DNS_PACKET];
+    loop {
+        let n = socket.recv(&mut buf)?;
+        let Ok(reply) = Packet::parse(&buf[..n]) else {
+            continue;
+        };
+        if reply.header.id != id || reply.header.query {
+            continue;
+        }
+        if reply.header.response_code != ResponseCode::NoError {
+            return Err(io::Error::other(format!(
+                "nameserver answered {:?}",
+                reply.header.response_code
+            )));
+        }
+        return Ok(reply
+            .answers
+            .iter()
+            .filter(|rr| matches!(rr.data, RData::A(_) | RData::AAAA(_) | RData::CNAME(_)))
+            .map(|rr| rr.ttl)
+            .min());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const HOST: &str = "anchor.example";
+
+    /// Answers with whatever the test last set for a host, counting calls.
+    #[derive(Default)]
+    struct Scripted {
+        answers: Mutex<HashMap<String, (Vec<IpAddr>, Duration)>>,
+        resolves: Mutex<usize>,
+        stalls: Mutex<HashMap<String, Duration>>,
+    }
+
+    impl Scripted {
+        fn set(&self, host: &str, answer: Option<(Vec<IpAddr>, Duration)>) {
+            let mut answers = self.answers.lock().unwrap();
+            match answer {
+                Some(answer) => answers.insert(host.to_string(), answer),
+                None => answers.remove(host),
+            };
+        }
+
+        /// Makes every resolve of `host` take `delay` first.
+        fn stall(&self, host: &str, delay: Duration) {
+            self.stalls.lock().unwrap().insert(host.to_string(), delay);
+        }
+
+        fn resolves(&self) -> usize {
+            *self.resolves.lock().unwrap()
+        }
+    }
+
+    impl HostLookup for Scripted {
+        fn addrs(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+            self.resolve(host).map(|r| r.addrs)
+        }
+
+        fn resolve(&self, host: &str) -> io::Result<Resolved> {
+            *self.resolves.lock().unwrap() += 1;
+            let stall = self.stalls.lock().unwrap().get(host).copied();
+            if let Some(delay) = stall {
+                thread::sleep(delay);
+            }
+            match self.answers.lock().unwrap().get(host) {
+                Some((addrs, ttl)) => Ok(Resolved {
+                    addrs: addrs.clone(),
+                    ttl: *ttl,
+                }),
+                None => Err(io::Error::new(io::ErrorKind::NotFound, "NXDOMAIN")),
+            }
+        }
+    }
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(192, 0, 2, last))
+    }
+
+    fn addrs_of(resolver: &Resolver, host: &str) -> Vec<IpAddr> {
+        resolver.lookup(host).unwrap().unwrap().addrs
+    }
+
+    fn resolver(hosts: &[&str], source: &Arc<Scripted>, now: Instant) -> Resolver {
+        Resolver::new(
+            hosts.iter().copied(),
+            &BTreeMap::new(),
+            Arc::clone(source) as Arc<dyn HostLookup>,
+            now,
+        )
+    }
+
+    #[test]
+    fn ip_literals_answer_at_once_and_stay_out_of_the_cache() {
+        let source = Arc::new(Scripted::default());
+        let now = Instant::now();
+        let resolver = resolver(&["192.0.2.1", "2001:db8::1"], &source, now);
+        for (host, want) in [
+            ("192.0.2.1", ip(1)),
+            ("2001:db8::1", "2001:db8::1".parse().unwrap()),
+        ] {
+            let answer = resolver.lookup(host).unwrap().unwrap();
+            assert_eq!(answer.addrs, [want]);
+            assert_eq!(answer.ttl, MAX_TTL);
+        }
+        resolver.refresh_due(now + MAX_TTL);
+        assert_eq!(source.resolves(), 0);
+        assert!(resolver.cache().entries.is_empty());
+    }
+
+    #[test]
+    fn first_lookup_of_a_new_host_waits_for_the_background() {
+        let source = Arc::new(Scripted::default());
+        source.set(HOST, Some((vec![ip(1)], Duration::from_secs(30))));
+        let resolver = resolver(&[], &source, Instant::now());
+        assert!(resolver.lookup(HOST).is_none());
+        assert!(resolver.lookup(HOST).is_none());
+        assert!(resolver.lookup("gone.example").is_none());
+        resolver.refresh_due(Instant::now());
+        assert_eq!(source.resolves(), 2);
+        assert_eq!(addrs_of(&resolver, HOST), [ip(1)]);
+        let err = resolver.lookup("gone.example").unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn seeds_from_the_system_then_from_known_addresses() {
+        let source = Arc::new(Scripted::default());
+        source.set(HOST, Some((vec![ip(1)], Duration::from_secs(30))));
+        let known = BTreeMap::from([("down.example".to_string(), vec![ip(9)])]);
+        let resolver = Resolver::new(
+            [HOST, "down.example", "gone.example"],
+            &known,
+            Arc::clone(&source) as Arc<dyn HostLookup>,
+            Instant::now(),
+        );
+        let seeded = resolver.lookup(HOST).unwrap().unwrap();
+        assert_eq!((seeded.addrs, seeded.ttl), (vec![ip(1)], FALLBACK_TTL));
+        assert_eq!(addrs_of(&resolver, "down.example"), [ip(9)]);
+        assert!(resolver.lookup("gone.example").unwrap().is_err());
+    }
+
+    #[test]
+    fn refreshes_after_the_ttl_or_sooner_after_a_failure() {
+        let source = Arc::new(Scripted::default());
+        let ttl = Duration::from_secs(30);
+        source.set(HOST, Some((vec![ip(1)], ttl)));
+        let start = Instant::now();
+        let resolver = resolver(&[HOST], &source, start);
+        let seeded = source.resolves();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        resolver.refresh_due(start);
+        assert_eq!(source.resolves(), seeded + 1);
+        resolver.refresh_due(at(29));
+        assert_eq!(source.resolves(), seeded + 1);
+        resolver.refresh_due(at(30));
+        assert_eq!(source.resolves(), seeded + 2);
+
+        source.set(HOST, None);
+        resolver.refresh_due(at(60));
+        assert_eq!(source.resolves(), seeded + 3);
+        resolver.refresh_due(at(69));
+        assert_eq!(source.resolves(), seeded + 3);
+        resolver.refresh_due(at(70));
+        assert_eq!(source.resolves(), seeded + 4);
+    }
+
+    #[test]
+    fn keeps_the_last_good_answer_through_failed_refreshes() {
+        let source = Arc::new(Scripted::default());
+        source.set(HOST, Some((vec![ip(1)], Duration::from_secs(30))));
+        let start = Instant::now();
+        let resolver = resolver(&[HOST], &source, start);
+        resolver.refresh_due(start);
+
+        source.set(HOST, None);
+        for secs in [30, 40, 50] {
+            resolver.refresh_due(start + Duration::from_secs(secs));
+            assert_eq!(addrs_of(&resolver, HOST), [ip(1)]);
+        }
+        source.set(HOST, Some((vec![ip(2), ip(3)], Duration::from_secs(30))));
+        resolver.refresh_due(start + Duration::from_secs(60));
+        assert_eq!(addrs_of(&resolver, HOST), [ip(2), ip(3)]);
+    }
+
+    #[test]
+    fn a_slow_host_times_out_without_holding_up_the_others() {
+        let source = Arc::new(Scripted::default());
+        source.set(HOST, Some((vec![ip(1)], Duration::from_secs(30))));
+        source.set("slow.example", Some((vec![ip(2)], Duration::from_secs(30))));
+        source.stall("slow.example", Duration::from_secs(2));
+        let mut resolver = resolver(&[], &source, Instant::now());
+        resolver.timeout = Duration::from_millis(200);
+        assert!(resolver.lookup(HOST).is_none());
+        assert!(resolver.lookup("slow.example").is_none());
+
+        let started = Instant::now();
+        resolver.refresh_due(Instant::now());
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(addrs_of(&resolver, HOST), [ip(1)]);
+        let err = resolver.lookup("slow.example").unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn answers_after_a_thread_panicked_holding_the_cache() {
+        let source = Arc::new(Scripted::default());
+        source.set(HOST, Some((vec![ip(1)], Duration::from_secs(30))));
+        let resolver = Arc::new(resolver(&[HOST], &source, Instant::now()));
+        let holder = Arc::clone(&resolver);
+        let _ = thread::spawn(move || {
+            let _cache = holder.cache();
+            panic!("worker panicked");
+        })
+        .join();
+        assert!(resolver.cache.is_poisoned());
+        assert_eq!(addrs_of(&resolver, HOST), [ip(1)]);
+        resolver.refresh_due(Instant::now());
+        assert_eq!(addrs_of(&resolver, HOST), [ip(1)]);
+    }
+}
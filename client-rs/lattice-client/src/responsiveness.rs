@@ -0,0 +1,358 @@
+use crate::bloat::{self, Load, Probing};
+use crate::cli::ResponsivenessArgs;
+use crate::sequence::Sequencer;
+use crate::status::{self, ControlRequest, Status};
+use crate::transport::Prober;
+use crate::ProbeTarget;
+use lattice_core::{
+    now_unix_ms, ns_to_ms, summarize, BloatSource, Bufferbloat, ResponsivenessRecord,
+    RESPONSIVENESS_RECORD_TYPE,
+};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::net::Shutdown;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_SECONDS: u64 = 10;
+pub const DEFAULT_STREAMS: usize = 4;
+pub const DEFAULT_MBPS: f64 = 50.0;
+const MAX_STREAMS: usize = 16;
+/// The most upload a caller may ask for, split across the streams.
+const MAX_MBPS: f64 = 1000.0;
+const MAX_DURATION_MS: u64 = 60_000;
+const MS_PER_SEC: u64 = 1000;
+/// Probes sent before the load starts, for the idle reference.
+const IDLE_PROBES: u32 = 20;
+/// Slack on top of the test itself while the requester waits: the worker
+/// picks the job up between bursts, so up to one interval late.
+const REPLY_GRACE: Duration = Duration::from_secs(10);
+
+/// Set while a test is waiting or running. Each test saturates the uplink,
+/// so a second one is refused rather than queued.
+static TEST_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Clears `TEST_RUNNING` when dropped. It travels with the job, so a test
+/// the requester gave up on still holds the flag until the worker is done
+/// with it.
+struct RunningGuard;
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        TEST_RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// A test as asked for over the control socket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponsivenessRequest {
+    /// Defaults to the first configured endpoint.
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    pub duration_ms: u64,
+    pub streams: usize,
+    pub mbps: f64,
+}
+
+impl ResponsivenessRequest {
+    fn validate(&self) -> Result<(), String> {
+        if self.duration_ms == 0 || self.duration_ms > MAX_DURATION_MS {
+            return Err(format!(
+                "durationMs must be between 1 and {MAX_DURATION_MS}"
+            ));
+        }
+        if self.streams == 0 || self.streams > MAX_STREAMS {
+            return Err(format!("streams must be between 1 and {MAX_STREAMS}"));
+        }
+        if !(self.mbps > 0.0 && self.mbps <= MAX_MBPS) {
+            return Err(format!("mbps must be > 0 and at most {MAX_MBPS}"));
+        }
+        Ok(())
+    }
+}
+
+/// A request handed to the endpoint worker that owns the endpoint's socket.
+pub struct Job {
+    pub request: ResponsivenessRequest,
+    pub reply: mpsc::Sender<ResponsivenessRecord>,
+    _running: RunningGuard,
+}
+
+/// Control-socket side: queues the test for the endpoint's worker and waits
+/// for its result.
+pub fn dispatch(
+    status: &Status,
+    mut request: ResponsivenessRequest,
+    endpoint_ids: &[String],
+    interval: Duration,
+) -> Result<ResponsivenessRecord, String> {
+    request.validate()?;
+    let endpoint = match request.endpoint.take() {
+        Some(id) if endpoint_ids.contains(&id) => id,
+        Some(id) => return Err(format!("unknown endpoint {id}")),
+        None => endpoint_ids
+            .first()
+            .cloned()
+            .ok_or("no endpoints configured")?,
+    };
+    request.endpoint = Some(endpoint.clone());
+    if TEST_RUNNING.swap(true, Ordering::SeqCst) {
+        return Err("another responsiveness test is running".to_string());
+    }
+    let running = RunningGuard;
+    // Only a test that would run costs a token.
+    if let Err(retry) = status.take_test(Instant::now()) {
+        let secs = retry.as_secs_f64().ceil() as u64;
+        return Err(format!("too many responsiveness tests, retry in {secs}s"));
+    }
+    let wait = Duration::from_millis(request.duration_ms) + interval + REPLY_GRACE;
+    let (reply, result) = mpsc::channel();
+    status.queue_job(
+        &endpoint,
+        Job {
+            request,
+            reply,
+            _running: running,
+        },
+    );
+    result
+        .recv_timeout(wait)
+        .map_err(|_| format!("{endpoint}: no result within {}s", wait.as_secs()))
+}
+
+/// Worker side: probes the endpoint idle, then under `streams` parallel
+/// uploads, through the worker's own socket. The test takes a burst number
+/// of its own.
+pub fn run_test(
+    prober: &mut Prober,
+    target: &ProbeTarget,
+    request: &ResponsivenessRequest,
+    probing: &Probing,
+    sequencer: &mut Sequencer,
+) -> io::Result<ResponsivenessRecord> {
+    sequencer.next_burst();
+    let start = Instant::now();
+    let (idle, _) = bloat::probe_window(
+        prober,
+        probing,
+        sequencer,
+        start,
+        start + probing.spacing * IDLE_PROBES,
+    );
+    let load = Load {
+        streams: request.streams,
+        mbps: request.mbps,
+        duration: Duration::from_millis(request.duration_ms),
+    };
+    let (loaded, sent) =
+        bloat::probe_under_load(prober, target.bind_ip, &load, probing, sequencer)?;
+    let (_, idle_p05, idle_p50) = summarize(&idle);
+    let idle_p05 = idle_p05.map(ns_to_ms);
+    let idle_p50 = idle_p50.map(ns_to_ms);
+    let loaded_p50 = summarize(&loaded).2.map(ns_to_ms);
+    Ok(ResponsivenessRecord {
+        record_type: RESPONSIVENESS_RECORD_TYPE.to_string(),
+        ts_unix_ms: now_unix_ms(),
+        session_id: target.session_id.to_string(),
+        endpoint_id: target.endpoint.id.clone(),
+        probe_path: target.path_id.clone(),
+        streams: request.streams,
+        mbps: request.mbps,
+        duration_ms: request.duration_ms,
+        loaded_lost: sent - loaded.len(),
+        idle_samples_ms: idle.into_iter().map(ns_to_ms).collect(),
+        loaded_samples_ms: loaded.into_iter().map(ns_to_ms).collect(),
+        idle_p50_ms: idle_p50,
+        loaded_p50_ms: loaded_p50,
+        rpm: loaded_p50.and_then(ResponsivenessRecord::rpm),
+        bufferbloat: idle_p05
+            .zip(loaded_p50)
+            .map(|(idle, loaded)| Bufferbloat::new(BloatSource::Load, idle, loaded)),
+    })
+}
+
+pub fn print_record(rec: &ResponsivenessRecord) {
+    let fmt = |v: Option<f64>| v.map_or("-".to_string(), |v| format!("{:.1}", v));
+    println!(
+        "[rpm] {} {} stream(s) at {} Mbit/s for {}ms: idle p50={}ms loaded p50={}ms lost={} rpm={} grade={}",
+        rec.endpoint_id,
+        rec.streams,
+        rec.mbps,
+        rec.duration_ms,
+        fmt(rec.idle_p50_ms),
+        fmt(rec.loaded_p50_ms),
+        rec.loaded_lost,
+        rec.rpm.map_or("-".to_string(), |r| format!("{:.0}", r)),
+        rec.bufferbloat.as_ref().map_or("-", |b| b.grade.as_str())
+    );
+}
+
+/// `lattice responsiveness`: asks a running prober to run the test and
+/// prints the result, which the prober also appends to its output.
+pub fn run(args: &ResponsivenessArgs) -> io::Result<()> {
+    let socket = status::resolve_socket(&args.source, args.socket.as_deref())?;
+    let request = ControlRequest::Responsiveness(ResponsivenessRequest {
+        endpoint: args.endpoint.clone(),
+        duration_ms: args.seconds * MS_PER_SEC,
+        streams: args.streams,
+        mbps: args.mbps,
+    });
+    let mut stream = status::connect(&socket)?;
+    let mut line = serde_json::to_vec(&request).map_err(io::Error::other)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+    stream.shutdown(Shutdown::Write)?;
+    let mut body = String::new();
+    stream.read_to_string(&mut body)?;
+    let reply: serde_json::Value =
+        serde_json::from_str(&body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if let Some(err) = reply.get("error").and_then(|e| e.as_str()) {
+        return Err(io::Error::other(err.to_string()));
+    }
+    if args.json {
+        print!("{body}");
+        return Ok(());
+    }
+    let rec: ResponsivenessRecord =
+        serde_json::from_value(reply).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    print_record(&rec);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn request(endpoint: Option<&str>) -> ResponsivenessRequest {
+        ResponsivenessRequest {
+            endpoint: endpoint.map(str::to_string),
+            duration_ms: 1000,
+            streams: 2,
+            mbps: 10.0,
+        }
+    }
+
+    fn result(request: &ResponsivenessRequest) -> ResponsivenessRecord {
+        ResponsivenessRecord {
+            record_type: RESPONSIVENESS_RECORD_TYPE.to_string(),
+            ts_unix_ms: 0,
+            session_id: String::new(),
+            endpoint_id: request.endpoint.clone().unwrap(),
+            probe_path: String::new(),
+            streams: request.streams,
+            mbps: request.mbps,
+            duration_ms: request.duration_ms,
+            idle_samples_ms: vec![10.0],
+            loaded_samples_ms: vec![40.0],
+            loaded_lost: 0,
+            idle_p50_ms: Some(10.0),
+            loaded_p50_ms: Some(40.0),
+            rpm: ResponsivenessRecord::rpm(40.0),
+            bufferbloat: None,
+        }
+    }
+
+    #[test]
+    fn requests_outside_the_limits_are_refused() {
+        assert!(request(None).validate().is_ok());
+        for (field, bad) in [
+            (
+                "durationMs",
+                ResponsivenessRequest {
+                    duration_ms: 0,
+                    ..request(None)
+                },
+            ),
+            (
+                "durationMs",
+                ResponsivenessRequest {
+                    duration_ms: MAX_DURATION_MS + 1,
+                    ..request(None)
+                },
+            ),
+            (
+                "streams",
+                ResponsivenessRequest {
+                    streams: 0,
+                    ..request(None)
+                },
+            ),
+            (
+                "streams",
+                ResponsivenessRequest {
+                    streams: MAX_STREAMS + 1,
+                    ..request(None)
+                },
+            ),
+            (
+                "mbps",
+                ResponsivenessRequest {
+                    mbps: f64::NAN,
+                    ..request(None)
+                },
+            ),
+            (
+                "mbps",
+                ResponsivenessRequest {
+                    mbps: 0.0,
+                    ..request(None)
+                },
+            ),
+            (
+                "mbps",
+                ResponsivenessRequest {
+                    mbps: MAX_MBPS + 1.0,
+                    ..request(None)
+                },
+            ),
+        ] {
+            let err = bad.validate().unwrap_err();
+            assert!(err.starts_with(field), "{err}");
+        }
+    }
+
+    #[test]
+    fn dispatch_hands_one_test_at_a_time_to_the_endpoint_worker() {
+        let status = Arc::new(Status::new(Path::new("out.jsonl")));
+        let ids = ["a".to_string(), "b".to_string()];
+        let interval = Duration::from_secs(1);
+        assert_eq!(
+            dispatch(&status, request(Some("z")), &ids, interval).unwrap_err(),
+            "unknown endpoint z"
+        );
+        assert_eq!(
+            dispatch(&status, request(None), &[], interval).unwrap_err(),
+            "no endpoints configured"
+        );
+
+        let requester = {
+            let status = Arc::clone(&status);
+            let ids = ids.clone();
+            thread::spawn(move || dispatch(&status, request(None), &ids, interval))
+        };
+        let job = loop {
+            if let Some(job) = status.take_job("a") {
+                break job;
+            }
+            thread::sleep(Duration::from_millis(5));
+        };
+        assert_eq!(job.request.endpoint.as_deref(), Some("a"));
+        assert_eq!(
+            dispatch(&status, request(Some("b")), &ids, interval).unwrap_err(),
+            "another responsiveness test is running"
+        );
+        job.reply.send(result(&job.request)).unwrap();
+        let rec = requester.join().unwrap().unwrap();
+        assert_eq!(rec.endpoint_id, "a");
+        assert_eq!(rec.rpm, Some(1500.0));
+        // The worker still holds the job, so the test is not over yet.
+        assert!(TEST_RUNNING.load(Ordering::SeqCst));
+        drop(job);
+        assert!(!TEST_RUNNING.load(Ordering::SeqCst));
+    }
+}
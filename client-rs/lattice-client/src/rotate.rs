@@ -0,0 +1,338 @@
+//! The writer's output files, rotated per `rotate` in the config. A rotated
+//! file is renamed next to the live one and compressed on a helper thread, so
+//! the writer never waits on the compressor.
+
+use lattice_core::{civil_from_days, now_unix_ms, LogCompression, LogRotation, RotateEvery};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::UNIX_EPOCH;
+
+const MS_PER_HOUR: i64 = 3_600_000;
+const MS_PER_DAY: i64 = 24 * MS_PER_HOUR;
+const ZSTD_LEVEL: i32 = 3;
+/// Extension of archives while they are being compressed.
+const PARTIAL_EXT: &str = "part";
+
+/// One output file the writer appends JSON lines to.
+pub struct LogFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    bytes: u64,
+    /// When the open file's content started, for its period and its
+    /// archive's name.
+    started_ms: i64,
+    rotation: Option<LogRotation>,
+    /// Written again at the top of every new file, so each archive carries
+    /// its session's header.
+    header: Option<Vec<u8>>,
+    archiving: Option<JoinHandle<()>>,
+}
+
+impl LogFile {
+    pub fn open(path: &Path, rotation: Option<LogRotation>) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = File::options().create(true).append(true).open(path)?;
+        let meta = file.metadata()?;
+        // An existing file may hold an earlier period's records; it dates
+        // from its last write.
+        let started_ms = match meta.modified() {
+            Ok(modified) if meta.len() > 0 => modified
+                .duration_since(UNIX_EPOCH)
+                .map_or_else(|_| now_unix_ms(), |d| d.as_millis() as i64),
+            _ => now_unix_ms(),
+        };
+        Ok(LogFile {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(file),
+            bytes: meta.len(),
+            started_ms,
+            rotation,
+            header: None,
+            archiving: None,
+        })
+    }
+
+    pub fn write_line<T: serde::Serialize>(&mut self, value: &T) -> io::Result<()> {
+        let mut line = serde_json::to_vec(value)?;
+        line.push(b'\n');
+        if self.rotation_due(line.len() as u64, now_unix_ms()) {
+            self.rotate()?;
+        }
+        self.append(&line)
+    }
+
+    /// Writes a session header and repeats it after each rotation.
+    pub fn write_header<T: serde::Serialize>(&mut self, value: &T) -> io::Result<()> {
+        self.write_line(value)?;
+        let mut line = serde_json::to_vec(value)?;
+        line.push(b'\n');
+        self.header = Some(line);
+        Ok(())
+    }
+
+    fn append(&mut self, line: &[u8]) -> io::Result<()> {
+        self.writer.write_all(line)?;
+        self.writer.flush()?;
+        self.bytes += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotation_due(&self, next_len: u64, now_ms: i64) -> bool {
+        let Some(rotation) = &self.rotation else {
+            return false;
+        };
+        if self.bytes == 0 {
+            return false;
+        }
+        let too_big = rotation.max_bytes > 0 && self.bytes + next_len > rotation.max_bytes;
+        let new_period = rotation
+            .every
+            .is_some_and(|every| period(every, now_ms) != period(every, self.started_ms));
+        too_big || new_period
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let Some(rotation) = self.rotation.clone() else {
+            return Ok(());
+        };
+        self.writer.flush()?;
+        // One archive at a time, so pruning sees every earlier one finished.
+        if let Some(previous) = self.archiving.take() {
+            let _ = previous.join();
+        }
+        let names = ArchiveNames::new(&self.path);
+        let archived = names.next_free(
+            &stamp(rotation.every, self.started_ms),
+            rotation.compression,
+        );
+        fs::rename(&self.path, &archived)?;
+        let file = File::options().create(true).append(true).open(&self.path)?;
+        self.writer = BufWriter::new(file);
+        self.bytes = 0;
+        self.started_ms = now_unix_ms();
+        if let Some(header) = self.header.clone() {
+            self.append(&header)?;
+        }
+        self.archiving = Some(thread::spawn(move || {
+            if let Err(err) = compress(&archived, rotation.compression) {
+                eprintln!("[!!] compressing {} failed: {}", archived.display(), err);
+            }
+            if rotation.keep > 0 {
+                if let Err(err) = names.prune(rotation.keep) {
+                    eprintln!("[!!] pruning old logs failed: {}", err);
+                }
+            }
+        }));
+        Ok(())
+    }
+}
+
+impl Drop for LogFile {
+    fn drop(&mut self) {
+        if let Some(archiving) = self.archiving.take() {
+            let _ = archiving.join();
+        }
+    }
+}
+
+/// UTC hour or day number.
+fn period(every: RotateEvery, unix_ms: i64) -> i64 {
+    match every {
+        RotateEvery::Hour => unix_ms.div_euclid(MS_PER_HOUR),
+        RotateEvery::Day => unix_ms.div_euclid(MS_PER_DAY),
+    }
+}
+
+/// `2024-05-01`, or `2024-05-01T13` when rotating hourly.
+fn stamp(every: Option<RotateEvery>, unix_ms: i64) -> String {
+    let (year, month, day) = civil_from_days(unix_ms.div_euclid(MS_PER_DAY));
+    let date = format!("{year:04}-{month:02}-{day:02}");
+    match every {
+        Some(RotateEvery::Hour) => {
+            format!("{date}T{:02}", unix_ms.rem_euclid(MS_PER_DAY) / MS_PER_HOUR)
+        }
+        _ => date,
+    }
+}
+
+/// Archives of one output file: `<stem>-<stamp>[-<n>].<ext>[.zst|.gz]` in
+/// its directory.
+struct ArchiveNames {
+    dir: PathBuf,
+    stem: String,
+    ext: String,
+}
+
+impl ArchiveNames {
+    fn new(path: &Path) -> Self {
+        ArchiveNames {
+            dir: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            stem: path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            ext: path
+                .extension()
+                .map_or_else(|| "jsonl".to_string(), |e| e.to_string_lossy().into_owned()),
+        }
+    }
+
+    /// The uncompressed name for a new archive, numbered past any archive
+    /// (compressed or not) already using the stamp.
+    fn next_free(&self, stamp: &str, compression: LogCompression) -> PathBuf {
+        let taken = |name: &str| {
+            let plain = self.dir.join(name);
+            let packed = compression
+                .extension()
+                .map(|ext| self.dir.join(format!("{name}.{ext}")));
+            plain.exists() || packed.is_some_and(|p| p.exists())
+        };
+        let mut name = format!("{}-{stamp}.{}", self.stem, self.ext);
+        let mut n = 1;
+        while taken(&name) {
+            name = format!("{}-{stamp}-{n}.{}", self.stem, self.ext);
+            n += 1;
+        }
+        self.dir.join(name)
+    }
+
+    /// Whether `name` is one of these archives: the stem, a dash, then a
+    /// date, so `session-baseline.jsonl` is not taken for a `session` archive.
+    fn matches(&self, name: &str) -> bool {
+        let Some(rest) = name.strip_prefix(&format!("{}-", self.stem)) else {
+            return false;
+        };
+        let date = rest.as_bytes();
+        date.len() > 5
+            && date[..4].iter().all(u8::is_ascii_digit)
+            && date[4] == b'-'
+            && name.contains(&format!(".{}", self.ext))
+            && !name.ends_with(PARTIAL_EXT)
+    }
+
+    /// Deletes all but the newest `keep` archives.
+    fn prune(&self, keep: usize) -> io::Result<()> {
+        let dir = if self.dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            &self.dir
+        };
+        let mut archives = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if self.matches(&entry.file_name().to_string_lossy()) {
+                archives.push((entry.metadata()?.modified()?, entry.path()));
+            }
+        }
+        archives.sort();
+        let excess = archives.len().saturating_sub(keep);
+        for (_, path) in archives.into_iter().take(excess) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replaces `path` with its compressed copy. The copy is written under a
+/// temporary name first, so a crash leaves the plain archive in place.
+fn compress(path: &Path, compression: LogCompression) -> io::Result<()> {
+    let Some(ext) = compression.extension() else {
+        return Ok(());
+    };
+    let mut packed_name = path.as_os_str().to_owned();
+    packed_name.push(format!(".{ext}"));
+    let packed = PathBuf::from(packed_name);
+    let partial = packed.with_extension(format!("{ext}.{PARTIAL_EXT}"));
+    let mut input = BufReader::new(File::open(path)?);
+    let output = BufWriter::new(File::create(&partial)?);
+    match compression {
+        LogCompression::Zstd => {
+            let mut encoder = zstd::Encoder::new(output, ZSTD_LEVEL)?;
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+        LogCompression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::default());
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+        LogCompression::None => {}
+    }
+    fs::rename(&partial, &packed)?;
+    fs::remove_file(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::temp_dir;
+    use serde_json::{json, Value};
+    use std::io::{BufRead, Read};
+    use std::time::Duration;
+
+    fn lines(reader: impl Read) -> Vec<Value> {
+        BufReader::new(reader)
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn rotates_past_the_size_limit_and_keeps_the_newest_archives() {
+        let dir = temp_dir("rotate");
+        // Shares the stem but is not an archive.
+        let baseline = dir.join("session-baseline.jsonl");
+        fs::write(&baseline, "{}\n").unwrap();
+
+        let path = dir.join("session.jsonl");
+        let rotation = LogRotation {
+            max_bytes: 100,
+            every: None,
+            keep: 2,
+            compression: LogCompression::Gzip,
+        };
+        let header = json!({ "recordType": "header" });
+        let record = |n: usize| json!({ "n": n, "pad": "x".repeat(24) });
+        let day = stamp(None, now_unix_ms());
+        {
+            let mut log = LogFile::open(&path, Some(rotation)).unwrap();
+            log.write_header(&header).unwrap();
+            // Header and one record fit; each further record rotates.
+            for n in 0..4 {
+                log.write_line(&record(n)).unwrap();
+                // Archives are pruned by age, which the filesystem keeps
+                // only so finely.
+                thread::sleep(Duration::from_millis(30));
+            }
+        }
+
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                format!("session-{day}-1.jsonl.gz"),
+                format!("session-{day}-2.jsonl.gz"),
+                "session-baseline.jsonl".to_string(),
+                "session.jsonl".to_string(),
+            ]
+        );
+        assert_eq!(
+            lines(File::open(&path).unwrap()),
+            [header.clone(), record(3)]
+        );
+        let newest = File::open(dir.join(format!("session-{day}-2.jsonl.gz"))).unwrap();
+        assert_eq!(
+            lines(flate2::read::GzDecoder::new(newest)),
+            [header, record(2)]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,126 @@
+use crate::os;
+use std::io;
+#[cfg(windows)]
+use std::net::{Ipv4Addr, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Long enough that a loaded host still answers; a reply this late is not a floor anyway.
+const SELF_PROBE_TIMEOUT: Duration = Duration::from_millis(100);
+/// How often the echo thread wakes to check whether its prober is gone.
+const ECHO_POLL: Duration = Duration::from_millis(500);
+const SELF_PROBE_BYTES: usize = 32;
+
+#[cfg(unix)]
+type PairSocket = UnixDatagram;
+#[cfg(windows)]
+type PairSocket = UdpSocket;
+
+/// An in-process reflector on a unix socket pair (two connected loopback UDP
+/// sockets on Windows). Round trips through it cost
+/// the same syscalls and wakeups as a network probe minus the network, so
+/// their minimum is the host's own share of every RTT.
+pub struct SelfFloorProbe {
+    sock: PairSocket,
+    stop: Arc<AtomicBool>,
+    seq: u64,
+}
+
+impl SelfFloorProbe {
+    pub fn new() -> io::Result<Self> {
+        let (sock, echo) = socket_pair()?;
+        sock.set_read_timeout(Some(SELF_PROBE_TIMEOUT))?;
+        echo.set_read_timeout(Some(ECHO_POLL))?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_echo = Arc::clone(&stop);
+        thread::Builder::new()
+            .name("self-floor-echo".to_string())
+            .spawn(move || {
+                let mut buf = [0u8; SELF_PROBE_BYTES];
+                while !stop_echo.load(Ordering::Relaxed) {
+                    match echo.recv(&mut buf) {
+                        Ok(n) => {
+                            if echo.send(&buf[..n]).is_err() {
+                                break;
+                            }
+                        }
+                        Err(err)
+                            if matches!(
+                                err.kind(),
+                                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                            ) => {}
+                        Err(_) => break,
+                    }
+                }
+            })?;
+        Ok(SelfFloorProbe { sock, stop, seq: 0 })
+    }
+
+    /// Smallest of `probes` round trips in ns, or `None` if none came back.
+    pub fn measure(&mut self, probes: usize) -> Option<u64> {
+        let mut best: Option<u64> = None;
+        for _ in 0..probes {
+            self.seq = self.seq.wrapping_add(1);
+            let mut msg = [0u8; SELF_PROBE_BYTES];
+            msg[..8].copy_from_slice(&self.seq.to_be_bytes());
+            let start = os::monotonic_now_ns();
+            if self.sock.send(&msg).is_err() {
+                continue;
+            }
+            // A reply that missed an earlier probe's timeout arrives first; skip it
+            // rather than time it against this probe.
+            let mut buf = [0u8; SELF_PROBE_BYTES];
+            let answered = loop {
+                match self.sock.recv(&mut buf) {
+                    Ok(n) if n >= 8 && buf[..8] == msg[..8] => break true,
+                    Ok(_) => continue,
+                    Err(_) => break false,
+                }
+            };
+            if answered {
+                let rtt_ns = os::monotonic_now_ns().saturating_sub(start);
+                best = Some(best.map_or(rtt_ns, |b| b.min(rtt_ns)));
+            }
+        }
+        best
+    }
+}
+
+#[cfg(unix)]
+fn socket_pair() -> io::Result<(PairSocket, PairSocket)> {
+    UnixDatagram::pair()
+}
+
+#[cfg(windows)]
+fn socket_pair() -> io::Result<(PairSocket, PairSocket)> {
+    let a = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let b = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+    a.connect(b.local_addr()?)?;
+    b.connect(a.local_addr()?)?;
+    Ok((a, b))
+}
+
+impl Drop for SelfFloorProbe {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_the_in_process_echo() {
+        let mut probe = SelfFloorProbe::new().unwrap();
+        assert_eq!(probe.measure(0), None);
+        let floor = probe.measure(5).unwrap();
+        assert!(floor > 0);
+        assert!(floor < SELF_PROBE_TIMEOUT.as_nanos() as u64);
+        assert_eq!(probe.seq, 5);
+    }
+}
@@ -0,0 +1,197 @@
+use lattice_core::{
+    build_packet, build_packet_v2, burst_before, Packet, ProbeId, Replay, ReplayGuard, Secret,
+    LATO_PACKET_LEN, LATO_V2_VERSION, LATO_VERSION,
+};
+use rand::Rng;
+
+/// Numbers an endpoint worker's probes: a random epoch per socket, a burst
+/// counter that keeps counting across sockets, and the probe within the
+/// burst. All three ride in the probe and come back in its echo, so a reply
+/// names the probe it answers however late it arrives.
+pub struct Sequencer {
+    epoch: u32,
+    burst: u16,
+    index: u16,
+    version: u32,
+    /// Replies already taken, so a later copy of one is told from a late
+    /// reply.
+    answered: ReplayGuard,
+}
+
+/// A probe outside any burst, sent to keep NAT and firewall state open: v1,
+/// which every reflector answers, under a random nonce and so a random
+/// epoch, whose echo no `Sequencer` counts as its own.
+pub fn keepalive(send_ns: u64, secret: &Secret) -> [u8; LATO_PACKET_LEN] {
+    build_packet(0, send_ns, rand::random(), secret.as_bytes())
+}
+
+/// Verified echoes that were not the reply being waited for: probes that
+/// missed their timeout, or duplicates.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LateReplies {
+    pub this_burst: usize,
+    pub prior_bursts: usize,
+    /// Copies of replies already taken.
+    pub duplicates: usize,
+}
+
+impl Sequencer {
+    pub fn new() -> Self {
+        Sequencer::resume(rand::random(), 0)
+    }
+
+    /// Picks up the burst counter where an earlier run left it. The epoch
+    /// is new, so that run's late echoes are not taken for this one's.
+    pub fn resume(last_epoch: u32, last_burst: u16) -> Self {
+        let mut sequencer = Sequencer {
+            epoch: last_epoch,
+            burst: last_burst,
+            index: 0,
+            version: LATO_VERSION,
+            // Only the current epoch's replies are counted.
+            answered: ReplayGuard::new(1),
+        };
+        sequencer.new_epoch();
+        sequencer
+    }
+
+    /// For a fresh socket, so echoes of the old one's probes are told apart.
+    pub fn new_epoch(&mut self) {
+        let old = self.epoch;
+        while self.epoch == old {
+            self.epoch = rand::random();
+        }
+    }
+
+    /// Starts the next burst and returns its number; it wraps at 65536.
+    pub fn next_burst(&mut self) -> u16 {
+        self.burst = self.burst.wrapping_add(1);
+        self.index = 0;
+        self.burst
+    }
+
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// The LATO version of the probes from now on.
+    pub fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
+    /// The next probe of the current burst.
+    pub fn packet(&mut self, send_ns: u64, secret: &Secret) -> Vec<u8> {
+        let id = ProbeId {
+            epoch: self.epoch,
+            burst: self.burst,
+            index: self.index,
+        };
+        self.index = self.index.wrapping_add(1);
+        let nonce = id.nonce(rand::thread_rng().gen());
+        match self.version {
+            LATO_V2_VERSION => {
+                build_packet_v2(id.seq(), send_ns, nonce, secret.as_bytes()).to_vec()
+            }
+            _ => build_packet(id.seq(), send_ns, nonce, secret.as_bytes()).to_vec(),
+        }
+    }
+
+    /// The last probe got its reply.
+    pub fn answered(&mut self) {
+        let id = ProbeId {
+            epoch: self.epoch,
+            burst: self.burst,
+            index: self.index.wrapping_sub(1),
+        };
+        self.answered.check_seq(id.epoch, id.seq());
+    }
+
+    /// Attributes a prober's stray replies. Forged ones, echoes from an
+    /// earlier socket's epoch, and bursts not yet sent are left out.
+    pub fn count_late(&mut self, strays: &[Packet], secret: &Secret) -> LateReplies {
+        let mut late = LateReplies::default();
+        for packet in strays
+            .iter()
+            .filter(|p| p.verify_reply(secret.as_bytes()))
+            .filter(|p| p.probe_id().epoch == self.epoch)
+        {
+            let id = packet.probe_id();
+            if self.answered.check(packet) == Replay::Seen {
+                late.duplicates += 1;
+            } else if id.burst == self.burst {
+                late.this_burst += 1;
+            } else if burst_before(id.burst, self.burst) {
+                late.prior_bursts += 1;
+            }
+        }
+        late
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lattice_core::{parse_lato_packet, SecretHex};
+    use lattice_server::{Server, ServerConfig};
+    use std::net::UdpSocket;
+    use std::thread;
+    use std::time::Duration;
+
+    const SECRET: &str = "00112233445566778899aabbccddeeff";
+
+    fn exchange(socket: &UdpSocket, msg: &[u8]) -> Packet {
+        let mut buf = [0u8; 128];
+        socket.send(msg).unwrap();
+        let n = socket.recv(&mut buf).expect("no echo");
+        parse_lato_packet(&buf[..n]).unwrap()
+    }
+
+    #[test]
+    fn keepalive_is_echoed_and_counted_by_no_burst() {
+        let secret = SecretHex::from(SECRET).decode().unwrap();
+        let msg = keepalive(1_700_000_000_000_000_000, &secret);
+        let sent = parse_lato_packet(&msg).unwrap();
+        assert_eq!(sent.version, LATO_VERSION);
+        assert!(sent.verify(secret.as_bytes()));
+
+        // The `lattice serve` reflector on a loopback port.
+        let server = Server::bind(&ServerConfig {
+            secret_hex: SecretHex::from(SECRET),
+            listen: vec!["127.0.0.1:0".to_string()],
+            ..ServerConfig::default()
+        })
+        .unwrap();
+        let addr = server.local_addrs().unwrap()[0];
+        let shutdown = server.shutdown_handle();
+        let serving = thread::spawn(move || server.serve());
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.connect(addr).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let echo = exchange(&socket, &msg);
+        assert!(echo.echoes(&sent));
+        assert!(echo.verify_reply(secret.as_bytes()));
+
+        // Mid-burst, the keepalive's echo is none of the burst's replies.
+        let mut sequencer = Sequencer::new();
+        sequencer.next_burst();
+        let probe = sequencer.packet(1, &secret);
+        let late = sequencer.count_late(&[echo], &secret);
+        assert_eq!(
+            (late.this_burst, late.prior_bursts, late.duplicates),
+            (0, 0, 0)
+        );
+        // While a real straggler from the same socket is counted.
+        let straggler = exchange(&socket, &probe);
+        let late = sequencer.count_late(&[echo, straggler], &secret);
+        assert_eq!(
+            (late.this_burst, late.prior_bursts, late.duplicates),
+            (1, 0, 0)
+        );
+
+        shutdown.shutdown();
+        serving.join().unwrap().unwrap();
+    }
+}
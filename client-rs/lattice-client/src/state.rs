@@ -0,0 +1,242 @@
+//! What the client carries over a restart, kept in `stateDir`: each worker's
+//! burst counter and bufferbloat idle baseline, the address each host last
+//! answered from, and a calibration for the rolling estimate.
+
+use crate::{expand_tilde, write_atomic};
+use lattice_analysis::{load_calibration, Calibration};
+use lattice_core::{now_unix_ms, Config};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const STATE_EXTENSION: &str = "state";
+const STATE_FILE: &str = "state.json";
+/// Read at startup when present, e.g. written there by
+/// `analyze --calibration-out`.
+const CALIBRATION_FILE: &str = "calibration.json";
+const STATE_VERSION: u32 = 1;
+/// Workers' updates are written at most this often, and once more on exit.
+const SAVE_EVERY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SavedState {
+    pub version: u32,
+    pub saved_unix_ms: i64,
+    /// Per host: the address the last burst with replies went to.
+    pub addresses: BTreeMap<String, Vec<IpAddr>>,
+    /// Per endpoint worker (`id` or `id@path`).
+    pub workers: BTreeMap<String, WorkerState>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WorkerState {
+    pub socket_epoch: u32,
+    pub burst_seq: u16,
+    /// Recent quiet-burst p05s, oldest first.
+    pub idle_p05_ns: Vec<u64>,
+}
+
+/// `stateDir` from the config, or the output path with a `.state` extension.
+pub fn state_dir(cfg: &Config) -> PathBuf {
+    match &cfg.state_dir {
+        Some(dir) => expand_tilde(dir),
+        None => expand_tilde(&cfg.output_path).with_extension(STATE_EXTENSION),
+    }
+}
+
+pub struct StateStore {
+    dir: PathBuf,
+    state: Mutex<SavedState>,
+    last_save: Mutex<Instant>,
+}
+
+impl StateStore {
+    /// Creates the directory and reads what an earlier run saved. A missing
+    /// file starts empty; an unreadable one is reported and replaced.
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(STATE_FILE);
+        let state = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|err| {
+                eprintln!("[!] ignoring unreadable {}: {}", path.display(), err);
+                SavedState::default()
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => SavedState::default(),
+            Err(err) => return Err(err),
+        };
+        Ok(StateStore {
+            dir: dir.to_path_buf(),
+            state: Mutex::new(state),
+            last_save: Mutex::new(Instant::now()),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn addresses(&self) -> BTreeMap<String, Vec<IpAddr>> {
+        self.state
+            .lock()
+            .map(|s| s.addresses.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn worker(&self, key: &str) -> Option<WorkerState> {
+        self.state.lock().ok()?.workers.get(key).cloned()
+    }
+
+    /// The calibration left in the directory, if any.
+    pub fn calibration(&self) -> io::Result<Option<(PathBuf, Calibration)>> {
+        let path = self.dir.join(CALIBRATION_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some((path.clone(), load_calibration(&path)?)))
+    }
+
+    /// Records a worker's state after a burst, and the address its host
+    /// answered from when it did. Written out once `SAVE_EVERY` has passed.
+    pub fn update(&self, key: &str, worker: WorkerState, answered: Option<(&str, IpAddr)>) {
+        if let Ok(mut state) = self.state.lock() {
+            state.workers.insert(key.to_string(), worker);
+            if let Some((host, ip)) = answered.filter(|(host, _)| host.parse::<IpAddr>().is_err()) {
+                state.addresses.insert(host.to_string(), vec![ip]);
+            }
+        }
+        let due = self
+            .last_save
+            .lock()
+            .is_ok_and(|last| last.elapsed() >= SAVE_EVERY);
+        if due {
+            if let Err(err) = self.save() {
+                eprintln!("[!] saving state to {} failed: {}", self.dir.display(), err);
+            }
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let mut state = self.state.lock().map(|s| s.clone()).unwrap_or_default();
+        state.version = STATE_VERSION;
+        state.saved_unix_ms = now_unix_ms();
+        let data = serde_json::to_vec_pretty(&state).map_err(io::Error::other)?;
+        write_atomic(&self.dir.join(STATE_FILE), &data)?;
+        if let Ok(mut last) = self.last_save.lock() {
+            *last = Instant::now();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::temp_dir;
+    use std::net::Ipv4Addr;
+
+    const ANCHOR_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 7));
+
+    fn worker(seq: u16) -> WorkerState {
+        WorkerState {
+            socket_epoch: 0xdead_beef,
+            burst_seq: seq,
+            idle_p05_ns: vec![1_200_000, 1_150_000],
+        }
+    }
+
+    #[test]
+    fn saved_state_comes_back_after_a_restart() {
+        let dir = temp_dir("state-round-trip");
+        let store = StateStore::open(&dir).unwrap();
+        store.update("a", worker(u16::MAX), Some(("anchor.example", ANCHOR_IP)));
+        store.update("b@wifi", worker(3), Some(("192.0.2.9", ANCHOR_IP)));
+        store.update("c", worker(4), None);
+        store.save().unwrap();
+
+        let reopened = StateStore::open(&dir).unwrap();
+        for (key, seq) in [("a", u16::MAX), ("b@wifi", 3), ("c", 4)] {
+            let saved = reopened.worker(key).unwrap();
+            let want = worker(seq);
+            assert_eq!(
+                (saved.socket_epoch, saved.burst_seq, saved.idle_p05_ns),
+                (want.socket_epoch, want.burst_seq, want.idle_p05_ns),
+                "{key}"
+            );
+        }
+        assert!(reopened.worker("d").is_none());
+        // IP literals need no remembering.
+        assert_eq!(
+            reopened.addresses(),
+            BTreeMap::from([("anchor.example".to_string(), vec![ANCHOR_IP])])
+        );
+        let saved: SavedState =
+            serde_json::from_slice(&fs::read(dir.join(STATE_FILE)).unwrap()).unwrap();
+        assert_eq!(saved.version, STATE_VERSION);
+        assert!(saved.saved_unix_ms > 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_or_corrupt_state_starts_empty() {
+        let dir = temp_dir("state-corrupt");
+        let nested = dir.join("not").join("yet");
+        let store = StateStore::open(&nested).unwrap();
+        assert!(nested.is_dir());
+        assert!(store.worker("a").is_none());
+        assert!(store.addresses().is_empty());
+
+        for bad in [
+            &b"{\"workers\": {\"a\": {\"burstSeq"[..],
+            b"{\"workers\": 5}",
+            b"\xff\xfe",
+        ] {
+            fs::write(nested.join(STATE_FILE), bad).unwrap();
+            let store = StateStore::open(&nested).unwrap();
+            assert!(store.worker("a").is_none());
+            assert!(store.addresses().is_empty());
+        }
+        // The next save replaces what could not be read.
+        let store = StateStore::open(&nested).unwrap();
+        store.update("a", worker(1), None);
+        store.save().unwrap();
+        assert_eq!(
+            StateStore::open(&nested)
+                .unwrap()
+                .worker("a")
+                .unwrap()
+                .burst_seq,
+            1
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn save_replaces_the_file_whole() {
+        let dir = temp_dir("state-replace");
+        let store = StateStore::open(&dir).unwrap();
+        store.update("a", worker(1), Some(("anchor.example", ANCHOR_IP)));
+        store.save().unwrap();
+        // A temp file left by a save that died part way.
+        let tmp = dir.join(STATE_FILE).with_extension("tmp");
+        fs::write(&tmp, b"{\"work").unwrap();
+        store.update("a", worker(2), None);
+        store.save().unwrap();
+
+        let names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, [STATE_FILE]);
+        let reopened = StateStore::open(&dir).unwrap();
+        assert_eq!(reopened.worker("a").unwrap().burst_seq, 2);
+        assert_eq!(reopened.addresses()["anchor.example"], [ANCHOR_IP]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
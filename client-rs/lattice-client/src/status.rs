@@ -0,0 +1,731 @@
+use crate::api::Limiter;
+use crate::cli::{ConfigSource, StatusArgs};
+use crate::expand_tilde;
+use crate::responsiveness::{self, Job, ResponsivenessRequest};
+#[cfg(windows)]
+use lattice_core::bytes_to_hex;
+use lattice_core::{
+    constant_time_eq, now_unix_ms, ApiRateLimit, BurstRecord, Config, EventRecord, PrivacyMode,
+    ANCHOR_DOWN_EVENT, ENDPOINT_RECOVERED_EVENT, PATH_DOWN_EVENT,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+#[cfg(windows)]
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SOCKET_EXTENSION: &str = "sock";
+/// The bucket responsiveness tests are taken from. Only the socket's owner
+/// can connect (on Windows, whoever can read the token), so every
+/// connection is the same caller.
+const CONTROL_CALLER: &str = "control socket";
+#[cfg(unix)]
+const SOCKET_MODE: u32 = 0o600;
+/// The directory the socket is bound in before it is moved into place.
+#[cfg(unix)]
+const STAGING_DIR_MODE: u32 = 0o700;
+/// Same size as an API token.
+#[cfg(windows)]
+const TOKEN_BYTES: usize = 16;
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long the socket waits for a request line before answering with the
+/// status snapshot, for clients that connect and only read.
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(200);
+const MS_PER_SEC: i64 = 1000;
+
+#[cfg(unix)]
+pub type ControlStream = UnixStream;
+/// The standard library has no unix sockets on Windows, so the control
+/// socket is a loopback TCP listener. The socket path holds its port and a
+/// token each connection must send first, since any local user can reach
+/// the port.
+#[cfg(windows)]
+pub type ControlStream = TcpStream;
+
+/// One request line on the control socket. A client that sends nothing gets
+/// the status snapshot, as before requests existed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "camelCase")]
+pub enum ControlRequest {
+    Status,
+    Responsiveness(ResponsivenessRequest),
+}
+
+/// Counters and last-burst summaries shared by the workers, the writer, and
+/// the control socket.
+pub struct Status {
+    started_unix_ms: i64,
+    inner: Mutex<StatusReport>,
+    /// Responsiveness tests waiting for their endpoint's worker.
+    jobs: Mutex<Vec<(String, Job)>>,
+    /// Rate limit on responsiveness tests, as `localApi` limits bursts.
+    test_limiter: Mutex<Limiter>,
+    /// Local API callers waiting for their endpoint's next burst.
+    burst_requests: Mutex<Vec<(String, mpsc::Sender<BurstRecord>)>>,
+    /// What responsiveness requests are checked against; replaced when the
+    /// config is reloaded.
+    probed: Mutex<Probed>,
+}
+
+#[derive(Default)]
+struct Probed {
+    endpoint_ids: Vec<String>,
+    interval: Duration,
+    /// `privacyMode: "features"`: bursts stay inside the writer.
+    features_only: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusReport {
+    pub pid: u32,
+    pub started_unix_ms: i64,
+    pub uptime_seconds: i64,
+    pub endpoints: BTreeMap<String, EndpointStatus>,
+    pub sink: SinkStatus,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointStatus {
+    pub bursts: u64,
+    pub empty_bursts: u64,
+    pub init_errors: u64,
+    pub send_errors: u64,
+    pub in_blackout: bool,
+    /// `path` or `anchor` while the endpoint is down, from the worker's
+    /// `path_down`/`anchor_down` events.
+    #[serde(default)]
+    pub outage: Option<String>,
+    pub last_burst_unix_ms: Option<i64>,
+    pub last_min_ms: Option<f64>,
+    pub last_p05_ms: Option<f64>,
+    pub last_median_ms: Option<f64>,
+    pub last_notes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SinkStatus {
+    pub path: String,
+    pub records_written: u64,
+    pub write_errors: u64,
+    pub last_write_unix_ms: Option<i64>,
+    pub last_error: Option<String>,
+}
+
+impl Status {
+    pub fn new(output_path: &Path) -> Self {
+        let report = StatusReport {
+            pid: std::process::id(),
+            sink: SinkStatus {
+                path: output_path.display().to_string(),
+                ..SinkStatus::default()
+            },
+            ..StatusReport::default()
+        };
+        Self {
+            started_unix_ms: now_unix_ms(),
+            inner: Mutex::new(report),
+            jobs: Mutex::new(Vec::new()),
+            test_limiter: Mutex::new(Limiter::new(ApiRateLimit::default())),
+            burst_requests: Mutex::new(Vec::new()),
+            probed: Mutex::new(Probed::default()),
+        }
+    }
+
+    /// The endpoints and interval of the running config.
+    pub fn set_config(&self, cfg: &Config) {
+        if let Ok(mut probed) = self.probed.lock() {
+            *probed = Probed {
+                endpoint_ids: cfg.endpoints.iter().map(|e| e.id.clone()).collect(),
+                interval: Duration::from_secs(cfg.interval_seconds),
+                features_only: cfg.privacy_mode == PrivacyMode::Features,
+            };
+        }
+    }
+
+    /// The endpoint ids and interval requests are checked against.
+    pub fn probed(&self) -> (Vec<String>, Duration) {
+        match self.probed.lock() {
+            Ok(probed) => (probed.endpoint_ids.clone(), probed.interval),
+            Err(_) => (Vec::new(), Duration::ZERO),
+        }
+    }
+
+    /// Whether the running config keeps only hourly features, so no burst
+    /// may be handed to anyone.
+    pub fn features_only(&self) -> bool {
+        self.probed.lock().is_ok_and(|p| p.features_only)
+    }
+
+    fn with<F: FnOnce(&mut StatusReport)>(&self, f: F) {
+        if let Ok(mut report) = self.inner.lock() {
+            f(&mut report);
+        }
+    }
+
+    fn endpoint<F: FnOnce(&mut EndpointStatus)>(&self, id: &str, f: F) {
+        self.with(|r| f(r.endpoints.entry(id.to_string()).or_default()));
+    }
+
+    pub fn init_error(&self, id: &str) {
+        self.endpoint(id, |e| e.init_errors += 1);
+    }
+
+    pub fn send_error(&self, id: &str) {
+        self.endpoint(id, |e| e.send_errors += 1);
+    }
+
+    pub fn burst_written(&self, rec: &BurstRecord) {
+        let features_only = self.features_only();
+        self.endpoint(&rec.endpoint_id, |e| {
+            e.bursts += 1;
+            if rec.min_ms.is_none() {
+                e.empty_bursts += 1;
+            }
+            e.last_burst_unix_ms = Some(rec.ts_unix_ms);
+            if features_only {
+                return;
+            }
+            e.last_min_ms = rec.min_ms;
+            e.last_p05_ms = rec.p05_ms;
+            e.last_median_ms = rec.median_ms;
+            e.last_notes = rec.notes.clone();
+        });
+        self.sink_ok();
+    }
+
+    pub fn event_written(&self, ev: &EventRecord) {
+        if let Some(id) = &ev.endpoint_id {
+            match ev.event.as_str() {
+                "blackout_start" => self.endpoint(id, |e| e.in_blackout = true),
+                "blackout_end" => self.endpoint(id, |e| e.in_blackout = false),
+                PATH_DOWN_EVENT => self.endpoint(id, |e| e.outage = Some("path".to_string())),
+                ANCHOR_DOWN_EVENT => self.endpoint(id, |e| e.outage = Some("anchor".to_string())),
+                ENDPOINT_RECOVERED_EVENT => self.endpoint(id, |e| e.outage = None),
+                _ => {}
+            }
+        }
+        self.sink_ok();
+    }
+
+    pub fn responsiveness_written(&self) {
+        self.sink_ok();
+    }
+
+    pub fn queue_job(&self, endpoint_id: &str, job: Job) {
+        if let Ok(mut jobs) = self.jobs.lock() {
+            jobs.push((endpoint_id.to_string(), job));
+        }
+    }
+
+    /// Takes a responsiveness test from the control socket's rate limit, or
+    /// says how long until one is allowed.
+    pub fn take_test(&self, now: Instant) -> Result<(), Duration> {
+        self.test_limiter
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take(CONTROL_CALLER, now)
+    }
+
+    /// The oldest job for `endpoint_id`. With several probe paths the first
+    /// worker to ask runs it.
+    pub fn take_job(&self, endpoint_id: &str) -> Option<Job> {
+        let mut jobs = self.jobs.lock().ok()?;
+        let idx = jobs.iter().position(|(id, _)| id == endpoint_id)?;
+        Some(jobs.remove(idx).1)
+    }
+
+    pub fn request_burst(&self, endpoint_id: &str, reply: mpsc::Sender<BurstRecord>) {
+        if let Ok(mut requests) = self.burst_requests.lock() {
+            requests.push((endpoint_id.to_string(), reply));
+        }
+    }
+
+    /// Whether a caller is waiting on `endpoint_id`, so its worker can cut
+    /// the sleep short.
+    pub fn burst_requested(&self, endpoint_id: &str) -> bool {
+        self.burst_requests
+            .lock()
+            .is_ok_and(|requests| requests.iter().any(|(id, _)| id == endpoint_id))
+    }
+
+    /// Everyone waiting on `endpoint_id`; one burst answers them all.
+    pub fn take_burst_requests(&self, endpoint_id: &str) -> Vec<mpsc::Sender<BurstRecord>> {
+        let Ok(mut requests) = self.burst_requests.lock() else {
+            return Vec::new();
+        };
+        let (taken, kept): (Vec<_>, Vec<_>) =
+            requests.drain(..).partition(|(id, _)| id == endpoint_id);
+        *requests = kept;
+        taken.into_iter().map(|(_, reply)| reply).collect()
+    }
+
+    fn sink_ok(&self) {
+        self.with(|r| {
+            r.sink.records_written += 1;
+            r.sink.last_write_unix_ms = Some(now_unix_ms());
+        });
+    }
+
+    pub fn sink_error(&self, err: &dyn std::fmt::Display) {
+        self.with(|r| {
+            r.sink.write_errors += 1;
+            r.sink.last_error = Some(err.to_string());
+        });
+    }
+
+    pub fn report(&self) -> StatusReport {
+        let mut report = self.inner.lock().map(|r| r.clone()).unwrap_or_default();
+        report.started_unix_ms = self.started_unix_ms;
+        report.uptime_seconds = (now_unix_ms() - self.started_unix_ms) / MS_PER_SEC;
+        report
+    }
+}
+
+/// `controlSocket` from the config, or the output path with a `.sock` extension.
+pub fn socket_path(cfg: &Config) -> PathBuf {
+    match &cfg.control_socket {
+        Some(p) => expand_tilde(p),
+        None => expand_tilde(&cfg.output_path).with_extension(SOCKET_EXTENSION),
+    }
+}
+
+/// Binds the control socket and answers each connection's request line (see
+/// `ControlRequest`) with one JSON line. A stale socket left by a dead
+/// instance is replaced.
+pub fn serve(path: &Path, status: Arc<Status>, cfg: &Config) -> io::Result<()> {
+    if connect_raw(path).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("another instance is serving {}", path.display()),
+        ));
+    }
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let (listener, token) = bind(path)?;
+    status.set_config(cfg);
+    let token = Arc::new(token);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            let status = Arc::clone(&status);
+            let token = Arc::clone(&token);
+            // A responsiveness test holds its connection for seconds; status
+            // queries must not queue behind it.
+            thread::spawn(move || answer(stream, &status, token.as_deref()));
+        }
+    });
+    Ok(())
+}
+
+/// Only the owner (and root) may connect: the socket starts responsiveness
+/// tests and reports every endpoint's last burst. It is bound inside a
+/// directory only the owner can enter and only then moved to `path`, so
+/// there is no moment at which another user could connect to it. No token:
+/// the file mode is the check.
+#[cfg(unix)]
+fn bind(path: &Path) -> io::Result<(UnixListener, Option<String>)> {
+    let parent = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let staging = parent.join(format!(".lattice-{}", std::process::id()));
+    let staged = staging.join(SOCKET_EXTENSION);
+    // Left behind by an instance that died here with our pid.
+    let _ = fs::remove_file(&staged);
+    let _ = fs::remove_dir(&staging);
+    fs::DirBuilder::new()
+        .mode(STAGING_DIR_MODE)
+        .create(&staging)?;
+    let bound = UnixListener::bind(&staged).and_then(|listener| {
+        fs::set_permissions(&staged, fs::Permissions::from_mode(SOCKET_MODE))?;
+        fs::rename(&staged, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_file(&staged);
+    fs::remove_dir(&staging)?;
+    Ok((bound?, None))
+}
+
+/// Any local user can connect to a loopback port, so each connection must
+/// first send the token written next to the port, which only those who can
+/// read `path` know.
+#[cfg(windows)]
+fn bind(path: &Path) -> io::Result<(TcpListener, Option<String>)> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let mut token = [0u8; TOKEN_BYTES];
+    rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut token);
+    let token = bytes_to_hex(&token);
+    fs::write(path, format!("{} {token}", listener.local_addr()?.port()))?;
+    Ok((listener, Some(token)))
+}
+
+#[cfg(unix)]
+fn connect_raw(path: &Path) -> io::Result<UnixStream> {
+    UnixStream::connect(path)
+}
+
+/// Connects and sends the token line `answer` expects before the request.
+#[cfg(windows)]
+fn connect_raw(path: &Path) -> io::Result<TcpStream> {
+    let contents = fs::read_to_string(path)?;
+    let mut fields = contents.split_whitespace();
+    let (Some(port), Some(token)) = (fields.next(), fields.next()) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} holds no port and token", path.display()),
+        ));
+    };
+    let port: u16 = port
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))?;
+    stream.write_all(format!("{token}\n").as_bytes())?;
+    Ok(stream)
+}
+
+/// Answers one connection. With a `token`, its first line must be that
+/// token or the connection is answered with an error and nothing else.
+fn answer(mut stream: ControlStream, status: &Status, token: Option<&str>) {
+    let _ = stream.set_read_timeout(Some(REQUEST_TIMEOUT));
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    if let Some(token) = token {
+        let presented = reader.read_line(&mut line).map(|_| line.trim());
+        if !presented.is_ok_and(|p| constant_time_eq(p.as_bytes(), token.as_bytes())) {
+            drop(reader);
+            let _ = stream.write_all(b"{\"error\":\"missing or wrong control token\"}\n");
+            return;
+        }
+        line.clear();
+    }
+    let request = match reader.read_line(&mut line) {
+        Ok(n) if n > 0 => serde_json::from_str(&line).map_err(|e| e.to_string()),
+        _ => Ok(ControlRequest::Status),
+    };
+    drop(reader);
+    let body = match request {
+        Ok(ControlRequest::Status) => serde_json::to_value(status.report()),
+        Ok(ControlRequest::Responsiveness(request)) => {
+            let (endpoint_ids, interval) = status.probed();
+            match responsiveness::dispatch(status, request, &endpoint_ids, interval) {
+                Ok(rec) => serde_json::to_value(rec),
+                Err(err) => Ok(serde_json::json!({ "error": err })),
+            }
+        }
+        Err(err) => Ok(serde_json::json!({ "error": format!("bad request: {err}") })),
+    };
+    if let Ok(body) = body {
+        let _ = stream.write_all(body.to_string().as_bytes());
+        let _ = stream.write_all(b"\n");
+    }
+}
+
+/// `--socket`, or the control socket derived from the config.
+pub fn resolve_socket(source: &ConfigSource, socket: Option<&Path>) -> io::Result<PathBuf> {
+    match (socket, source.path()) {
+        (Some(s), _) => Ok(expand_tilde(&s.to_string_lossy())),
+        (None, Some(_)) => Ok(socket_path(&source.load()?)),
+        (None, None) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "a config or --socket is required to reach a running instance",
+        )),
+    }
+}
+
+pub fn connect(socket: &Path) -> io::Result<ControlStream> {
+    connect_raw(socket).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("no running instance at {} ({})", socket.display(), e),
+        )
+    })
+}
+
+/// `lattice status`: queries a running instance over its control socket.
+pub fn run(args: &StatusArgs) -> io::Result<()> {
+    let socket = resolve_socket(&args.source, args.socket.as_deref())?;
+    let mut stream = connect(&socket)?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    // Instances from before request lines ignore this and answer anyway.
+    let mut request = serde_json::to_vec(&ControlRequest::Status).map_err(io::Error::other)?;
+    request.push(b'\n');
+    let _ = stream.write_all(&request);
+    let mut body = String::new();
+    stream.read_to_string(&mut body)?;
+    if args.json {
+        print!("{body}");
+        return Ok(());
+    }
+    let report: StatusReport =
+        serde_json::from_str(&body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    print_report(&report);
+    Ok(())
+}
+
+fn print_report(report: &StatusReport) {
+    let now = now_unix_ms();
+    println!("LATTICE running (pid {})", report.pid);
+    println!("  uptime:  {}s", report.uptime_seconds);
+    println!(
+        "  sink:    {} ({} written, {} errors)",
+        report.sink.path, report.sink.records_written, report.sink.write_errors
+    );
+    if let Some(err) = &report.sink.last_error {
+        println!("  [!] last sink error: {}", err);
+    }
+    for (id, ep) in &report.endpoints {
+        let age = ep
+            .last_burst_unix_ms
+            .map(|ts| format!("{}s ago", (now - ts) / MS_PER_SEC))
+            .unwrap_or_else(|| "never".to_string());
+        let stats = match (ep.last_min_ms, ep.last_p05_ms, ep.last_median_ms) {
+            (Some(min), Some(p05), Some(med)) => {
+                format!("min={:.1}ms p05={:.1}ms med={:.1}ms", min, p05, med)
+            }
+            _ => "no samples".to_string(),
+        };
+        let blackout = if ep.in_blackout { " [blackout]" } else { "" };
+        let outage = ep
+            .outage
+            .as_ref()
+            .map(|o| format!(" [{o} down]"))
+            .unwrap_or_default();
+        println!(
+            "  - {}{}{} last={} {} bursts={} empty={} initErr={} sendErr={}",
+            id,
+            blackout,
+            outage,
+            age,
+            stats,
+            ep.bursts,
+            ep.empty_bursts,
+            ep.init_errors,
+            ep.send_errors
+        );
+        if !ep.last_notes.is_empty() {
+            println!("      [!] {}", ep.last_notes.join(" | "));
+        }
+    }
+}
+
+/// End-of-run totals printed when a bounded capture (`--duration`,
+/// `--max-bursts`, `--once`) finishes.
+pub fn print_summary(report: &StatusReport, elapsed: Duration) {
+    println!();
+    println!("LATTICE capture finished after {}s", elapsed.as_secs());
+    println!(
+        "  sink:    {} ({} written, {} errors)",
+        report.sink.path, report.sink.records_written, report.sink.write_errors
+    );
+    for (id, ep) in &report.endpoints {
+        let stats = match (ep.last_min_ms, ep.last_p05_ms) {
+            (Some(min), Some(p05)) => format!("last min={:.1}ms p05={:.1}ms", min, p05),
+            _ => "last burst had no samples".to_string(),
+        };
+        println!(
+            "  - {} bursts={} empty={} initErr={} sendErr={} {}",
+            id, ep.bursts, ep.empty_bursts, ep.init_errors, ep.send_errors, stats
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::temp_dir;
+    use serde_json::{json, Value};
+    use std::net::Shutdown;
+
+    fn config(extra: Value) -> Config {
+        let mut cfg = json!({
+            "secretHex": "00",
+            "endpoints": [
+                { "id": "a", "host": "127.0.0.1", "port": 9000 },
+                { "id": "b", "host": "127.0.0.1", "port": 9001 },
+            ],
+            "samplesPerEndpoint": 10,
+            "spacingMs": 10,
+            "timeoutMs": 1000,
+            "intervalSeconds": 10,
+            "outputPath": "out.jsonl",
+            "physicsMismatchThresholdMs": 5.0,
+        });
+        cfg.as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(cfg).unwrap()
+    }
+
+    fn burst(id: &str, min_ms: Option<f64>) -> BurstRecord {
+        BurstRecord {
+            endpoint_id: id.to_string(),
+            ts_unix_ms: 1_000,
+            min_ms,
+            p05_ms: min_ms,
+            median_ms: min_ms,
+            notes: vec!["note".to_string()],
+            ..BurstRecord::default()
+        }
+    }
+
+    fn ask(path: &Path, request: &str) -> Value {
+        let mut stream = connect(path).unwrap();
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT)).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        stream.shutdown(Shutdown::Write).unwrap();
+        let mut body = String::new();
+        stream.read_to_string(&mut body).unwrap();
+        serde_json::from_str(&body).unwrap()
+    }
+
+    #[test]
+    fn responsiveness_tests_are_rate_limited() {
+        let status = Status::new(Path::new("out.jsonl"));
+        let now = Instant::now();
+        let burst = ApiRateLimit::default().burst as usize;
+        for _ in 0..burst {
+            assert_eq!(status.take_test(now), Ok(()));
+        }
+        assert_eq!(status.take_test(now), Err(Duration::from_secs(10)));
+        assert_eq!(status.take_test(now + Duration::from_secs(10)), Ok(()));
+    }
+
+    #[test]
+    fn counts_bursts_events_and_sink_writes() {
+        let status = Status::new(Path::new("out.jsonl"));
+        status.set_config(&config(json!({})));
+        assert_eq!(
+            status.probed(),
+            (
+                vec!["a".to_string(), "b".to_string()],
+                Duration::from_secs(10)
+            )
+        );
+        status.burst_written(&burst("a", Some(12.0)));
+        status.burst_written(&burst("a", None));
+        status.send_error("a");
+        status.init_error("b");
+        status.event_written(&EventRecord::new(
+            PATH_DOWN_EVENT,
+            Some("b".to_string()),
+            json!({}),
+        ));
+        status.event_written(&EventRecord::new(
+            "blackout_start",
+            Some("b".to_string()),
+            json!({}),
+        ));
+        status.sink_error(&"disk full");
+
+        let report = status.report();
+        assert_eq!(report.pid, std::process::id());
+        assert_eq!(report.sink.path, "out.jsonl");
+        assert_eq!(report.sink.records_written, 4);
+        assert_eq!(report.sink.write_errors, 1);
+        assert_eq!(report.sink.last_error.as_deref(), Some("disk full"));
+        let a = &report.endpoints["a"];
+        assert_eq!((a.bursts, a.empty_bursts, a.send_errors), (2, 1, 1));
+        assert_eq!(a.last_min_ms, None);
+        assert_eq!(a.last_burst_unix_ms, Some(1_000));
+        let b = &report.endpoints["b"];
+        assert_eq!(b.init_errors, 1);
+        assert_eq!(b.outage.as_deref(), Some("path"));
+        assert!(b.in_blackout);
+
+        status.event_written(&EventRecord::new(
+            ENDPOINT_RECOVERED_EVENT,
+            Some("b".to_string()),
+            json!({}),
+        ));
+        assert_eq!(status.report().endpoints["b"].outage, None);
+    }
+
+    #[test]
+    fn features_mode_keeps_burst_values_out_of_the_report() {
+        let status = Status::new(Path::new("out.jsonl"));
+        status.set_config(&config(json!({ "privacyMode": "features" })));
+        assert!(status.features_only());
+        status.burst_written(&burst("a", Some(12.0)));
+        let a = &status.report().endpoints["a"];
+        assert_eq!(a.bursts, 1);
+        assert_eq!(
+            (a.last_min_ms, a.last_p05_ms, a.last_median_ms),
+            (None, None, None)
+        );
+        assert!(a.last_notes.is_empty());
+    }
+
+    #[test]
+    fn burst_requests_are_answered_per_endpoint() {
+        let status = Status::new(Path::new("out.jsonl"));
+        let (tx, _rx) = mpsc::channel();
+        status.request_burst("a", tx.clone());
+        status.request_burst("b", tx.clone());
+        status.request_burst("a", tx);
+        assert!(status.burst_requested("a"));
+        assert_eq!(status.take_burst_requests("a").len(), 2);
+        assert!(!status.burst_requested("a"));
+        assert!(status.burst_requested("b"));
+        assert!(status.take_job("a").is_none());
+    }
+
+    #[test]
+    fn socket_defaults_next_to_the_output() {
+        assert_eq!(socket_path(&config(json!({}))), Path::new("out.sock"));
+        assert_eq!(
+            socket_path(&config(json!({ "controlSocket": "/run/lattice.sock" }))),
+            Path::new("/run/lattice.sock")
+        );
+        let err = resolve_socket(&ConfigSource::default(), None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn control_socket_answers_status_and_rejects_bad_requests() {
+        let dir = temp_dir("status");
+        let path = dir.join("control.sock");
+        let status = Arc::new(Status::new(Path::new("out.jsonl")));
+        status.burst_written(&burst("a", Some(12.0)));
+        let cfg = config(json!({}));
+        serve(&path, Arc::clone(&status), &cfg).unwrap();
+        #[cfg(unix)]
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            SOCKET_MODE
+        );
+
+        let report: StatusReport = serde_json::from_value(ask(&path, "")).unwrap();
+        assert_eq!(report.endpoints["a"].last_min_ms, Some(12.0));
+        let report = ask(&path, "{\"command\":\"status\"}\n");
+        assert_eq!(report["sink"]["recordsWritten"], 1);
+        let bad = ask(&path, "{\"command\":\"reboot\"}\n");
+        assert!(
+            bad["error"].as_str().unwrap().starts_with("bad request:"),
+            "{bad}"
+        );
+        let refused = ask(
+            &path,
+            "{\"command\":\"responsiveness\",\"endpoint\":\"z\",\"durationMs\":1000,\"streams\":1,\"mbps\":1.0}\n",
+        );
+        assert_eq!(refused["error"], "unknown endpoint z");
+
+        let err = serve(&path, status, &cfg).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+    }
+}
@@ -0,0 +1,13 @@
+//! Helpers shared by the client's unit tests.
+
+use std::fs;
+use std::path::PathBuf;
+
+/// A fresh, empty directory for one test, named after it, since tests run
+/// in parallel.
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lattice-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,175 @@
+//! TLS for the HTTPS transport, on rustls. The peer's certificate is checked
+//! against the system roots (or a given CA file) and the endpoint's name.
+
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use socket2::Socket;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+/// The system roots, read once: the first HTTPS endpoint pays for it and
+/// every reconnect after reuses them.
+fn system_config() -> io::Result<Arc<ClientConfig>> {
+    static CONFIG: OnceLock<Result<Arc<ClientConfig>, String>> = OnceLock::new();
+    CONFIG
+        .get_or_init(|| {
+            let found = rustls_native_certs::load_native_certs();
+            let mut roots = RootCertStore::empty();
+            let (added, _) = roots.add_parsable_certificates(found.certs);
+            if added == 0 {
+                return Err(match found.errors.first() {
+                    Some(e) => e.to_string(),
+                    None => "no system roots found".to_string(),
+                });
+            }
+            Ok(client_config(roots))
+        })
+        .clone()
+        .map_err(|e| io::Error::other(format!("loading trusted certificates failed: {e}")))
+}
+
+/// A config trusting only the PEM certificates in `path`.
+fn ca_file_config(path: &Path) -> io::Result<Arc<ClientConfig>> {
+    let failed = |e: &dyn std::fmt::Display| {
+        io::Error::other(format!(
+            "loading trusted certificates failed: {}: {e}",
+            path.display()
+        ))
+    };
+    let certs = CertificateDer::pem_file_iter(path)
+        .map_err(|e| failed(&e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| failed(&e))?;
+    let mut roots = RootCertStore::empty();
+    let (added, _) = roots.add_parsable_certificates(certs);
+    if added == 0 {
+        return Err(failed(&"no certificates in it"));
+    }
+    Ok(client_config(roots))
+}
+
+fn client_config(roots: RootCertStore) -> Arc<ClientConfig> {
+    Arc::new(
+        ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+    )
+}
+
+/// A TLS client connection over a connected TCP socket. Reads and writes
+/// block up to the socket's timeouts; a read that runs out of time is
+/// `WouldBlock` (or `TimedOut`), and the connection stays usable.
+pub struct TlsStream {
+    stream: StreamOwned<ClientConnection, Socket>,
+}
+
+impl TlsStream {
+    /// Runs the handshake, checking the certificate against `ca_file` (or
+    /// the system roots) and `server_name`, a DNS name or an IP address.
+    pub fn connect(socket: Socket, server_name: &str, ca_file: Option<&Path>) -> io::Result<Self> {
+        let config = match ca_file {
+            Some(path) => ca_file_config(path)?,
+            None => system_config()?,
+        };
+        Self::handshake(socket, server_name, config)
+    }
+
+    fn handshake(
+        mut socket: Socket,
+        server_name: &str,
+        config: Arc<ClientConfig>,
+    ) -> io::Result<Self> {
+        let name = ServerName::try_from(server_name.to_string())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut conn = ClientConnection::new(config, name)
+            .map_err(|e| io::Error::other(format!("TLS setup failed: {e}")))?;
+        while conn.is_handshaking() {
+            if let Err(err) = conn.complete_io(&mut socket) {
+                let rejected = err
+                    .get_ref()
+                    .and_then(|e| e.downcast_ref::<rustls::Error>())
+                    .filter(|e| matches!(e, rustls::Error::InvalidCertificate(_)));
+                return Err(match rejected {
+                    Some(e) => io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        format!("TLS certificate of {server_name} not trusted ({e})"),
+                    ),
+                    None => io::Error::new(err.kind(), format!("TLS handshake failed: {err}")),
+                });
+            }
+        }
+        Ok(TlsStream {
+            stream: StreamOwned::new(conn, socket),
+        })
+    }
+
+    pub fn socket(&self) -> &Socket {
+        &self.stream.sock
+    }
+
+    pub fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.stream.write_all(buf)?;
+        self.stream.flush()
+    }
+
+    /// 0 once the peer closed the connection.
+    pub fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+
+    /// Connects to a loopback peer that answers the ClientHello with plain
+    /// HTTP and hangs up.
+    fn handshake_with_plain_http(ca_file: Option<&Path>) -> io::Result<TlsStream> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let peer = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut hello = [0u8; 512];
+            let _ = conn.read(&mut hello);
+            let _ = conn.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n");
+        });
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let result = match ca_file {
+            Some(path) => TlsStream::connect(Socket::from(stream), "localhost", Some(path)),
+            // No roots at all: the handshake fails on the peer's reply
+            // before any certificate would be checked.
+            None => TlsStream::handshake(
+                Socket::from(stream),
+                "localhost",
+                client_config(RootCertStore::empty()),
+            ),
+        };
+        peer.join().unwrap();
+        result
+    }
+
+    #[test]
+    fn a_peer_without_tls_fails_the_handshake() {
+        let err = handshake_with_plain_http(None).err().unwrap();
+        assert!(err.to_string().starts_with("TLS handshake failed"), "{err}");
+    }
+
+    #[test]
+    fn an_unreadable_ca_file_is_refused_before_the_handshake() {
+        let missing = Path::new("/nonexistent/lattice-ca.pem");
+        let err = handshake_with_plain_http(Some(missing)).err().unwrap();
+        assert!(
+            err.to_string()
+                .starts_with("loading trusted certificates failed"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,361 @@
+use crate::https::HttpsProber;
+use crate::os;
+use lattice_core::{
+    Ecn, Endpoint, EventRecord, Packet, ReflectorTiming, Secret, TcpConnectProber, TcpProber,
+    TimestampSource, Transport, TRANSPORT_CHANGED_EVENT,
+};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
+
+/// An endpoint socket over the endpoint's configured transport.
+pub enum Prober {
+    /// Boxed: its receive buffers make it far bigger than a TCP socket.
+    Udp(Box<os::UdpProber>),
+    Tcp(TcpProber),
+    TcpConnect(TcpConnectProber),
+    /// Boxed for the TLS session's buffers, as `Udp` is.
+    Https(Box<HttpsProber>),
+}
+
+impl Prober {
+    /// Opens a socket to `host`, `ep`'s name or one of its addresses, from
+    /// `source_port` (0 lets the OS pick). A TCP connect or handshake waits
+    /// at most `timeout`, the same as one probe.
+    pub fn open(
+        transport: Transport,
+        ep: &Endpoint,
+        host: &str,
+        bind_ip: Option<IpAddr>,
+        source_port: u16,
+        timeout: Duration,
+    ) -> io::Result<Self> {
+        let port = ep.port_for(transport);
+        Ok(match transport {
+            Transport::Udp => Prober::Udp(Box::new(os::UdpProber::with_source_port(
+                host,
+                port,
+                bind_ip,
+                source_port,
+            )?)),
+            Transport::Tcp => Prober::Tcp(TcpProber::connect(
+                host,
+                port,
+                bind_ip,
+                source_port,
+                timeout,
+                os::monotonic_now_ns,
+            )?),
+            Transport::TcpConnect => Prober::TcpConnect(TcpConnectProber::open(
+                host,
+                port,
+                bind_ip,
+                source_port,
+                timeout,
+                os::monotonic_now_ns,
+            )?),
+            Transport::Https => Prober::Https(Box::new(HttpsProber::connect(
+                host,
+                port,
+                &ep.host,
+                ep.https_ca_file.as_deref().map(Path::new),
+                bind_ip,
+                source_port,
+                timeout,
+                os::monotonic_now_ns,
+            )?)),
+        })
+    }
+
+    pub fn transport(&self) -> Transport {
+        match self {
+            Prober::Udp(_) => Transport::Udp,
+            Prober::Tcp(_) => Transport::Tcp,
+            Prober::TcpConnect(_) => Transport::TcpConnect,
+            Prober::Https(_) => Transport::Https,
+        }
+    }
+
+    pub fn send_and_receive_rtt(
+        &mut self,
+        msg: &[u8],
+        send_realtime_ns: u64,
+        send_mono_ns: u64,
+        timeout: Duration,
+    ) -> io::Result<Option<u64>> {
+        match self {
+            Prober::Udp(p) => p.send_and_receive_rtt(msg, send_realtime_ns, send_mono_ns, timeout),
+            Prober::Tcp(p) => p.send_and_receive_rtt(msg, send_realtime_ns, send_mono_ns, timeout),
+            Prober::TcpConnect(p) => {
+                p.send_and_receive_rtt(msg, send_realtime_ns, send_mono_ns, timeout)
+            }
+            Prober::Https(p) => {
+                p.send_and_receive_rtt(msg, send_realtime_ns, send_mono_ns, timeout)
+            }
+        }
+    }
+
+    pub fn iface_name(&self) -> io::Result<String> {
+        match self {
+            Prober::Udp(p) => p.iface_name(),
+            Prober::Tcp(_) | Prober::TcpConnect(_) | Prober::Https(_) => {
+                os::iface_for_ip(self.local_addr()?.ip())
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "iface not found"))
+            }
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Prober::Udp(p) => p.local_addr(),
+            Prober::Tcp(p) => p.local_addr(),
+            Prober::TcpConnect(p) => p.local_addr(),
+            Prober::Https(p) => p.local_addr(),
+        }
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Prober::Udp(p) => p.peer_addr(),
+            Prober::Tcp(p) => p.peer_addr(),
+            Prober::TcpConnect(p) => p.peer_addr(),
+            Prober::Https(p) => p.peer_addr(),
+        }
+    }
+
+    pub fn take_strays(&mut self) -> Vec<Packet> {
+        match self {
+            Prober::Udp(p) => p.take_strays(),
+            Prober::Tcp(p) => p.take_strays(),
+            Prober::TcpConnect(p) => p.take_strays(),
+            Prober::Https(p) => p.take_strays(),
+        }
+    }
+
+    /// Where the last RTT came from. TCP and HTTPS RTTs are clock reads
+    /// around the socket calls.
+    pub fn timestamp_source(&self) -> TimestampSource {
+        match self {
+            Prober::Udp(p) => p.timestamp_source(),
+            Prober::Tcp(_) | Prober::TcpConnect(_) | Prober::Https(_) => TimestampSource::Userspace,
+        }
+    }
+
+    /// The reflector's stamps on the last echo. Only v2 replies carry
+    /// them, and v2 is UDP's alone.
+    pub fn reflector_timing(&self) -> Option<ReflectorTiming> {
+        match self {
+            Prober::Udp(p) => p.reflector_timing(),
+            Prober::Tcp(_) | Prober::TcpConnect(_) | Prober::Https(_) => None,
+        }
+    }
+
+    /// Sends a keepalive from a UDP socket. TCP and TLS mappings outlive any
+    /// interval worth probing at, and a TCP handshake per probe has none to
+    /// keep, so they send nothing and return false.
+    pub fn keepalive(&mut self, msg: &[u8]) -> io::Result<bool> {
+        match self {
+            Prober::Udp(p) => p.send_only(msg).map(|()| true),
+            Prober::Tcp(_) | Prober::TcpConnect(_) | Prober::Https(_) => Ok(false),
+        }
+    }
+
+    /// Takes only replies signed under `secret`, for v2 probes. TCP and
+    /// HTTPS carry v1 packets only, so this is UDP's alone.
+    pub fn check_replies(&mut self, secret: &Secret) {
+        if let Prober::Udp(p) = self {
+            p.check_replies(secret.clone());
+        }
+    }
+
+    /// Marks UDP probes ECT(0) and reads the ECN field of their echoes.
+    /// TCP (and HTTPS over it) leaves ECN to the kernel's own negotiation,
+    /// so it returns false.
+    pub fn mark_ecn(&mut self) -> io::Result<bool> {
+        match self {
+            Prober::Udp(p) => p.mark_ecn().map(|()| true),
+            Prober::Tcp(_) | Prober::TcpConnect(_) | Prober::Https(_) => Ok(false),
+        }
+    }
+
+    pub fn reply_ecn(&self) -> Option<Ecn> {
+        match self {
+            Prober::Udp(p) => p.reply_ecn(),
+            Prober::Tcp(_) | Prober::TcpConnect(_) | Prober::Https(_) => None,
+        }
+    }
+
+    /// Only UDP sockets send with a chosen flow label.
+    pub fn set_flow_label(&mut self, label: u32) -> io::Result<()> {
+        match self {
+            Prober::Udp(p) => p.set_flow_label(label),
+            Prober::Tcp(_) | Prober::TcpConnect(_) | Prober::Https(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "flow labels are only set on UDP probes",
+            )),
+        }
+    }
+}
+
+/// `ep`'s transports, preferred first, as `udp>https:443`: HTTPS is the
+/// one that does not go to `ep.port`.
+pub fn describe_transports(ep: &Endpoint) -> String {
+    ep.transport_order()
+        .iter()
+        .map(|&t| match t {
+            Transport::Https => format!("{}:{}", t.as_str(), ep.port_for(t)),
+            _ => t.as_str().to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(">")
+}
+
+/// Unanswered bursts in a row before an endpoint moves to its next transport.
+const FALLBACK_SILENT_BURSTS: usize = 3;
+/// Answered bursts on a fallback transport before the preferred one is tried
+/// again.
+const PREFERRED_RETRY_BURSTS: usize = 30;
+
+/// Walks an endpoint down its `transports` while the current one goes
+/// unanswered, and back to the preferred one now and then, since whatever
+/// blocked it may have gone away.
+pub struct TransportLadder {
+    endpoint_id: String,
+    order: Vec<Transport>,
+    current: usize,
+    silent_bursts: usize,
+    fallback_bursts: usize,
+}
+
+impl TransportLadder {
+    pub fn new(endpoint_id: &str, order: &[Transport]) -> Self {
+        TransportLadder {
+            endpoint_id: endpoint_id.to_string(),
+            order: order.to_vec(),
+            current: 0,
+            silent_bursts: 0,
+            fallback_bursts: 0,
+        }
+    }
+
+    pub fn current(&self) -> Transport {
+        self.order[self.current]
+    }
+
+    /// A burst got replies. Returns the event to write, and the caller
+    /// reopens its sockets, when it is time to retry the preferred transport.
+    pub fn answered(&mut self) -> Option<EventRecord> {
+        self.silent_bursts = 0;
+        if self.current == 0 {
+            return None;
+        }
+        self.fallback_bursts += 1;
+        if self.fallback_bursts < PREFERRED_RETRY_BURSTS {
+            return None;
+        }
+        Some(self.switch_to(0, "retry"))
+    }
+
+    /// A burst went unanswered or the anchor refused the connection. Returns
+    /// the event to write, and the caller reopens its sockets, when this
+    /// moves to the next transport; after the last it starts over.
+    pub fn silent(&mut self) -> Option<EventRecord> {
+        self.silent_bursts += 1;
+        if self.silent_bursts < FALLBACK_SILENT_BURSTS || self.order.len() < 2 {
+            return None;
+        }
+        Some(self.switch_to((self.current + 1) % self.order.len(), "silent"))
+    }
+
+    fn switch_to(&mut self, next: usize, reason: &str) -> EventRecord {
+        let from = self.current();
+        self.current = next;
+        self.silent_bursts = 0;
+        self.fallback_bursts = 0;
+        EventRecord::new(
+            TRANSPORT_CHANGED_EVENT,
+            Some(self.endpoint_id.clone()),
+            serde_json::json!({
+                "from": from.as_str(),
+                "to": self.current().as_str(),
+                "reason": reason,
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORDER: [Transport; 3] = [Transport::Udp, Transport::Tcp, Transport::Https];
+
+    fn change(event: &EventRecord) -> (&str, &str, &str) {
+        let field = |name| event.detail[name].as_str().unwrap();
+        (field("from"), field("to"), field("reason"))
+    }
+
+    #[test]
+    fn falls_back_after_three_silent_bursts_and_wraps_around() {
+        let mut ladder = TransportLadder::new("a", &ORDER);
+        for next in ["tcp", "https", "udp"] {
+            for _ in 1..FALLBACK_SILENT_BURSTS {
+                assert!(ladder.silent().is_none());
+            }
+            let event = ladder.silent().unwrap();
+            assert_eq!(event.endpoint_id.as_deref(), Some("a"));
+            assert_eq!(change(&event).1, next);
+            assert_eq!(change(&event).2, "silent");
+            assert_eq!(ladder.current().as_str(), next);
+        }
+    }
+
+    #[test]
+    fn an_answer_restarts_the_silent_count() {
+        let mut ladder = TransportLadder::new("a", &ORDER);
+        for _ in 0..10 {
+            assert!(ladder.silent().is_none());
+            assert!(ladder.silent().is_none());
+            assert!(ladder.answered().is_none());
+        }
+        assert_eq!(ladder.current(), Transport::Udp);
+    }
+
+    #[test]
+    fn returns_to_the_preferred_transport_after_thirty_answered_bursts() {
+        let mut ladder = TransportLadder::new("a", &ORDER);
+        for _ in 0..FALLBACK_SILENT_BURSTS * 2 {
+            ladder.silent();
+        }
+        assert_eq!(ladder.current(), Transport::Https);
+        for _ in 1..PREFERRED_RETRY_BURSTS {
+            assert!(ladder.answered().is_none());
+        }
+        let event = ladder.answered().unwrap();
+        assert_eq!(change(&event), ("https", "udp", "retry"));
+        assert_eq!(ladder.current(), Transport::Udp);
+        // Settled on the preferred transport, answers change nothing.
+        for _ in 0..PREFERRED_RETRY_BURSTS * 2 {
+            assert!(ladder.answered().is_none());
+        }
+    }
+
+    #[test]
+    fn describes_the_https_port() {
+        let ep: Endpoint = serde_json::from_value(serde_json::json!({
+            "id": "a", "host": "a.example", "port": 9000, "regionHint": null,
+            "transports": ["udp", "tcp", "https"], "httpsPort": 8443,
+        }))
+        .unwrap();
+        assert_eq!(describe_transports(&ep), "udp>tcp>https:8443");
+    }
+
+    #[test]
+    fn a_single_transport_never_moves() {
+        let mut ladder = TransportLadder::new("a", &[Transport::Udp]);
+        for _ in 0..FALLBACK_SILENT_BURSTS * 3 {
+            assert!(ladder.silent().is_none());
+        }
+    }
+}
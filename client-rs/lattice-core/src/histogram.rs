@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+
+/// Lower edge of bucket 0; anything faster lands in the first bucket.
+pub const HISTOGRAM_FLOOR_MS: f64 = 0.01;
+/// Ratio between consecutive bucket edges (~5% relative precision).
+pub const HISTOGRAM_GROWTH: f64 = 1.05;
+
+/// Compact log-scale histogram of one burst's RTT samples.
+///
+/// Bucket `i` covers `[floor_ms * growth^i, floor_ms * growth^(i+1))`. Only
+/// non-empty buckets are stored, as `[index, count]` pairs in ascending order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Histogram {
+    pub floor_ms: f64,
+    pub growth: f64,
+    pub buckets: Vec<(u32, u32)>,
+}
+
+impl Histogram {
+    pub fn from_samples(samples: &[f64]) -> Self {
+        Self::with_params(samples, HISTOGRAM_FLOOR_MS, HISTOGRAM_GROWTH)
+    }
+
+    pub fn with_params(samples: &[f64], floor_ms: f64, growth: f64) -> Self {
+        let mut idx: Vec<u32> = samples
+            .iter()
+            .filter(|v| v.is_finite() && **v >= 0.0)
+            .map(|v| bucket_index(*v, floor_ms, growth))
+            .collect();
+        idx.sort_unstable();
+        let mut buckets: Vec<(u32, u32)> = Vec::new();
+        for i in idx {
+            match buckets.last_mut() {
+                Some((last, count)) if *last == i => *count += 1,
+                _ => buckets.push((i, 1)),
+            }
+        }
+        Self {
+            floor_ms,
+            growth,
+            buckets,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.buckets.iter().map(|(_, c)| *c as usize).sum()
+    }
+
+    /// Lower and upper edge of bucket `index` in ms.
+    pub fn bucket_bounds(&self, index: u32) -> (f64, f64) {
+        let lo = self.floor_ms * self.growth.powi(index as i32);
+        (lo, lo * self.growth)
+    }
+
+    /// Nearest-rank quantile (same rank rule as the analyzer), reported as the
+    /// geometric midpoint of the bucket holding that rank.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let n = self.count();
+        if n == 0 {
+            return None;
+        }
+        let rank = ((n - 1) as f64 * q.clamp(0.0, 1.0)).round() as usize;
+        let mut seen = 0usize;
+        for (index, count) in &self.buckets {
+            seen += *count as usize;
+            if rank < seen {
+                let (lo, hi) = self.bucket_bounds(*index);
+                return Some((lo * hi).sqrt());
+            }
+        }
+        None
+    }
+
+    /// Expands buckets back into one representative value per sample, in
+    /// ascending order, for code paths that expect raw samples.
+    pub fn representative_samples(&self) -> Vec<f64> {
+        let mut out = Vec::with_capacity(self.count());
+        for (index, count) in &self.buckets {
+            let (lo, hi) = self.bucket_bounds(*index);
+            let mid = (lo * hi).sqrt();
+            out.extend(std::iter::repeat_n(mid, *count as usize));
+        }
+        out
+    }
+}
+
+fn bucket_index(v: f64, floor_ms: f64, growth: f64) -> u32 {
+    if v <= floor_ms || growth <= 1.0 {
+        return 0;
+    }
+    ((v / floor_ms).ln() / growth.ln()).floor() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantiles_stay_within_bucket_precision() {
+        let samples: Vec<f64> = (1..=100).map(|i| i as f64).collect();
+        let h = Histogram::from_samples(&samples);
+        assert_eq!(h.count(), 100);
+        for (q, want) in [(0.0, 1.0), (0.5, 51.0), (1.0, 100.0)] {
+            let got = h.quantile(q).unwrap();
+            assert!(
+                (got - want).abs() / want <= HISTOGRAM_GROWTH - 1.0,
+                "q={q} got={got}"
+            );
+        }
+    }
+
+    #[test]
+    fn empty_and_invalid_samples_are_skipped() {
+        let h = Histogram::from_samples(&[f64::NAN, -1.0]);
+        assert_eq!(h.count(), 0);
+        assert!(h.quantile(0.5).is_none());
+    }
+}
@@ -1,3 +1,34 @@
+mod histogram;
+mod net;
+mod packet;
+mod regions;
+mod replay;
+mod schedule;
+mod secret;
+mod shutdown;
+mod tcp;
+mod templates;
+
+pub use histogram::{Histogram, HISTOGRAM_FLOOR_MS, HISTOGRAM_GROWTH};
+pub use net::{resolve_first_for_family, MAX_STRAYS};
+pub use packet::{
+    build_packet, build_packet_v2, burst_before, parse_lato_packet, Packet, ParseError, ProbeId,
+    ReflectorSync, ReflectorTiming, LATO_MAGIC, LATO_PACKET_LEN, LATO_TAG_LEN, LATO_V2_PACKET_LEN,
+    LATO_V2_VERSION, LATO_VERSION, UNKNOWN_CLOCK_OFFSET_US, UNSYNCED_STRATUM,
+};
+pub use regions::{cloud_region, find_cloud_region, CloudRegion, CLOUD_REGIONS};
+pub use replay::{Replay, ReplayGuard, REPLAY_WINDOW};
+pub use schedule::{
+    active_blackout, civil_from_days, utc_minute, Blackout, CompiledBlackout, CronSchedule,
+    UtcMinute, MAX_BLACKOUT_MINUTES,
+};
+pub use secret::{constant_time_eq, Secret, SecretHex, MIN_SECRET_BYTES, SECRET_BYTES};
+#[cfg(unix)]
+pub use shutdown::catch_shutdown;
+pub use shutdown::{request_shutdown, shutdown_requested};
+pub use tcp::{TcpConnectProber, TcpProber};
+pub use templates::{expand_endpoint_templates, ENDPOINT_TEMPLATES_KEY};
+
 use std::fs;
 use std::io;
 use std::path::Path;
@@ -7,6 +38,17 @@ use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 
+/// Consistency constant relating MAD to the standard deviation of a normal.
+const MAD_SCALE: f64 = 1.4826;
+/// The same constant for the mean absolute deviation: sqrt(pi / 2).
+const MEAN_AD_SCALE: f64 = 1.2533;
+/// Below this many samples the median/MAD pair is too noisy to filter on.
+const HAMPEL_MIN_SAMPLES: usize = 3;
+/// Mean Earth radius, for great-circle distances.
+pub const EARTH_RADIUS_KM: f64 = 6371.0;
+/// 64 bits of the keyed hash; plenty to keep a few thousand hosts distinct.
+pub const PSEUDONYM_HEX_LEN: usize = 16;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Endpoint {
@@ -18,6 +60,270 @@ pub struct Endpoint {
     pub lat: Option<f64>,
     #[serde(default)]
     pub lon: Option<f64>,
+    /// Height above the ellipsoid in metres, for anchors well off the ground
+    /// (stratospheric platforms, ships' masts, aircraft). Unset means surface.
+    #[serde(default)]
+    pub alt_m: Option<f64>,
+    /// Set when the endpoint is operated by us or its operator consented to probing.
+    #[serde(default)]
+    pub consented: bool,
+    #[serde(default)]
+    pub blackouts: Vec<Blackout>,
+    /// Transports to try, preferred first; empty means UDP only.
+    #[serde(default)]
+    pub transports: Vec<Transport>,
+    /// Port of the reflector's HTTPS echo; unset means 443.
+    #[serde(default)]
+    pub https_port: Option<u16>,
+    /// PEM file of the CA that signed the reflector's HTTPS certificate,
+    /// for self-signed reflectors; unset means the system roots.
+    #[serde(default)]
+    pub https_ca_file: Option<String>,
+    /// LATO version to probe with: 1, or 2 (`LATO_V2_VERSION`) for signed,
+    /// stamped replies from a reflector that refuses replayed probes (UDP
+    /// only).
+    #[serde(default = "default_packet_version")]
+    pub packet_version: u32,
+}
+
+pub const DEFAULT_HTTPS_PORT: u16 = 443;
+
+impl Endpoint {
+    pub fn transport_order(&self) -> &[Transport] {
+        if self.transports.is_empty() {
+            &[Transport::Udp]
+        } else {
+            &self.transports
+        }
+    }
+
+    /// The port `transport` reaches the reflector on: HTTPS has its own,
+    /// the rest share `port`.
+    pub fn port_for(&self, transport: Transport) -> u16 {
+        match transport {
+            Transport::Https => self.https_port.unwrap_or(DEFAULT_HTTPS_PORT),
+            _ => self.port,
+        }
+    }
+}
+
+/// How probes reach an endpoint's reflector.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum Transport {
+    /// One datagram per probe, with kernel receive timestamps.
+    #[default]
+    Udp,
+    /// The same 32-byte packets back to back on one TCP connection, for
+    /// networks that drop UDP. Every RTT includes any retransmission.
+    Tcp,
+    /// A fresh TCP handshake per probe, timed from SYN to SYN/ACK (or the
+    /// RST of a closed port), for anchors that run no reflector. Nothing in
+    /// the reply is signed.
+    TcpConnect,
+    /// Each packet POSTed to the reflector's `/v1/echo` over one TLS
+    /// connection, for networks that pass only web traffic. Every RTT
+    /// includes the reflector's HTTP handling.
+    Https,
+}
+
+impl Transport {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Transport::Udp => "udp",
+            Transport::Tcp => "tcp",
+            Transport::TcpConnect => "tcpConnect",
+            Transport::Https => "https",
+        }
+    }
+}
+
+/// Which clock reading an RTT came from, coarsest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TimestampSource {
+    /// Clock reads around the syscalls, so scheduling delay lands in the RTT
+    /// (TCP, and UDP when the kernel gave no receive timestamp).
+    Userspace,
+    /// Kernel software receive timestamp against the send-time clock read.
+    Kernel,
+    /// Kernel software timestamps on both the probe and its echo, so the
+    /// time between the clock read and the send is left out.
+    KernelTx,
+    /// NIC transmit and receive timestamps, both on the NIC's clock.
+    Hardware,
+}
+
+impl TimestampSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TimestampSource::Userspace => "userspace",
+            TimestampSource::Kernel => "kernel",
+            TimestampSource::KernelTx => "kernelTx",
+            TimestampSource::Hardware => "hardware",
+        }
+    }
+}
+
+/// A stable target on the local network probed right after every remote
+/// sample, so host-side stalls show up on both series at once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InterleaveTarget {
+    pub host: String,
+    pub port: u16,
+}
+
+/// A brief upload the client generates toward the endpoint (as datagrams the
+/// reflector discards) while it probes, to see how far RTT climbs once the
+/// access link's queue fills.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BufferbloatLoad {
+    pub duration_ms: u64,
+    pub mbps: f64,
+    /// Run the load on every Nth burst per endpoint.
+    #[serde(default = "default_bufferbloat_every_bursts")]
+    pub every_bursts: u64,
+}
+
+/// On-client location estimate: every `everyBursts` bursts the client runs
+/// the analyzer's estimate and claim check over the newest `windowBursts`
+/// and writes an `estimate` record, so alerts need no raw data off the
+/// machine.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RollingEstimate {
+    #[serde(default = "default_rolling_every_bursts")]
+    pub every_bursts: usize,
+    #[serde(default = "default_rolling_window_bursts")]
+    pub window_bursts: usize,
+    /// Location to check; `claimedEgressRegion` is used when unset.
+    #[serde(default)]
+    pub claim_lat: Option<f64>,
+    #[serde(default)]
+    pub claim_lon: Option<f64>,
+}
+
+/// When the writer moves the output file aside and starts a new one. The
+/// old file becomes `<stem>-<UTC date>.jsonl`, compressed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogRotation {
+    /// Rotate before a line would take the file past this size; 0 for no
+    /// size limit.
+    #[serde(default)]
+    pub max_bytes: u64,
+    /// Rotate when the UTC hour or day changes.
+    #[serde(default)]
+    pub every: Option<RotateEvery>,
+    /// Archives kept per output file, oldest deleted first; 0 keeps all.
+    #[serde(default)]
+    pub keep: usize,
+    #[serde(default)]
+    pub compression: LogCompression,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RotateEvery {
+    Hour,
+    Day,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LogCompression {
+    #[default]
+    Zstd,
+    Gzip,
+    None,
+}
+
+impl LogCompression {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogCompression::Zstd => "zstd",
+            LogCompression::Gzip => "gzip",
+            LogCompression::None => "none",
+        }
+    }
+
+    /// Suffix added after `.jsonl`; `None` leaves archives uncompressed.
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            LogCompression::Zstd => Some("zst"),
+            LogCompression::Gzip => Some("gz"),
+            LogCompression::None => None,
+        }
+    }
+}
+
+/// Localhost HTTP API through which other software on the host (a browser
+/// extension, an endpoint agent) asks for a burst and gets its summary.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalApi {
+    /// Loopback address and port to listen on.
+    #[serde(default = "default_local_api_listen")]
+    pub listen: String,
+    /// Callers allowed in, each with its own bearer token.
+    #[serde(default)]
+    pub tokens: Vec<ApiToken>,
+    /// Bursts each caller may ask for.
+    #[serde(default)]
+    pub rate_limit: ApiRateLimit,
+}
+
+/// One caller of the local API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiToken {
+    /// Shown in the log and in errors; the token itself never is.
+    pub name: String,
+    pub token_hex: SecretHex,
+}
+
+/// Per-caller token bucket: `burst` requests at once, refilled at
+/// `perMinute`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ApiRateLimit {
+    pub burst: f64,
+    pub per_minute: f64,
+}
+
+impl Default for ApiRateLimit {
+    fn default() -> Self {
+        ApiRateLimit {
+            burst: 3.0,
+            per_minute: 6.0,
+        }
+    }
+}
+
+/// What the client keeps on disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PrivacyMode {
+    /// Every burst, with its samples and addresses as configured.
+    #[default]
+    Off,
+    /// No bursts: hourly `features` records per anchor, plus `estimate`
+    /// records when `rollingEstimate` is set. Samples and local addresses
+    /// stay in memory.
+    Features,
+}
+
+impl PrivacyMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PrivacyMode::Off => "off",
+            PrivacyMode::Features => "features",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,55 +334,278 @@ pub struct ProbePath {
     pub bind_interface: Option<String>,
     #[serde(default)]
     pub bind_ip: Option<String>,
+    /// Overrides the session-wide `accessFloorMs` for this path.
+    #[serde(default)]
+    pub access_floor_ms: Option<f64>,
+    /// Fixed UUID so records from this path can be matched across sessions.
+    #[serde(default)]
+    pub uuid: Option<String>,
+    /// Source ports to probe from, pinned; overrides `ecmpPorts` for this path.
+    #[serde(default)]
+    pub source_ports: Vec<u16>,
+    /// Overrides the session-wide `keepaliveSeconds` for this path.
+    #[serde(default)]
+    pub keepalive_seconds: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
-    pub secret_hex: String,
+    pub secret_hex: SecretHex,
     pub endpoints: Vec<Endpoint>,
     #[serde(default)]
     pub probe_paths: Vec<ProbePath>,
+    /// Adds a probe path per up interface with a routable address that no
+    /// `probePaths` entry binds, for hosts with several upstreams.
+    #[serde(default)]
+    pub auto_probe_paths: bool,
     pub samples_per_endpoint: usize,
     pub spacing_ms: u64,
     pub timeout_ms: u64,
     pub interval_seconds: u64,
     #[serde(default = "default_pacing_spin_us")]
     pub pacing_spin_us: u64,
+    #[serde(default = "default_max_probes_per_sec_per_dest")]
+    pub max_probes_per_sec_per_dest: f64,
+    #[serde(default)]
+    pub outlier_mad_k: Option<f64>,
+    #[serde(default)]
+    pub record_histogram: bool,
+    #[serde(default = "default_record_raw_samples")]
+    pub record_raw_samples: bool,
+    #[serde(default = "default_display_decimals")]
+    pub display_decimals: usize,
+    #[serde(default = "default_baseline_bursts")]
+    pub baseline_bursts: u64,
+    /// Last-mile latency (DOCSIS, LTE, ...) the analyzer subtracts before
+    /// converting RTT to distance.
+    #[serde(default)]
+    pub access_floor_ms: Option<f64>,
+    /// Between bursts, sends a keepalive this often from each UDP probe
+    /// socket, so a stateful firewall or NAT keeps the mapping. 0 turns it
+    /// off for a path.
+    #[serde(default)]
+    pub keepalive_seconds: Option<u64>,
+    /// Endpoints with fewer valid samples (or bursts) are left out of the
+    /// analyzer's estimate and claim checks. Zero disables the gate.
+    #[serde(default)]
+    pub min_samples_per_endpoint: usize,
+    #[serde(default)]
+    pub min_bursts_per_endpoint: usize,
+    /// Round trips through the in-process loopback reflector per burst; their
+    /// minimum is recorded as `selfFloorMs`. Zero disables the self-test.
+    #[serde(default = "default_self_floor_probes")]
+    pub self_floor_probes: usize,
+    #[serde(default)]
+    pub interleave: Option<InterleaveTarget>,
+    /// Self-generated upload during some bursts, for bufferbloat scoring.
+    /// Off when unset.
+    #[serde(default)]
+    pub bufferbloat_load: Option<BufferbloatLoad>,
+    /// Estimate records written by the client itself. Off when unset.
+    #[serde(default)]
+    pub rolling_estimate: Option<RollingEstimate>,
+    /// Rotation of the output file into compressed archives. Off when unset.
+    #[serde(default)]
+    pub rotate: Option<LogRotation>,
+    /// `features` writes derived features instead of bursts.
+    #[serde(default)]
+    pub privacy_mode: PrivacyMode,
+    /// Distinct source ports per endpoint, probed round-robin so ECMP
+    /// hashing can spread them over different paths. 0 or 1 keeps one socket.
+    #[serde(default)]
+    pub ecmp_ports: usize,
+    /// IPv6 flow labels the samples of a burst rotate through, for the same
+    /// purpose on v6 paths. Empty leaves the label to the OS.
+    #[serde(default)]
+    pub ipv6_flow_labels: Vec<u32>,
+    /// Sends UDP probes ECN-capable (ECT(0)) and counts the codepoints the
+    /// replies arrive with.
+    #[serde(default)]
+    pub ecn: bool,
     pub output_path: String,
+    #[serde(default)]
+    pub control_socket: Option<String>,
+    /// HTTP API on a loopback port. Off when unset.
+    #[serde(default)]
+    pub local_api: Option<LocalApi>,
+    /// Directory for what the client keeps across restarts. Defaults to
+    /// `outputPath` with a `.state` extension.
+    #[serde(default)]
+    pub state_dir: Option<String>,
+    /// Name for this host in fleet reports, written to `session_start`.
+    /// Defaults to a hash of the OS machine id.
+    #[serde(default)]
+    pub machine_id: Option<String>,
+    #[serde(default)]
+    pub blackouts: Vec<Blackout>,
     pub claimed_egress_region: Option<String>,
     pub physics_mismatch_threshold_ms: f64,
 }
 
 impl Config {
     pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let data = fs::read(path)?;
-        let cfg = serde_json::from_slice(&data)
+        Self::load_layered(path, &[] as &[&Path])
+    }
+
+    /// Loads `base` and applies each override file in order; later files win.
+    /// See `merge_json` for the precedence rules.
+    pub fn load_layered<P: AsRef<Path>, O: AsRef<Path>>(
+        base: P,
+        overrides: &[O],
+    ) -> io::Result<Self> {
+        let mut doc = read_json(base.as_ref())?;
+        for path in overrides {
+            merge_json(&mut doc, read_json(path.as_ref())?);
+        }
+        interpolate_env(&mut doc, "", &|name| std::env::var(name).ok())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        expand_endpoint_templates(&mut doc)
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        Ok(cfg)
+        serde_json::from_value(doc).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+fn read_json(path: &Path) -> io::Result<serde_json::Value> {
+    let data = fs::read(path)?;
+    serde_json::from_slice(&data).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })
+}
+
+/// Expands `${NAME}` in every string value using `lookup`; `$${` is a
+/// literal `${`. A missing variable is an error naming the config key, so a
+/// service manager that forgot to inject a secret fails loudly at startup.
+pub fn interpolate_env(
+    value: &mut serde_json::Value,
+    key_path: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<(), String> {
+    use serde_json::Value;
+    match value {
+        Value::String(s) if s.contains('$') => {
+            *s = interpolate_str(s, key_path, lookup)?;
+        }
+        Value::Object(obj) => {
+            for (key, v) in obj.iter_mut() {
+                let child = if key_path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", key_path, key)
+                };
+                interpolate_env(v, &child, lookup)?;
+            }
+        }
+        Value::Array(items) => {
+            for (i, v) in items.iter_mut().enumerate() {
+                interpolate_env(v, &format!("{}[{}]", key_path, i), lookup)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn interpolate_str(
+    s: &str,
+    key_path: &str,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        let tail = &rest[pos..];
+        if let Some(after) = tail.strip_prefix("$${") {
+            out.push_str("${");
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix("${") {
+            let end = after
+                .find('}')
+                .ok_or_else(|| format!("{}: unterminated ${{ in config value", key_path))?;
+            let name = &after[..end];
+            let value = lookup(name)
+                .ok_or_else(|| format!("{}: environment variable {} is not set", key_path, name))?;
+            out.push_str(&value);
+            rest = &after[end + 1..];
+        } else {
+            out.push('$');
+            rest = &tail[1..];
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Layers `overlay` onto `base`: objects merge key by key (recursively),
+/// everything else (scalars and arrays, including `endpoints`) replaces the
+/// base value wholesale, and `null` removes the key.
+pub fn merge_json(base: &mut serde_json::Value, overlay: serde_json::Value) {
+    use serde_json::Value;
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                if value.is_null() {
+                    base.remove(&key);
+                    continue;
+                }
+                match base.get_mut(&key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BurstRecord {
     pub ts_unix_ms: i64,
+    /// UUIDs for cross-referencing; empty in logs from older clients.
+    #[serde(default)]
+    pub session_id: String,
+    #[serde(default)]
+    pub burst_id: String,
     pub endpoint_id: String,
     pub host: String,
     pub port: u16,
+    /// Older records were all UDP.
+    #[serde(default)]
+    pub transport: Transport,
+    /// The coarsest source among the burst's samples; null without samples
+    /// and in logs from older clients.
+    #[serde(default)]
+    pub timestamp_source: Option<TimestampSource>,
     #[serde(default)]
     pub probe_path: String,
     #[serde(default)]
+    pub probe_path_uuid: String,
+    #[serde(default)]
     pub probe_bind_iface: String,
     #[serde(default)]
     pub probe_bind_ip: String,
     #[serde(default)]
     pub local_addr: String,
+    /// Resolved IP of `host` for this burst; empty in logs from older clients.
+    #[serde(default)]
+    pub remote_addr: String,
     pub region_hint: Option<String>,
+    #[serde(default)]
     pub samples_ms: Vec<f64>,
     pub min_ms: Option<f64>,
     pub p05_ms: Option<f64>,
     pub median_ms: Option<f64>,
+    #[serde(default)]
+    pub outliers_filtered: usize,
+    #[serde(default)]
+    pub histogram: Option<Histogram>,
     pub iface: String,
     #[serde(default)]
     pub iface_name: String,
@@ -90,10 +619,548 @@ pub struct BurstRecord {
     pub utun_interfaces: Vec<UtunInterface>,
     #[serde(default)]
     pub dest_is_loopback: bool,
+    /// Configured access-latency floor for this probe path, if any.
+    #[serde(default)]
+    pub access_floor_ms: Option<f64>,
+    /// Host scheduling/syscall latency measured against the in-process
+    /// loopback reflector during this burst.
+    #[serde(default)]
+    pub self_floor_ms: Option<f64>,
+    /// With `interleave` set, the LAN reference RTT taken right after each
+    /// entry of `samplesMs` (null when the reference timed out).
+    #[serde(default)]
+    pub reference_samples_ms: Vec<Option<f64>>,
+    /// Everything the burst's interface carried while the burst ran, probes
+    /// included; null when the OS counters were unreadable.
+    #[serde(default)]
+    pub iface_traffic: Option<IfaceTraffic>,
+    /// The OS's own evaluation of the path to this endpoint from the
+    /// burst's local address (macOS only; null elsewhere).
+    #[serde(default)]
+    pub network_path: Option<NetworkPath>,
+    /// A system relay that was carrying some of the host's traffic, such as
+    /// `privateRelay`. It moves where web services see the host without a
+    /// tunnel interface; the probes themselves do not go through it.
+    #[serde(default)]
+    pub relay: Option<String>,
+    /// With `bufferbloatLoad`, the RTTs probed while the load ran.
+    #[serde(default)]
+    pub loaded_samples_ms: Vec<f64>,
+    #[serde(default)]
+    pub bufferbloat: Option<Bufferbloat>,
+    /// Random per-socket epoch carried in every probe; a new one after each
+    /// socket refresh.
+    #[serde(default)]
+    pub socket_epoch: u32,
+    /// The burst's number in its endpoint worker's sequence; wraps at 65536.
+    #[serde(default)]
+    pub burst_seq: u16,
+    /// Verified echoes seen during this burst that missed their timeout: of
+    /// this burst's own probes, and of earlier bursts' on the same socket.
+    #[serde(default)]
+    pub late_replies: usize,
+    #[serde(default)]
+    pub late_replies_prior: usize,
+    /// Verified copies of a reply already taken for this socket's probes:
+    /// duplicated on the path, or replayed.
+    #[serde(default)]
+    pub duplicate_replies: usize,
+    /// Keepalives sent from this endpoint's sockets since its previous burst.
+    #[serde(default)]
+    pub keepalives_sent: usize,
+    /// Sends the local stack refused during this burst. Those probes have no
+    /// entry in `samplesMs`.
+    #[serde(default)]
+    pub send_errors: SendErrors,
+    /// With `ecn`, this burst's replies by ECN codepoint; null when off or
+    /// when the OS does not report it (TCP, Windows).
+    #[serde(default)]
+    pub ecn: Option<EcnCounts>,
+    /// With more than one source port (`ecmpPorts` or a path's
+    /// `sourcePorts`), the local port each entry of `samplesMs` went out from.
+    #[serde(default)]
+    pub sample_source_ports: Vec<u16>,
+    /// With `ipv6FlowLabels` on an IPv6 endpoint, the flow label each entry
+    /// of `samplesMs` was sent with (0 where setting it failed).
+    #[serde(default)]
+    pub sample_flow_labels: Vec<u32>,
+    /// For v2 endpoints, each entry of `samplesMs` split at the reflector's
+    /// stamps (null where the reply carried none).
+    #[serde(default)]
+    pub sample_legs: Vec<Option<SampleLegs>>,
+    /// For v2 endpoints, the reflector's clock as the burst's last stamped
+    /// reply reported it.
+    #[serde(default)]
+    pub reflector_clock: Option<ReflectorClock>,
     pub claimed_egress_region: Option<String>,
     pub notes: Vec<String>,
 }
 
+/// Cumulative per-interface counters as the OS keeps them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IfaceCounters {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    /// Counter width: 64 on Linux, 32 on macOS (`if_data`), where a busy
+    /// link wraps the byte counters every few seconds to minutes.
+    pub bits: u32,
+}
+
+impl IfaceCounters {
+    /// Traffic between `earlier` and `self`, allowing for one wrap of each
+    /// counter. `None` if the widths differ.
+    pub fn since(&self, earlier: &IfaceCounters, duration_ms: f64) -> Option<IfaceTraffic> {
+        if self.bits != earlier.bits {
+            return None;
+        }
+        let mask = u64::MAX >> (u64::BITS - self.bits.min(u64::BITS));
+        let delta = |now: u64, then: u64| now.wrapping_sub(then) & mask;
+        Some(IfaceTraffic {
+            rx_bytes: delta(self.rx_bytes, earlier.rx_bytes),
+            tx_bytes: delta(self.tx_bytes, earlier.tx_bytes),
+            rx_packets: delta(self.rx_packets, earlier.rx_packets),
+            tx_packets: delta(self.tx_packets, earlier.tx_packets),
+            duration_ms,
+        })
+    }
+}
+
+/// `BurstRecord::relay` for iCloud Private Relay.
+pub const PRIVATE_RELAY: &str = "privateRelay";
+
+/// A path as Network.framework evaluates it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkPath {
+    /// `satisfied`, `unsatisfied`, `satisfiable` (e.g. waiting on VPN on
+    /// demand), or `invalid`.
+    pub status: String,
+    /// The system treats the path as metered: cellular, or a hotspot.
+    pub expensive: bool,
+    /// Low Data Mode is on for it.
+    pub constrained: bool,
+    /// Interfaces the path may use, the one in use first.
+    pub interfaces: Vec<PathInterface>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathInterface {
+    pub iface_name: String,
+    /// `wifi`, `cellular`, `ethernet`, `loopback`, or `other`.
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+/// Interface counter deltas over one burst.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IfaceTraffic {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    /// From the counter read before the first probe to the one after the last.
+    pub duration_ms: f64,
+}
+
+impl IfaceTraffic {
+    /// Both directions combined.
+    pub fn bytes_per_sec(&self) -> Option<f64> {
+        (self.duration_ms > 0.0)
+            .then(|| (self.rx_bytes + self.tx_bytes) as f64 / (self.duration_ms / MS_PER_SEC))
+    }
+}
+
+/// Combined receive and transmit rate above which an interface counts as
+/// busy (about 8 Mbit/s; a backup or a large download, not a video call).
+pub const BUSY_IFACE_BYTES_PER_SEC: f64 = 1_000_000.0;
+
+/// Upper bounds of the bufferbloat grades, in ms of added latency under load;
+/// anything above the last is `F`.
+const BLOAT_GRADES: [(f64, &str); 4] = [(5.0, "A"), (30.0, "B"), (60.0, "C"), (200.0, "D")];
+const BLOAT_FAIL_GRADE: &str = "F";
+
+/// Where a burst's loaded RTT came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BloatSource {
+    /// Probes during the client's own `bufferbloatLoad`.
+    Load,
+    /// The burst ran while the interface was busy with other traffic.
+    Traffic,
+}
+
+/// A burst's failed sends by cause.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SendErrors {
+    /// The interface queue or socket buffer was full (ENOBUFS).
+    pub no_buffer: usize,
+    /// A local firewall rule rejected the packet (EPERM/EACCES).
+    pub denied: usize,
+    /// Anything else: no route, interface down, port unreachable.
+    pub other: usize,
+}
+
+/// The ECN field of an IP header (RFC 3168): the low two bits of the IPv4
+/// TOS byte or the IPv6 traffic class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ecn {
+    NotEct,
+    Ect1,
+    Ect0,
+    /// Congestion experienced: a router marked the packet instead of
+    /// dropping it.
+    Ce,
+}
+
+impl Ecn {
+    pub fn from_tos(tos: u8) -> Self {
+        match tos & 0b11 {
+            0b00 => Ecn::NotEct,
+            0b01 => Ecn::Ect1,
+            0b10 => Ecn::Ect0,
+            _ => Ecn::Ce,
+        }
+    }
+
+    /// The field's value, for a TOS byte or traffic class.
+    pub fn bits(self) -> u8 {
+        match self {
+            Ecn::NotEct => 0b00,
+            Ecn::Ect1 => 0b01,
+            Ecn::Ect0 => 0b10,
+            Ecn::Ce => 0b11,
+        }
+    }
+}
+
+/// A burst's replies by the ECN codepoint they arrived with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EcnCounts {
+    /// ECT(0) or ECT(1): capable and unmarked.
+    pub ect: usize,
+    pub ce: usize,
+    /// The marking was cleared on the way, or the reflector sends without it.
+    pub not_ect: usize,
+}
+
+impl EcnCounts {
+    pub fn count(&mut self, ecn: Ecn) {
+        match ecn {
+            Ecn::Ect0 | Ecn::Ect1 => self.ect += 1,
+            Ecn::Ce => self.ce += 1,
+            Ecn::NotEct => self.not_ect += 1,
+        }
+    }
+}
+
+/// One v2 round trip split at the reflector's stamps. The legs are read off
+/// two clocks, so each carries their offset (with opposite signs) and may be
+/// negative; `forwardMs + reverseMs` is the RTT in `samplesMs`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SampleLegs {
+    /// Time between the reflector's receive and transmit, left out of the RTT.
+    pub hold_ms: f64,
+    pub forward_ms: f64,
+    pub reverse_ms: f64,
+}
+
+impl From<ReflectorTiming> for SampleLegs {
+    fn from(t: ReflectorTiming) -> Self {
+        SampleLegs {
+            hold_ms: ns_to_ms(t.hold_ns),
+            forward_ms: t.forward_ns as f64 / NS_PER_MS,
+            reverse_ms: t.reverse_ns as f64 / NS_PER_MS,
+        }
+    }
+}
+
+/// A reflector's NTP state, from its v2 replies.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReflectorClock {
+    /// Its clock less the NTP reference, as its daemon estimated it; null
+    /// when it could not say.
+    pub offset_ms: Option<f64>,
+    /// NTP stratum; null when unknown, 16 when unsynchronized.
+    pub stratum: Option<u8>,
+}
+
+impl ReflectorClock {
+    /// Whether its stamps can be read as UTC, give or take `offset_ms`: as
+    /// `ReflectorSync::synced`.
+    pub fn synced(&self) -> bool {
+        self.offset_ms.is_some() && self.stratum != Some(UNSYNCED_STRATUM)
+    }
+}
+
+impl From<ReflectorSync> for ReflectorClock {
+    fn from(s: ReflectorSync) -> Self {
+        ReflectorClock {
+            offset_ms: s.offset_us.map(|us| f64::from(us) / US_PER_MS),
+            stratum: s.stratum,
+        }
+    }
+}
+
+/// How much latency the access link adds under load: the loaded median
+/// against the idle p05.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bufferbloat {
+    pub source: BloatSource,
+    pub idle_p05_ms: f64,
+    pub loaded_p50_ms: f64,
+    pub bloat_ms: f64,
+    pub grade: String,
+}
+
+impl Bufferbloat {
+    pub fn new(source: BloatSource, idle_p05_ms: f64, loaded_p50_ms: f64) -> Self {
+        let bloat_ms = (loaded_p50_ms - idle_p05_ms).max(0.0);
+        Bufferbloat {
+            source,
+            idle_p05_ms,
+            loaded_p50_ms,
+            bloat_ms,
+            grade: bloat_grade(bloat_ms).to_string(),
+        }
+    }
+}
+
+/// Letter grade for `bloat_ms` of added latency under load.
+pub fn bloat_grade(bloat_ms: f64) -> &'static str {
+    BLOAT_GRADES
+        .iter()
+        .find(|(max_ms, _)| bloat_ms < *max_ms)
+        .map_or(BLOAT_FAIL_GRADE, |(_, grade)| grade)
+}
+
+pub const EVENT_RECORD_TYPE: &str = "event";
+/// Event written once per client start; its `detail.clockQuality` is the
+/// session header the analyzer reads.
+pub const SESSION_START_EVENT: &str = "session_start";
+
+/// Event written when a capture ends at a limit (`--duration`, `--max-bursts`;
+/// reason `limit`) or on SIGINT/SIGTERM (reason `signal`).
+pub const SESSION_END_EVENT: &str = "session_end";
+
+/// Event written when the OS announces it is about to suspend. Best effort:
+/// the machine may freeze before the line reaches disk.
+pub const SUSPEND_EVENT: &str = "suspend";
+
+/// Event written after the machine wakes. `detail.suspendedMs` is how long it
+/// slept, from the clock that keeps counting through suspend.
+pub const RESUME_EVENT: &str = "resume";
+
+/// Event written when an endpoint's sends start failing locally (no route,
+/// network down) or its socket can't be opened. `detail.errno` is the OS error.
+pub const PATH_DOWN_EVENT: &str = "path_down";
+
+/// Event written when the path works but the anchor doesn't answer: the
+/// reflector port refuses (`detail.errno`) or bursts come back empty.
+pub const ANCHOR_DOWN_EVENT: &str = "anchor_down";
+
+/// Event written at the first answered burst after `path_down` or
+/// `anchor_down`; `detail.outage` says which one ended.
+pub const ENDPOINT_RECOVERED_EVENT: &str = "endpoint_recovered";
+
+/// Event written when an endpoint moves to another of its `transports`:
+/// `detail.from`, `detail.to`, and `detail.reason` (`silent` after bursts
+/// went unanswered, `retry` when going back to the preferred one).
+pub const TRANSPORT_CHANGED_EVENT: &str = "transport_changed";
+
+/// Event written when an endpoint's host re-resolves to another address and
+/// its sockets move there before the next burst: `detail.from`, `detail.to`,
+/// and `detail.ttlSeconds` of the new answer.
+pub const DESTINATION_CHANGED_EVENT: &str = "destination_changed";
+
+/// Event written when a `bindInterface` path's address changes (a new DHCP
+/// lease, an expired IPv6 address) and its sockets are rebound to the new one
+/// before the next burst: `detail.interface`, `detail.from`, and `detail.to`.
+pub const BIND_ADDRESS_CHANGED_EVENT: &str = "bind_address_changed";
+
+/// Event written when an edited config is picked up without a restart:
+/// `detail.added` and `detail.removed` list probe targets by endpoint id,
+/// and `detail.restartNeeded` the changed settings that were kept as is.
+pub const CONFIG_RELOADED_EVENT: &str = "config_reloaded";
+
+/// A system sleep transition reported by the OS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerEvent {
+    Suspending,
+    Resumed,
+}
+
+const NS_PER_MS: f64 = 1_000_000.0;
+const MS_PER_SEC: f64 = 1000.0;
+const PPM: f64 = 1e-6;
+
+/// Startup clock self-test for the probing host.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClockQuality {
+    /// Resolution the OS advertises for the realtime clock.
+    pub realtime_resolution_ns: Option<u64>,
+    /// Smallest non-zero step seen between back-to-back realtime reads.
+    pub observed_granularity_ns: Option<u64>,
+    /// Realtime rate relative to monotonic over the self-test window.
+    pub realtime_vs_monotonic_ppm: Option<f64>,
+    pub ntp_synced: Option<bool>,
+    pub ntp_offset_ms: Option<f64>,
+    pub ntp_est_error_ms: Option<f64>,
+    pub ntp_source: Option<String>,
+}
+
+impl ClockQuality {
+    /// Timestamp uncertainty for one RTT of `rtt_ms`: one clock step plus the
+    /// realtime rate error accumulated over the RTT.
+    pub fn rtt_error_ms(&self, rtt_ms: f64) -> Option<f64> {
+        let step_ns = self
+            .observed_granularity_ns
+            .or(self.realtime_resolution_ns)?;
+        let rate = self.realtime_vs_monotonic_ppm.unwrap_or(0.0).abs() * PPM;
+        Some(step_ns as f64 / NS_PER_MS + rate * rtt_ms)
+    }
+}
+
+/// The probing host's configured timezone and locale, as the user set them.
+/// Written in the session header (`detail.hostLocale`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostLocale {
+    /// IANA zone name, e.g. `Europe/Berlin`.
+    pub timezone: Option<String>,
+    /// Offset from UTC at session start, daylight saving included.
+    pub utc_offset_minutes: Option<i32>,
+    /// `LC_ALL`, `LC_TIME`, or `LANG`, first one set.
+    pub locale: Option<String>,
+}
+
+/// Non-burst line in the JSONL output (blackouts, lifecycle changes). Carries
+/// `recordType: "event"` so burst readers can skip it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventRecord {
+    pub record_type: String,
+    pub ts_unix_ms: i64,
+    pub event: String,
+    #[serde(default)]
+    pub endpoint_id: Option<String>,
+    #[serde(default)]
+    pub detail: serde_json::Value,
+}
+
+impl EventRecord {
+    pub fn new(event: &str, endpoint_id: Option<String>, detail: serde_json::Value) -> Self {
+        Self {
+            record_type: EVENT_RECORD_TYPE.to_string(),
+            ts_unix_ms: now_unix_ms(),
+            event: event.to_string(),
+            endpoint_id,
+            detail,
+        }
+    }
+}
+
+/// Record type of the client's own rolling estimate (`rollingEstimate`).
+pub const ESTIMATE_RECORD_TYPE: &str = "estimate";
+
+/// Location estimate and claim verdict over the client's newest bursts,
+/// written with `recordType: "estimate"` so burst readers skip it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EstimateRecord {
+    pub record_type: String,
+    pub ts_unix_ms: i64,
+    #[serde(default)]
+    pub session_id: String,
+    /// Bursts the estimate was made from.
+    pub bursts: usize,
+    /// Anchors in the fit.
+    pub anchors_used: usize,
+    pub estimate_lat: Option<f64>,
+    pub estimate_lon: Option<f64>,
+    /// Radius of the fit band around the estimate.
+    pub radius_km: Option<f64>,
+    pub claim_lat: Option<f64>,
+    pub claim_lon: Option<f64>,
+    /// `consistent`, `suspect`, or `inconsistent`, as in `analyze fleet`;
+    /// null without a claim or a bounded anchor.
+    pub claim_verdict: Option<String>,
+    /// Anchors whose bound misses the claim.
+    #[serde(default)]
+    pub falsifying: Vec<String>,
+    /// `active`, `partial`, or `none`.
+    pub tunnel: String,
+}
+
+/// Record type of the hourly per-anchor summary written in
+/// `privacyMode: "features"`.
+pub const FEATURE_RECORD_TYPE: &str = "features";
+
+/// One anchor over one UTC hour, in place of its bursts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureRecord {
+    pub record_type: String,
+    pub ts_unix_ms: i64,
+    #[serde(default)]
+    pub session_id: String,
+    pub endpoint_id: String,
+    /// Start of the hour the bursts fell in.
+    pub hour_start_unix_ms: i64,
+    pub bursts: usize,
+    pub samples: usize,
+    /// p05 over every sample of the hour.
+    pub p05_ms: Option<f64>,
+    /// Bursts sent with a tunnel interface up or as the route.
+    pub tunnel_bursts: usize,
+}
+
+/// Record type of an on-demand latency-under-load test.
+pub const RESPONSIVENESS_RECORD_TYPE: &str = "responsiveness";
+const MS_PER_MIN: f64 = 60_000.0;
+
+/// Result of a responsiveness test: probe RTTs while idle, then under
+/// parallel bulk upload to the endpoint. Written with
+/// `recordType: "responsiveness"` so burst readers skip it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponsivenessRecord {
+    pub record_type: String,
+    pub ts_unix_ms: i64,
+    #[serde(default)]
+    pub session_id: String,
+    pub endpoint_id: String,
+    #[serde(default)]
+    pub probe_path: String,
+    pub streams: usize,
+    pub mbps: f64,
+    pub duration_ms: u64,
+    pub idle_samples_ms: Vec<f64>,
+    pub loaded_samples_ms: Vec<f64>,
+    /// Probes sent under load that never came back.
+    pub loaded_lost: usize,
+    pub idle_p50_ms: Option<f64>,
+    pub loaded_p50_ms: Option<f64>,
+    /// Round trips per minute at the loaded median: 60000 / `loadedP50Ms`.
+    pub rpm: Option<f64>,
+    pub bufferbloat: Option<Bufferbloat>,
+}
+
+impl ResponsivenessRecord {
+    pub fn rpm(loaded_p50_ms: f64) -> Option<f64> {
+        (loaded_p50_ms > 0.0).then(|| MS_PER_MIN / loaded_p50_ms)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct UtunInterface {
@@ -104,6 +1171,30 @@ pub struct UtunInterface {
     pub has_non_loopback_addr: bool,
 }
 
+/// Typical access-latency floors (round trip) by last-mile technology.
+pub const ACCESS_FLOORS_MS: &[(&str, f64)] = &[("fiber", 1.0), ("docsis", 5.0), ("lte", 20.0)];
+
+/// Floor to suggest for an `iface` type when none is configured. Ethernet and
+/// Wi-Fi say nothing about the line behind the router, so only cellular maps.
+pub fn suggested_access_floor_ms(iface: &str) -> Option<(&'static str, f64)> {
+    let tech = match iface {
+        "cellular" => "lte",
+        _ => return None,
+    };
+    ACCESS_FLOORS_MS
+        .iter()
+        .copied()
+        .find(|(name, _)| *name == tech)
+}
+
+pub const US_PER_MS: f64 = 1000.0;
+
+/// Milliseconds to whole microseconds, for integer fields where sub-0.1 ms
+/// differences matter (LAN calibration).
+pub fn ms_to_us(ms: f64) -> i64 {
+    (ms * US_PER_MS).round() as i64
+}
+
 pub fn now_unix_ms() -> i64 {
     let dur = SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -113,7 +1204,7 @@ pub fn now_unix_ms() -> i64 {
 
 pub fn hex_to_bytes(s: &str) -> Result<Vec<u8>, String> {
     let s = s.trim();
-    if s.len() % 2 != 0 {
+    if !s.len().is_multiple_of(2) {
         return Err("secretHex must be even-length hex".into());
     }
     let mut out = Vec::with_capacity(s.len() / 2);
@@ -127,34 +1218,133 @@ pub fn hex_to_bytes(s: &str) -> Result<Vec<u8>, String> {
     Ok(out)
 }
 
-pub fn build_packet(seq: u32, send_ns: u64, nonce: u64, secret: &[u8]) -> [u8; 32] {
-    let mut buf = [0u8; 32];
-    buf[0..4].copy_from_slice(b"LATO");
-    buf[4..8].copy_from_slice(&1u32.to_be_bytes());
-    buf[8..16].copy_from_slice(&send_ns.to_be_bytes());
-    buf[16..20].copy_from_slice(&seq.to_be_bytes());
-    buf[20..28].copy_from_slice(&nonce.to_be_bytes());
+pub fn bytes_to_hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        out.push_str(&format!("{:02x}", b));
+    }
+    out
+}
 
-    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC key");
-    mac.update(&buf[..28]);
-    let tag = mac.finalize().into_bytes();
-    buf[28..32].copy_from_slice(&tag[..4]);
+/// Hex SHA-256 of `data`, for content hashes in provenance records.
+pub fn sha256_hex(data: &[u8]) -> String {
+    use sha2::Digest;
+    bytes_to_hex(&Sha256::digest(data))
+}
+
+/// Stable keyed pseudonym for an identifier: the first `PSEUDONYM_HEX_LEN`
+/// hex digits of HMAC-SHA256(key, value). Without the key it cannot be
+/// reversed by hashing candidate hostnames.
+pub fn pseudonym(key: &[u8], value: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC key");
+    mac.update(value.as_bytes());
+    let mut hex = bytes_to_hex(&mac.finalize().into_bytes());
+    hex.truncate(PSEUDONYM_HEX_LEN);
+    hex
+}
 
-    buf
+/// The quantile records report as `p05Ms`.
+const P05: f64 = 0.05;
+
+/// Where the p05 sits among `len` sorted samples: the `P05` rank, rounded
+/// down. Every p05 the client writes is picked this way.
+pub fn p05_index(len: usize) -> usize {
+    (len.saturating_sub(1) as f64 * P05).floor() as usize
 }
 
-pub fn summarize(samples: &[f64]) -> (Option<f64>, Option<f64>, Option<f64>) {
-    if samples.is_empty() {
+/// Min, p05 and median of RTTs in integer nanoseconds, as measured. Order
+/// statistics pick samples rather than average them, so nothing is rounded
+/// until the caller converts with `ns_to_ms`.
+pub fn summarize(samples_ns: &[u64]) -> (Option<u64>, Option<u64>, Option<u64>) {
+    if samples_ns.is_empty() {
         return (None, None, None);
     }
-    let mut s = samples.to_vec();
-    s.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let mut s = samples_ns.to_vec();
+    s.sort_unstable();
     let mn = s[0];
-    let p05 = s[((s.len() - 1) as f64 * 0.05).floor() as usize];
+    let p05 = s[p05_index(s.len())];
     let med = s[s.len() / 2];
     (Some(mn), Some(p05), Some(med))
 }
 
+/// The one place RTTs leave integer nanoseconds: records carry f64 ms.
+/// Exact to the nanosecond below 2^53 ns (about 104 days).
+pub fn ns_to_ms(ns: u64) -> f64 {
+    ns as f64 / NS_PER_MS
+}
+
+/// Hampel-style pre-filter: drops samples more than `k` scaled MADs above the
+/// median. Only the upper side is filtered since host stalls inflate RTTs but
+/// never shrink them. Returns the kept samples (original order) and the number
+/// dropped. When over half the samples sit exactly on the median the MAD is
+/// zero, and the scale falls back to the mean absolute deviation. Outliers
+/// inflate that, so the fallback drops only far ones. A burst of identical
+/// samples is left as is.
+pub fn hampel_filter(samples: &[f64], k: f64) -> (Vec<f64>, usize) {
+    let Some(limit) = hampel_limit(samples, k) else {
+        return (samples.to_vec(), 0);
+    };
+    let kept: Vec<f64> = samples.iter().copied().filter(|v| *v <= limit).collect();
+    let dropped = samples.len() - kept.len();
+    (kept, dropped)
+}
+
+/// `hampel_filter` over integer-nanosecond RTTs, as the client measures them.
+pub fn hampel_filter_ns(samples_ns: &[u64], k: f64) -> (Vec<u64>, usize) {
+    let as_f64: Vec<f64> = samples_ns.iter().map(|&ns| ns as f64).collect();
+    let Some(limit) = hampel_limit(&as_f64, k) else {
+        return (samples_ns.to_vec(), 0);
+    };
+    let kept: Vec<u64> = samples_ns
+        .iter()
+        .copied()
+        .filter(|&ns| ns as f64 <= limit)
+        .collect();
+    let dropped = samples_ns.len() - kept.len();
+    (kept, dropped)
+}
+
+/// Upper cut-off of the Hampel filter, or `None` when nothing is to be dropped.
+fn hampel_limit(samples: &[f64], k: f64) -> Option<f64> {
+    if samples.len() < HAMPEL_MIN_SAMPLES || !k.is_finite() || k <= 0.0 {
+        return None;
+    }
+    let mut s = samples.to_vec();
+    s.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let med = median_sorted(&s);
+    let mut dev: Vec<f64> = s.iter().map(|v| (v - med).abs()).collect();
+    dev.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let mut scale = median_sorted(&dev) * MAD_SCALE;
+    if scale == 0.0 {
+        scale = dev.iter().sum::<f64>() / dev.len() as f64 * MEAN_AD_SCALE;
+    }
+    if !scale.is_finite() || scale <= 0.0 {
+        return None;
+    }
+    Some(med + k * scale)
+}
+
+fn median_sorted(sorted: &[f64]) -> f64 {
+    let n = sorted.len();
+    if !n.is_multiple_of(2) {
+        sorted[n / 2]
+    } else {
+        (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+    }
+}
+
+/// Great-circle distance on a sphere of mean Earth radius.
+pub fn haversine_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let r = EARTH_RADIUS_KM;
+    let dlat = (lat2 - lat1).to_radians();
+    let dlon = (lon2 - lon1).to_radians();
+    let lat1 = lat1.to_radians();
+    let lat2 = lat2.to_radians();
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    let c = 2.0 * a.sqrt().asin();
+    r * c
+}
+
 pub fn physics_notes(
     region_hint: &Option<String>,
     claimed: &Option<String>,
@@ -180,6 +1370,313 @@ pub fn physics_notes(
     Vec::new()
 }
 
+fn default_packet_version() -> u32 {
+    LATO_VERSION
+}
+
 fn default_pacing_spin_us() -> u64 {
     200
 }
+
+fn default_max_probes_per_sec_per_dest() -> f64 {
+    100.0
+}
+
+fn default_record_raw_samples() -> bool {
+    true
+}
+
+fn default_display_decimals() -> usize {
+    1
+}
+
+fn default_baseline_bursts() -> u64 {
+    10
+}
+
+fn default_self_floor_probes() -> usize {
+    3
+}
+
+fn default_bufferbloat_every_bursts() -> u64 {
+    10
+}
+
+fn default_rolling_every_bursts() -> usize {
+    10
+}
+
+fn default_rolling_window_bursts() -> usize {
+    100
+}
+
+fn default_local_api_listen() -> String {
+    "127.0.0.1:7717".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn merge_json_layers_objects_and_replaces_arrays() {
+        let mut base = json!({
+            "endpoints": [{ "id": "a" }, { "id": "b" }],
+            "outputPath": "/var/lib/lattice/out.jsonl",
+            "probePaths": [{ "id": "wifi" }],
+            "claimedEgressRegion": "SE",
+            "nested": { "keep": 1, "change": 1 },
+        });
+        merge_json(
+            &mut base,
+            json!({
+                "outputPath": "/data/out.jsonl",
+                "probePaths": [{ "id": "eth" }],
+                "claimedEgressRegion": null,
+                "nested": { "change": 2 },
+            }),
+        );
+        assert_eq!(base["endpoints"].as_array().unwrap().len(), 2);
+        assert_eq!(base["outputPath"], "/data/out.jsonl");
+        assert_eq!(base["probePaths"], json!([{ "id": "eth" }]));
+        assert!(base.get("claimedEgressRegion").is_none());
+        assert_eq!(base["nested"], json!({ "keep": 1, "change": 2 }));
+    }
+
+    #[test]
+    fn interpolate_env_expands_and_reports_missing_vars() {
+        let lookup = |name: &str| (name == "SECRET").then(|| "abcd".to_string());
+        let mut doc = json!({
+            "secretHex": "${SECRET}",
+            "outputPath": "/logs/$${literal}/out.jsonl",
+            "endpoints": [{ "host": "${ANCHOR_HOST}" }],
+        });
+        let err = interpolate_env(&mut doc, "", &lookup).unwrap_err();
+        assert!(err.contains("endpoints[0].host") && err.contains("ANCHOR_HOST"));
+
+        let mut doc = json!({ "secretHex": "${SECRET}", "outputPath": "/logs/$${literal}/$x" });
+        interpolate_env(&mut doc, "", &lookup).unwrap();
+        assert_eq!(doc["secretHex"], "abcd");
+        assert_eq!(doc["outputPath"], "/logs/${literal}/$x");
+    }
+
+    #[test]
+    fn iface_counters_delta_survives_a_32_bit_wrap() {
+        let counters = |rx: u64, tx: u64| IfaceCounters {
+            rx_bytes: rx,
+            tx_bytes: tx,
+            rx_packets: 10,
+            tx_packets: 10,
+            bits: 32,
+        };
+        let before = counters(u32::MAX as u64 - 999, 5_000);
+        let traffic = counters(1_000, 6_000).since(&before, 500.0).unwrap();
+        assert_eq!(
+            (traffic.rx_bytes, traffic.tx_bytes, traffic.rx_packets),
+            (2_000, 1_000, 0)
+        );
+        assert_eq!(traffic.bytes_per_sec(), Some(6_000.0));
+
+        let wide = IfaceCounters { bits: 64, ..before };
+        assert!(wide.since(&before, 1.0).is_none());
+    }
+
+    #[test]
+    fn bufferbloat_grades_added_latency_and_never_goes_negative() {
+        let bloat = Bufferbloat::new(BloatSource::Load, 12.0, 40.0);
+        assert_eq!(bloat.bloat_ms, 28.0);
+        assert_eq!(bloat.grade, "B");
+        let faster = Bufferbloat::new(BloatSource::Traffic, 12.0, 11.0);
+        assert_eq!((faster.bloat_ms, faster.grade.as_str()), (0.0, "A"));
+        assert_eq!(bloat_grade(60.0), "D");
+        assert_eq!(bloat_grade(250.0), "F");
+    }
+
+    #[test]
+    fn responsiveness_records_are_not_read_as_bursts() {
+        let rec = ResponsivenessRecord {
+            record_type: RESPONSIVENESS_RECORD_TYPE.to_string(),
+            ts_unix_ms: 1,
+            session_id: String::new(),
+            endpoint_id: "a".to_string(),
+            probe_path: String::new(),
+            streams: 4,
+            mbps: 50.0,
+            duration_ms: 10_000,
+            idle_samples_ms: vec![20.0],
+            loaded_samples_ms: vec![120.0],
+            loaded_lost: 0,
+            idle_p50_ms: Some(20.0),
+            loaded_p50_ms: Some(120.0),
+            rpm: ResponsivenessRecord::rpm(120.0),
+            bufferbloat: Some(Bufferbloat::new(BloatSource::Load, 20.0, 120.0)),
+        };
+        assert_eq!(rec.rpm, Some(500.0));
+        assert_eq!(ResponsivenessRecord::rpm(0.0), None);
+        let line = serde_json::to_string(&rec).unwrap();
+        assert!(serde_json::from_str::<BurstRecord>(&line).is_err());
+    }
+
+    #[test]
+    fn rtts_stay_integer_nanoseconds_until_converted_to_ms() {
+        // Odd nanoseconds survive the conversion exactly, and reading the ms
+        // value back gives the same integer.
+        for ns in [1, 999, 1_000_001, 12_345_678, 59_999_999_999] {
+            let ms = ns_to_ms(ns);
+            assert_eq!((ms * NS_PER_MS).round() as u64, ns);
+        }
+        assert_eq!(ns_to_ms(12_345_678), 12.345678);
+
+        // Order statistics pick samples as measured, without averaging.
+        let samples: Vec<u64> = (1..=20).map(|i| 10_000_000 + i * 7).collect();
+        let (mn, p05, med) = summarize(&samples);
+        assert_eq!(mn, Some(10_000_007));
+        assert_eq!(p05, Some(10_000_007));
+        assert_eq!(med, Some(10_000_077));
+        assert_eq!(summarize(&[]), (None, None, None));
+        // The p05 moves past the fastest sample from 21 samples on.
+        assert_eq!(p05_index(20), 0);
+        assert_eq!(p05_index(21), 1);
+        assert_eq!(p05_index(0), 0);
+
+        // The ns filter drops what the ms filter drops.
+        let mut stalled = samples.clone();
+        stalled.push(40_000_000);
+        let (kept, dropped) = hampel_filter_ns(&stalled, 3.0);
+        assert_eq!((kept, dropped), (samples.clone(), 1));
+        let as_ms: Vec<f64> = stalled.iter().map(|&ns| ns_to_ms(ns)).collect();
+        assert_eq!(hampel_filter(&as_ms, 3.0).1, 1);
+    }
+
+    #[test]
+    fn hampel_filter_falls_back_to_the_mean_deviation_when_the_mad_is_zero() {
+        // Most samples on one quantized value: the MAD is zero.
+        let (kept, dropped) = hampel_filter(&[10.0, 10.0, 10.0, 10.0, 250.0], 3.0);
+        assert_eq!((kept, dropped), (vec![10.0; 4], 1));
+        let (kept, dropped) = hampel_filter_ns(&[10, 10, 10, 11, 12, 10, 900], 3.0);
+        assert_eq!((kept, dropped), (vec![10, 10, 10, 11, 12, 10], 1));
+        // Nothing stands out, or nothing varies at all: all kept.
+        assert_eq!(hampel_filter(&[10.0, 10.0, 10.0, 11.0, 12.0], 3.0).1, 0);
+        assert_eq!(hampel_filter(&[10.0; 6], 3.0).1, 0);
+    }
+
+    #[test]
+    fn endpoints_and_records_default_to_udp() {
+        let ep: Endpoint = serde_json::from_value(json!({
+            "id": "a", "host": "a.example", "port": 9000, "regionHint": null,
+        }))
+        .unwrap();
+        assert_eq!(ep.transport_order(), [Transport::Udp]);
+        let ep: Endpoint = serde_json::from_value(json!({
+            "id": "a", "host": "a.example", "port": 9000, "regionHint": null, "transports": ["tcp", "udp"],
+        }))
+        .unwrap();
+        assert_eq!(ep.transport_order(), [Transport::Tcp, Transport::Udp]);
+        assert_eq!(
+            serde_json::to_value(Transport::Tcp).unwrap(),
+            json!(Transport::Tcp.as_str())
+        );
+        assert!(serde_json::from_value::<Transport>(json!("quic")).is_err());
+    }
+
+    #[test]
+    fn https_has_its_own_port() {
+        let ep: Endpoint = serde_json::from_value(json!({
+            "id": "a", "host": "a.example", "port": 9000, "regionHint": null, "transports": ["udp", "https"],
+        }))
+        .unwrap();
+        assert_eq!(ep.transport_order(), [Transport::Udp, Transport::Https]);
+        assert_eq!(ep.port_for(Transport::Udp), 9000);
+        assert_eq!(ep.port_for(Transport::Https), DEFAULT_HTTPS_PORT);
+        let ep = Endpoint {
+            https_port: Some(8443),
+            ..ep
+        };
+        assert_eq!(ep.port_for(Transport::Https), 8443);
+        assert_eq!(ep.port_for(Transport::Tcp), 9000);
+    }
+
+    #[test]
+    fn tcp_connect_transport_round_trips() {
+        let ep: Endpoint = serde_json::from_value(json!({
+            "id": "cdn", "host": "cdn.example", "port": 443, "regionHint": null, "transports": ["udp", "tcpConnect"],
+        }))
+        .unwrap();
+        assert_eq!(
+            ep.transport_order(),
+            [Transport::Udp, Transport::TcpConnect]
+        );
+        for transport in [Transport::Udp, Transport::Tcp, Transport::TcpConnect] {
+            assert_eq!(
+                serde_json::to_value(transport).unwrap(),
+                json!(transport.as_str())
+            );
+        }
+    }
+
+    #[test]
+    fn feature_records_are_not_read_as_bursts() {
+        for mode in [PrivacyMode::Off, PrivacyMode::Features] {
+            assert_eq!(serde_json::to_value(mode).unwrap(), json!(mode.as_str()));
+        }
+        assert_eq!(PrivacyMode::default(), PrivacyMode::Off);
+        let rec = FeatureRecord {
+            record_type: FEATURE_RECORD_TYPE.to_string(),
+            ts_unix_ms: 1,
+            session_id: String::new(),
+            endpoint_id: "a".to_string(),
+            hour_start_unix_ms: 0,
+            bursts: 360,
+            samples: 3600,
+            p05_ms: Some(12.5),
+            tunnel_bursts: 0,
+        };
+        let line = serde_json::to_string(&rec).unwrap();
+        assert!(serde_json::from_str::<BurstRecord>(&line).is_err());
+    }
+
+    #[test]
+    fn timestamp_sources_order_coarsest_first() {
+        let sources = [
+            TimestampSource::Hardware,
+            TimestampSource::Kernel,
+            TimestampSource::Hardware,
+        ];
+        assert_eq!(sources.into_iter().min(), Some(TimestampSource::Kernel));
+        assert!(TimestampSource::Userspace < TimestampSource::Kernel);
+        assert!(TimestampSource::Kernel < TimestampSource::KernelTx);
+        for source in [
+            TimestampSource::Userspace,
+            TimestampSource::Kernel,
+            TimestampSource::KernelTx,
+            TimestampSource::Hardware,
+        ] {
+            assert_eq!(
+                serde_json::to_value(source).unwrap(),
+                json!(source.as_str())
+            );
+        }
+    }
+    #[test]
+    fn ecn_codepoints_come_from_the_low_two_bits() {
+        for ecn in [Ecn::NotEct, Ecn::Ect1, Ecn::Ect0, Ecn::Ce] {
+            assert_eq!(Ecn::from_tos(ecn.bits()), ecn);
+        }
+        // DSCP in the upper six bits does not matter.
+        assert_eq!(Ecn::from_tos(0xb8 | Ecn::Ce.bits()), Ecn::Ce);
+
+        let mut counts = EcnCounts::default();
+        for ecn in [Ecn::Ect0, Ecn::Ect1, Ecn::Ce, Ecn::NotEct, Ecn::Ce] {
+            counts.count(ecn);
+        }
+        assert_eq!(
+            counts,
+            EcnCounts {
+                ect: 2,
+                ce: 2,
+                not_ect: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,34 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+
+/// Stray replies a prober keeps until the caller takes them; a flood beyond
+/// this is dropped rather than buffered.
+pub const MAX_STRAYS: usize = 1024;
+
+/// The first address `host` resolves to, of `bind_ip`'s family when one is
+/// given, so a bound socket can reach it.
+pub fn resolve_first_for_family(
+    host: &str,
+    port: u16,
+    bind_ip: Option<IpAddr>,
+) -> io::Result<SocketAddr> {
+    let mut addrs = (host, port).to_socket_addrs()?;
+    if let Some(ip) = bind_ip {
+        let want_v4 = ip.is_ipv4();
+        for addr in addrs {
+            if want_v4 && matches!(addr, SocketAddr::V4(_)) {
+                return Ok(addr);
+            }
+            if !want_v4 && matches!(addr, SocketAddr::V6(_)) {
+                return Ok(addr);
+            }
+        }
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no resolved addresses for bind family",
+        ));
+    }
+    addrs
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no resolved addresses"))
+}
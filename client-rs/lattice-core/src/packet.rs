@@ -0,0 +1,485 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt;
+use subtle::ConstantTimeEq;
+
+/// First four bytes of every probe and reply.
+pub const LATO_MAGIC: [u8; 4] = *b"LATO";
+pub const LATO_VERSION: u32 = 1;
+/// A v1 packet: magic, version, send time, seq, nonce, truncated MAC.
+pub const LATO_PACKET_LEN: usize = 32;
+/// v2 replies carry their own tag, so a reply can only come from a holder of
+/// the secret and not from anyone who saw the probe go by.
+pub const LATO_V2_VERSION: u32 = 2;
+/// A v2 packet: the v1 fields and tag, the reflector's receive and transmit
+/// times, its clock's NTP offset and stratum, and the reply tag.
+pub const LATO_V2_PACKET_LEN: usize = 57;
+/// Bytes of HMAC-SHA256 kept as the tag.
+pub const LATO_TAG_LEN: usize = 4;
+const LATO_MAC_OFFSET: usize = LATO_PACKET_LEN - LATO_TAG_LEN;
+const LATO_REFLECTOR_RX_OFFSET: usize = LATO_PACKET_LEN;
+const LATO_REFLECTOR_TX_OFFSET: usize = LATO_REFLECTOR_RX_OFFSET + 8;
+const LATO_CLOCK_OFFSET_OFFSET: usize = LATO_REFLECTOR_TX_OFFSET + 8;
+const LATO_STRATUM_OFFSET: usize = LATO_CLOCK_OFFSET_OFFSET + 4;
+const LATO_REPLY_TAG_OFFSET: usize = LATO_V2_PACKET_LEN - LATO_TAG_LEN;
+/// `reflector_offset_us` of a reflector that does not know its offset.
+pub const UNKNOWN_CLOCK_OFFSET_US: i32 = i32::MIN;
+/// NTP's stratum for an unsynchronized clock; 0 means unknown.
+pub const UNSYNCED_STRATUM: u8 = 16;
+
+/// A decoded LATO packet. Parsing checks framing only; the tag is carried as
+/// received and checked by the caller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Packet {
+    pub version: u32,
+    pub send_ns: u64,
+    pub seq: u32,
+    pub nonce: u64,
+    pub tag: [u8; LATO_TAG_LEN],
+    /// v2 only, zero in probes and in v1: the reflector's clock when the
+    /// probe arrived and when the reply left.
+    pub reflector_rx_ns: u64,
+    pub reflector_tx_ns: u64,
+    /// v2 only: how far the reflector's NTP daemon put its clock from the
+    /// reference, in µs (`UNKNOWN_CLOCK_OFFSET_US` if it could not say),
+    /// and its stratum (0 unknown, `UNSYNCED_STRATUM` unsynchronized).
+    pub reflector_offset_us: i32,
+    pub reflector_stratum: u8,
+    /// v2 only: the MAC of everything before it, set by the reflector.
+    pub reply_tag: [u8; LATO_TAG_LEN],
+}
+
+/// Where a v2 round trip went, from the reflector's stamps: the time the
+/// reflector held the probe, and the legs either side of it. The legs are
+/// read off two different clocks, so each is off by their offset (in
+/// opposite directions); their sum is not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReflectorTiming {
+    pub hold_ns: u64,
+    pub forward_ns: i64,
+    pub reverse_ns: i64,
+    pub sync: ReflectorSync,
+}
+
+/// How well the reflector's clock, which stamped a v2 reply, kept to NTP.
+/// The legs are only as good as the two clocks behind them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReflectorSync {
+    pub offset_us: Option<i32>,
+    /// `None` when the reflector did not know it.
+    pub stratum: Option<u8>,
+}
+
+impl ReflectorSync {
+    /// Whether the reflector reported an NTP-disciplined clock: a known
+    /// offset, not flagged unsynchronized. A kernel-only reading knows no
+    /// stratum.
+    pub fn synced(&self) -> bool {
+        self.offset_us.is_some() && self.stratum != Some(UNSYNCED_STRATUM)
+    }
+}
+
+/// Why a datagram is not a LATO packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// Fewer bytes than the header, or than the version's fixed length.
+    Truncated {
+        len: usize,
+    },
+    /// More bytes than the version defines.
+    TrailingBytes {
+        len: usize,
+    },
+    BadMagic,
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Truncated { len } => write!(f, "truncated packet ({len} bytes)"),
+            ParseError::TrailingBytes { len } => {
+                write!(f, "packet longer than its version allows ({len} bytes)")
+            }
+            ParseError::BadMagic => write!(f, "not a LATO packet"),
+            ParseError::UnsupportedVersion(v) => write!(f, "unsupported LATO version {v}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn field<const N: usize>(buf: &[u8], offset: usize) -> Result<[u8; N], ParseError> {
+    buf.get(offset..offset + N)
+        .and_then(|b| b.try_into().ok())
+        .ok_or(ParseError::Truncated { len: buf.len() })
+}
+
+/// Decodes a datagram from the network. Every read is bounds-checked, so any
+/// input, however short, long, or malformed, yields an error instead of a panic.
+pub fn parse_lato_packet(buf: &[u8]) -> Result<Packet, ParseError> {
+    if field::<4>(buf, 0)? != LATO_MAGIC {
+        return Err(ParseError::BadMagic);
+    }
+    let version = u32::from_be_bytes(field(buf, 4)?);
+    let len = match version {
+        LATO_VERSION => LATO_PACKET_LEN,
+        LATO_V2_VERSION => LATO_V2_PACKET_LEN,
+        _ => return Err(ParseError::UnsupportedVersion(version)),
+    };
+    if buf.len() > len {
+        return Err(ParseError::TrailingBytes { len: buf.len() });
+    }
+    let mut packet = Packet {
+        version,
+        send_ns: u64::from_be_bytes(field(buf, 8)?),
+        seq: u32::from_be_bytes(field(buf, 16)?),
+        nonce: u64::from_be_bytes(field(buf, 20)?),
+        tag: field(buf, LATO_MAC_OFFSET)?,
+        reflector_rx_ns: 0,
+        reflector_tx_ns: 0,
+        reflector_offset_us: 0,
+        reflector_stratum: 0,
+        reply_tag: [0; LATO_TAG_LEN],
+    };
+    if version == LATO_V2_VERSION {
+        packet.reflector_rx_ns = u64::from_be_bytes(field(buf, LATO_REFLECTOR_RX_OFFSET)?);
+        packet.reflector_tx_ns = u64::from_be_bytes(field(buf, LATO_REFLECTOR_TX_OFFSET)?);
+        packet.reflector_offset_us = i32::from_be_bytes(field(buf, LATO_CLOCK_OFFSET_OFFSET)?);
+        packet.reflector_stratum = field::<1>(buf, LATO_STRATUM_OFFSET)?[0];
+        packet.reply_tag = field(buf, LATO_REPLY_TAG_OFFSET)?;
+    }
+    Ok(packet)
+}
+
+impl Packet {
+    /// Whether `self`, a parsed reply, echoes the probe `sent` unchanged: in
+    /// v2, every field the probe set. The tags are compared in constant time.
+    pub fn echoes(&self, sent: &Packet) -> bool {
+        let fields_match = (self.version, self.send_ns, self.seq, self.nonce)
+            == (sent.version, sent.send_ns, sent.seq, sent.nonce);
+        let tag_match: bool = self.tag.ct_eq(&sent.tag).into();
+        fields_match & tag_match
+    }
+
+    /// Whether the tag is the MAC of the other fields under `secret`, checked
+    /// in constant time so timing does not reveal how much of a forged tag
+    /// was right.
+    pub fn verify(&self, secret: &[u8]) -> bool {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC key");
+        mac.update(&header(self.version, self.send_ns, self.seq, self.nonce));
+        mac.verify_truncated_left(&self.tag).is_ok()
+    }
+
+    /// Whether a reply came from a holder of the secret. A v1 reply is the
+    /// probe itself, so only its tag can be checked; a v2 reply must carry
+    /// the reply tag as well.
+    pub fn verify_reply(&self, secret: &[u8]) -> bool {
+        if !self.verify(secret) {
+            return false;
+        }
+        if self.version != LATO_V2_VERSION {
+            return true;
+        }
+        let encoded = self.encode_v2();
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC key");
+        mac.update(&encoded[..LATO_REPLY_TAG_OFFSET]);
+        mac.verify_truncated_left(&self.reply_tag).is_ok()
+    }
+
+    /// The reflector's stamps on a v2 reply to a probe sent at
+    /// `send_realtime_ns` that came back `rtt_ns` later. None without
+    /// stamps, or with stamps that cannot be right: transmit before receive,
+    /// or a hold longer than the round trip.
+    pub fn reflector_timing(&self, send_realtime_ns: u64, rtt_ns: u64) -> Option<ReflectorTiming> {
+        if self.version != LATO_V2_VERSION || self.reflector_rx_ns == 0 {
+            return None;
+        }
+        let hold_ns = self.reflector_tx_ns.checked_sub(self.reflector_rx_ns)?;
+        let network_ns = rtt_ns.checked_sub(hold_ns)?;
+        let forward_ns = self.reflector_rx_ns as i64 - send_realtime_ns as i64;
+        Some(ReflectorTiming {
+            hold_ns,
+            forward_ns,
+            reverse_ns: network_ns as i64 - forward_ns,
+            sync: ReflectorSync {
+                offset_us: Some(self.reflector_offset_us)
+                    .filter(|&us| us != UNKNOWN_CLOCK_OFFSET_US),
+                stratum: Some(self.reflector_stratum).filter(|&s| s != 0),
+            },
+        })
+    }
+
+    /// The v2 reply to this probe: its fields and tag unchanged, the
+    /// reflector times and clock state as set on `self`, and a fresh reply
+    /// tag.
+    pub fn reply_v2(&self, secret: &[u8]) -> [u8; LATO_V2_PACKET_LEN] {
+        let mut buf = self.encode_v2();
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC key");
+        mac.update(&buf[..LATO_REPLY_TAG_OFFSET]);
+        let tag = mac.finalize().into_bytes();
+        buf[LATO_REPLY_TAG_OFFSET..].copy_from_slice(&tag[..LATO_TAG_LEN]);
+        buf
+    }
+
+    fn encode_v2(&self) -> [u8; LATO_V2_PACKET_LEN] {
+        let mut buf = [0u8; LATO_V2_PACKET_LEN];
+        buf[..LATO_MAC_OFFSET].copy_from_slice(&header(
+            self.version,
+            self.send_ns,
+            self.seq,
+            self.nonce,
+        ));
+        buf[LATO_MAC_OFFSET..LATO_PACKET_LEN].copy_from_slice(&self.tag);
+        buf[LATO_REFLECTOR_RX_OFFSET..LATO_REFLECTOR_TX_OFFSET]
+            .copy_from_slice(&self.reflector_rx_ns.to_be_bytes());
+        buf[LATO_REFLECTOR_TX_OFFSET..LATO_CLOCK_OFFSET_OFFSET]
+            .copy_from_slice(&self.reflector_tx_ns.to_be_bytes());
+        buf[LATO_CLOCK_OFFSET_OFFSET..LATO_STRATUM_OFFSET]
+            .copy_from_slice(&self.reflector_offset_us.to_be_bytes());
+        buf[LATO_STRATUM_OFFSET] = self.reflector_stratum;
+        buf[LATO_REPLY_TAG_OFFSET..].copy_from_slice(&self.reply_tag);
+        buf
+    }
+
+    /// The sender's sequence position, as laid out by `ProbeId`.
+    pub fn probe_id(&self) -> ProbeId {
+        ProbeId {
+            epoch: (self.nonce >> u32::BITS) as u32,
+            burst: (self.seq >> u16::BITS) as u16,
+            index: self.seq as u16,
+        }
+    }
+}
+
+/// Where a probe sits in its sender's sequence, carried in fields the
+/// reflector echoes unchanged: `seq` holds the burst (high 16 bits) and the
+/// probe within it (low 16), and the nonce's high 32 bits hold the epoch of
+/// the socket it went out on. The nonce's low 32 bits stay random.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeId {
+    pub epoch: u32,
+    pub burst: u16,
+    pub index: u16,
+}
+
+impl ProbeId {
+    pub fn seq(&self) -> u32 {
+        (u32::from(self.burst) << u16::BITS) | u32::from(self.index)
+    }
+
+    pub fn nonce(&self, random: u32) -> u64 {
+        (u64::from(self.epoch) << u32::BITS) | u64::from(random)
+    }
+}
+
+/// Whether burst `a` was sent before burst `b`, in serial-number order
+/// (RFC 1982): the counter wraps, so of two bursts less than half the space
+/// apart the one "behind" is the earlier, whichever number is larger.
+pub fn burst_before(a: u16, b: u16) -> bool {
+    a != b && b.wrapping_sub(a) < 1 << (u16::BITS - 1)
+}
+
+/// The MAC-covered bytes: everything before the tag.
+fn header(version: u32, send_ns: u64, seq: u32, nonce: u64) -> [u8; LATO_MAC_OFFSET] {
+    let mut buf = [0u8; LATO_MAC_OFFSET];
+    buf[0..4].copy_from_slice(&LATO_MAGIC);
+    buf[4..8].copy_from_slice(&version.to_be_bytes());
+    buf[8..16].copy_from_slice(&send_ns.to_be_bytes());
+    buf[16..20].copy_from_slice(&seq.to_be_bytes());
+    buf[20..28].copy_from_slice(&nonce.to_be_bytes());
+    buf
+}
+
+pub fn build_packet(seq: u32, send_ns: u64, nonce: u64, secret: &[u8]) -> [u8; LATO_PACKET_LEN] {
+    let mut buf = [0u8; LATO_PACKET_LEN];
+    sign_probe(&mut buf, LATO_VERSION, seq, send_ns, nonce, secret);
+    buf
+}
+
+/// A v2 probe: the v1 fields under the v2 version, reflector fields zero.
+pub fn build_packet_v2(
+    seq: u32,
+    send_ns: u64,
+    nonce: u64,
+    secret: &[u8],
+) -> [u8; LATO_V2_PACKET_LEN] {
+    let mut buf = [0u8; LATO_V2_PACKET_LEN];
+    sign_probe(&mut buf, LATO_V2_VERSION, seq, send_ns, nonce, secret);
+    buf
+}
+
+/// Writes the header and its tag, the first `LATO_PACKET_LEN` bytes of
+/// either version.
+fn sign_probe(buf: &mut [u8], version: u32, seq: u32, send_ns: u64, nonce: u64, secret: &[u8]) {
+    let header = header(version, send_ns, seq, nonce);
+    buf[..LATO_MAC_OFFSET].copy_from_slice(&header);
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC key");
+    mac.update(&header);
+    let tag = mac.finalize().into_bytes();
+    buf[LATO_MAC_OFFSET..LATO_PACKET_LEN].copy_from_slice(&tag[..LATO_TAG_LEN]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_built_packets_and_rejects_malformed_input() {
+        let buf = build_packet(7, 123_456_789, 42, b"secret");
+        let packet = parse_lato_packet(&buf).unwrap();
+        assert_eq!(
+            (packet.seq, packet.send_ns, packet.nonce),
+            (7, 123_456_789, 42)
+        );
+        assert_eq!(packet.tag, buf[LATO_MAC_OFFSET..]);
+        assert!(packet.echoes(&packet));
+
+        for len in 0..LATO_PACKET_LEN {
+            assert!(parse_lato_packet(&buf[..len]).is_err(), "len {len}");
+        }
+        let mut long = buf.to_vec();
+        long.push(0);
+        assert_eq!(
+            parse_lato_packet(&long),
+            Err(ParseError::TrailingBytes { len: 33 })
+        );
+        let mut bad = buf;
+        bad[0] = b'X';
+        assert_eq!(parse_lato_packet(&bad), Err(ParseError::BadMagic));
+        bad = buf;
+        bad[7] = 9;
+        assert_eq!(
+            parse_lato_packet(&bad),
+            Err(ParseError::UnsupportedVersion(9))
+        );
+
+        let mut other = packet;
+        other.nonce += 1;
+        assert!(!other.echoes(&packet));
+    }
+
+    #[test]
+    fn verify_accepts_only_the_matching_secret_and_fields() {
+        let packet = parse_lato_packet(&build_packet(3, 99, 5, b"secret")).unwrap();
+        assert!(packet.verify(b"secret"));
+        assert!(!packet.verify(b"other"));
+        let mut forged = packet;
+        forged.tag[3] ^= 1;
+        assert!(!forged.verify(b"secret"));
+        assert!(!forged.echoes(&packet));
+        let mut moved = packet;
+        moved.seq += 1;
+        assert!(!moved.verify(b"secret"));
+    }
+
+    #[test]
+    fn probe_ids_round_trip_and_bursts_order_across_wraparound() {
+        let id = ProbeId {
+            epoch: 0xdead_beef,
+            burst: 0xfffe,
+            index: 513,
+        };
+        let buf = build_packet(id.seq(), 1, id.nonce(0x1234_5678), b"secret");
+        let packet = parse_lato_packet(&buf).unwrap();
+        assert_eq!(packet.probe_id(), id);
+        assert_eq!(packet.nonce as u32, 0x1234_5678);
+
+        assert!(burst_before(4, 5));
+        assert!(!burst_before(5, 4));
+        assert!(!burst_before(7, 7));
+        // 65535 is the burst just before 0 once the counter wraps.
+        assert!(burst_before(u16::MAX, 0));
+        assert!(!burst_before(0, u16::MAX));
+        assert!(burst_before(0xfff0, 0x0010));
+    }
+
+    #[test]
+    fn v2_replies_need_the_reply_tag_and_keep_the_probe_fields() {
+        let probe_buf = build_packet_v2(7, 123, 42, b"secret");
+        let probe = parse_lato_packet(&probe_buf).unwrap();
+        assert_eq!(probe.version, LATO_V2_VERSION);
+        assert!(probe.verify(b"secret"));
+        // The probe itself bounced back, by anyone, is not a reply.
+        assert!(!probe.verify_reply(b"secret"));
+
+        let mut stamped = probe;
+        stamped.reflector_rx_ns = 1_000;
+        stamped.reflector_tx_ns = 1_050;
+        stamped.reflector_offset_us = -250;
+        stamped.reflector_stratum = 2;
+        let reply = parse_lato_packet(&stamped.reply_v2(b"secret")).unwrap();
+        assert!(reply.echoes(&probe));
+        assert!(reply.verify_reply(b"secret"));
+        assert!(!reply.verify_reply(b"other"));
+        assert_eq!(
+            (
+                reply.reflector_rx_ns,
+                reply.reflector_tx_ns,
+                reply.reflector_offset_us,
+                reply.reflector_stratum
+            ),
+            (1_000, 1_050, -250, 2)
+        );
+
+        let mut moved = reply;
+        moved.reflector_tx_ns += 1;
+        assert!(!moved.verify_reply(b"secret"));
+        // The clock state is signed too: no one on the path can vouch for
+        // an unsynced reflector.
+        let mut promoted = reply;
+        promoted.reflector_stratum = 1;
+        assert!(!promoted.verify_reply(b"secret"));
+        // A v1 tag does not carry over to v2.
+        let v1 = build_packet(7, 123, 42, b"secret");
+        assert_ne!(probe.tag, v1[LATO_MAC_OFFSET..]);
+        assert_eq!(
+            parse_lato_packet(&probe_buf[..LATO_PACKET_LEN]),
+            Err(ParseError::Truncated { len: 32 })
+        );
+    }
+
+    #[test]
+    fn reflector_stamps_split_the_round_trip() {
+        let mut reply = parse_lato_packet(&build_packet_v2(7, 1_000, 42, b"secret")).unwrap();
+        // No stamps yet.
+        assert_eq!(reply.reflector_timing(1_000, 500), None);
+
+        // Sent at 1_000, held 100 at a reflector whose clock runs 30 ahead,
+        // back 500 after sending: 200 out and 200 back.
+        reply.reflector_rx_ns = 1_230;
+        reply.reflector_tx_ns = 1_330;
+        reply.reflector_offset_us = UNKNOWN_CLOCK_OFFSET_US;
+        let timing = reply.reflector_timing(1_000, 500).unwrap();
+        assert_eq!(
+            timing,
+            ReflectorTiming {
+                hold_ns: 100,
+                forward_ns: 230,
+                reverse_ns: 170,
+                sync: ReflectorSync {
+                    offset_us: None,
+                    stratum: None
+                },
+            }
+        );
+        assert_eq!(timing.forward_ns + timing.reverse_ns, 400);
+        assert!(!timing.sync.synced());
+
+        reply.reflector_offset_us = 40;
+        assert!(reply.reflector_timing(1_000, 500).unwrap().sync.synced());
+        reply.reflector_stratum = 3;
+        assert!(reply.reflector_timing(1_000, 500).unwrap().sync.synced());
+        reply.reflector_stratum = UNSYNCED_STRATUM;
+        assert!(!reply.reflector_timing(1_000, 500).unwrap().sync.synced());
+
+        // A hold longer than the round trip, or sent before received, is wrong.
+        assert_eq!(reply.reflector_timing(1_000, 50), None);
+        reply.reflector_tx_ns = 1_200;
+        assert_eq!(reply.reflector_timing(1_000, 500), None);
+        // v1 has nowhere to put them.
+        let v1 = parse_lato_packet(&build_packet(7, 1_000, 42, b"secret")).unwrap();
+        assert_eq!(v1.reflector_timing(1_000, 500), None);
+    }
+}
@@ -0,0 +1,127 @@
+/// A cloud provider region with approximate datacenter coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CloudRegion {
+    pub provider: &'static str,
+    pub code: &'static str,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+const fn region(provider: &'static str, code: &'static str, lat: f64, lon: f64) -> CloudRegion {
+    CloudRegion {
+        provider,
+        code,
+        lat,
+        lon,
+    }
+}
+
+/// Bundled coordinates for common AWS/GCP/Azure/DigitalOcean regions. Values
+/// are the metro the provider names, which is as precise as public data gets.
+pub const CLOUD_REGIONS: &[CloudRegion] = &[
+    // AWS
+    region("aws", "us-east-1", 38.9940, -77.4524),
+    region("aws", "us-east-2", 39.9612, -82.9988),
+    region("aws", "us-west-1", 37.3541, -121.9552),
+    region("aws", "us-west-2", 45.8399, -119.7006),
+    region("aws", "ca-central-1", 45.5017, -73.5673),
+    region("aws", "sa-east-1", -23.5505, -46.6333),
+    region("aws", "eu-west-1", 53.3498, -6.2603),
+    region("aws", "eu-west-2", 51.5074, -0.1278),
+    region("aws", "eu-west-3", 48.8566, 2.3522),
+    region("aws", "eu-central-1", 50.1109, 8.6821),
+    region("aws", "eu-central-2", 47.3769, 8.5417),
+    region("aws", "eu-north-1", 59.3293, 18.0686),
+    region("aws", "eu-south-1", 45.4642, 9.1900),
+    region("aws", "me-south-1", 26.0667, 50.5577),
+    region("aws", "af-south-1", -33.9249, 18.4241),
+    region("aws", "ap-south-1", 19.0760, 72.8777),
+    region("aws", "ap-northeast-1", 35.6762, 139.6503),
+    region("aws", "ap-northeast-2", 37.5665, 126.9780),
+    region("aws", "ap-northeast-3", 34.6937, 135.5023),
+    region("aws", "ap-southeast-1", 1.3521, 103.8198),
+    region("aws", "ap-southeast-2", -33.8688, 151.2093),
+    region("aws", "ap-east-1", 22.3193, 114.1694),
+    // GCP
+    region("gcp", "us-central1", 41.2619, -95.8608),
+    region("gcp", "us-east1", 33.1960, -80.0131),
+    region("gcp", "us-east4", 39.0438, -77.4874),
+    region("gcp", "us-west1", 45.5946, -121.1787),
+    region("gcp", "us-west2", 34.0522, -118.2437),
+    region("gcp", "us-west3", 40.7608, -111.8910),
+    region("gcp", "us-west4", 36.1699, -115.1398),
+    region("gcp", "us-south1", 32.7767, -96.7970),
+    region("gcp", "northamerica-northeast1", 45.5017, -73.5673),
+    region("gcp", "northamerica-northeast2", 43.6532, -79.3832),
+    region("gcp", "southamerica-east1", -23.5505, -46.6333),
+    region("gcp", "southamerica-west1", -33.4489, -70.6693),
+    region("gcp", "europe-west1", 50.4706, 3.8170),
+    region("gcp", "europe-west2", 51.5074, -0.1278),
+    region("gcp", "europe-west3", 50.1109, 8.6821),
+    region("gcp", "europe-west4", 53.4386, 6.8355),
+    region("gcp", "europe-west6", 47.3769, 8.5417),
+    region("gcp", "europe-north1", 60.5693, 27.1878),
+    region("gcp", "europe-central2", 52.2297, 21.0122),
+    region("gcp", "asia-east1", 24.0518, 120.5161),
+    region("gcp", "asia-east2", 22.3193, 114.1694),
+    region("gcp", "asia-northeast1", 35.6762, 139.6503),
+    region("gcp", "asia-northeast3", 37.5665, 126.9780),
+    region("gcp", "asia-south1", 19.0760, 72.8777),
+    region("gcp", "asia-southeast1", 1.3521, 103.8198),
+    region("gcp", "australia-southeast1", -33.8688, 151.2093),
+    region("gcp", "me-west1", 32.0853, 34.7818),
+    // Azure
+    region("azure", "eastus2", 36.6681, -78.3889),
+    region("azure", "eastus", 37.3719, -79.8164),
+    region("azure", "westus2", 47.2330, -119.8520),
+    region("azure", "westus3", 33.4484, -112.0740),
+    region("azure", "westus", 37.7830, -122.4170),
+    region("azure", "centralus", 41.5908, -93.6208),
+    region("azure", "southcentralus", 29.4241, -98.4936),
+    region("azure", "canadacentral", 43.6532, -79.3832),
+    region("azure", "brazilsouth", -23.5505, -46.6333),
+    region("azure", "northeurope", 53.3478, -6.2597),
+    region("azure", "westeurope", 52.3667, 4.9000),
+    region("azure", "uksouth", 51.5074, -0.1278),
+    region("azure", "francecentral", 46.3772, 2.3730),
+    region("azure", "germanywestcentral", 50.1109, 8.6821),
+    region("azure", "swedencentral", 60.6749, 17.1413),
+    region("azure", "switzerlandnorth", 47.3769, 8.5417),
+    region("azure", "eastasia", 22.2670, 114.1880),
+    region("azure", "southeastasia", 1.2830, 103.8330),
+    region("azure", "japaneast", 35.6800, 139.7700),
+    region("azure", "koreacentral", 37.5665, 126.9780),
+    region("azure", "centralindia", 18.5822, 73.9197),
+    region("azure", "australiaeast", -33.8600, 151.2094),
+    region("azure", "southafricanorth", -25.7313, 28.2184),
+    // DigitalOcean
+    region("do", "nyc1", 40.7128, -74.0060),
+    region("do", "nyc3", 40.7128, -74.0060),
+    region("do", "sfo2", 37.7749, -122.4194),
+    region("do", "sfo3", 37.7749, -122.4194),
+    region("do", "tor1", 43.6532, -79.3832),
+    region("do", "ams3", 52.3676, 4.9041),
+    region("do", "lon1", 51.5074, -0.1278),
+    region("do", "fra1", 50.1109, 8.6821),
+    region("do", "sgp1", 1.3521, 103.8198),
+    region("do", "blr1", 12.9716, 77.5946),
+    region("do", "syd1", -33.8688, 151.2093),
+];
+
+/// Finds the cloud region whose code appears in `text` (case-insensitive),
+/// preferring the longest code so `eastus2` wins over `eastus`.
+pub fn find_cloud_region(text: &str) -> Option<&'static CloudRegion> {
+    let haystack = text.to_ascii_lowercase();
+    CLOUD_REGIONS
+        .iter()
+        .filter(|r| haystack.contains(r.code))
+        .max_by_key(|r| r.code.len())
+}
+
+/// Looks a region up by exact code, optionally restricted to one provider.
+pub fn cloud_region(provider: Option<&str>, code: &str) -> Option<&'static CloudRegion> {
+    CLOUD_REGIONS.iter().find(|r| {
+        r.code.eq_ignore_ascii_case(code)
+            && provider.is_none_or(|p| r.provider.eq_ignore_ascii_case(p))
+    })
+}
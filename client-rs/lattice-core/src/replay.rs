@@ -0,0 +1,134 @@
+use crate::Packet;
+use std::collections::HashMap;
+
+/// Sequence numbers behind the newest of an epoch that are still told apart;
+/// anything older is refused (the sliding window of RFC 4303, §3.4.3).
+pub const REPLAY_WINDOW: u32 = u64::BITS;
+
+/// What a `ReplayGuard` makes of a packet's sequence number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Replay {
+    /// Not seen before: recorded, and the packet may be used.
+    Fresh,
+    /// The same epoch and sequence number came before.
+    Seen,
+    /// More than `REPLAY_WINDOW` behind the newest of its epoch, so whether it
+    /// came before can no longer be told.
+    TooOld,
+}
+
+/// One sender epoch: the newest sequence number, and which of the
+/// `REPLAY_WINDOW` before it were seen (bit n is `newest - n`).
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    newest: u32,
+    seen: u64,
+    /// `ReplayGuard::checks` at the last packet, for evicting the idlest.
+    touched: u64,
+}
+
+/// Refuses packets whose sequence number was already used in their epoch
+/// (the high half of the nonce). Sequence numbers compare in serial-number
+/// order, so the window survives the counter wrapping. Check the tag first:
+/// the guard trusts the epoch and sequence number it is given.
+#[derive(Debug)]
+pub struct ReplayGuard {
+    windows: HashMap<u32, Window>,
+    max_epochs: usize,
+    checks: u64,
+}
+
+impl ReplayGuard {
+    /// Tracks at most `max_epochs` epochs; past that the one idle longest
+    /// is forgotten.
+    pub fn new(max_epochs: usize) -> Self {
+        ReplayGuard {
+            windows: HashMap::new(),
+            max_epochs: max_epochs.max(1),
+            checks: 0,
+        }
+    }
+
+    pub fn check(&mut self, packet: &Packet) -> Replay {
+        let id = packet.probe_id();
+        self.check_seq(id.epoch, packet.seq)
+    }
+
+    pub fn check_seq(&mut self, epoch: u32, seq: u32) -> Replay {
+        self.checks += 1;
+        let touched = self.checks;
+        if !self.windows.contains_key(&epoch) && self.windows.len() >= self.max_epochs {
+            if let Some(idlest) = self
+                .windows
+                .iter()
+                .min_by_key(|(_, w)| w.touched)
+                .map(|(e, _)| *e)
+            {
+                self.windows.remove(&idlest);
+            }
+        }
+        let Some(window) = self.windows.get_mut(&epoch) else {
+            self.windows.insert(
+                epoch,
+                Window {
+                    newest: seq,
+                    seen: 1,
+                    touched,
+                },
+            );
+            return Replay::Fresh;
+        };
+        window.touched = touched;
+        let ahead = seq.wrapping_sub(window.newest);
+        if ahead != 0 && ahead < 1 << (u32::BITS - 1) {
+            window.seen = if ahead >= REPLAY_WINDOW {
+                0
+            } else {
+                window.seen << ahead
+            };
+            window.seen |= 1;
+            window.newest = seq;
+            return Replay::Fresh;
+        }
+        let behind = window.newest.wrapping_sub(seq);
+        if behind >= REPLAY_WINDOW {
+            return Replay::TooOld;
+        }
+        let bit = 1u64 << behind;
+        if window.seen & bit != 0 {
+            return Replay::Seen;
+        }
+        window.seen |= bit;
+        Replay::Fresh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replays_are_refused_and_reordering_within_the_window_is_not() {
+        let mut guard = ReplayGuard::new(2);
+        assert_eq!(guard.check_seq(1, 10), Replay::Fresh);
+        assert_eq!(guard.check_seq(1, 10), Replay::Seen);
+        assert_eq!(guard.check_seq(1, 12), Replay::Fresh);
+        assert_eq!(guard.check_seq(1, 11), Replay::Fresh);
+        assert_eq!(guard.check_seq(1, 11), Replay::Seen);
+        // Another epoch has its own window.
+        assert_eq!(guard.check_seq(2, 10), Replay::Fresh);
+
+        assert_eq!(guard.check_seq(1, 12 + REPLAY_WINDOW), Replay::Fresh);
+        assert_eq!(guard.check_seq(1, 12), Replay::TooOld);
+        assert_eq!(guard.check_seq(1, 13), Replay::Fresh);
+
+        // Across the wrap, u32::MAX came just before 0.
+        assert_eq!(guard.check_seq(3, u32::MAX), Replay::Fresh);
+        assert_eq!(guard.check_seq(3, 0), Replay::Fresh);
+        assert_eq!(guard.check_seq(3, u32::MAX), Replay::Seen);
+        // The guard holds two epochs; epoch 2, idle longest, made room for
+        // epoch 3, so it starts over.
+        assert_eq!(guard.check_seq(2, 10), Replay::Fresh);
+        assert_eq!(guard.check_seq(3, 0), Replay::Seen);
+    }
+}
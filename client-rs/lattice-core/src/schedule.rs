@@ -0,0 +1,263 @@
+use serde::{Deserialize, Serialize};
+
+/// Longest supported blackout window (one week).
+pub const MAX_BLACKOUT_MINUTES: u32 = 7 * 24 * 60;
+
+const SECS_PER_MIN: i64 = 60;
+const MINS_PER_DAY: i64 = 24 * 60;
+
+/// A do-not-probe window: starts whenever `cron` (5-field, UTC) matches and
+/// lasts `durationMinutes`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Blackout {
+    pub cron: String,
+    pub duration_minutes: u32,
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+/// Parsed 5-field cron expression (`minute hour day-of-month month day-of-week`).
+/// Supports `*`, numbers, ranges `a-b`, steps `*/n` and `a-b/n`, lists, and
+/// three-letter day/month names. As in cron, when both day fields are
+/// restricted a time matches if either does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u32,
+    days: u32,
+    months: u16,
+    weekdays: u8,
+    days_any: bool,
+    weekdays_any: bool,
+}
+
+/// UTC calendar fields for one minute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UtcMinute {
+    pub minute: u32,
+    pub hour: u32,
+    pub day: u32,
+    pub month: u32,
+    pub weekday: u32,
+}
+
+impl CronSchedule {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("cron '{}' must have 5 fields", expr));
+        }
+        let minutes = parse_field(fields[0], 0, 59, &[])?;
+        let hours = parse_field(fields[1], 0, 23, &[])?;
+        let days = parse_field(fields[2], 1, 31, &[])?;
+        let months = parse_field(fields[3], 1, 12, &MONTH_NAMES)?;
+        let mut weekdays = parse_field(fields[4], 0, 7, &WEEKDAY_NAMES)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Self {
+            minutes,
+            hours: hours as u32,
+            days: days as u32,
+            months: months as u16,
+            weekdays: (weekdays & 0x7f) as u8,
+            days_any: fields[2] == "*",
+            weekdays_any: fields[4] == "*",
+        })
+    }
+
+    pub fn matches(&self, t: &UtcMinute) -> bool {
+        if self.minutes & (1 << t.minute) == 0
+            || self.hours & (1 << t.hour) == 0
+            || self.months & (1 << t.month) == 0
+        {
+            return false;
+        }
+        let day_ok = self.days & (1 << t.day) != 0;
+        let weekday_ok = self.weekdays & (1 << t.weekday) != 0;
+        match (self.days_any, self.weekdays_any) {
+            (true, true) => true,
+            (false, true) => day_ok,
+            (true, false) => weekday_ok,
+            (false, false) => day_ok || weekday_ok,
+        }
+    }
+}
+
+/// A blackout with its schedule compiled once up front.
+#[derive(Debug, Clone)]
+pub struct CompiledBlackout {
+    pub schedule: CronSchedule,
+    pub duration_minutes: u32,
+    pub label: String,
+}
+
+impl CompiledBlackout {
+    pub fn compile(b: &Blackout) -> Result<Self, String> {
+        if b.duration_minutes == 0 || b.duration_minutes > MAX_BLACKOUT_MINUTES {
+            return Err(format!(
+                "blackout '{}' durationMinutes must be 1..={}",
+                b.cron, MAX_BLACKOUT_MINUTES
+            ));
+        }
+        Ok(Self {
+            schedule: CronSchedule::parse(&b.cron)?,
+            duration_minutes: b.duration_minutes,
+            label: b.label.clone().unwrap_or_else(|| b.cron.clone()),
+        })
+    }
+
+    /// If `unix_ms` falls inside a window, returns the window's end (unix ms).
+    pub fn active_until(&self, unix_ms: i64) -> Option<i64> {
+        let now_min = unix_ms.div_euclid(SECS_PER_MIN * 1000);
+        for back in 0..self.duration_minutes as i64 {
+            let start = now_min - back;
+            if self.schedule.matches(&utc_minute(start)) {
+                return Some((start + self.duration_minutes as i64) * SECS_PER_MIN * 1000);
+            }
+        }
+        None
+    }
+}
+
+/// First active window among `blackouts`, with its end time.
+pub fn active_blackout(
+    blackouts: &[CompiledBlackout],
+    unix_ms: i64,
+) -> Option<(&CompiledBlackout, i64)> {
+    blackouts
+        .iter()
+        .find_map(|b| b.active_until(unix_ms).map(|end| (b, end)))
+}
+
+/// Calendar fields for a minute index since the Unix epoch (UTC).
+pub fn utc_minute(minutes_since_epoch: i64) -> UtcMinute {
+    let days = minutes_since_epoch.div_euclid(MINS_PER_DAY);
+    let in_day = minutes_since_epoch.rem_euclid(MINS_PER_DAY);
+    let (_, month, day) = civil_from_days(days);
+    UtcMinute {
+        minute: (in_day % 60) as u32,
+        hour: (in_day / 60) as u32,
+        day,
+        month,
+        // 1970-01-01 was a Thursday.
+        weekday: (days + 4).rem_euclid(7) as u32,
+    }
+}
+
+/// Days since 1970-01-01 to (year, month, day); Howard Hinnant's algorithm.
+pub fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+fn parse_value(s: &str, min: u32, names: &[&str]) -> Result<u32, String> {
+    if let Ok(v) = s.parse::<u32>() {
+        return Ok(v);
+    }
+    let lower = s.to_ascii_lowercase();
+    names
+        .iter()
+        .position(|n| *n == lower)
+        .map(|i| i as u32 + min)
+        .ok_or_else(|| format!("invalid cron value '{}'", s))
+}
+
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    // Day-of-week names start at 0 (sun) while month names start at 1.
+    let name_base = if names.len() == WEEKDAY_NAMES.len() {
+        0
+    } else {
+        min
+    };
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((r, s)) => {
+                let step: u32 = s
+                    .parse()
+                    .map_err(|_| format!("invalid cron step '{}'", part))?;
+                if step == 0 {
+                    return Err(format!("invalid cron step '{}'", part));
+                }
+                (r, step)
+            }
+            None => (part, 1),
+        };
+        let (lo, hi) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (
+                parse_value(a, name_base, names)?,
+                parse_value(b, name_base, names)?,
+            )
+        } else {
+            let v = parse_value(range, name_base, names)?;
+            (v, if step > 1 { max } else { v })
+        };
+        if lo < min || hi > max || lo > hi {
+            return Err(format!(
+                "cron field '{}' out of range {}-{}",
+                part, min, max
+            ));
+        }
+        let mut v = lo;
+        while v <= hi {
+            bits |= 1 << v;
+            v += step;
+        }
+    }
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-05-01T02:30:00Z, a Wednesday.
+    const WED_0230_MS: i64 = 1_714_530_600_000;
+
+    #[test]
+    fn utc_minute_matches_known_date() {
+        let t = utc_minute(WED_0230_MS / 60_000);
+        assert_eq!(
+            (t.month, t.day, t.hour, t.minute, t.weekday),
+            (5, 1, 2, 30, 3)
+        );
+    }
+
+    #[test]
+    fn blackout_window_covers_duration() {
+        let b = CompiledBlackout::compile(&Blackout {
+            cron: "0 2 * * wed".to_string(),
+            duration_minutes: 60,
+            label: None,
+        })
+        .unwrap();
+        let end = b.active_until(WED_0230_MS).unwrap();
+        assert_eq!(end, WED_0230_MS + 30 * 60_000);
+        assert!(b.active_until(WED_0230_MS + 31 * 60_000).is_none());
+        assert!(b.active_until(WED_0230_MS - 31 * 60_000).is_none());
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        assert!(CronSchedule::parse("* * * *").is_err());
+        assert!(CronSchedule::parse("61 * * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use crate::hex_to_bytes;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+/// Length of the secrets `keygen` and `reflect bootstrap` draw.
+pub const SECRET_BYTES: usize = 32;
+/// The shortest `secretHex` (or API token) the client and reflector accept.
+pub const MIN_SECRET_BYTES: usize = 16;
+
+/// Compares in time that depends only on the lengths, so response timing
+/// does not reveal how much of a guessed token was right.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// The shared secret as configured (`secretHex`). Wiped from memory on drop
+/// and never shown by `Debug`, so printing a `Config` cannot leak it.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SecretHex(Zeroizing<String>);
+
+impl SecretHex {
+    /// The hex text itself, for the few places that must write it out.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn decode(&self) -> Result<Secret, String> {
+        hex_to_bytes(&self.0).map(|bytes| Secret(Zeroizing::new(bytes)))
+    }
+}
+
+impl From<String> for SecretHex {
+    fn from(hex: String) -> Self {
+        SecretHex(Zeroizing::new(hex))
+    }
+}
+
+impl From<&str> for SecretHex {
+    fn from(hex: &str) -> Self {
+        SecretHex::from(hex.to_string())
+    }
+}
+
+impl fmt::Debug for SecretHex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretHex(<redacted>)")
+    }
+}
+
+impl Serialize for SecretHex {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretHex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(SecretHex::from)
+    }
+}
+
+/// Decoded key bytes, wiped on drop and redacted in `Debug`.
+#[derive(Clone)]
+pub struct Secret(Zeroizing<Vec<u8>>);
+
+impl Secret {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(<{} bytes redacted>)", self.0.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_round_trip_but_never_debug_print() {
+        let hex = SecretHex::from("00112233445566778899aabbccddeeff");
+        let json = serde_json::to_string(&hex).unwrap();
+        assert_eq!(json, "\"00112233445566778899aabbccddeeff\"");
+        let back: SecretHex = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, hex);
+
+        let secret = hex.decode().unwrap();
+        assert_eq!(secret.len(), 16);
+        assert_eq!(secret.as_bytes()[15], 0xff);
+        for shown in [format!("{hex:?}"), format!("{secret:?}")] {
+            assert!(!shown.contains("0011"), "{shown}");
+        }
+        assert!(SecretHex::from("abc").decode().is_err());
+    }
+
+    #[test]
+    fn constant_time_eq_needs_equal_lengths_and_bytes() {
+        assert!(constant_time_eq(b"token", b"token"));
+        assert!(!constant_time_eq(b"token", b"tokem"));
+        assert!(!constant_time_eq(b"token", b"token!"));
+        assert!(constant_time_eq(b"", b""));
+    }
+}
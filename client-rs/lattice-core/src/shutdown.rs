@@ -0,0 +1,38 @@
+//! The shutdown request the OS's stop signal turns into. The flag is shared;
+//! how the signal is caught is the OS's business, and on Unix it is the same
+//! everywhere, so that handler lives here too.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Records a shutdown request; true if this was the first.
+pub fn request_shutdown() -> bool {
+    !REQUESTED.swap(true, Ordering::SeqCst)
+}
+
+pub fn shutdown_requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(_: libc::c_int) {
+    request_shutdown();
+}
+
+/// Turns the first SIGINT or SIGTERM into a request that
+/// `shutdown_requested` reports. The handler resets itself, so a second
+/// signal ends the process as before.
+#[cfg(unix)]
+pub fn catch_shutdown() -> std::io::Result<()> {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESETHAND | libc::SA_RESTART;
+        unsafe { libc::sigemptyset(&mut action.sa_mask) };
+        if unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
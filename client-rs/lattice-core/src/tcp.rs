@@ -0,0 +1,252 @@
+//! The TCP probers. Nothing in them differs between the OSes, so they live
+//! here; each OS crate's monotonic clock is passed in, and `send_mono_ns`
+//! must come from the same one.
+
+use crate::{parse_lato_packet, resolve_first_for_family, Packet, LATO_PACKET_LEN, MAX_STRAYS};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+const READ_BUF_LEN: usize = 512;
+
+/// Probes an endpoint over one TCP connection, for networks that drop UDP.
+/// Packets are the same 32 bytes back to back, and the reflector echoes each
+/// one whole, so the stream stays framed. There is no kernel timestamp: the
+/// RTT runs from `send_mono_ns` to when the read returned.
+pub struct TcpProber {
+    socket: Socket,
+    clock: fn() -> u64,
+    /// Bytes read past the last whole packet.
+    pending: Vec<u8>,
+    strays: Vec<Packet>,
+}
+
+impl TcpProber {
+    /// Connects from `source_port` (0 lets the OS pick), giving up after
+    /// `timeout`. `clock` reads monotonic nanoseconds.
+    pub fn connect(
+        host: &str,
+        port: u16,
+        bind_ip: Option<IpAddr>,
+        source_port: u16,
+        timeout: Duration,
+        clock: fn() -> u64,
+    ) -> io::Result<Self> {
+        let addr = resolve_first_for_family(host, port, bind_ip)?;
+        let domain = match addr {
+            SocketAddr::V4(_) => Domain::IPV4,
+            SocketAddr::V6(_) => Domain::IPV6,
+        };
+        let socket = Socket::new(domain, Type::STREAM, Some(Protocol::TCP))?;
+        if bind_ip.is_some() || source_port != 0 {
+            // A pinned port comes back on every reconnect, possibly while
+            // the last connection from it is still in TIME_WAIT.
+            socket.set_reuse_address(true)?;
+            let ip = bind_ip.unwrap_or(match addr {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            });
+            socket.bind(&SocketAddr::new(ip, source_port).into())?;
+        }
+        socket.connect_timeout(&addr.into(), timeout)?;
+        socket.set_nodelay(true)?;
+        Ok(Self {
+            socket,
+            clock,
+            pending: Vec::with_capacity(READ_BUF_LEN),
+            strays: Vec::new(),
+        })
+    }
+
+    /// Sends `msg` and waits for its echo, in whole nanoseconds. Echoes of
+    /// earlier probes that come first are kept as strays.
+    pub fn send_and_receive_rtt(
+        &mut self,
+        msg: &[u8],
+        _send_realtime_ns: u64,
+        send_mono_ns: u64,
+        timeout: Duration,
+    ) -> io::Result<Option<u64>> {
+        let probe =
+            parse_lato_packet(msg).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        (&self.socket).write_all(msg)?;
+
+        let deadline = Instant::now() + timeout;
+        let mut recv_mono_ns: u64 = 0;
+        let mut buf = [0u8; READ_BUF_LEN];
+        loop {
+            while self.pending.len() >= LATO_PACKET_LEN {
+                let frame: Vec<u8> = self.pending.drain(..LATO_PACKET_LEN).collect();
+                match parse_lato_packet(&frame) {
+                    Ok(reply) if reply.echoes(&probe) => {
+                        return Ok(Some(recv_mono_ns.saturating_sub(send_mono_ns)));
+                    }
+                    Ok(reply) => {
+                        if self.strays.len() < MAX_STRAYS {
+                            self.strays.push(reply);
+                        }
+                    }
+                    // Out of step with the reflector; nothing later on this
+                    // connection can be matched up.
+                    Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+                }
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            self.socket.set_read_timeout(Some(deadline - now))?;
+            match (&self.socket).read(&mut buf) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "reflector closed the connection",
+                    ))
+                }
+                Ok(n) => {
+                    recv_mono_ns = (self.clock)();
+                    self.pending.extend_from_slice(&buf[..n]);
+                }
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Ok(None)
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket
+            .local_addr()?
+            .as_socket()
+            .ok_or_else(|| io::Error::other("non-IP socket"))
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.socket
+            .peer_addr()?
+            .as_socket()
+            .ok_or_else(|| io::Error::other("non-IP socket"))
+    }
+
+    /// Echoes of probes that had already timed out, oldest first; unverified.
+    pub fn take_strays(&mut self) -> Vec<Packet> {
+        std::mem::take(&mut self.strays)
+    }
+}
+
+/// Times TCP handshakes instead of echoes, for anchors that run no
+/// reflector (any open or closed port answers). Each probe opens a fresh
+/// connection: the RTT runs from `connect` to the SYN/ACK, or to the RST of a
+/// closed port, and the connection is then reset so nothing lingers in
+/// TIME_WAIT. Nothing in the answer is signed: a middlebox that terminates
+/// TCP answers for the anchor.
+pub struct TcpConnectProber {
+    addr: SocketAddr,
+    bind_ip: Option<IpAddr>,
+    source_port: u16,
+    clock: fn() -> u64,
+    /// The last completed connection's local address.
+    local: Option<SocketAddr>,
+}
+
+impl TcpConnectProber {
+    /// Resolves the anchor and times one handshake, so an anchor that does
+    /// not answer within `timeout` fails here, as with `TcpProber::connect`.
+    pub fn open(
+        host: &str,
+        port: u16,
+        bind_ip: Option<IpAddr>,
+        source_port: u16,
+        timeout: Duration,
+        clock: fn() -> u64,
+    ) -> io::Result<Self> {
+        let addr = resolve_first_for_family(host, port, bind_ip)?;
+        let mut prober = Self {
+            addr,
+            bind_ip,
+            source_port,
+            clock,
+            local: None,
+        };
+        if prober.handshake(timeout)?.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "no TCP handshake answer",
+            ));
+        }
+        Ok(prober)
+    }
+
+    fn handshake(&mut self, timeout: Duration) -> io::Result<Option<u64>> {
+        let domain = match self.addr {
+            SocketAddr::V4(_) => Domain::IPV4,
+            SocketAddr::V6(_) => Domain::IPV6,
+        };
+        let socket = Socket::new(domain, Type::STREAM, Some(Protocol::TCP))?;
+        if self.bind_ip.is_some() || self.source_port != 0 {
+            socket.set_reuse_address(true)?;
+            let ip = self.bind_ip.unwrap_or(match self.addr {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            });
+            socket.bind(&SocketAddr::new(ip, self.source_port).into())?;
+        }
+        // Closing with a zero linger sends RST, which frees a pinned source
+        // port for the next probe.
+        socket.set_linger(Some(Duration::ZERO))?;
+        let start_ns = (self.clock)();
+        let result = socket.connect_timeout(&self.addr.into(), timeout);
+        let rtt_ns = (self.clock)().saturating_sub(start_ns);
+        match result {
+            Ok(()) => {
+                self.local = socket.local_addr()?.as_socket();
+                Ok(Some(rtt_ns))
+            }
+            // The RST of a closed port is a round trip too.
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => Ok(Some(rtt_ns)),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Times one handshake; `msg` is not sent.
+    pub fn send_and_receive_rtt(
+        &mut self,
+        _msg: &[u8],
+        _send_realtime_ns: u64,
+        _send_mono_ns: u64,
+        timeout: Duration,
+    ) -> io::Result<Option<u64>> {
+        self.handshake(timeout)
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.local.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotConnected, "no handshake completed yet")
+        })
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.addr)
+    }
+
+    /// Handshakes carry no packets, so there are never strays.
+    pub fn take_strays(&mut self) -> Vec<Packet> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,137 @@
+use crate::regions::cloud_region;
+use serde_json::{Map, Value};
+
+pub const ENDPOINT_TEMPLATES_KEY: &str = "endpointTemplates";
+const REGION_PLACEHOLDER: &str = "{region}";
+
+/// Expands `endpointTemplates` into concrete `endpoints` entries, appended
+/// after any literal endpoints. A template is an endpoint object plus
+/// `regions` (provider region codes) and an optional `provider`; every
+/// `{region}` in its strings is replaced per region, and `regionHint`,
+/// `lat`, and `lon` default to the region code and its bundled coordinates.
+pub fn expand_endpoint_templates(doc: &mut Value) -> Result<(), String> {
+    let Some(obj) = doc.as_object_mut() else {
+        return Ok(());
+    };
+    let Some(templates) = obj.remove(ENDPOINT_TEMPLATES_KEY) else {
+        return Ok(());
+    };
+    let templates = match templates {
+        Value::Array(items) => items,
+        _ => return Err(format!("{} must be an array", ENDPOINT_TEMPLATES_KEY)),
+    };
+
+    let mut expanded = Vec::new();
+    for (i, template) in templates.into_iter().enumerate() {
+        let Value::Object(mut template) = template else {
+            return Err(format!(
+                "{}[{}] must be an object",
+                ENDPOINT_TEMPLATES_KEY, i
+            ));
+        };
+        let provider = template
+            .remove("provider")
+            .and_then(|v| v.as_str().map(str::to_string));
+        let regions: Vec<String> = match template.remove("regions") {
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|v| v.as_str().map(str::to_string))
+                .collect::<Option<_>>()
+                .ok_or_else(|| {
+                    format!("{}[{}].regions must be strings", ENDPOINT_TEMPLATES_KEY, i)
+                })?,
+            _ => {
+                return Err(format!(
+                    "{}[{}] needs a regions list",
+                    ENDPOINT_TEMPLATES_KEY, i
+                ))
+            }
+        };
+        for code in regions {
+            expanded.push(Value::Object(instantiate(
+                &template,
+                provider.as_deref(),
+                &code,
+                i,
+            )?));
+        }
+    }
+
+    match obj
+        .entry("endpoints")
+        .or_insert_with(|| Value::Array(Vec::new()))
+    {
+        Value::Array(endpoints) => endpoints.extend(expanded),
+        _ => return Err("endpoints must be an array".to_string()),
+    }
+    Ok(())
+}
+
+fn instantiate(
+    template: &Map<String, Value>,
+    provider: Option<&str>,
+    code: &str,
+    index: usize,
+) -> Result<Map<String, Value>, String> {
+    let mut ep = Map::new();
+    for (key, value) in template {
+        let value = match value {
+            Value::String(s) => Value::String(s.replace(REGION_PLACEHOLDER, code)),
+            other => other.clone(),
+        };
+        ep.insert(key.clone(), value);
+    }
+    ep.entry("regionHint")
+        .or_insert_with(|| Value::String(code.to_string()));
+    if !(ep.contains_key("lat") && ep.contains_key("lon")) {
+        let region = cloud_region(provider, code).ok_or_else(|| {
+            format!(
+                "{}[{}]: no bundled coordinates for region '{}'{}; set provider or lat/lon",
+                ENDPOINT_TEMPLATES_KEY,
+                index,
+                code,
+                provider.map(|p| format!(" ({})", p)).unwrap_or_default()
+            )
+        })?;
+        ep.entry("lat").or_insert(region.lat.into());
+        ep.entry("lon").or_insert(region.lon.into());
+    }
+    Ok(ep)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn expands_regions_with_registry_coordinates() {
+        let mut doc = json!({
+            "endpoints": [{ "id": "lab", "host": "10.0.0.2", "port": 9000 }],
+            "endpointTemplates": [{
+                "id": "anchor-{region}",
+                "host": "anchor-{region}.example.com",
+                "port": 9000,
+                "provider": "aws",
+                "regions": ["us-east-1", "eu-west-1"],
+                "consented": true,
+            }],
+        });
+        expand_endpoint_templates(&mut doc).unwrap();
+        let eps = doc["endpoints"].as_array().unwrap();
+        assert_eq!(eps.len(), 3);
+        assert_eq!(eps[2]["host"], "anchor-eu-west-1.example.com");
+        assert_eq!(eps[2]["regionHint"], "eu-west-1");
+        assert_eq!(eps[2]["lat"], 53.3498);
+        assert!(eps[1].get("regions").is_none());
+        assert!(doc.get("endpointTemplates").is_none());
+    }
+
+    #[test]
+    fn unknown_region_without_coordinates_is_an_error() {
+        let mut doc = json!({
+            "endpointTemplates": [{ "id": "{region}", "host": "h", "port": 1, "regions": ["mars-1"] }],
+        });
+        assert!(expand_endpoint_templates(&mut doc).is_err());
+    }
+}
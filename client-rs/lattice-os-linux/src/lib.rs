@@ -1,16 +1,111 @@
+use lattice_core::{
+    parse_lato_packet, resolve_first_for_family, Ecn, IfaceCounters, Packet, ReflectorTiming,
+    Secret, TimestampSource, MAX_STRAYS,
+};
 use socket2::{Domain, Protocol, Socket, Type};
 use std::collections::HashMap;
 use std::ffi::CStr;
 use std::io;
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::path::Path;
 use std::time::{Duration, Instant};
 
+mod power;
+pub use lattice_core::{catch_shutdown, shutdown_requested};
+pub use power::{suspended_ns, watch_power, POWER_SOURCE};
+
+const NS_PER_SEC: u64 = 1_000_000_000;
+/// A receive timestamp this close to a clock's current reading was taken on
+/// that clock.
+const CLOCK_MATCH_NS: u64 = 5 * NS_PER_SEC;
+/// Anything longer is a clock mix-up, not a round trip.
+const MAX_RTT_NS: u64 = 60 * NS_PER_SEC;
+const CMSG_BUF_LEN: usize = 256;
+/// IPv6 flow labels are 20 bits.
+pub const MAX_FLOW_LABEL: u32 = 0xF_FFFF;
+// <linux/in6.h> flow label manager, not in the libc crate.
+const IPV6_FL_A_GET: u8 = 0;
+const IPV6_FL_F_CREATE: u16 = 1;
+const IPV6_FL_S_PROCESS: u8 = 2;
+/// Kernel software stamps on receive and on transmit. Transmit stamps come
+/// back on the error queue without the payload, tagged with a per-socket
+/// send counter.
+const SW_TIMESTAMPING: libc::c_uint = libc::SOF_TIMESTAMPING_RX_SOFTWARE
+    | libc::SOF_TIMESTAMPING_TX_SOFTWARE
+    | libc::SOF_TIMESTAMPING_SOFTWARE
+    | libc::SOF_TIMESTAMPING_OPT_ID
+    | libc::SOF_TIMESTAMPING_OPT_TSONLY;
+/// The same plus NIC stamps both ways; each send then gets two error-queue
+/// messages with the same counter, one per clock.
+const HW_TIMESTAMPING: libc::c_uint = SW_TIMESTAMPING
+    | libc::SOF_TIMESTAMPING_TX_HARDWARE
+    | libc::SOF_TIMESTAMPING_RX_HARDWARE
+    | libc::SOF_TIMESTAMPING_RAW_HARDWARE;
+/// `scm_timestamping.ts`: software, legacy, raw hardware.
+const SCM_TS_SOFTWARE: usize = 0;
+const SCM_TS_HARDWARE: usize = 2;
+
+/// `struct in6_flowlabel_req`: asks the kernel for a flow label lease, which
+/// a socket must hold before it may send with that label.
+#[repr(C)]
+struct In6FlowlabelReq {
+    flr_dst: [u8; 16],
+    flr_label: u32,
+    flr_action: u8,
+    flr_share: u8,
+    flr_flags: u16,
+    flr_expires: u16,
+    flr_linger: u16,
+    flr_pad: u32,
+}
+
+/// Control-message space for `recvmsg`. The kernel writes `cmsghdr`s into it,
+/// which need `size_t` alignment to be read in place.
+#[repr(C, align(8))]
+struct CmsgBuf([u8; CMSG_BUF_LEN]);
+
 pub struct UdpProber {
     socket: Socket,
     recv_buf: [u8; 2048],
-    cmsg_buf: [u8; 256],
+    cmsg_buf: CmsgBuf,
+    strays: Vec<Packet>,
+    /// Set for v2 probes: replies must carry a reply tag under it.
+    reply_secret: Option<Secret>,
+    /// The stamps on the last v2 reply, when it had any.
+    last_timing: Option<ReflectorTiming>,
+    /// The ECN field of the last echo, once `mark_ecn` asked for it.
+    last_ecn: Option<Ecn>,
+    /// Label the next probes carry, and the labels this socket has leased.
+    flow_label: Option<u32>,
+    leased_labels: Vec<u32>,
+    stamping: Stamping,
+    /// Sends so far, which is how the kernel numbers transmit stamps.
+    sends: u32,
+    last_source: TimestampSource,
+}
+
+/// Which timestamps the socket gets from the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stamping {
+    /// `SO_TIMESTAMPNS`, for kernels without `SO_TIMESTAMPING`.
+    ReceiveOnly,
+    Software,
+    Hardware,
+}
+
+/// What the kernel attached to a datagram, or to a send on the error queue.
+#[derive(Debug, Clone, Copy, Default)]
+struct Stamps {
+    software_ns: Option<u64>,
+    hardware_ns: Option<u64>,
+}
+
+impl Stamps {
+    fn merge(&mut self, other: Stamps) {
+        self.software_ns = self.software_ns.or(other.software_ns);
+        self.hardware_ns = self.hardware_ns.or(other.hardware_ns);
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -27,45 +122,95 @@ pub struct UtunReport {
     pub interfaces: Vec<UtunInterfaceInfo>,
 }
 
+/// Kernel NTP discipline state; fields are `None` when the platform does not expose them.
+#[derive(Debug, Clone, Default)]
+pub struct NtpReport {
+    pub synced: Option<bool>,
+    pub offset_ms: Option<f64>,
+    pub est_error_ms: Option<f64>,
+    pub source: Option<String>,
+}
+
 impl UdpProber {
     pub fn new(host: &str, port: u16, bind_ip: Option<IpAddr>) -> io::Result<Self> {
+        Self::with_source_port(host, port, bind_ip, 0)
+    }
+
+    /// Like `new`, but sends from `source_port` (0 lets the OS pick).
+    pub fn with_source_port(
+        host: &str,
+        port: u16,
+        bind_ip: Option<IpAddr>,
+        source_port: u16,
+    ) -> io::Result<Self> {
         let addr = resolve_first_for_family(host, port, bind_ip)?;
         let domain = match addr {
             SocketAddr::V4(_) => Domain::IPV4,
             SocketAddr::V6(_) => Domain::IPV6,
         };
         let socket = Socket::new(domain, Type::DGRAM, Some(Protocol::UDP))?;
-        if let Some(ip) = bind_ip {
-            let bind_addr = SocketAddr::new(ip, 0);
-            socket.bind(&bind_addr.into())?;
+        if bind_ip.is_some() || source_port != 0 {
+            let ip = bind_ip.unwrap_or(match addr {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            });
+            socket.bind(&SocketAddr::new(ip, source_port).into())?;
         }
         socket.connect(&addr.into())?;
 
-        enable_rx_timestamping(socket.as_raw_fd())?;
+        let iface = socket
+            .local_addr()?
+            .as_socket()
+            .and_then(|local| iface_for_ip(local.ip()));
+        let stamping = enable_rx_timestamping(socket.as_raw_fd(), iface.as_deref())?;
 
         Ok(Self {
             socket,
             recv_buf: [0u8; 2048],
-            cmsg_buf: [0u8; 256],
+            cmsg_buf: CmsgBuf([0u8; CMSG_BUF_LEN]),
+            strays: Vec::new(),
+            reply_secret: None,
+            last_timing: None,
+            last_ecn: None,
+            flow_label: None,
+            leased_labels: Vec::new(),
+            stamping,
+            sends: 0,
+            last_source: TimestampSource::Kernel,
         })
     }
 
+    /// Sends `msg` and waits for its echo. The round trip is in whole
+    /// nanoseconds, straight from the clock readings; callers convert to ms
+    /// only when they write it out.
     pub fn send_and_receive_rtt(
         &mut self,
         msg: &[u8],
         send_realtime_ns: u64,
         send_mono_ns: u64,
         timeout: Duration,
-    ) -> io::Result<Option<f64>> {
+    ) -> io::Result<Option<u64>> {
+        let probe =
+            parse_lato_packet(msg).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        self.last_timing = None;
+        self.last_ecn = None;
         let fd = self.socket.as_raw_fd();
+        let tx_stamped = self.stamping != Stamping::ReceiveOnly;
+        if tx_stamped {
+            // Stamps of earlier sends that came back too late to be used.
+            while self.recv_tx_stamp().is_some() {}
+        }
         let send_instant = Instant::now();
-        let sent = unsafe { libc::send(fd, msg.as_ptr() as *const _, msg.len(), 0) };
+        let sent = self.send_probe(msg)?;
         if sent < 0 {
             return Err(io::Error::last_os_error());
         }
+        let send_id = self.sends;
+        self.sends = self.sends.wrapping_add(1);
         if sent as usize != msg.len() {
-            return Err(io::Error::new(io::ErrorKind::Other, "short send"));
+            return Err(io::Error::other("short send"));
         }
+        let mut tx = Stamps::default();
 
         let deadline = Instant::now() + timeout;
         loop {
@@ -73,9 +218,7 @@ impl UdpProber {
             if now >= deadline {
                 return Ok(None);
             }
-            let remaining_ms = (deadline - now)
-                .as_millis()
-                .min(i32::MAX as u128) as i32;
+            let remaining_ms = (deadline - now).as_millis().min(i32::MAX as u128) as i32;
 
             let mut pfd = libc::pollfd {
                 fd,
@@ -89,23 +232,71 @@ impl UdpProber {
             if rv == 0 {
                 return Ok(None);
             }
+            if tx_stamped && (pfd.revents & libc::POLLERR) != 0 {
+                self.take_tx_stamps(send_id, &mut tx);
+            }
             if (pfd.revents & libc::POLLIN) == 0 {
                 continue;
             }
 
-            let (n, recv_ns) = self.recv_with_timestamp()?;
-            if n != msg.len() {
-                continue;
-            }
-            if &self.recv_buf[..n] != msg {
-                continue;
-            }
+            let (n, rx, ecn) = self.recv_with_timestamp()?;
+            // Anything on the wire may be hostile; only a well-formed echo of
+            // this probe counts.
+            let reply = match parse_lato_packet(&self.recv_buf[..n]) {
+                Ok(reply) if reply.echoes(&probe) && self.signed(&reply) => reply,
+                Ok(reply) => {
+                    if self.strays.len() < MAX_STRAYS {
+                        self.strays.push(reply);
+                    }
+                    continue;
+                }
+                Err(_) => continue,
+            };
             let recv_instant = Instant::now();
-            let fallback_rtt_ms = (recv_instant - send_instant).as_secs_f64() * 1000.0;
+            let fallback_rtt_ns =
+                u64::try_from((recv_instant - send_instant).as_nanos()).unwrap_or(u64::MAX);
 
-            let rtt_ms = choose_rtt_ms(recv_ns, send_realtime_ns, send_mono_ns)
-                .unwrap_or(fallback_rtt_ms);
-            return Ok(Some(rtt_ms));
+            if tx_stamped {
+                self.take_tx_stamps(send_id, &mut tx);
+            }
+            let between = |sent: Option<u64>, received: Option<u64>| {
+                received?
+                    .checked_sub(sent?)
+                    .filter(|&rtt_ns| rtt_ns <= MAX_RTT_NS)
+            };
+            let (rtt_ns, source) = if let Some(rtt_ns) = between(tx.hardware_ns, rx.hardware_ns) {
+                (rtt_ns, TimestampSource::Hardware)
+            } else if let Some(rtt_ns) = between(tx.software_ns, rx.software_ns) {
+                (rtt_ns, TimestampSource::KernelTx)
+            } else if let Some(rtt_ns) = rx
+                .software_ns
+                .and_then(|recv_ns| choose_rtt_ns(recv_ns, send_realtime_ns, send_mono_ns))
+            {
+                (rtt_ns, TimestampSource::Kernel)
+            } else {
+                (fallback_rtt_ns, TimestampSource::Userspace)
+            };
+            self.last_source = source;
+            self.last_ecn = ecn;
+            // Time the reflector sat on the probe is not distance.
+            self.last_timing = reply.reflector_timing(send_realtime_ns, rtt_ns);
+            return Ok(Some(rtt_ns - self.last_timing.map_or(0, |t| t.hold_ns)));
+        }
+    }
+
+    /// Where the last RTT came from: the best pair of stamps both ends of
+    /// the probe got, NIC before kernel transmit before kernel receive only.
+    pub fn timestamp_source(&self) -> TimestampSource {
+        self.last_source
+    }
+
+    /// Reads every queued transmit stamp, keeping the ones for send
+    /// `send_id`. A NIC stamp may land well after the software one.
+    fn take_tx_stamps(&mut self, send_id: u32, out: &mut Stamps) {
+        while let Some((id, stamps)) = self.recv_tx_stamp() {
+            if id == send_id {
+                out.merge(stamps);
+            }
         }
     }
 
@@ -114,7 +305,7 @@ impl UdpProber {
             .socket
             .local_addr()?
             .as_socket()
-            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "non-IP socket"))?;
+            .ok_or_else(|| io::Error::other("non-IP socket"))?;
         iface_for_ip(addr.ip())
             .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "iface not found"))
     }
@@ -123,7 +314,143 @@ impl UdpProber {
         self.socket
             .local_addr()?
             .as_socket()
-            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "non-IP socket"))
+            .ok_or_else(|| io::Error::other("non-IP socket"))
+    }
+
+    /// Well-formed LATO packets that arrived while waiting for another probe's
+    /// echo, oldest first: mostly replies that missed their own timeout. They
+    /// are unverified; the caller checks their tags.
+    pub fn take_strays(&mut self) -> Vec<Packet> {
+        std::mem::take(&mut self.strays)
+    }
+
+    /// Sends `msg` without waiting for its echo, which turns up among the
+    /// strays later. For keepalives between bursts.
+    pub fn send_only(&mut self, msg: &[u8]) -> io::Result<()> {
+        if self.send_probe(msg)? < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // The kernel numbers its transmit stamp like a probe's.
+        self.sends = self.sends.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Takes an echo only once its v2 reply tag checks out under `secret`,
+    /// so a copy of the probe bounced back by anyone on the path is a stray.
+    pub fn check_replies(&mut self, secret: Secret) {
+        self.reply_secret = Some(secret);
+    }
+
+    /// The reflector's stamps on the last echo, for v2 probes to a reflector
+    /// that sets them. The RTT returned with it already leaves out the hold.
+    pub fn reflector_timing(&self) -> Option<ReflectorTiming> {
+        self.last_timing
+    }
+
+    /// Sends later probes ECT(0) and has the kernel pass up the ECN field
+    /// of what comes back (`IP_RECVTOS`, `IPV6_RECVTCLASS`).
+    pub fn mark_ecn(&mut self) -> io::Result<()> {
+        let fd = self.socket.as_raw_fd();
+        let ect = libc::c_int::from(Ecn::Ect0.bits());
+        let on: libc::c_int = 1;
+        match self.peer_addr()? {
+            SocketAddr::V4(_) => {
+                set_opt(fd, libc::IPPROTO_IP, libc::IP_TOS, &ect)?;
+                set_opt(fd, libc::IPPROTO_IP, libc::IP_RECVTOS, &on)
+            }
+            SocketAddr::V6(_) => {
+                set_ipv6_opt(fd, libc::IPV6_TCLASS, &ect)?;
+                set_ipv6_opt(fd, libc::IPV6_RECVTCLASS, &on)
+            }
+        }
+    }
+
+    /// The ECN field the last echo arrived with, after `mark_ecn`.
+    pub fn reply_ecn(&self) -> Option<Ecn> {
+        self.last_ecn
+    }
+
+    fn signed(&self, reply: &Packet) -> bool {
+        self.reply_secret
+            .as_ref()
+            .is_none_or(|secret| reply.verify_reply(secret.as_bytes()))
+    }
+
+    /// Sends later probes with IPv6 flow label `label`, leasing it from the
+    /// kernel the first time. Only for IPv6 peers.
+    pub fn set_flow_label(&mut self, label: u32) -> io::Result<()> {
+        let SocketAddr::V6(peer) = self.peer_addr()? else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "flow labels need an IPv6 endpoint",
+            ));
+        };
+        if label == 0 || label > MAX_FLOW_LABEL {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid flow label {label}"),
+            ));
+        }
+        if !self.leased_labels.contains(&label) {
+            let fd = self.socket.as_raw_fd();
+            let req = In6FlowlabelReq {
+                flr_dst: peer.ip().octets(),
+                flr_label: label.to_be(),
+                flr_action: IPV6_FL_A_GET,
+                flr_share: IPV6_FL_S_PROCESS,
+                flr_flags: IPV6_FL_F_CREATE,
+                flr_expires: 0,
+                flr_linger: 0,
+                flr_pad: 0,
+            };
+            set_ipv6_opt(fd, libc::IPV6_FLOWLABEL_MGR, &req)?;
+            if self.leased_labels.is_empty() {
+                set_ipv6_opt(fd, libc::IPV6_FLOWINFO_SEND, &(1 as libc::c_int))?;
+            }
+            self.leased_labels.push(label);
+        }
+        self.flow_label = Some(label);
+        Ok(())
+    }
+
+    /// A labelled probe goes out through `sendto` with the label in
+    /// `sin6_flowinfo`; the connected peer is the same either way.
+    fn send_probe(&self, msg: &[u8]) -> io::Result<isize> {
+        let fd = self.socket.as_raw_fd();
+        let plain = || Ok(unsafe { libc::send(fd, msg.as_ptr() as *const _, msg.len(), 0) });
+        let Some(label) = self.flow_label else {
+            return plain();
+        };
+        let SocketAddr::V6(peer) = self.peer_addr()? else {
+            return plain();
+        };
+        let addr = libc::sockaddr_in6 {
+            sin6_family: libc::AF_INET6 as libc::sa_family_t,
+            sin6_port: peer.port().to_be(),
+            sin6_flowinfo: label.to_be(),
+            sin6_addr: libc::in6_addr {
+                s6_addr: peer.ip().octets(),
+            },
+            sin6_scope_id: peer.scope_id(),
+        };
+        Ok(unsafe {
+            libc::sendto(
+                fd,
+                msg.as_ptr() as *const _,
+                msg.len(),
+                0,
+                &addr as *const _ as *const libc::sockaddr,
+                std::mem::size_of_val(&addr) as libc::socklen_t,
+            )
+        })
+    }
+
+    /// Address the host resolved to when the socket was connected.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.socket
+            .peer_addr()?
+            .as_socket()
+            .ok_or_else(|| io::Error::other("non-IP socket"))
     }
 }
 
@@ -131,9 +458,7 @@ pub fn iface_type(name: &str) -> String {
     if name == "lo" {
         return "loopback".into();
     }
-    let wireless = Path::new("/sys/class/net")
-        .join(name)
-        .join("wireless");
+    let wireless = Path::new("/sys/class/net").join(name).join("wireless");
     if wireless.exists() {
         return "wifi".into();
     }
@@ -149,6 +474,28 @@ pub fn iface_type(name: &str) -> String {
     "other".into()
 }
 
+/// Byte and packet counters for `name` from sysfs.
+pub fn iface_counters(name: &str) -> Option<IfaceCounters> {
+    if name.is_empty() || name.contains('/') {
+        return None;
+    }
+    let stats = Path::new("/sys/class/net").join(name).join("statistics");
+    let read = |counter: &str| -> Option<u64> {
+        std::fs::read_to_string(stats.join(counter))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    };
+    Some(IfaceCounters {
+        rx_bytes: read("rx_bytes")?,
+        tx_bytes: read("tx_bytes")?,
+        rx_packets: read("rx_packets")?,
+        tx_packets: read("tx_packets")?,
+        bits: u64::BITS,
+    })
+}
+
 pub fn utun_present() -> bool {
     utun_report().present
 }
@@ -159,7 +506,7 @@ pub fn realtime_now_ns() -> u64 {
         if libc::clock_gettime(libc::CLOCK_REALTIME, &mut ts) != 0 {
             return 0;
         }
-        (ts.tv_sec as u64) * 1_000_000_000u64 + (ts.tv_nsec as u64)
+        (ts.tv_sec as u64) * NS_PER_SEC + (ts.tv_nsec as u64)
     }
 }
 
@@ -169,7 +516,38 @@ pub fn monotonic_now_ns() -> u64 {
         if libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) != 0 {
             return 0;
         }
-        (ts.tv_sec as u64) * 1_000_000_000u64 + (ts.tv_nsec as u64)
+        (ts.tv_sec as u64) * NS_PER_SEC + (ts.tv_nsec as u64)
+    }
+}
+
+/// Advertised resolution of the clock behind `realtime_now_ns`.
+pub fn realtime_resolution_ns() -> Option<u64> {
+    unsafe {
+        let mut ts: libc::timespec = std::mem::zeroed();
+        if libc::clock_getres(libc::CLOCK_REALTIME, &mut ts) != 0 {
+            return None;
+        }
+        Some((ts.tv_sec as u64) * NS_PER_SEC + (ts.tv_nsec as u64))
+    }
+}
+
+/// NTP sync state from `adjtimex` (read-only: `modes` is zero).
+pub fn ntp_report() -> NtpReport {
+    let mut tx: libc::timex = unsafe { std::mem::zeroed() };
+    let state = unsafe { libc::adjtimex(&mut tx) };
+    if state < 0 {
+        return NtpReport::default();
+    }
+    let offset_ms = if tx.status & libc::STA_NANO != 0 {
+        tx.offset as f64 / 1_000_000.0
+    } else {
+        tx.offset as f64 / 1_000.0
+    };
+    NtpReport {
+        synced: Some(state != libc::TIME_ERROR && tx.status & libc::STA_UNSYNC == 0),
+        offset_ms: Some(offset_ms),
+        est_error_ms: Some(tx.esterror as f64 / 1_000.0),
+        source: Some("adjtimex".to_string()),
     }
 }
 
@@ -189,9 +567,7 @@ pub fn utun_report() -> UtunReport {
         while !cur.is_null() {
             let ifa = &*cur;
             if !ifa.ifa_name.is_null() {
-                let name = CStr::from_ptr(ifa.ifa_name)
-                    .to_string_lossy()
-                    .to_string();
+                let name = CStr::from_ptr(ifa.ifa_name).to_string_lossy().to_string();
                 if name.starts_with("tun")
                     || name.starts_with("tap")
                     || name.starts_with("wg")
@@ -226,24 +602,22 @@ pub fn utun_report() -> UtunReport {
     }
 }
 
-fn choose_rtt_ms(recv_ns: u64, send_realtime_ns: u64, send_mono_ns: u64) -> Option<f64> {
-    const THRESH_NS: u64 = 5_000_000_000;
-
+/// Receive timestamp minus the send time on whichever clock the kernel
+/// stamped the reply with.
+fn choose_rtt_ns(recv_ns: u64, send_realtime_ns: u64, send_mono_ns: u64) -> Option<u64> {
     let now_realtime = realtime_now_ns();
-    if now_realtime > 0 && abs_diff(recv_ns, now_realtime) <= THRESH_NS {
-        let diff = recv_ns.saturating_sub(send_realtime_ns);
-        let rtt_ms = diff as f64 / 1_000_000.0;
-        if rtt_ms.is_finite() && rtt_ms <= 60_000.0 {
-            return Some(rtt_ms);
+    if now_realtime > 0 && abs_diff(recv_ns, now_realtime) <= CLOCK_MATCH_NS {
+        let rtt_ns = recv_ns.saturating_sub(send_realtime_ns);
+        if rtt_ns <= MAX_RTT_NS {
+            return Some(rtt_ns);
         }
     }
 
     let now_mono = monotonic_now_ns();
-    if now_mono > 0 && abs_diff(recv_ns, now_mono) <= THRESH_NS {
-        let diff = recv_ns.saturating_sub(send_mono_ns);
-        let rtt_ms = diff as f64 / 1_000_000.0;
-        if rtt_ms.is_finite() && rtt_ms <= 60_000.0 {
-            return Some(rtt_ms);
+    if now_mono > 0 && abs_diff(recv_ns, now_mono) <= CLOCK_MATCH_NS {
+        let rtt_ns = recv_ns.saturating_sub(send_mono_ns);
+        if rtt_ns <= MAX_RTT_NS {
+            return Some(rtt_ns);
         }
     }
 
@@ -251,33 +625,7 @@ fn choose_rtt_ms(recv_ns: u64, send_realtime_ns: u64, send_mono_ns: u64) -> Opti
 }
 
 fn abs_diff(a: u64, b: u64) -> u64 {
-    if a >= b { a - b } else { b - a }
-}
-
-fn resolve_first_for_family(
-    host: &str,
-    port: u16,
-    bind_ip: Option<IpAddr>,
-) -> io::Result<SocketAddr> {
-    let mut addrs = (host, port).to_socket_addrs()?;
-    if let Some(ip) = bind_ip {
-        let want_v4 = ip.is_ipv4();
-        for addr in addrs {
-            if want_v4 && matches!(addr, SocketAddr::V4(_)) {
-                return Ok(addr);
-            }
-            if !want_v4 && matches!(addr, SocketAddr::V6(_)) {
-                return Ok(addr);
-            }
-        }
-        return Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            "no resolved addresses for bind family",
-        ));
-    }
-    addrs
-        .next()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no resolved addresses"))
+    a.abs_diff(b)
 }
 
 pub fn iface_ips(name: &str) -> io::Result<Vec<IpAddr>> {
@@ -296,9 +644,7 @@ pub fn iface_ips(name: &str) -> io::Result<Vec<IpAddr>> {
                 continue;
             }
             if !ifa.ifa_name.is_null() {
-                let if_name = CStr::from_ptr(ifa.ifa_name)
-                    .to_string_lossy()
-                    .to_string();
+                let if_name = CStr::from_ptr(ifa.ifa_name).to_string_lossy().to_string();
                 if if_name == name {
                     let sa_family = (*ifa.ifa_addr).sa_family as i32;
                     if sa_family == libc::AF_INET {
@@ -319,7 +665,112 @@ pub fn iface_ips(name: &str) -> io::Result<Vec<IpAddr>> {
     Ok(out)
 }
 
-fn enable_rx_timestamping(fd: RawFd) -> io::Result<()> {
+/// Linux has no system relay that moves egress without a tunnel interface.
+pub fn system_relay() -> Option<&'static str> {
+    None
+}
+
+/// Network.framework's per-destination path has no Linux counterpart.
+pub struct PathMonitor;
+
+impl PathMonitor {
+    pub fn new(_destination: SocketAddr, _bind_ip: Option<IpAddr>) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "path monitoring is not supported on Linux",
+        ))
+    }
+
+    pub fn current(&self) -> Option<lattice_core::NetworkPath> {
+        None
+    }
+}
+
+/// Every address of the interfaces that are up and running, loopback left
+/// out, by interface name.
+pub fn up_iface_ips() -> io::Result<Vec<(String, IpAddr)>> {
+    let mut ifap: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut ifap) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let up = (libc::IFF_UP | libc::IFF_RUNNING) as u32;
+    let mut out = Vec::new();
+    let mut cur = ifap;
+    unsafe {
+        while !cur.is_null() {
+            let ifa = &*cur;
+            cur = ifa.ifa_next;
+            if ifa.ifa_addr.is_null()
+                || ifa.ifa_name.is_null()
+                || ifa.ifa_flags as u32 & up != up
+                || ifa.ifa_flags as u32 & libc::IFF_LOOPBACK as u32 != 0
+            {
+                continue;
+            }
+            let name = CStr::from_ptr(ifa.ifa_name).to_string_lossy().to_string();
+            let sa_family = (*ifa.ifa_addr).sa_family as i32;
+            if sa_family == libc::AF_INET {
+                let sa = *(ifa.ifa_addr as *const libc::sockaddr_in);
+                out.push((
+                    name,
+                    IpAddr::V4(Ipv4Addr::from(u32::from_be(sa.sin_addr.s_addr))),
+                ));
+            } else if sa_family == libc::AF_INET6 {
+                let sa = *(ifa.ifa_addr as *const libc::sockaddr_in6);
+                out.push((name, IpAddr::V6(Ipv6Addr::from(sa.sin6_addr.s6_addr))));
+            }
+        }
+        libc::freeifaddrs(ifap);
+    }
+    Ok(out)
+}
+
+fn set_ipv6_opt<T>(fd: RawFd, name: libc::c_int, value: &T) -> io::Result<()> {
+    set_opt(fd, libc::IPPROTO_IPV6, name, value)
+}
+
+fn set_opt<T>(fd: RawFd, level: libc::c_int, name: libc::c_int, value: &T) -> io::Result<()> {
+    let rv = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            value as *const T as *const _,
+            std::mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+    if rv != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Asks for NIC timestamps with `SO_TIMESTAMPING` when `iface` already has
+/// hardware stamping on for both directions (set up by ptp4l or
+/// `hwstamp_ctl`; changing it needs CAP_NET_ADMIN and affects every socket
+/// on the NIC, so the prober never does), and for kernel software stamps on
+/// send and receive otherwise. If the kernel refuses both, falls back to
+/// receive stamps from `SO_TIMESTAMPNS`.
+fn enable_rx_timestamping(fd: RawFd, iface: Option<&str>) -> io::Result<Stamping> {
+    let set_stamping = |flags: libc::c_uint| {
+        let flags = flags as libc::c_int;
+        let rv = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_TIMESTAMPING,
+                &flags as *const _ as *const _,
+                std::mem::size_of_val(&flags) as libc::socklen_t,
+            )
+        };
+        rv == 0
+    };
+    if iface.is_some_and(|name| nic_stamps_both_ways(fd, name)) && set_stamping(HW_TIMESTAMPING) {
+        return Ok(Stamping::Hardware);
+    }
+    if set_stamping(SW_TIMESTAMPING) {
+        return Ok(Stamping::Software);
+    }
     let on: libc::c_int = 1;
     let rv = unsafe {
         libc::setsockopt(
@@ -333,21 +784,94 @@ fn enable_rx_timestamping(fd: RawFd) -> io::Result<()> {
     if rv != 0 {
         return Err(io::Error::last_os_error());
     }
-    Ok(())
+    Ok(Stamping::ReceiveOnly)
 }
 
-fn recv_timestamp_ns(msg: &libc::msghdr) -> Option<u64> {
+/// The NIC's current `SIOCGHWTSTAMP` config stamps outgoing packets and
+/// at least some incoming ones.
+fn nic_stamps_both_ways(fd: RawFd, iface: &str) -> bool {
+    let name = iface.as_bytes();
+    if name.len() >= libc::IFNAMSIZ {
+        return false;
+    }
+    let mut config: libc::hwtstamp_config = unsafe { std::mem::zeroed() };
+    let mut req: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (dst, &src) in req.ifr_name.iter_mut().zip(name) {
+        *dst = src as libc::c_char;
+    }
+    req.ifr_ifru.ifru_data = &mut config as *mut _ as *mut libc::c_char;
+    if unsafe { libc::ioctl(fd, libc::SIOCGHWTSTAMP as _, &mut req) } != 0 {
+        return false;
+    }
+    config.tx_type as libc::c_uint == libc::HWTSTAMP_TX_ON
+        && config.rx_filter as libc::c_uint != libc::HWTSTAMP_FILTER_NONE
+}
+
+fn timespec_ns(ts: &libc::timespec) -> Option<u64> {
+    let ns = (ts.tv_sec as u64) * NS_PER_SEC + (ts.tv_nsec as u64);
+    (ns != 0).then_some(ns)
+}
+
+/// Receive stamps from `SCM_TIMESTAMPNS`, or the software and raw hardware
+/// slots of `SCM_TIMESTAMPING`; a zero slot means that stamp is missing.
+fn recv_timestamps(msg: &libc::msghdr) -> Stamps {
+    let mut stamps = Stamps::default();
     unsafe {
         let mut cmsg = cmsg_firsthdr(msg);
         while !cmsg.is_null() {
             let cmsg_ref = &*cmsg;
-            if cmsg_ref.cmsg_level == libc::SOL_SOCKET
-                && cmsg_ref.cmsg_type == libc::SCM_TIMESTAMPNS
-            {
-                let data = cmsg_data(cmsg) as *const libc::timespec;
-                if !data.is_null() {
-                    let ts = *data;
-                    return Some((ts.tv_sec as u64) * 1_000_000_000u64 + (ts.tv_nsec as u64));
+            if cmsg_ref.cmsg_level == libc::SOL_SOCKET {
+                if cmsg_ref.cmsg_type == libc::SCM_TIMESTAMPNS {
+                    let ts = *(cmsg_data(cmsg) as *const libc::timespec);
+                    stamps.software_ns = timespec_ns(&ts);
+                } else if cmsg_ref.cmsg_type == libc::SCM_TIMESTAMPING {
+                    let ts = *(cmsg_data(cmsg) as *const [libc::timespec; 3]);
+                    stamps.software_ns = timespec_ns(&ts[SCM_TS_SOFTWARE]);
+                    stamps.hardware_ns = timespec_ns(&ts[SCM_TS_HARDWARE]);
+                }
+            }
+            cmsg = cmsg_nxthdr(msg, cmsg);
+        }
+    }
+    stamps
+}
+
+/// The ECN bits of a received datagram's TOS byte or traffic class, when
+/// the socket asked for them.
+fn recv_ecn(msg: &libc::msghdr) -> Option<Ecn> {
+    unsafe {
+        let mut cmsg = cmsg_firsthdr(msg);
+        while !cmsg.is_null() {
+            let cmsg_ref = &*cmsg;
+            if cmsg_ref.cmsg_level == libc::SOL_IP && cmsg_ref.cmsg_type == libc::IP_TOS {
+                return Some(Ecn::from_tos(*cmsg_data(cmsg)));
+            }
+            if cmsg_ref.cmsg_level == libc::SOL_IPV6 && cmsg_ref.cmsg_type == libc::IPV6_TCLASS {
+                let tclass = *(cmsg_data(cmsg) as *const libc::c_int);
+                return Some(Ecn::from_tos(tclass as u8));
+            }
+            cmsg = cmsg_nxthdr(msg, cmsg);
+        }
+    }
+    None
+}
+
+/// The send counter of an error-queue timestamp (`SOF_TIMESTAMPING_OPT_ID`).
+fn tx_stamp_id(msg: &libc::msghdr) -> Option<u32> {
+    unsafe {
+        let mut cmsg = cmsg_firsthdr(msg);
+        while !cmsg.is_null() {
+            let cmsg_ref = &*cmsg;
+            let recverr = (cmsg_ref.cmsg_level == libc::SOL_IP
+                && cmsg_ref.cmsg_type == libc::IP_RECVERR)
+                || (cmsg_ref.cmsg_level == libc::SOL_IPV6
+                    && cmsg_ref.cmsg_type == libc::IPV6_RECVERR);
+            if recverr {
+                let err = *(cmsg_data(cmsg) as *const libc::sock_extended_err);
+                if err.ee_errno == libc::ENOMSG as u32
+                    && err.ee_origin == libc::SO_EE_ORIGIN_TIMESTAMPING
+                {
+                    return Some(err.ee_data);
                 }
             }
             cmsg = cmsg_nxthdr(msg, cmsg);
@@ -357,7 +881,7 @@ fn recv_timestamp_ns(msg: &libc::msghdr) -> Option<u64> {
 }
 
 impl UdpProber {
-    fn recv_with_timestamp(&mut self) -> io::Result<(usize, u64)> {
+    fn recv_with_timestamp(&mut self) -> io::Result<(usize, Stamps, Option<Ecn>)> {
         unsafe {
             let mut iov = libc::iovec {
                 iov_base: self.recv_buf.as_mut_ptr() as *mut _,
@@ -366,42 +890,43 @@ impl UdpProber {
             let mut hdr: libc::msghdr = std::mem::zeroed();
             hdr.msg_iov = &mut iov;
             hdr.msg_iovlen = 1;
-            hdr.msg_control = self.cmsg_buf.as_mut_ptr() as *mut _;
-            hdr.msg_controllen = self.cmsg_buf.len();
+            hdr.msg_control = self.cmsg_buf.0.as_mut_ptr() as *mut _;
+            hdr.msg_controllen = self.cmsg_buf.0.len();
 
             let n = libc::recvmsg(self.socket.as_raw_fd(), &mut hdr, 0);
             if n < 0 {
                 return Err(io::Error::last_os_error());
             }
-            let ts = recv_timestamp_ns(&hdr)
-                .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "missing timestamp"))?;
-            Ok(n as usize, ts)
+            let stamps = recv_timestamps(&hdr);
+            if stamps.software_ns.is_none() && stamps.hardware_ns.is_none() {
+                return Err(io::Error::other("missing timestamp"));
+            }
+            Ok((n as usize, stamps, recv_ecn(&hdr)))
         }
     }
-}
 
-fn list_ifaces() -> io::Result<Vec<String>> {
-    let mut ifap: *mut libc::ifaddrs = std::ptr::null_mut();
-    let rv = unsafe { libc::getifaddrs(&mut ifap) };
-    if rv != 0 {
-        return Err(io::Error::last_os_error());
-    }
-    let mut out = Vec::new();
-    let mut cur = ifap;
-    unsafe {
-        while !cur.is_null() {
-            let ifa = &*cur;
-            if !ifa.ifa_name.is_null() {
-                let name = CStr::from_ptr(ifa.ifa_name)
-                    .to_string_lossy()
-                    .to_string();
-                out.push(name);
+    /// One transmit stamp off the error queue without waiting, with the
+    /// send counter it belongs to.
+    fn recv_tx_stamp(&mut self) -> Option<(u32, Stamps)> {
+        unsafe {
+            let mut iov = libc::iovec {
+                iov_base: self.recv_buf.as_mut_ptr() as *mut _,
+                iov_len: self.recv_buf.len(),
+            };
+            let mut hdr: libc::msghdr = std::mem::zeroed();
+            hdr.msg_iov = &mut iov;
+            hdr.msg_iovlen = 1;
+            hdr.msg_control = self.cmsg_buf.0.as_mut_ptr() as *mut _;
+            hdr.msg_controllen = self.cmsg_buf.0.len();
+
+            let flags = libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT;
+            if libc::recvmsg(self.socket.as_raw_fd(), &mut hdr, flags) < 0 {
+                return None;
             }
-            cur = ifa.ifa_next;
+            let id = tx_stamp_id(&hdr)?;
+            Some((id, recv_timestamps(&hdr)))
         }
-        libc::freeifaddrs(ifap);
     }
-    Ok(out)
 }
 
 fn has_non_loopback_addr(addr: *const libc::sockaddr) -> bool {
@@ -423,7 +948,8 @@ fn has_non_loopback_addr(addr: *const libc::sockaddr) -> bool {
     false
 }
 
-fn iface_for_ip(ip: IpAddr) -> Option<String> {
+/// The interface holding local address `ip`.
+pub fn iface_for_ip(ip: IpAddr) -> Option<String> {
     let mut ifap: *mut libc::ifaddrs = std::ptr::null_mut();
     let rv = unsafe { libc::getifaddrs(&mut ifap) };
     if rv != 0 {
@@ -442,28 +968,16 @@ fn iface_for_ip(ip: IpAddr) -> Option<String> {
             if sa_family == libc::AF_INET {
                 let sa = *(ifa.ifa_addr as *const libc::sockaddr_in);
                 let addr = IpAddr::V4(Ipv4Addr::from(u32::from_be(sa.sin_addr.s_addr)));
-                if addr == ip {
-                    if !ifa.ifa_name.is_null() {
-                        found = Some(
-                            CStr::from_ptr(ifa.ifa_name)
-                                .to_string_lossy()
-                                .to_string(),
-                        );
-                        break;
-                    }
+                if addr == ip && !ifa.ifa_name.is_null() {
+                    found = Some(CStr::from_ptr(ifa.ifa_name).to_string_lossy().to_string());
+                    break;
                 }
             } else if sa_family == libc::AF_INET6 {
                 let sa = *(ifa.ifa_addr as *const libc::sockaddr_in6);
                 let addr = IpAddr::V6(Ipv6Addr::from(sa.sin6_addr.s6_addr));
-                if addr == ip {
-                    if !ifa.ifa_name.is_null() {
-                        found = Some(
-                            CStr::from_ptr(ifa.ifa_name)
-                                .to_string_lossy()
-                                .to_string(),
-                        );
-                        break;
-                    }
+                if addr == ip && !ifa.ifa_name.is_null() {
+                    found = Some(CStr::from_ptr(ifa.ifa_name).to_string_lossy().to_string());
+                    break;
                 }
             }
             cur = ifa.ifa_next;
@@ -473,6 +987,8 @@ fn iface_for_ip(ip: IpAddr) -> Option<String> {
     found
 }
 
+// msghdr/cmsghdr length fields are socklen_t on some targets and usize on others.
+#[allow(clippy::unnecessary_cast)]
 unsafe fn cmsg_firsthdr(msg: &libc::msghdr) -> *mut libc::cmsghdr {
     if (msg.msg_controllen as usize) < std::mem::size_of::<libc::cmsghdr>() {
         std::ptr::null_mut()
@@ -481,6 +997,7 @@ unsafe fn cmsg_firsthdr(msg: &libc::msghdr) -> *mut libc::cmsghdr {
     }
 }
 
+#[allow(clippy::unnecessary_cast)]
 unsafe fn cmsg_nxthdr(msg: &libc::msghdr, cmsg: *const libc::cmsghdr) -> *mut libc::cmsghdr {
     let next = (cmsg as *const u8).add(cmsg_align((*cmsg).cmsg_len as usize));
     let end = (msg.msg_control as *const u8).add(msg.msg_controllen as usize);
@@ -499,3 +1016,139 @@ fn cmsg_align(len: usize) -> usize {
 unsafe fn cmsg_data(cmsg: *const libc::cmsghdr) -> *const u8 {
     (cmsg as *const u8).add(cmsg_align(std::mem::size_of::<libc::cmsghdr>()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lattice_core::build_packet;
+    use std::net::UdpSocket;
+    use std::thread;
+
+    /// A `msghdr` whose control buffer holds one message of `level`/`kind`
+    /// carrying `data`, laid out with libc's own `CMSG_*` macros.
+    fn control<T>(
+        buf: &mut CmsgBuf,
+        level: libc::c_int,
+        kind: libc::c_int,
+        data: T,
+    ) -> libc::msghdr {
+        let len = std::mem::size_of::<T>() as u32;
+        let mut hdr: libc::msghdr = unsafe { std::mem::zeroed() };
+        hdr.msg_control = buf.0.as_mut_ptr() as *mut _;
+        hdr.msg_controllen = unsafe { libc::CMSG_SPACE(len) } as _;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&hdr);
+            (*cmsg).cmsg_level = level;
+            (*cmsg).cmsg_type = kind;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(len) as _;
+            std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut T, data);
+        }
+        hdr
+    }
+
+    fn timespec(sec: i64, nsec: i64) -> libc::timespec {
+        libc::timespec {
+            tv_sec: sec as _,
+            tv_nsec: nsec as _,
+        }
+    }
+
+    #[test]
+    fn reads_software_and_nic_stamps() {
+        let mut buf = CmsgBuf([0; CMSG_BUF_LEN]);
+        let slots = [timespec(1_700_000_000, 5), timespec(0, 0), timespec(12, 34)];
+        let hdr = control(&mut buf, libc::SOL_SOCKET, libc::SCM_TIMESTAMPING, slots);
+        let stamps = recv_timestamps(&hdr);
+        assert_eq!(stamps.software_ns, Some(1_700_000_000_000_000_005));
+        assert_eq!(stamps.hardware_ns, Some(12_000_000_034));
+
+        // A zero slot is a stamp the kernel or NIC didn't take.
+        let slots = [timespec(3, 0), timespec(0, 0), timespec(0, 0)];
+        let hdr = control(&mut buf, libc::SOL_SOCKET, libc::SCM_TIMESTAMPING, slots);
+        let stamps = recv_timestamps(&hdr);
+        assert_eq!(stamps.software_ns, Some(3_000_000_000));
+        assert_eq!(stamps.hardware_ns, None);
+
+        let hdr = control(
+            &mut buf,
+            libc::SOL_SOCKET,
+            libc::SCM_TIMESTAMPNS,
+            timespec(2, 1),
+        );
+        let stamps = recv_timestamps(&hdr);
+        assert_eq!(stamps.software_ns, Some(2_000_000_001));
+        assert_eq!(stamps.hardware_ns, None);
+    }
+
+    #[test]
+    fn matches_transmit_stamps_by_send_counter() {
+        let mut buf = CmsgBuf([0; CMSG_BUF_LEN]);
+        let mut err: libc::sock_extended_err = unsafe { std::mem::zeroed() };
+        err.ee_errno = libc::ENOMSG as u32;
+        err.ee_origin = libc::SO_EE_ORIGIN_TIMESTAMPING;
+        err.ee_data = 41;
+        let hdr = control(&mut buf, libc::SOL_IP, libc::IP_RECVERR, err);
+        assert_eq!(tx_stamp_id(&hdr), Some(41));
+        let hdr = control(&mut buf, libc::SOL_IPV6, libc::IPV6_RECVERR, err);
+        assert_eq!(tx_stamp_id(&hdr), Some(41));
+
+        // An ICMP error on the same queue is not a stamp.
+        err.ee_errno = libc::ECONNREFUSED as u32;
+        err.ee_origin = libc::SO_EE_ORIGIN_ICMP;
+        let hdr = control(&mut buf, libc::SOL_IP, libc::IP_RECVERR, err);
+        assert_eq!(tx_stamp_id(&hdr), None);
+    }
+
+    #[test]
+    fn a_late_nic_stamp_fills_in_beside_the_software_one() {
+        let mut tx = Stamps {
+            software_ns: Some(10),
+            hardware_ns: None,
+        };
+        tx.merge(Stamps {
+            software_ns: Some(11),
+            hardware_ns: Some(9),
+        });
+        assert_eq!(tx.software_ns, Some(10));
+        assert_eq!(tx.hardware_ns, Some(9));
+    }
+
+    #[test]
+    fn times_from_whichever_clock_stamped_the_reply() {
+        let ms = 1_000_000;
+        let realtime = realtime_now_ns();
+        assert_eq!(choose_rtt_ns(realtime, realtime - 3 * ms, 0), Some(3 * ms));
+        let mono = monotonic_now_ns();
+        assert_eq!(choose_rtt_ns(mono, 0, mono - 5 * ms), Some(5 * ms));
+        // A stamp on neither clock, or an RTT past the cap, is no RTT.
+        assert_eq!(choose_rtt_ns(realtime / 2, 0, 0), None);
+        assert_eq!(choose_rtt_ns(realtime, realtime - MAX_RTT_NS - 1, 0), None);
+    }
+
+    #[test]
+    fn times_a_loopback_echo_from_the_kernel_send_stamp() {
+        let reflector = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = reflector.local_addr().unwrap().port();
+        let echo = thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            let (n, from) = reflector.recv_from(&mut buf).unwrap();
+            reflector.send_to(&buf[..n], from).unwrap();
+        });
+        let mut prober = UdpProber::new("127.0.0.1", port, None).unwrap();
+        let msg = build_packet(1, realtime_now_ns(), 7, b"0123456789abcdef");
+        let rtt_ns = prober
+            .send_and_receive_rtt(
+                &msg,
+                realtime_now_ns(),
+                monotonic_now_ns(),
+                Duration::from_secs(2),
+            )
+            .unwrap()
+            .expect("echo");
+        echo.join().unwrap();
+        assert!(rtt_ns < NS_PER_SEC);
+        // Loopback has no NIC to stamp in hardware, but the kernel stamps
+        // the send on its way out.
+        assert_eq!(prober.timestamp_source(), TimestampSource::KernelTx);
+    }
+}
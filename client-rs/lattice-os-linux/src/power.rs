@@ -0,0 +1,87 @@
+use lattice_core::PowerEvent;
+use std::io::{self, BufRead, BufReader};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Name recorded as the `source` of suspend/resume events.
+pub const POWER_SOURCE: &str = "logind";
+
+/// logind broadcasts `PrepareForSleep(true)` before suspending and
+/// `PrepareForSleep(false)` after waking.
+const PREPARE_FOR_SLEEP: &str = "PrepareForSleep";
+
+/// Follows logind's sleep signals on the system bus through `gdbus monitor`
+/// and calls `on_event` from a background thread. Fails when `gdbus` is not
+/// installed; the watch ends quietly if the bus goes away.
+pub fn watch_power(on_event: Box<dyn Fn(PowerEvent) + Send>) -> io::Result<()> {
+    let mut command = Command::new("gdbus");
+    command
+        .args([
+            "monitor",
+            "--system",
+            "--dest",
+            "org.freedesktop.login1",
+            "--object-path",
+            "/org/freedesktop/login1",
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    // Take the monitor down with the client instead of leaving it on the bus.
+    unsafe {
+        command.pre_exec(|| {
+            libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM);
+            Ok(())
+        });
+    }
+    let mut child = command.spawn()?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| io::Error::other("gdbus monitor has no stdout"))?;
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else { break };
+            if let Some(event) = parse_prepare_for_sleep(&line) {
+                on_event(event);
+            }
+        }
+        let _ = child.wait();
+    });
+    Ok(())
+}
+
+/// Reads one `gdbus monitor` line, e.g.
+/// `/org/freedesktop/login1: org.freedesktop.login1.Manager.PrepareForSleep (true,)`.
+fn parse_prepare_for_sleep(line: &str) -> Option<PowerEvent> {
+    let (_, args) = line.split_once(PREPARE_FOR_SLEEP)?;
+    match args
+        .trim_start()
+        .trim_start_matches('(')
+        .split(',')
+        .next()?
+    {
+        "true" => Some(PowerEvent::Suspending),
+        "false" => Some(PowerEvent::Resumed),
+        _ => None,
+    }
+}
+
+/// Total time spent suspended since boot: `CLOCK_BOOTTIME` keeps counting
+/// through suspend and `CLOCK_MONOTONIC` stops.
+pub fn suspended_ns() -> Option<u64> {
+    let boot = clock_ns(libc::CLOCK_BOOTTIME)?;
+    let mono = clock_ns(libc::CLOCK_MONOTONIC)?;
+    Some(boot.saturating_sub(mono))
+}
+
+fn clock_ns(clock: libc::clockid_t) -> Option<u64> {
+    unsafe {
+        let mut ts: libc::timespec = std::mem::zeroed();
+        if libc::clock_gettime(clock, &mut ts) != 0 {
+            return None;
+        }
+        Some((ts.tv_sec as u64) * 1_000_000_000u64 + (ts.tv_nsec as u64))
+    }
+}
@@ -1,15 +1,57 @@
+use lattice_core::{
+    parse_lato_packet, resolve_first_for_family, Ecn, Packet, ReflectorTiming, Secret,
+    TimestampSource, MAX_STRAYS,
+};
 use socket2::{Domain, Protocol, Socket, Type};
 use std::collections::HashMap;
 use std::ffi::CStr;
 use std::io;
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::time::{Duration, Instant};
 
+// IOKit, Network.framework, and the sleep-excluding clock exist only on
+// macOS; the crate itself still builds elsewhere as part of the workspace.
+#[cfg(target_os = "macos")]
+mod nwpath;
+#[cfg(target_os = "macos")]
+pub use nwpath::PathMonitor;
+#[cfg(target_os = "macos")]
+mod power;
+#[cfg(target_os = "macos")]
+pub use power::{suspended_ns, watch_power, POWER_SOURCE};
+mod relay;
+pub use lattice_core::{catch_shutdown, shutdown_requested};
+pub use relay::system_relay;
+
+const NS_PER_SEC: u64 = 1_000_000_000;
+const NS_PER_US: u64 = 1_000;
+/// A receive timestamp this close to a clock's current reading was taken on
+/// that clock.
+const CLOCK_MATCH_NS: u64 = 5 * NS_PER_SEC;
+/// Anything longer is a clock mix-up, not a round trip.
+const MAX_RTT_NS: u64 = 60 * NS_PER_SEC;
+const CMSG_BUF_LEN: usize = 256;
+/// IPv6 flow labels are 20 bits.
+pub const MAX_FLOW_LABEL: u32 = 0xF_FFFF;
+
+/// Control-message space for `recvmsg`. The kernel writes `cmsghdr`s into it,
+/// which need `size_t` alignment to be read in place.
+#[repr(C, align(8))]
+struct CmsgBuf([u8; CMSG_BUF_LEN]);
+
 pub struct UdpProber {
     socket: Socket,
     recv_buf: [u8; 2048],
-    cmsg_buf: [u8; 256],
+    cmsg_buf: CmsgBuf,
+    strays: Vec<Packet>,
+    /// Set for v2 probes: replies must carry a reply tag under it.
+    reply_secret: Option<Secret>,
+    /// The stamps on the last v2 reply, when it had any.
+    last_timing: Option<ReflectorTiming>,
+    /// The ECN field of the last echo, once `mark_ecn` asked for it.
+    last_ecn: Option<Ecn>,
+    last_source: TimestampSource,
 }
 
 #[derive(Debug, Clone)]
@@ -26,17 +68,39 @@ pub struct UtunReport {
     pub interfaces: Vec<UtunInterfaceInfo>,
 }
 
+/// Kernel NTP discipline state; fields are `None` when the platform does not expose them.
+#[derive(Debug, Clone, Default)]
+pub struct NtpReport {
+    pub synced: Option<bool>,
+    pub offset_ms: Option<f64>,
+    pub est_error_ms: Option<f64>,
+    pub source: Option<String>,
+}
+
 impl UdpProber {
     pub fn new(host: &str, port: u16, bind_ip: Option<IpAddr>) -> io::Result<Self> {
+        Self::with_source_port(host, port, bind_ip, 0)
+    }
+
+    /// Like `new`, but sends from `source_port` (0 lets the OS pick).
+    pub fn with_source_port(
+        host: &str,
+        port: u16,
+        bind_ip: Option<IpAddr>,
+        source_port: u16,
+    ) -> io::Result<Self> {
         let addr = resolve_first_for_family(host, port, bind_ip)?;
         let domain = match addr {
             SocketAddr::V4(_) => Domain::IPV4,
             SocketAddr::V6(_) => Domain::IPV6,
         };
         let socket = Socket::new(domain, Type::DGRAM, Some(Protocol::UDP))?;
-        if let Some(ip) = bind_ip {
-            let bind_addr = SocketAddr::new(ip, 0);
-            socket.bind(&bind_addr.into())?;
+        if bind_ip.is_some() || source_port != 0 {
+            let ip = bind_ip.unwrap_or(match addr {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            });
+            socket.bind(&SocketAddr::new(ip, source_port).into())?;
         }
         socket.connect(&addr.into())?;
 
@@ -45,17 +109,29 @@ impl UdpProber {
         Ok(Self {
             socket,
             recv_buf: [0u8; 2048],
-            cmsg_buf: [0u8; 256],
+            cmsg_buf: CmsgBuf([0u8; CMSG_BUF_LEN]),
+            strays: Vec::new(),
+            reply_secret: None,
+            last_timing: None,
+            last_ecn: None,
+            last_source: TimestampSource::Kernel,
         })
     }
 
+    /// Sends `msg` and waits for its echo. The round trip is in whole
+    /// nanoseconds, straight from the clock readings; callers convert to ms
+    /// only when they write it out.
     pub fn send_and_receive_rtt(
         &mut self,
         msg: &[u8],
         send_realtime_ns: u64,
         send_mono_ns: u64,
         timeout: Duration,
-    ) -> io::Result<Option<f64>> {
+    ) -> io::Result<Option<u64>> {
+        let probe =
+            parse_lato_packet(msg).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        self.last_timing = None;
+        self.last_ecn = None;
         let fd = self.socket.as_raw_fd();
         let send_instant = Instant::now();
         let sent = unsafe { libc::send(fd, msg.as_ptr() as *const _, msg.len(), 0) };
@@ -63,7 +139,7 @@ impl UdpProber {
             return Err(io::Error::last_os_error());
         }
         if sent as usize != msg.len() {
-            return Err(io::Error::new(io::ErrorKind::Other, "short send"));
+            return Err(io::Error::other("short send"));
         }
 
         let deadline = Instant::now() + timeout;
@@ -72,9 +148,7 @@ impl UdpProber {
             if now >= deadline {
                 return Ok(None);
             }
-            let remaining_ms = (deadline - now)
-                .as_millis()
-                .min(i32::MAX as u128) as i32;
+            let remaining_ms = (deadline - now).as_millis().min(i32::MAX as u128) as i32;
 
             let mut pfd = libc::pollfd {
                 fd,
@@ -92,36 +166,131 @@ impl UdpProber {
                 continue;
             }
 
-            let (n, recv_ns) = self.recv_with_timestamp()?;
-            if n != msg.len() {
-                continue;
-            }
-            if &self.recv_buf[..n] != msg {
-                continue;
-            }
+            let (n, recv_ns, ecn) = self.recv_with_timestamp()?;
+            // Anything on the wire may be hostile; only a well-formed echo of
+            // this probe counts.
+            let reply = match parse_lato_packet(&self.recv_buf[..n]) {
+                Ok(reply) if reply.echoes(&probe) && self.signed(&reply) => reply,
+                Ok(reply) => {
+                    if self.strays.len() < MAX_STRAYS {
+                        self.strays.push(reply);
+                    }
+                    continue;
+                }
+                Err(_) => continue,
+            };
             let recv_instant = Instant::now();
-            let fallback_rtt_ms = (recv_instant - send_instant).as_secs_f64() * 1000.0;
+            let fallback_rtt_ns =
+                u64::try_from((recv_instant - send_instant).as_nanos()).unwrap_or(u64::MAX);
 
-            let rtt_ms = choose_rtt_ms(recv_ns, send_realtime_ns, send_mono_ns)
-                .unwrap_or(fallback_rtt_ms);
-            return Ok(Some(rtt_ms));
+            let (rtt_ns, source) = match choose_rtt_ns(recv_ns, send_realtime_ns, send_mono_ns) {
+                Some(rtt_ns) => (rtt_ns, TimestampSource::Kernel),
+                None => (fallback_rtt_ns, TimestampSource::Userspace),
+            };
+            self.last_source = source;
+            self.last_ecn = ecn;
+            // Time the reflector sat on the probe is not distance.
+            self.last_timing = reply.reflector_timing(send_realtime_ns, rtt_ns);
+            return Ok(Some(rtt_ns - self.last_timing.map_or(0, |t| t.hold_ns)));
         }
     }
 
+    /// Where the last RTT came from. macOS has no hardware timestamps.
+    pub fn timestamp_source(&self) -> TimestampSource {
+        self.last_source
+    }
+
     pub fn iface_name(&self) -> io::Result<String> {
         let addr = self
             .socket
             .local_addr()?
             .as_socket()
-            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "non-IP socket"))?;
-        iface_for_ip(addr.ip()).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "iface not found"))
+            .ok_or_else(|| io::Error::other("non-IP socket"))?;
+        iface_for_ip(addr.ip())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "iface not found"))
     }
 
     pub fn local_addr(&self) -> io::Result<SocketAddr> {
         self.socket
             .local_addr()?
             .as_socket()
-            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "non-IP socket"))
+            .ok_or_else(|| io::Error::other("non-IP socket"))
+    }
+
+    /// Well-formed LATO packets that arrived while waiting for another probe's
+    /// echo, oldest first: mostly replies that missed their own timeout. They
+    /// are unverified; the caller checks their tags.
+    pub fn take_strays(&mut self) -> Vec<Packet> {
+        std::mem::take(&mut self.strays)
+    }
+
+    /// Sends `msg` without waiting for its echo, which turns up among the
+    /// strays later. For keepalives between bursts.
+    pub fn send_only(&mut self, msg: &[u8]) -> io::Result<()> {
+        let fd = self.socket.as_raw_fd();
+        if unsafe { libc::send(fd, msg.as_ptr() as *const _, msg.len(), 0) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Takes an echo only once its v2 reply tag checks out under `secret`,
+    /// so a copy of the probe bounced back by anyone on the path is a stray.
+    pub fn check_replies(&mut self, secret: Secret) {
+        self.reply_secret = Some(secret);
+    }
+
+    /// The reflector's stamps on the last echo, for v2 probes to a reflector
+    /// that sets them. The RTT returned with it already leaves out the hold.
+    pub fn reflector_timing(&self) -> Option<ReflectorTiming> {
+        self.last_timing
+    }
+
+    /// Sends later probes ECT(0) and has the kernel pass up the ECN field
+    /// of what comes back (`IP_RECVTOS`, `IPV6_RECVTCLASS`).
+    pub fn mark_ecn(&mut self) -> io::Result<()> {
+        let fd = self.socket.as_raw_fd();
+        let ect = libc::c_int::from(Ecn::Ect0.bits());
+        let on: libc::c_int = 1;
+        match self.socket.peer_addr()?.as_socket() {
+            Some(SocketAddr::V4(_)) => {
+                set_opt(fd, libc::IPPROTO_IP, libc::IP_TOS, &ect)?;
+                set_opt(fd, libc::IPPROTO_IP, libc::IP_RECVTOS, &on)
+            }
+            Some(SocketAddr::V6(_)) => {
+                set_opt(fd, libc::IPPROTO_IPV6, libc::IPV6_TCLASS, &ect)?;
+                set_opt(fd, libc::IPPROTO_IPV6, libc::IPV6_RECVTCLASS, &on)
+            }
+            None => Err(io::Error::other("non-IP socket")),
+        }
+    }
+
+    /// The ECN field the last echo arrived with, after `mark_ecn`.
+    pub fn reply_ecn(&self) -> Option<Ecn> {
+        self.last_ecn
+    }
+
+    fn signed(&self, reply: &Packet) -> bool {
+        self.reply_secret
+            .as_ref()
+            .is_none_or(|secret| reply.verify_reply(secret.as_bytes()))
+    }
+
+    /// Darwin picks a socket's IPv6 flow label itself and has no way to set
+    /// one per probe.
+    pub fn set_flow_label(&mut self, _label: u32) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "setting IPv6 flow labels is not supported on macOS",
+        ))
+    }
+
+    /// Address the host resolved to when the socket was connected.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.socket
+            .peer_addr()?
+            .as_socket()
+            .ok_or_else(|| io::Error::other("non-IP socket"))
     }
 }
 
@@ -141,6 +310,44 @@ pub fn iface_type(name: &str) -> String {
     "other".into()
 }
 
+/// Byte and packet counters for `name` from the `AF_LINK` entry's `if_data`.
+/// These are 32-bit; `IfaceCounters::since` allows for the wrap.
+#[cfg(target_os = "macos")]
+pub fn iface_counters(name: &str) -> Option<lattice_core::IfaceCounters> {
+    let mut ifap: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut ifap) } != 0 {
+        return None;
+    }
+    let mut found = None;
+    let mut cur = ifap;
+    unsafe {
+        while !cur.is_null() {
+            let ifa = &*cur;
+            cur = ifa.ifa_next;
+            if ifa.ifa_addr.is_null() || ifa.ifa_name.is_null() || ifa.ifa_data.is_null() {
+                continue;
+            }
+            if (*ifa.ifa_addr).sa_family as i32 != libc::AF_LINK {
+                continue;
+            }
+            if CStr::from_ptr(ifa.ifa_name).to_string_lossy() != name {
+                continue;
+            }
+            let data = &*(ifa.ifa_data as *const libc::if_data);
+            found = Some(lattice_core::IfaceCounters {
+                rx_bytes: data.ifi_ibytes as u64,
+                tx_bytes: data.ifi_obytes as u64,
+                rx_packets: data.ifi_ipackets as u64,
+                tx_packets: data.ifi_opackets as u64,
+                bits: u32::BITS,
+            });
+            break;
+        }
+        libc::freeifaddrs(ifap);
+    }
+    found
+}
+
 pub fn utun_present() -> bool {
     utun_report().present
 }
@@ -151,7 +358,7 @@ pub fn realtime_now_ns() -> u64 {
         if libc::gettimeofday(&mut tv, std::ptr::null_mut()) != 0 {
             return 0;
         }
-        (tv.tv_sec as u64) * 1_000_000_000u64 + (tv.tv_usec as u64) * 1_000u64
+        (tv.tv_sec as u64) * NS_PER_SEC + (tv.tv_usec as u64) * NS_PER_US
     }
 }
 
@@ -161,10 +368,22 @@ pub fn monotonic_now_ns() -> u64 {
         if libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) != 0 {
             return 0;
         }
-        (ts.tv_sec as u64) * 1_000_000_000u64 + (ts.tv_nsec as u64)
+        (ts.tv_sec as u64) * NS_PER_SEC + (ts.tv_nsec as u64)
     }
 }
 
+/// `realtime_now_ns` is backed by `gettimeofday`, so it only ever advances in
+/// whole microseconds.
+pub fn realtime_resolution_ns() -> Option<u64> {
+    Some(1_000)
+}
+
+/// macOS does not expose kernel NTP state through libc; callers fall back to
+/// `chronyc` when it is installed.
+pub fn ntp_report() -> NtpReport {
+    NtpReport::default()
+}
+
 pub fn utun_report() -> UtunReport {
     let mut map: HashMap<String, UtunInterfaceInfo> = HashMap::new();
     let mut ifap: *mut libc::ifaddrs = std::ptr::null_mut();
@@ -181,9 +400,7 @@ pub fn utun_report() -> UtunReport {
         while !cur.is_null() {
             let ifa = &*cur;
             if !ifa.ifa_name.is_null() {
-                let name = CStr::from_ptr(ifa.ifa_name)
-                    .to_string_lossy()
-                    .to_string();
+                let name = CStr::from_ptr(ifa.ifa_name).to_string_lossy().to_string();
                 if name.starts_with("utun") {
                     let entry = map.entry(name.clone()).or_insert(UtunInterfaceInfo {
                         name,
@@ -213,24 +430,22 @@ pub fn utun_report() -> UtunReport {
     }
 }
 
-fn choose_rtt_ms(recv_ns: u64, send_realtime_ns: u64, send_mono_ns: u64) -> Option<f64> {
-    const THRESH_NS: u64 = 5_000_000_000;
-
+/// Receive timestamp minus the send time on whichever clock the kernel
+/// stamped the reply with.
+fn choose_rtt_ns(recv_ns: u64, send_realtime_ns: u64, send_mono_ns: u64) -> Option<u64> {
     let now_realtime = realtime_now_ns();
-    if now_realtime > 0 && abs_diff(recv_ns, now_realtime) <= THRESH_NS {
-        let diff = recv_ns.saturating_sub(send_realtime_ns);
-        let rtt_ms = diff as f64 / 1_000_000.0;
-        if rtt_ms.is_finite() && rtt_ms <= 60_000.0 {
-            return Some(rtt_ms);
+    if now_realtime > 0 && abs_diff(recv_ns, now_realtime) <= CLOCK_MATCH_NS {
+        let rtt_ns = recv_ns.saturating_sub(send_realtime_ns);
+        if rtt_ns <= MAX_RTT_NS {
+            return Some(rtt_ns);
         }
     }
 
     let now_mono = monotonic_now_ns();
-    if now_mono > 0 && abs_diff(recv_ns, now_mono) <= THRESH_NS {
-        let diff = recv_ns.saturating_sub(send_mono_ns);
-        let rtt_ms = diff as f64 / 1_000_000.0;
-        if rtt_ms.is_finite() && rtt_ms <= 60_000.0 {
-            return Some(rtt_ms);
+    if now_mono > 0 && abs_diff(recv_ns, now_mono) <= CLOCK_MATCH_NS {
+        let rtt_ns = recv_ns.saturating_sub(send_mono_ns);
+        if rtt_ns <= MAX_RTT_NS {
+            return Some(rtt_ns);
         }
     }
 
@@ -238,33 +453,7 @@ fn choose_rtt_ms(recv_ns: u64, send_realtime_ns: u64, send_mono_ns: u64) -> Opti
 }
 
 fn abs_diff(a: u64, b: u64) -> u64 {
-    if a >= b { a - b } else { b - a }
-}
-
-fn resolve_first_for_family(
-    host: &str,
-    port: u16,
-    bind_ip: Option<IpAddr>,
-) -> io::Result<SocketAddr> {
-    let mut addrs = (host, port).to_socket_addrs()?;
-    if let Some(ip) = bind_ip {
-        let want_v4 = ip.is_ipv4();
-        for addr in addrs {
-            if want_v4 && matches!(addr, SocketAddr::V4(_)) {
-                return Ok(addr);
-            }
-            if !want_v4 && matches!(addr, SocketAddr::V6(_)) {
-                return Ok(addr);
-            }
-        }
-        return Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            "no resolved addresses for bind family",
-        ));
-    }
-    addrs
-        .next()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no resolved addresses"))
+    a.abs_diff(b)
 }
 
 pub fn iface_ips(name: &str) -> io::Result<Vec<IpAddr>> {
@@ -283,9 +472,7 @@ pub fn iface_ips(name: &str) -> io::Result<Vec<IpAddr>> {
                 continue;
             }
             if !ifa.ifa_name.is_null() {
-                let if_name = CStr::from_ptr(ifa.ifa_name)
-                    .to_string_lossy()
-                    .to_string();
+                let if_name = CStr::from_ptr(ifa.ifa_name).to_string_lossy().to_string();
                 if if_name == name {
                     let sa_family = (*ifa.ifa_addr).sa_family as i32;
                     if sa_family == libc::AF_INET {
@@ -306,6 +493,45 @@ pub fn iface_ips(name: &str) -> io::Result<Vec<IpAddr>> {
     Ok(out)
 }
 
+/// Every address of the interfaces that are up and running, loopback left
+/// out, by interface name.
+pub fn up_iface_ips() -> io::Result<Vec<(String, IpAddr)>> {
+    let mut ifap: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut ifap) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let up = (libc::IFF_UP | libc::IFF_RUNNING) as u32;
+    let mut out = Vec::new();
+    let mut cur = ifap;
+    unsafe {
+        while !cur.is_null() {
+            let ifa = &*cur;
+            cur = ifa.ifa_next;
+            if ifa.ifa_addr.is_null()
+                || ifa.ifa_name.is_null()
+                || ifa.ifa_flags as u32 & up != up
+                || ifa.ifa_flags as u32 & libc::IFF_LOOPBACK as u32 != 0
+            {
+                continue;
+            }
+            let name = CStr::from_ptr(ifa.ifa_name).to_string_lossy().to_string();
+            let sa_family = (*ifa.ifa_addr).sa_family as i32;
+            if sa_family == libc::AF_INET {
+                let sa = *(ifa.ifa_addr as *const libc::sockaddr_in);
+                out.push((
+                    name,
+                    IpAddr::V4(Ipv4Addr::from(u32::from_be(sa.sin_addr.s_addr))),
+                ));
+            } else if sa_family == libc::AF_INET6 {
+                let sa = *(ifa.ifa_addr as *const libc::sockaddr_in6);
+                out.push((name, IpAddr::V6(Ipv6Addr::from(sa.sin6_addr.s6_addr))));
+            }
+        }
+        libc::freeifaddrs(ifap);
+    }
+    Ok(out)
+}
+
 fn enable_rx_timestamping(fd: RawFd) -> io::Result<()> {
     let on: libc::c_int = 1;
     let rv = unsafe {
@@ -323,16 +549,54 @@ fn enable_rx_timestamping(fd: RawFd) -> io::Result<()> {
     Ok(())
 }
 
+fn set_opt<T>(fd: RawFd, level: libc::c_int, name: libc::c_int, value: &T) -> io::Result<()> {
+    let rv = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            value as *const T as *const _,
+            std::mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+    if rv != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The ECN bits of a received datagram's TOS byte or traffic class, when
+/// the socket asked for them. Darwin tags the IPv4 byte `IP_RECVTOS`.
+fn recv_ecn(msg: &libc::msghdr) -> Option<Ecn> {
+    unsafe {
+        let mut cmsg = cmsg_firsthdr(msg);
+        while !cmsg.is_null() {
+            let cmsg_ref = &*cmsg;
+            if cmsg_ref.cmsg_level == libc::IPPROTO_IP && cmsg_ref.cmsg_type == libc::IP_RECVTOS {
+                return Some(Ecn::from_tos(*cmsg_data(cmsg)));
+            }
+            if cmsg_ref.cmsg_level == libc::IPPROTO_IPV6 && cmsg_ref.cmsg_type == libc::IPV6_TCLASS
+            {
+                let tclass = *(cmsg_data(cmsg) as *const libc::c_int);
+                return Some(Ecn::from_tos(tclass as u8));
+            }
+            cmsg = cmsg_nxthdr(msg, cmsg);
+        }
+    }
+    None
+}
+
 fn recv_timestamp_ns(msg: &libc::msghdr) -> Option<u64> {
     unsafe {
         let mut cmsg = cmsg_firsthdr(msg);
         while !cmsg.is_null() {
             let cmsg_ref = &*cmsg;
-            if cmsg_ref.cmsg_level == libc::SOL_SOCKET && cmsg_ref.cmsg_type == libc::SCM_TIMESTAMP {
+            if cmsg_ref.cmsg_level == libc::SOL_SOCKET && cmsg_ref.cmsg_type == libc::SCM_TIMESTAMP
+            {
                 let data = cmsg_data(cmsg) as *const libc::timeval;
                 if !data.is_null() {
                     let tv = *data;
-                    return Some((tv.tv_sec as u64) * 1_000_000_000u64 + (tv.tv_usec as u64) * 1_000u64);
+                    return Some((tv.tv_sec as u64) * NS_PER_SEC + (tv.tv_usec as u64) * NS_PER_US);
                 }
             }
             cmsg = cmsg_nxthdr(msg, cmsg);
@@ -342,7 +606,7 @@ fn recv_timestamp_ns(msg: &libc::msghdr) -> Option<u64> {
 }
 
 impl UdpProber {
-    fn recv_with_timestamp(&mut self) -> io::Result<(usize, u64)> {
+    fn recv_with_timestamp(&mut self) -> io::Result<(usize, u64, Option<Ecn>)> {
         unsafe {
             let mut iov = libc::iovec {
                 iov_base: self.recv_buf.as_mut_ptr() as *mut _,
@@ -351,16 +615,16 @@ impl UdpProber {
             let mut hdr: libc::msghdr = std::mem::zeroed();
             hdr.msg_iov = &mut iov;
             hdr.msg_iovlen = 1;
-            hdr.msg_control = self.cmsg_buf.as_mut_ptr() as *mut _;
-            hdr.msg_controllen = self.cmsg_buf.len() as _;
+            hdr.msg_control = self.cmsg_buf.0.as_mut_ptr() as *mut _;
+            hdr.msg_controllen = self.cmsg_buf.0.len() as _;
 
             let n = libc::recvmsg(self.socket.as_raw_fd(), &mut hdr, 0);
             if n < 0 {
                 return Err(io::Error::last_os_error());
             }
-            let ts = recv_timestamp_ns(&hdr)
-                .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "missing timestamp"))?;
-            Ok((n as usize, ts))
+            let ts =
+                recv_timestamp_ns(&hdr).ok_or_else(|| io::Error::other("missing timestamp"))?;
+            Ok((n as usize, ts, recv_ecn(&hdr)))
         }
     }
 }
@@ -384,7 +648,8 @@ fn has_non_loopback_addr(addr: *const libc::sockaddr) -> bool {
     false
 }
 
-fn iface_for_ip(ip: IpAddr) -> Option<String> {
+/// The interface holding local address `ip`.
+pub fn iface_for_ip(ip: IpAddr) -> Option<String> {
     let mut ifap: *mut libc::ifaddrs = std::ptr::null_mut();
     let rv = unsafe { libc::getifaddrs(&mut ifap) };
     if rv != 0 {
@@ -403,28 +668,16 @@ fn iface_for_ip(ip: IpAddr) -> Option<String> {
             if sa_family == libc::AF_INET {
                 let sa = *(ifa.ifa_addr as *const libc::sockaddr_in);
                 let addr = IpAddr::V4(Ipv4Addr::from(u32::from_be(sa.sin_addr.s_addr)));
-                if addr == ip {
-                    if !ifa.ifa_name.is_null() {
-                        found = Some(
-                            CStr::from_ptr(ifa.ifa_name)
-                                .to_string_lossy()
-                                .to_string(),
-                        );
-                        break;
-                    }
+                if addr == ip && !ifa.ifa_name.is_null() {
+                    found = Some(CStr::from_ptr(ifa.ifa_name).to_string_lossy().to_string());
+                    break;
                 }
             } else if sa_family == libc::AF_INET6 {
                 let sa = *(ifa.ifa_addr as *const libc::sockaddr_in6);
                 let addr = IpAddr::V6(Ipv6Addr::from(sa.sin6_addr.s6_addr));
-                if addr == ip {
-                    if !ifa.ifa_name.is_null() {
-                        found = Some(
-                            CStr::from_ptr(ifa.ifa_name)
-                                .to_string_lossy()
-                                .to_string(),
-                        );
-                        break;
-                    }
+                if addr == ip && !ifa.ifa_name.is_null() {
+                    found = Some(CStr::from_ptr(ifa.ifa_name).to_string_lossy().to_string());
+                    break;
                 }
             }
             cur = ifa.ifa_next;
@@ -434,6 +687,8 @@ fn iface_for_ip(ip: IpAddr) -> Option<String> {
     found
 }
 
+// msghdr/cmsghdr length fields are socklen_t on some targets and usize on others.
+#[allow(clippy::unnecessary_cast)]
 unsafe fn cmsg_firsthdr(msg: &libc::msghdr) -> *mut libc::cmsghdr {
     if (msg.msg_controllen as usize) < std::mem::size_of::<libc::cmsghdr>() {
         std::ptr::null_mut()
@@ -442,6 +697,7 @@ unsafe fn cmsg_firsthdr(msg: &libc::msghdr) -> *mut libc::cmsghdr {
     }
 }
 
+#[allow(clippy::unnecessary_cast)]
 unsafe fn cmsg_nxthdr(msg: &libc::msghdr, cmsg: *const libc::cmsghdr) -> *mut libc::cmsghdr {
     let next = (cmsg as *const u8).add(cmsg_align((*cmsg).cmsg_len as usize));
     let end = (msg.msg_control as *const u8).add(msg.msg_controllen as usize);
@@ -460,3 +716,67 @@ fn cmsg_align(len: usize) -> usize {
 unsafe fn cmsg_data(cmsg: *const libc::cmsghdr) -> *const u8 {
     (cmsg as *const u8).add(cmsg_align(std::mem::size_of::<libc::cmsghdr>()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lattice_core::build_packet;
+    use std::net::UdpSocket;
+    use std::thread;
+
+    #[test]
+    fn reads_the_scm_timestamp_stamp() {
+        let mut buf = CmsgBuf([0; CMSG_BUF_LEN]);
+        let tv = libc::timeval {
+            tv_sec: 1_700_000_000,
+            tv_usec: 250,
+        };
+        let len = std::mem::size_of_val(&tv) as u32;
+        let mut hdr: libc::msghdr = unsafe { std::mem::zeroed() };
+        hdr.msg_control = buf.0.as_mut_ptr() as *mut _;
+        hdr.msg_controllen = unsafe { libc::CMSG_SPACE(len) } as _;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&hdr);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_TIMESTAMP;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(len) as _;
+            std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut libc::timeval, tv);
+        }
+        assert_eq!(recv_timestamp_ns(&hdr), Some(1_700_000_000_000_250_000));
+    }
+
+    #[test]
+    fn times_from_whichever_clock_stamped_the_reply() {
+        let ms = 1_000_000;
+        let realtime = realtime_now_ns();
+        assert_eq!(choose_rtt_ns(realtime, realtime - 3 * ms, 0), Some(3 * ms));
+        let mono = monotonic_now_ns();
+        assert_eq!(choose_rtt_ns(mono, 0, mono - 5 * ms), Some(5 * ms));
+        assert_eq!(choose_rtt_ns(realtime / 2, 0, 0), None);
+    }
+
+    #[test]
+    fn times_a_loopback_echo_on_kernel_stamps() {
+        let reflector = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = reflector.local_addr().unwrap().port();
+        let echo = thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            let (n, from) = reflector.recv_from(&mut buf).unwrap();
+            reflector.send_to(&buf[..n], from).unwrap();
+        });
+        let mut prober = UdpProber::new("127.0.0.1", port, None).unwrap();
+        let msg = build_packet(1, realtime_now_ns(), 7, b"0123456789abcdef");
+        let rtt_ns = prober
+            .send_and_receive_rtt(
+                &msg,
+                realtime_now_ns(),
+                monotonic_now_ns(),
+                Duration::from_secs(2),
+            )
+            .unwrap()
+            .expect("echo");
+        echo.join().unwrap();
+        assert!(rtt_ns < NS_PER_SEC);
+        assert_eq!(prober.timestamp_source(), TimestampSource::Kernel);
+    }
+}
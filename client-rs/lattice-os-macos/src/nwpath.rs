@@ -0,0 +1,208 @@
+use lattice_core::{NetworkPath, PathInterface};
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::ptr;
+
+type NwObject = *mut c_void;
+type DispatchQueue = *mut c_void;
+
+// Network/path.h, Network/interface.h
+const NW_PATH_STATUS_SATISFIED: i32 = 1;
+const NW_PATH_STATUS_UNSATISFIED: i32 = 2;
+const NW_PATH_STATUS_SATISFIABLE: i32 = 3;
+const NW_INTERFACE_TYPE_WIFI: i32 = 1;
+const NW_INTERFACE_TYPE_CELLULAR: i32 = 2;
+const NW_INTERFACE_TYPE_WIRED: i32 = 3;
+const NW_INTERFACE_TYPE_LOOPBACK: i32 = 4;
+
+#[link(name = "Network", kind = "framework")]
+extern "C" {
+    static _nw_parameters_configure_protocol_disable: *const c_void;
+    static _nw_parameters_configure_protocol_default_configuration: *const c_void;
+    fn nw_endpoint_create_host(hostname: *const c_char, port: *const c_char) -> NwObject;
+    fn nw_parameters_create_secure_udp(
+        configure_dtls: *const c_void,
+        configure_udp: *const c_void,
+    ) -> NwObject;
+    fn nw_parameters_set_local_endpoint(parameters: NwObject, local_endpoint: NwObject);
+    fn nw_connection_create(endpoint: NwObject, parameters: NwObject) -> NwObject;
+    fn nw_connection_set_queue(connection: NwObject, queue: DispatchQueue);
+    fn nw_connection_start(connection: NwObject);
+    fn nw_connection_cancel(connection: NwObject);
+    fn nw_connection_copy_current_path(connection: NwObject) -> NwObject;
+    fn nw_path_get_status(path: NwObject) -> i32;
+    fn nw_path_is_expensive(path: NwObject) -> bool;
+    fn nw_path_is_constrained(path: NwObject) -> bool;
+    fn nw_path_enumerate_interfaces(path: NwObject, enumerate_block: *mut InterfaceBlock);
+    fn nw_interface_get_name(interface: NwObject) -> *const c_char;
+    fn nw_interface_get_type(interface: NwObject) -> i32;
+    fn nw_release(obj: NwObject);
+}
+
+// libSystem: the block runtime and libdispatch.
+extern "C" {
+    static _NSConcreteStackBlock: [*const c_void; 32];
+    fn dispatch_queue_create(label: *const c_char, attr: *const c_void) -> DispatchQueue;
+    fn dispatch_release(object: DispatchQueue);
+}
+
+/// Block literal layout from the Clang block ABI.
+#[repr(C)]
+struct BlockDescriptor {
+    reserved: usize,
+    size: usize,
+}
+
+/// A `nw_path_enumerate_interfaces_block_t` that collects into `out`. The
+/// enumeration is synchronous, so the block can live on the stack and hold a
+/// plain pointer.
+#[repr(C)]
+struct InterfaceBlock {
+    isa: *const c_void,
+    flags: i32,
+    reserved: i32,
+    invoke: extern "C" fn(*mut InterfaceBlock, NwObject) -> bool,
+    descriptor: *const BlockDescriptor,
+    /// A `*mut Vec<PathInterface>`.
+    out: *mut c_void,
+}
+
+static INTERFACE_BLOCK_DESCRIPTOR: BlockDescriptor = BlockDescriptor {
+    reserved: 0,
+    size: std::mem::size_of::<InterfaceBlock>(),
+};
+
+extern "C" fn on_interface(block: *mut InterfaceBlock, interface: NwObject) -> bool {
+    let out = unsafe { &mut *((*block).out as *mut Vec<PathInterface>) };
+    let name = unsafe { nw_interface_get_name(interface) };
+    if !name.is_null() {
+        out.push(PathInterface {
+            iface_name: unsafe { CStr::from_ptr(name) }
+                .to_string_lossy()
+                .into_owned(),
+            kind: interface_type(unsafe { nw_interface_get_type(interface) }).to_string(),
+        });
+    }
+    true
+}
+
+/// Named like `iface_type` names interfaces.
+fn interface_type(nw_type: i32) -> &'static str {
+    match nw_type {
+        NW_INTERFACE_TYPE_WIFI => "wifi",
+        NW_INTERFACE_TYPE_CELLULAR => "cellular",
+        NW_INTERFACE_TYPE_WIRED => "ethernet",
+        NW_INTERFACE_TYPE_LOOPBACK => "loopback",
+        _ => "other",
+    }
+}
+
+fn path_status(status: i32) -> &'static str {
+    match status {
+        NW_PATH_STATUS_SATISFIED => "satisfied",
+        NW_PATH_STATUS_UNSATISFIED => "unsatisfied",
+        NW_PATH_STATUS_SATISFIABLE => "satisfiable",
+        _ => "invalid",
+    }
+}
+
+/// Network.framework's path to one destination from one local address.
+/// `NWPathMonitor` only follows the default route, so this holds a UDP
+/// connection, which gets the same evaluation for its own endpoint and keeps
+/// it current. Nothing is ever sent on it.
+pub struct PathMonitor {
+    connection: Owned,
+    queue: DispatchQueue,
+}
+
+impl PathMonitor {
+    pub fn new(destination: SocketAddr, bind_ip: Option<IpAddr>) -> io::Result<Self> {
+        let endpoint = host_endpoint(destination.ip(), destination.port())?;
+        let local = bind_ip.map(|ip| host_endpoint(ip, 0)).transpose()?;
+        unsafe {
+            let parameters = Owned::new(
+                nw_parameters_create_secure_udp(
+                    _nw_parameters_configure_protocol_disable,
+                    _nw_parameters_configure_protocol_default_configuration,
+                ),
+                "nw_parameters_create_secure_udp",
+            )?;
+            if let Some(local) = &local {
+                nw_parameters_set_local_endpoint(parameters.0, local.0);
+            }
+            let connection = Owned::new(
+                nw_connection_create(endpoint.0, parameters.0),
+                "nw_connection_create",
+            )?;
+            let queue = dispatch_queue_create(c"lattice.nwpath".as_ptr(), ptr::null());
+            nw_connection_set_queue(connection.0, queue);
+            nw_connection_start(connection.0);
+            Ok(PathMonitor { connection, queue })
+        }
+    }
+
+    /// The path as last evaluated; `None` until the first evaluation.
+    pub fn current(&self) -> Option<NetworkPath> {
+        unsafe {
+            let path = nw_connection_copy_current_path(self.connection.0);
+            if path.is_null() {
+                return None;
+            }
+            let mut interfaces = Vec::new();
+            let mut block = InterfaceBlock {
+                isa: _NSConcreteStackBlock.as_ptr() as *const c_void,
+                flags: 0,
+                reserved: 0,
+                invoke: on_interface,
+                descriptor: &INTERFACE_BLOCK_DESCRIPTOR,
+                out: &mut interfaces as *mut Vec<PathInterface> as *mut c_void,
+            };
+            nw_path_enumerate_interfaces(path, &mut block);
+            let out = NetworkPath {
+                status: path_status(nw_path_get_status(path)).to_string(),
+                expensive: nw_path_is_expensive(path),
+                constrained: nw_path_is_constrained(path),
+                interfaces,
+            };
+            nw_release(path);
+            Some(out)
+        }
+    }
+}
+
+impl Drop for PathMonitor {
+    fn drop(&mut self) {
+        unsafe {
+            nw_connection_cancel(self.connection.0);
+            dispatch_release(self.queue);
+        }
+    }
+}
+
+fn host_endpoint(ip: IpAddr, port: u16) -> io::Result<Owned> {
+    let host = CString::new(ip.to_string()).map_err(io::Error::other)?;
+    let port = CString::new(port.to_string()).map_err(io::Error::other)?;
+    Owned::new(
+        unsafe { nw_endpoint_create_host(host.as_ptr(), port.as_ptr()) },
+        "nw_endpoint_create_host",
+    )
+}
+
+/// A Network.framework object released on drop.
+struct Owned(NwObject);
+
+impl Owned {
+    fn new(obj: NwObject, made_by: &str) -> io::Result<Self> {
+        if obj.is_null() {
+            return Err(io::Error::other(format!("{made_by} failed")));
+        }
+        Ok(Owned(obj))
+    }
+}
+
+impl Drop for Owned {
+    fn drop(&mut self) {
+        unsafe { nw_release(self.0) };
+    }
+}
@@ -0,0 +1,121 @@
+use lattice_core::PowerEvent;
+use std::ffi::c_void;
+use std::io;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+/// Name recorded as the `source` of suspend/resume events.
+pub const POWER_SOURCE: &str = "iokit";
+
+// IOKit/IOMessage.h
+const IO_MESSAGE_CAN_SYSTEM_SLEEP: u32 = 0xE000_0270;
+const IO_MESSAGE_SYSTEM_WILL_SLEEP: u32 = 0xE000_0280;
+const IO_MESSAGE_SYSTEM_HAS_POWERED_ON: u32 = 0xE000_0300;
+
+type IoConnect = u32;
+type IoObject = u32;
+type NotificationPort = *mut c_void;
+type RunLoopSource = *mut c_void;
+type RunLoop = *mut c_void;
+type CfString = *const c_void;
+type InterestCallback = extern "C" fn(*mut c_void, IoObject, u32, *mut c_void);
+type OnEvent = Box<dyn Fn(PowerEvent) + Send>;
+
+#[link(name = "IOKit", kind = "framework")]
+extern "C" {
+    fn IORegisterForSystemPower(
+        refcon: *mut c_void,
+        port: *mut NotificationPort,
+        callback: InterestCallback,
+        notifier: *mut IoObject,
+    ) -> IoConnect;
+    fn IONotificationPortGetRunLoopSource(port: NotificationPort) -> RunLoopSource;
+    fn IOAllowPowerChange(kernel_port: IoConnect, notification_id: isize) -> i32;
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    static kCFRunLoopDefaultMode: CfString;
+    fn CFRunLoopGetCurrent() -> RunLoop;
+    fn CFRunLoopAddSource(rl: RunLoop, source: RunLoopSource, mode: CfString);
+    fn CFRunLoopRun();
+}
+
+/// Root power domain connection. Sleep is held up until every registered
+/// client acknowledges on it, so the callback must always answer.
+static ROOT_PORT: AtomicU32 = AtomicU32::new(0);
+
+fn allow_power_change(argument: *mut c_void) {
+    unsafe {
+        IOAllowPowerChange(ROOT_PORT.load(Ordering::SeqCst), argument as isize);
+    }
+}
+
+extern "C" fn on_power_message(
+    refcon: *mut c_void,
+    _service: IoObject,
+    message: u32,
+    argument: *mut c_void,
+) {
+    let on_event = unsafe { &*(refcon as *const OnEvent) };
+    match message {
+        IO_MESSAGE_CAN_SYSTEM_SLEEP => allow_power_change(argument),
+        IO_MESSAGE_SYSTEM_WILL_SLEEP => {
+            on_event(PowerEvent::Suspending);
+            allow_power_change(argument);
+        }
+        IO_MESSAGE_SYSTEM_HAS_POWERED_ON => on_event(PowerEvent::Resumed),
+        _ => {}
+    }
+}
+
+/// Registers for IOKit system power notifications on a dedicated run-loop
+/// thread and calls `on_event` from it. Call once per process.
+pub fn watch_power(on_event: OnEvent) -> io::Result<()> {
+    let (ready_tx, ready_rx) = mpsc::channel::<io::Result<()>>();
+    thread::spawn(move || {
+        let refcon = Box::into_raw(Box::new(on_event)) as *mut c_void;
+        let mut port: NotificationPort = ptr::null_mut();
+        let mut notifier: IoObject = 0;
+        let root =
+            unsafe { IORegisterForSystemPower(refcon, &mut port, on_power_message, &mut notifier) };
+        if root == 0 {
+            drop(unsafe { Box::from_raw(refcon as *mut OnEvent) });
+            let _ = ready_tx.send(Err(io::Error::other("IORegisterForSystemPower failed")));
+            return;
+        }
+        ROOT_PORT.store(root, Ordering::SeqCst);
+        unsafe {
+            CFRunLoopAddSource(
+                CFRunLoopGetCurrent(),
+                IONotificationPortGetRunLoopSource(port),
+                kCFRunLoopDefaultMode,
+            );
+        }
+        let _ = ready_tx.send(Ok(()));
+        unsafe { CFRunLoopRun() };
+    });
+    ready_rx
+        .recv()
+        .map_err(|_| io::Error::other("power notification thread exited"))?
+}
+
+/// Total time spent asleep since boot: `CLOCK_MONOTONIC_RAW` keeps counting
+/// through sleep and `CLOCK_UPTIME_RAW` stops.
+pub fn suspended_ns() -> Option<u64> {
+    let awake_or_asleep = clock_ns(libc::CLOCK_MONOTONIC_RAW)?;
+    let awake = clock_ns(libc::CLOCK_UPTIME_RAW)?;
+    Some(awake_or_asleep.saturating_sub(awake))
+}
+
+fn clock_ns(clock: libc::clockid_t) -> Option<u64> {
+    unsafe {
+        let mut ts: libc::timespec = std::mem::zeroed();
+        if libc::clock_gettime(clock, &mut ts) != 0 {
+            return None;
+        }
+        Some((ts.tv_sec as u64) * 1_000_000_000u64 + (ts.tv_nsec as u64))
+    }
+}
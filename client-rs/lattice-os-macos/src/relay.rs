@@ -0,0 +1,59 @@
+use lattice_core::PRIVATE_RELAY;
+use std::process::Command;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a check stays fresh; `lsof` is too slow to run every burst.
+const RELAY_RECHECK: Duration = Duration::from_secs(60);
+/// The per-user agent that carries Private Relay traffic to Apple's ingress
+/// proxies.
+const PRIVATE_RELAY_AGENT: &str = "networkserviceproxy";
+
+struct RelayCheck {
+    checked_at: Option<Instant>,
+    running: bool,
+    found: Option<&'static str>,
+}
+
+static RELAY_CHECK: Mutex<RelayCheck> = Mutex::new(RelayCheck {
+    checked_at: None,
+    running: false,
+    found: None,
+});
+
+/// A system relay that carries some of this host's traffic without a tunnel
+/// interface: iCloud Private Relay while its agent holds a QUIC flow (UDP
+/// 443) to the ingress proxies. The first call checks; later ones answer from
+/// the last check and refresh it in the background once it is stale.
+pub fn system_relay() -> Option<&'static str> {
+    let mut check = RELAY_CHECK.lock().unwrap_or_else(|e| e.into_inner());
+    match check.checked_at {
+        None => {
+            check.found = private_relay_connected().then_some(PRIVATE_RELAY);
+            check.checked_at = Some(Instant::now());
+        }
+        Some(at) if !check.running && at.elapsed() >= RELAY_RECHECK => {
+            check.running = true;
+            thread::spawn(|| {
+                let found = private_relay_connected().then_some(PRIVATE_RELAY);
+                let mut check = RELAY_CHECK.lock().unwrap_or_else(|e| e.into_inner());
+                *check = RelayCheck {
+                    checked_at: Some(Instant::now()),
+                    running: false,
+                    found,
+                };
+            });
+        }
+        Some(_) => {}
+    }
+    check.found
+}
+
+fn private_relay_connected() -> bool {
+    // lsof exits non-zero when nothing matches.
+    Command::new("lsof")
+        .args(["-nP", "-a", "-c", PRIVATE_RELAY_AGENT, "-i", "UDP:443"])
+        .output()
+        .is_ok_and(|out| out.status.success() && !out.stdout.is_empty())
+}
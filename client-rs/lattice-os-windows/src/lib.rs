@@ -0,0 +1,652 @@
+// Winsock and IP Helper exist only on Windows; the crate is empty elsewhere
+// so the workspace still builds on the other platforms.
+#![cfg(windows)]
+
+use lattice_core::{
+    parse_lato_packet, resolve_first_for_family, Ecn, IfaceCounters, Packet, ReflectorTiming,
+    Secret, TimestampSource, MAX_STRAYS,
+};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::process::Command;
+use std::ptr;
+use std::time::{Duration, Instant};
+use windows_sys::Win32::Foundation::{ERROR_BUFFER_OVERFLOW, ERROR_NO_DATA, FILETIME, NO_ERROR};
+use windows_sys::Win32::NetworkManagement::IpHelper::{
+    GetAdaptersAddresses, GetIfEntry2, GAA_FLAG_SKIP_ANYCAST, GAA_FLAG_SKIP_DNS_SERVER,
+    GAA_FLAG_SKIP_MULTICAST, IF_TYPE_ETHERNET_CSMACD, IF_TYPE_IEEE80211, IF_TYPE_PPP,
+    IF_TYPE_PROP_VIRTUAL, IF_TYPE_SOFTWARE_LOOPBACK, IF_TYPE_TUNNEL, IF_TYPE_WWANPP,
+    IF_TYPE_WWANPP2, IP_ADAPTER_ADDRESSES_LH, MIB_IF_ROW2,
+};
+use windows_sys::Win32::NetworkManagement::Ndis::{IfOperStatusUp, NET_LUID_LH, TUNNEL_TYPE_NONE};
+use windows_sys::Win32::Networking::WinSock::{
+    AF_INET, AF_INET6, AF_UNSPEC, SOCKADDR, SOCKADDR_IN, SOCKADDR_IN6,
+};
+use windows_sys::Win32::System::Performance::{QueryPerformanceCounter, QueryPerformanceFrequency};
+use windows_sys::Win32::System::SystemInformation::GetSystemTimePreciseAsFileTime;
+use windows_sys::Win32::System::Time::{
+    GetTimeZoneInformation, TIME_ZONE_ID_INVALID, TIME_ZONE_INFORMATION,
+};
+
+mod power;
+pub use power::{suspended_ns, watch_power, POWER_SOURCE};
+mod shutdown;
+pub use lattice_core::shutdown_requested;
+pub use shutdown::catch_shutdown;
+
+const NS_PER_SEC: u64 = 1_000_000_000;
+/// `FILETIME` counts 100 ns ticks.
+const NS_PER_FILETIME_TICK: u64 = 100;
+/// Seconds from the `FILETIME` epoch (1601-01-01) to the Unix epoch.
+const FILETIME_UNIX_EPOCH_S: u64 = 11_644_473_600;
+/// IPv6 flow labels are 20 bits.
+pub const MAX_FLOW_LABEL: u32 = 0xF_FFFF;
+/// First guess at the `GetAdaptersAddresses` buffer; Microsoft suggests
+/// 15 KB, and the call reports the size it wants when that is too small.
+const ADAPTER_BUF_LEN: u32 = 15 * 1024;
+/// Adapters can appear between the size query and the call that fills the
+/// buffer; give up after this many tries.
+const ADAPTER_TRIES: usize = 3;
+/// `GetTimeZoneInformation` result while daylight saving time is in effect
+/// (winnt.h, not in the `Time` bindings).
+const TIME_ZONE_ID_DAYLIGHT: u32 = 2;
+/// `w32tm` reports leap indicator 3 while the clock is unsynchronized.
+const W32TM_LEAP_UNSYNCED: &str = "3";
+
+// Windows has no interface flags word; `utun_report` builds one from the
+// adapter state with the BSD bit values, so callers decode it the same way
+// on every platform.
+pub const IFF_UP: i32 = 0x1;
+pub const IFF_LOOPBACK: i32 = 0x8;
+pub const IFF_POINTOPOINT: i32 = 0x10;
+pub const IFF_RUNNING: i32 = 0x40;
+pub const IFF_MULTICAST: i32 = 0x1000;
+/// `WSAENOBUFS`, what Winsock returns where Unix returns ENOBUFS.
+pub const ENOBUFS: i32 = 10055;
+
+/// No kernel receive timestamps on Winsock: the RTT runs from
+/// `send_mono_ns` to when the read returned, as with `TcpProber`.
+pub struct UdpProber {
+    socket: Socket,
+    recv_buf: [u8; 2048],
+    strays: Vec<Packet>,
+    /// Set for v2 probes: replies must carry a reply tag under it.
+    reply_secret: Option<Secret>,
+    /// The stamps on the last v2 reply, when it had any.
+    last_timing: Option<ReflectorTiming>,
+}
+
+#[derive(Debug, Clone)]
+pub struct UtunInterfaceInfo {
+    pub name: String,
+    pub flags: u32,
+    pub has_non_loopback_addr: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct UtunReport {
+    pub present: bool,
+    pub active: bool,
+    pub interfaces: Vec<UtunInterfaceInfo>,
+}
+
+/// Kernel NTP discipline state; fields are `None` when the platform does not expose them.
+#[derive(Debug, Clone, Default)]
+pub struct NtpReport {
+    pub synced: Option<bool>,
+    pub offset_ms: Option<f64>,
+    pub est_error_ms: Option<f64>,
+    pub source: Option<String>,
+}
+
+/// One entry from `GetAdaptersAddresses`, named by its friendly name
+/// ("Ethernet", "Wi-Fi"), which is what the client logs as `ifaceName`.
+struct Adapter {
+    name: String,
+    if_type: u32,
+    tunnel: bool,
+    up: bool,
+    luid: NET_LUID_LH,
+    addrs: Vec<IpAddr>,
+}
+
+impl UdpProber {
+    pub fn new(host: &str, port: u16, bind_ip: Option<IpAddr>) -> io::Result<Self> {
+        Self::with_source_port(host, port, bind_ip, 0)
+    }
+
+    /// Like `new`, but sends from `source_port` (0 lets the OS pick).
+    pub fn with_source_port(
+        host: &str,
+        port: u16,
+        bind_ip: Option<IpAddr>,
+        source_port: u16,
+    ) -> io::Result<Self> {
+        let addr = resolve_first_for_family(host, port, bind_ip)?;
+        let domain = match addr {
+            SocketAddr::V4(_) => Domain::IPV4,
+            SocketAddr::V6(_) => Domain::IPV6,
+        };
+        let socket = Socket::new(domain, Type::DGRAM, Some(Protocol::UDP))?;
+        if bind_ip.is_some() || source_port != 0 {
+            let ip = bind_ip.unwrap_or(match addr {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            });
+            socket.bind(&SocketAddr::new(ip, source_port).into())?;
+        }
+        socket.connect(&addr.into())?;
+
+        Ok(Self {
+            socket,
+            recv_buf: [0u8; 2048],
+            strays: Vec::new(),
+            reply_secret: None,
+            last_timing: None,
+        })
+    }
+
+    /// Sends `msg` and waits for its echo. The round trip is in whole
+    /// nanoseconds on the monotonic clock; callers convert to ms only when
+    /// they write it out.
+    pub fn send_and_receive_rtt(
+        &mut self,
+        msg: &[u8],
+        send_realtime_ns: u64,
+        send_mono_ns: u64,
+        timeout: Duration,
+    ) -> io::Result<Option<u64>> {
+        let probe =
+            parse_lato_packet(msg).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        self.last_timing = None;
+        let sent = self.socket.send(msg)?;
+        if sent != msg.len() {
+            return Err(io::Error::other("short send"));
+        }
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            self.socket.set_read_timeout(Some(deadline - now))?;
+            let n = match (&self.socket).read(&mut self.recv_buf) {
+                Ok(n) => n,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Ok(None)
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            let recv_mono_ns = monotonic_now_ns();
+            // Anything on the wire may be hostile; only a well-formed echo of
+            // this probe counts.
+            match parse_lato_packet(&self.recv_buf[..n]) {
+                Ok(reply) if reply.echoes(&probe) && self.signed(&reply) => {
+                    let rtt_ns = recv_mono_ns.saturating_sub(send_mono_ns);
+                    // Time the reflector sat on the probe is not distance.
+                    self.last_timing = reply.reflector_timing(send_realtime_ns, rtt_ns);
+                    return Ok(Some(rtt_ns - self.last_timing.map_or(0, |t| t.hold_ns)));
+                }
+                Ok(reply) => {
+                    if self.strays.len() < MAX_STRAYS {
+                        self.strays.push(reply);
+                    }
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    pub fn iface_name(&self) -> io::Result<String> {
+        iface_for_ip(self.local_addr()?.ip())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "iface not found"))
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket
+            .local_addr()?
+            .as_socket()
+            .ok_or_else(|| io::Error::other("non-IP socket"))
+    }
+
+    /// Well-formed LATO packets that arrived while waiting for another probe's
+    /// echo, oldest first: mostly replies that missed their own timeout. They
+    /// are unverified; the caller checks their tags.
+    pub fn take_strays(&mut self) -> Vec<Packet> {
+        std::mem::take(&mut self.strays)
+    }
+
+    /// Sends `msg` without waiting for its echo, which turns up among the
+    /// strays later. For keepalives between bursts.
+    pub fn send_only(&mut self, msg: &[u8]) -> io::Result<()> {
+        self.socket.send(msg)?;
+        Ok(())
+    }
+
+    /// Takes an echo only once its v2 reply tag checks out under `secret`,
+    /// so a copy of the probe bounced back by anyone on the path is a stray.
+    pub fn check_replies(&mut self, secret: Secret) {
+        self.reply_secret = Some(secret);
+    }
+
+    /// The reflector's stamps on the last echo, for v2 probes to a reflector
+    /// that sets them. The RTT returned with it already leaves out the hold.
+    pub fn reflector_timing(&self) -> Option<ReflectorTiming> {
+        self.last_timing
+    }
+
+    fn signed(&self, reply: &Packet) -> bool {
+        self.reply_secret
+            .as_ref()
+            .is_none_or(|secret| reply.verify_reply(secret.as_bytes()))
+    }
+
+    /// Winsock has no per-socket way to choose the IPv6 flow label.
+    pub fn set_flow_label(&mut self, _label: u32) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "setting IPv6 flow labels is not supported on Windows",
+        ))
+    }
+
+    /// Reading the ECN field of replies needs `WSARecvMsg`, which this
+    /// prober does not use.
+    pub fn mark_ecn(&mut self) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "ECN is not supported on Windows",
+        ))
+    }
+
+    pub fn reply_ecn(&self) -> Option<Ecn> {
+        None
+    }
+
+    /// Address the host resolved to when the socket was connected.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.socket
+            .peer_addr()?
+            .as_socket()
+            .ok_or_else(|| io::Error::other("non-IP socket"))
+    }
+
+    /// Every RTT here is two counter reads around the socket calls.
+    pub fn timestamp_source(&self) -> TimestampSource {
+        TimestampSource::Userspace
+    }
+}
+
+pub fn iface_type(name: &str) -> String {
+    let Some(adapter) = adapter(name) else {
+        return "other".into();
+    };
+    match adapter.if_type {
+        IF_TYPE_SOFTWARE_LOOPBACK => "loopback",
+        IF_TYPE_IEEE80211 => "wifi",
+        IF_TYPE_ETHERNET_CSMACD => "ethernet",
+        IF_TYPE_WWANPP | IF_TYPE_WWANPP2 => "cellular",
+        _ => "other",
+    }
+    .into()
+}
+
+/// Byte and packet counters for `name` from `GetIfEntry2`.
+pub fn iface_counters(name: &str) -> Option<IfaceCounters> {
+    let adapter = adapter(name)?;
+    let mut row: MIB_IF_ROW2 = unsafe { std::mem::zeroed() };
+    row.InterfaceLuid = adapter.luid;
+    if unsafe { GetIfEntry2(&mut row) } != NO_ERROR {
+        return None;
+    }
+    Some(IfaceCounters {
+        rx_bytes: row.InOctets,
+        tx_bytes: row.OutOctets,
+        rx_packets: row.InUcastPkts + row.InNUcastPkts,
+        tx_packets: row.OutUcastPkts + row.OutNUcastPkts,
+        bits: u64::BITS,
+    })
+}
+
+pub fn utun_present() -> bool {
+    utun_report().present
+}
+
+/// Wall clock from `GetSystemTimePreciseAsFileTime`.
+pub fn realtime_now_ns() -> u64 {
+    let mut ft = FILETIME {
+        dwLowDateTime: 0,
+        dwHighDateTime: 0,
+    };
+    unsafe { GetSystemTimePreciseAsFileTime(&mut ft) };
+    let ticks = (u64::from(ft.dwHighDateTime) << 32) | u64::from(ft.dwLowDateTime);
+    ticks
+        .saturating_mul(NS_PER_FILETIME_TICK)
+        .saturating_sub(FILETIME_UNIX_EPOCH_S * NS_PER_SEC)
+}
+
+/// The performance counter, which does not move with wall-clock steps.
+pub fn monotonic_now_ns() -> u64 {
+    let mut count: i64 = 0;
+    let mut freq: i64 = 0;
+    unsafe {
+        if QueryPerformanceCounter(&mut count) == 0 || QueryPerformanceFrequency(&mut freq) == 0 {
+            return 0;
+        }
+    }
+    if count < 0 || freq <= 0 {
+        return 0;
+    }
+    u64::try_from(count as u128 * NS_PER_SEC as u128 / freq as u128).unwrap_or(u64::MAX)
+}
+
+/// `realtime_now_ns` is backed by a `FILETIME`, so it only ever advances in
+/// 100 ns ticks.
+pub fn realtime_resolution_ns() -> Option<u64> {
+    Some(NS_PER_FILETIME_TICK)
+}
+
+/// Sync state of the Windows Time service from `w32tm /query /status`.
+/// Empty when the service is stopped or `w32tm` is missing.
+pub fn ntp_report() -> NtpReport {
+    let Ok(out) = Command::new("w32tm")
+        .args(["/query", "/status", "/verbose"])
+        .output()
+    else {
+        return NtpReport::default();
+    };
+    if !out.status.success() {
+        return NtpReport::default();
+    }
+    parse_w32tm_status(&String::from_utf8_lossy(&out.stdout))
+}
+
+/// Reads the `Leap Indicator`, `Phase Offset` and `Root Dispersion` lines;
+/// offsets are printed in seconds with an `s` suffix.
+fn parse_w32tm_status(text: &str) -> NtpReport {
+    let field = |key: &str| {
+        text.lines().find_map(|l| {
+            l.trim()
+                .strip_prefix(key)?
+                .trim()
+                .strip_prefix(':')
+                .map(str::trim)
+        })
+    };
+    let seconds_ms = |key: &str| -> Option<f64> {
+        let secs: f64 = field(key)?.trim_end_matches('s').parse().ok()?;
+        Some(secs * 1_000.0)
+    };
+    let Some(leap) = field("Leap Indicator") else {
+        return NtpReport::default();
+    };
+    NtpReport {
+        synced: Some(!leap.starts_with(W32TM_LEAP_UNSYNCED)),
+        offset_ms: seconds_ms("Phase Offset"),
+        est_error_ms: seconds_ms("Root Dispersion"),
+        source: Some("w32tm".to_string()),
+    }
+}
+
+/// Tunnel, PPP and virtual adapters: what VPN clients install on Windows.
+/// Adapters carry no flags word, so one is built from the operational state.
+pub fn utun_report() -> UtunReport {
+    let interfaces: Vec<UtunInterfaceInfo> = adapters()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|a| {
+            a.tunnel
+                || matches!(
+                    a.if_type,
+                    IF_TYPE_TUNNEL | IF_TYPE_PPP | IF_TYPE_PROP_VIRTUAL
+                )
+        })
+        .map(|a| {
+            let mut flags = IFF_POINTOPOINT as u32;
+            if a.up {
+                flags |= (IFF_UP | IFF_RUNNING) as u32;
+            }
+            UtunInterfaceInfo {
+                name: a.name,
+                flags,
+                has_non_loopback_addr: a.addrs.iter().any(|ip| !ip.is_loopback()),
+            }
+        })
+        .collect();
+    let active = interfaces.iter().any(|i| {
+        (i.flags & (IFF_UP as u32)) != 0
+            && (i.flags & (IFF_RUNNING as u32)) != 0
+            && i.has_non_loopback_addr
+    });
+    UtunReport {
+        present: !interfaces.is_empty(),
+        active,
+        interfaces,
+    }
+}
+
+/// Offset of local time from UTC right now, daylight saving included.
+pub fn utc_offset_minutes() -> Option<i32> {
+    let mut tz: TIME_ZONE_INFORMATION = unsafe { std::mem::zeroed() };
+    let id = unsafe { GetTimeZoneInformation(&mut tz) };
+    if id == TIME_ZONE_ID_INVALID {
+        return None;
+    }
+    // Bias is UTC minus local time.
+    let bias = if id == TIME_ZONE_ID_DAYLIGHT {
+        tz.Bias + tz.DaylightBias
+    } else {
+        tz.Bias + tz.StandardBias
+    };
+    Some(-bias)
+}
+
+pub fn iface_ips(name: &str) -> io::Result<Vec<IpAddr>> {
+    Ok(adapters()?
+        .into_iter()
+        .filter(|a| a.name == name)
+        .flat_map(|a| a.addrs)
+        .collect())
+}
+
+/// Windows has no system relay that moves egress without a tunnel interface.
+pub fn system_relay() -> Option<&'static str> {
+    None
+}
+
+/// Network.framework's per-destination path has no Windows counterpart.
+pub struct PathMonitor;
+
+impl PathMonitor {
+    pub fn new(_destination: SocketAddr, _bind_ip: Option<IpAddr>) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "path monitoring is not supported on Windows",
+        ))
+    }
+
+    pub fn current(&self) -> Option<lattice_core::NetworkPath> {
+        None
+    }
+}
+
+/// Every address of the adapters that are up, loopback and the adapters
+/// `utun_report` counts as tunnels left out, by adapter name.
+pub fn up_iface_ips() -> io::Result<Vec<(String, IpAddr)>> {
+    Ok(adapters()?
+        .into_iter()
+        .filter(|a| a.up && a.if_type != IF_TYPE_SOFTWARE_LOOPBACK)
+        .filter(|a| {
+            !(a.tunnel
+                || matches!(
+                    a.if_type,
+                    IF_TYPE_TUNNEL | IF_TYPE_PPP | IF_TYPE_PROP_VIRTUAL
+                ))
+        })
+        .flat_map(|a| {
+            let name = a.name;
+            a.addrs.into_iter().map(move |ip| (name.clone(), ip))
+        })
+        .collect())
+}
+
+/// The interface holding local address `ip`.
+pub fn iface_for_ip(ip: IpAddr) -> Option<String> {
+    adapters()
+        .ok()?
+        .into_iter()
+        .find(|a| a.addrs.contains(&ip))
+        .map(|a| a.name)
+}
+
+fn adapter(name: &str) -> Option<Adapter> {
+    adapters().ok()?.into_iter().find(|a| a.name == name)
+}
+
+/// Every adapter with its unicast addresses, IPv4 and IPv6.
+fn adapters() -> io::Result<Vec<Adapter>> {
+    let flags = GAA_FLAG_SKIP_ANYCAST | GAA_FLAG_SKIP_MULTICAST | GAA_FLAG_SKIP_DNS_SERVER;
+    let mut len = ADAPTER_BUF_LEN;
+    for _ in 0..ADAPTER_TRIES {
+        // u64 words keep the adapter structs, which hold pointers and
+        // 64-bit LUIDs, aligned.
+        let mut buf: Vec<u64> = vec![0; (len as usize).div_ceil(8)];
+        let head = buf.as_mut_ptr() as *mut IP_ADAPTER_ADDRESSES_LH;
+        let rv =
+            unsafe { GetAdaptersAddresses(AF_UNSPEC as u32, flags, ptr::null(), head, &mut len) };
+        match rv {
+            NO_ERROR => return Ok(unsafe { read_adapters(head) }),
+            ERROR_NO_DATA => return Ok(Vec::new()),
+            ERROR_BUFFER_OVERFLOW => continue,
+            _ => return Err(io::Error::from_raw_os_error(rv as i32)),
+        }
+    }
+    Err(io::Error::other("adapter list kept growing"))
+}
+
+unsafe fn read_adapters(head: *const IP_ADAPTER_ADDRESSES_LH) -> Vec<Adapter> {
+    let mut out = Vec::new();
+    let mut cur = head;
+    while !cur.is_null() {
+        let a = &*cur;
+        let mut addrs = Vec::new();
+        let mut unicast = a.FirstUnicastAddress;
+        while !unicast.is_null() {
+            if let Some(ip) = sockaddr_ip((*unicast).Address.lpSockaddr) {
+                addrs.push(ip);
+            }
+            unicast = (*unicast).Next;
+        }
+        out.push(Adapter {
+            name: wide_to_string(a.FriendlyName),
+            if_type: a.IfType,
+            tunnel: a.TunnelType != TUNNEL_TYPE_NONE,
+            up: a.OperStatus == IfOperStatusUp,
+            luid: a.Luid,
+            addrs,
+        });
+        cur = a.Next;
+    }
+    out
+}
+
+unsafe fn sockaddr_ip(sa: *const SOCKADDR) -> Option<IpAddr> {
+    if sa.is_null() {
+        return None;
+    }
+    match (*sa).sa_family {
+        AF_INET => {
+            let sin = &*(sa as *const SOCKADDR_IN);
+            Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(
+                sin.sin_addr.S_un.S_addr,
+            ))))
+        }
+        AF_INET6 => {
+            let sin6 = &*(sa as *const SOCKADDR_IN6);
+            Some(IpAddr::V6(Ipv6Addr::from(sin6.sin6_addr.u.Byte)))
+        }
+        _ => None,
+    }
+}
+
+unsafe fn wide_to_string(s: *const u16) -> String {
+    if s.is_null() {
+        return String::new();
+    }
+    let len = (0..).take_while(|&i| *s.add(i) != 0).count();
+    String::from_utf16_lossy(std::slice::from_raw_parts(s, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lattice_core::build_packet;
+    use std::net::UdpSocket;
+    use std::thread;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn parses_w32tm_status() {
+        let text = "Leap Indicator: 0(no warning)\r\n\
+                    Stratum: 4 (secondary reference - syncd by (S)NTP)\r\n\
+                    Root Dispersion: 0.2500000s\r\n\
+                    Phase Offset: -0.5000000s\r\n";
+        let report = parse_w32tm_status(text);
+        assert_eq!(report.synced, Some(true));
+        assert_eq!(report.offset_ms, Some(-500.0));
+        assert_eq!(report.est_error_ms, Some(250.0));
+        assert_eq!(report.source.as_deref(), Some("w32tm"));
+
+        let unsynced = parse_w32tm_status("Leap Indicator: 3(not synchronized)\r\n");
+        assert_eq!(unsynced.synced, Some(false));
+        assert_eq!(unsynced.offset_ms, None);
+        // Not a w32tm status at all, e.g. the service is stopped.
+        assert_eq!(
+            parse_w32tm_status("The service has not been started.").synced,
+            None
+        );
+    }
+
+    #[test]
+    fn clocks_read_unix_time_and_count_up() {
+        let system_ns = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        assert!(realtime_now_ns().abs_diff(system_ns) < NS_PER_SEC);
+        let before = monotonic_now_ns();
+        thread::sleep(Duration::from_millis(5));
+        assert!(monotonic_now_ns() >= before + 5_000_000);
+    }
+
+    #[test]
+    fn times_a_loopback_echo_on_the_performance_counter() {
+        let reflector = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = reflector.local_addr().unwrap().port();
+        let echo = thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            let (n, from) = reflector.recv_from(&mut buf).unwrap();
+            reflector.send_to(&buf[..n], from).unwrap();
+        });
+        let mut prober = UdpProber::new("127.0.0.1", port, None).unwrap();
+        let msg = build_packet(1, realtime_now_ns(), 7, b"0123456789abcdef");
+        let rtt_ns = prober
+            .send_and_receive_rtt(
+                &msg,
+                realtime_now_ns(),
+                monotonic_now_ns(),
+                Duration::from_secs(2),
+            )
+            .unwrap()
+            .expect("echo");
+        echo.join().unwrap();
+        assert!(rtt_ns < NS_PER_SEC);
+    }
+}
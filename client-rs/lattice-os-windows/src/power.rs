@@ -0,0 +1,87 @@
+use lattice_core::PowerEvent;
+use std::ffi::c_void;
+use std::io;
+use std::ptr;
+use std::sync::mpsc;
+use std::thread;
+use windows_sys::Win32::Foundation::{HANDLE, NO_ERROR};
+use windows_sys::Win32::System::Power::{
+    PowerRegisterSuspendResumeNotification, DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS,
+};
+use windows_sys::Win32::System::SystemInformation::GetTickCount64;
+use windows_sys::Win32::System::WindowsProgramming::QueryUnbiasedInterruptTime;
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    DEVICE_NOTIFY_CALLBACK, PBT_APMRESUMEAUTOMATIC, PBT_APMSUSPEND,
+};
+
+/// Name recorded as the `source` of suspend/resume events.
+pub const POWER_SOURCE: &str = "powrprof";
+
+const NS_PER_MS: u64 = 1_000_000;
+/// `QueryUnbiasedInterruptTime` counts 100 ns ticks.
+const NS_PER_INTERRUPT_TICK: u64 = 100;
+
+type OnEvent = Box<dyn Fn(PowerEvent) + Send>;
+
+/// Registers for suspend/resume notifications with the power manager and
+/// calls `on_event` from a background thread. Windows delivers them on its
+/// own thread, so they are passed along over a channel. The registration
+/// lives as long as the process.
+pub fn watch_power(on_event: OnEvent) -> io::Result<()> {
+    let (tx, rx) = mpsc::channel::<PowerEvent>();
+    // Both are read by the callback until the process exits.
+    let context = Box::into_raw(Box::new(tx));
+    let params = Box::into_raw(Box::new(DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS {
+        Callback: Some(on_power),
+        Context: context as *mut c_void,
+    }));
+    let mut registration: *mut c_void = ptr::null_mut();
+    let rv = unsafe {
+        PowerRegisterSuspendResumeNotification(
+            DEVICE_NOTIFY_CALLBACK,
+            params as HANDLE,
+            &mut registration,
+        )
+    };
+    if rv != NO_ERROR {
+        unsafe {
+            drop(Box::from_raw(params));
+            drop(Box::from_raw(context));
+        }
+        return Err(io::Error::from_raw_os_error(rv as i32));
+    }
+    thread::spawn(move || {
+        for event in rx {
+            on_event(event);
+        }
+    });
+    Ok(())
+}
+
+/// `PBT_APMRESUMESUSPEND` follows the automatic resume only when a user is
+/// present, so the automatic one alone marks every wake.
+unsafe extern "system" fn on_power(
+    context: *const c_void,
+    kind: u32,
+    _setting: *const c_void,
+) -> u32 {
+    let tx = &*(context as *const mpsc::Sender<PowerEvent>);
+    let event = match kind {
+        PBT_APMSUSPEND => PowerEvent::Suspending,
+        PBT_APMRESUMEAUTOMATIC => PowerEvent::Resumed,
+        _ => return NO_ERROR,
+    };
+    let _ = tx.send(event);
+    NO_ERROR
+}
+
+/// Total time spent suspended since boot: the tick count keeps running
+/// through sleep and the unbiased interrupt time stops.
+pub fn suspended_ns() -> Option<u64> {
+    let mut unbiased: u64 = 0;
+    if unsafe { QueryUnbiasedInterruptTime(&mut unbiased) } == 0 {
+        return None;
+    }
+    let since_boot = unsafe { GetTickCount64() }.saturating_mul(NS_PER_MS);
+    Some(since_boot.saturating_sub(unbiased.saturating_mul(NS_PER_INTERRUPT_TICK)))
+}
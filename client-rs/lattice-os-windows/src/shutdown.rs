@@ -0,0 +1,24 @@
+use lattice_core::request_shutdown;
+use std::io;
+use windows_sys::Win32::Foundation::{BOOL, FALSE, TRUE};
+use windows_sys::Win32::System::Console::{
+    SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_CLOSE_EVENT, CTRL_C_EVENT,
+};
+
+/// Runs on a thread Windows creates for the event. Declining a second event
+/// passes it to the default handler, which ends the process.
+unsafe extern "system" fn on_ctrl(event: u32) -> BOOL {
+    match event {
+        CTRL_C_EVENT | CTRL_BREAK_EVENT | CTRL_CLOSE_EVENT if request_shutdown() => TRUE,
+        _ => FALSE,
+    }
+}
+
+/// Turns the first Ctrl-C, Ctrl-Break, or console close into a request that
+/// `shutdown_requested` reports. A second one ends the process as before.
+pub fn catch_shutdown() -> io::Result<()> {
+    if unsafe { SetConsoleCtrlHandler(Some(on_ctrl), TRUE) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
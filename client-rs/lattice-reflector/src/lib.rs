@@ -0,0 +1,554 @@
+//! Reflector deployment tooling, shared by the `lattice-reflector` binary and
+//! `lattice reflect`.
+
+use clap::{Parser, Subcommand};
+use lattice_core::{
+    bytes_to_hex, cloud_region, hex_to_bytes, CloudRegion, CLOUD_REGIONS, MIN_SECRET_BYTES,
+    SECRET_BYTES,
+};
+use rand::RngCore;
+use serde_json::json;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const REFLECTOR_PORT: u16 = 9000;
+const DEFAULT_REPO: &str = "https://github.com/svdrecbd/LATTICE.git";
+const DEFAULT_ID_PREFIX: &str = "lattice";
+const HOST_PLACEHOLDER: &str = "REPLACE_WITH_IP_OR_DNS";
+
+#[derive(Parser, Debug)]
+#[command(about = "LATTICE reflector deployment tooling")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Emit cloud-init user-data (and optionally Terraform) for a set of reflector regions,
+    /// plus the matching client endpoints block.
+    Bootstrap(BootstrapArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct BootstrapArgs {
+    /// Cloud provider: aws, gcp, azure, or do.
+    #[arg(long)]
+    provider: String,
+
+    /// Comma-separated provider region codes (e.g. us-east-1,eu-west-1).
+    #[arg(long, value_delimiter = ',', required = true)]
+    regions: Vec<String>,
+
+    /// Shared secret to deploy; a fresh 32-byte secret is generated when omitted.
+    #[arg(long)]
+    secret_hex: Option<String>,
+
+    /// Endpoint id prefix; ids become `<prefix>-<region>`.
+    #[arg(long, default_value = DEFAULT_ID_PREFIX)]
+    id_prefix: String,
+
+    /// Source CIDR allowed to reach port 9000, UDP and TCP (host firewall and Terraform security rules).
+    #[arg(long)]
+    allow_cidr: Option<String>,
+
+    /// Git repository the instances build the reflector (`lattice-server`) from.
+    #[arg(long, default_value = DEFAULT_REPO)]
+    repo: String,
+
+    /// Also emit a Terraform file (aws, gcp, do).
+    #[arg(long)]
+    terraform: bool,
+
+    /// Write files into this directory instead of printing them.
+    #[arg(long)]
+    out_dir: Option<PathBuf>,
+}
+
+/// Runs the reflector tooling CLI; `argv[0]` is the program name shown in help.
+pub fn run(argv: &[String]) -> io::Result<()> {
+    let cli = Cli::parse_from(argv);
+    match cli.command {
+        Command::Bootstrap(args) => bootstrap(args),
+    }
+}
+
+fn bootstrap(args: BootstrapArgs) -> io::Result<()> {
+    let provider = args.provider.to_ascii_lowercase();
+    let mut regions: Vec<&'static CloudRegion> = Vec::new();
+    for code in &args.regions {
+        let region = cloud_region(Some(&provider), code.trim()).ok_or_else(|| {
+            let known: Vec<&str> = CLOUD_REGIONS
+                .iter()
+                .filter(|r| r.provider == provider)
+                .map(|r| r.code)
+                .collect();
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "unknown {} region '{}' (known: {})",
+                    provider,
+                    code,
+                    known.join(", ")
+                ),
+            )
+        })?;
+        regions.push(region);
+    }
+
+    let secret_hex = match &args.secret_hex {
+        Some(s) => {
+            let bytes =
+                hex_to_bytes(s).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            if bytes.len() < MIN_SECRET_BYTES {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("secretHex must be at least {MIN_SECRET_BYTES} bytes"),
+                ));
+            }
+            s.trim().to_ascii_lowercase()
+        }
+        None => {
+            let mut bytes = [0u8; SECRET_BYTES];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            bytes_to_hex(&bytes)
+        }
+    };
+
+    let user_data = cloud_init(&secret_hex, &args.repo, args.allow_cidr.as_deref());
+    let endpoints = endpoints_block(&secret_hex, &args.id_prefix, &regions);
+    let terraform = if args.terraform {
+        Some(terraform(&provider, &regions, args.allow_cidr.as_deref())?)
+    } else {
+        None
+    };
+
+    if args.allow_cidr.is_none() {
+        eprintln!(
+            "[!] no --allow-cidr given; port {} will be open to any source",
+            REFLECTOR_PORT
+        );
+    }
+
+    match &args.out_dir {
+        Some(dir) => {
+            fs::create_dir_all(dir)?;
+            fs::write(dir.join("user-data.yaml"), &user_data)?;
+            fs::write(dir.join("endpoints.json"), &endpoints)?;
+            if let Some(tf) = &terraform {
+                fs::write(dir.join("main.tf"), tf)?;
+            }
+            println!("Wrote reflector bootstrap files to {}", dir.display());
+            println!(
+                "  user-data.yaml and endpoints.json contain the shared secret; keep them private."
+            );
+            println!(
+                "  Replace {} in endpoints.json with each instance's address.",
+                HOST_PLACEHOLDER
+            );
+        }
+        None => {
+            println!("# ---- user-data.yaml ----");
+            println!("{user_data}");
+            println!("# ---- endpoints.json ----");
+            println!("{endpoints}");
+            if let Some(tf) = &terraform {
+                println!("# ---- main.tf ----");
+                println!("{tf}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// User-data that builds `lattice-server` from `repo` and runs it as the
+/// `lattice-reflector` service. It is the reflector that speaks v2; the Go
+/// server in `server/` answers v1 only.
+fn cloud_init(secret_hex: &str, repo: &str, allow_cidr: Option<&str>) -> String {
+    let firewall = match allow_cidr {
+        Some(cidr) => format!(
+            "  - ufw allow proto udp from {cidr} to any port {port}\n  - ufw allow proto tcp from {cidr} to any port {port}\n  - ufw allow OpenSSH\n  - ufw --force enable\n",
+            cidr = cidr,
+            port = REFLECTOR_PORT
+        ),
+        None => String::new(),
+    };
+    format!(
+        r#"#cloud-config
+package_update: true
+packages:
+  - build-essential
+  - curl
+  - git
+  - ufw
+write_files:
+  - path: /etc/lattice/reflector.env
+    permissions: "0600"
+    content: |
+      LATTICE_SECRET_HEX={secret}
+  - path: /etc/systemd/system/lattice-reflector.service
+    content: |
+      [Unit]
+      Description=LATTICE reflector
+      After=network-online.target
+      Wants=network-online.target
+
+      [Service]
+      EnvironmentFile=/etc/lattice/reflector.env
+      ExecStart=/usr/local/bin/lattice-server
+      Restart=always
+      DynamicUser=yes
+
+      [Install]
+      WantedBy=multi-user.target
+runcmd:
+  - git clone --depth 1 {repo} /opt/lattice
+  - curl -sSf https://sh.rustup.rs | HOME=/root sh -s -- -y --profile minimal
+  - cd /opt/lattice/client-rs && HOME=/root /root/.cargo/bin/cargo build --release -p lattice-server
+  - install -m 0755 /opt/lattice/client-rs/target/release/lattice-server /usr/local/bin/lattice-server
+{firewall}  - systemctl daemon-reload
+  - systemctl enable --now lattice-reflector
+"#,
+        secret = secret_hex,
+        repo = repo,
+        firewall = firewall,
+    )
+}
+
+fn endpoints_block(secret_hex: &str, id_prefix: &str, regions: &[&CloudRegion]) -> String {
+    let endpoints: Vec<serde_json::Value> = regions
+        .iter()
+        .map(|r| {
+            json!({
+                "id": format!("{}-{}", id_prefix, r.code),
+                "host": HOST_PLACEHOLDER,
+                "port": REFLECTOR_PORT,
+                "regionHint": r.code,
+                "packetVersion": 2,
+                "lat": r.lat,
+                "lon": r.lon,
+                "consented": true,
+            })
+        })
+        .collect();
+    let block = json!({
+        "secretHex": secret_hex,
+        "endpoints": endpoints,
+    });
+    serde_json::to_string_pretty(&block).unwrap_or_default()
+}
+
+/// TCP the build needs out of a droplet: apt over HTTP, git and crates over
+/// HTTPS, and DNS that falls back to TCP.
+const BUILD_TCP_PORTS: [u16; 3] = [80, 443, 53];
+
+fn do_tcp_outbound() -> String {
+    BUILD_TCP_PORTS
+        .iter()
+        .map(|port| {
+            format!(
+                "  outbound_rule {{\n    protocol              = \"tcp\"\n    port_range            = \"{port}\"\n    destination_addresses = [\"0.0.0.0/0\", \"::/0\"]\n  }}\n"
+            )
+        })
+        .collect()
+}
+
+fn tf_name(code: &str) -> String {
+    code.replace('-', "_")
+}
+
+fn terraform(
+    provider: &str,
+    regions: &[&CloudRegion],
+    allow_cidr: Option<&str>,
+) -> io::Result<String> {
+    let cidr = allow_cidr.unwrap_or("0.0.0.0/0");
+    let mut out = format!(
+        "# Generated by lattice-reflector bootstrap. Expects user-data.yaml next to this file.\n\nvariable \"allow_cidr\" {{\n  default = \"{}\"\n}}\n\n",
+        cidr
+    );
+    match provider {
+        "aws" => {
+            out.push_str("variable \"instance_type\" {\n  default = \"t3.micro\"\n}\n");
+            for r in regions {
+                let n = tf_name(r.code);
+                out.push_str(&format!(
+                    r#"
+provider "aws" {{
+  alias  = "{n}"
+  region = "{code}"
+}}
+
+data "aws_ami" "ubuntu_{n}" {{
+  provider    = aws.{n}
+  most_recent = true
+  owners      = ["099720109477"]
+  filter {{
+    name   = "name"
+    values = ["ubuntu/images/hvm-ssd/ubuntu-jammy-22.04-amd64-server-*"]
+  }}
+}}
+
+resource "aws_security_group" "lattice_{n}" {{
+  provider = aws.{n}
+  name     = "lattice-reflector"
+  ingress {{
+    from_port   = {port}
+    to_port     = {port}
+    protocol    = "udp"
+    cidr_blocks = [var.allow_cidr]
+  }}
+  ingress {{
+    from_port   = {port}
+    to_port     = {port}
+    protocol    = "tcp"
+    cidr_blocks = [var.allow_cidr]
+  }}
+  egress {{
+    from_port   = 0
+    to_port     = 0
+    protocol    = "-1"
+    cidr_blocks = ["0.0.0.0/0"]
+  }}
+}}
+
+resource "aws_instance" "lattice_{n}" {{
+  provider               = aws.{n}
+  ami                    = data.aws_ami.ubuntu_{n}.id
+  instance_type          = var.instance_type
+  user_data              = file("${{path.module}}/user-data.yaml")
+  vpc_security_group_ids = [aws_security_group.lattice_{n}.id]
+  tags = {{ Name = "lattice-{code}" }}
+}}
+
+output "lattice_{n}_ip" {{
+  value = aws_instance.lattice_{n}.public_ip
+}}
+"#,
+                    n = n,
+                    code = r.code,
+                    port = REFLECTOR_PORT
+                ));
+            }
+        }
+        "gcp" => {
+            out.push_str(&format!(
+                r#"variable "machine_type" {{
+  default = "e2-micro"
+}}
+
+resource "google_compute_firewall" "lattice" {{
+  name          = "lattice-reflector"
+  network       = "default"
+  source_ranges = [var.allow_cidr]
+  target_tags   = ["lattice-reflector"]
+  allow {{
+    protocol = "udp"
+    ports    = ["{port}"]
+  }}
+  allow {{
+    protocol = "tcp"
+    ports    = ["{port}"]
+  }}
+}}
+"#,
+                port = REFLECTOR_PORT
+            ));
+            for r in regions {
+                let n = tf_name(r.code);
+                out.push_str(&format!(
+                    r#"
+data "google_compute_zones" "lattice_{n}" {{
+  region = "{code}"
+  status = "UP"
+}}
+
+resource "google_compute_instance" "lattice_{n}" {{
+  name         = "lattice-{code}"
+  machine_type = var.machine_type
+  zone         = data.google_compute_zones.lattice_{n}.names[0]
+  tags         = ["lattice-reflector"]
+  boot_disk {{
+    initialize_params {{
+      image = "ubuntu-os-cloud/ubuntu-2204-lts"
+    }}
+  }}
+  network_interface {{
+    network = "default"
+    access_config {{}}
+  }}
+  metadata = {{
+    user-data = file("${{path.module}}/user-data.yaml")
+  }}
+}}
+
+output "lattice_{n}_ip" {{
+  value = google_compute_instance.lattice_{n}.network_interface[0].access_config[0].nat_ip
+}}
+"#,
+                    n = n,
+                    code = r.code
+                ));
+            }
+        }
+        "do" => {
+            let mut ids = Vec::new();
+            for r in regions {
+                let n = tf_name(r.code);
+                ids.push(format!("digitalocean_droplet.lattice_{}.id", n));
+                out.push_str(&format!(
+                    r#"
+resource "digitalocean_droplet" "lattice_{n}" {{
+  name      = "lattice-{code}"
+  region    = "{code}"
+  size      = "s-1vcpu-1gb"
+  image     = "ubuntu-22-04-x64"
+  user_data = file("${{path.module}}/user-data.yaml")
+}}
+
+output "lattice_{n}_ip" {{
+  value = digitalocean_droplet.lattice_{n}.ipv4_address
+}}
+"#,
+                    n = n,
+                    code = r.code
+                ));
+            }
+            out.push_str(&format!(
+                r#"
+resource "digitalocean_firewall" "lattice" {{
+  name        = "lattice-reflector"
+  droplet_ids = [{ids}]
+  inbound_rule {{
+    protocol         = "udp"
+    port_range       = "{port}"
+    source_addresses = [var.allow_cidr]
+  }}
+  inbound_rule {{
+    protocol         = "tcp"
+    port_range       = "{port}"
+    source_addresses = [var.allow_cidr]
+  }}
+  outbound_rule {{
+    protocol              = "udp"
+    port_range            = "1-65535"
+    destination_addresses = ["0.0.0.0/0", "::/0"]
+  }}
+{tcp_out}}}
+"#,
+                ids = ids.join(", "),
+                port = REFLECTOR_PORT,
+                tcp_out = do_tcp_outbound(),
+            ));
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "terraform output is not supported for provider '{}'; use the cloud-init user-data",
+                    provider
+                ),
+            ));
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "00112233445566778899aabbccddeeff";
+
+    fn regions(provider: &str, codes: &[&str]) -> Vec<&'static CloudRegion> {
+        codes
+            .iter()
+            .map(|code| cloud_region(Some(provider), code).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn cloud_init_carries_the_secret_and_firewalls_to_the_cidr() {
+        let open = cloud_init(SECRET, DEFAULT_REPO, None);
+        assert!(open.starts_with("#cloud-config\n"));
+        assert!(open.contains(&format!("      LATTICE_SECRET_HEX={SECRET}\n")));
+        assert!(open.contains(&format!("git clone --depth 1 {DEFAULT_REPO} /opt/lattice")));
+        // The Rust reflector, which answers v2, not the Go one.
+        assert!(open.contains("cargo build --release -p lattice-server\n"));
+        assert!(open.contains("      ExecStart=/usr/local/bin/lattice-server\n"));
+        assert!(!open.contains("/opt/lattice/server"));
+        assert!(!open.contains("ufw allow"));
+
+        let closed = cloud_init(SECRET, DEFAULT_REPO, Some("203.0.113.0/24"));
+        assert!(closed.contains("  - ufw allow proto udp from 203.0.113.0/24 to any port 9000\n"));
+        // lattice-server echoes TCP on the same port.
+        assert!(closed.contains("  - ufw allow proto tcp from 203.0.113.0/24 to any port 9000\n"));
+        assert!(closed.contains("  - ufw allow OpenSSH\n  - ufw --force enable\n"));
+        // The firewall is up before the reflector starts listening.
+        assert!(closed.find("ufw --force enable") < closed.find("systemctl enable --now"));
+    }
+
+    #[test]
+    fn endpoints_block_has_one_placeholder_endpoint_per_region() {
+        let block = endpoints_block(SECRET, "edge", &regions("aws", &["us-east-1", "eu-west-1"]));
+        let block: serde_json::Value = serde_json::from_str(&block).unwrap();
+        assert_eq!(block["secretHex"], SECRET);
+        let endpoints = block["endpoints"].as_array().unwrap();
+        assert_eq!(endpoints.len(), 2);
+        assert_eq!(endpoints[0]["id"], "edge-us-east-1");
+        assert_eq!(endpoints[1]["regionHint"], "eu-west-1");
+        for ep in endpoints {
+            assert_eq!(ep["host"], HOST_PLACEHOLDER);
+            assert_eq!(ep["port"], REFLECTOR_PORT);
+            assert_eq!(ep["packetVersion"], 2);
+        }
+    }
+
+    #[test]
+    fn terraform_declares_one_instance_per_region() {
+        let aws = terraform(
+            "aws",
+            &regions("aws", &["us-east-1", "eu-west-1"]),
+            Some("198.51.100.7/32"),
+        )
+        .unwrap();
+        assert!(aws.contains("variable \"allow_cidr\" {\n  default = \"198.51.100.7/32\"\n}"));
+        assert!(aws.contains("resource \"aws_instance\" \"lattice_us_east_1\""));
+        assert!(aws.contains("resource \"aws_instance\" \"lattice_eu_west_1\""));
+        assert!(aws.contains("  region = \"eu-west-1\"\n"));
+        assert!(aws.contains("output \"lattice_us_east_1_ip\""));
+        assert!(aws.contains("    protocol    = \"tcp\"\n    cidr_blocks = [var.allow_cidr]\n"));
+
+        let gcp = terraform("gcp", &regions("gcp", &["us-central1"]), None).unwrap();
+        assert!(gcp.contains("  default = \"0.0.0.0/0\"\n"));
+        assert_eq!(
+            gcp.matches("resource \"google_compute_firewall\"").count(),
+            1
+        );
+        assert!(gcp.contains("resource \"google_compute_instance\" \"lattice_us_central1\""));
+        // The zone is looked up, not guessed: not every region has a `-b`.
+        assert!(gcp.contains("  region = \"us-central1\"\n  status = \"UP\"\n"));
+        assert!(gcp
+            .contains("  zone         = data.google_compute_zones.lattice_us_central1.names[0]\n"));
+        assert!(!gcp.contains("us-central1-b"));
+
+        let droplets = terraform("do", &regions("do", &["nyc1", "sfo2"]), None).unwrap();
+        assert!(droplets.contains(
+            "droplet_ids = [digitalocean_droplet.lattice_nyc1.id, digitalocean_droplet.lattice_sfo2.id]"
+        ));
+        // The droplets fetch packages, the repo, and crates over TCP.
+        for port in ["80", "443", "53"] {
+            assert!(droplets.contains(&format!(
+                "  outbound_rule {{\n    protocol              = \"tcp\"\n    port_range            = \"{port}\"\n"
+            )));
+        }
+    }
+
+    #[test]
+    fn terraform_refuses_providers_it_has_no_template_for() {
+        let err = terraform("azure", &regions("azure", &["eastus"]), None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("'azure'"));
+    }
+}
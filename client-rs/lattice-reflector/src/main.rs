@@ -0,0 +1,6 @@
+use std::io;
+
+fn main() -> io::Result<()> {
+    let argv: Vec<String> = std::env::args().collect();
+    lattice_reflector::run(&argv)
+}
@@ -0,0 +1,175 @@
+//! The reflector's NTP state, signed into every v2 reply so a client can
+//! tell stamps read off a disciplined clock from ones that are not: chrony's
+//! view when it runs, else the kernel's (Linux).
+
+use lattice_core::{UNKNOWN_CLOCK_OFFSET_US, UNSYNCED_STRATUM};
+use std::process::Command;
+use std::sync::atomic::{AtomicI32, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// NTP daemons poll no faster than this, so nothing moves in between.
+const REFRESH_EVERY: Duration = Duration::from_secs(64);
+const US_PER_SEC: f64 = 1_000_000.0;
+
+// `chronyc -c tracking` CSV columns.
+const CHRONY_STRATUM: usize = 2;
+const CHRONY_SYSTEM_OFFSET: usize = 4;
+const CHRONY_LEAP_STATUS: usize = 13;
+
+/// Offset in µs and stratum as they go on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockState {
+    pub offset_us: i32,
+    pub stratum: u8,
+}
+
+impl ClockState {
+    pub const UNKNOWN: ClockState = ClockState {
+        offset_us: UNKNOWN_CLOCK_OFFSET_US,
+        stratum: 0,
+    };
+}
+
+/// The last reading, shared by every listener and refreshed in the
+/// background so no reply waits on a subprocess.
+pub struct SharedClock {
+    offset_us: AtomicI32,
+    stratum: AtomicU8,
+}
+
+impl SharedClock {
+    /// Reads the clock once, then every `REFRESH_EVERY` on a thread of its own.
+    pub fn start() -> Arc<Self> {
+        let clock = Arc::new(SharedClock::new(read_clock()));
+        let shared = Arc::clone(&clock);
+        thread::spawn(move || loop {
+            thread::sleep(REFRESH_EVERY);
+            shared.set(read_clock());
+        });
+        clock
+    }
+
+    pub fn new(state: ClockState) -> Self {
+        SharedClock {
+            offset_us: AtomicI32::new(state.offset_us),
+            stratum: AtomicU8::new(state.stratum),
+        }
+    }
+
+    pub fn set(&self, state: ClockState) {
+        self.offset_us.store(state.offset_us, Ordering::Relaxed);
+        self.stratum.store(state.stratum, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> ClockState {
+        ClockState {
+            offset_us: self.offset_us.load(Ordering::Relaxed),
+            stratum: self.stratum.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn read_clock() -> ClockState {
+    chronyc_tracking()
+        .or_else(kernel_clock)
+        .unwrap_or(ClockState::UNKNOWN)
+}
+
+/// Clock less reference, in whole µs, saturating short of the unknown value.
+fn offset_us(seconds: f64) -> i32 {
+    (seconds * US_PER_SEC)
+        .round()
+        .clamp(f64::from(UNKNOWN_CLOCK_OFFSET_US + 1), f64::from(i32::MAX)) as i32
+}
+
+fn chronyc_tracking() -> Option<ClockState> {
+    let out = Command::new("chronyc")
+        .args(["-c", "tracking"])
+        .output()
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    parse_chronyc_tracking(&String::from_utf8_lossy(&out.stdout))
+}
+
+/// chrony's system-time field is the correction still to apply, positive
+/// when the clock is slow, so the clock's offset is its negation.
+fn parse_chronyc_tracking(text: &str) -> Option<ClockState> {
+    let fields: Vec<&str> = text.trim().split(',').collect();
+    let leap = fields.get(CHRONY_LEAP_STATUS)?;
+    if leap.eq_ignore_ascii_case("Not synchronised") {
+        return Some(ClockState {
+            offset_us: UNKNOWN_CLOCK_OFFSET_US,
+            stratum: UNSYNCED_STRATUM,
+        });
+    }
+    let stratum = fields.get(CHRONY_STRATUM)?.parse::<u8>().ok()?;
+    let correction_s = fields.get(CHRONY_SYSTEM_OFFSET)?.parse::<f64>().ok()?;
+    Some(ClockState {
+        offset_us: offset_us(-correction_s),
+        stratum,
+    })
+}
+
+/// The kernel's PLL state (read-only: `modes` is zero). It knows whether a
+/// daemon disciplines the clock and the offset left to slew, not the stratum.
+#[cfg(target_os = "linux")]
+fn kernel_clock() -> Option<ClockState> {
+    let mut tx: libc::timex = unsafe { std::mem::zeroed() };
+    let state = unsafe { libc::adjtimex(&mut tx) };
+    if state < 0 {
+        return None;
+    }
+    if state == libc::TIME_ERROR || tx.status & libc::STA_UNSYNC != 0 {
+        return Some(ClockState {
+            offset_us: UNKNOWN_CLOCK_OFFSET_US,
+            stratum: UNSYNCED_STRATUM,
+        });
+    }
+    let scale = if tx.status & libc::STA_NANO != 0 {
+        1e9
+    } else {
+        US_PER_SEC
+    };
+    Some(ClockState {
+        offset_us: offset_us(-(tx.offset as f64) / scale),
+        stratum: 0,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn kernel_clock() -> Option<ClockState> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_chronyc_tracking() {
+        let synced = "A29FC87B,162.159.200.123,3,1760601234.123456789,0.000001500,\
+                      -0.000000800,0.000002100,-11.482,0.001,0.012,0.009860,0.000479,\
+                      64.6,Normal\n";
+        assert_eq!(
+            parse_chronyc_tracking(synced),
+            Some(ClockState {
+                offset_us: -2,
+                stratum: 3
+            })
+        );
+        let unsynced = "7F7F0101,,10,0.000000000,0.0,0.0,0.0,0.0,0.0,0.0,0.0,0.0,0.0,\
+                        Not synchronised\n";
+        assert_eq!(
+            parse_chronyc_tracking(unsynced),
+            Some(ClockState {
+                offset_us: UNKNOWN_CLOCK_OFFSET_US,
+                stratum: UNSYNCED_STRATUM
+            })
+        );
+        assert_eq!(parse_chronyc_tracking("506 Cannot talk to daemon"), None);
+    }
+}
@@ -0,0 +1,374 @@
+//! The HTTPS echo, for clients that can only get out over web traffic: a
+//! POST of one v1 packet to `/v1/echo` is answered with the same 32 bytes,
+//! over kept-alive HTTP/1.1 connections. Served only with a certificate.
+
+use crate::{is_timeout, Reflector, IDLE_TIMEOUT};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Where clients POST their probes, as on the Go server.
+pub const ECHO_PATH: &str = "/v1/echo";
+/// The TLS handshake, and each request from its first byte, must be in by
+/// then.
+const HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+const READ_BUF_LEN: usize = 4096;
+/// Request line plus headers.
+const MAX_HEAD_LEN: usize = 8 * 1024;
+/// A probe is 32 bytes; anything much longer is not one.
+const MAX_BODY_LEN: usize = 1024;
+
+/// Loads the certificate chain and key, both PEM.
+pub(crate) fn tls_config(cert: &Path, key: &Path) -> io::Result<Arc<ServerConfig>> {
+    let invalid = |path: &Path, e: &dyn std::fmt::Display| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}: {e}", path.display()),
+        )
+    };
+    let chain = CertificateDer::pem_file_iter(cert)
+        .map_err(|e| invalid(cert, &e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(cert, &e))?;
+    if chain.is_empty() {
+        return Err(invalid(cert, &"no certificates in it"));
+    }
+    let key_der = PrivateKeyDer::from_pem_file(key).map_err(|e| invalid(key, &e))?;
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(chain, key_der)
+        .map_err(|e| invalid(cert, &e))?;
+    Ok(Arc::new(config))
+}
+
+/// One HTTP/1.1 request off the wire.
+#[derive(Debug, PartialEq, Eq)]
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+    /// `Connection: close`, or HTTP/1.0.
+    close: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Response {
+    status: u16,
+    body: Vec<u8>,
+    /// Set on errors that leave the stream unframed.
+    close: bool,
+}
+
+impl Response {
+    fn error(status: u16, message: &str) -> Self {
+        Response {
+            status,
+            body: format!("{message}\n").into_bytes(),
+            close: false,
+        }
+    }
+
+    fn fatal(status: u16, message: &str) -> Self {
+        Response {
+            close: true,
+            ..Response::error(status, message)
+        }
+    }
+
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        let content_type = if self.status == 200 {
+            "application/octet-stream"
+        } else {
+            "text/plain; charset=utf-8"
+        };
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-store\r\n",
+            self.status,
+            reason(self.status),
+            self.body.len()
+        );
+        if self.status == 405 {
+            head.push_str("Allow: POST\r\n");
+        }
+        if self.close {
+            head.push_str("Connection: close\r\n");
+        }
+        head.push_str("\r\n");
+        out.write_all(head.as_bytes())?;
+        out.write_all(&self.body)?;
+        out.flush()
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Splits the first whole request off `buf`, returning it and its length
+/// on the wire, or `None` until all of it has arrived. Only bodies sized by
+/// `Content-Length` are read, which is all the client sends.
+fn parse_request(buf: &[u8]) -> Result<Option<(Request, usize)>, Response> {
+    let Some(head_len) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+        if buf.len() > MAX_HEAD_LEN {
+            return Err(Response::fatal(431, "request head too large"));
+        }
+        return Ok(None);
+    };
+    let head = std::str::from_utf8(&buf[..head_len])
+        .map_err(|_| Response::fatal(400, "request head is not text"))?;
+    let mut lines = head.split("\r\n");
+    let mut parts = lines.next().unwrap_or_default().split(' ');
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(Response::fatal(400, "malformed request line"));
+    };
+    let mut close = match version {
+        "HTTP/1.1" => false,
+        "HTTP/1.0" => true,
+        _ => return Err(Response::fatal(400, "unsupported HTTP version")),
+    };
+    let mut content_length = 0;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(Response::fatal(400, "malformed header"));
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value
+                .parse()
+                .map_err(|_| Response::fatal(400, "bad Content-Length"))?;
+            if content_length > MAX_BODY_LEN {
+                return Err(Response::fatal(413, "body too large"));
+            }
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            return Err(Response::fatal(400, "only Content-Length bodies are read"));
+        } else if name.eq_ignore_ascii_case("connection") {
+            close |= value.eq_ignore_ascii_case("close");
+        }
+    }
+    let total = head_len + 4 + content_length;
+    if buf.len() < total {
+        return Ok(None);
+    }
+    let request = Request {
+        method: method.to_string(),
+        path: target.split('?').next().unwrap_or_default().to_string(),
+        body: buf[head_len + 4..total].to_vec(),
+        close,
+    };
+    Ok(Some((request, total)))
+}
+
+/// The Go server's echo handler: the method first, then the sender's rate,
+/// then the packet.
+fn handle(request: &Request, from: IpAddr, reflector: &Reflector) -> Response {
+    if request.path != ECHO_PATH {
+        return Response::error(404, "404 page not found");
+    }
+    if request.method != "POST" {
+        return Response::error(405, "POST a 32-byte LATO packet");
+    }
+    if !reflector.allow(from, Instant::now()) {
+        return Response::error(429, "rate limited");
+    }
+    if !reflector.echoes_v1(&request.body) {
+        return Response::error(400, "not a valid LATO packet");
+    }
+    Response {
+        status: 200,
+        body: request.body.clone(),
+        close: false,
+    }
+}
+
+/// Answers requests on one connection until the client hangs up, asks to
+/// close, goes `IDLE_TIMEOUT` between requests, or takes longer than
+/// `HEADER_TIMEOUT` over the handshake or a request.
+pub(crate) fn echo(
+    conn: TcpStream,
+    tls: Arc<ServerConfig>,
+    reflector: &Reflector,
+    stop: &AtomicBool,
+) {
+    let Ok(peer) = conn.peer_addr() else {
+        return;
+    };
+    let _ = conn.set_nodelay(true);
+    let _ = conn.set_write_timeout(Some(IDLE_TIMEOUT));
+    let Ok(session) = ServerConnection::new(tls) else {
+        return;
+    };
+    let mut stream = StreamOwned::new(session, conn);
+    let started = Instant::now();
+    while stream.conn.is_handshaking() {
+        match stream.conn.complete_io(&mut stream.sock) {
+            Ok(_) => {}
+            Err(err) if is_timeout(&err) && started.elapsed() < HEADER_TIMEOUT => {
+                if stop.load(Ordering::Relaxed) {
+                    return;
+                }
+            }
+            Err(_) => return,
+        }
+    }
+
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; READ_BUF_LEN];
+    let mut idle_since = Instant::now();
+    let mut request_since = None;
+    while !stop.load(Ordering::Relaxed) {
+        match parse_request(&buf) {
+            Ok(Some((request, len))) => {
+                buf.drain(..len);
+                let mut response = handle(&request, peer.ip(), reflector);
+                response.close |= request.close;
+                if response.write_to(&mut stream).is_err() || response.close {
+                    return;
+                }
+                idle_since = Instant::now();
+                request_since = (!buf.is_empty()).then_some(idle_since);
+                continue;
+            }
+            Ok(None) => {}
+            Err(response) => {
+                let _ = response.write_to(&mut stream);
+                return;
+            }
+        }
+        match stream.read(&mut chunk) {
+            Ok(0) => return,
+            Ok(n) => {
+                if buf.is_empty() {
+                    request_since = Some(Instant::now());
+                }
+                buf.extend_from_slice(&chunk[..n]);
+            }
+            Err(err) if is_timeout(&err) => {
+                let expired = match request_since {
+                    Some(since) => since.elapsed() >= HEADER_TIMEOUT,
+                    None => idle_since.elapsed() >= IDLE_TIMEOUT,
+                };
+                if expired {
+                    return;
+                }
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lattice_core::LATO_PACKET_LEN;
+
+    const CLIENT: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 1));
+
+    fn post(body: &[u8]) -> Vec<u8> {
+        let mut req = format!(
+            "POST /v1/echo HTTP/1.1\r\nHost: reflector\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        req.extend_from_slice(body);
+        req
+    }
+
+    #[test]
+    fn parses_requests_off_a_kept_alive_stream() {
+        let mut wire = post(&[7; 32]);
+        wire.extend(post(&[8; 32]));
+        let (first, len) = parse_request(&wire).unwrap().unwrap();
+        assert_eq!(first.method, "POST");
+        assert_eq!(first.path, ECHO_PATH);
+        assert_eq!(first.body, [7; 32]);
+        assert!(!first.close);
+        let (second, rest) = parse_request(&wire[len..]).unwrap().unwrap();
+        assert_eq!(second.body, [8; 32]);
+        assert_eq!(len + rest, wire.len());
+
+        // Incomplete heads and bodies wait for more.
+        assert_eq!(parse_request(&wire[..10]), Ok(None));
+        assert_eq!(parse_request(&wire[..len - 1]), Ok(None));
+
+        let closing = b"GET /v1/echo?x=1 HTTP/1.0\r\n\r\n";
+        let (get, _) = parse_request(closing).unwrap().unwrap();
+        assert_eq!((get.method.as_str(), get.path.as_str()), ("GET", ECHO_PATH));
+        assert!(get.close);
+    }
+
+    #[test]
+    fn refuses_what_it_cannot_frame() {
+        for (wire, status) in [
+            (&b"POST /v1/echo\r\n\r\n"[..], 400),
+            (b"POST /v1/echo HTTP/2\r\n\r\n", 400),
+            (b"POST /v1/echo HTTP/1.1\r\nContent-Length: x\r\n\r\n", 400),
+            (
+                b"POST /v1/echo HTTP/1.1\r\nContent-Length: 5000\r\n\r\n",
+                413,
+            ),
+            (
+                b"POST /v1/echo HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n",
+                400,
+            ),
+        ] {
+            let response = parse_request(wire).unwrap_err();
+            assert_eq!(response.status, status, "{}", String::from_utf8_lossy(wire));
+            assert!(response.close);
+        }
+        let endless = vec![b'a'; MAX_HEAD_LEN + 1];
+        assert_eq!(parse_request(&endless).unwrap_err().status, 431);
+    }
+
+    #[test]
+    fn answers_like_the_go_handler() {
+        let reflector = crate::tests::reflector(crate::RateLimit {
+            burst: 1.0,
+            per_second: 0.001,
+        });
+        let probe = lattice_core::build_packet(1, 1, 1, crate::tests::secret().as_bytes());
+        let request = |method: &str, path: &str, body: &[u8]| Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            body: body.to_vec(),
+            close: false,
+        };
+        assert_eq!(
+            handle(&request("POST", "/", &probe), CLIENT, &reflector).status,
+            404
+        );
+        assert_eq!(
+            handle(&request("GET", ECHO_PATH, &[]), CLIENT, &reflector).status,
+            405
+        );
+        let echoed = handle(&request("POST", ECHO_PATH, &probe), CLIENT, &reflector);
+        assert_eq!((echoed.status, echoed.body.as_slice()), (200, &probe[..]));
+        // The bucket is empty now; the rate is checked before the packet.
+        assert_eq!(
+            handle(&request("POST", ECHO_PATH, b"junk"), CLIENT, &reflector).status,
+            429
+        );
+        let other = IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 2));
+        let mut forged = probe;
+        forged[LATO_PACKET_LEN - 1] ^= 1;
+        assert_eq!(
+            handle(&request("POST", ECHO_PATH, &forged), other, &reflector).status,
+            400
+        );
+    }
+}
@@ -0,0 +1,863 @@
+//! The reflector for the `lattice` client, shared by the `lattice-server`
+//! binary and `lattice serve`. It echoes LATO packets whose tag checks out
+//! under the shared secret and drops everything else, rate-limited per source
+//! address like the Go server, over UDP, TCP on the same ports, and HTTPS
+//! when given a certificate. v2 probes get a signed reply carrying the
+//! times the probe arrived and the reply left, and the NTP state of the clock
+//! that stamped them, and only once: a replayed v2 probe is dropped. Replies
+//! go out ECN-capable, so the client can see congestion marks from the way
+//! back.
+
+mod clock;
+mod https;
+mod tcp;
+
+use clap::Parser;
+use clock::SharedClock;
+use lattice_core::{
+    interpolate_env, parse_lato_packet, Ecn, Replay, ReplayGuard, Secret, SecretHex,
+    LATO_PACKET_LEN, LATO_V2_PACKET_LEN, LATO_V2_VERSION, LATO_VERSION, MIN_SECRET_BYTES,
+};
+use serde::Deserialize;
+use socket2::SockRef;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub use https::ECHO_PATH;
+
+const DEFAULT_LISTEN: &str = "0.0.0.0:9000";
+const DEFAULT_HTTPS_LISTEN: &str = "0.0.0.0:443";
+/// Read when no config is given, as by the Go server and the systemd unit
+/// `bootstrap` installs.
+const SECRET_ENV: &str = "LATTICE_SECRET_HEX";
+/// Read with the secret, under the Go server's names.
+const TLS_CERT_ENV: &str = "LATTICE_TLS_CERT";
+const TLS_KEY_ENV: &str = "LATTICE_TLS_KEY";
+const HTTPS_ADDR_ENV: &str = "LATTICE_HTTPS_ADDR";
+/// One byte more than the longest packet, so a longer datagram shows up as
+/// too long instead of being cut to size.
+const RECV_BUF_LEN: usize = LATO_V2_PACKET_LEN + 1;
+/// Client sockets whose v2 sequence windows are kept; each is a few dozen
+/// bytes.
+const REPLAY_EPOCHS: usize = 16 * 1024;
+const DEFAULT_BURST: f64 = 60.0;
+const DEFAULT_PER_SECOND: f64 = 30.0;
+/// A source quiet for this long loses its bucket; the next packet starts full.
+const BUCKET_TTL: Duration = Duration::from_secs(2 * 60);
+const SWEEP_EVERY: Duration = Duration::from_secs(30);
+/// How long a listener waits on its socket before checking whether it was
+/// asked to stop.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(200);
+/// Open TCP connections, and separately HTTPS ones, as the Go server caps
+/// them; past this a new one is closed at once.
+const MAX_STREAM_CONNS: usize = 256;
+/// A TCP connection with no packet, or an HTTPS one with no request, for
+/// this long is closed.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(2 * 60);
+
+#[derive(Parser, Debug)]
+#[command(about = "LATTICE UDP reflector: echoes packets signed with the shared secret")]
+struct Cli {
+    /// Server config (JSON). A client config works too: only `secretHex`,
+    /// `listen`, and `rateLimit` are read. Without one the secret comes from
+    /// LATTICE_SECRET_HEX.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Address to listen on, e.g. `[::]:9000` (repeatable); replaces `listen`
+    /// from the config.
+    #[arg(long = "listen", value_name = "ADDR")]
+    listen: Vec<String>,
+}
+
+/// Per-source token bucket: `burst` packets at once, refilled at
+/// `perSecond`.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RateLimit {
+    pub burst: f64,
+    pub per_second: f64,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        RateLimit {
+            burst: DEFAULT_BURST,
+            per_second: DEFAULT_PER_SECOND,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ServerConfig {
+    /// Same field and format as the client's.
+    pub secret_hex: SecretHex,
+    /// One UDP socket and one TCP listener on the same port per address.
+    pub listen: Vec<String>,
+    pub rate_limit: RateLimit,
+    /// PEM certificate chain and key for the HTTPS echo, which is off
+    /// without both.
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    /// Where the HTTPS echo listens.
+    pub https_listen: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            secret_hex: SecretHex::default(),
+            listen: vec![DEFAULT_LISTEN.to_string()],
+            rate_limit: RateLimit::default(),
+            tls_cert: None,
+            tls_key: None,
+            https_listen: vec![DEFAULT_HTTPS_LISTEN.to_string()],
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Reads `path`, expanding `${NAME}` from the environment as the client
+    /// does, so `"secretHex": "${LATTICE_SECRET_HEX}"` works in both.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let mut doc: serde_json::Value = serde_json::from_slice(&data).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })?;
+        interpolate_env(&mut doc, "", &|name| std::env::var(name).ok())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        serde_json::from_value(doc).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Defaults with the secret from `LATTICE_SECRET_HEX`, and the HTTPS
+    /// echo from `LATTICE_TLS_CERT`, `LATTICE_TLS_KEY`, and
+    /// `LATTICE_HTTPS_ADDR`.
+    pub fn from_env() -> Self {
+        let var = |name| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let mut cfg = ServerConfig {
+            secret_hex: SecretHex::from(var(SECRET_ENV).unwrap_or_default()),
+            tls_cert: var(TLS_CERT_ENV).map(PathBuf::from),
+            tls_key: var(TLS_KEY_ENV).map(PathBuf::from),
+            ..ServerConfig::default()
+        };
+        if let Some(addr) = var(HTTPS_ADDR_ENV) {
+            cfg.https_listen = vec![addr];
+        }
+        cfg
+    }
+
+    /// Checks the settings and decodes the secret.
+    pub fn validate(&self) -> io::Result<Secret> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+        if self.secret_hex.expose().is_empty() {
+            return Err(invalid(format!(
+                "no secret: set secretHex in the config or {SECRET_ENV}"
+            )));
+        }
+        let secret = self.secret_hex.decode().map_err(invalid)?;
+        if secret.len() < MIN_SECRET_BYTES {
+            return Err(invalid(format!(
+                "secretHex must be at least {MIN_SECRET_BYTES} bytes"
+            )));
+        }
+        if self.listen.is_empty() {
+            return Err(invalid("listen needs at least one address".to_string()));
+        }
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            return Err(invalid(
+                "tlsCert and tlsKey go together: set both for HTTPS, or neither".to_string(),
+            ));
+        }
+        if self.tls_cert.is_some() && self.https_listen.is_empty() {
+            return Err(invalid(
+                "httpsListen needs at least one address".to_string(),
+            ));
+        }
+        let RateLimit { burst, per_second } = self.rate_limit;
+        if !(burst.is_finite() && burst >= 1.0 && per_second.is_finite() && per_second > 0.0) {
+            return Err(invalid(
+                "rateLimit needs burst >= 1 and perSecond > 0".to_string(),
+            ));
+        }
+        Ok(secret)
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/// Buckets by source IP, not IP and port, and shared by every socket, so a
+/// client gains nothing by spreading probes over ports.
+struct Limiter {
+    rate: RateLimit,
+    buckets: HashMap<IpAddr, Bucket>,
+    last_sweep: Instant,
+}
+
+impl Limiter {
+    fn new(rate: RateLimit) -> Self {
+        Limiter {
+            rate,
+            buckets: HashMap::new(),
+            last_sweep: Instant::now(),
+        }
+    }
+
+    fn allow(&mut self, ip: IpAddr, now: Instant) -> bool {
+        if now.duration_since(self.last_sweep) >= SWEEP_EVERY {
+            self.buckets
+                .retain(|_, b| now.duration_since(b.last) <= BUCKET_TTL);
+            self.last_sweep = now;
+        }
+        let rate = self.rate;
+        let bucket = self.buckets.entry(ip).or_insert(Bucket {
+            tokens: rate.burst,
+            last: now,
+        });
+        let refill = now.duration_since(bucket.last).as_secs_f64() * rate.per_second;
+        bucket.tokens = (bucket.tokens + refill).min(rate.burst);
+        bucket.last = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+/// Runs the reflector CLI; `argv[0]` is the program name shown in help.
+/// Serves until the process is stopped.
+pub fn run(argv: &[String]) -> io::Result<()> {
+    let cli = Cli::parse_from(argv);
+    let mut cfg = match &cli.config {
+        Some(path) => ServerConfig::load(path)?,
+        None => ServerConfig::from_env(),
+    };
+    if !cli.listen.is_empty() {
+        cfg.listen = cli.listen;
+    }
+    Server::bind(&cfg)?.serve()
+}
+
+/// A reflector bound to its listen addresses.
+pub struct Server {
+    sockets: Vec<UdpSocket>,
+    tcp: Vec<TcpListener>,
+    /// Each with the certificate it serves.
+    https: Vec<(TcpListener, Arc<rustls::ServerConfig>)>,
+    reflector: Arc<Reflector>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Server {
+    /// Checks `cfg` and binds one UDP socket and one TCP listener per
+    /// `listen` address, and the HTTPS listeners when there is a
+    /// certificate.
+    pub fn bind(cfg: &ServerConfig) -> io::Result<Self> {
+        let secret = cfg.validate()?;
+        let mut sockets = Vec::new();
+        let mut tcp = Vec::new();
+        for addr in &cfg.listen {
+            let socket = UdpSocket::bind(addr.as_str())
+                .map_err(|e| io::Error::new(e.kind(), format!("listen {addr}: {e}")))?;
+            // The port UDP got, which differs from `addr` only for port 0.
+            let local = socket.local_addr()?;
+            let listener = TcpListener::bind(local)
+                .map_err(|e| io::Error::new(e.kind(), format!("listen {addr}/tcp: {e}")))?;
+            eprintln!("LATTICE echo listening on {local}/udp and {local}/tcp");
+            if let Err(err) = mark_ect(&socket) {
+                eprintln!("[!] {addr}: replies sent without ECN: {err}");
+            }
+            socket.set_read_timeout(Some(SHUTDOWN_POLL))?;
+            sockets.push(socket);
+            tcp.push(listener);
+        }
+        let mut https = Vec::new();
+        if let (Some(cert), Some(key)) = (&cfg.tls_cert, &cfg.tls_key) {
+            let tls = https::tls_config(cert, key)?;
+            for addr in &cfg.https_listen {
+                let listener = TcpListener::bind(addr.as_str())
+                    .map_err(|e| io::Error::new(e.kind(), format!("listen {addr}/https: {e}")))?;
+                eprintln!(
+                    "LATTICE echo listening on https://{}{ECHO_PATH}",
+                    listener.local_addr()?
+                );
+                https.push((listener, Arc::clone(&tls)));
+            }
+        }
+        Ok(Server {
+            sockets,
+            tcp,
+            https,
+            reflector: Arc::new(Reflector::new(secret, cfg.rate_limit, SharedClock::start())),
+            stop: Arc::default(),
+        })
+    }
+
+    /// The bound UDP addresses, in `listen` order, with port 0 resolved.
+    /// TCP listens on the same ones.
+    pub fn local_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        self.sockets.iter().map(UdpSocket::local_addr).collect()
+    }
+
+    /// The bound HTTPS addresses, in `httpsListen` order; none without a
+    /// certificate.
+    pub fn https_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        self.https.iter().map(|(l, _)| l.local_addr()).collect()
+    }
+
+    /// A handle that stops `serve` from another thread.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        let listeners = self.tcp.iter().chain(self.https.iter().map(|(l, _)| l));
+        ShutdownHandle {
+            stop: Arc::clone(&self.stop),
+            wake: listeners.filter_map(|l| l.local_addr().ok()).collect(),
+        }
+    }
+
+    /// Serves every socket and listener, one thread each and one more per
+    /// TCP or HTTPS connection, until stopped through a `ShutdownHandle`.
+    pub fn serve(self) -> io::Result<()> {
+        let mut workers: Vec<_> = self
+            .sockets
+            .into_iter()
+            .map(|socket| {
+                let reflector = Arc::clone(&self.reflector);
+                let stop = Arc::clone(&self.stop);
+                thread::spawn(move || serve(&socket, &reflector, &stop))
+            })
+            .collect();
+        let tcp_slots = Slots::new(MAX_STREAM_CONNS);
+        for listener in self.tcp {
+            let reflector = Arc::clone(&self.reflector);
+            let answer = move |conn, stop: &AtomicBool| tcp::echo(conn, &reflector, stop);
+            let (slots, stop) = (Arc::clone(&tcp_slots), Arc::clone(&self.stop));
+            workers.push(thread::spawn(move || {
+                accept(&listener, &slots, &stop, answer)
+            }));
+        }
+        let https_slots = Slots::new(MAX_STREAM_CONNS);
+        for (listener, tls) in self.https {
+            let reflector = Arc::clone(&self.reflector);
+            let answer = move |conn, stop: &AtomicBool| {
+                https::echo(conn, Arc::clone(&tls), &reflector, stop)
+            };
+            let (slots, stop) = (Arc::clone(&https_slots), Arc::clone(&self.stop));
+            workers.push(thread::spawn(move || {
+                accept(&listener, &slots, &stop, answer)
+            }));
+        }
+        for worker in workers {
+            worker
+                .join()
+                .map_err(|_| io::Error::other("listener thread panicked"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Stops a `Server`: its listeners return within `SHUTDOWN_POLL`, and its
+/// connections within `SHUTDOWN_POLL` of their next read.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    stop: Arc<AtomicBool>,
+    /// The TCP listeners, each woken from `accept` by a connection.
+    wake: Vec<SocketAddr>,
+}
+
+impl ShutdownHandle {
+    pub fn shutdown(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for addr in &self.wake {
+            let ip = match addr.ip() {
+                IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+                ip => ip,
+            };
+            let _ = TcpStream::connect_timeout(&SocketAddr::new(ip, addr.port()), SHUTDOWN_POLL);
+        }
+    }
+}
+
+/// Counts a kind of open connection against its cap.
+struct Slots {
+    open: AtomicUsize,
+    max: usize,
+}
+
+impl Slots {
+    fn new(max: usize) -> Arc<Self> {
+        Arc::new(Slots {
+            open: AtomicUsize::new(0),
+            max,
+        })
+    }
+
+    fn take(slots: &Arc<Slots>) -> Option<Slot> {
+        slots
+            .open
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n < slots.max).then_some(n + 1)
+            })
+            .ok()?;
+        Some(Slot(Arc::clone(slots)))
+    }
+}
+
+/// One open connection, given back on drop.
+struct Slot(Arc<Slots>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.open.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Hands each accepted connection to `answer` on its own thread, until
+/// stopped. Past the cap, a connection is closed unanswered, as the Go
+/// server does. Reads time out every `SHUTDOWN_POLL` so `answer` can check
+/// `stop`.
+fn accept<F>(listener: &TcpListener, slots: &Arc<Slots>, stop: &Arc<AtomicBool>, answer: F)
+where
+    F: Fn(TcpStream, &AtomicBool) + Clone + Send + 'static,
+{
+    for conn in listener.incoming() {
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+        let Ok(conn) = conn else {
+            continue;
+        };
+        let Some(slot) = Slots::take(slots) else {
+            continue;
+        };
+        if conn.set_read_timeout(Some(SHUTDOWN_POLL)).is_err() {
+            continue;
+        }
+        let (answer, stop) = (answer.clone(), Arc::clone(stop));
+        thread::spawn(move || {
+            answer(conn, &stop);
+            drop(slot);
+        });
+    }
+}
+
+/// A read that ran out of time rather than failed.
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+    )
+}
+
+/// What a packet gets back.
+#[derive(Debug, PartialEq, Eq)]
+enum Reply {
+    /// v1: the packet itself.
+    Echo,
+    /// v2: the probe stamped and signed.
+    Signed([u8; LATO_V2_PACKET_LEN]),
+}
+
+/// The state every listening socket shares.
+struct Reflector {
+    secret: Secret,
+    limiter: Mutex<Limiter>,
+    /// Shared like the limiter, so a probe replayed to another address is
+    /// still a replay.
+    guard: Mutex<ReplayGuard>,
+    clock: Arc<SharedClock>,
+}
+
+impl Reflector {
+    fn new(secret: Secret, rate: RateLimit, clock: Arc<SharedClock>) -> Self {
+        Reflector {
+            secret,
+            limiter: Mutex::new(Limiter::new(rate)),
+            guard: Mutex::new(ReplayGuard::new(REPLAY_EPOCHS)),
+            clock,
+        }
+    }
+
+    /// The reply to `msg`, received from `from` at `received_ns` (wall
+    /// clock) and `now`, or `None` when it is dropped: the wrong size, over
+    /// the sender's rate, not signed with the secret, or a replayed v2
+    /// probe.
+    fn answer(&self, msg: &[u8], from: IpAddr, received_ns: u64, now: Instant) -> Option<Reply> {
+        if msg.len() != LATO_PACKET_LEN && msg.len() != LATO_V2_PACKET_LEN {
+            return None;
+        }
+        if !self.allow(from, now) {
+            return None;
+        }
+        let mut packet = parse_lato_packet(msg)
+            .ok()
+            .filter(|packet| packet.verify(self.secret.as_bytes()))?;
+        if packet.version != LATO_V2_VERSION {
+            return Some(Reply::Echo);
+        }
+        let fresh = self
+            .guard
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .check(&packet)
+            == Replay::Fresh;
+        if !fresh {
+            return None;
+        }
+        packet.reflector_rx_ns = received_ns;
+        let state = self.clock.get();
+        packet.reflector_offset_us = state.offset_us;
+        packet.reflector_stratum = state.stratum;
+        packet.reflector_tx_ns = realtime_ns().max(received_ns);
+        Some(Reply::Signed(packet.reply_v2(self.secret.as_bytes())))
+    }
+
+    /// Takes a token from `from`'s bucket, which every transport shares.
+    fn allow(&self, from: IpAddr, now: Instant) -> bool {
+        // Dual-stack sockets report IPv4 peers as mapped v6 addresses.
+        self.limiter
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .allow(from.to_canonical(), now)
+    }
+
+    /// Whether `msg` is a v1 packet signed with the secret, the only kind
+    /// TCP and HTTPS carry: v2 is UDP's alone.
+    fn echoes_v1(&self, msg: &[u8]) -> bool {
+        msg.len() == LATO_PACKET_LEN
+            && parse_lato_packet(msg).is_ok_and(|packet| {
+                packet.version == LATO_VERSION && packet.verify(self.secret.as_bytes())
+            })
+    }
+}
+
+/// Answers each valid packet 1:1 to its sender with a reply of the same
+/// size, so the server is never an amplifier. Receive errors (ICMP
+/// unreachable from a gone client, say) and read timeouts are skipped.
+fn serve(socket: &UdpSocket, reflector: &Reflector, stop: &AtomicBool) {
+    let mut buf = [0u8; RECV_BUF_LEN];
+    while !stop.load(Ordering::Relaxed) {
+        let Ok((n, from)) = socket.recv_from(&mut buf) else {
+            continue;
+        };
+        let received_ns = realtime_ns();
+        let msg = &buf[..n];
+        let _ = match reflector.answer(msg, from.ip(), received_ns, Instant::now()) {
+            Some(Reply::Echo) => socket.send_to(msg, from),
+            Some(Reply::Signed(reply)) => socket.send_to(&reply, from),
+            None => continue,
+        };
+    }
+}
+
+/// Sends replies ECT(0): a router congested on the way back may then mark
+/// them CE instead of dropping them, and a client with `ecn` on counts the
+/// marks.
+fn mark_ect(socket: &UdpSocket) -> io::Result<()> {
+    let sock = SockRef::from(socket);
+    let ect = u32::from(Ecn::Ect0.bits());
+    match socket.local_addr()? {
+        SocketAddr::V4(_) => sock.set_tos(ect),
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        SocketAddr::V6(_) => {
+            sock.set_tclass_v6(ect)?;
+            // A dual-stack socket answers IPv4 peers with the TOS byte; a
+            // v6-only one refuses it, which is fine.
+            let _ = sock.set_tos(ect);
+            Ok(())
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+        SocketAddr::V6(_) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "traffic class not settable here",
+        )),
+    }
+}
+
+/// Wall-clock nanoseconds, as the client stamps its probes.
+fn realtime_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| u64::try_from(d.as_nanos()).unwrap_or(u64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::ClockState;
+    use lattice_core::{build_packet, build_packet_v2, REPLAY_WINDOW};
+    use std::io::{Read, Write};
+
+    const SECRET: &str = "00112233445566778899aabbccddeeff";
+    const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    const CLOCK: ClockState = ClockState {
+        offset_us: -250,
+        stratum: 2,
+    };
+
+    pub(crate) fn secret() -> Secret {
+        SecretHex::from(SECRET).decode().unwrap()
+    }
+
+    pub(crate) fn reflector(rate: RateLimit) -> Reflector {
+        Reflector::new(secret(), rate, Arc::new(SharedClock::new(CLOCK)))
+    }
+
+    /// Binds `cfg` under the test secret, with a handle that stops it.
+    fn bound(cfg: ServerConfig) -> (Server, ShutdownHandle) {
+        let server = Server::bind(&ServerConfig {
+            secret_hex: SecretHex::from(SECRET),
+            ..cfg
+        })
+        .unwrap();
+        let shutdown = server.shutdown_handle();
+        (server, shutdown)
+    }
+
+    #[test]
+    fn echoes_over_tcp_until_something_else_comes() {
+        let (server, shutdown) = bound(ServerConfig {
+            listen: vec!["127.0.0.1:0".to_string()],
+            ..ServerConfig::default()
+        });
+        let addr = server.local_addrs().unwrap()[0];
+        let serving = thread::spawn(move || server.serve());
+        let mut conn = TcpStream::connect(addr).unwrap();
+        conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let secret = secret();
+        let first = build_packet(1, 1, 1, secret.as_bytes());
+        let second = build_packet(2, 2, 2, secret.as_bytes());
+        // Back to back in one write, echoed whole and in order.
+        conn.write_all(&[first, second].concat()).unwrap();
+        let mut echo = [0u8; 2 * LATO_PACKET_LEN];
+        conn.read_exact(&mut echo).unwrap();
+        assert_eq!(echo, [first, second].concat()[..]);
+
+        let mut forged = first;
+        forged[LATO_PACKET_LEN - 1] ^= 1;
+        conn.write_all(&forged).unwrap();
+        assert_eq!(conn.read(&mut echo).unwrap(), 0);
+
+        shutdown.shutdown();
+        serving.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn echoes_over_https_on_one_kept_alive_connection() {
+        let dir = std::env::temp_dir().join(format!("lattice-server-tls-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let issued = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        fs::write(dir.join("cert.pem"), issued.cert.pem()).unwrap();
+        fs::write(dir.join("key.pem"), issued.signing_key.serialize_pem()).unwrap();
+        let (server, shutdown) = bound(ServerConfig {
+            listen: vec!["127.0.0.1:0".to_string()],
+            tls_cert: Some(dir.join("cert.pem")),
+            tls_key: Some(dir.join("key.pem")),
+            https_listen: vec!["127.0.0.1:0".to_string()],
+            ..ServerConfig::default()
+        });
+        let addr = server.https_addrs().unwrap()[0];
+        let serving = thread::spawn(move || server.serve());
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(issued.cert.der().clone()).unwrap();
+        let tls = rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let session =
+            rustls::ClientConnection::new(Arc::new(tls), "localhost".try_into().unwrap()).unwrap();
+        let conn = TcpStream::connect(addr).unwrap();
+        conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut stream = rustls::StreamOwned::new(session, conn);
+        let probe = build_packet(1, 1, 1, secret().as_bytes());
+        for _ in 0..2 {
+            let head = format!(
+                "POST {ECHO_PATH} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {LATO_PACKET_LEN}\r\n\r\n"
+            );
+            stream
+                .write_all(&[head.as_bytes(), &probe].concat())
+                .unwrap();
+            let mut response = Vec::new();
+            let mut buf = [0u8; 512];
+            while !response.ends_with(&probe) {
+                let n = stream.read(&mut buf).unwrap();
+                assert_ne!(n, 0, "closed after {response:?}");
+                response.extend_from_slice(&buf[..n]);
+            }
+            let text = String::from_utf8_lossy(&response);
+            assert!(text.starts_with("HTTP/1.1 200 OK\r\n"), "{text}");
+            assert!(text.contains(&format!("Content-Length: {LATO_PACKET_LEN}\r\n")));
+        }
+
+        shutdown.shutdown();
+        serving.join().unwrap().unwrap();
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn https_needs_both_halves_of_the_certificate() {
+        let cfg = ServerConfig {
+            secret_hex: SecretHex::from(SECRET),
+            tls_cert: Some(PathBuf::from("cert.pem")),
+            ..ServerConfig::default()
+        };
+        let err = cfg.validate().unwrap_err();
+        assert!(err.to_string().starts_with("tlsCert and tlsKey"), "{err}");
+    }
+
+    #[test]
+    fn bucket_allows_the_burst_then_refills_per_ip() {
+        let rate = RateLimit {
+            burst: 3.0,
+            per_second: 2.0,
+        };
+        let mut limiter = Limiter::new(rate);
+        let start = Instant::now();
+        let other = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+        for _ in 0..3 {
+            assert!(limiter.allow(CLIENT, start));
+        }
+        assert!(!limiter.allow(CLIENT, start));
+        assert!(limiter.allow(other, start));
+        // One token every 500 ms.
+        assert!(!limiter.allow(CLIENT, start + Duration::from_millis(400)));
+        assert!(limiter.allow(CLIENT, start + Duration::from_millis(900)));
+        assert!(!limiter.allow(CLIENT, start + Duration::from_millis(900)));
+        // Long quiet refills to the burst, no further.
+        let later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(limiter.allow(CLIENT, later));
+        }
+        assert!(!limiter.allow(CLIENT, later));
+
+        // Sources quiet past the TTL lose their bucket at the next sweep.
+        assert_eq!(limiter.buckets.len(), 2);
+        let swept = later + BUCKET_TTL + SWEEP_EVERY;
+        assert!(limiter.allow(CLIENT, swept - BUCKET_TTL));
+        assert!(limiter.allow(CLIENT, swept));
+        assert_eq!(limiter.buckets.keys().collect::<Vec<_>>(), [&CLIENT]);
+    }
+
+    #[test]
+    fn rate_limits_by_ip_whatever_the_address_family() {
+        let reflector = reflector(RateLimit {
+            burst: 2.0,
+            per_second: 1.0,
+        });
+        let now = Instant::now();
+        let probe = build_packet(1, 1, 1, secret().as_bytes());
+        let IpAddr::V4(v4) = CLIENT else {
+            unreachable!()
+        };
+        let mapped = IpAddr::V6(v4.to_ipv6_mapped());
+        assert_eq!(reflector.answer(&probe, CLIENT, 1, now), Some(Reply::Echo));
+        assert_eq!(reflector.answer(&probe, mapped, 1, now), Some(Reply::Echo));
+        assert_eq!(reflector.answer(&probe, CLIENT, 1, now), None);
+        assert_eq!(reflector.answer(&probe, mapped, 1, now), None);
+        let v6 = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        assert_eq!(reflector.answer(&probe, v6, 1, now), Some(Reply::Echo));
+    }
+
+    #[test]
+    fn echoes_v1_and_drops_what_is_not_signed_or_sized_right() {
+        let reflector = reflector(RateLimit::default());
+        let now = Instant::now();
+        let probe = build_packet(7, 1_000, 42, secret().as_bytes());
+        assert_eq!(reflector.answer(&probe, CLIENT, 1, now), Some(Reply::Echo));
+
+        let mut forged = probe;
+        forged[LATO_PACKET_LEN - 1] ^= 1;
+        let mut edited = probe;
+        edited[8] ^= 1;
+        let other_secret = build_packet(7, 1_000, 42, &[0x55; 16]);
+        let mut long = probe.to_vec();
+        long.push(0);
+        let mut v2_sized = probe.to_vec();
+        v2_sized.resize(LATO_V2_PACKET_LEN, 0);
+        for msg in [
+            &probe[..LATO_PACKET_LEN - 1],
+            &long,
+            &forged,
+            &edited,
+            &other_secret,
+            &v2_sized,
+            &[][..],
+        ] {
+            assert_eq!(reflector.answer(msg, CLIENT, 1, now), None, "{msg:?}");
+        }
+    }
+
+    #[test]
+    fn stamps_and_signs_v2_probes() {
+        let reflector = reflector(RateLimit::default());
+        let secret = secret();
+        let received_ns = realtime_ns();
+        let probe = build_packet_v2(7, received_ns - 5_000_000, 42, secret.as_bytes());
+        let Some(Reply::Signed(reply)) =
+            reflector.answer(&probe, CLIENT, received_ns, Instant::now())
+        else {
+            panic!("no signed reply");
+        };
+        let sent = parse_lato_packet(&probe).unwrap();
+        let reply = parse_lato_packet(&reply).unwrap();
+        assert!(reply.verify_reply(secret.as_bytes()));
+        assert!(reply.echoes(&sent));
+        assert_eq!(reply.reflector_rx_ns, received_ns);
+        assert!(reply.reflector_tx_ns >= received_ns);
+        assert_eq!(
+            (reply.reflector_offset_us, reply.reflector_stratum),
+            (CLOCK.offset_us, CLOCK.stratum)
+        );
+
+        // A v2 probe cut to v1 size no longer carries a valid tag.
+        assert_eq!(
+            reflector.answer(&probe[..LATO_PACKET_LEN], CLIENT, 1, Instant::now()),
+            None
+        );
+    }
+
+    #[test]
+    fn answers_a_v2_probe_only_once() {
+        let reflector = reflector(RateLimit::default());
+        let secret = secret();
+        let now = Instant::now();
+        let epoch = 9u64 << u32::BITS;
+        let probe = |seq: u32| build_packet_v2(seq, 1, epoch | u64::from(seq), secret.as_bytes());
+        let signed = |msg: &[u8], from: IpAddr| {
+            matches!(reflector.answer(msg, from, 1, now), Some(Reply::Signed(_)))
+        };
+        let newest = REPLAY_WINDOW + 10;
+        assert!(signed(&probe(newest), CLIENT));
+        // Replayed, from the same source or any other.
+        assert!(!signed(&probe(newest), CLIENT));
+        assert!(!signed(
+            &probe(newest),
+            IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7))
+        ));
+        // Out of order within the window is fine, once.
+        assert!(signed(&probe(newest - 1), CLIENT));
+        assert!(!signed(&probe(newest - 1), CLIENT));
+        // Too far behind the newest to tell.
+        assert!(!signed(&probe(newest - REPLAY_WINDOW), CLIENT));
+        assert!(signed(&probe(newest + 1), CLIENT));
+        // v1 has no sequence state: a repeat is echoed again.
+        let v1 = build_packet(1, 1, epoch, secret.as_bytes());
+        assert_eq!(reflector.answer(&v1, CLIENT, 1, now), Some(Reply::Echo));
+        assert_eq!(reflector.answer(&v1, CLIENT, 1, now), Some(Reply::Echo));
+    }
+}
@@ -0,0 +1,49 @@
+//! The TCP echo, for clients on networks that drop UDP: v1 packets back to
+//! back on one connection, each written back whole and in order, so the
+//! stream stays framed at 32 bytes.
+
+use crate::{is_timeout, Reflector, IDLE_TIMEOUT};
+use lattice_core::LATO_PACKET_LEN;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+/// Echoes packets until the client hangs up, sends anything that is not a
+/// signed packet, or goes `IDLE_TIMEOUT` without a whole one. Over the
+/// sender's rate, a packet is read and dropped.
+pub(crate) fn echo(mut conn: TcpStream, reflector: &Reflector, stop: &AtomicBool) {
+    let Ok(peer) = conn.peer_addr() else {
+        return;
+    };
+    let _ = conn.set_nodelay(true);
+    let _ = conn.set_write_timeout(Some(IDLE_TIMEOUT));
+    let mut buf = [0u8; LATO_PACKET_LEN];
+    let mut filled = 0;
+    let mut last_packet = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+        match conn.read(&mut buf[filled..]) {
+            Ok(0) => return,
+            Ok(n) => filled += n,
+            Err(err) if is_timeout(&err) => {
+                if last_packet.elapsed() >= IDLE_TIMEOUT {
+                    return;
+                }
+                continue;
+            }
+            Err(_) => return,
+        }
+        if filled < LATO_PACKET_LEN {
+            continue;
+        }
+        filled = 0;
+        last_packet = Instant::now();
+        if !reflector.allow(peer.ip(), last_packet) {
+            continue;
+        }
+        // Anything else on the stream means it is not a LATTICE client.
+        if !reflector.echoes_v1(&buf) || conn.write_all(&buf).is_err() {
+            return;
+        }
+    }
+}
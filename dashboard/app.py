@@ -39,6 +39,7 @@ try:
         MIN_CALIBRATION_SCALE,
         MS_PER_MIN,
         MS_PER_SEC,
+        NON_BURST_RECORD_TYPES,
         REFINE_WINDOW_MULT,
         RTT_FACTOR,
         SEC_PER_MIN,
@@ -68,6 +69,7 @@ except ImportError:
         MIN_CALIBRATION_SCALE,
         MS_PER_MIN,
         MS_PER_SEC,
+        NON_BURST_RECORD_TYPES,
         REFINE_WINDOW_MULT,
         RTT_FACTOR,
         SEC_PER_MIN,
@@ -252,6 +254,8 @@ class StateManager:
                     rec = json.loads(raw)
                 except json.JSONDecodeError:
                     continue
+                if rec.get("recordType") in NON_BURST_RECORD_TYPES:
+                    continue
                 ts = rec.get("tsUnixMs")
                 if not isinstance(ts, int):
                     continue
@@ -482,6 +486,8 @@ def parse_endpoints_text(text, default_port=DEFAULT_PORT):
                 item["lat"] = float(ep["lat"])
             if ep.get("lon") is not None:
                 item["lon"] = float(ep["lon"])
+            if ep.get("consented") is True:
+                item["consented"] = True
             out.append(item)
         return out
 
@@ -510,6 +516,8 @@ def parse_endpoints_text(text, default_port=DEFAULT_PORT):
                 item["lat"] = float(lat)
             if lon not in (None, ""):
                 item["lon"] = float(lon)
+            if (row.get("consented") or "").strip().lower() in ("1", "true", "yes"):
+                item["consented"] = True
             out.append(item)
         return out
 
@@ -1055,6 +1063,8 @@ def build_endpoint_hygiene(endpoints):
 def compute_stats(records):
     samples = {}
     for rec in records:
+        if rec.get("recordType") in NON_BURST_RECORD_TYPES:
+            continue
         ep = rec.get("endpointId")
         if not ep:
             continue
@@ -25,3 +25,14 @@ SSE_EPSILON = 1e-6
 MIN_JITTER_MS = 1.0
 REFINE_WINDOW_MULT = 2.0
 LOG_RESET_NOTICE_MS = 60 * MS_PER_SEC
+EVENT_RECORD_TYPE = "event"
+RESPONSIVENESS_RECORD_TYPE = "responsiveness"
+ESTIMATE_RECORD_TYPE = "estimate"
+FEATURE_RECORD_TYPE = "features"
+# Log lines that are not bursts; bursts carry no recordType.
+NON_BURST_RECORD_TYPES = (
+    EVENT_RECORD_TYPE,
+    RESPONSIVENESS_RECORD_TYPE,
+    ESTIMATE_RECORD_TYPE,
+    FEATURE_RECORD_TYPE,
+)
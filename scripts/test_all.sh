@@ -4,7 +4,8 @@ set -euo pipefail
 ROOT="$(cd "$(dirname "${BASH_SOURCE[0]}")/.." && pwd)"
 
 cd "$ROOT/client-rs"
-cargo test -p lattice-analyze
+cargo test -p lattice-analysis -p lattice-analyze
+cargo run -q -p lattice-analyze -- demo > /dev/null
 
 cd "$ROOT"
 PYTHON_BIN="${PYTHON:-}"
@@ -3,23 +3,46 @@ package main
 import (
 	"crypto/hmac"
 	"crypto/sha256"
-	"encoding/binary"
+	"crypto/subtle"
+	"io"
 	"log"
 	"net"
+	"net/http"
 	"os"
+	"sync"
 	"time"
 )
 
 const (
 	ListenAddr = ":9000"
 	MsgLen     = 32
+	// HTTPS fallback, served only when LATTICE_TLS_CERT and LATTICE_TLS_KEY
+	// are set. LATTICE_HTTPS_ADDR overrides the address.
+	DefaultHTTPSAddr = ":443"
+	EchoPath         = "/v1/echo"
+	// A TCP connection with no packet for this long is closed.
+	tcpIdleTimeout = 2 * time.Minute
+	maxTCPConns    = 256
+	httpReadHeader = 10 * time.Second
 )
 
-func tag32(secret []byte, msg []byte) uint32 {
+// Lightweight per-source token bucket (also firewall allowlist in production!)
+const (
+	maxTokens  = 60 // burst capacity
+	refillPerS = 30 // tokens per second
+	cost       = 1
+	bucketTTL  = 2 * time.Minute
+	sweepEvery = 30 * time.Second
+)
+
+// tagValid reports whether the last 4 bytes are the truncated HMAC of the rest.
+// The comparison is constant-time so response timing does not reveal how many
+// bytes of a forged tag were right.
+func tagValid(secret []byte, msg []byte) bool {
 	mac := hmac.New(sha256.New, secret)
 	mac.Write(msg[:28]) // tag covers first 28 bytes; last 4 bytes are the tag itself
 	sum := mac.Sum(nil)
-	return binary.BigEndian.Uint32(sum[:4])
+	return subtle.ConstantTimeCompare(sum[:4], msg[28:32]) == 1
 }
 
 func decodeHexIfValid(s string) ([]byte, bool) {
@@ -55,6 +78,168 @@ func hexNibble(c byte) byte {
 	}
 }
 
+type bucket struct {
+	tokens   int
+	last     time.Time
+	lastSeen time.Time
+}
+
+// limiter rate-limits by source IP (not ip:port). UDP, TCP, and HTTPS share
+// it, so switching transports does not buy a client more echoes.
+type limiter struct {
+	mu        sync.Mutex
+	buckets   map[string]*bucket
+	lastSweep time.Time
+}
+
+func newLimiter() *limiter {
+	return &limiter{buckets: make(map[string]*bucket), lastSweep: time.Now()}
+}
+
+func (l *limiter) allow(key string) bool {
+	l.mu.Lock()
+	defer l.mu.Unlock()
+	now := time.Now()
+	b, ok := l.buckets[key]
+	if !ok {
+		b = &bucket{tokens: maxTokens, last: now, lastSeen: now}
+		l.buckets[key] = b
+	}
+	elapsed := now.Sub(b.last).Seconds()
+	if elapsed > 0 {
+		b.tokens += int(elapsed * refillPerS)
+		if b.tokens > maxTokens {
+			b.tokens = maxTokens
+		}
+		b.last = now
+	}
+	b.lastSeen = now
+
+	// Periodic cleanup of idle buckets.
+	if now.Sub(l.lastSweep) >= sweepEvery {
+		for k, v := range l.buckets {
+			if now.Sub(v.lastSeen) > bucketTTL {
+				delete(l.buckets, k)
+			}
+		}
+		l.lastSweep = now
+	}
+
+	if b.tokens < cost {
+		return false
+	}
+	b.tokens -= cost
+	return true
+}
+
+// packetValid reports whether msg is a whole LATO packet with a valid tag.
+func packetValid(secret []byte, msg []byte) bool {
+	if len(msg) != MsgLen {
+		return false
+	}
+	if msg[0] != 'L' || msg[1] != 'A' || msg[2] != 'T' || msg[3] != 'O' {
+		return false
+	}
+	return tagValid(secret, msg)
+}
+
+func serveUDP(pc *net.UDPConn, secret []byte, limits *limiter) {
+	buf := make([]byte, MsgLen)
+	for {
+		n, addr, err := pc.ReadFromUDP(buf)
+		if err != nil {
+			continue
+		}
+		if n != MsgLen {
+			continue
+		}
+		if !limits.allow(addr.IP.String()) {
+			continue
+		}
+		msg := buf[:MsgLen]
+		if !packetValid(secret, msg) {
+			continue
+		}
+		_, _ = pc.WriteToUDP(msg, addr) // echo 1:1 (not an amplifier)
+	}
+}
+
+// serveTCP echoes packets sent back to back on a TCP connection, for clients
+// on networks that drop UDP. Each valid packet is written back whole, in
+// order, so the stream stays framed at 32 bytes.
+func serveTCP(ln net.Listener, secret []byte, limits *limiter) {
+	slots := make(chan struct{}, maxTCPConns)
+	for {
+		conn, err := ln.Accept()
+		if err != nil {
+			continue
+		}
+		select {
+		case slots <- struct{}{}:
+		default:
+			conn.Close()
+			continue
+		}
+		go func() {
+			defer func() { <-slots }()
+			echoTCP(conn, secret, limits)
+		}()
+	}
+}
+
+func echoTCP(conn net.Conn, secret []byte, limits *limiter) {
+	defer conn.Close()
+	host, _, err := net.SplitHostPort(conn.RemoteAddr().String())
+	if err != nil {
+		return
+	}
+	buf := make([]byte, MsgLen)
+	for {
+		_ = conn.SetReadDeadline(time.Now().Add(tcpIdleTimeout))
+		if _, err := io.ReadFull(conn, buf); err != nil {
+			return
+		}
+		if !limits.allow(host) {
+			continue
+		}
+		// Anything else on the stream means it is not a LATTICE client.
+		if !packetValid(secret, buf) {
+			return
+		}
+		if _, err := conn.Write(buf); err != nil {
+			return
+		}
+	}
+}
+
+// echoHandler answers a POST of one packet with the same 32 bytes, for
+// clients that can only get out over HTTPS.
+func echoHandler(secret []byte, limits *limiter) http.HandlerFunc {
+	return func(w http.ResponseWriter, r *http.Request) {
+		if r.Method != http.MethodPost {
+			w.Header().Set("Allow", http.MethodPost)
+			http.Error(w, "POST a 32-byte LATO packet", http.StatusMethodNotAllowed)
+			return
+		}
+		host, _, err := net.SplitHostPort(r.RemoteAddr)
+		if err != nil {
+			host = r.RemoteAddr
+		}
+		if !limits.allow(host) {
+			http.Error(w, "rate limited", http.StatusTooManyRequests)
+			return
+		}
+		msg, err := io.ReadAll(io.LimitReader(r.Body, MsgLen+1))
+		if err != nil || !packetValid(secret, msg) {
+			http.Error(w, "not a valid LATO packet", http.StatusBadRequest)
+			return
+		}
+		w.Header().Set("Content-Type", "application/octet-stream")
+		w.Header().Set("Cache-Control", "no-store")
+		_, _ = w.Write(msg)
+	}
+}
+
 func main() {
 	secretEnv := os.Getenv("LATTICE_SECRET")
 	if secretEnv == "" {
@@ -79,83 +264,39 @@ func main() {
 	}
 	defer pc.Close()
 
-	log.Printf("LATTICE UDP echo listening on %s/udp", ListenAddr)
-
-	_ = pc.SetReadBuffer(1 << 20)
-	_ = pc.SetWriteBuffer(1 << 20)
-
-	buf := make([]byte, MsgLen)
-
-	// Lightweight per-source token bucket (also firewall allowlist in production!)
-	type bucket struct {
-		tokens   int
-		last     time.Time
-		lastSeen time.Time
+	ln, err := net.Listen("tcp", ListenAddr)
+	if err != nil {
+		log.Fatal(err)
 	}
-	limits := make(map[string]*bucket)
+	defer ln.Close()
 
-	const (
-		maxTokens   = 60              // burst capacity
-		refillPerS  = 30              // tokens per second
-		cost        = 1
-		bucketTTL   = 2 * time.Minute
-		sweepEvery  = 30 * time.Second
-	)
+	log.Printf("LATTICE echo listening on %s/udp and %s/tcp", ListenAddr, ListenAddr)
 
-	lastSweep := time.Now()
-
-	for {
-		n, addr, err := pc.ReadFromUDP(buf)
-		if err != nil {
-			continue
-		}
-		if n != MsgLen {
-			continue
-		}
-
-		// Rate limit by source IP (not ip:port)
-		key := addr.IP.String()
-		now := time.Now()
-		b, ok := limits[key]
-		if !ok {
-			b = &bucket{tokens: maxTokens, last: now, lastSeen: now}
-			limits[key] = b
-		}
-		elapsed := now.Sub(b.last).Seconds()
-		if elapsed > 0 {
-			b.tokens += int(elapsed * refillPerS)
-			if b.tokens > maxTokens {
-				b.tokens = maxTokens
-			}
-			b.last = now
-		}
-		b.lastSeen = now
-		if b.tokens < cost {
-			continue
-		}
-		b.tokens -= cost
+	_ = pc.SetReadBuffer(1 << 20)
+	_ = pc.SetWriteBuffer(1 << 20)
 
-		msg := buf[:MsgLen]
-		if msg[0] != 'L' || msg[1] != 'A' || msg[2] != 'T' || msg[3] != 'O' {
-			continue
-		}
+	limits := newLimiter()
+	go serveTCP(ln, secret, limits)
 
-		want := tag32(secret, msg)
-		got := binary.BigEndian.Uint32(msg[28:32])
-		if want != got {
-			continue
+	cert, key := os.Getenv("LATTICE_TLS_CERT"), os.Getenv("LATTICE_TLS_KEY")
+	if cert != "" && key != "" {
+		addr := os.Getenv("LATTICE_HTTPS_ADDR")
+		if addr == "" {
+			addr = DefaultHTTPSAddr
 		}
-
-		_, _ = pc.WriteToUDP(msg, addr) // echo 1:1 (not an amplifier)
-
-		// Periodic cleanup of idle buckets.
-		if now.Sub(lastSweep) >= sweepEvery && len(limits) > 0 {
-			for k, v := range limits {
-				if now.Sub(v.lastSeen) > bucketTTL {
-					delete(limits, k)
-				}
-			}
-			lastSweep = now
+		mux := http.NewServeMux()
+		mux.HandleFunc(EchoPath, echoHandler(secret, limits))
+		srv := &http.Server{
+			Addr:              addr,
+			Handler:           mux,
+			ReadHeaderTimeout: httpReadHeader,
+			IdleTimeout:       tcpIdleTimeout,
 		}
+		go func() {
+			log.Fatal(srv.ListenAndServeTLS(cert, key))
+		}()
+		log.Printf("LATTICE echo listening on https://%s%s", addr, EchoPath)
 	}
+
+	serveUDP(pc, secret, limits)
 }